@@ -0,0 +1,172 @@
+//! Anchor bindings of the merkle tree program: CPI account structs and helpers building the
+//! program's own instruction data, so Anchor programs call it without handwritten account
+//! metas, and a `MerkleState` wrapper reading the tree state. The program isn't an Anchor
+//! program, its instructions are tagged by their first byte rather than an 8 bytes sighash,
+//! which the helpers take care of.
+
+use anchor_lang::{
+    error::ErrorCode,
+    prelude::*,
+    solana_program::{instruction::Instruction, program::invoke_signed},
+};
+use merkle_tree_program::{instruction::MerkleTreeInstruction, state::MerkleStateAccount};
+use std::ops::Deref;
+
+pub use merkle_tree_program::pda::{
+    find_event_authority_pda, find_leaf_page_pda, find_merkle_state_pda,
+};
+
+/// Merkle state of a tree, see `MerkleStateAccount`.
+#[derive(Debug)]
+pub struct MerkleState(pub MerkleStateAccount);
+
+impl MerkleState {
+    /// Merkle state held by `merkle_state`, failing unless it's the merkle state account of
+    /// `program_id`.
+    pub fn load(merkle_state: &AccountInfo, program_id: &Pubkey) -> Result<Self> {
+        if merkle_state.owner != program_id {
+            return Err(ErrorCode::AccountOwnedByWrongProgram.into());
+        }
+        if merkle_state.key != &find_merkle_state_pda(program_id).0 {
+            return Err(ErrorCode::ConstraintSeeds.into());
+        }
+
+        Self::try_deserialize(&mut &merkle_state.try_borrow_data()?[..])
+    }
+}
+
+impl AccountDeserialize for MerkleState {
+    fn try_deserialize_unchecked(buf: &mut &[u8]) -> Result<Self> {
+        MerkleStateAccount::unpack(buf)
+            .map(Self)
+            .map_err(|_| ErrorCode::AccountDidNotDeserialize.into())
+    }
+}
+
+impl AccountSerialize for MerkleState {}
+
+impl Deref for MerkleState {
+    type Target = MerkleStateAccount;
+
+    fn deref(&self) -> &MerkleStateAccount {
+        &self.0
+    }
+}
+
+/// CPI helpers, the program account of each `CpiContext` being the merkle tree program.
+/// Inserts into trees with leaf pages pass the page of the new leaf as remaining account,
+/// see `find_leaf_page_pda`.
+pub mod cpi {
+    use super::*;
+
+    pub mod accounts {
+        use anchor_lang::prelude::*;
+
+        /// Accounts of inserts: `payer` pays rent of the grown merkle state and events are
+        /// emitted by self-CPI through `event_authority`.
+        #[derive(Accounts)]
+        pub struct Insert<'info> {
+            /// CHECK: merkle state PDA, checked by the merkle tree program
+            #[account(mut)]
+            pub merkle_state: AccountInfo<'info>,
+            #[account(mut)]
+            pub payer: Signer<'info>,
+            pub system_program: Program<'info, System>,
+            /// CHECK: event authority PDA, checked by the merkle tree program
+            pub event_authority: AccountInfo<'info>,
+            /// CHECK: merkle tree program, invoked by itself to emit events
+            pub merkle_tree_program: AccountInfo<'info>,
+        }
+
+        /// Accounts of read-only instructions.
+        #[derive(Accounts)]
+        pub struct View<'info> {
+            /// CHECK: merkle state PDA, checked by the merkle tree program
+            pub merkle_state: AccountInfo<'info>,
+        }
+    }
+
+    /// Insert leaf `hash`, failing unless the root is `expected_root` if set.
+    pub fn insert_leaf<'info>(
+        ctx: CpiContext<'_, '_, '_, 'info, accounts::Insert<'info>>,
+        hash: [u8; 32],
+        expected_root: Option<[u8; 32]>,
+    ) -> Result<()> {
+        invoke(
+            ctx,
+            MerkleTreeInstruction::InsertLeaf {
+                hash,
+                expected_root,
+            },
+        )
+    }
+
+    /// Insert leaf hashed on-chain from raw `data`, see `insert_leaf`.
+    pub fn insert_value<'info>(
+        ctx: CpiContext<'_, '_, '_, 'info, accounts::Insert<'info>>,
+        data: Vec<u8>,
+        expected_root: Option<[u8; 32]>,
+    ) -> Result<()> {
+        invoke(
+            ctx,
+            MerkleTreeInstruction::InsertValue {
+                data,
+                expected_root,
+            },
+        )
+    }
+
+    /// Insert leaf `hash` sweepable once `expires_at`(unix timestamp) passed, see
+    /// `insert_leaf`.
+    pub fn insert_expiring_leaf<'info>(
+        ctx: CpiContext<'_, '_, '_, 'info, accounts::Insert<'info>>,
+        hash: [u8; 32],
+        expires_at: i64,
+        expected_root: Option<[u8; 32]>,
+    ) -> Result<()> {
+        invoke(
+            ctx,
+            MerkleTreeInstruction::InsertExpiringLeaf {
+                hash,
+                expires_at,
+                expected_root,
+            },
+        )
+    }
+
+    /// Verify `leaf` at `leaf_index`, failing the transaction if `proof` doesn't verify.
+    pub fn verify_leaf<'info>(
+        ctx: CpiContext<'_, '_, '_, 'info, accounts::View<'info>>,
+        leaf_index: u32,
+        leaf: [u8; 32],
+        proof: Vec<[u8; 32]>,
+    ) -> Result<()> {
+        invoke(
+            ctx,
+            MerkleTreeInstruction::VerifyLeaf {
+                leaf_index,
+                leaf,
+                proof,
+            },
+        )
+    }
+
+    /// Invoke `instruction` with the accounts of `ctx` followed by its remaining accounts.
+    fn invoke<'info, T: ToAccountMetas + ToAccountInfos<'info>>(
+        ctx: CpiContext<'_, '_, '_, 'info, T>,
+        instruction: MerkleTreeInstruction,
+    ) -> Result<()> {
+        let mut accounts = ctx.accounts.to_account_metas(None);
+        accounts.extend(ctx.remaining_accounts.to_account_metas(None));
+        let instruction = Instruction {
+            program_id: *ctx.program.key,
+            accounts,
+            data: instruction.pack(),
+        };
+
+        let mut account_infos = ctx.accounts.to_account_infos();
+        account_infos.extend_from_slice(&ctx.remaining_accounts);
+        account_infos.push(ctx.program);
+        invoke_signed(&instruction, &account_infos, ctx.signer_seeds).map_err(Into::into)
+    }
+}
@@ -0,0 +1,37 @@
+//! Generate `Deployment` constants from the repo `deployments.json` manifest.
+
+use serde_json::{Map, Value};
+use std::{env, fs, path::Path};
+
+fn main() {
+    let manifest_path =
+        Path::new(&env::var("CARGO_MANIFEST_DIR").unwrap()).join("../deployments.json");
+    println!("cargo:rerun-if-changed={}", manifest_path.display());
+
+    let manifest: Map<String, Value> = serde_json::from_str(
+        &fs::read_to_string(&manifest_path).expect("Can't read deployments manifest"),
+    )
+    .expect("Invalid deployments manifest");
+
+    let mut deployments = String::from("pub const DEPLOYMENTS: &[Deployment] = &[\n");
+    for (cluster, deployment) in &manifest {
+        let field = |name: &str| {
+            deployment[name]
+                .as_str()
+                .unwrap_or_else(|| panic!("Deployment `{cluster}` has no `{name}`"))
+                .to_owned()
+        };
+        deployments.push_str(&format!(
+            "    Deployment {{ cluster: {cluster:?}, url: {:?}, program_id: solana_sdk::pubkey!({:?}) }},\n",
+            field("url"),
+            field("program_id"),
+        ));
+    }
+    deployments.push_str("];\n");
+
+    fs::write(
+        Path::new(&env::var("OUT_DIR").unwrap()).join("deployments.rs"),
+        deployments,
+    )
+    .expect("Can't write deployments constants");
+}
@@ -0,0 +1,185 @@
+use borsh::{BorshDeserialize, BorshSerialize};
+use merkle_tree_core::tree::compute_tree_root;
+use merkle_tree_program::{hash::TreeHasher, utils::hash_leaves};
+use reqwest::multipart::{Form, Part};
+use serde::Deserialize;
+use std::path::Path;
+
+/// Leaves snapshot, leaf hashes concatenated in insertion order.
+pub struct Snapshot {
+    pub bytes: Vec<u8>,
+    /// `utils::hash_leaves` of the snapshot leaves, same as `sha256(bytes)`.
+    pub content_hash: [u8; 32],
+}
+
+/// Compressed snapshot file, leaves split into zstd-framed pages of `PAGE_LEAVES` leaves.
+/// Checked against its manifest and the tree root when read.
+#[derive(BorshSerialize, BorshDeserialize)]
+struct CompressedSnapshot {
+    /// Always `CompressedSnapshot::MAGIC`, tells compressed snapshots apart from raw ones.
+    magic: [u8; 8],
+    /// Tree root the snapshot was taken at.
+    root: [u8; 32],
+    tree_hasher: TreeHasher,
+    leaves_count: u32,
+    pages: Vec<Vec<u8>>,
+    manifest: SnapshotManifest,
+}
+
+#[derive(BorshSerialize, BorshDeserialize)]
+struct SnapshotManifest {
+    /// `utils::hash_leaves` of each page.
+    page_hashes: Vec<[u8; 32]>,
+    /// `utils::hash_leaves` of all leaves.
+    content_hash: [u8; 32],
+}
+
+impl CompressedSnapshot {
+    const MAGIC: [u8; 8] = *b"MTSNAPZ1";
+
+    /// Leaves per page.
+    const PAGE_LEAVES: usize = 4096;
+
+    /// Decompress and verify pages, manifest and root, returning the leaves.
+    fn leaves(&self) -> Result<Vec<[u8; 32]>, String> {
+        if self.pages.len() != self.manifest.page_hashes.len() {
+            return Err("manifest doesn't list every page".into());
+        }
+
+        let mut leaves = Vec::with_capacity(self.leaves_count as usize);
+        for (index, (page, page_hash)) in self
+            .pages
+            .iter()
+            .zip(&self.manifest.page_hashes)
+            .enumerate()
+        {
+            let bytes = zstd::decode_all(page.as_slice())
+                .map_err(|err| format!("page {index} doesn't decompress: {err}"))?;
+            if bytes.len() % 32 != 0 {
+                return Err(format!("page {index} has a partial leaf"));
+            }
+            let page_leaves: Vec<[u8; 32]> = bytes
+                .chunks(32)
+                .map(|leaf| leaf.try_into().expect("Invalid leaf length"))
+                .collect();
+            if hash_leaves(&page_leaves) != *page_hash {
+                return Err(format!("page {index} checksum mismatch"));
+            }
+            leaves.extend(page_leaves);
+        }
+
+        if leaves.is_empty() || leaves.len() != self.leaves_count as usize {
+            return Err(format!(
+                "{} leaves, header declares {}",
+                leaves.len(),
+                self.leaves_count
+            ));
+        }
+        if hash_leaves(&leaves) != self.manifest.content_hash {
+            return Err("content hash mismatch".into());
+        }
+        if compute_tree_root(&self.tree_hasher, &leaves) != self.root {
+            return Err("leaves don't match the tree root".into());
+        }
+
+        Ok(leaves)
+    }
+}
+
+impl Snapshot {
+    pub fn new(leaves: &[[u8; 32]]) -> Self {
+        Self {
+            bytes: leaves.concat(),
+            content_hash: hash_leaves(leaves),
+        }
+    }
+
+    /// Read raw or compressed snapshot, verifying the latter.
+    pub fn read(path: &Path) -> Self {
+        let bytes = std::fs::read(path).expect("Can't read snapshot file");
+        if bytes.starts_with(&CompressedSnapshot::MAGIC) {
+            let leaves = CompressedSnapshot::try_from_slice(&bytes)
+                .map_err(|err| err.to_string())
+                .and_then(|snapshot| snapshot.leaves())
+                .unwrap_or_else(|err| panic!("Invalid compressed snapshot: {err}"));
+            return Self::new(&leaves);
+        }
+
+        let leaves: Vec<[u8; 32]> = bytes
+            .chunks(32)
+            .map(|leaf| leaf.try_into().expect("Invalid snapshot length"))
+            .collect();
+
+        Self::new(&leaves)
+    }
+
+    pub fn write(&self, path: &Path) {
+        std::fs::write(path, &self.bytes).expect("Can't write snapshot file");
+    }
+
+    /// Write compressed snapshot of the tree at `root` hashing nodes with `tree_hasher`.
+    pub fn write_compressed(&self, path: &Path, root: &[u8; 32], tree_hasher: TreeHasher) {
+        let page_len = CompressedSnapshot::PAGE_LEAVES * 32;
+        let (pages, page_hashes) = self
+            .bytes
+            .chunks(page_len)
+            .map(|page| {
+                let page_leaves: Vec<[u8; 32]> = page
+                    .chunks(32)
+                    .map(|leaf| leaf.try_into().expect("Invalid leaf length"))
+                    .collect();
+                (
+                    zstd::encode_all(page, 0).expect("Can't compress snapshot page"),
+                    hash_leaves(&page_leaves),
+                )
+            })
+            .unzip();
+
+        let snapshot = CompressedSnapshot {
+            magic: CompressedSnapshot::MAGIC,
+            root: *root,
+            tree_hasher,
+            leaves_count: (self.bytes.len() / 32) as u32,
+            pages,
+            manifest: SnapshotManifest {
+                page_hashes,
+                content_hash: self.content_hash,
+            },
+        };
+        std::fs::write(
+            path,
+            borsh::to_vec(&snapshot).expect("Can't serialize snapshot"),
+        )
+        .expect("Can't write snapshot file");
+    }
+}
+
+#[derive(Deserialize)]
+struct IpfsAddResponse {
+    #[serde(rename = "Hash")]
+    hash: String,
+}
+
+/// Add `snapshot` via IPFS node HTTP API (`/api/v0/add`), returns `ipfs://<cid>` URI.
+pub async fn publish_to_ipfs(ipfs_api: &str, snapshot: &Snapshot) -> String {
+    let form = Form::new().part(
+        "file",
+        Part::bytes(snapshot.bytes.clone()).file_name("snapshot.bin"),
+    );
+
+    let response: IpfsAddResponse = reqwest::Client::new()
+        .post(format!(
+            "{}/api/v0/add?pin=true",
+            ipfs_api.trim_end_matches('/')
+        ))
+        .multipart(form)
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .expect("Can't add snapshot to IPFS")
+        .json()
+        .await
+        .expect("Invalid IPFS add response");
+
+    format!("ipfs://{}", response.hash)
+}
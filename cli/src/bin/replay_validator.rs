@@ -0,0 +1,612 @@
+//! Independent watchdog: replays the tree state machine from raw transaction history and
+//! asserts the result equals the on-chain merkle state account.
+
+use clap::Parser;
+use merkle_tree_program::{
+    config::{ConfigChange, PendingConfigAccount},
+    events::EVENT_IX_TAG_LE,
+    hash::TreeHasher,
+    instruction::MerkleTreeInstruction,
+    leaf_page,
+    multisig::MultisigAccount,
+    pda::find_merkle_state_pda,
+    state::{ArchiveRecord, MerkleStateAccount},
+    tree_metadata::TreeMetadataAccount,
+};
+use solana_client::{
+    nonblocking::rpc_client::RpcClient, rpc_client::GetConfirmedSignaturesForAddress2Config,
+    rpc_config::RpcTransactionConfig,
+};
+use solana_sdk::{
+    bs58, commitment_config::CommitmentConfig, epoch_schedule::EpochSchedule, pubkey::Pubkey,
+    signature::Signature,
+};
+use solana_transaction_status::{
+    UiInstruction, UiTransactionEncoding, option_serializer::OptionSerializer,
+};
+use std::{str::FromStr, time::Duration};
+
+const DEFAULT_URL: &str = "http://127.0.0.1:8899";
+const DEFAULT_PROGRAM_ID: &str = "FuWr9Bgn4aWiXLzDoV69Amp3pLwThpjwXJVAE7GTT7bV";
+
+#[derive(Debug, Parser)]
+#[command(version, about, long_about = None)]
+struct Args {
+    /// Solana RPC URL.
+    #[arg(short, long, default_value = DEFAULT_URL)]
+    url: String,
+
+    /// Merkle tree program id.
+    #[arg(short, long, default_value = DEFAULT_PROGRAM_ID)]
+    program_id: Pubkey,
+
+    /// Seconds between checks.
+    #[arg(long, default_value_t = 30)]
+    interval: u64,
+
+    /// Check once and exit, non-zero exit code on divergence.
+    #[arg(long)]
+    once: bool,
+
+    /// URL to POST divergence alerts to, as `{"text": "..."}`.
+    #[arg(long)]
+    alert_webhook: Option<String>,
+}
+
+/// Tree state replayed from finalized transactions up to `slot`.
+struct Replay {
+    state: Option<MerkleStateAccount>,
+    pending_config: PendingConfigAccount,
+    last_signature: Option<Signature>,
+    slot: u64,
+}
+
+#[tokio::main]
+async fn main() {
+    let args = Args::parse();
+    let client = RpcClient::new_with_commitment(args.url.clone(), CommitmentConfig::finalized());
+    let (merkle_state_pda, _) = find_merkle_state_pda(&args.program_id);
+    let epoch_schedule = client
+        .get_epoch_schedule()
+        .await
+        .expect("Can't get epoch schedule");
+
+    let mut replay = Replay {
+        state: None,
+        pending_config: PendingConfigAccount::new(),
+        last_signature: None,
+        slot: 0,
+    };
+    loop {
+        match check(
+            &client,
+            &args.program_id,
+            &merkle_state_pda,
+            &epoch_schedule,
+            &mut replay,
+        )
+        .await
+        {
+            Ok(leaves_count) => println!(
+                "Slot {}: replayed state matches on-chain account ({leaves_count} leaves)",
+                replay.slot
+            ),
+            Err(divergence) => {
+                let alert = format!("Merkle tree {merkle_state_pda} diverged: {divergence}");
+                eprintln!("{alert}");
+                if let Some(webhook) = &args.alert_webhook {
+                    send_alert(webhook, &alert).await;
+                }
+                if args.once {
+                    std::process::exit(1);
+                }
+            }
+        }
+
+        if args.once {
+            return;
+        }
+        tokio::time::sleep(Duration::from_secs(args.interval)).await;
+    }
+}
+
+/// Replay new transactions up to the slot of the fetched account and compare, returns replayed
+/// leaves count.
+async fn check(
+    client: &RpcClient,
+    program_id: &Pubkey,
+    merkle_state_pda: &Pubkey,
+    epoch_schedule: &EpochSchedule,
+    replay: &mut Replay,
+) -> Result<usize, String> {
+    // Pin account first, transactions after its slot are replayed on the next check
+    let response = client
+        .get_account_with_commitment(merkle_state_pda, CommitmentConfig::finalized())
+        .await
+        .map_err(|err| format!("can't get merkle state account: {err}"))?;
+    let account_slot = response.context.slot;
+    let account_data = response.value.map(|account| account.data);
+
+    for (signature, slot) in get_new_signatures(client, merkle_state_pda, replay).await? {
+        if slot > account_slot {
+            break;
+        }
+        let epoch = epoch_schedule.get_epoch(slot);
+        replay_transaction(
+            client,
+            program_id,
+            &signature,
+            slot,
+            epoch,
+            &mut replay.state,
+            &mut replay.pending_config,
+        )
+        .await
+        .map_err(|err| format!("tx {signature}: {err}"))?;
+        replay.last_signature = Some(signature);
+        replay.slot = slot;
+    }
+
+    let replayed_data = replay
+        .state
+        .as_ref()
+        .map(|state| borsh::to_vec(state).expect("Can't serialize replayed state"));
+    if !state_matches(replayed_data.as_deref(), account_data.as_deref()) {
+        return Err(format!(
+            "account at slot {account_slot} doesn't match state replayed up to slot {}",
+            replay.slot
+        ));
+    }
+
+    Ok(replay
+        .state
+        .as_ref()
+        .map_or(0, |state| state.get_leaves_count()))
+}
+
+/// Whether the borsh encoded `replayed` state matches account `data`. Preallocated and
+/// geometrically grown accounts are longer than their state, their spare bytes aren't state
+/// and may hold what a shrinking write left behind, so only the encoded prefix is compared.
+fn state_matches(replayed: Option<&[u8]>, data: Option<&[u8]>) -> bool {
+    match (replayed, data) {
+        (Some(replayed), Some(data)) => data.starts_with(replayed),
+        (replayed, data) => replayed.is_none() && data.is_none(),
+    }
+}
+
+/// Successful transactions touching `address` after `replay.last_signature`, oldest first.
+async fn get_new_signatures(
+    client: &RpcClient,
+    address: &Pubkey,
+    replay: &Replay,
+) -> Result<Vec<(Signature, u64)>, String> {
+    let mut signatures = Vec::new();
+    let mut before = None;
+    loop {
+        let page = client
+            .get_signatures_for_address_with_config(
+                address,
+                GetConfirmedSignaturesForAddress2Config {
+                    before,
+                    until: replay.last_signature,
+                    limit: None,
+                    commitment: Some(CommitmentConfig::finalized()),
+                },
+            )
+            .await
+            .map_err(|err| format!("can't get signatures: {err}"))?;
+        let Some(last) = page.last() else {
+            break;
+        };
+        before = Some(Signature::from_str(&last.signature).expect("Invalid signature"));
+
+        signatures.extend(
+            page.into_iter()
+                .filter(|status| status.err.is_none())
+                .map(|status| {
+                    (
+                        Signature::from_str(&status.signature).expect("Invalid signature"),
+                        status.slot,
+                    )
+                }),
+        );
+    }
+
+    signatures.reverse();
+    Ok(signatures)
+}
+
+/// Apply every program instruction of transaction `signature`, including CPIs, in order.
+async fn replay_transaction(
+    client: &RpcClient,
+    program_id: &Pubkey,
+    signature: &Signature,
+    slot: u64,
+    epoch: u64,
+    state: &mut Option<MerkleStateAccount>,
+    pending_config: &mut PendingConfigAccount,
+) -> Result<(), String> {
+    let tx = client
+        .get_transaction_with_config(
+            signature,
+            RpcTransactionConfig {
+                encoding: Some(UiTransactionEncoding::Base64),
+                commitment: Some(CommitmentConfig::finalized()),
+                max_supported_transaction_version: Some(0),
+            },
+        )
+        .await
+        .map_err(|err| format!("can't get tx: {err}"))?;
+    let block_time = tx.block_time.ok_or("tx block time is empty")?;
+    let meta = tx.transaction.meta.ok_or("tx meta is empty")?;
+    let versioned_tx = tx
+        .transaction
+        .transaction
+        .decode()
+        .ok_or("can't decode tx")?;
+
+    // Static keys followed by keys loaded from lookup tables
+    let mut account_keys = versioned_tx.message.static_account_keys().to_vec();
+    if let OptionSerializer::Some(loaded) = &meta.loaded_addresses {
+        for key in loaded.writable.iter().chain(&loaded.readonly) {
+            account_keys.push(Pubkey::from_str(key).map_err(|_| "invalid loaded address")?);
+        }
+    }
+    let inner_instructions = match meta.inner_instructions {
+        OptionSerializer::Some(inner_instructions) => inner_instructions,
+        _ => Vec::new(),
+    };
+
+    for (index, instruction) in versioned_tx.message.instructions().iter().enumerate() {
+        let mut instructions = vec![(
+            instruction.program_id_index,
+            instruction.accounts.clone(),
+            instruction.data.clone(),
+        )];
+        for inner in inner_instructions
+            .iter()
+            .filter(|inner| inner.index as usize == index)
+            .flat_map(|inner| &inner.instructions)
+        {
+            let UiInstruction::Compiled(inner) = inner else {
+                return Err("inner instruction isn't compiled".into());
+            };
+            let data = bs58::decode(&inner.data)
+                .into_vec()
+                .map_err(|_| "invalid inner instruction data")?;
+            instructions.push((inner.program_id_index, inner.accounts.clone(), data));
+        }
+
+        for (program_id_index, accounts, data) in instructions {
+            if account_keys.get(program_id_index as usize) != Some(program_id)
+                || data.starts_with(&EVENT_IX_TAG_LE)
+            {
+                continue;
+            }
+            let accounts: Vec<Pubkey> = accounts
+                .iter()
+                .map(|index| account_keys[*index as usize])
+                .collect();
+            let instruction = MerkleTreeInstruction::unpack(&data)
+                .map_err(|err| format!("can't unpack instruction: {err}"))?;
+            apply_instruction(
+                state,
+                pending_config,
+                instruction,
+                &accounts,
+                slot,
+                epoch,
+                block_time,
+            )?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Re-execute `instruction` of a successful transaction at `slot` of `epoch` and
+/// `block_time`(the clock unix timestamp), failing if the replayed state would have rejected
+/// it. Config changes queued in the pending config account are replayed in `pending_config`.
+fn apply_instruction(
+    state: &mut Option<MerkleStateAccount>,
+    pending_config: &mut PendingConfigAccount,
+    instruction: MerkleTreeInstruction,
+    accounts: &[Pubkey],
+    slot: u64,
+    epoch: u64,
+    block_time: i64,
+) -> Result<(), String> {
+    let hash = match &instruction {
+        MerkleTreeInstruction::InsertLeaf { hash, .. }
+        | MerkleTreeInstruction::InsertExpiringLeaf { hash, .. }
+        | MerkleTreeInstruction::InitTree { hash, .. } => Some(*hash),
+        MerkleTreeInstruction::InsertValue { data, .. } => {
+            let tree_hasher = state
+                .as_ref()
+                .map_or_else(TreeHasher::default, |state| state.get_tree_hasher());
+            Some(tree_hasher.hash_value(data))
+        }
+        _ => None,
+    };
+    let expected_root = match &instruction {
+        MerkleTreeInstruction::InsertLeaf { expected_root, .. }
+        | MerkleTreeInstruction::InsertValue { expected_root, .. }
+        | MerkleTreeInstruction::InsertExpiringLeaf { expected_root, .. } => *expected_root,
+        _ => None,
+    };
+    if let (Some(hash), None) = (hash, &state) {
+        if expected_root.is_some() {
+            return Err("tree created with an expected root".to_string());
+        }
+        let mut new_state = MerkleStateAccount::new(&hash, &accounts[1]);
+        match instruction {
+            MerkleTreeInstruction::InsertExpiringLeaf { expires_at, .. } => {
+                new_state.set_leaf_expiry(0, expires_at)
+            }
+            MerkleTreeInstruction::InitTree {
+                leaf_capacity,
+                hash_algo,
+                domain_separation,
+                ordered,
+                left_balanced,
+                zero_padded,
+                ..
+            } => {
+                new_state.set_leaf_capacity(leaf_capacity);
+                new_state.set_tree_hasher(TreeHasher {
+                    hash_algo,
+                    domain_separation,
+                    ordered,
+                    left_balanced,
+                    zero_padded,
+                });
+            }
+            _ => {}
+        }
+        *state = Some(new_state);
+        return Ok(());
+    }
+
+    let state = state.as_mut().ok_or("instruction before tree creation")?;
+    let signer = accounts.get(1);
+    let is_authority = signer == Some(&state.get_authority());
+    let can_configure = is_authority && state.get_config_timelock().is_none();
+    let accepted = match instruction {
+        MerkleTreeInstruction::InsertLeaf { .. } | MerkleTreeInstruction::InsertValue { .. } => {
+            let hash = hash.expect("Insert hash is set");
+            let accepted = !state.is_paused()
+                && expected_root
+                    .is_none_or(|root| !state.is_root_dirty() && root == state.get_root_hash())
+                && !state.is_sealed()
+                && !state.is_full()
+                && state.get_hash_algo().is_valid_leaf(&hash)
+                && !state.is_sentinel_leaf(&hash)
+                && !state.is_duplicate(&hash)
+                && state.can_insert(&accounts[1])
+                && state.count_insert(&accounts[1], slot, epoch);
+            state.add_leaf(&hash);
+            state.record_leaf_metadata(slot, &accounts[1]);
+            accepted
+        }
+        MerkleTreeInstruction::ProposeAuthority { new_authority } => {
+            state.propose_authority(&new_authority);
+            is_authority
+        }
+        MerkleTreeInstruction::AcceptAuthority => {
+            let accepted = signer.is_some() && state.get_pending_authority().as_ref() == signer;
+            state.accept_authority();
+            accepted
+        }
+        MerkleTreeInstruction::PauseTree => {
+            state.set_paused(true);
+            is_authority
+        }
+        MerkleTreeInstruction::ResumeTree => {
+            state.set_paused(false);
+            is_authority
+        }
+        MerkleTreeInstruction::ReplaceLeaf {
+            leaf_index,
+            old_leaf,
+            new_leaf,
+            proof,
+        } => {
+            let accepted = is_authority
+                && !state.is_paused()
+                && !state.is_sealed()
+                && !state.is_root_dirty()
+                && state.get_hash_algo().is_valid_leaf(&new_leaf)
+                && !state.is_sentinel_leaf(&new_leaf)
+                && !state.is_duplicate(&new_leaf);
+            state.replace_leaf(leaf_index, &old_leaf, &new_leaf, &proof) && accepted
+        }
+        MerkleTreeInstruction::SetArchiveUri {
+            root,
+            content_hash,
+            uri,
+        } => {
+            is_authority
+                && !state.is_frontier_mode()
+                && !state.is_root_dirty()
+                && state.set_archive(ArchiveRecord {
+                    root,
+                    content_hash,
+                    uri,
+                })
+        }
+        MerkleTreeInstruction::SetRejectDuplicates { reject_duplicates } => {
+            let accepted = can_configure && !(reject_duplicates && state.is_frontier_mode());
+            state.set_reject_duplicates(reject_duplicates);
+            accepted
+        }
+        MerkleTreeInstruction::FinalizeTree => {
+            let accepted =
+                is_authority && !state.is_paused() && !state.is_sealed() && !state.is_root_dirty();
+            state.seal(slot);
+            accepted
+        }
+        MerkleTreeInstruction::SetEventVerbosity { event_verbosity } => {
+            state.set_event_verbosity(event_verbosity);
+            can_configure
+        }
+        MerkleTreeInstruction::EnableFrontierMode => {
+            let accepted = is_authority
+                && !state.is_paused()
+                && !state.rejects_duplicates()
+                && state.get_leaf_expiries().is_empty()
+                && !state.get_tree_hasher().left_balanced
+                && !state.is_lazy_root();
+            state.enable_frontier_mode();
+            accepted
+        }
+        MerkleTreeInstruction::InsertExpiringLeaf {
+            hash, expires_at, ..
+        } => {
+            let accepted = !state.is_paused()
+                && expected_root
+                    .is_none_or(|root| !state.is_root_dirty() && root == state.get_root_hash())
+                && !state.is_sealed()
+                && !state.is_frontier_mode()
+                && !state.is_full()
+                && state.get_hash_algo().is_valid_leaf(&hash)
+                && !state.is_sentinel_leaf(&hash)
+                && !state.is_duplicate(&hash)
+                && state.can_insert(&accounts[1])
+                && state.count_insert(&accounts[1], slot, epoch);
+            state.add_leaf(&hash);
+            state.record_leaf_metadata(slot, &accounts[1]);
+            state.set_leaf_expiry(state.get_leaves_count() as u32 - 1, expires_at);
+            accepted
+        }
+        MerkleTreeInstruction::EnableLeafPages {
+            first_page,
+            pages_count,
+        } => {
+            let end_page = first_page.saturating_add(pages_count);
+            let accepted = is_authority
+                && !state.is_paused()
+                && !state.is_frontier_mode()
+                && !state.rejects_duplicates()
+                && state.get_leaf_expiries().is_empty()
+                && !state.get_tree_hasher().left_balanced
+                && !state.is_lazy_root()
+                && first_page == leaf_page::page_index(state.get_paged_leaves())
+                && pages_count > 0
+                && end_page as usize <= leaf_page::pages_count(state.get_leaves_count());
+            state.page_leaves(end_page);
+            accepted
+        }
+        MerkleTreeInstruction::SetRecordLeafMetadata {
+            record_leaf_metadata,
+        } => {
+            state.set_record_leaf_metadata(record_leaf_metadata);
+            can_configure
+        }
+        MerkleTreeInstruction::SweepExpired => {
+            let accepted = !state.is_paused() && !state.is_sealed();
+            state.sweep_expired(block_time) > 0 && accepted
+        }
+        MerkleTreeInstruction::AddInserter { inserter } => {
+            state.add_inserter(&inserter);
+            can_configure
+        }
+        MerkleTreeInstruction::RemoveInserter { inserter } => {
+            state.remove_inserter(&inserter) && can_configure
+        }
+        MerkleTreeInstruction::SetPermissioned { permissioned } => {
+            state.set_permissioned(permissioned);
+            can_configure
+        }
+        // Multisig lives in its own account, only acceptance is checked
+        MerkleTreeInstruction::SetMultisig { threshold, signers } => {
+            is_authority && MultisigAccount::new(threshold, signers).is_ok()
+        }
+        MerkleTreeInstruction::SetRateLimit {
+            max_inserts_per_slot,
+            max_inserts_per_epoch,
+        } => {
+            state.set_rate_limit(max_inserts_per_slot, max_inserts_per_epoch);
+            can_configure
+        }
+        MerkleTreeInstruction::SetMaxLeaves { max_leaves } => {
+            state.set_max_leaves(max_leaves);
+            can_configure
+        }
+        MerkleTreeInstruction::SetRejectSentinelLeaves {
+            reject_sentinel_leaves,
+        } => {
+            state.set_reject_sentinel_leaves(reject_sentinel_leaves);
+            can_configure
+        }
+        MerkleTreeInstruction::SetConfigTimelock { config_timelock } => {
+            state.set_config_timelock(config_timelock);
+            can_configure
+        }
+        MerkleTreeInstruction::SetGrowthPercent { growth_percent } => {
+            let accepted = can_configure && !(growth_percent != 0 && state.is_frontier_mode());
+            state.set_growth_percent(growth_percent);
+            accepted
+        }
+        MerkleTreeInstruction::SetCanopyDepth { canopy_depth } => {
+            can_configure && ConfigChange::CanopyDepth(canopy_depth).apply(state).is_ok()
+        }
+        MerkleTreeInstruction::SetLazyRoot { lazy_root } => {
+            can_configure && ConfigChange::LazyRoot(lazy_root).apply(state).is_ok()
+        }
+        // Anyone may commit, up to date roots are left as is
+        MerkleTreeInstruction::CommitRoot => {
+            let accepted = !state.is_paused();
+            state.commit_root();
+            accepted
+        }
+        MerkleTreeInstruction::QueueConfig { change } => {
+            let effective_slot =
+                slot.saturating_add(state.get_config_timelock().unwrap_or_default());
+            is_authority
+                && change.apply(&mut state.clone()).is_ok()
+                && pending_config.push(change, effective_slot)
+        }
+        MerkleTreeInstruction::ApplyPendingConfig => {
+            let changes = pending_config.take_due(slot);
+            let applied = changes.iter().all(|change| change.apply(state).is_ok());
+            is_authority && !changes.is_empty() && applied
+        }
+        MerkleTreeInstruction::CancelPendingConfig => {
+            let accepted = is_authority && !pending_config.changes.is_empty();
+            *pending_config = PendingConfigAccount::new();
+            accepted
+        }
+        MerkleTreeInstruction::InitTree { .. } => false,
+        // Replayed state is always of the current layout
+        MerkleTreeInstruction::MigrateState => true,
+        // Spare bytes don't change the replayed state
+        MerkleTreeInstruction::GrowTree { .. } => state.keeps_spare(),
+        // Metadata lives in its own account, only acceptance is checked
+        MerkleTreeInstruction::SetMetadata {
+            name,
+            description,
+            uri,
+        } => is_authority && TreeMetadataAccount::new(name, description, uri).is_ok(),
+        MerkleTreeInstruction::VerifyLeaf { .. }
+        | MerkleTreeInstruction::VerifyMultiProof { .. }
+        | MerkleTreeInstruction::VerifyConsistency { .. }
+        | MerkleTreeInstruction::GetRootHash => !state.is_root_dirty(),
+    };
+
+    if !accepted {
+        return Err("program accepted instruction rejected by replay".into());
+    }
+    Ok(())
+}
+
+async fn send_alert(webhook: &str, text: &str) {
+    let result = reqwest::Client::new()
+        .post(webhook)
+        .json(&serde_json::json!({ "text": text }))
+        .send()
+        .await
+        .and_then(|response| response.error_for_status());
+    if let Err(err) = result {
+        eprintln!("Can't send alert: {err}");
+    }
+}
@@ -0,0 +1,101 @@
+use clap::Args;
+use hkdf::Hkdf;
+use merkle_tree_program::utils::hash_salted_value;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::{collections::BTreeMap, path::PathBuf};
+
+/// HKDF salt, separates commitment salts from other uses of the master secret.
+const HKDF_SALT: &[u8] = b"merkle-tree-commitment";
+
+/// Where per-leaf commitment salts come from.
+#[derive(Debug, Args)]
+#[group(required = true, multiple = false)]
+pub struct SaltArgs {
+    /// JSON file with per-leaf salts, missing salts are generated and saved.
+    #[arg(long)]
+    pub salt_file: Option<PathBuf>,
+    /// File with master secret, salts are derived with HKDF-SHA256 per value.
+    #[arg(long)]
+    pub master_secret_file: Option<PathBuf>,
+}
+
+impl SaltArgs {
+    /// Salt for raw `value` bytes.
+    pub fn salt(&self, value: &[u8]) -> [u8; 32] {
+        match (&self.salt_file, &self.master_secret_file) {
+            (_, Some(path)) => {
+                let master_secret = std::fs::read(path).expect("Can't read master secret file");
+                derive_salt(&master_secret, value)
+            }
+            (Some(path), None) => {
+                let mut salts: BTreeMap<String, String> = std::fs::read(path)
+                    .map(|data| serde_json::from_slice(&data).expect("Invalid salt file"))
+                    .unwrap_or_default();
+
+                let salt = salts.entry(hex::encode(value)).or_insert_with(|| {
+                    let mut salt = [0u8; 32];
+                    rand::thread_rng().fill_bytes(&mut salt);
+                    hex::encode(salt)
+                });
+                let salt = parse_hash(salt);
+
+                let data = serde_json::to_vec_pretty(&salts).expect("Can't serialize salts");
+                std::fs::write(path, data).expect("Can't write salt file");
+                salt
+            }
+            (None, None) => unreachable!(),
+        }
+    }
+}
+
+/// Per-value salt: HKDF-SHA256 of `master_secret` with `value` as info.
+pub fn derive_salt(master_secret: &[u8], value: &[u8]) -> [u8; 32] {
+    let mut salt = [0u8; 32];
+    Hkdf::<Sha256>::new(Some(HKDF_SALT), master_secret)
+        .expand(value, &mut salt)
+        .expect("Invalid HKDF output length");
+    salt
+}
+
+/// Everything needed to prove a committed value is in the tree, revealing only that value.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CommitmentProof {
+    /// Hex encoded raw value bytes.
+    pub value: String,
+    pub salt: String,
+    pub leaf_index: u32,
+    pub leaf: String,
+    pub proof: Vec<String>,
+}
+
+impl CommitmentProof {
+    pub fn new(value: &[u8], salt: &[u8; 32], leaf_index: u32, proof: &[[u8; 32]]) -> Self {
+        Self {
+            value: hex::encode(value),
+            salt: hex::encode(salt),
+            leaf_index,
+            leaf: hex::encode(hash_salted_value(salt, value)),
+            proof: proof.iter().map(hex::encode).collect(),
+        }
+    }
+
+    /// Recompute leaf from revealed value and salt, `None` if it doesn't match `leaf`.
+    pub fn verified_leaf(&self) -> Option<[u8; 32]> {
+        let value = hex::decode(&self.value).expect("Invalid value hex");
+        let leaf = hash_salted_value(&parse_hash(&self.salt), &value);
+        (leaf == parse_hash(&self.leaf)).then_some(leaf)
+    }
+
+    pub fn proof(&self) -> Vec<[u8; 32]> {
+        self.proof.iter().map(|node| parse_hash(node)).collect()
+    }
+}
+
+fn parse_hash(value: &str) -> [u8; 32] {
+    hex::decode(value)
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .expect("Invalid 32 bytes hex")
+}
@@ -0,0 +1,53 @@
+use crate::output;
+use solana_sdk::pubkey::Pubkey;
+use std::io::{self, BufRead};
+
+/// Planned change of a single field, shown before an admin command is sent.
+pub struct Change {
+    pub field: &'static str,
+    pub from: String,
+    pub to: String,
+}
+
+/// Print `changes` of `account` and ask for `y/N` confirmation, `yes` skips the prompt.
+pub fn confirm(account: &Pubkey, changes: &[Change], yes: bool) -> bool {
+    print_changes(account, changes);
+    if yes {
+        return true;
+    }
+
+    let answer = prompt("Proceed? [y/N]: ");
+    matches!(answer.to_lowercase().as_str(), "y" | "yes")
+}
+
+/// Same as `confirm`, but irreversible operations require typing the `account` address.
+pub fn confirm_irreversible(account: &Pubkey, changes: &[Change], yes: bool) -> bool {
+    print_changes(account, changes);
+    if yes {
+        return true;
+    }
+
+    output::note("This operation is irreversible.\n");
+    prompt("Type the tree address to confirm: ") == account.to_string()
+}
+
+fn print_changes(account: &Pubkey, changes: &[Change]) {
+    output::note(format_args!("Account: {account}\n"));
+    for change in changes {
+        output::note(format_args!(
+            "  {}: {} -> {}\n",
+            change.field, change.from, change.to
+        ));
+    }
+}
+
+fn prompt(message: &str) -> String {
+    output::note(message);
+
+    let mut answer = String::new();
+    io::stdin()
+        .lock()
+        .read_line(&mut answer)
+        .expect("Can't read stdin");
+    answer.trim().to_string()
+}
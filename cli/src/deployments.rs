@@ -0,0 +1,45 @@
+//! Known program deployments by cluster, generated from `deployments.json` at build time.
+
+use crate::output;
+use merkle_tree_program::pda::{find_event_authority_pda, find_merkle_state_pda};
+use serde_json::json;
+use solana_sdk::pubkey::Pubkey;
+
+/// Cluster the CLI connects to unless `--url` or `--program-id` override it.
+pub const DEFAULT_CLUSTER: &str = "localnet";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Deployment {
+    /// Cluster name, e.g. `devnet`.
+    pub cluster: &'static str,
+    pub url: &'static str,
+    pub program_id: Pubkey,
+}
+
+include!(concat!(env!("OUT_DIR"), "/deployments.rs"));
+
+pub fn find(cluster: &str) -> Option<&'static Deployment> {
+    DEPLOYMENTS
+        .iter()
+        .find(|deployment| deployment.cluster == cluster)
+}
+
+/// Print `deployment` with its canonical PDAs.
+pub fn show(deployment: &Deployment) {
+    let (merkle_state_pda, _) = find_merkle_state_pda(&deployment.program_id);
+    let (event_authority_pda, _) = find_event_authority_pda(&deployment.program_id);
+
+    output::print(
+        format_args!(
+            "{}:\n  url: {}\n  program_id: {}\n  merkle_state: {merkle_state_pda}\n  event_authority: {event_authority_pda}",
+            deployment.cluster, deployment.url, deployment.program_id
+        ),
+        json!({
+            "cluster": deployment.cluster,
+            "url": deployment.url,
+            "program_id": deployment.program_id.to_string(),
+            "merkle_state": merkle_state_pda.to_string(),
+            "event_authority": event_authority_pda.to_string(),
+        }),
+    );
+}
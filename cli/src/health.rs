@@ -0,0 +1,135 @@
+use merkle_tree_program::state::{Frontier, MerkleStateAccount};
+use serde::Serialize;
+use solana_sdk::{account::Account, pubkey::Pubkey};
+
+/// Outcome of a single `health-check` validation.
+#[derive(Debug, Serialize)]
+pub struct Check {
+    pub name: &'static str,
+    pub passed: bool,
+    pub details: String,
+}
+
+/// `health-check` result, passed only if every check passed.
+#[derive(Debug, Serialize)]
+pub struct HealthReport {
+    pub passed: bool,
+    pub checks: Vec<Check>,
+}
+
+/// Validate merkle state `account` of `program_id`, `rent_minimum` being the rent-exempt
+/// balance for its length. Checks needing the decoded state are skipped if it doesn't decode.
+pub fn check_tree(program_id: &Pubkey, account: &Account, rent_minimum: u64) -> HealthReport {
+    let mut checks = vec![
+        check(
+            "owner",
+            account.owner == *program_id,
+            format!("owner {}", account.owner),
+        ),
+        check(
+            "rent_exemption",
+            account.lamports >= rent_minimum,
+            format!(
+                "{} lamports, {rent_minimum} required for {} bytes",
+                account.lamports,
+                account.data.len()
+            ),
+        ),
+    ];
+
+    match MerkleStateAccount::unpack(&account.data) {
+        Ok(merkle_state) => {
+            checks.push(check(
+                "layout",
+                true,
+                format!(
+                    "discriminator and version match, {:?} hashing",
+                    merkle_state.get_tree_hasher()
+                ),
+            ));
+            checks.extend(check_state(&merkle_state));
+        }
+        Err(err) => checks.push(check("layout", false, err.to_string())),
+    }
+
+    HealthReport {
+        passed: checks.iter().all(|check| check.passed),
+        checks,
+    }
+}
+
+fn check_state(merkle_state: &MerkleStateAccount) -> Vec<Check> {
+    let root_hash = merkle_state.get_root_hash();
+    let (recomputed_root, recomputed_canopy) = merkle_state.recompute_root();
+    let root_history = merkle_state.get_root_history();
+
+    let structure = match merkle_state.get_frontier() {
+        Some(frontier) => check_frontier(frontier, &root_hash),
+        None => check(
+            "canopy",
+            recomputed_canopy == merkle_state.canopy(),
+            format!(
+                "{} nodes cached for depth {}",
+                merkle_state.canopy().len(),
+                merkle_state.get_canopy_depth()
+            ),
+        ),
+    };
+
+    vec![
+        check(
+            "root",
+            recomputed_root == root_hash,
+            format!(
+                "stored {}, recomputed {} from {} leaves{}",
+                hex::encode(root_hash),
+                hex::encode(recomputed_root),
+                merkle_state.get_leaves_count(),
+                match merkle_state.is_root_dirty() {
+                    true => ", pending commit-root",
+                    false => "",
+                }
+            ),
+        ),
+        structure,
+        check(
+            "root_history",
+            root_history.last() == Some(&root_hash)
+                && root_history.len() <= MerkleStateAccount::ROOT_HISTORY_LEN,
+            format!("{} recent roots, latest is current", root_history.len()),
+        ),
+    ]
+}
+
+/// Frontier has a node per level of its leaves count, and its changelog ends at the root.
+fn check_frontier(frontier: &Frontier, root_hash: &[u8; 32]) -> Check {
+    let levels = (u32::BITS - frontier.leaves_count.leading_zeros()) as usize;
+    let changelog_ok = frontier.changelog.len() <= Frontier::CHANGELOG_LEN
+        && frontier
+            .changelog
+            .last()
+            .is_none_or(|entry| entry.root == *root_hash)
+        && frontier
+            .changelog
+            .iter()
+            .all(|entry| entry.leaves_count <= frontier.leaves_count);
+
+    check(
+        "frontier",
+        frontier.nodes.len() >= levels && changelog_ok,
+        format!(
+            "{} nodes for {} leaves, {} changelog entries",
+            frontier.nodes.len(),
+            frontier.leaves_count,
+            frontier.changelog.len()
+        ),
+    )
+}
+
+fn check(name: &'static str, passed: bool, details: String) -> Check {
+    Check {
+        name,
+        passed,
+        details,
+    }
+}
@@ -0,0 +1,441 @@
+//! Shank format IDL of the program, for client generators like Codama. It's built from the
+//! client instruction builders rather than annotations on the program, so discriminants and
+//! account order, mutability and signers are the ones clients actually send. The optional
+//! trailing `expected_root` of inserts isn't a borsh option, it has no tag byte, so it's left
+//! out of the args: generated clients append its 32 bytes themselves.
+
+use merkle_tree_client::instruction::{
+    accept_authority_ix, add_inserter_ix, apply_pending_config_ix, cancel_pending_config_ix,
+    commit_root_ix, enable_frontier_mode_ix, enable_leaf_pages_ix, finalize_tree_ix,
+    get_root_hash_ix, grow_tree_ix, init_tree_ix, insert_expiring_leaf_ix, insert_leaf_ix,
+    insert_value_ix, migrate_state_ix, pause_tree_ix, propose_authority_ix, queue_config_ix,
+    remove_inserter_ix, replace_leaf_ix, resume_tree_ix, set_archive_uri_ix, set_canopy_depth_ix,
+    set_config_timelock_ix, set_event_verbosity_ix, set_growth_percent_ix, set_lazy_root_ix,
+    set_max_leaves_ix, set_metadata_ix, set_multisig_ix, set_permissioned_ix, set_rate_limit_ix,
+    set_record_leaf_metadata_ix, set_reject_duplicates_ix, set_reject_sentinel_leaves_ix,
+    sweep_expired_ix, verify_consistency_ix, verify_leaf_ix, verify_multiproof_ix, with_leaf_page,
+};
+use merkle_tree_program::{config::ConfigChange, events::EventVerbosity, hash::TreeHasher};
+use serde_json::{Value, json};
+use solana_sdk::{instruction::Instruction, pubkey::Pubkey};
+
+const VIEW: &[&str] = &["merkleState"];
+const AUTHORITY: &[&str] = &["merkleState", "authority"];
+const PAYER: &[&str] = &["merkleState", "payer", "systemProgram"];
+const INSERT: &[&str] = &[
+    "merkleState",
+    "payer",
+    "systemProgram",
+    "eventAuthority",
+    "program",
+    "leafPage",
+];
+
+/// IDL of the program deployed at `program_id`.
+pub fn idl(program_id: &Pubkey) -> Value {
+    let key = Pubkey::default();
+    let instructions = vec![
+        insert(
+            "InsertLeaf",
+            insert_leaf_ix(program_id, &key, [0; 32], None),
+            json!([arg("hash", hash())]),
+        ),
+        instruction(
+            "ProposeAuthority",
+            propose_authority_ix(program_id, &key, key),
+            AUTHORITY,
+            json!([arg("newAuthority", "publicKey")]),
+        ),
+        instruction(
+            "AcceptAuthority",
+            accept_authority_ix(program_id, &key),
+            &["merkleState", "pendingAuthority"],
+            json!([]),
+        ),
+        instruction(
+            "PauseTree",
+            pause_tree_ix(program_id, &key),
+            AUTHORITY,
+            json!([]),
+        ),
+        instruction(
+            "ResumeTree",
+            resume_tree_ix(program_id, &key),
+            AUTHORITY,
+            json!([]),
+        ),
+        insert(
+            "InsertValue",
+            insert_value_ix(program_id, &key, Vec::new(), None),
+            json!([arg("data", "bytes")]),
+        ),
+        instruction(
+            "VerifyLeaf",
+            verify_leaf_ix(program_id, 0, [0; 32], Vec::new()),
+            VIEW,
+            json!([
+                arg("leafIndex", "u32"),
+                arg("leaf", hash()),
+                arg("proof", proof())
+            ]),
+        ),
+        insert(
+            "ReplaceLeaf",
+            replace_leaf_ix(program_id, &key, 0, [0; 32], [0; 32], Vec::new()),
+            json!([
+                arg("leafIndex", "u32"),
+                arg("oldLeaf", hash()),
+                arg("newLeaf", hash()),
+                arg("proof", proof())
+            ]),
+        ),
+        instruction(
+            "VerifyMultiProof",
+            verify_multiproof_ix(program_id, Vec::new(), Vec::new()),
+            VIEW,
+            json!([
+                arg("leaves", json!({ "vec": { "tuple": ["u32", hash()] } })),
+                arg("proof", proof())
+            ]),
+        ),
+        instruction(
+            "SetArchiveUri",
+            set_archive_uri_ix(program_id, &key, [0; 32], [0; 32], String::new()),
+            PAYER,
+            json!([
+                arg("root", hash()),
+                arg("contentHash", hash()),
+                arg("uri", "string")
+            ]),
+        ),
+        instruction("GetRootHash", get_root_hash_ix(program_id), VIEW, json!([])),
+        instruction(
+            "SetRejectDuplicates",
+            set_reject_duplicates_ix(program_id, &key, false),
+            AUTHORITY,
+            json!([arg("rejectDuplicates", "bool")]),
+        ),
+        instruction(
+            "FinalizeTree",
+            finalize_tree_ix(program_id, &key),
+            PAYER,
+            json!([]),
+        ),
+        instruction(
+            "VerifyConsistency",
+            verify_consistency_ix(program_id, [0; 32], 0, [0; 32], Vec::new()),
+            VIEW,
+            json!([
+                arg("oldRoot", hash()),
+                arg("oldLeavesCount", "u32"),
+                arg("leaf", hash()),
+                arg("proof", proof())
+            ]),
+        ),
+        instruction(
+            "EnableFrontierMode",
+            enable_frontier_mode_ix(program_id, &key),
+            AUTHORITY,
+            json!([]),
+        ),
+        instruction(
+            "SetEventVerbosity",
+            set_event_verbosity_ix(program_id, &key, EventVerbosity::default()),
+            AUTHORITY,
+            json!([arg("eventVerbosity", defined("EventVerbosity"))]),
+        ),
+        insert(
+            "InsertExpiringLeaf",
+            insert_expiring_leaf_ix(program_id, &key, [0; 32], 0, None),
+            json!([arg("hash", hash()), arg("expiresAt", "i64")]),
+        ),
+        instruction(
+            "SweepExpired",
+            sweep_expired_ix(program_id, &key),
+            &["merkleState", "cranker", "eventAuthority", "program"],
+            json!([]),
+        ),
+        instruction(
+            "SetRecordLeafMetadata",
+            set_record_leaf_metadata_ix(program_id, &key, false),
+            AUTHORITY,
+            json!([arg("recordLeafMetadata", "bool")]),
+        ),
+        instruction(
+            "EnableLeafPages",
+            enable_leaf_pages_ix(program_id, &key, 0..0),
+            &["merkleState", "authority", "systemProgram"],
+            json!([arg("firstPage", "u32"), arg("pagesCount", "u32")]),
+        ),
+        instruction(
+            "InitTree",
+            init_tree_ix(program_id, &key, [0; 32], 0, &TreeHasher::default()),
+            &INSERT[..INSERT.len() - 1],
+            json!([
+                arg("hash", hash()),
+                arg("leafCapacity", "u32"),
+                arg("hashAlgo", defined("HashAlgo")),
+                arg("domainSeparation", "bool"),
+                arg("ordered", "bool"),
+                arg("leftBalanced", "bool"),
+                arg("zeroPadded", "bool")
+            ]),
+        ),
+        instruction(
+            "MigrateState",
+            migrate_state_ix(program_id, &key),
+            PAYER,
+            json!([]),
+        ),
+        instruction(
+            "SetMetadata",
+            set_metadata_ix(
+                program_id,
+                &key,
+                String::new(),
+                String::new(),
+                String::new(),
+            ),
+            &["merkleState", "authority", "systemProgram", "treeMetadata"],
+            json!([
+                arg("name", "string"),
+                arg("description", "string"),
+                arg("uri", "string")
+            ]),
+        ),
+        instruction(
+            "AddInserter",
+            add_inserter_ix(program_id, &key, key),
+            PAYER,
+            json!([arg("inserter", "publicKey")]),
+        ),
+        instruction(
+            "RemoveInserter",
+            remove_inserter_ix(program_id, &key, key),
+            AUTHORITY,
+            json!([arg("inserter", "publicKey")]),
+        ),
+        instruction(
+            "SetPermissioned",
+            set_permissioned_ix(program_id, &key, false),
+            AUTHORITY,
+            json!([arg("permissioned", "bool")]),
+        ),
+        instruction(
+            "SetMultisig",
+            set_multisig_ix(program_id, &key, &key, 0, Vec::new()),
+            &[
+                "merkleState",
+                "authority",
+                "payer",
+                "systemProgram",
+                "multisig",
+            ],
+            json!([
+                arg("threshold", "u8"),
+                arg("signers", json!({ "vec": "publicKey" }))
+            ]),
+        ),
+        instruction(
+            "SetRateLimit",
+            set_rate_limit_ix(program_id, &key, 0, 0),
+            AUTHORITY,
+            json!([
+                arg("maxInsertsPerSlot", "u32"),
+                arg("maxInsertsPerEpoch", "u32")
+            ]),
+        ),
+        instruction(
+            "SetMaxLeaves",
+            set_max_leaves_ix(program_id, &key, 0),
+            AUTHORITY,
+            json!([arg("maxLeaves", "u32")]),
+        ),
+        instruction(
+            "SetRejectSentinelLeaves",
+            set_reject_sentinel_leaves_ix(program_id, &key, false),
+            AUTHORITY,
+            json!([arg("rejectSentinelLeaves", "bool")]),
+        ),
+        instruction(
+            "SetConfigTimelock",
+            set_config_timelock_ix(program_id, &key, 0),
+            AUTHORITY,
+            json!([arg("configTimelock", "u64")]),
+        ),
+        instruction(
+            "QueueConfig",
+            queue_config_ix(program_id, &key, &key, ConfigChange::MaxLeaves(0)),
+            &[
+                "merkleState",
+                "authority",
+                "payer",
+                "systemProgram",
+                "pendingConfig",
+            ],
+            json!([arg("change", defined("ConfigChange"))]),
+        ),
+        instruction(
+            "ApplyPendingConfig",
+            apply_pending_config_ix(program_id, &key),
+            &["merkleState", "authority", "systemProgram", "pendingConfig"],
+            json!([]),
+        ),
+        instruction(
+            "CancelPendingConfig",
+            cancel_pending_config_ix(program_id, &key),
+            &["merkleState", "authority", "pendingConfig"],
+            json!([]),
+        ),
+        instruction(
+            "SetGrowthPercent",
+            set_growth_percent_ix(program_id, &key, 0),
+            AUTHORITY,
+            json!([arg("growthPercent", "u16")]),
+        ),
+        instruction(
+            "GrowTree",
+            grow_tree_ix(program_id, &key, 0),
+            PAYER,
+            json!([arg("targetLen", "u32")]),
+        ),
+        instruction(
+            "SetCanopyDepth",
+            set_canopy_depth_ix(program_id, &key, 0),
+            PAYER,
+            json!([arg("canopyDepth", "u8")]),
+        ),
+        instruction(
+            "SetLazyRoot",
+            set_lazy_root_ix(program_id, &key, false),
+            AUTHORITY,
+            json!([arg("lazyRoot", "bool")]),
+        ),
+        instruction(
+            "CommitRoot",
+            commit_root_ix(program_id),
+            &["merkleState", "eventAuthority", "program"],
+            json!([]),
+        ),
+    ];
+
+    json!({
+        "version": env!("CARGO_PKG_VERSION"),
+        "name": "merkle_tree_program",
+        "instructions": instructions,
+        "types": types(),
+        "metadata": {
+            "origin": "shank",
+            "address": program_id.to_string(),
+        },
+    })
+}
+
+/// Instruction `name` taking the accounts of `instruction`, named by `accounts`.
+fn instruction(name: &str, instruction: Instruction, accounts: &[&str], args: Value) -> Value {
+    assert_eq!(
+        instruction.accounts.len(),
+        accounts.len(),
+        "Accounts of `{name}` are out of sync"
+    );
+    let accounts: Vec<Value> = instruction
+        .accounts
+        .iter()
+        .zip(accounts)
+        .map(|(account, name)| {
+            json!({
+                "name": name,
+                "isMut": account.is_writable,
+                "isSigner": account.is_signer,
+            })
+        })
+        .collect();
+
+    json!({
+        "name": name,
+        "accounts": accounts,
+        "args": args,
+        "discriminant": { "type": "u8", "value": instruction.data[0] },
+    })
+}
+
+/// Insert-like instruction `name`, taking the leaf page of the leaf in trees with leaf pages.
+fn insert(name: &str, instruction: Instruction, args: Value) -> Value {
+    let mut idl_instruction = self::instruction(name, with_leaf_page(instruction, 0), INSERT, args);
+    idl_instruction["accounts"][INSERT.len() - 1]["isOptional"] = json!(true);
+
+    idl_instruction
+}
+
+fn arg(name: &str, ty: impl Into<Value>) -> Value {
+    json!({ "name": name, "type": ty.into() })
+}
+
+fn defined(name: &str) -> Value {
+    json!({ "defined": name })
+}
+
+fn hash() -> Value {
+    json!({ "array": ["u8", 32] })
+}
+
+fn proof() -> Value {
+    json!({ "vec": hash() })
+}
+
+fn types() -> Value {
+    let field = |name: &str, ty: &str| json!({ "name": name, "type": ty });
+    json!([
+        {
+            "name": "HashAlgo",
+            "type": {
+                "kind": "enum",
+                "variants": [
+                    { "name": "Sha256" },
+                    { "name": "Keccak256" },
+                    { "name": "Poseidon" },
+                    { "name": "Blake3" },
+                    { "name": "Sha256d" }
+                ]
+            }
+        },
+        {
+            "name": "EventVerbosity",
+            "type": {
+                "kind": "enum",
+                "variants": [
+                    { "name": "None" },
+                    { "name": "RootOnly" },
+                    { "name": "Full" }
+                ]
+            }
+        },
+        {
+            "name": "ConfigChange",
+            "type": {
+                "kind": "enum",
+                "variants": [
+                    { "name": "RejectDuplicates", "fields": ["bool"] },
+                    { "name": "RecordLeafMetadata", "fields": ["bool"] },
+                    { "name": "EventVerbosity", "fields": [defined("EventVerbosity")] },
+                    { "name": "Permissioned", "fields": ["bool"] },
+                    { "name": "AddInserter", "fields": ["publicKey"] },
+                    { "name": "RemoveInserter", "fields": ["publicKey"] },
+                    {
+                        "name": "RateLimit",
+                        "fields": [
+                            field("maxInsertsPerSlot", "u32"),
+                            field("maxInsertsPerEpoch", "u32")
+                        ]
+                    },
+                    { "name": "MaxLeaves", "fields": ["u32"] },
+                    { "name": "RejectSentinelLeaves", "fields": ["bool"] },
+                    { "name": "ConfigTimelock", "fields": ["u64"] },
+                    { "name": "GrowthPercent", "fields": ["u16"] },
+                    { "name": "CanopyDepth", "fields": ["u8"] },
+                    { "name": "LazyRoot", "fields": ["bool"] }
+                ]
+            }
+        }
+    ])
+}
@@ -0,0 +1,196 @@
+use merkle_tree_client::events::parse_inserts;
+use merkle_tree_program::hash::TreeHasher;
+use serde::{Deserialize, Serialize};
+use solana_client::{nonblocking::rpc_client::RpcClient, rpc_config::RpcTransactionConfig};
+use solana_sdk::{pubkey::Pubkey, signature::Signature};
+use std::{
+    collections::{HashMap, VecDeque},
+    path::Path,
+};
+
+/// Entry of an `insert-batch` file: a value hashed as `insert-leaf` hashes it, or a
+/// precomputed hex leaf hash. CSV files have a `value` or `hash` column, JSON files are an
+/// array of objects with either field.
+#[derive(Debug, Clone, Deserialize)]
+pub struct BatchInput {
+    pub value: Option<u32>,
+    pub hash: Option<String>,
+}
+
+impl BatchInput {
+    pub fn leaf(&self, tree_hasher: &TreeHasher) -> [u8; 32] {
+        match (self.value, &self.hash) {
+            (Some(value), None) => tree_hasher.hash_value(&value.to_le_bytes()),
+            (None, Some(hash)) => hex::decode(hash)
+                .ok()
+                .and_then(|bytes| bytes.try_into().ok())
+                .unwrap_or_else(|| panic!("Invalid leaf hash hex: {hash}")),
+            _ => panic!("Batch entry must have either a value or a hash: {self:?}"),
+        }
+    }
+}
+
+/// Outcome of one `insert-batch` entry, `leaf_index` unknown for trees not emitting
+/// `LeafInserted` events.
+#[derive(Debug, Serialize)]
+pub struct BatchResult {
+    pub value: Option<u32>,
+    pub hash: String,
+    pub leaf_index: Option<u32>,
+    pub signature: Option<String>,
+    pub error: Option<String>,
+}
+
+/// Entries of the CSV or, with a `.json` extension, JSON file at `path`.
+pub fn read_inputs(path: &Path) -> Vec<BatchInput> {
+    let data = std::fs::read(path).expect("Can't read batch file");
+    match is_json(path) {
+        true => serde_json::from_slice(&data).expect("Invalid batch JSON"),
+        false => csv::ReaderBuilder::new()
+            .trim(csv::Trim::All)
+            .from_reader(data.as_slice())
+            .deserialize()
+            .collect::<Result<_, _>>()
+            .expect("Invalid batch CSV"),
+    }
+}
+
+/// Write `results` as CSV or, with a `.json` extension, JSON to `path`.
+pub fn write_results(path: &Path, results: &[BatchResult]) {
+    match is_json(path) {
+        true => std::fs::write(
+            path,
+            serde_json::to_string_pretty(results).expect("Can't serialize results"),
+        )
+        .expect("Can't write results file"),
+        false => {
+            let mut writer = csv::Writer::from_path(path).expect("Can't create results file");
+            results
+                .iter()
+                .for_each(|result| writer.serialize(result).expect("Can't write results file"));
+            writer.flush().expect("Can't write results file");
+        }
+    }
+}
+
+/// Leaf index of each insert sent in transaction `signatures`, read from the `LeafInserted`
+/// events of each transaction in order.
+pub async fn leaf_indices(
+    client: &RpcClient,
+    program_id: &Pubkey,
+    signatures: &[Option<Signature>],
+) -> Vec<Option<u32>> {
+    let mut inserts: HashMap<Signature, VecDeque<u32>> = HashMap::new();
+    for signature in signatures.iter().flatten() {
+        if inserts.contains_key(signature) {
+            continue;
+        }
+        let tx = client
+            .get_transaction_with_config(signature, RpcTransactionConfig::default())
+            .await
+            .expect("Can't get tx by sig");
+        let leaf_indices = parse_inserts(program_id, &tx.transaction)
+            .unwrap_or_else(|err| panic!("Can't parse tx events: {err}"))
+            .iter()
+            .map(|insert| insert.leaf_index)
+            .collect();
+        inserts.insert(*signature, leaf_indices);
+    }
+
+    signatures
+        .iter()
+        .map(|signature| signature.and_then(|signature| inserts.get_mut(&signature)?.pop_front()))
+        .collect()
+}
+
+fn is_json(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|extension| extension == "json")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Path of a scratch file named `name` in the temp dir.
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("{}-{name}", std::process::id()))
+    }
+
+    #[test]
+    fn reads_csv_and_json_inputs() {
+        let hash = "22".repeat(32);
+        let csv_path = temp_path("batch.csv");
+        std::fs::write(&csv_path, format!("value,hash\n7,\n, {hash}\n")).unwrap();
+        let json_path = temp_path("batch.json");
+        std::fs::write(
+            &json_path,
+            format!(r#"[{{"value": 7}}, {{"hash": "{hash}"}}]"#),
+        )
+        .unwrap();
+
+        let tree_hasher = TreeHasher::default();
+        for path in [csv_path, json_path] {
+            let inputs = read_inputs(&path);
+            std::fs::remove_file(&path).unwrap();
+
+            let leaves: Vec<_> = inputs
+                .iter()
+                .map(|input| input.leaf(&tree_hasher))
+                .collect();
+            assert_eq!(
+                leaves,
+                [tree_hasher.hash_value(&7u32.to_le_bytes()), [0x22; 32]]
+            );
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "either a value or a hash")]
+    fn input_needs_value_or_hash() {
+        let input = BatchInput {
+            value: Some(1),
+            hash: Some("22".repeat(32)),
+        };
+        input.leaf(&TreeHasher::default());
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid leaf hash hex")]
+    fn input_hash_must_be_32_bytes() {
+        let input = BatchInput {
+            value: None,
+            hash: Some("22".repeat(31)),
+        };
+        input.leaf(&TreeHasher::default());
+    }
+
+    #[test]
+    fn writes_csv_results() {
+        let path = temp_path("results.csv");
+        let results = [
+            BatchResult {
+                value: Some(7),
+                hash: "aa".into(),
+                leaf_index: Some(0),
+                signature: Some("sig".into()),
+                error: None,
+            },
+            BatchResult {
+                value: None,
+                hash: "bb".into(),
+                leaf_index: None,
+                signature: None,
+                error: Some("TreePaused".into()),
+            },
+        ];
+
+        write_results(&path, &results);
+        let csv = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(
+            csv,
+            "value,hash,leaf_index,signature,error\n7,aa,0,sig,\n,bb,,,TreePaused\n"
+        );
+    }
+}
@@ -1,86 +1,856 @@
+use archive::{Snapshot, publish_to_ipfs};
 use borsh::BorshDeserialize;
-use clap::{Parser, Subcommand};
-use merkle_tree_program::{instruction, state::MerkleStateAccount, utils::find_merkle_state_pda};
-use sha2::{Digest, Sha256};
-use solana_client::{nonblocking::rpc_client::RpcClient, rpc_config::RpcTransactionConfig};
+use clap::{Parser, Subcommand, ValueEnum};
+use commitment::{CommitmentProof, SaltArgs};
+use confirm::{Change, confirm, confirm_irreversible};
+use futures::StreamExt;
+use insert_batch::BatchResult;
+use merkle_tree_client::{
+    account::{fetch_leaves, fetch_merkle_state, fetch_merkle_tree, fetch_tree_hasher},
+    batch::{BatchConfig, insert_leaves},
+    events::parse_inserts,
+    instruction::{
+        accept_authority_ix, add_inserter_ix, apply_pending_config_ix, cancel_pending_config_ix,
+        commit_root_ix, config_change_ix, enable_frontier_mode_ix, enable_leaf_pages_ix,
+        finalize_tree_ix, get_root_hash_ix, grow_tree_ix, init_tree_ix, insert_expiring_leaf_ix,
+        insert_leaf_ix, insert_value_ix, migrate_state_ix, pause_tree_ix, propose_authority_ix,
+        queue_config_ix, resume_tree_ix, set_archive_uri_ix, set_metadata_ix, set_multisig_ix,
+        set_permissioned_ix, sweep_expired_ix, verify_consistency_ix, verify_leaf_ix,
+        with_leaf_page, with_multisig,
+    },
+    send::{SendConfig, send_transaction},
+    subscription::{RootUpdate, subscribe_roots},
+    transaction::{MAX_COMPUTE_UNIT_LIMIT, TransactionBuilder},
+    tree::verify_proof,
+};
+use merkle_tree_core::MerkleProof;
+use merkle_tree_errors::{MerkleTreeError, ProofError, SdkError};
+use merkle_tree_program::{
+    config::{ConfigChange, PendingConfigAccount},
+    events::EventVerbosity,
+    hash::{HashAlgo, TreeHasher},
+    leaf_page,
+    multisig::MultisigAccount,
+    pda::{
+        find_merkle_state_pda, find_multisig_pda, find_pending_config_pda, find_tree_metadata_pda,
+    },
+    return_data::ReturnData,
+    state::MerkleStateAccount,
+    tree_metadata::TreeMetadataAccount,
+    utils::hash_salted_value,
+};
+use output::OutputFormat;
+use profile::{Profile, Profiles};
+use serde_json::json;
+use solana_client::{
+    nonblocking::{pubsub_client::PubsubClient, rpc_client::RpcClient},
+    rpc_config::{RpcSimulateTransactionConfig, RpcTransactionConfig},
+    rpc_response::RpcSimulateTransactionResult,
+};
 use solana_sdk::{
-    instruction::{AccountMeta, Instruction},
+    commitment_config::CommitmentConfig,
+    entrypoint::MAX_PERMITTED_DATA_INCREASE,
+    instruction::Instruction,
     pubkey::Pubkey,
-    signature::read_keypair_file,
+    signature::{Keypair, Signature, read_keypair_file},
     signer::Signer,
-    system_program,
-    transaction::Transaction,
+    transaction::{Transaction, TransactionError},
 };
-use solana_transaction_status::option_serializer::OptionSerializer;
-use std::path::PathBuf;
+use std::{io::Read, path::PathBuf, sync::Arc, time::Duration};
+use stress::{StressConfig, parse_duration, run_stress};
+
+mod archive;
+mod commitment;
+mod confirm;
+mod deployments;
+mod health;
+mod idl;
+mod insert_batch;
+mod output;
+mod profile;
+mod stress;
+
+/// `GrowTree` instructions sent per transaction, each reallocs and tops up rent.
+const GROW_TREE_IXS_PER_TX: usize = 16;
+
+/// Leaf pages written per `EnableLeafPages` transaction, each is created and paid for.
+const LEAF_PAGES_PER_TX: u32 = 8;
 
 #[derive(Debug, Parser)]
 #[command(version, about, long_about = None)]
 struct Args {
-    /// Solana RPC URL.
-    #[arg(short, long, default_value = "http://127.0.0.1:8899")]
-    url: String,
-
-    /// Merkle tree program id.
-    #[arg(
-        short,
-        long,
-        default_value = "FuWr9Bgn4aWiXLzDoV69Amp3pLwThpjwXJVAE7GTT7bV"
-    )]
-    program_id: Pubkey,
+    /// Solana RPC URL [default: `--cluster` deployment URL].
+    #[arg(short, long)]
+    url: Option<String>,
+
+    /// Merkle tree program id [default: `--cluster` deployment program id].
+    #[arg(short, long)]
+    program_id: Option<Pubkey>,
+
+    /// Cluster from `deployments.json` to take defaults from [default: localnet].
+    #[arg(short, long)]
+    cluster: Option<String>,
 
     /// Keypair path.
     #[arg(short, long)]
-    keypair_path: PathBuf,
+    keypair_path: Option<PathBuf>,
+
+    /// Named profile from `~/.merkle-cli/` to take defaults from.
+    #[arg(short, long)]
+    tree_profile: Option<String>,
+
+    /// Skip confirmation prompts of admin commands.
+    #[arg(short, long)]
+    yes: bool,
+
+    /// Keypair path of a tree multisig signer, repeated up to its threshold. Admin commands
+    /// then act as the tree multisig instead of the keypair, which only pays fees.
+    #[arg(long = "multisig-signer")]
+    multisig_signers: Vec<PathBuf>,
+
+    /// Commitment transactions are confirmed and accounts are read at.
+    #[arg(long, value_enum, default_value_t = CommitmentArg::Finalized)]
+    commitment: CommitmentArg,
+
+    /// Output format, `json` prints one JSON object per line with stable field names.
+    #[arg(long, value_enum, default_value_t)]
+    output: OutputFormat,
 
     #[command(subcommand)]
     command: Commands,
 }
 
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum CommitmentArg {
+    Processed,
+    Confirmed,
+    Finalized,
+}
+
+impl From<CommitmentArg> for CommitmentConfig {
+    fn from(commitment: CommitmentArg) -> Self {
+        match commitment {
+            CommitmentArg::Processed => Self::processed(),
+            CommitmentArg::Confirmed => Self::confirmed(),
+            CommitmentArg::Finalized => Self::finalized(),
+        }
+    }
+}
+
+/// Proof of a leaf: the proof file from `get-proof`, JSON or hex, or its parts.
+#[derive(Debug, clap::Args)]
+struct ProofArgs {
+    #[arg(required_unless_present = "leaf", conflicts_with_all = ["leaf", "leaf_index", "path"])]
+    proof_path: Option<PathBuf>,
+    #[arg(long, requires = "leaf")]
+    leaf_index: Option<u32>,
+    /// Hex leaf hash.
+    #[arg(long, value_parser = parse_hash, requires = "leaf_index")]
+    leaf: Option<[u8; 32]>,
+    /// Hex proof nodes, comma separated.
+    #[arg(long, value_parser = parse_hash, value_delimiter = ',')]
+    path: Vec<[u8; 32]>,
+}
+
+impl ProofArgs {
+    /// Leaf index, leaf and proof path.
+    fn read(self) -> (u32, [u8; 32], Vec<[u8; 32]>) {
+        match self.proof_path {
+            Some(proof_path) => {
+                let proof = read_proof(&proof_path);
+                (proof.leaf_index, proof.leaf, proof.path)
+            }
+            None => (
+                self.leaf_index.expect("Leaf index is not set"),
+                self.leaf.expect("Leaf is not set"),
+                self.path,
+            ),
+        }
+    }
+}
+
+/// Value to hash into a leaf, encoded to the bytes `TreeHasher::hash_value` takes: a `u32`
+/// as 4 little-endian bytes, a string as its UTF-8 bytes, hex as the decoded bytes and files
+/// or stdin as their raw contents.
+#[derive(Debug, clap::Args)]
+#[group(required = true, multiple = false)]
+struct ValueArgs {
+    /// Encoded as 4 little-endian bytes.
+    value: Option<u32>,
+    /// Encoded as its UTF-8 bytes.
+    #[arg(long)]
+    string: Option<String>,
+    /// Hex bytes, optionally `0x` prefixed.
+    #[arg(long)]
+    hex: Option<String>,
+    /// Path of a file taken as raw bytes.
+    #[arg(long)]
+    file: Option<PathBuf>,
+    /// Read the value from stdin until EOF, trailing newlines included.
+    #[arg(long)]
+    stdin: bool,
+}
+
+impl ValueArgs {
+    /// Canonical bytes of the value.
+    fn bytes(self) -> Vec<u8> {
+        self.read(std::io::stdin())
+    }
+
+    /// Canonical bytes of the value, read from `stdin` with `--stdin`.
+    fn read(self, mut stdin: impl Read) -> Vec<u8> {
+        if let Some(value) = self.value {
+            return value.to_le_bytes().to_vec();
+        }
+        if let Some(string) = self.string {
+            return string.into_bytes();
+        }
+        if let Some(hex) = self.hex {
+            return hex::decode(hex.strip_prefix("0x").unwrap_or(&hex))
+                .unwrap_or_else(|_| panic!("Invalid value hex: {hex}"));
+        }
+        if let Some(file) = self.file {
+            return std::fs::read(file).expect("Can't read value file");
+        }
+
+        let mut bytes = Vec::new();
+        stdin.read_to_end(&mut bytes).expect("Can't read stdin");
+        bytes
+    }
+}
+
+/// Hashing scheme of a tree, see `TreeHasher`.
+#[derive(Debug, clap::Args)]
+struct TreeHasherArgs {
+    /// Hash function of the tree nodes.
+    #[arg(long, value_enum, default_value_t)]
+    hash_algo: HashAlgoArg,
+    /// Prefix leaf values and nodes by their kind before hashing.
+    #[arg(long)]
+    domain_separation: bool,
+    /// Hash nodes in tree order instead of as sorted pairs.
+    #[arg(long)]
+    ordered: bool,
+    /// Promote nodes without sibling instead of pairing them with themselves.
+    #[arg(long)]
+    left_balanced: bool,
+    /// Pair nodes without sibling with the empty subtree of their level instead of with
+    /// themselves.
+    #[arg(long, conflicts_with = "left_balanced")]
+    zero_padded: bool,
+    /// RFC 6962 (Certificate Transparency) hashing, sets all the options above.
+    #[arg(long, conflicts_with_all = ["hash_algo", "domain_separation", "ordered", "left_balanced", "zero_padded"])]
+    rfc6962: bool,
+}
+
+impl From<TreeHasherArgs> for TreeHasher {
+    fn from(args: TreeHasherArgs) -> Self {
+        match args.rfc6962 {
+            true => Self::RFC6962,
+            false => Self {
+                hash_algo: args.hash_algo.into(),
+                domain_separation: args.domain_separation,
+                ordered: args.ordered,
+                left_balanced: args.left_balanced,
+                zero_padded: args.zero_padded,
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum ProofFormat {
+    /// `MerkleProof` as JSON with hex hashes.
+    Json,
+    /// Hex of the borsh encoded `MerkleProof`.
+    Hex,
+    /// Hex proof nodes only, one per line.
+    Path,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum EventVerbosityArg {
+    /// No events.
+    None,
+    /// Root updates only.
+    RootOnly,
+    /// Inserted leaves and root updates.
+    Full,
+}
+
+impl From<EventVerbosityArg> for EventVerbosity {
+    fn from(event_verbosity: EventVerbosityArg) -> Self {
+        match event_verbosity {
+            EventVerbosityArg::None => Self::None,
+            EventVerbosityArg::RootOnly => Self::RootOnly,
+            EventVerbosityArg::Full => Self::Full,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, ValueEnum)]
+enum HashAlgoArg {
+    #[default]
+    Sha256,
+    /// Ethereum compatible sorted pairs, via the native syscall. Matches OpenZeppelin's
+    /// `MerkleProof` unless combined with other hashing options.
+    #[value(alias = "openzeppelin")]
+    Keccak256,
+    /// BN254 Poseidon for ZK circuits, leaves must be field elements.
+    Poseidon,
+    /// Fast off-chain, needs a program built with the `blake3` feature.
+    Blake3,
+    /// Double SHA-256 as in Bitcoin.
+    Sha256d,
+}
+
+impl From<HashAlgoArg> for HashAlgo {
+    fn from(hash_algo: HashAlgoArg) -> Self {
+        match hash_algo {
+            HashAlgoArg::Sha256 => Self::Sha256,
+            HashAlgoArg::Keccak256 => Self::Keccak256,
+            HashAlgoArg::Poseidon => Self::Poseidon,
+            HashAlgoArg::Blake3 => Self::Blake3,
+            HashAlgoArg::Sha256d => Self::Sha256d,
+        }
+    }
+}
+
 #[derive(Debug, Subcommand)]
 enum Commands {
-    /// Send `InsertLeaf` transaction instruction.
-    InsertLeaf { value: u32 },
-    /// Fetch root hash from merkle state pda.
+    /// Send `InsertLeaf` transaction instruction of the hashed value, `InsertExpiringLeaf`
+    /// with `--expires-at`.
+    InsertLeaf {
+        #[command(flatten)]
+        value: ValueArgs,
+        /// Unix timestamp the leaf can be swept at.
+        #[arg(long)]
+        expires_at: Option<i64>,
+        /// Hex root the tree must still have, the insert fails with `RootMismatch` otherwise.
+        #[arg(long, value_parser = parse_hash)]
+        expected_root: Option<[u8; 32]>,
+    },
+    /// Create the tree with first leaf `value`, preallocated for `leaf_capacity` leaves
+    /// unless it's 0.
+    InitTree {
+        value: u32,
+        leaf_capacity: u32,
+        #[command(flatten)]
+        tree_hasher: TreeHasherArgs,
+    },
+    /// Create the tree with first leaf `value` and its config in one transaction, printing the
+    /// merkle state PDA, the program's single tree. The keypair pays and is the authority
+    /// until `--authority` accepts.
+    CreateTree {
+        value: u32,
+        /// Leaves to preallocate rent and space for, 0 to grow with inserts.
+        #[arg(long, default_value_t = 0)]
+        capacity: u32,
+        /// Authority to propose, which takes over once it sends `accept-authority`.
+        #[arg(long)]
+        authority: Option<Pubkey>,
+        /// Accept inserts only from `--inserter`s.
+        #[arg(long)]
+        permissioned: bool,
+        /// Inserter to allow, repeated for each.
+        #[arg(long = "inserter", requires = "permissioned")]
+        inserters: Vec<Pubkey>,
+        #[command(flatten)]
+        tree_hasher: TreeHasherArgs,
+    },
+    /// Insert the values or hex leaf hashes of CSV or JSON `file`, see `BatchInput`, packed
+    /// into as few transactions as fit. Writes each entry's leaf index, signature or error to
+    /// `out`, JSON for a `.json` extension and CSV otherwise.
+    InsertBatch {
+        #[arg(long)]
+        file: PathBuf,
+        #[arg(long, default_value = "results.csv")]
+        out: PathBuf,
+        /// Transactions in flight at once.
+        #[arg(long, default_value_t = 8)]
+        max_concurrency: usize,
+        /// Compute unit price in micro-lamports.
+        #[arg(long)]
+        priority_fee: Option<u64>,
+    },
+    /// Send `InsertValue` transaction instruction, leaf hash is computed on-chain.
+    InsertValue {
+        value: u32,
+        /// Hex root the tree must still have, the insert fails with `RootMismatch` otherwise.
+        #[arg(long, value_parser = parse_hash)]
+        expected_root: Option<[u8; 32]>,
+    },
+    /// Send `InsertLeaf` with salted commitment `sha256(salt || value)` hiding `value`.
+    InsertCommitment {
+        value: u32,
+        #[command(flatten)]
+        salt: SaltArgs,
+    },
+    /// Print JSON proof revealing committed `value` and its salt only.
+    ProveCommitment {
+        value: u32,
+        #[command(flatten)]
+        salt: SaltArgs,
+    },
+    /// Check commitment proof file from `prove-commitment` against the tree.
+    VerifyCommitment { proof_path: PathBuf },
+    /// Print proof for leaf `leaf_index`, the leaf of `--value` or `--leaf-hash`, built from
+    /// the fetched leaves.
+    GetProof {
+        #[arg(required_unless_present_any = ["value", "leaf_hash"], conflicts_with_all = ["value", "leaf_hash"])]
+        leaf_index: Option<u32>,
+        /// First leaf hashed from `value` as `insert-leaf` hashes it.
+        #[arg(long, conflicts_with = "leaf_hash")]
+        value: Option<u32>,
+        /// First leaf with hex hash `leaf_hash`.
+        #[arg(long, value_parser = parse_hash)]
+        leaf_hash: Option<[u8; 32]>,
+        #[arg(long, value_enum, default_value_t = ProofFormat::Json)]
+        format: ProofFormat,
+    },
+    /// Print leaf hashes in insertion order, one per line, from leaf `offset` on.
+    ListLeaves {
+        #[arg(long, default_value_t = 0)]
+        offset: u32,
+        /// Max leaves to print, all remaining if unset.
+        #[arg(long)]
+        limit: Option<u32>,
+        /// Prefix each leaf with its index.
+        #[arg(long)]
+        indexes: bool,
+        /// Append slot and inserter of leaves inserted while the tree recorded leaf metadata,
+        /// `-` for other leaves.
+        #[arg(long)]
+        metadata: bool,
+    },
+    /// Print each new root with its slot and leaves count, and the leaves inserted since, as
+    /// updates of the merkle state account arrive over the websocket. Runs until interrupted.
+    Watch {
+        /// Websocket URL [default: RPC URL with `ws` scheme and port + 1].
+        #[arg(long)]
+        ws_url: Option<String>,
+    },
+    /// Check proof file from `get-proof`, JSON or hex, against the tree via simulated
+    /// `VerifyLeaf` instruction.
+    VerifyProof { proof_path: PathBuf },
+    /// Check a proof against trusted hex `root` locally, without RPC: the proof file from
+    /// `get-proof`, JSON or hex, or `--leaf-index`, `--leaf` and `--path`. Exits with 1 if it
+    /// doesn't verify.
+    VerifyProofOffline {
+        #[arg(value_parser = parse_hash)]
+        root: [u8; 32],
+        #[command(flatten)]
+        proof: ProofArgs,
+        /// Leaves count of the tree, required for left-balanced trees.
+        #[arg(long)]
+        leaves_count: Option<u32>,
+        #[command(flatten)]
+        tree_hasher: TreeHasherArgs,
+    },
+    /// Send `VerifyLeaf` transaction instruction for the proof file from `get-proof`, JSON or
+    /// hex, or `--leaf-index`, `--leaf` and `--path`. Exits with 1 if the program rejects it.
+    VerifyOnchain {
+        #[command(flatten)]
+        proof: ProofArgs,
+    },
+    /// Check the tree at hex `old_root` with `old_leaves_count` leaves is a prefix of the
+    /// current one via simulated `VerifyConsistency` instruction.
+    VerifyConsistency {
+        old_root: String,
+        old_leaves_count: u32,
+    },
+    /// Validate the merkle state account and print a JSON report, failing if any check fails.
+    HealthCheck,
+    /// Fetch root hash via simulated `GetRootHash` instruction.
     GetRootHash,
-    /// Compute sha256 hash for `value`.
-    GetValueHash { value: u32 },
+    /// Compute leaf hash for the value with the tree hash function.
+    GetValueHash {
+        #[command(flatten)]
+        value: ValueArgs,
+    },
+    /// Propose `new_authority` as merkle tree authority, signed by the current one.
+    ProposeAuthority { new_authority: Pubkey },
+    /// Accept merkle tree authority, signed by the pending one.
+    AcceptAuthority,
+    /// Pause merkle tree, rejecting leaf changes, sealing and leaf storage changes until resumed.
+    PauseTree,
+    /// Resume paused merkle tree.
+    ResumeTree,
+    /// Permanently seal merkle tree against further inserts.
+    FinalizeTree,
+    /// Permanently drop stored leaves keeping only the tree frontier, refunding freed rent.
+    EnableFrontierMode,
+    /// Permanently move stored leaves to leaf page accounts keeping only the tree frontier.
+    EnableLeafPages,
+    /// Upgrade merkle state account of an older layout version to the current one.
+    MigrateState,
+    /// Grow the tree account ahead of inserts to `target_len` bytes, one realloc per
+    /// instruction. Only trees preallocated or growing geometrically keep the space.
+    GrowTree { target_len: u32 },
+    /// Set which events the tree emits on inserts and replacements.
+    SetEventVerbosity { event_verbosity: EventVerbosityArg },
+    /// Tombstone expired leaves, collecting the sweep bounty.
+    SweepExpired,
+    /// Toggle rejection of leaves already present in the tree.
+    SetRejectDuplicates {
+        #[arg(action = clap::ArgAction::Set)]
+        reject_duplicates: bool,
+    },
+    /// Toggle rejection of the zero hash and empty subtree roots as leaves.
+    SetRejectSentinelLeaves {
+        #[arg(action = clap::ArgAction::Set)]
+        reject_sentinel_leaves: bool,
+    },
+    /// Toggle recording slot and inserter of newly inserted leaves.
+    SetRecordLeafMetadata {
+        #[arg(action = clap::ArgAction::Set)]
+        record_leaf_metadata: bool,
+    },
+    /// Toggle accepting inserts only from allowed inserters.
+    SetPermissioned {
+        #[arg(action = clap::ArgAction::Set)]
+        permissioned: bool,
+    },
+    /// Allow `inserter` to insert while the tree is permissioned.
+    AddInserter { inserter: Pubkey },
+    /// Disallow `inserter`, refunding its rent.
+    RemoveInserter { inserter: Pubkey },
+    /// Limit inserts of each payer per slot and per epoch, 0 lifts a limit.
+    SetRateLimit {
+        #[arg(long, default_value_t = 0)]
+        per_slot: u32,
+        #[arg(long, default_value_t = 0)]
+        per_epoch: u32,
+    },
+    /// Cap the tree at `max_leaves` leaves, 0 lifts the cap.
+    SetMaxLeaves { max_leaves: u32 },
+    /// Add `growth_percent` of the leaves count in spare leaves whenever the tree account
+    /// grows, 0 grows it exactly and refunds spare bytes.
+    SetGrowthPercent { growth_percent: u16 },
+    /// Cache the top `canopy_depth` levels of the tree, so replacing a leaf only rehashes the
+    /// subtree below them. Deeper canopies grow the account.
+    SetCanopyDepth { canopy_depth: u8 },
+    /// Toggle only appending leaves on insert, e.g. while bulk loading, until `commit-root`.
+    /// The root must be committed before disabling it.
+    SetLazyRoot {
+        #[arg(action = clap::ArgAction::Set)]
+        lazy_root: bool,
+    },
+    /// Recompute and publish the root of leaves inserted in lazy root mode.
+    CommitRoot,
+    /// Delay config changes by `config_timelock` slots, 0 applies them directly again. Config
+    /// commands of a timelocked tree queue their change instead.
+    SetConfigTimelock { config_timelock: u64 },
+    /// Print config changes queued in a timelocked tree.
+    GetPendingConfig,
+    /// Apply queued config changes whose timelock passed.
+    ApplyPendingConfig,
+    /// Drop all queued config changes.
+    CancelPendingConfig,
+    /// Create or overwrite the tree multisig requiring `threshold` of `signers`.
+    SetMultisig {
+        threshold: u8,
+        #[arg(required = true)]
+        signers: Vec<Pubkey>,
+    },
+    /// Print slot and inserter recorded for leaf `leaf_index`.
+    GetLeafMetadata { leaf_index: u32 },
+    /// Set tree name, description and URI, signed by the authority.
+    SetMetadata {
+        name: String,
+        #[arg(long, default_value = "")]
+        description: String,
+        #[arg(long, default_value = "")]
+        uri: String,
+    },
+    /// Print tree name, description and URI.
+    GetMetadata,
+    /// Generate sustained `InsertLeaf` load and report throughput.
+    Stress {
+        /// Target transactions per second.
+        #[arg(long, default_value_t = 10, value_parser = clap::value_parser!(u32).range(1..))]
+        tps: u32,
+        /// Run duration, e.g. `30s`, `10m`, `1h`.
+        #[arg(long, default_value = "1m", value_parser = parse_duration)]
+        duration: Duration,
+        /// Compute unit price in micro-lamports.
+        #[arg(long, default_value_t = 0)]
+        priority_fee: u64,
+        /// First deterministic leaf index.
+        #[arg(long, default_value_t = 0)]
+        seed: u64,
+    },
+    /// Write tree leaves snapshot to `out` and print its content hash.
+    ExportSnapshot {
+        out: PathBuf,
+        /// Write zstd-compressed pages with a checksum manifest, verified when read.
+        #[arg(long)]
+        compress: bool,
+    },
+    /// Add tree leaves snapshot to IPFS and link it to the tree.
+    PublishSnapshot {
+        /// IPFS node HTTP API URL.
+        #[arg(long, default_value = "http://127.0.0.1:5001")]
+        ipfs_api: String,
+    },
+    /// Link snapshot already uploaded to `uri`(e.g. `ar://<tx id>`) to the tree.
+    SetArchiveUri {
+        uri: String,
+        /// Local copy of the uploaded snapshot, must match the on-chain leaves.
+        #[arg(long)]
+        snapshot: PathBuf,
+    },
+    /// Manage named profiles.
+    #[command(subcommand)]
+    Profile(ProfileCommands),
+    /// Inspect known program deployments.
+    #[command(subcommand)]
+    Deployments(DeploymentsCommands),
+    /// Print the shank format IDL of the program, for client generators like Codama.
+    Idl {
+        /// Write the IDL to `out` instead.
+        #[arg(long)]
+        out: Option<PathBuf>,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+enum DeploymentsCommands {
+    /// Print program id and canonical PDAs of `cluster`, or of all clusters.
+    Show { cluster: Option<String> },
+}
+
+#[derive(Debug, Subcommand)]
+enum ProfileCommands {
+    /// Save current `--url`, `--program-id` and `--keypair-path` as profile `name`.
+    Add { name: String },
+    /// List saved profiles.
+    List,
+    /// Remove profile `name`.
+    Remove { name: String },
 }
 
 #[tokio::main]
 async fn main() {
     let args = Args::parse();
-    let payer = read_keypair_file(&args.keypair_path).expect("Invalid keypair file/path");
+    output::init(args.output);
+    let mut profiles = Profiles::load();
 
-    let client = RpcClient::new(args.url);
-    let (merkle_state_pda, _) = find_merkle_state_pda(&args.program_id);
+    if let Commands::Deployments(DeploymentsCommands::Show { cluster }) = &args.command {
+        match cluster {
+            Some(cluster) => deployments::show(
+                deployments::find(cluster)
+                    .unwrap_or_else(|| panic!("Deployment `{cluster}` is not found")),
+            ),
+            None => deployments::DEPLOYMENTS.iter().for_each(deployments::show),
+        }
+        return;
+    }
 
-    match args.command {
-        Commands::InsertLeaf { value } => {
-            let hash: [u8; 32] = Sha256::digest(value.to_le_bytes()).into();
+    // Resolve connection settings: explicit args, then profile, then cluster deployment
+    let deployment = args.cluster.as_deref().map_or_else(
+        || deployments::find(deployments::DEFAULT_CLUSTER).expect("Default deployment is not set"),
+        |cluster| {
+            deployments::find(cluster)
+                .unwrap_or_else(|| panic!("Deployment `{cluster}` is not found"))
+        },
+    );
+    let profile = args.tree_profile.as_deref().map(|name| {
+        profiles
+            .get(name)
+            .cloned()
+            .unwrap_or_else(|| panic!("Profile `{name}` is not found"))
+    });
+    let url = args
+        .url
+        .or_else(|| profile.as_ref().map(|profile| profile.url.clone()))
+        .unwrap_or_else(|| deployment.url.to_string());
+    let program_id = args
+        .program_id
+        .or_else(|| {
+            profile.as_ref().map(|profile| {
+                profile
+                    .program_id
+                    .parse()
+                    .expect("Invalid profile program id")
+            })
+        })
+        .unwrap_or(deployment.program_id);
+    let keypair_path = args
+        .keypair_path
+        .or_else(|| profile.and_then(|profile| profile.keypair_path));
 
-            let insert_leaf_ix = Instruction::new_with_bytes(
-                args.program_id,
-                &instruction::MerkleTreeInstruction::InsertLeaf { hash }.pack(),
-                vec![
-                    AccountMeta::new(merkle_state_pda, false),
-                    AccountMeta::new(payer.pubkey(), true),
-                    AccountMeta::new_readonly(system_program::id(), false),
-                ],
+    let command = match args.command {
+        Commands::Profile(command) => {
+            process_profile_command(
+                &mut profiles,
+                command,
+                Profile {
+                    url,
+                    program_id: program_id.to_string(),
+                    keypair_path,
+                },
             );
+            return;
+        }
+        Commands::VerifyProofOffline {
+            root,
+            proof,
+            leaves_count,
+            tree_hasher,
+        } => {
+            let (leaf_index, leaf, path) = proof.read();
+            let tree_hasher = TreeHasher::from(tree_hasher);
+            let leaves_count = match tree_hasher.left_balanced {
+                true => leaves_count.expect("Left-balanced trees need --leaves-count"),
+                false => leaves_count.unwrap_or(u32::MAX),
+            };
 
-            let mut tx = Transaction::new_with_payer(&[insert_leaf_ix], Some(&payer.pubkey()));
-            let recent_blockhash = client
-                .get_latest_blockhash()
+            match verify_proof(&tree_hasher, &root, leaves_count, leaf_index, &leaf, &path) {
+                true => output::print(
+                    format_args!("Valid: leaf {leaf_index} is in the tree"),
+                    json!({ "valid": true, "leaf_index": leaf_index }),
+                ),
+                false => {
+                    output::print(
+                        "Invalid: proof doesn't verify against the root",
+                        json!({
+                            "valid": false,
+                            "leaf_index": leaf_index,
+                            "error": "proof doesn't verify against the root",
+                        }),
+                    );
+                    std::process::exit(1);
+                }
+            }
+            return;
+        }
+        Commands::Watch { ws_url } => {
+            let ws_url = ws_url.unwrap_or_else(|| websocket_url(&url));
+            let pubsub_client = PubsubClient::new(&ws_url)
                 .await
-                .expect("Can't get latest blockhash");
-            tx.sign(&[&payer], recent_blockhash);
+                .unwrap_or_else(|err| panic!("Can't connect to {ws_url}: {err}"));
+            let (mut updates, _unsubscribe) =
+                subscribe_roots(&pubsub_client, &program_id, args.commitment.into())
+                    .await
+                    .unwrap_or_else(|err| panic!("Can't subscribe to merkle state: {err}"));
+
+            while let Some(update) = updates.next().await {
+                let update = update.unwrap_or_else(|err| panic!("Invalid update: {err}"));
+                let first_leaf_index = update.leaves_count as usize - update.new_leaves.len();
+                let new_leaves: Vec<_> = (first_leaf_index..).zip(&update.new_leaves).collect();
+
+                let mut text = format!(
+                    "Slot {}: root {}, {} leaves",
+                    update.slot,
+                    hex::encode(update.root),
+                    update.leaves_count
+                );
+                for (leaf_index, leaf) in &new_leaves {
+                    text += &format!("\n  leaf {leaf_index}: {}", hex::encode(leaf));
+                }
+                output::print(text, root_update_json(&update, &new_leaves));
+            }
+            return;
+        }
+        Commands::Idl { out } => {
+            let idl = serde_json::to_string_pretty(&idl::idl(&program_id)).unwrap();
+            match out {
+                Some(out) => std::fs::write(out, idl + "\n").expect("Can't write IDL"),
+                None => output::print(&idl, serde_json::from_str(&idl).unwrap()),
+            }
+            return;
+        }
+        command => command,
+    };
+
+    let payer = read_keypair_file(keypair_path.expect("Keypair path is not set"))
+        .expect("Invalid keypair file/path");
+
+    let client = RpcClient::new_with_commitment(url, args.commitment.into());
+    let (merkle_state_pda, _) = find_merkle_state_pda(&program_id);
+    let (multisig_pda, _) = find_multisig_pda(&program_id, &merkle_state_pda);
+    let (pending_config_pda, _) = find_pending_config_pda(&program_id, &merkle_state_pda);
+    let multisig_signers: Vec<Keypair> = args
+        .multisig_signers
+        .iter()
+        .map(|path| read_keypair_file(path).expect("Invalid multisig signer keypair file/path"))
+        .collect();
+    let admin = Admin {
+        payer: &payer,
+        multisig: &multisig_pda,
+        multisig_signers: &multisig_signers,
+    };
+
+    match command {
+        Commands::InsertLeaf { .. }
+        | Commands::InitTree { .. }
+        | Commands::InsertValue { .. }
+        | Commands::InsertCommitment { .. } => {
+            let insert_leaf_ix = match command {
+                Commands::InsertLeaf {
+                    value,
+                    expires_at,
+                    expected_root,
+                } => {
+                    let hash = get_tree_hasher(&client, &program_id)
+                        .await
+                        .hash_value(&value.bytes());
+                    match expires_at {
+                        Some(expires_at) => insert_expiring_leaf_ix(
+                            &program_id,
+                            &payer.pubkey(),
+                            hash,
+                            expires_at,
+                            expected_root,
+                        ),
+                        None => insert_leaf_ix(&program_id, &payer.pubkey(), hash, expected_root),
+                    }
+                }
+                Commands::InitTree {
+                    value,
+                    leaf_capacity,
+                    tree_hasher,
+                } => {
+                    let tree_hasher = TreeHasher::from(tree_hasher);
+                    init_tree_ix(
+                        &program_id,
+                        &payer.pubkey(),
+                        tree_hasher.hash_value(&value.to_le_bytes()),
+                        leaf_capacity,
+                        &tree_hasher,
+                    )
+                }
+                Commands::InsertValue {
+                    value,
+                    expected_root,
+                } => insert_value_ix(
+                    &program_id,
+                    &payer.pubkey(),
+                    value.to_le_bytes().to_vec(),
+                    expected_root,
+                ),
+                Commands::InsertCommitment { value, salt } => {
+                    let salt = salt.salt(&value.to_le_bytes());
+                    let hash = hash_salted_value(&salt, &value.to_le_bytes());
+                    insert_leaf_ix(&program_id, &payer.pubkey(), hash, None)
+                }
+                _ => unreachable!(),
+            };
 
-            let tx_sig = client
-                .send_and_confirm_transaction(&tx)
+            // Trees with leaf pages take the page of the new leaf
+            let merkle_state = fetch_merkle_state(&client, &program_id)
                 .await
-                .expect("Can't send tx");
-            println!("Signature: {}", tx_sig);
+                .ok()
+                .flatten();
+            let insert_leaf_ix = match merkle_state.filter(|state| state.has_leaf_pages()) {
+                Some(merkle_state) => {
+                    with_leaf_page(insert_leaf_ix, merkle_state.get_leaves_count() as u32)
+                }
+                None => insert_leaf_ix,
+            };
+
+            let tx_sig = send_instructions(&client, &payer, &[insert_leaf_ix]).await;
 
             let tx_with_meta = client
                 .get_transaction_with_config(
@@ -93,31 +863,1804 @@ async fn main() {
                 )
                 .await
                 .expect("Can't get tx by sig");
-            let tx_meta = tx_with_meta.transaction.meta.expect("Tx meta is empty");
-            let OptionSerializer::Some(tx_logs) = tx_meta.log_messages else {
-                panic!("Tx logs are empty");
-            };
+            let inserts = parse_inserts(&program_id, &tx_with_meta.transaction)
+                .unwrap_or_else(|err| panic!("Can't parse tx events: {err}"));
+            let mut text = format!("Signature: {}", tx_sig);
+            for insert in &inserts {
+                text += &format!("\nLeaf inserted: #{} {:x?}", insert.leaf_index, insert.leaf);
+                if let (Some(root), Some(leaves_count)) = (insert.root, insert.leaves_count) {
+                    text += &format!("\nRoot hash: {:x?} (leaves: {})", root, leaves_count);
+                }
+            }
 
-            let root_hash_log = tx_logs
+            // Tree may emit fewer events, fall back to the account state
+            let last_root = inserts
                 .iter()
-                .find(|&tx_log| tx_log.contains("Program log: ["))
-                .expect("Tx program log is not found");
-            println!("Root hash log: {root_hash_log}");
+                .rev()
+                .find_map(|insert| insert.root.zip(insert.leaves_count));
+            let (root, leaves_count) = match last_root {
+                Some(last_root) => last_root,
+                None => {
+                    let merkle_state = get_merkle_state(&client, &program_id).await;
+                    text += &format!(
+                        "\nRoot hash: {:x?} (leaves: {}, from account)",
+                        merkle_state.get_root_hash(),
+                        merkle_state.get_leaves_count()
+                    );
+                    (
+                        merkle_state.get_root_hash(),
+                        merkle_state.get_leaves_count() as u32,
+                    )
+                }
+            };
+            output::print(
+                text,
+                json!({
+                    "signature": tx_sig.to_string(),
+                    "inserts": inserts
+                        .iter()
+                        .map(|insert| json!({
+                            "leaf_index": insert.leaf_index,
+                            "leaf": hex::encode(insert.leaf),
+                        }))
+                        .collect::<Vec<_>>(),
+                    "root": hex::encode(root),
+                    "leaves_count": leaves_count,
+                }),
+            );
         }
-        Commands::GetRootHash => {
+        Commands::HealthCheck => {
             let merkle_state_account = client
                 .get_account(&merkle_state_pda)
                 .await
                 .expect("Can't get merkle state account or it's empty(not initialized)");
+            let rent_minimum = client
+                .get_minimum_balance_for_rent_exemption(merkle_state_account.data.len())
+                .await
+                .expect("Can't get rent-exempt minimum");
 
-            let merkle_state = MerkleStateAccount::try_from_slice(&merkle_state_account.data)
-                .expect("Invalid account data");
+            let report = health::check_tree(&program_id, &merkle_state_account, rent_minimum);
+            output::print(
+                serde_json::to_string_pretty(&report).expect("Can't serialize report"),
+                json!(report),
+            );
+            if !report.passed {
+                std::process::exit(1);
+            }
+        }
+        Commands::GetRootHash => {
+            let get_root_hash_ix = get_root_hash_ix(&program_id);
 
-            println!("Root hash: {:x?}", merkle_state.get_root_hash());
+            let root_hash: [u8; 32] =
+                simulate_getter(&client, &payer, &program_id, get_root_hash_ix).await;
+            output::print(
+                format_args!("Root hash: {:x?}", root_hash),
+                json!({ "root": hex::encode(root_hash) }),
+            );
         }
         Commands::GetValueHash { value } => {
-            let hash: [u8; 32] = Sha256::digest(value.to_le_bytes()).into();
-            println!("Value hash: {:x?}", hash);
+            let hash = get_tree_hasher(&client, &program_id)
+                .await
+                .hash_value(&value.bytes());
+            output::print(
+                format_args!("Value hash: {:x?}", hash),
+                json!({ "hash": hex::encode(hash) }),
+            );
+        }
+        Commands::ProposeAuthority { new_authority } => {
+            let merkle_state = get_merkle_state(&client, &program_id).await;
+            let changes = [Change {
+                field: "pending_authority",
+                from: format_authority(merkle_state.get_pending_authority()),
+                to: new_authority.to_string(),
+            }];
+            if !confirm(&merkle_state_pda, &changes, args.yes) {
+                output::aborted();
+                return;
+            }
+
+            let propose_authority_ix =
+                propose_authority_ix(&program_id, &payer.pubkey(), new_authority);
+
+            let tx_sig = send_admin_instructions(&client, &admin, &[propose_authority_ix]).await;
+            output::signature(&tx_sig);
+        }
+        Commands::AcceptAuthority => {
+            let merkle_state = get_merkle_state(&client, &program_id).await;
+            let changes = [
+                Change {
+                    field: "authority",
+                    from: merkle_state.get_authority().to_string(),
+                    to: payer.pubkey().to_string(),
+                },
+                Change {
+                    field: "pending_authority",
+                    from: format_authority(merkle_state.get_pending_authority()),
+                    to: format_authority(None),
+                },
+            ];
+            if !confirm_irreversible(&merkle_state_pda, &changes, args.yes) {
+                output::aborted();
+                return;
+            }
+
+            let accept_authority_ix = accept_authority_ix(&program_id, &payer.pubkey());
+
+            let tx_sig = send_admin_instructions(&client, &admin, &[accept_authority_ix]).await;
+            output::signature(&tx_sig);
+        }
+        Commands::CreateTree {
+            value,
+            capacity,
+            authority,
+            permissioned,
+            inserters,
+            tree_hasher,
+        } => {
+            let tree_hasher = TreeHasher::from(tree_hasher);
+            let mut instructions = vec![init_tree_ix(
+                &program_id,
+                &payer.pubkey(),
+                tree_hasher.hash_value(&value.to_le_bytes()),
+                capacity,
+                &tree_hasher,
+            )];
+            if permissioned {
+                instructions.push(set_permissioned_ix(&program_id, &payer.pubkey(), true));
+            }
+            instructions.extend(
+                inserters
+                    .into_iter()
+                    .map(|inserter| add_inserter_ix(&program_id, &payer.pubkey(), inserter)),
+            );
+            if let Some(authority) = authority {
+                instructions.push(propose_authority_ix(
+                    &program_id,
+                    &payer.pubkey(),
+                    authority,
+                ));
+            }
+
+            let tx_sig = send_instructions(&client, &payer, &instructions).await;
+            output::print(
+                format_args!("Tree: {}\nSignature: {}", merkle_state_pda, tx_sig),
+                json!({ "tree": merkle_state_pda.to_string(), "signature": tx_sig.to_string() }),
+            );
+        }
+        Commands::InsertBatch {
+            file,
+            out,
+            max_concurrency,
+            priority_fee,
+        } => {
+            let inputs = insert_batch::read_inputs(&file);
+            let tree_hasher = get_tree_hasher(&client, &program_id).await;
+            let leaves: Vec<[u8; 32]> = inputs
+                .iter()
+                .map(|input| input.leaf(&tree_hasher))
+                .collect();
+
+            let config = BatchConfig {
+                max_concurrency,
+                compute_unit_price: priority_fee,
+                send: SendConfig {
+                    commitment: client.commitment(),
+                    ..Default::default()
+                },
+            };
+            let outcomes = insert_leaves(&client, &program_id, &payer, &leaves, &config)
+                .await
+                .unwrap_or_else(|err| panic!("Can't insert batch: {err}"));
+            let signatures: Vec<Option<Signature>> = outcomes
+                .iter()
+                .map(|outcome| outcome.clone().ok())
+                .collect();
+            let leaf_indices = insert_batch::leaf_indices(&client, &program_id, &signatures).await;
+
+            let results: Vec<BatchResult> = inputs
+                .iter()
+                .zip(&leaves)
+                .zip(outcomes)
+                .zip(leaf_indices)
+                .map(|(((input, leaf), outcome), leaf_index)| BatchResult {
+                    value: input.value,
+                    hash: hex::encode(leaf),
+                    leaf_index,
+                    signature: outcome.as_ref().ok().map(Signature::to_string),
+                    error: outcome.err().map(|err| err.to_string()),
+                })
+                .collect();
+            let failed = results
+                .iter()
+                .filter(|result| result.error.is_some())
+                .count();
+            insert_batch::write_results(&out, &results);
+            output::print(
+                format_args!(
+                    "Inserted {} of {} leaves, results written to {}",
+                    results.len() - failed,
+                    results.len(),
+                    out.display()
+                ),
+                json!({
+                    "inserted": results.len() - failed,
+                    "failed": failed,
+                    "out": out.display().to_string(),
+                }),
+            );
+        }
+        Commands::Stress {
+            tps,
+            duration,
+            priority_fee,
+            seed,
+        } => {
+            let tree_hasher = get_tree_hasher(&client, &program_id).await;
+            run_stress(
+                Arc::new(client),
+                Arc::new(payer),
+                program_id,
+                StressConfig {
+                    tps,
+                    duration,
+                    priority_fee,
+                    seed,
+                    tree_hasher,
+                },
+            )
+            .await;
+        }
+        Commands::ExportSnapshot { out, compress } => {
+            let merkle_state = get_merkle_state(&client, &program_id).await;
+            let snapshot = Snapshot::new(merkle_state.leaf_hashes());
+            match compress {
+                true if merkle_state.is_frontier_mode() => {
+                    panic!("Tree in frontier mode keeps no leaves")
+                }
+                true => snapshot.write_compressed(
+                    &out,
+                    &merkle_state.get_root_hash(),
+                    merkle_state.get_tree_hasher(),
+                ),
+                false => snapshot.write(&out),
+            }
+
+            output::print(
+                format_args!(
+                    "Root hash: {:x?}\nContent hash: {:x?}",
+                    merkle_state.get_root_hash(),
+                    snapshot.content_hash
+                ),
+                json!({
+                    "root": hex::encode(merkle_state.get_root_hash()),
+                    "content_hash": hex::encode(snapshot.content_hash),
+                }),
+            );
+        }
+        Commands::PublishSnapshot { .. } | Commands::SetArchiveUri { .. } => {
+            let merkle_state = get_merkle_state(&client, &program_id).await;
+            let leaves_snapshot = Snapshot::new(merkle_state.leaf_hashes());
+
+            let (snapshot, uri) = match command {
+                Commands::PublishSnapshot { ipfs_api } => {
+                    let uri = publish_to_ipfs(&ipfs_api, &leaves_snapshot).await;
+                    (leaves_snapshot, uri)
+                }
+                Commands::SetArchiveUri { uri, snapshot } => {
+                    let snapshot = Snapshot::read(&snapshot);
+                    if snapshot.content_hash != leaves_snapshot.content_hash {
+                        panic!("Snapshot doesn't match on-chain leaves");
+                    }
+                    (snapshot, uri)
+                }
+                _ => unreachable!(),
+            };
+
+            let changes = [Change {
+                field: "archive_uri",
+                from: merkle_state
+                    .get_archive()
+                    .map_or("none".into(), |archive| archive.uri.clone()),
+                to: uri.clone(),
+            }];
+            if !confirm(&merkle_state_pda, &changes, args.yes) {
+                output::aborted();
+                return;
+            }
+
+            let set_archive_uri_ix = set_archive_uri_ix(
+                &program_id,
+                &payer.pubkey(),
+                merkle_state.get_root_hash(),
+                snapshot.content_hash,
+                uri,
+            );
+
+            let tx_sig = send_admin_instructions(&client, &admin, &[set_archive_uri_ix]).await;
+            output::signature(&tx_sig);
+        }
+        Commands::Profile(_)
+        | Commands::Deployments(_)
+        | Commands::Idl { .. }
+        | Commands::VerifyProofOffline { .. }
+        | Commands::Watch { .. } => unreachable!(),
+        Commands::ProveCommitment { value, salt } => {
+            let salt = salt.salt(&value.to_le_bytes());
+            let leaf = hash_salted_value(&salt, &value.to_le_bytes());
+
+            let merkle_tree = fetch_merkle_tree(&client, &program_id)
+                .await
+                .unwrap_or_else(|err| panic!("Can't get merkle tree leaves: {err}"))
+                .expect("Merkle state account is empty(not initialized)");
+            let (leaf_index, proof) = merkle_tree
+                .proof_by_hash(&leaf)
+                .unwrap_or_else(|err| panic!("{err}"));
+
+            let proof = CommitmentProof::new(&value.to_le_bytes(), &salt, leaf_index, &proof);
+            output::print(
+                serde_json::to_string_pretty(&proof).expect("Can't serialize proof"),
+                json!(proof),
+            );
+        }
+        Commands::GetProof {
+            leaf_index,
+            value,
+            leaf_hash,
+            format,
+        } => {
+            let merkle_tree = fetch_merkle_tree(&client, &program_id)
+                .await
+                .unwrap_or_else(|err| panic!("Can't get merkle tree leaves: {err}"))
+                .expect("Merkle state account is empty(not initialized)");
+            let leaf_hash = value
+                .map(|value| merkle_tree.tree_hasher().hash_value(&value.to_le_bytes()))
+                .or(leaf_hash);
+            let leaf_index = match leaf_hash {
+                Some(leaf_hash) => merkle_tree
+                    .leaf_index(&leaf_hash)
+                    .unwrap_or_else(|| panic!("{}", ProofError::LeafNotFound)),
+                None => leaf_index.expect("Leaf index is not set"),
+            };
+            let proof = merkle_tree
+                .merkle_proof(&merkle_state_pda, leaf_index)
+                .unwrap_or_else(|err| panic!("{err}"));
+            match format {
+                ProofFormat::Json => output::print(
+                    serde_json::to_string_pretty(&proof).expect("Can't serialize proof"),
+                    json!(proof),
+                ),
+                ProofFormat::Hex => {
+                    output::print(proof.to_hex(), json!({ "proof": proof.to_hex() }))
+                }
+                ProofFormat::Path => {
+                    let path: Vec<String> = proof.path.iter().map(hex::encode).collect();
+                    output::print(path.join("\n"), json!({ "path": path }))
+                }
+            }
+        }
+        Commands::ListLeaves {
+            offset,
+            limit,
+            indexes,
+            metadata,
+        } => {
+            let (_, leaves) = fetch_leaves(&client, &program_id)
+                .await
+                .unwrap_or_else(|err| panic!("Can't get merkle tree leaves: {err}"))
+                .expect("Merkle state account is empty(not initialized)");
+            let merkle_state = match metadata {
+                true => Some(get_merkle_state(&client, &program_id).await),
+                false => None,
+            };
+
+            let page = leaves
+                .iter()
+                .enumerate()
+                .skip(offset as usize)
+                .take(limit.map_or(usize::MAX, |limit| limit as usize));
+            for (leaf_index, leaf) in page {
+                let mut line = hex::encode(leaf);
+                let mut entry = json!({ "leaf_index": leaf_index, "leaf": hex::encode(leaf) });
+                if indexes {
+                    line = format!("{leaf_index} {line}");
+                }
+                if let Some(merkle_state) = &merkle_state {
+                    let leaf_metadata = merkle_state.get_leaf_metadata(leaf_index as u32);
+                    match leaf_metadata {
+                        Some(leaf_metadata) => {
+                            line =
+                                format!("{line} {} {}", leaf_metadata.slot, leaf_metadata.inserter)
+                        }
+                        None => line.push_str(" - -"),
+                    }
+                    entry["slot"] = json!(leaf_metadata.map(|leaf_metadata| leaf_metadata.slot));
+                    entry["inserter"] = json!(
+                        leaf_metadata.map(|leaf_metadata| leaf_metadata.inserter.to_string())
+                    );
+                }
+                output::print(line, entry);
+            }
+        }
+        Commands::VerifyProof { proof_path } => {
+            let proof = read_proof(&proof_path);
+            if proof.tree_id != merkle_state_pda.to_bytes() {
+                panic!("Proof is for tree {}", Pubkey::from(proof.tree_id));
+            }
+
+            let verify_leaf_ix =
+                verify_leaf_ix(&program_id, proof.leaf_index, proof.leaf, proof.path);
+
+            match simulate_instruction(&client, &payer, verify_leaf_ix)
+                .await
+                .err
+            {
+                None => print_valid(
+                    format_args!("Valid: leaf {} is in the tree", proof.leaf_index),
+                    json!({ "leaf_index": proof.leaf_index }),
+                ),
+                Some(err) => print_invalid(&describe_error(&err)),
+            }
+        }
+        Commands::VerifyOnchain { proof } => {
+            let (leaf_index, leaf, path) = proof.read();
+            let verify_leaf_ix = verify_leaf_ix(&program_id, leaf_index, leaf, path);
+
+            let tx = match TransactionBuilder::new(&payer.pubkey())
+                .instruction(verify_leaf_ix)
+                .build(&client)
+                .await
+            {
+                Ok(tx) => tx,
+                Err(SdkError::Program(err)) => {
+                    print_invalid(&err.to_string());
+                    std::process::exit(1);
+                }
+                Err(err) => panic!("Can't build tx: {err}"),
+            };
+            let config = SendConfig {
+                commitment: client.commitment(),
+                ..Default::default()
+            };
+            match send_transaction(&client, tx, &[&payer], &config).await {
+                Ok(signature) => print_valid(
+                    format_args!(
+                        "Valid: leaf {leaf_index} verified on-chain, signature: {signature}"
+                    ),
+                    json!({ "leaf_index": leaf_index, "signature": signature.to_string() }),
+                ),
+                Err(SdkError::Program(err)) => {
+                    print_invalid(&err.to_string());
+                    std::process::exit(1);
+                }
+                Err(err) => panic!("Can't send tx: {err}"),
+            }
+        }
+        Commands::VerifyConsistency {
+            old_root,
+            old_leaves_count,
+        } => {
+            let old_root: [u8; 32] = hex::decode(&old_root)
+                .ok()
+                .and_then(|bytes| bytes.try_into().ok())
+                .expect("Invalid old root hex");
+
+            let merkle_state = get_merkle_state(&client, &program_id).await;
+            if merkle_state.is_frontier_mode() {
+                panic!("{}", ProofError::LeavesNotStored);
+            }
+            if old_leaves_count == 0 || old_leaves_count as usize > merkle_state.get_leaves_count()
+            {
+                panic!(
+                    "{}",
+                    ProofError::LeafIndexOutOfRange {
+                        leaf_index: old_leaves_count.saturating_sub(1),
+                        leaves_count: merkle_state.get_leaves_count() as u32,
+                    }
+                );
+            }
+            let (leaf, proof) = merkle_state.get_consistency_proof(old_leaves_count);
+
+            let verify_consistency_ix =
+                verify_consistency_ix(&program_id, old_root, old_leaves_count, leaf, proof);
+
+            match simulate_instruction(&client, &payer, verify_consistency_ix)
+                .await
+                .err
+            {
+                None => output::print(
+                    format_args!("Consistent: tree only grew since {old_leaves_count} leaves"),
+                    json!({ "consistent": true, "old_leaves_count": old_leaves_count }),
+                ),
+                Some(err) => output::print(
+                    format_args!("Inconsistent: {}", describe_error(&err)),
+                    json!({ "consistent": false, "error": describe_error(&err) }),
+                ),
+            }
+        }
+        Commands::VerifyCommitment { proof_path } => {
+            let proof: CommitmentProof =
+                serde_json::from_slice(&std::fs::read(proof_path).expect("Can't read proof file"))
+                    .expect("Invalid proof file");
+            let leaf = proof
+                .verified_leaf()
+                .expect("Commitment doesn't match revealed value and salt");
+
+            let verify_leaf_ix = verify_leaf_ix(&program_id, proof.leaf_index, leaf, proof.proof());
+
+            match simulate_instruction(&client, &payer, verify_leaf_ix)
+                .await
+                .err
+            {
+                None => print_valid(
+                    format_args!("Valid: value {} is in the tree", proof.value),
+                    json!({ "value": proof.value, "leaf_index": proof.leaf_index }),
+                ),
+                Some(err) => print_invalid(&describe_error(&err)),
+            }
+        }
+        Commands::FinalizeTree => {
+            let merkle_state = get_merkle_state(&client, &program_id).await;
+            let changes = [Change {
+                field: "seal",
+                from: "none".into(),
+                to: format!(
+                    "root={:x?} leaves={}",
+                    merkle_state.get_root_hash(),
+                    merkle_state.get_leaves_count()
+                ),
+            }];
+            if !confirm_irreversible(&merkle_state_pda, &changes, args.yes) {
+                output::aborted();
+                return;
+            }
+
+            let finalize_tree_ix = finalize_tree_ix(&program_id, &payer.pubkey());
+
+            let tx_sig = send_admin_instructions(&client, &admin, &[finalize_tree_ix]).await;
+            output::signature(&tx_sig);
+        }
+        Commands::EnableFrontierMode => {
+            let merkle_state = get_merkle_state(&client, &program_id).await;
+            let changes = [Change {
+                field: "stored_leaves",
+                from: merkle_state.get_leaves_count().to_string(),
+                to: "none, frontier only".into(),
+            }];
+            if !confirm_irreversible(&merkle_state_pda, &changes, args.yes) {
+                output::aborted();
+                return;
+            }
+
+            let enable_frontier_mode_ix = enable_frontier_mode_ix(&program_id, &payer.pubkey());
+
+            let tx_sig = send_admin_instructions(&client, &admin, &[enable_frontier_mode_ix]).await;
+            output::signature(&tx_sig);
+        }
+        Commands::SweepExpired => {
+            let sweep_expired_ix = sweep_expired_ix(&program_id, &payer.pubkey());
+
+            let tx_sig = send_instructions(&client, &payer, &[sweep_expired_ix]).await;
+            output::signature(&tx_sig);
+        }
+        Commands::EnableLeafPages => {
+            let merkle_state = get_merkle_state(&client, &program_id).await;
+            let pages_count = leaf_page::pages_count(merkle_state.get_leaves_count());
+            let changes = [Change {
+                field: "stored_leaves",
+                from: merkle_state.get_leaves_count().to_string(),
+                to: format!("none, {pages_count} leaf pages"),
+            }];
+            if !confirm_irreversible(&merkle_state_pda, &changes, args.yes) {
+                output::aborted();
+                return;
+            }
+
+            // Resume at the next page to write, leaves inserted meanwhile go to later pages
+            let mut merkle_state = merkle_state;
+            while !merkle_state.has_leaf_pages() {
+                let first_page = leaf_page::page_index(merkle_state.get_paged_leaves());
+                let end_page = (leaf_page::pages_count(merkle_state.get_leaves_count()) as u32)
+                    .min(first_page + LEAF_PAGES_PER_TX);
+                let enable_leaf_pages_ix =
+                    enable_leaf_pages_ix(&program_id, &payer.pubkey(), first_page..end_page);
+
+                let tx_sig =
+                    send_admin_instructions(&client, &admin, &[enable_leaf_pages_ix]).await;
+                output::signature(&tx_sig);
+                merkle_state = get_merkle_state(&client, &program_id).await;
+            }
+        }
+        Commands::MigrateState => {
+            // Older layouts don't unpack, only read the version, headerless state of trees
+            // created before the header counts as version 0
+            let data = client
+                .get_account_data(&merkle_state_pda)
+                .await
+                .expect("Can't get merkle state account or it's empty(not initialized)");
+            let version = match data.starts_with(&MerkleStateAccount::DISCRIMINATOR) {
+                true => *data.get(8).expect("Invalid account data"),
+                false => 0,
+            };
+            if version == MerkleStateAccount::VERSION {
+                output::print(
+                    "Merkle state is up to date",
+                    json!({ "version": version, "up_to_date": true }),
+                );
+                return;
+            }
+            let changes = [Change {
+                field: "version",
+                from: version.to_string(),
+                to: MerkleStateAccount::VERSION.to_string(),
+            }];
+            if !confirm_irreversible(&merkle_state_pda, &changes, args.yes) {
+                output::aborted();
+                return;
+            }
+
+            let migrate_state_ix = migrate_state_ix(&program_id, &payer.pubkey());
+
+            let tx_sig = send_instructions(&client, &payer, &[migrate_state_ix]).await;
+            output::signature(&tx_sig);
         }
+        Commands::GrowTree { target_len } => {
+            let data_len = client
+                .get_account_data(&merkle_state_pda)
+                .await
+                .expect("Can't get merkle state account or it's empty(not initialized)")
+                .len();
+            let steps = (target_len as usize)
+                .saturating_sub(data_len)
+                .div_ceil(MAX_PERMITTED_DATA_INCREASE);
+            if steps == 0 {
+                output::print(
+                    format_args!("Merkle state is already {} bytes", data_len),
+                    json!({ "account_len": data_len }),
+                );
+                return;
+            }
+
+            let grow_tree_ix = grow_tree_ix(&program_id, &payer.pubkey(), target_len);
+            // Each instruction grows as far as one realloc may, repeat it within the
+            // compute budget of a transaction
+            let grow_tree_ixs = vec![grow_tree_ix; steps];
+            for grow_tree_ixs in grow_tree_ixs.chunks(GROW_TREE_IXS_PER_TX) {
+                let tx_sig = send_instructions(&client, &payer, grow_tree_ixs).await;
+                output::signature(&tx_sig);
+            }
+        }
+        Commands::SetEventVerbosity { event_verbosity } => {
+            let event_verbosity = EventVerbosity::from(event_verbosity);
+            let merkle_state = get_merkle_state(&client, &program_id).await;
+            let changes = [Change {
+                field: "event_verbosity",
+                from: format!("{:?}", merkle_state.get_event_verbosity()),
+                to: format!("{:?}", event_verbosity),
+            }];
+            if !confirm(&merkle_state_pda, &changes, args.yes) {
+                output::aborted();
+                return;
+            }
+
+            let set_event_verbosity_ix = config_instruction(
+                &program_id,
+                &admin,
+                &merkle_state,
+                ConfigChange::EventVerbosity(event_verbosity),
+            );
+            let tx_sig = send_admin_instructions(&client, &admin, &[set_event_verbosity_ix]).await;
+            output::signature(&tx_sig);
+        }
+        Commands::SetRejectDuplicates { reject_duplicates } => {
+            let merkle_state = get_merkle_state(&client, &program_id).await;
+            let changes = [Change {
+                field: "reject_duplicates",
+                from: merkle_state.rejects_duplicates().to_string(),
+                to: reject_duplicates.to_string(),
+            }];
+            if !confirm(&merkle_state_pda, &changes, args.yes) {
+                output::aborted();
+                return;
+            }
+
+            let set_reject_duplicates_ix = config_instruction(
+                &program_id,
+                &admin,
+                &merkle_state,
+                ConfigChange::RejectDuplicates(reject_duplicates),
+            );
+            let tx_sig =
+                send_admin_instructions(&client, &admin, &[set_reject_duplicates_ix]).await;
+            output::signature(&tx_sig);
+        }
+        Commands::SetRejectSentinelLeaves {
+            reject_sentinel_leaves,
+        } => {
+            let merkle_state = get_merkle_state(&client, &program_id).await;
+            let changes = [Change {
+                field: "reject_sentinel_leaves",
+                from: merkle_state.rejects_sentinel_leaves().to_string(),
+                to: reject_sentinel_leaves.to_string(),
+            }];
+            if !confirm(&merkle_state_pda, &changes, args.yes) {
+                output::aborted();
+                return;
+            }
+
+            let set_reject_sentinel_leaves_ix = config_instruction(
+                &program_id,
+                &admin,
+                &merkle_state,
+                ConfigChange::RejectSentinelLeaves(reject_sentinel_leaves),
+            );
+            let tx_sig =
+                send_admin_instructions(&client, &admin, &[set_reject_sentinel_leaves_ix]).await;
+            output::signature(&tx_sig);
+        }
+        Commands::SetRecordLeafMetadata {
+            record_leaf_metadata,
+        } => {
+            let merkle_state = get_merkle_state(&client, &program_id).await;
+            let changes = [Change {
+                field: "record_leaf_metadata",
+                from: merkle_state.records_leaf_metadata().to_string(),
+                to: record_leaf_metadata.to_string(),
+            }];
+            if !confirm(&merkle_state_pda, &changes, args.yes) {
+                output::aborted();
+                return;
+            }
+
+            let set_record_leaf_metadata_ix = config_instruction(
+                &program_id,
+                &admin,
+                &merkle_state,
+                ConfigChange::RecordLeafMetadata(record_leaf_metadata),
+            );
+            let tx_sig =
+                send_admin_instructions(&client, &admin, &[set_record_leaf_metadata_ix]).await;
+            output::signature(&tx_sig);
+        }
+        Commands::SetPermissioned { permissioned } => {
+            let merkle_state = get_merkle_state(&client, &program_id).await;
+            let changes = [Change {
+                field: "permissioned",
+                from: merkle_state.is_permissioned().to_string(),
+                to: permissioned.to_string(),
+            }];
+            if !confirm(&merkle_state_pda, &changes, args.yes) {
+                output::aborted();
+                return;
+            }
+
+            let set_permissioned_ix = config_instruction(
+                &program_id,
+                &admin,
+                &merkle_state,
+                ConfigChange::Permissioned(permissioned),
+            );
+            let tx_sig = send_admin_instructions(&client, &admin, &[set_permissioned_ix]).await;
+            output::signature(&tx_sig);
+        }
+        Commands::AddInserter { .. } | Commands::RemoveInserter { .. } => {
+            let merkle_state = get_merkle_state(&client, &program_id).await;
+            let mut inserters = merkle_state.get_inserters().to_vec();
+            let change = match command {
+                Commands::AddInserter { inserter } => {
+                    inserters.push(inserter);
+                    ConfigChange::AddInserter(inserter)
+                }
+                Commands::RemoveInserter { inserter } => {
+                    inserters.retain(|allowed| allowed != &inserter);
+                    ConfigChange::RemoveInserter(inserter)
+                }
+                _ => unreachable!(),
+            };
+
+            let format_inserters = |inserters: &[Pubkey]| {
+                inserters
+                    .iter()
+                    .map(Pubkey::to_string)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            };
+            let changes = [Change {
+                field: "inserters",
+                from: format_inserters(merkle_state.get_inserters()),
+                to: format_inserters(&inserters),
+            }];
+            if !confirm(&merkle_state_pda, &changes, args.yes) {
+                output::aborted();
+                return;
+            }
+
+            let inserter_ix = config_instruction(&program_id, &admin, &merkle_state, change);
+            let tx_sig = send_admin_instructions(&client, &admin, &[inserter_ix]).await;
+            output::signature(&tx_sig);
+        }
+        Commands::SetRateLimit {
+            per_slot,
+            per_epoch,
+        } => {
+            let merkle_state = get_merkle_state(&client, &program_id).await;
+            let (max_inserts_per_slot, max_inserts_per_epoch) = merkle_state.get_rate_limit();
+            let changes = [
+                Change {
+                    field: "max_inserts_per_slot",
+                    from: max_inserts_per_slot.to_string(),
+                    to: per_slot.to_string(),
+                },
+                Change {
+                    field: "max_inserts_per_epoch",
+                    from: max_inserts_per_epoch.to_string(),
+                    to: per_epoch.to_string(),
+                },
+            ];
+            if !confirm(&merkle_state_pda, &changes, args.yes) {
+                output::aborted();
+                return;
+            }
+
+            let set_rate_limit_ix = config_instruction(
+                &program_id,
+                &admin,
+                &merkle_state,
+                ConfigChange::RateLimit {
+                    max_inserts_per_slot: per_slot,
+                    max_inserts_per_epoch: per_epoch,
+                },
+            );
+            let tx_sig = send_admin_instructions(&client, &admin, &[set_rate_limit_ix]).await;
+            output::signature(&tx_sig);
+        }
+        Commands::SetMaxLeaves { max_leaves } => {
+            let merkle_state = get_merkle_state(&client, &program_id).await;
+            let changes = [Change {
+                field: "max_leaves",
+                from: merkle_state
+                    .get_max_leaves()
+                    .map_or("none".into(), |max_leaves| max_leaves.to_string()),
+                to: match max_leaves {
+                    0 => "none".into(),
+                    max_leaves => max_leaves.to_string(),
+                },
+            }];
+            if !confirm(&merkle_state_pda, &changes, args.yes) {
+                output::aborted();
+                return;
+            }
+
+            let set_max_leaves_ix = config_instruction(
+                &program_id,
+                &admin,
+                &merkle_state,
+                ConfigChange::MaxLeaves(max_leaves),
+            );
+            let tx_sig = send_admin_instructions(&client, &admin, &[set_max_leaves_ix]).await;
+            output::signature(&tx_sig);
+        }
+        Commands::SetGrowthPercent { growth_percent } => {
+            let merkle_state = get_merkle_state(&client, &program_id).await;
+            let changes = [Change {
+                field: "growth_percent",
+                from: merkle_state
+                    .get_growth_percent()
+                    .unwrap_or_default()
+                    .to_string(),
+                to: growth_percent.to_string(),
+            }];
+            if !confirm(&merkle_state_pda, &changes, args.yes) {
+                output::aborted();
+                return;
+            }
+
+            let set_growth_percent_ix = config_instruction(
+                &program_id,
+                &admin,
+                &merkle_state,
+                ConfigChange::GrowthPercent(growth_percent),
+            );
+
+            let tx_sig = send_admin_instructions(&client, &admin, &[set_growth_percent_ix]).await;
+            output::signature(&tx_sig);
+        }
+        Commands::SetCanopyDepth { canopy_depth } => {
+            let merkle_state = get_merkle_state(&client, &program_id).await;
+            let changes = [Change {
+                field: "canopy_depth",
+                from: merkle_state.get_canopy_depth().to_string(),
+                to: canopy_depth.to_string(),
+            }];
+            if !confirm(&merkle_state_pda, &changes, args.yes) {
+                output::aborted();
+                return;
+            }
+
+            let set_canopy_depth_ix = config_instruction(
+                &program_id,
+                &admin,
+                &merkle_state,
+                ConfigChange::CanopyDepth(canopy_depth),
+            );
+
+            let tx_sig = send_admin_instructions(&client, &admin, &[set_canopy_depth_ix]).await;
+            output::signature(&tx_sig);
+        }
+        Commands::SetLazyRoot { lazy_root } => {
+            let merkle_state = get_merkle_state(&client, &program_id).await;
+            let changes = [Change {
+                field: "lazy_root",
+                from: merkle_state.is_lazy_root().to_string(),
+                to: lazy_root.to_string(),
+            }];
+            if !confirm(&merkle_state_pda, &changes, args.yes) {
+                output::aborted();
+                return;
+            }
+
+            let set_lazy_root_ix = config_instruction(
+                &program_id,
+                &admin,
+                &merkle_state,
+                ConfigChange::LazyRoot(lazy_root),
+            );
+
+            let tx_sig = send_admin_instructions(&client, &admin, &[set_lazy_root_ix]).await;
+            output::signature(&tx_sig);
+        }
+        Commands::CommitRoot => {
+            let commit_root_ix = commit_root_ix(&program_id);
+
+            let tx_sig = send_instructions(&client, &payer, &[commit_root_ix]).await;
+            output::signature(&tx_sig);
+        }
+        Commands::SetConfigTimelock { config_timelock } => {
+            let merkle_state = get_merkle_state(&client, &program_id).await;
+            let changes = [Change {
+                field: "config_timelock",
+                from: merkle_state
+                    .get_config_timelock()
+                    .unwrap_or_default()
+                    .to_string(),
+                to: config_timelock.to_string(),
+            }];
+            if !confirm(&merkle_state_pda, &changes, args.yes) {
+                output::aborted();
+                return;
+            }
+
+            let set_config_timelock_ix = config_instruction(
+                &program_id,
+                &admin,
+                &merkle_state,
+                ConfigChange::ConfigTimelock(config_timelock),
+            );
+
+            let tx_sig = send_admin_instructions(&client, &admin, &[set_config_timelock_ix]).await;
+            output::signature(&tx_sig);
+        }
+        Commands::GetPendingConfig => {
+            let changes = match client.get_account_data(&pending_config_pda).await {
+                Ok(data) => {
+                    PendingConfigAccount::unpack(&data)
+                        .expect("Invalid pending config account")
+                        .changes
+                }
+                Err(_) => Vec::new(),
+            };
+            let text = match changes.is_empty() {
+                true => "No config changes queued".to_string(),
+                false => changes
+                    .iter()
+                    .map(|pending| format!("Slot {}: {:?}", pending.effective_slot, pending.change))
+                    .collect::<Vec<_>>()
+                    .join("\n"),
+            };
+            let changes: Vec<_> = changes
+                .iter()
+                .map(|pending| {
+                    json!({
+                        "effective_slot": pending.effective_slot,
+                        "change": format!("{:?}", pending.change),
+                    })
+                })
+                .collect();
+            output::print(text, json!({ "changes": changes }));
+        }
+        Commands::ApplyPendingConfig | Commands::CancelPendingConfig => {
+            let pending_config_ix = match command {
+                Commands::ApplyPendingConfig => {
+                    apply_pending_config_ix(&program_id, &payer.pubkey())
+                }
+                _ => cancel_pending_config_ix(&program_id, &payer.pubkey()),
+            };
+
+            let tx_sig = send_admin_instructions(&client, &admin, &[pending_config_ix]).await;
+            output::signature(&tx_sig);
+        }
+        Commands::SetMultisig { threshold, signers } => {
+            let current = client
+                .get_account_data(&multisig_pda)
+                .await
+                .ok()
+                .and_then(|data| MultisigAccount::unpack(&data).ok());
+            let format_multisig = |threshold: u8, signers: &[Pubkey]| {
+                let signers = signers.iter().map(Pubkey::to_string).collect::<Vec<_>>();
+                format!("{threshold} of [{}]", signers.join(", "))
+            };
+            let changes = [Change {
+                field: "multisig",
+                from: current.map_or("none".into(), |multisig| {
+                    format_multisig(multisig.threshold, &multisig.signers)
+                }),
+                to: format_multisig(threshold, &signers),
+            }];
+            if !confirm(&multisig_pda, &changes, args.yes) {
+                output::aborted();
+                return;
+            }
+
+            let set_multisig_ix = set_multisig_ix(
+                &program_id,
+                &payer.pubkey(),
+                &payer.pubkey(),
+                threshold,
+                signers,
+            );
+
+            let tx_sig = send_admin_instructions(&client, &admin, &[set_multisig_ix]).await;
+            output::print(
+                format_args!("Multisig: {}\nSignature: {}", multisig_pda, tx_sig),
+                json!({ "multisig": multisig_pda.to_string(), "signature": tx_sig.to_string() }),
+            );
+        }
+        Commands::GetLeafMetadata { leaf_index } => {
+            let merkle_state = get_merkle_state(&client, &program_id).await;
+            let metadata = merkle_state.get_leaf_metadata(leaf_index);
+            let text = match metadata {
+                Some(metadata) => {
+                    format!("Slot: {}\nInserter: {}", metadata.slot, metadata.inserter)
+                }
+                None => format!("No metadata recorded for leaf {leaf_index}"),
+            };
+            output::print(
+                text,
+                json!({
+                    "leaf_index": leaf_index,
+                    "slot": metadata.map(|metadata| metadata.slot),
+                    "inserter": metadata.map(|metadata| metadata.inserter.to_string()),
+                }),
+            );
+        }
+        Commands::SetMetadata {
+            name,
+            description,
+            uri,
+        } => {
+            let (metadata_pda, _) = find_tree_metadata_pda(&program_id, &merkle_state_pda);
+            let current = client
+                .get_account_data(&metadata_pda)
+                .await
+                .ok()
+                .and_then(|data| TreeMetadataAccount::unpack(&data).ok());
+            let field = |value: Option<&String>| value.map_or("none".into(), |value| value.clone());
+            let changes = [
+                Change {
+                    field: "name",
+                    from: field(current.as_ref().map(|metadata| &metadata.name)),
+                    to: name.clone(),
+                },
+                Change {
+                    field: "description",
+                    from: field(current.as_ref().map(|metadata| &metadata.description)),
+                    to: description.clone(),
+                },
+                Change {
+                    field: "uri",
+                    from: field(current.as_ref().map(|metadata| &metadata.uri)),
+                    to: uri.clone(),
+                },
+            ];
+            if !confirm(&metadata_pda, &changes, args.yes) {
+                output::aborted();
+                return;
+            }
+
+            let set_metadata_ix =
+                set_metadata_ix(&program_id, &payer.pubkey(), name, description, uri);
+
+            let tx_sig = send_admin_instructions(&client, &admin, &[set_metadata_ix]).await;
+            output::signature(&tx_sig);
+        }
+        Commands::GetMetadata => {
+            let (metadata_pda, _) = find_tree_metadata_pda(&program_id, &merkle_state_pda);
+            let Ok(data) = client.get_account_data(&metadata_pda).await else {
+                output::print(
+                    "No metadata set",
+                    json!({ "name": null, "description": null, "uri": null }),
+                );
+                return;
+            };
+            let metadata = TreeMetadataAccount::unpack(&data).expect("Invalid metadata account");
+            output::print(
+                format_args!(
+                    "Name: {}\nDescription: {}\nURI: {}",
+                    metadata.name, metadata.description, metadata.uri
+                ),
+                json!({
+                    "name": metadata.name,
+                    "description": metadata.description,
+                    "uri": metadata.uri,
+                }),
+            );
+        }
+        Commands::PauseTree | Commands::ResumeTree => {
+            let (set_paused_ix, paused) = match command {
+                Commands::PauseTree => (pause_tree_ix(&program_id, &payer.pubkey()), true),
+                _ => (resume_tree_ix(&program_id, &payer.pubkey()), false),
+            };
+
+            let merkle_state = get_merkle_state(&client, &program_id).await;
+            let changes = [Change {
+                field: "paused",
+                from: merkle_state.is_paused().to_string(),
+                to: paused.to_string(),
+            }];
+            if !confirm(&merkle_state_pda, &changes, args.yes) {
+                output::aborted();
+                return;
+            }
+
+            let tx_sig = send_admin_instructions(&client, &admin, &[set_paused_ix]).await;
+            output::signature(&tx_sig);
+        }
+    }
+}
+
+/// Manage profiles stored in CLI state dir, `current` holds resolved connection settings.
+fn process_profile_command(profiles: &mut Profiles, command: ProfileCommands, current: Profile) {
+    match command {
+        ProfileCommands::Add { name } => {
+            profiles.insert(name.clone(), current);
+            profiles.save();
+            output::print(
+                format_args!("Profile `{name}` saved"),
+                json!({ "profile": name, "saved": true }),
+            );
+        }
+        ProfileCommands::List => {
+            for (name, profile) in profiles.iter() {
+                let keypair_path = profile
+                    .keypair_path
+                    .as_ref()
+                    .map(|path| path.display().to_string());
+                output::print(
+                    format_args!(
+                        "{name}: url={} program_id={} keypair_path={}",
+                        profile.url,
+                        profile.program_id,
+                        keypair_path.as_deref().unwrap_or("-")
+                    ),
+                    json!({
+                        "profile": name,
+                        "url": profile.url,
+                        "program_id": profile.program_id,
+                        "keypair_path": keypair_path,
+                    }),
+                );
+            }
+        }
+        ProfileCommands::Remove { name } => {
+            profiles
+                .remove(&name)
+                .unwrap_or_else(|| panic!("Profile `{name}` is not found"));
+            profiles.save();
+            output::print(
+                format_args!("Profile `{name}` removed"),
+                json!({ "profile": name, "removed": true }),
+            );
+        }
+    }
+}
+
+async fn get_merkle_state(client: &RpcClient, program_id: &Pubkey) -> MerkleStateAccount {
+    fetch_merkle_state(client, program_id)
+        .await
+        .unwrap_or_else(|err| panic!("Can't get merkle state account: {err}"))
+        .expect("Merkle state account is empty(not initialized)")
+}
+
+/// Websocket URL of the RPC node at `url`, served on the next port by default.
+fn websocket_url(url: &str) -> String {
+    let mut ws_url = url::Url::parse(url).expect("Invalid RPC URL");
+    let scheme = match ws_url.scheme() {
+        "https" => "wss",
+        _ => "ws",
+    };
+    ws_url.set_scheme(scheme).expect("Invalid RPC URL");
+    if let Some(port) = ws_url.port() {
+        let ws_port = port
+            .checked_add(1)
+            .unwrap_or_else(|| panic!("No websocket port after {port}, pass --ws-url"));
+        ws_url.set_port(Some(ws_port)).expect("Invalid RPC URL");
+    }
+    ws_url.to_string()
+}
+
+/// Hashing scheme of the tree, plain SHA-256 if it isn't created yet as the first insert
+/// creates such a tree.
+async fn get_tree_hasher(client: &RpcClient, program_id: &Pubkey) -> TreeHasher {
+    fetch_tree_hasher(client, program_id)
+        .await
+        .unwrap_or_else(|err| panic!("Can't get merkle state account: {err}"))
+        .unwrap_or_default()
+}
+
+/// Proof file from `get-proof`, JSON or hex.
+fn read_proof(proof_path: &PathBuf) -> MerkleProof {
+    let data = std::fs::read(proof_path).expect("Can't read proof file");
+    serde_json::from_slice(&data)
+        .ok()
+        .or_else(|| MerkleProof::from_hex(String::from_utf8_lossy(&data).trim()))
+        .expect("Invalid proof file")
+}
+
+fn parse_hash(value: &str) -> Result<[u8; 32], String> {
+    hex::decode(value)
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(|| format!("invalid hash hex: {value}"))
+}
+
+/// JSON of a `watch` update, `new_leaves` paired with their leaf index.
+fn root_update_json(update: &RootUpdate, new_leaves: &[(usize, &[u8; 32])]) -> serde_json::Value {
+    json!({
+        "slot": update.slot,
+        "root": hex::encode(update.root),
+        "leaves_count": update.leaves_count,
+        "new_leaves": new_leaves
+            .iter()
+            .map(|(leaf_index, leaf)| json!({ "leaf_index": leaf_index, "leaf": hex::encode(leaf) }))
+            .collect::<Vec<_>>(),
+    })
+}
+
+/// Print a passed verification, `json` fields alongside `"valid": true`.
+fn print_valid(text: impl std::fmt::Display, mut json: serde_json::Value) {
+    json["valid"] = true.into();
+    output::print(text, json);
+}
+
+/// Print a failed verification with its `error`.
+fn print_invalid(error: &str) {
+    output::print(
+        format_args!("Invalid: {error}"),
+        json!({ "valid": false, "error": error }),
+    );
+}
+
+fn format_authority(authority: Option<Pubkey>) -> String {
+    authority.map_or("none".into(), |authority| authority.to_string())
+}
+
+/// Name program errors of a failed transaction as in `MerkleTreeError`.
+fn describe_error(err: &TransactionError) -> String {
+    match err {
+        TransactionError::InstructionError(index, instruction_error) => {
+            match MerkleTreeError::from_instruction_error(instruction_error) {
+                Some(error) => format!("instruction {index}: {error}"),
+                None => err.to_string(),
+            }
+        }
+        _ => err.to_string(),
+    }
+}
+
+/// Simulate `instruction` without signature verification.
+async fn simulate_instruction(
+    client: &RpcClient,
+    payer: &Keypair,
+    instruction: Instruction,
+) -> RpcSimulateTransactionResult {
+    // Simulate under the highest limit so long proofs aren't cut off by the default one
+    let tx = TransactionBuilder::new(&payer.pubkey())
+        .instruction(instruction)
+        .transaction(MAX_COMPUTE_UNIT_LIMIT);
+    client
+        .simulate_transaction_with_config(
+            &tx,
+            RpcSimulateTransactionConfig {
+                sig_verify: false,
+                replace_recent_blockhash: true,
+                ..Default::default()
+            },
+        )
+        .await
+        .expect("Can't simulate tx")
+        .value
+}
+
+/// Simulate getter `instruction` and decode its return data.
+async fn simulate_getter<T: BorshDeserialize>(
+    client: &RpcClient,
+    payer: &Keypair,
+    program_id: &Pubkey,
+    instruction: Instruction,
+) -> T {
+    let simulation = simulate_instruction(client, payer, instruction).await;
+    if let Some(err) = simulation.err {
+        panic!("Getter simulation failed: {}", describe_error(&err));
+    }
+
+    let return_data = simulation.return_data.expect("Getter return data is empty");
+    ReturnData::from_rpc(program_id, &return_data.program_id, &return_data.data.0)
+        .unwrap_or_else(|err| panic!("Invalid getter return data: {err}"))
+}
+
+/// Sign `instructions` with `payer` and send them in a single transaction.
+/// Signers of admin commands: the payer, or the tree multisig with `multisig_signers` set.
+struct Admin<'a> {
+    payer: &'a Keypair,
+    multisig: &'a Pubkey,
+    multisig_signers: &'a [Keypair],
+}
+
+/// Direct config instruction applying `change`, or `QueueConfig` of it if `merkle_state` has
+/// a config timelock. Rent of the queued change is paid by the payer.
+fn config_instruction(
+    program_id: &Pubkey,
+    admin: &Admin<'_>,
+    merkle_state: &MerkleStateAccount,
+    change: ConfigChange,
+) -> Instruction {
+    let authority = admin.payer.pubkey();
+    let Some(config_timelock) = merkle_state.get_config_timelock() else {
+        return config_change_ix(program_id, &authority, change);
+    };
+
+    output::note(format_args!(
+        "Config timelock: queueing change, apply it in {config_timelock} slots\n"
+    ));
+    queue_config_ix(program_id, &authority, &authority, change)
+}
+
+/// Send admin `instructions`, their authority account(the second one) signed by the payer.
+/// With multisig signers it's replaced with the tree multisig, approved by the signers
+/// appended to each instruction.
+async fn send_admin_instructions(
+    client: &RpcClient,
+    admin: &Admin<'_>,
+    instructions: &[Instruction],
+) -> Signature {
+    if admin.multisig_signers.is_empty() {
+        return send_instructions(client, admin.payer, instructions).await;
+    }
+
+    let multisig_signers: Vec<Pubkey> =
+        admin.multisig_signers.iter().map(Keypair::pubkey).collect();
+    let instructions: Vec<_> = instructions
+        .iter()
+        .cloned()
+        .map(|instruction| with_multisig(instruction, admin.multisig, &multisig_signers))
+        .collect();
+    let mut signers: Vec<&Keypair> = vec![admin.payer];
+    signers.extend(admin.multisig_signers);
+
+    let tx = TransactionBuilder::new(&admin.payer.pubkey())
+        .instructions(instructions)
+        .build(client)
+        .await
+        .unwrap_or_else(|err| panic!("Can't build tx: {err}"));
+    send_tx(client, tx, &signers).await
+}
+
+async fn send_instructions(
+    client: &RpcClient,
+    payer: &Keypair,
+    instructions: &[Instruction],
+) -> Signature {
+    let tx = TransactionBuilder::new(&payer.pubkey())
+        .instructions(instructions.iter().cloned())
+        .build(client)
+        .await
+        .unwrap_or_else(|err| panic!("Can't build tx: {err}"));
+    send_tx(client, tx, &[payer]).await
+}
+
+/// Send `tx` with retries, confirmed at the client commitment.
+async fn send_tx(client: &RpcClient, tx: Transaction, signers: &[&Keypair]) -> Signature {
+    let config = SendConfig {
+        commitment: client.commitment(),
+        ..Default::default()
+    };
+    send_transaction(client, tx, signers, &config)
+        .await
+        .unwrap_or_else(|err| panic!("Can't send tx: {err}"))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Args, clap::Error> {
+        Args::try_parse_from(["merkle_tree_cli"].iter().chain(args))
+    }
+
+    fn value_args(args: &[&str]) -> ValueArgs {
+        let args = [&["get-value-hash"], args].concat();
+        match parse(&args).unwrap().command {
+            Commands::GetValueHash { value } => value,
+            command => panic!("Unexpected command {command:?}"),
+        }
+    }
+
+    #[test]
+    fn value_args_decode_each_source() {
+        assert_eq!(value_args(&["258"]).bytes(), [2, 1, 0, 0]);
+        assert_eq!(
+            value_args(&["--string", "héllo"]).bytes(),
+            "héllo".as_bytes()
+        );
+        assert_eq!(value_args(&["--hex", "00ff10"]).bytes(), [0x00, 0xff, 0x10]);
+        assert_eq!(value_args(&["--hex", "0xABcd"]).bytes(), [0xab, 0xcd]);
+        assert_eq!(value_args(&["--hex", ""]).bytes(), [] as [u8; 0]);
+
+        let path = std::env::temp_dir().join(format!("value-{}.bin", std::process::id()));
+        std::fs::write(&path, [0, 1, 2, 0xff]).unwrap();
+        let bytes = value_args(&["--file", path.to_str().unwrap()]).bytes();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(bytes, [0, 1, 2, 0xff]);
+
+        // Trailing newlines are part of the value
+        assert_eq!(value_args(&["--stdin"]).read(&b"value\n"[..]), b"value\n");
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid value hex: 0xabc")]
+    fn value_args_reject_odd_hex() {
+        value_args(&["--hex", "0xabc"]).bytes();
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid value hex: zz")]
+    fn value_args_reject_non_hex() {
+        value_args(&["--hex", "zz"]).bytes();
+    }
+
+    #[test]
+    fn value_args_take_exactly_one_source() {
+        assert!(parse(&["get-value-hash"]).is_err());
+        assert!(parse(&["get-value-hash", "1", "--string", "a"]).is_err());
+        assert!(parse(&["insert-leaf", "--hex", "00", "--stdin"]).is_err());
+        assert!(parse(&["get-value-hash", "-1"]).is_err());
+    }
+
+    const HASH: &str = "1111111111111111111111111111111111111111111111111111111111111111";
+
+    #[test]
+    fn get_proof_locates_leaf_by_index_value_or_hash() {
+        assert!(matches!(
+            parse(&["get-proof", "3"]).unwrap().command,
+            Commands::GetProof {
+                leaf_index: Some(3),
+                value: None,
+                leaf_hash: None,
+                format: ProofFormat::Json,
+            }
+        ));
+        assert!(matches!(
+            parse(&["get-proof", "--value", "5", "--format", "hex"])
+                .unwrap()
+                .command,
+            Commands::GetProof {
+                leaf_index: None,
+                value: Some(5),
+                format: ProofFormat::Hex,
+                ..
+            }
+        ));
+        assert!(matches!(
+            parse(&["get-proof", "--leaf-hash", HASH, "--format", "path"])
+                .unwrap()
+                .command,
+            Commands::GetProof {
+                leaf_hash: Some([0x11, ..]),
+                format: ProofFormat::Path,
+                ..
+            }
+        ));
+
+        assert!(parse(&["get-proof"]).is_err());
+        assert!(parse(&["get-proof", "3", "--value", "5"]).is_err());
+        assert!(parse(&["get-proof", "--value", "5", "--leaf-hash", HASH]).is_err());
+        assert!(parse(&["get-proof", "--leaf-hash", "11"]).is_err());
+    }
+
+    #[test]
+    fn verify_proof_offline_takes_proof_file_or_parts() {
+        let Commands::VerifyProofOffline { root, proof, .. } =
+            parse(&["verify-proof-offline", HASH, "proof.json"])
+                .unwrap()
+                .command
+        else {
+            panic!("Unexpected command");
+        };
+        assert_eq!(root, [0x11; 32]);
+        assert_eq!(proof.proof_path, Some(PathBuf::from("proof.json")));
+
+        let path = format!("{HASH},{}", "22".repeat(32));
+        let args = [
+            "verify-proof-offline",
+            HASH,
+            "--leaf-index",
+            "1",
+            "--leaf",
+            HASH,
+            "--path",
+            &path,
+        ];
+        let Commands::VerifyProofOffline { proof, .. } = parse(&args).unwrap().command else {
+            panic!("Unexpected command");
+        };
+        assert_eq!(proof.read(), (1, [0x11; 32], vec![[0x11; 32], [0x22; 32]]));
+
+        assert!(parse(&["verify-proof-offline", HASH]).is_err());
+        assert!(parse(&["verify-proof-offline", HASH, "--leaf", HASH]).is_err());
+        assert!(parse(&["verify-proof-offline", HASH, "--leaf-index", "1"]).is_err());
+        let args = [
+            "verify-proof-offline",
+            HASH,
+            "proof.json",
+            "--leaf-index",
+            "1",
+            "--leaf",
+            HASH,
+        ];
+        assert!(parse(&args).is_err());
+        let args = [
+            "verify-proof-offline",
+            HASH,
+            "proof.json",
+            "--rfc6962",
+            "--ordered",
+        ];
+        assert!(parse(&args).is_err());
+    }
+
+    #[test]
+    fn verify_onchain_takes_proof_file_or_parts() {
+        let Commands::VerifyOnchain { proof } =
+            parse(&["verify-onchain", "--leaf-index", "0", "--leaf", HASH])
+                .unwrap()
+                .command
+        else {
+            panic!("Unexpected command");
+        };
+        assert_eq!(proof.read(), (0, [0x11; 32], vec![]));
+
+        assert!(parse(&["verify-onchain", "proof.hex"]).is_ok());
+        assert!(parse(&["verify-onchain"]).is_err());
+        assert!(parse(&["verify-onchain", "proof.hex", "--path", HASH]).is_err());
+    }
+
+    #[test]
+    fn insert_batch_defaults() {
+        let Commands::InsertBatch {
+            file,
+            out,
+            max_concurrency,
+            priority_fee,
+        } = parse(&["insert-batch", "--file", "leaves.json"])
+            .unwrap()
+            .command
+        else {
+            panic!("Unexpected command");
+        };
+        assert_eq!(file, PathBuf::from("leaves.json"));
+        assert_eq!(out, PathBuf::from("results.csv"));
+        assert_eq!((max_concurrency, priority_fee), (8, None));
+
+        assert!(parse(&["insert-batch"]).is_err());
+    }
+
+    #[test]
+    fn list_leaves_pages_from_offset() {
+        assert!(matches!(
+            parse(&["list-leaves"]).unwrap().command,
+            Commands::ListLeaves {
+                offset: 0,
+                limit: None,
+                indexes: false,
+                metadata: false,
+            }
+        ));
+        assert!(matches!(
+            parse(&[
+                "list-leaves",
+                "--offset",
+                "10",
+                "--limit",
+                "5",
+                "--indexes",
+                "--metadata"
+            ])
+            .unwrap()
+            .command,
+            Commands::ListLeaves {
+                offset: 10,
+                limit: Some(5),
+                indexes: true,
+                metadata: true,
+            }
+        ));
+        assert!(parse(&["list-leaves", "--limit", "-1"]).is_err());
+    }
+
+    #[test]
+    fn create_tree_collects_config() {
+        let inserter = Pubkey::new_unique().to_string();
+        let authority = Pubkey::new_unique();
+        let args = [
+            "create-tree",
+            "1",
+            "--capacity",
+            "1024",
+            "--authority",
+            &authority.to_string(),
+            "--permissioned",
+            "--inserter",
+            &inserter,
+            "--inserter",
+            &inserter,
+            "--hash-algo",
+            "keccak256",
+            "--ordered",
+        ];
+        let Commands::CreateTree {
+            value,
+            capacity,
+            authority: proposed,
+            permissioned,
+            inserters,
+            tree_hasher,
+        } = parse(&args).unwrap().command
+        else {
+            panic!("Unexpected command");
+        };
+        assert_eq!((value, capacity, proposed), (1, 1024, Some(authority)));
+        assert!(permissioned);
+        assert_eq!(inserters.len(), 2);
+        let tree_hasher = TreeHasher::from(tree_hasher);
+        assert_eq!(tree_hasher.hash_algo, HashAlgo::Keccak256);
+        assert!(tree_hasher.ordered);
+
+        assert!(matches!(
+            parse(&["create-tree", "1"]).unwrap().command,
+            Commands::CreateTree {
+                capacity: 0,
+                authority: None,
+                permissioned: false,
+                ..
+            }
+        ));
+        assert!(parse(&["create-tree", "1", "--inserter", &inserter]).is_err());
+        assert!(parse(&["create-tree", "1", "--left-balanced", "--zero-padded"]).is_err());
+    }
+
+    #[test]
+    fn watch_derives_websocket_url() {
+        assert!(matches!(
+            parse(&["watch"]).unwrap().command,
+            Commands::Watch { ws_url: None }
+        ));
+        assert!(matches!(
+            parse(&["watch", "--ws-url", "ws://node:9000"]).unwrap().command,
+            Commands::Watch { ws_url: Some(ws_url) } if ws_url == "ws://node:9000"
+        ));
+
+        assert_eq!(
+            websocket_url("http://127.0.0.1:8899"),
+            "ws://127.0.0.1:8900/"
+        );
+        assert_eq!(
+            websocket_url("https://api.devnet.solana.com"),
+            "wss://api.devnet.solana.com/"
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "No websocket port after 65535, pass --ws-url")]
+    fn websocket_url_rejects_last_port() {
+        websocket_url("http://127.0.0.1:65535");
+    }
+
+    #[test]
+    fn output_flag_is_global() {
+        assert_eq!(
+            parse(&["get-root-hash"]).unwrap().output,
+            OutputFormat::Text
+        );
+        assert_eq!(
+            parse(&["--output", "json", "get-root-hash"])
+                .unwrap()
+                .output,
+            OutputFormat::Json
+        );
+        assert!(parse(&["--output", "yaml", "get-root-hash"]).is_err());
+    }
+
+    #[test]
+    fn json_field_names_are_stable() {
+        let batch_result = BatchResult {
+            value: Some(7),
+            hash: "aa".into(),
+            leaf_index: Some(3),
+            signature: None,
+            error: Some("TreePaused".into()),
+        };
+        assert_eq!(
+            json!(batch_result),
+            json!({
+                "value": 7,
+                "hash": "aa",
+                "leaf_index": 3,
+                "signature": null,
+                "error": "TreePaused",
+            })
+        );
+
+        let proof = MerkleProof {
+            tree_id: [1; 32],
+            leaf_index: 2,
+            leaf: [3; 32],
+            root: [4; 32],
+            path: vec![[5; 32]],
+        };
+        assert_eq!(
+            json!(proof),
+            json!({
+                "tree_id": "01".repeat(32),
+                "leaf_index": 2,
+                "leaf": "03".repeat(32),
+                "root": "04".repeat(32),
+                "path": ["05".repeat(32)],
+            })
+        );
+
+        let update = RootUpdate {
+            slot: 9,
+            root: [6; 32],
+            leaves_count: 5,
+            new_leaves: vec![[7; 32]],
+        };
+        assert_eq!(
+            root_update_json(&update, &[(4, &update.new_leaves[0])]),
+            json!({
+                "slot": 9,
+                "root": "06".repeat(32),
+                "leaves_count": 5,
+                "new_leaves": [{ "leaf_index": 4, "leaf": "07".repeat(32) }],
+            })
+        );
+
+        let report = health::HealthReport {
+            passed: false,
+            checks: vec![health::Check {
+                name: "owner",
+                passed: false,
+                details: "owner 11111111111111111111111111111111".into(),
+            }],
+        };
+        assert_eq!(
+            json!(report),
+            json!({
+                "passed": false,
+                "checks": [{
+                    "name": "owner",
+                    "passed": false,
+                    "details": "owner 11111111111111111111111111111111",
+                }],
+            })
+        );
     }
 }
@@ -0,0 +1,97 @@
+//! Command output, human readable text or, with `--output json`, one JSON object per line on
+//! stdout. JSON field names are stable: snake_case, hashes as hex, pubkeys and signatures as
+//! base58. Prompts and notes go to stderr in JSON mode so stdout stays parseable.
+
+use clap::ValueEnum;
+use serde_json::{Value, json};
+use solana_sdk::signature::Signature;
+use std::{
+    fmt::Display,
+    io::{self, Write},
+    sync::OnceLock,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum OutputFormat {
+    #[default]
+    Text,
+    /// One JSON object per line.
+    Json,
+}
+
+static FORMAT: OnceLock<OutputFormat> = OnceLock::new();
+
+/// Set the format of all output, once before any is printed.
+pub fn init(format: OutputFormat) {
+    FORMAT.set(format).expect("Output format is already set");
+}
+
+pub fn is_json() -> bool {
+    FORMAT.get() == Some(&OutputFormat::Json)
+}
+
+/// Print a result, `text` or `json` depending on the format.
+pub fn print(text: impl Display, json: Value) {
+    match is_json() {
+        true => println!("{json}"),
+        false => println!("{text}"),
+    }
+}
+
+/// Print signature of a sent transaction.
+pub fn signature(signature: &Signature) {
+    print(
+        format_args!("Signature: {signature}"),
+        signature_json(signature),
+    );
+}
+
+fn signature_json(signature: &Signature) -> Value {
+    json!({ "signature": signature.to_string() })
+}
+
+/// Print that a confirmation prompt was declined.
+pub fn aborted() {
+    print("Aborted", json!({ "aborted": true }));
+}
+
+/// Print `message` that isn't a result, e.g. a prompt, without a newline.
+pub fn note(message: impl Display) {
+    match is_json() {
+        true => {
+            eprint!("{message}");
+            io::stderr().flush().expect("Can't flush stderr");
+        }
+        false => {
+            print!("{message}");
+            io::stdout().flush().expect("Can't flush stdout");
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn output_format_parses() {
+        assert_eq!(
+            OutputFormat::from_str("json", false),
+            Ok(OutputFormat::Json)
+        );
+        assert_eq!(
+            OutputFormat::from_str("text", false),
+            Ok(OutputFormat::Text)
+        );
+        assert_eq!(OutputFormat::default(), OutputFormat::Text);
+    }
+
+    #[test]
+    fn signature_json_is_stable() {
+        let signature = Signature::from([1; 64]);
+        assert_eq!(
+            signature_json(&signature).to_string(),
+            format!(r#"{{"signature":"{signature}"}}"#)
+        );
+    }
+}
@@ -0,0 +1,58 @@
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, fs, path::PathBuf};
+
+/// Named set of connection defaults, stored in `~/.merkle-cli/profiles.json`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Profile {
+    /// Solana RPC URL.
+    pub url: String,
+    /// Merkle tree program id(base58).
+    pub program_id: String,
+    /// Default signer keypair path.
+    pub keypair_path: Option<PathBuf>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Profiles(BTreeMap<String, Profile>);
+
+impl Profiles {
+    const FILE_NAME: &str = "profiles.json";
+
+    /// CLI state directory, `~/.merkle-cli/`.
+    pub fn state_dir() -> PathBuf {
+        let home = std::env::var_os("HOME").expect("HOME is not set");
+        PathBuf::from(home).join(".merkle-cli")
+    }
+
+    pub fn load() -> Self {
+        let path = Self::state_dir().join(Self::FILE_NAME);
+        match fs::read(&path) {
+            Ok(data) => serde_json::from_slice(&data).expect("Invalid profiles file"),
+            Err(_) => Self::default(),
+        }
+    }
+
+    pub fn save(&self) {
+        let state_dir = Self::state_dir();
+        fs::create_dir_all(&state_dir).expect("Can't create CLI state dir");
+
+        let data = serde_json::to_vec_pretty(self).expect("Can't serialize profiles");
+        fs::write(state_dir.join(Self::FILE_NAME), data).expect("Can't write profiles file");
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Profile> {
+        self.0.get(name)
+    }
+
+    pub fn insert(&mut self, name: String, profile: Profile) {
+        self.0.insert(name, profile);
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<Profile> {
+        self.0.remove(name)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &Profile)> {
+        self.0.iter()
+    }
+}
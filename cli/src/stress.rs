@@ -0,0 +1,211 @@
+use crate::output;
+use merkle_tree_client::instruction::insert_leaf_ix;
+use merkle_tree_program::{hash::TreeHasher, pda::find_merkle_state_pda};
+use serde_json::json;
+use solana_client::{
+    client_error::ClientError, nonblocking::rpc_client::RpcClient, rpc_config::RpcTransactionConfig,
+};
+use solana_sdk::{
+    compute_budget::ComputeBudgetInstruction, hash::Hash, pubkey::Pubkey, signature::Keypair,
+    signer::Signer, transaction::Transaction,
+};
+use solana_transaction_status::option_serializer::OptionSerializer;
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::task::JoinSet;
+
+/// Stress run settings.
+pub struct StressConfig {
+    /// Target transactions per second.
+    pub tps: u32,
+    /// Total submission window.
+    pub duration: Duration,
+    /// Compute unit price in micro-lamports.
+    pub priority_fee: u64,
+    /// First leaf index, leaf `i` is `tree_hasher.hash_value(i.to_le_bytes())`.
+    pub seed: u64,
+    /// Hashing scheme of the tree.
+    pub tree_hasher: TreeHasher,
+}
+
+/// Outcome of a single stress transaction.
+enum TxOutcome {
+    Confirmed { compute_units: Option<u64> },
+    Failed { reason: String },
+}
+
+/// Parse durations like `30s`, `10m`, `1h` or plain seconds.
+pub fn parse_duration(value: &str) -> Result<Duration, String> {
+    let (amount, unit) = match value.find(|c: char| !c.is_ascii_digit()) {
+        Some(idx) => value.split_at(idx),
+        None => (value, "s"),
+    };
+    let amount: u64 = amount
+        .parse()
+        .map_err(|_| format!("Invalid duration `{value}`"))?;
+
+    match unit {
+        "s" => Ok(Duration::from_secs(amount)),
+        "m" => Ok(Duration::from_secs(amount * 60)),
+        "h" => Ok(Duration::from_secs(amount * 60 * 60)),
+        _ => Err(format!("Invalid duration unit `{unit}`, expected s/m/h")),
+    }
+}
+
+/// Submit deterministic `InsertLeaf` transactions at `config.tps` and print a throughput report.
+pub async fn run_stress(
+    client: Arc<RpcClient>,
+    payer: Arc<Keypair>,
+    program_id: Pubkey,
+    config: StressConfig,
+) {
+    let (merkle_state_pda, _) = find_merkle_state_pda(&program_id);
+    let initial_size = get_account_size(&client, &merkle_state_pda).await;
+
+    let mut interval = tokio::time::interval(Duration::from_secs_f64(1.0 / config.tps as f64));
+    let mut recent_blockhash = Hash::default();
+    let mut blockhash_updated_at: Option<Instant> = None;
+    let mut tasks = JoinSet::new();
+
+    let started_at = Instant::now();
+    let mut leaf_index = config.seed;
+    while started_at.elapsed() < config.duration {
+        interval.tick().await;
+
+        // Refresh blockhash once per second
+        if blockhash_updated_at.is_none_or(|updated_at| updated_at.elapsed().as_secs() >= 1) {
+            recent_blockhash = client
+                .get_latest_blockhash()
+                .await
+                .expect("Can't get latest blockhash");
+            blockhash_updated_at = Some(Instant::now());
+        }
+
+        let hash = config.tree_hasher.hash_value(&leaf_index.to_le_bytes());
+        leaf_index += 1;
+
+        let instructions = [
+            ComputeBudgetInstruction::set_compute_unit_price(config.priority_fee),
+            insert_leaf_ix(&program_id, &payer.pubkey(), hash, None),
+        ];
+        let mut tx = Transaction::new_with_payer(&instructions, Some(&payer.pubkey()));
+        tx.sign(&[&payer], recent_blockhash);
+
+        let client = client.clone();
+        tasks.spawn(async move { send_stress_tx(&client, tx).await });
+    }
+    let sent = leaf_index - config.seed;
+    let submit_elapsed = started_at.elapsed();
+
+    // Collect outcomes
+    let mut confirmed = 0u64;
+    let mut compute_units = Vec::new();
+    let mut failures: BTreeMap<String, u64> = BTreeMap::new();
+    while let Some(outcome) = tasks.join_next().await {
+        match outcome.expect("Stress task panicked") {
+            TxOutcome::Confirmed {
+                compute_units: units,
+            } => {
+                confirmed += 1;
+                compute_units.extend(units);
+            }
+            TxOutcome::Failed { reason } => *failures.entry(reason).or_default() += 1,
+        }
+    }
+    let final_size = get_account_size(&client, &merkle_state_pda).await;
+
+    let elapsed = submit_elapsed.as_secs_f64();
+    let tps = confirmed as f64 / elapsed;
+    let compute_units = compute_units
+        .iter()
+        .min()
+        .zip(compute_units.iter().max())
+        .map(|(min, max)| {
+            let avg = compute_units.iter().sum::<u64>() / compute_units.len() as u64;
+            (avg, *min, *max)
+        });
+
+    let mut text = format!("Sent: {sent} in {elapsed:.1}s\n");
+    writeln!(text, "Confirmed: {confirmed} ({tps:.2} TPS)").unwrap();
+    writeln!(text, "Failed: {}", sent - confirmed).unwrap();
+    for (reason, count) in &failures {
+        writeln!(text, "  {reason}: {count}").unwrap();
+    }
+    if let Some((avg, min, max)) = compute_units {
+        writeln!(text, "Compute units: avg={avg} min={min} max={max}").unwrap();
+    }
+    write!(
+        text,
+        "Account size: {initial_size} -> {final_size} bytes (+{})",
+        final_size.saturating_sub(initial_size)
+    )
+    .unwrap();
+    output::print(
+        text,
+        json!({
+            "sent": sent,
+            "elapsed_secs": elapsed,
+            "confirmed": confirmed,
+            "tps": tps,
+            "failed": sent - confirmed,
+            "failures": failures,
+            "compute_units": compute_units
+                .map(|(avg, min, max)| json!({ "avg": avg, "min": min, "max": max })),
+            "initial_size": initial_size,
+            "final_size": final_size,
+        }),
+    );
+}
+
+async fn send_stress_tx(client: &RpcClient, tx: Transaction) -> TxOutcome {
+    let tx_sig = match client.send_and_confirm_transaction(&tx).await {
+        Ok(tx_sig) => tx_sig,
+        Err(err) => {
+            return TxOutcome::Failed {
+                reason: failure_reason(&err),
+            };
+        }
+    };
+
+    let compute_units = client
+        .get_transaction_with_config(
+            &tx_sig,
+            RpcTransactionConfig {
+                encoding: None,
+                commitment: None,
+                max_supported_transaction_version: None,
+            },
+        )
+        .await
+        .ok()
+        .and_then(|tx| tx.transaction.meta)
+        .and_then(|meta| match meta.compute_units_consumed {
+            OptionSerializer::Some(units) => Some(units),
+            _ => None,
+        });
+
+    TxOutcome::Confirmed { compute_units }
+}
+
+/// Group failures by transaction error, falling back to the client error kind.
+fn failure_reason(err: &ClientError) -> String {
+    match err.get_transaction_error() {
+        Some(tx_err) => format!("{tx_err:?}"),
+        None => format!("{:?}", err.kind())
+            .split(['(', ' '])
+            .next()
+            .unwrap_or_default()
+            .to_string(),
+    }
+}
+
+async fn get_account_size(client: &RpcClient, address: &Pubkey) -> usize {
+    client
+        .get_account(address)
+        .await
+        .map_or(0, |account| account.data.len())
+}
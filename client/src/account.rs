@@ -0,0 +1,189 @@
+//! Fetch helpers decoding program accounts into their typed state. Accounts that don't exist
+//! yet are `None`, accounts that exist with other data fail with `SdkError::InvalidAccount`.
+
+use crate::{SdkError, pda::tree_pdas, tree::MerkleTree};
+use merkle_tree_errors::ProofError;
+use merkle_tree_program::{
+    config::PendingConfigAccount, hash::TreeHasher, leaf_page, multisig::MultisigAccount,
+    state::MerkleStateAccount, tree_metadata::TreeMetadataAccount, zero_copy::MerkleStateHeader,
+};
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_program::{program_error::ProgramError, pubkey::Pubkey};
+
+/// Hashing scheme and leaves of a tree, see `fetch_leaves`.
+pub type TreeLeaves = (TreeHasher, Vec<[u8; 32]>);
+
+/// Merkle state of the tree of `program_id`.
+pub async fn fetch_merkle_state(
+    client: &RpcClient,
+    program_id: &Pubkey,
+) -> Result<Option<MerkleStateAccount>, SdkError> {
+    let (merkle_state_pda, _) = tree_pdas(program_id).merkle_state;
+    fetch_account(client, &merkle_state_pda, MerkleStateAccount::unpack).await
+}
+
+/// Tree of `program_id` built from its leaves, see `fetch_leaves`.
+pub async fn fetch_merkle_tree(
+    client: &RpcClient,
+    program_id: &Pubkey,
+) -> Result<Option<MerkleTree>, SdkError> {
+    Ok(fetch_leaves(client, program_id)
+        .await?
+        .map(|(tree_hasher, leaves)| MerkleTree::new(tree_hasher, leaves)))
+}
+
+/// Hashing scheme and leaves of the tree of `program_id`, read from the merkle state or from
+/// its leaf pages. Frontier mode trees without leaf pages fail with
+/// `ProofError::LeavesNotStored`.
+pub async fn fetch_leaves(
+    client: &RpcClient,
+    program_id: &Pubkey,
+) -> Result<Option<TreeLeaves>, SdkError> {
+    let Some(merkle_state) = fetch_merkle_state(client, program_id).await? else {
+        return Ok(None);
+    };
+    let tree_hasher = merkle_state.get_tree_hasher();
+    let (page_pdas, leaves_count) = match leaf_source(program_id, merkle_state)? {
+        LeafSource::Stored(leaves) => return Ok(Some((tree_hasher, leaves))),
+        LeafSource::Pages {
+            page_pdas,
+            leaves_count,
+        } => (page_pdas, leaves_count),
+    };
+
+    let mut leaves = Vec::with_capacity(page_pdas.len() * leaf_page::LEAVES_PER_PAGE);
+    for page_pdas in page_pdas.chunks(MAX_MULTIPLE_ACCOUNTS) {
+        let pages = client
+            .get_multiple_accounts_with_commitment(page_pdas, client.commitment())
+            .await
+            .map_err(|err| SdkError::Rpc(err.to_string()))?
+            .value;
+        for (page_pda, page) in page_pdas.iter().zip(pages) {
+            leaves.extend_from_slice(page_leaves(
+                page_pda,
+                page.as_ref().map(|page| page.data.as_slice()),
+            )?);
+        }
+    }
+    leaves.truncate(leaves_count);
+
+    Ok(Some((tree_hasher, leaves)))
+}
+
+/// Hashing scheme of the tree of `program_id`, read from the account header without
+/// deserializing its leaves.
+pub async fn fetch_tree_hasher(
+    client: &RpcClient,
+    program_id: &Pubkey,
+) -> Result<Option<TreeHasher>, SdkError> {
+    let (merkle_state_pda, _) = tree_pdas(program_id).merkle_state;
+    fetch_account(client, &merkle_state_pda, unpack_tree_hasher).await
+}
+
+/// Config changes queued for the timelocked tree of `program_id`.
+pub async fn fetch_pending_config(
+    client: &RpcClient,
+    program_id: &Pubkey,
+) -> Result<Option<PendingConfigAccount>, SdkError> {
+    let (pending_config_pda, _) = tree_pdas(program_id).pending_config;
+    fetch_account(client, &pending_config_pda, PendingConfigAccount::unpack).await
+}
+
+/// Multisig of the tree of `program_id`.
+pub async fn fetch_multisig(
+    client: &RpcClient,
+    program_id: &Pubkey,
+) -> Result<Option<MultisigAccount>, SdkError> {
+    let (multisig_pda, _) = tree_pdas(program_id).multisig;
+    fetch_account(client, &multisig_pda, MultisigAccount::unpack).await
+}
+
+/// Metadata of the tree of `program_id`.
+pub async fn fetch_tree_metadata(
+    client: &RpcClient,
+    program_id: &Pubkey,
+) -> Result<Option<TreeMetadataAccount>, SdkError> {
+    let (metadata_pda, _) = tree_pdas(program_id).tree_metadata;
+    fetch_account(client, &metadata_pda, TreeMetadataAccount::unpack).await
+}
+
+/// Max accounts of a single `getMultipleAccounts` request.
+pub(crate) const MAX_MULTIPLE_ACCOUNTS: usize = 100;
+
+/// Where the leaves of a tree are kept.
+pub(crate) enum LeafSource {
+    /// In the merkle state account.
+    Stored(Vec<[u8; 32]>),
+    /// In the leaf pages of `page_pdas` in page order, the last one partially written.
+    Pages {
+        page_pdas: Vec<Pubkey>,
+        leaves_count: usize,
+    },
+}
+
+/// Where the leaves of `merkle_state` of `program_id` are kept. Frontier mode trees without
+/// leaf pages don't keep them.
+pub(crate) fn leaf_source(
+    program_id: &Pubkey,
+    merkle_state: MerkleStateAccount,
+) -> Result<LeafSource, ProofError> {
+    if !merkle_state.has_leaf_pages() {
+        if merkle_state.is_frontier_mode() {
+            return Err(ProofError::LeavesNotStored);
+        }
+        return Ok(LeafSource::Stored(merkle_state.get_leaf_hashes()));
+    }
+
+    let leaves_count = merkle_state.get_leaves_count();
+    let tree_pdas = tree_pdas(program_id);
+    Ok(LeafSource::Pages {
+        page_pdas: (0..leaf_page::pages_count(leaves_count) as u32)
+            .map(|page_index| tree_pdas.find_leaf_page(page_index).0)
+            .collect(),
+        leaves_count,
+    })
+}
+
+/// Leaves of leaf page `page_pda` with account `data`, unwritten slots are zeroed.
+pub(crate) fn page_leaves<'a>(
+    page_pda: &Pubkey,
+    data: Option<&'a [u8]>,
+) -> Result<&'a [[u8; 32]], SdkError> {
+    let data = data.ok_or(ProofError::LeavesNotStored)?;
+    leaf_page::leaves(data).map_err(|_| SdkError::InvalidAccount(*page_pda))
+}
+
+/// Tree hasher of merkle state account `data`, see `fetch_tree_hasher`.
+pub(crate) fn unpack_tree_hasher(data: &[u8]) -> Result<TreeHasher, ProgramError> {
+    MerkleStateAccount::check_header(data)?;
+    MerkleStateHeader::from_bytes(data)?.get_tree_hasher()
+}
+
+/// Fetch `pubkey` at the client commitment and decode its data with `unpack`.
+async fn fetch_account<T>(
+    client: &RpcClient,
+    pubkey: &Pubkey,
+    unpack: impl FnOnce(&[u8]) -> Result<T, ProgramError>,
+) -> Result<Option<T>, SdkError> {
+    let account = client
+        .get_account_with_commitment(pubkey, client.commitment())
+        .await
+        .map_err(|err| SdkError::Rpc(err.to_string()))?
+        .value;
+
+    decode_account(
+        pubkey,
+        account.as_ref().map(|account| account.data.as_slice()),
+        unpack,
+    )
+}
+
+/// Decode account `pubkey` with `data`, `None` if it doesn't exist.
+pub(crate) fn decode_account<T>(
+    pubkey: &Pubkey,
+    data: Option<&[u8]>,
+    unpack: impl FnOnce(&[u8]) -> Result<T, ProgramError>,
+) -> Result<Option<T>, SdkError> {
+    data.map(|data| unpack(data).map_err(|_| SdkError::InvalidAccount(*pubkey)))
+        .transpose()
+}
@@ -0,0 +1,384 @@
+//! Bulk leaf inserts packed into as few transactions as fit the packet size and compute unit
+//! limits, sent with bounded concurrency.
+
+use crate::{
+    SdkError,
+    account::fetch_merkle_state,
+    instruction::{insert_leaf_ix, with_leaf_page},
+    send::{SendConfig, send_transaction},
+    transaction::{MAX_COMPUTE_UNIT_LIMIT, TransactionBuilder},
+};
+use futures::{StreamExt, stream};
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_program::{
+    instruction::Instruction,
+    pubkey::{PUBKEY_BYTES, Pubkey},
+};
+use solana_sdk::{
+    packet::PACKET_DATA_SIZE,
+    signature::{Keypair, Signature},
+    signer::Signer,
+    transaction::Transaction,
+};
+
+/// Options of `insert_leaves`.
+#[derive(Debug, Clone)]
+pub struct BatchConfig {
+    /// Transactions in flight at once. Trees with leaf pages are always sent one transaction
+    /// at a time, as each insert takes the page of the leaves count it lands at.
+    pub max_concurrency: usize,
+    /// Priority fee in micro-lamports per compute unit.
+    pub compute_unit_price: Option<u64>,
+    /// Retries and confirmation of each transaction.
+    pub send: SendConfig,
+}
+
+impl Default for BatchConfig {
+    fn default() -> Self {
+        Self {
+            max_concurrency: 8,
+            compute_unit_price: None,
+            send: SendConfig::default(),
+        }
+    }
+}
+
+/// Insert `leaves` paid by `payer`, packing as many inserts per transaction as fit. Returns
+/// the outcome of each leaf in `leaves` order: the signature of the transaction inserting it,
+/// or the error that transaction failed with. Leaves of one transaction land together or not
+/// at all, in order, though transactions may land in any order.
+pub async fn insert_leaves(
+    client: &RpcClient,
+    program_id: &Pubkey,
+    payer: &Keypair,
+    leaves: &[[u8; 32]],
+    config: &BatchConfig,
+) -> Result<Vec<Result<Signature, SdkError>>, SdkError> {
+    let Some(&first_leaf) = leaves.first() else {
+        return Ok(Vec::new());
+    };
+    let leaves_count = fetch_merkle_state(client, program_id)
+        .await?
+        .filter(|merkle_state| merkle_state.has_leaf_pages())
+        .map(|merkle_state| merkle_state.get_leaves_count() as u32);
+    let leaf_pages = leaves_count.is_some();
+
+    // Inserts cost more as the tree grows, the per-transaction margin absorbs that
+    let builder = transaction_builder(payer, config);
+    let insert_units = builder
+        .clone()
+        .instruction(insert_ix(program_id, payer, first_leaf, leaves_count))
+        .simulate(client)
+        .await?;
+    let max_inserts = (MAX_COMPUTE_UNIT_LIMIT / builder.compute_unit_limit(insert_units)).max(1);
+    let chunks = chunk_leaves(
+        program_id,
+        payer,
+        leaves,
+        &builder,
+        max_inserts as usize,
+        leaf_pages,
+        max_transaction_size(leaf_pages),
+    );
+
+    let outcomes: Vec<_> = if leaf_pages {
+        let mut outcomes = Vec::with_capacity(chunks.len());
+        for chunk in chunks {
+            outcomes.push(
+                send_chunk(
+                    client,
+                    program_id,
+                    payer,
+                    chunk,
+                    &builder,
+                    true,
+                    &config.send,
+                )
+                .await,
+            );
+        }
+        outcomes
+    } else {
+        stream::iter(chunks)
+            .map(|chunk| {
+                send_chunk(
+                    client,
+                    program_id,
+                    payer,
+                    chunk,
+                    &builder,
+                    false,
+                    &config.send,
+                )
+            })
+            .buffered(config.max_concurrency.max(1))
+            .collect()
+            .await
+    };
+
+    Ok(outcomes
+        .into_iter()
+        .flat_map(|(len, result)| std::iter::repeat_n(result, len))
+        .collect())
+}
+
+fn transaction_builder(payer: &Keypair, config: &BatchConfig) -> TransactionBuilder {
+    let builder = TransactionBuilder::new(&payer.pubkey());
+    match config.compute_unit_price {
+        Some(compute_unit_price) => builder.compute_unit_price(compute_unit_price),
+        None => builder,
+    }
+}
+
+/// Insert of `leaf`, taking the page of `leaf_index` if set.
+fn insert_ix(
+    program_id: &Pubkey,
+    payer: &Keypair,
+    leaf: [u8; 32],
+    leaf_index: Option<u32>,
+) -> Instruction {
+    let instruction = insert_leaf_ix(program_id, &payer.pubkey(), leaf, None);
+    match leaf_index {
+        Some(leaf_index) => with_leaf_page(instruction, leaf_index),
+        None => instruction,
+    }
+}
+
+/// Largest transaction of inserts that fits a packet. With `leaf_pages` room is kept for the
+/// two pages a run may span, chunks are built with the first page only.
+fn max_transaction_size(leaf_pages: bool) -> usize {
+    match leaf_pages {
+        true => PACKET_DATA_SIZE - PUBKEY_BYTES,
+        false => PACKET_DATA_SIZE,
+    }
+}
+
+/// Split `leaves` into runs of at most `max_inserts` whose transaction is at most `max_size`
+/// bytes, a leaf that doesn't fit alone still gets its own run.
+fn chunk_leaves<'a>(
+    program_id: &Pubkey,
+    payer: &Keypair,
+    leaves: &'a [[u8; 32]],
+    builder: &TransactionBuilder,
+    max_inserts: usize,
+    leaf_pages: bool,
+    max_size: usize,
+) -> Vec<&'a [[u8; 32]]> {
+    let leaf_index = leaf_pages.then_some(0);
+
+    let mut chunks = Vec::new();
+    let mut rest = leaves;
+    while !rest.is_empty() {
+        let mut len = 1;
+        while len < rest.len().min(max_inserts) {
+            let candidate = builder.clone().instructions(
+                rest[..=len]
+                    .iter()
+                    .map(|leaf| insert_ix(program_id, payer, *leaf, leaf_index)),
+            );
+            if transaction_size(&candidate.transaction(MAX_COMPUTE_UNIT_LIMIT)) > max_size {
+                break;
+            }
+            len += 1;
+        }
+
+        let (chunk, tail) = rest.split_at(len);
+        chunks.push(chunk);
+        rest = tail;
+    }
+
+    chunks
+}
+
+/// Serialized size of `tx` once signed.
+fn transaction_size(tx: &Transaction) -> usize {
+    let signatures = tx.message.header.num_required_signatures as usize;
+    1 + signatures * 64 + tx.message.serialize().len()
+}
+
+/// Send the inserts of `chunk`, returning its length with the outcome shared by its leaves.
+async fn send_chunk(
+    client: &RpcClient,
+    program_id: &Pubkey,
+    payer: &Keypair,
+    chunk: &[[u8; 32]],
+    builder: &TransactionBuilder,
+    leaf_pages: bool,
+    send_config: &SendConfig,
+) -> (usize, Result<Signature, SdkError>) {
+    let result = async {
+        let leaves_count = match leaf_pages {
+            true => fetch_merkle_state(client, program_id)
+                .await?
+                .map(|merkle_state| merkle_state.get_leaves_count() as u32),
+            false => None,
+        };
+        let instructions = chunk.iter().enumerate().map(|(offset, leaf)| {
+            let leaf_index = leaves_count.map(|leaves_count| leaves_count + offset as u32);
+            insert_ix(program_id, payer, *leaf, leaf_index)
+        });
+
+        let tx = builder
+            .clone()
+            .instructions(instructions)
+            .build(client)
+            .await?;
+        send_transaction(client, tx, &[payer], send_config).await
+    }
+    .await;
+
+    (chunk.len(), result)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn leaves(count: u8) -> Vec<[u8; 32]> {
+        (0..count).map(|i| [i; 32]).collect()
+    }
+
+    /// Size of the transaction inserting `leaves` in one go, as `chunk_leaves` measures it.
+    fn size_of(
+        payer: &Keypair,
+        builder: &TransactionBuilder,
+        leaves: &[[u8; 32]],
+        leaf_pages: bool,
+    ) -> usize {
+        let instructions = leaves
+            .iter()
+            .map(|leaf| insert_ix(&Pubkey::default(), payer, *leaf, leaf_pages.then_some(0)));
+        transaction_size(
+            &builder
+                .clone()
+                .instructions(instructions)
+                .transaction(MAX_COMPUTE_UNIT_LIMIT),
+        )
+    }
+
+    fn chunk_lens(chunks: &[&[[u8; 32]]]) -> Vec<usize> {
+        chunks.iter().map(|chunk| chunk.len()).collect()
+    }
+
+    #[test]
+    fn transaction_size_matches_signed_serialization() {
+        let payer = Keypair::new();
+        let builder = TransactionBuilder::new(&payer.pubkey());
+        let mut tx = builder
+            .instruction(insert_ix(&Pubkey::default(), &payer, [1; 32], None))
+            .transaction(MAX_COMPUTE_UNIT_LIMIT);
+        tx.sign(&[&payer], Default::default());
+
+        assert_eq!(
+            transaction_size(&tx),
+            bincode::serialized_size(&tx).unwrap() as usize
+        );
+    }
+
+    #[test]
+    fn chunk_at_size_limit_is_kept() {
+        let payer = Keypair::new();
+        let builder = TransactionBuilder::new(&payer.pubkey());
+        let leaves = leaves(6);
+        let size = size_of(&payer, &builder, &leaves[..3], false);
+
+        let chunks = chunk_leaves(
+            &Pubkey::default(),
+            &payer,
+            &leaves,
+            &builder,
+            usize::MAX,
+            false,
+            size,
+        );
+        assert_eq!(chunk_lens(&chunks), [3, 3]);
+
+        // One byte less and the third leaf goes to the next chunk
+        let chunks = chunk_leaves(
+            &Pubkey::default(),
+            &payer,
+            &leaves,
+            &builder,
+            usize::MAX,
+            false,
+            size - 1,
+        );
+        assert_eq!(chunk_lens(&chunks), [2, 2, 2]);
+        assert_eq!(chunks.concat(), leaves);
+    }
+
+    #[test]
+    fn chunks_fill_packets() {
+        let payer = Keypair::new();
+        let builder = TransactionBuilder::new(&payer.pubkey()).compute_unit_price(1);
+        let leaves = leaves(40);
+
+        for leaf_pages in [false, true] {
+            let max_size = max_transaction_size(leaf_pages);
+            let chunks = chunk_leaves(
+                &Pubkey::default(),
+                &payer,
+                &leaves,
+                &builder,
+                usize::MAX,
+                leaf_pages,
+                max_size,
+            );
+
+            assert!(chunks.len() > 1);
+            assert_eq!(chunks.concat(), leaves);
+            let full = chunks[0].len();
+            assert!(size_of(&payer, &builder, &leaves[..full], leaf_pages) <= max_size);
+            assert!(size_of(&payer, &builder, &leaves[..=full], leaf_pages) > max_size);
+            assert!(chunks.iter().all(|chunk| chunk.len() <= full));
+        }
+    }
+
+    #[test]
+    fn chunks_respect_max_inserts() {
+        let payer = Keypair::new();
+        let builder = TransactionBuilder::new(&payer.pubkey());
+        let leaves = leaves(10);
+
+        let chunks = chunk_leaves(
+            &Pubkey::default(),
+            &payer,
+            &leaves,
+            &builder,
+            3,
+            false,
+            PACKET_DATA_SIZE,
+        );
+        assert_eq!(chunk_lens(&chunks), [3, 3, 3, 1]);
+    }
+
+    #[test]
+    fn oversized_leaf_gets_own_chunk() {
+        let payer = Keypair::new();
+        let builder = TransactionBuilder::new(&payer.pubkey());
+        let leaves = leaves(3);
+
+        let chunks = chunk_leaves(
+            &Pubkey::default(),
+            &payer,
+            &leaves,
+            &builder,
+            usize::MAX,
+            false,
+            0,
+        );
+        assert_eq!(chunk_lens(&chunks), [1, 1, 1]);
+        assert!(
+            chunk_leaves(
+                &Pubkey::default(),
+                &payer,
+                &[],
+                &builder,
+                usize::MAX,
+                false,
+                0
+            )
+            .is_empty()
+        );
+    }
+}
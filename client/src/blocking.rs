@@ -0,0 +1,166 @@
+//! Blocking counterparts of the fetch helpers, `TreeMirror` sync and `TransactionBuilder`, on
+//! `solana_client::rpc_client::RpcClient` for integrators without an async runtime. Only with
+//! the `blocking` feature.
+
+use crate::{
+    SdkError,
+    account::{
+        LeafSource, MAX_MULTIPLE_ACCOUNTS, TreeLeaves, decode_account, leaf_source, page_leaves,
+        unpack_tree_hasher,
+    },
+    mirror::TreeMirror,
+    pda::tree_pdas,
+    transaction::{MAX_COMPUTE_UNIT_LIMIT, TransactionBuilder, simulated_units, simulation_config},
+    tree::MerkleTree,
+};
+use merkle_tree_errors::ProofError;
+use merkle_tree_program::{
+    config::PendingConfigAccount, hash::TreeHasher, leaf_page, multisig::MultisigAccount,
+    state::MerkleStateAccount, tree_metadata::TreeMetadataAccount,
+};
+use solana_client::rpc_client::RpcClient;
+use solana_program::{program_error::ProgramError, pubkey::Pubkey};
+use solana_sdk::transaction::Transaction;
+
+/// See `account::fetch_merkle_state`.
+pub fn fetch_merkle_state(
+    client: &RpcClient,
+    program_id: &Pubkey,
+) -> Result<Option<MerkleStateAccount>, SdkError> {
+    let (merkle_state_pda, _) = tree_pdas(program_id).merkle_state;
+    fetch_account(client, &merkle_state_pda, MerkleStateAccount::unpack)
+}
+
+/// See `account::fetch_merkle_tree`.
+pub fn fetch_merkle_tree(
+    client: &RpcClient,
+    program_id: &Pubkey,
+) -> Result<Option<MerkleTree>, SdkError> {
+    Ok(fetch_leaves(client, program_id)?
+        .map(|(tree_hasher, leaves)| MerkleTree::new(tree_hasher, leaves)))
+}
+
+/// See `account::fetch_leaves`.
+pub fn fetch_leaves(
+    client: &RpcClient,
+    program_id: &Pubkey,
+) -> Result<Option<TreeLeaves>, SdkError> {
+    let Some(merkle_state) = fetch_merkle_state(client, program_id)? else {
+        return Ok(None);
+    };
+    let tree_hasher = merkle_state.get_tree_hasher();
+    let (page_pdas, leaves_count) = match leaf_source(program_id, merkle_state)? {
+        LeafSource::Stored(leaves) => return Ok(Some((tree_hasher, leaves))),
+        LeafSource::Pages {
+            page_pdas,
+            leaves_count,
+        } => (page_pdas, leaves_count),
+    };
+
+    let mut leaves = Vec::with_capacity(page_pdas.len() * leaf_page::LEAVES_PER_PAGE);
+    for page_pdas in page_pdas.chunks(MAX_MULTIPLE_ACCOUNTS) {
+        let pages = client
+            .get_multiple_accounts_with_commitment(page_pdas, client.commitment())
+            .map_err(|err| SdkError::Rpc(err.to_string()))?
+            .value;
+        for (page_pda, page) in page_pdas.iter().zip(pages) {
+            leaves.extend_from_slice(page_leaves(
+                page_pda,
+                page.as_ref().map(|page| page.data.as_slice()),
+            )?);
+        }
+    }
+    leaves.truncate(leaves_count);
+
+    Ok(Some((tree_hasher, leaves)))
+}
+
+/// See `account::fetch_tree_hasher`.
+pub fn fetch_tree_hasher(
+    client: &RpcClient,
+    program_id: &Pubkey,
+) -> Result<Option<TreeHasher>, SdkError> {
+    let (merkle_state_pda, _) = tree_pdas(program_id).merkle_state;
+    fetch_account(client, &merkle_state_pda, unpack_tree_hasher)
+}
+
+/// See `account::fetch_pending_config`.
+pub fn fetch_pending_config(
+    client: &RpcClient,
+    program_id: &Pubkey,
+) -> Result<Option<PendingConfigAccount>, SdkError> {
+    let (pending_config_pda, _) = tree_pdas(program_id).pending_config;
+    fetch_account(client, &pending_config_pda, PendingConfigAccount::unpack)
+}
+
+/// See `account::fetch_multisig`.
+pub fn fetch_multisig(
+    client: &RpcClient,
+    program_id: &Pubkey,
+) -> Result<Option<MultisigAccount>, SdkError> {
+    let (multisig_pda, _) = tree_pdas(program_id).multisig;
+    fetch_account(client, &multisig_pda, MultisigAccount::unpack)
+}
+
+/// See `account::fetch_tree_metadata`.
+pub fn fetch_tree_metadata(
+    client: &RpcClient,
+    program_id: &Pubkey,
+) -> Result<Option<TreeMetadataAccount>, SdkError> {
+    let (metadata_pda, _) = tree_pdas(program_id).tree_metadata;
+    fetch_account(client, &metadata_pda, TreeMetadataAccount::unpack)
+}
+
+impl TreeMirror {
+    /// See `TreeMirror::fetch`.
+    pub fn fetch_blocking(
+        client: &RpcClient,
+        program_id: &Pubkey,
+    ) -> Result<Option<Self>, SdkError> {
+        Ok(fetch_leaves(client, program_id)?
+            .map(|(tree_hasher, leaves)| Self::new(program_id, tree_hasher, leaves)))
+    }
+
+    /// See `TreeMirror::sync`.
+    pub fn sync_blocking(&mut self, client: &RpcClient) -> Result<usize, SdkError> {
+        let (tree_hasher, leaves) =
+            fetch_leaves(client, self.program_id())?.ok_or(ProofError::LeavesNotStored)?;
+        Ok(self.update(tree_hasher, leaves))
+    }
+}
+
+impl TransactionBuilder {
+    /// See `TransactionBuilder::simulate`.
+    pub fn simulate_blocking(&self, client: &RpcClient) -> Result<u64, SdkError> {
+        let tx = self.transaction(MAX_COMPUTE_UNIT_LIMIT);
+        let simulation = client
+            .simulate_transaction_with_config(&tx, simulation_config())
+            .map_err(|err| SdkError::Rpc(err.to_string()))?
+            .value;
+        simulated_units(simulation)
+    }
+
+    /// See `TransactionBuilder::build`.
+    pub fn build_blocking(&self, client: &RpcClient) -> Result<Transaction, SdkError> {
+        let units = self.simulate_blocking(client)?;
+        Ok(self.transaction(self.compute_unit_limit(units)))
+    }
+}
+
+/// See `account::fetch_account`.
+fn fetch_account<T>(
+    client: &RpcClient,
+    pubkey: &Pubkey,
+    unpack: impl FnOnce(&[u8]) -> Result<T, ProgramError>,
+) -> Result<Option<T>, SdkError> {
+    let account = client
+        .get_account_with_commitment(pubkey, client.commitment())
+        .map_err(|err| SdkError::Rpc(err.to_string()))?
+        .value;
+
+    decode_account(
+        pubkey,
+        account.as_ref().map(|account| account.data.as_slice()),
+        unpack,
+    )
+}
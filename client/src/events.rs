@@ -0,0 +1,141 @@
+//! Typed events of tree transactions, decoded from the self-CPI inner instructions the
+//! program emits them as rather than from logs, which RPC nodes may truncate.
+
+use crate::SdkError;
+use merkle_tree_program::events::MerkleTreeEvent;
+use solana_program::pubkey::Pubkey;
+use solana_sdk::bs58;
+use solana_transaction_status::{
+    EncodedTransaction, EncodedTransactionWithStatusMeta, UiInstruction, UiLoadedAddresses,
+    UiMessage, UiParsedInstruction, option_serializer::OptionSerializer,
+};
+use std::str::FromStr;
+
+/// Leaf insert of a transaction, with the root and leaves count after it unless the tree
+/// event verbosity leaves them out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InsertedLeaf {
+    pub leaf_index: u32,
+    pub leaf: [u8; 32],
+    pub root: Option<[u8; 32]>,
+    pub leaves_count: Option<u32>,
+}
+
+/// Events of `program_id` in `tx`, in emission order. Only inner instructions invoking
+/// `program_id` itself are decoded, so other programs can't forge events.
+pub fn parse_events(
+    program_id: &Pubkey,
+    tx: &EncodedTransactionWithStatusMeta,
+) -> Result<Vec<MerkleTreeEvent>, SdkError> {
+    let meta = tx
+        .meta
+        .as_ref()
+        .ok_or(SdkError::InvalidTransaction("meta is empty"))?;
+    let OptionSerializer::Some(inner_instructions) = &meta.inner_instructions else {
+        return Ok(Vec::new());
+    };
+
+    let account_keys = account_keys(&tx.transaction, &meta.loaded_addresses)?;
+
+    let mut events = Vec::new();
+    for instruction in inner_instructions
+        .iter()
+        .flat_map(|inner_instructions| &inner_instructions.instructions)
+    {
+        let (instruction_program_id, data) = match instruction {
+            UiInstruction::Compiled(instruction) => (
+                account_keys
+                    .get(instruction.program_id_index as usize)
+                    .copied(),
+                &instruction.data,
+            ),
+            UiInstruction::Parsed(UiParsedInstruction::PartiallyDecoded(instruction)) => (
+                Some(parse_pubkey(&instruction.program_id)?),
+                &instruction.data,
+            ),
+            UiInstruction::Parsed(UiParsedInstruction::Parsed(_)) => continue,
+        };
+        if instruction_program_id.as_ref() != Some(program_id) {
+            continue;
+        }
+
+        let data = bs58::decode(data)
+            .into_vec()
+            .map_err(|_| SdkError::InvalidTransaction("inner instruction data isn't base58"))?;
+        events.extend(MerkleTreeEvent::decode(&data));
+    }
+
+    Ok(events)
+}
+
+/// Leaf inserts of `program_id` in `tx`, each with the `RootUpdated` event following it.
+pub fn parse_inserts(
+    program_id: &Pubkey,
+    tx: &EncodedTransactionWithStatusMeta,
+) -> Result<Vec<InsertedLeaf>, SdkError> {
+    let mut inserts: Vec<InsertedLeaf> = Vec::new();
+    for event in parse_events(program_id, tx)? {
+        match event {
+            MerkleTreeEvent::LeafInserted(event) => inserts.push(InsertedLeaf {
+                leaf_index: event.leaf_index,
+                leaf: event.leaf,
+                root: None,
+                leaves_count: None,
+            }),
+            MerkleTreeEvent::RootUpdated(event) => {
+                if let Some(insert) = inserts.last_mut().filter(|insert| insert.root.is_none()) {
+                    insert.root = Some(event.root);
+                    insert.leaves_count = Some(event.leaves_count);
+                }
+            }
+        }
+    }
+
+    Ok(inserts)
+}
+
+/// Account keys of `transaction`: static keys followed by keys `loaded` from lookup tables,
+/// which parsed messages already list.
+fn account_keys(
+    transaction: &EncodedTransaction,
+    loaded: &OptionSerializer<UiLoadedAddresses>,
+) -> Result<Vec<Pubkey>, SdkError> {
+    let static_keys = match transaction {
+        EncodedTransaction::Json(transaction) => match &transaction.message {
+            UiMessage::Raw(message) => message
+                .account_keys
+                .iter()
+                .map(|key| parse_pubkey(key))
+                .collect::<Result<Vec<_>, _>>()?,
+            UiMessage::Parsed(message) => {
+                return message
+                    .account_keys
+                    .iter()
+                    .map(|key| parse_pubkey(&key.pubkey))
+                    .collect();
+            }
+        },
+        transaction => transaction
+            .decode()
+            .ok_or(SdkError::InvalidTransaction("transaction can't be decoded"))?
+            .message
+            .static_account_keys()
+            .to_vec(),
+    };
+
+    let loaded_keys = match loaded {
+        OptionSerializer::Some(loaded) => loaded
+            .writable
+            .iter()
+            .chain(&loaded.readonly)
+            .map(|key| parse_pubkey(key))
+            .collect::<Result<Vec<_>, _>>()?,
+        _ => Vec::new(),
+    };
+
+    Ok([static_keys, loaded_keys].concat())
+}
+
+fn parse_pubkey(key: &str) -> Result<Pubkey, SdkError> {
+    Pubkey::from_str(key).map_err(|_| SdkError::InvalidTransaction("invalid account key"))
+}
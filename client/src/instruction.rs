@@ -0,0 +1,813 @@
+//! Instruction builders, one per `MerkleTreeInstruction` with the accounts its processor
+//! takes, in order. Authority instructions take the authority key, trees handed to a
+//! multisig pass the multisig and add its signers with `with_multisig`.
+
+use crate::pda::{find_leaf_page_pda, tree_pdas};
+use merkle_tree_program::{
+    config::ConfigChange, events::EventVerbosity, hash::TreeHasher,
+    instruction::MerkleTreeInstruction, leaf_page,
+};
+use solana_program::{
+    instruction::{AccountMeta, Instruction},
+    pubkey::Pubkey,
+    system_program,
+};
+use std::ops::Range;
+
+/// Create the tree with its first leaf `hash`, hashed as `tree_hasher`, paying rent for
+/// `leaf_capacity` leaves up front, 0 for a freely growing tree.
+pub fn init_tree_ix(
+    program_id: &Pubkey,
+    payer: &Pubkey,
+    hash: [u8; 32],
+    leaf_capacity: u32,
+    tree_hasher: &TreeHasher,
+) -> Instruction {
+    insert_instruction(
+        program_id,
+        payer,
+        MerkleTreeInstruction::InitTree {
+            hash,
+            leaf_capacity,
+            hash_algo: tree_hasher.hash_algo,
+            domain_separation: tree_hasher.domain_separation,
+            ordered: tree_hasher.ordered,
+            left_balanced: tree_hasher.left_balanced,
+            zero_padded: tree_hasher.zero_padded,
+        },
+    )
+}
+
+/// Insert leaf `hash`, failing unless the root is `expected_root` if set. Trees with leaf
+/// pages take the page of the new leaf as well, see `with_leaf_page`.
+pub fn insert_leaf_ix(
+    program_id: &Pubkey,
+    payer: &Pubkey,
+    hash: [u8; 32],
+    expected_root: Option<[u8; 32]>,
+) -> Instruction {
+    insert_instruction(
+        program_id,
+        payer,
+        MerkleTreeInstruction::InsertLeaf {
+            hash,
+            expected_root,
+        },
+    )
+}
+
+/// Insert leaf hashed on-chain from raw `data`, see `insert_leaf_ix`.
+pub fn insert_value_ix(
+    program_id: &Pubkey,
+    payer: &Pubkey,
+    data: Vec<u8>,
+    expected_root: Option<[u8; 32]>,
+) -> Instruction {
+    insert_instruction(
+        program_id,
+        payer,
+        MerkleTreeInstruction::InsertValue {
+            data,
+            expected_root,
+        },
+    )
+}
+
+/// Insert leaf `hash` sweepable once `expires_at`(unix timestamp) passed, see
+/// `insert_leaf_ix`.
+pub fn insert_expiring_leaf_ix(
+    program_id: &Pubkey,
+    payer: &Pubkey,
+    hash: [u8; 32],
+    expires_at: i64,
+    expected_root: Option<[u8; 32]>,
+) -> Instruction {
+    insert_instruction(
+        program_id,
+        payer,
+        MerkleTreeInstruction::InsertExpiringLeaf {
+            hash,
+            expires_at,
+            expected_root,
+        },
+    )
+}
+
+/// Replace `old_leaf` at `leaf_index` with `new_leaf`. Trees with leaf pages take the page
+/// of the leaf as well, see `with_leaf_page`.
+pub fn replace_leaf_ix(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    leaf_index: u32,
+    old_leaf: [u8; 32],
+    new_leaf: [u8; 32],
+    proof: Vec<[u8; 32]>,
+) -> Instruction {
+    insert_instruction(
+        program_id,
+        authority,
+        MerkleTreeInstruction::ReplaceLeaf {
+            leaf_index,
+            old_leaf,
+            new_leaf,
+            proof,
+        },
+    )
+}
+
+/// Append the leaf page holding leaf `leaf_index` to insert or replace `instruction`, as
+/// trees with leaf pages require. Inserts write the leaf at the current leaves count.
+pub fn with_leaf_page(mut instruction: Instruction, leaf_index: u32) -> Instruction {
+    let merkle_state_pda = instruction.accounts[0].pubkey;
+    let (leaf_page_pda, _) = find_leaf_page_pda(
+        &instruction.program_id,
+        &merkle_state_pda,
+        leaf_page::page_index(leaf_index),
+    );
+    instruction
+        .accounts
+        .push(AccountMeta::new(leaf_page_pda, false));
+
+    instruction
+}
+
+/// Verify `leaf` at `leaf_index`, `proof` may be truncated at the canopy.
+pub fn verify_leaf_ix(
+    program_id: &Pubkey,
+    leaf_index: u32,
+    leaf: [u8; 32],
+    proof: Vec<[u8; 32]>,
+) -> Instruction {
+    view_instruction(
+        program_id,
+        MerkleTreeInstruction::VerifyLeaf {
+            leaf_index,
+            leaf,
+            proof,
+        },
+    )
+}
+
+/// Verify `(leaf_index, leaf)` pairs(sorted by index) with one deduplicated multiproof.
+pub fn verify_multiproof_ix(
+    program_id: &Pubkey,
+    leaves: Vec<(u32, [u8; 32])>,
+    proof: Vec<[u8; 32]>,
+) -> Instruction {
+    view_instruction(
+        program_id,
+        MerkleTreeInstruction::VerifyMultiProof { leaves, proof },
+    )
+}
+
+/// Verify the tree at `old_root` with `old_leaves_count` leaves is a prefix of the current
+/// one, `proof` being the full proof of the last old leaf `leaf`.
+pub fn verify_consistency_ix(
+    program_id: &Pubkey,
+    old_root: [u8; 32],
+    old_leaves_count: u32,
+    leaf: [u8; 32],
+    proof: Vec<[u8; 32]>,
+) -> Instruction {
+    view_instruction(
+        program_id,
+        MerkleTreeInstruction::VerifyConsistency {
+            old_root,
+            old_leaves_count,
+            leaf,
+            proof,
+        },
+    )
+}
+
+/// Set the current root as return data, see `return_data::ReturnData`.
+pub fn get_root_hash_ix(program_id: &Pubkey) -> Instruction {
+    view_instruction(program_id, MerkleTreeInstruction::GetRootHash)
+}
+
+pub fn propose_authority_ix(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    new_authority: Pubkey,
+) -> Instruction {
+    authority_instruction(
+        program_id,
+        authority,
+        MerkleTreeInstruction::ProposeAuthority { new_authority },
+    )
+}
+
+pub fn accept_authority_ix(program_id: &Pubkey, pending_authority: &Pubkey) -> Instruction {
+    authority_instruction(
+        program_id,
+        pending_authority,
+        MerkleTreeInstruction::AcceptAuthority,
+    )
+}
+
+pub fn pause_tree_ix(program_id: &Pubkey, authority: &Pubkey) -> Instruction {
+    authority_instruction(program_id, authority, MerkleTreeInstruction::PauseTree)
+}
+
+pub fn resume_tree_ix(program_id: &Pubkey, authority: &Pubkey) -> Instruction {
+    authority_instruction(program_id, authority, MerkleTreeInstruction::ResumeTree)
+}
+
+/// Link leaves snapshot at `uri` with `content_hash` to the tree at its current `root`.
+pub fn set_archive_uri_ix(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    root: [u8; 32],
+    content_hash: [u8; 32],
+    uri: String,
+) -> Instruction {
+    payer_instruction(
+        program_id,
+        authority,
+        MerkleTreeInstruction::SetArchiveUri {
+            root,
+            content_hash,
+            uri,
+        },
+    )
+}
+
+pub fn finalize_tree_ix(program_id: &Pubkey, authority: &Pubkey) -> Instruction {
+    payer_instruction(program_id, authority, MerkleTreeInstruction::FinalizeTree)
+}
+
+/// Drop stored leaves keeping only the frontier, freed rent goes to the authority.
+pub fn enable_frontier_mode_ix(program_id: &Pubkey, authority: &Pubkey) -> Instruction {
+    tree_instruction(
+        program_id,
+        MerkleTreeInstruction::EnableFrontierMode,
+        vec![AccountMeta::new(*authority, true)],
+    )
+}
+
+/// Move stored leaves of `pages` to leaf pages, which the authority pays rent for. `pages`
+/// must start at the next page to write, see `MerkleStateAccount::get_paged_leaves`, and
+/// the call writing the last page drops the stored leaves.
+pub fn enable_leaf_pages_ix(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    pages: Range<u32>,
+) -> Instruction {
+    let tree_pdas = tree_pdas(program_id);
+    let leaf_pages = pages.clone().map(|page_index| {
+        let (leaf_page_pda, _) = tree_pdas.find_leaf_page(page_index);
+        AccountMeta::new(leaf_page_pda, false)
+    });
+
+    tree_instruction(
+        program_id,
+        MerkleTreeInstruction::EnableLeafPages {
+            first_page: pages.start,
+            pages_count: pages.len() as u32,
+        },
+        [
+            AccountMeta::new(*authority, true),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ]
+        .into_iter()
+        .chain(leaf_pages)
+        .collect(),
+    )
+}
+
+/// Tombstone expired leaves, `cranker` gets the sweep bounty.
+pub fn sweep_expired_ix(program_id: &Pubkey, cranker: &Pubkey) -> Instruction {
+    tree_instruction(
+        program_id,
+        MerkleTreeInstruction::SweepExpired,
+        [AccountMeta::new(*cranker, true)]
+            .into_iter()
+            .chain(event_accounts(program_id))
+            .collect(),
+    )
+}
+
+/// Upgrade the merkle state account to the current layout, `payer` pays rent for growth.
+pub fn migrate_state_ix(program_id: &Pubkey, payer: &Pubkey) -> Instruction {
+    payer_instruction(program_id, payer, MerkleTreeInstruction::MigrateState)
+}
+
+/// Grow the merkle state account towards `target_len` bytes by at most one realloc, repeat
+/// it to grow further.
+pub fn grow_tree_ix(program_id: &Pubkey, payer: &Pubkey, target_len: u32) -> Instruction {
+    payer_instruction(
+        program_id,
+        payer,
+        MerkleTreeInstruction::GrowTree { target_len },
+    )
+}
+
+/// Recompute and publish the root of leaves appended in lazy root mode, permissionless.
+pub fn commit_root_ix(program_id: &Pubkey) -> Instruction {
+    tree_instruction(
+        program_id,
+        MerkleTreeInstruction::CommitRoot,
+        event_accounts(program_id).to_vec(),
+    )
+}
+
+/// Create or overwrite the tree metadata account.
+pub fn set_metadata_ix(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    name: String,
+    description: String,
+    uri: String,
+) -> Instruction {
+    let (metadata_pda, _) = tree_pdas(program_id).tree_metadata;
+    tree_instruction(
+        program_id,
+        MerkleTreeInstruction::SetMetadata {
+            name,
+            description,
+            uri,
+        },
+        vec![
+            AccountMeta::new(*authority, true),
+            AccountMeta::new_readonly(system_program::id(), false),
+            AccountMeta::new(metadata_pda, false),
+        ],
+    )
+}
+
+/// Create or overwrite the tree multisig requiring `threshold` of `signers`, `payer` pays
+/// rent of the multisig account.
+pub fn set_multisig_ix(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    payer: &Pubkey,
+    threshold: u8,
+    signers: Vec<Pubkey>,
+) -> Instruction {
+    let (multisig_pda, _) = tree_pdas(program_id).multisig;
+    tree_instruction(
+        program_id,
+        MerkleTreeInstruction::SetMultisig { threshold, signers },
+        vec![
+            AccountMeta::new_readonly(*authority, true),
+            AccountMeta::new(*payer, true),
+            AccountMeta::new_readonly(system_program::id(), false),
+            AccountMeta::new(multisig_pda, false),
+        ],
+    )
+}
+
+/// Direct config instruction applying `change`, e.g. `SetRateLimit` for
+/// `ConfigChange::RateLimit`. Timelocked trees take `queue_config_ix` instead.
+pub fn config_change_ix(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    change: ConfigChange,
+) -> Instruction {
+    match change {
+        ConfigChange::RejectDuplicates(reject_duplicates) => {
+            set_reject_duplicates_ix(program_id, authority, reject_duplicates)
+        }
+        ConfigChange::RecordLeafMetadata(record_leaf_metadata) => {
+            set_record_leaf_metadata_ix(program_id, authority, record_leaf_metadata)
+        }
+        ConfigChange::EventVerbosity(event_verbosity) => {
+            set_event_verbosity_ix(program_id, authority, event_verbosity)
+        }
+        ConfigChange::Permissioned(permissioned) => {
+            set_permissioned_ix(program_id, authority, permissioned)
+        }
+        ConfigChange::AddInserter(inserter) => add_inserter_ix(program_id, authority, inserter),
+        ConfigChange::RemoveInserter(inserter) => {
+            remove_inserter_ix(program_id, authority, inserter)
+        }
+        ConfigChange::RateLimit {
+            max_inserts_per_slot,
+            max_inserts_per_epoch,
+        } => set_rate_limit_ix(
+            program_id,
+            authority,
+            max_inserts_per_slot,
+            max_inserts_per_epoch,
+        ),
+        ConfigChange::MaxLeaves(max_leaves) => set_max_leaves_ix(program_id, authority, max_leaves),
+        ConfigChange::RejectSentinelLeaves(reject_sentinel_leaves) => {
+            set_reject_sentinel_leaves_ix(program_id, authority, reject_sentinel_leaves)
+        }
+        ConfigChange::ConfigTimelock(config_timelock) => {
+            set_config_timelock_ix(program_id, authority, config_timelock)
+        }
+        ConfigChange::GrowthPercent(growth_percent) => {
+            set_growth_percent_ix(program_id, authority, growth_percent)
+        }
+        ConfigChange::CanopyDepth(canopy_depth) => {
+            set_canopy_depth_ix(program_id, authority, canopy_depth)
+        }
+        ConfigChange::LazyRoot(lazy_root) => set_lazy_root_ix(program_id, authority, lazy_root),
+    }
+}
+
+pub fn set_reject_duplicates_ix(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    reject_duplicates: bool,
+) -> Instruction {
+    authority_instruction(
+        program_id,
+        authority,
+        MerkleTreeInstruction::SetRejectDuplicates { reject_duplicates },
+    )
+}
+
+pub fn set_record_leaf_metadata_ix(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    record_leaf_metadata: bool,
+) -> Instruction {
+    authority_instruction(
+        program_id,
+        authority,
+        MerkleTreeInstruction::SetRecordLeafMetadata {
+            record_leaf_metadata,
+        },
+    )
+}
+
+pub fn set_event_verbosity_ix(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    event_verbosity: EventVerbosity,
+) -> Instruction {
+    authority_instruction(
+        program_id,
+        authority,
+        MerkleTreeInstruction::SetEventVerbosity { event_verbosity },
+    )
+}
+
+pub fn set_permissioned_ix(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    permissioned: bool,
+) -> Instruction {
+    authority_instruction(
+        program_id,
+        authority,
+        MerkleTreeInstruction::SetPermissioned { permissioned },
+    )
+}
+
+/// Allow `inserter` while the tree is permissioned, the authority pays rent for growth.
+pub fn add_inserter_ix(program_id: &Pubkey, authority: &Pubkey, inserter: Pubkey) -> Instruction {
+    payer_instruction(
+        program_id,
+        authority,
+        MerkleTreeInstruction::AddInserter { inserter },
+    )
+}
+
+/// Disallow `inserter`, freed rent goes to the authority.
+pub fn remove_inserter_ix(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    inserter: Pubkey,
+) -> Instruction {
+    tree_instruction(
+        program_id,
+        MerkleTreeInstruction::RemoveInserter { inserter },
+        vec![AccountMeta::new(*authority, true)],
+    )
+}
+
+/// Limit inserts of each payer per slot and per epoch, 0 lifts a limit.
+pub fn set_rate_limit_ix(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    max_inserts_per_slot: u32,
+    max_inserts_per_epoch: u32,
+) -> Instruction {
+    tree_instruction(
+        program_id,
+        MerkleTreeInstruction::SetRateLimit {
+            max_inserts_per_slot,
+            max_inserts_per_epoch,
+        },
+        vec![AccountMeta::new(*authority, true)],
+    )
+}
+
+/// Cap the tree at `max_leaves` leaves, 0 lifts the cap.
+pub fn set_max_leaves_ix(program_id: &Pubkey, authority: &Pubkey, max_leaves: u32) -> Instruction {
+    authority_instruction(
+        program_id,
+        authority,
+        MerkleTreeInstruction::SetMaxLeaves { max_leaves },
+    )
+}
+
+pub fn set_reject_sentinel_leaves_ix(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    reject_sentinel_leaves: bool,
+) -> Instruction {
+    authority_instruction(
+        program_id,
+        authority,
+        MerkleTreeInstruction::SetRejectSentinelLeaves {
+            reject_sentinel_leaves,
+        },
+    )
+}
+
+/// Delay config changes by `config_timelock` slots, 0 applies them directly again.
+pub fn set_config_timelock_ix(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    config_timelock: u64,
+) -> Instruction {
+    authority_instruction(
+        program_id,
+        authority,
+        MerkleTreeInstruction::SetConfigTimelock { config_timelock },
+    )
+}
+
+/// Grow the account by `growth_percent` of the leaves count in spare leaves, spare bytes are
+/// refunded to the authority when set to 0.
+pub fn set_growth_percent_ix(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    growth_percent: u16,
+) -> Instruction {
+    tree_instruction(
+        program_id,
+        MerkleTreeInstruction::SetGrowthPercent { growth_percent },
+        vec![AccountMeta::new(*authority, true)],
+    )
+}
+
+/// Cache the top `canopy_depth` levels, the authority pays rent for growth or is refunded.
+pub fn set_canopy_depth_ix(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    canopy_depth: u8,
+) -> Instruction {
+    payer_instruction(
+        program_id,
+        authority,
+        MerkleTreeInstruction::SetCanopyDepth { canopy_depth },
+    )
+}
+
+/// Toggle lazy root mode, the root must be committed before disabling it.
+pub fn set_lazy_root_ix(program_id: &Pubkey, authority: &Pubkey, lazy_root: bool) -> Instruction {
+    authority_instruction(
+        program_id,
+        authority,
+        MerkleTreeInstruction::SetLazyRoot { lazy_root },
+    )
+}
+
+/// Queue `change` of a timelocked tree, `payer` pays rent of the pending config account.
+pub fn queue_config_ix(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    payer: &Pubkey,
+    change: ConfigChange,
+) -> Instruction {
+    tree_instruction(
+        program_id,
+        MerkleTreeInstruction::QueueConfig { change },
+        vec![
+            AccountMeta::new_readonly(*authority, true),
+            AccountMeta::new(*payer, true),
+            AccountMeta::new_readonly(system_program::id(), false),
+            AccountMeta::new(pending_config_pda(program_id), false),
+        ],
+    )
+}
+
+/// Apply queued changes whose timelock passed, the authority pays rent for growth.
+pub fn apply_pending_config_ix(program_id: &Pubkey, authority: &Pubkey) -> Instruction {
+    tree_instruction(
+        program_id,
+        MerkleTreeInstruction::ApplyPendingConfig,
+        vec![
+            AccountMeta::new(*authority, true),
+            AccountMeta::new_readonly(system_program::id(), false),
+            AccountMeta::new(pending_config_pda(program_id), false),
+        ],
+    )
+}
+
+/// Drop all queued changes, rent goes back to the authority.
+pub fn cancel_pending_config_ix(program_id: &Pubkey, authority: &Pubkey) -> Instruction {
+    tree_instruction(
+        program_id,
+        MerkleTreeInstruction::CancelPendingConfig,
+        vec![
+            AccountMeta::new(*authority, true),
+            AccountMeta::new(pending_config_pda(program_id), false),
+        ],
+    )
+}
+
+/// Sign authority `instruction` by the tree `multisig` instead: its authority account(the
+/// second one) becomes the multisig, approved by `signers` appended to the accounts.
+pub fn with_multisig(
+    mut instruction: Instruction,
+    multisig: &Pubkey,
+    signers: &[Pubkey],
+) -> Instruction {
+    instruction.accounts[1] = AccountMeta {
+        pubkey: *multisig,
+        is_signer: false,
+        is_writable: instruction.accounts[1].is_writable,
+    };
+    instruction.accounts.extend(
+        signers
+            .iter()
+            .map(|signer| AccountMeta::new_readonly(*signer, true)),
+    );
+
+    instruction
+}
+
+/// Instruction taking the merkle state account first, then `accounts`.
+fn tree_instruction(
+    program_id: &Pubkey,
+    instruction: MerkleTreeInstruction,
+    accounts: Vec<AccountMeta>,
+) -> Instruction {
+    let (merkle_state_pda, _) = tree_pdas(program_id).merkle_state;
+    Instruction::new_with_bytes(
+        *program_id,
+        &instruction.pack(),
+        [AccountMeta::new(merkle_state_pda, false)]
+            .into_iter()
+            .chain(accounts)
+            .collect(),
+    )
+}
+
+/// Read-only instruction taking only the merkle state account.
+fn view_instruction(program_id: &Pubkey, instruction: MerkleTreeInstruction) -> Instruction {
+    let (merkle_state_pda, _) = tree_pdas(program_id).merkle_state;
+    Instruction::new_with_bytes(
+        *program_id,
+        &instruction.pack(),
+        vec![AccountMeta::new_readonly(merkle_state_pda, false)],
+    )
+}
+
+/// Instruction signed by the tree authority, which pays nothing.
+fn authority_instruction(
+    program_id: &Pubkey,
+    authority: &Pubkey,
+    instruction: MerkleTreeInstruction,
+) -> Instruction {
+    tree_instruction(
+        program_id,
+        instruction,
+        vec![AccountMeta::new_readonly(*authority, true)],
+    )
+}
+
+/// Instruction whose signer pays rent of a grown merkle state account.
+fn payer_instruction(
+    program_id: &Pubkey,
+    payer: &Pubkey,
+    instruction: MerkleTreeInstruction,
+) -> Instruction {
+    tree_instruction(
+        program_id,
+        instruction,
+        vec![
+            AccountMeta::new(*payer, true),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ],
+    )
+}
+
+/// Insert-like instruction, `payer` pays rent and events are emitted by self-CPI.
+fn insert_instruction(
+    program_id: &Pubkey,
+    payer: &Pubkey,
+    instruction: MerkleTreeInstruction,
+) -> Instruction {
+    tree_instruction(
+        program_id,
+        instruction,
+        [
+            AccountMeta::new(*payer, true),
+            AccountMeta::new_readonly(system_program::id(), false),
+        ]
+        .into_iter()
+        .chain(event_accounts(program_id))
+        .collect(),
+    )
+}
+
+/// Accounts of the self-CPI events are emitted with.
+fn event_accounts(program_id: &Pubkey) -> [AccountMeta; 2] {
+    let (event_authority_pda, _) = tree_pdas(program_id).event_authority;
+    [
+        AccountMeta::new_readonly(event_authority_pda, false),
+        AccountMeta::new_readonly(*program_id, false),
+    ]
+}
+
+fn pending_config_pda(program_id: &Pubkey) -> Pubkey {
+    tree_pdas(program_id).pending_config.0
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::pda::{find_event_authority_pda, find_merkle_state_pda};
+
+    #[test]
+    fn insert_leaf_ix_round_trips() {
+        let program_id = Pubkey::new_unique();
+        let payer = Pubkey::new_unique();
+        let instruction = insert_leaf_ix(&program_id, &payer, [1; 32], Some([2; 32]));
+
+        assert!(matches!(
+            MerkleTreeInstruction::unpack(&instruction.data).unwrap(),
+            MerkleTreeInstruction::InsertLeaf {
+                hash: [1, ..],
+                expected_root: Some([2, ..]),
+            }
+        ));
+        assert_eq!(instruction.program_id, program_id);
+        assert_eq!(
+            instruction.accounts,
+            [
+                AccountMeta::new(find_merkle_state_pda(&program_id).0, false),
+                AccountMeta::new(payer, true),
+                AccountMeta::new_readonly(system_program::id(), false),
+                AccountMeta::new_readonly(find_event_authority_pda(&program_id).0, false),
+                AccountMeta::new_readonly(program_id, false),
+            ]
+        );
+    }
+
+    #[test]
+    fn verify_leaf_ix_only_reads_merkle_state() {
+        let program_id = Pubkey::new_unique();
+        let instruction = verify_leaf_ix(&program_id, 3, [1; 32], vec![[2; 32]; 4]);
+
+        assert!(matches!(
+            MerkleTreeInstruction::unpack(&instruction.data).unwrap(),
+            MerkleTreeInstruction::VerifyLeaf { leaf_index: 3, proof, .. } if proof.len() == 4
+        ));
+        assert_eq!(
+            instruction.accounts,
+            [AccountMeta::new_readonly(
+                find_merkle_state_pda(&program_id).0,
+                false
+            )]
+        );
+    }
+
+    #[test]
+    fn with_leaf_page_appends_page_of_leaf() {
+        let program_id = Pubkey::new_unique();
+        let (merkle_state_pda, _) = find_merkle_state_pda(&program_id);
+        let leaf_index = 1_000;
+        let instruction = insert_leaf_ix(&program_id, &Pubkey::new_unique(), [1; 32], None);
+
+        let paged = with_leaf_page(instruction.clone(), leaf_index);
+        let (leaf_page_pda, _) = find_leaf_page_pda(
+            &program_id,
+            &merkle_state_pda,
+            leaf_page::page_index(leaf_index),
+        );
+        assert_eq!(
+            paged.accounts[..instruction.accounts.len()],
+            instruction.accounts
+        );
+        assert_eq!(
+            paged.accounts.last(),
+            Some(&AccountMeta::new(leaf_page_pda, false))
+        );
+    }
+
+    #[test]
+    fn with_multisig_replaces_authority_signer() {
+        let program_id = Pubkey::new_unique();
+        let multisig = Pubkey::new_unique();
+        let signers = [Pubkey::new_unique(), Pubkey::new_unique()];
+        let instruction = pause_tree_ix(&program_id, &Pubkey::new_unique());
+
+        let instruction = with_multisig(instruction, &multisig, &signers);
+        assert_eq!(
+            instruction.accounts[1..],
+            [
+                AccountMeta::new_readonly(multisig, false),
+                AccountMeta::new_readonly(signers[0], true),
+                AccountMeta::new_readonly(signers[1], true),
+            ]
+        );
+    }
+}
@@ -0,0 +1,23 @@
+//! Rust client of the merkle tree program: typed instruction builders, account fetch helpers,
+//! PDA derivation and off-chain proof generation, so integrators don't assemble account lists
+//! or send transactions to get proofs.
+
+pub mod account;
+pub mod batch;
+#[cfg(feature = "blocking")]
+pub mod blocking;
+pub mod events;
+pub mod instruction;
+pub mod mirror;
+pub mod pda;
+pub mod provider;
+pub mod send;
+pub mod subscription;
+pub mod transaction;
+pub mod tree;
+
+pub use merkle_tree_errors::SdkError;
+pub use pda::{
+    find_event_authority_pda, find_leaf_page_pda, find_merkle_state_pda, find_multisig_pda,
+    find_pending_config_pda, find_tree_metadata_pda,
+};
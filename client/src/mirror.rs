@@ -0,0 +1,89 @@
+//! Local copy of an on-chain tree kept in sync by diffing its fetched leaves or by applying
+//! its insert events, so proofs are served from memory without refetching or rehashing the
+//! tree each time.
+
+use crate::{SdkError, account::fetch_leaves, pda::tree_pdas, tree::MerkleTree};
+use merkle_tree_core::MerkleProof;
+use merkle_tree_errors::ProofError;
+use merkle_tree_program::{events::MerkleTreeEvent, hash::TreeHasher};
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_program::pubkey::Pubkey;
+
+/// Tree of `program_id` mirrored locally, see `MerkleTree` for proofs.
+#[derive(Debug, Clone)]
+pub struct TreeMirror {
+    program_id: Pubkey,
+    tree: MerkleTree,
+}
+
+impl TreeMirror {
+    /// Mirror the tree of `program_id`, `None` if it isn't created yet.
+    pub async fn fetch(client: &RpcClient, program_id: &Pubkey) -> Result<Option<Self>, SdkError> {
+        Ok(fetch_leaves(client, program_id)
+            .await?
+            .map(|(tree_hasher, leaves)| Self::new(program_id, tree_hasher, leaves)))
+    }
+
+    pub fn program_id(&self) -> &Pubkey {
+        &self.program_id
+    }
+
+    pub fn tree(&self) -> &MerkleTree {
+        &self.tree
+    }
+
+    /// Self-describing proof for leaf at `leaf_index`, with the merkle state account as tree
+    /// id.
+    pub fn merkle_proof(&self, leaf_index: u32) -> Result<MerkleProof, ProofError> {
+        let (merkle_state_pda, _) = tree_pdas(&self.program_id).merkle_state;
+        self.tree.merkle_proof(&merkle_state_pda, leaf_index)
+    }
+
+    /// Refetch the leaves and rehash only the paths of leaves appended or replaced since the
+    /// last sync. Returns how many leaves changed.
+    pub async fn sync(&mut self, client: &RpcClient) -> Result<usize, SdkError> {
+        let (tree_hasher, leaves) = fetch_leaves(client, &self.program_id)
+            .await?
+            .ok_or(ProofError::LeavesNotStored)?;
+        Ok(self.update(tree_hasher, leaves))
+    }
+
+    /// Tree of `program_id` built from fetched `leaves`.
+    pub(crate) fn new(program_id: &Pubkey, tree_hasher: TreeHasher, leaves: Vec<[u8; 32]>) -> Self {
+        Self {
+            program_id: *program_id,
+            tree: MerkleTree::new(tree_hasher, leaves),
+        }
+    }
+
+    /// Bring the mirror up to date with fetched `leaves`, see `sync`.
+    pub(crate) fn update(&mut self, tree_hasher: TreeHasher, leaves: Vec<[u8; 32]>) -> usize {
+        if tree_hasher != self.tree.tree_hasher() {
+            self.tree = MerkleTree::new(tree_hasher, leaves);
+            return self.tree.leaves().len();
+        }
+
+        self.tree.update(&leaves)
+    }
+
+    /// Apply an insert event decoded from the program logs, see `MerkleTreeEvent::decode`.
+    /// Returns `false` if it skips past leaves the mirror hasn't seen, which then needs a
+    /// `sync`. Other events are ignored.
+    pub fn apply_event(&mut self, event: &MerkleTreeEvent) -> bool {
+        let MerkleTreeEvent::LeafInserted(leaf_inserted) = event else {
+            return true;
+        };
+
+        let leaves_count = self.tree.leaves().len() as u32;
+        match leaf_inserted.leaf_index {
+            leaf_index if leaf_index == leaves_count => self.tree.append(leaf_inserted.leaf),
+            leaf_index if leaf_index < leaves_count => self
+                .tree
+                .set_leaf(leaf_index, leaf_inserted.leaf)
+                .expect("Leaf index is in range"),
+            _ => return false,
+        }
+
+        true
+    }
+}
@@ -0,0 +1,26 @@
+//! PDAs of the program, see `merkle_tree_program::pda`. Builders and fetch helpers go through
+//! `tree_pdas`, which searches the bumps of a program's fixed PDAs once per process rather
+//! than on every call.
+
+pub use merkle_tree_program::pda::*;
+
+use solana_program::pubkey::Pubkey;
+use std::{
+    collections::HashMap,
+    sync::{OnceLock, RwLock},
+};
+
+/// Fixed PDAs of the tree of `program_id`, derived on first use and cached after.
+pub fn tree_pdas(program_id: &Pubkey) -> TreePdas {
+    static TREE_PDAS: OnceLock<RwLock<HashMap<Pubkey, TreePdas>>> = OnceLock::new();
+
+    let cache = TREE_PDAS.get_or_init(Default::default);
+    if let Some(tree_pdas) = cache.read().unwrap().get(program_id) {
+        return *tree_pdas;
+    }
+    *cache
+        .write()
+        .unwrap()
+        .entry(*program_id)
+        .or_insert_with(|| TreePdas::find(program_id))
+}
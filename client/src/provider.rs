@@ -0,0 +1,293 @@
+//! Sources of tree leaves, roots and proofs behind `MerkleProvider`, so application code
+//! switches between the chain (`RpcProvider`) and a local copy (`LocalStore`) without
+//! changing. Other data sources plug in by implementing the trait.
+
+use crate::{
+    SdkError,
+    account::{fetch_leaves, fetch_merkle_state},
+    pda::tree_pdas,
+    tree::MerkleTree,
+};
+use borsh::{BorshDeserialize, BorshSerialize};
+use merkle_tree_core::MerkleProof;
+use merkle_tree_errors::ProofError;
+use merkle_tree_program::hash::TreeHasher;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_program::pubkey::Pubkey;
+use std::{fs::File, io, path::Path, sync::Arc};
+
+/// Source of a tree's data. Trees that aren't created yet have no leaves and no root, and
+/// fail proofs with `ProofError::LeafIndexOutOfRange`.
+pub trait MerkleProvider: Sync {
+    /// Leaf hashes in insertion order.
+    fn leaves(&self) -> impl Future<Output = Result<Vec<[u8; 32]>, SdkError>> + Send;
+
+    fn root(&self) -> impl Future<Output = Result<Option<[u8; 32]>, SdkError>> + Send;
+
+    /// Self-describing proof for leaf at `leaf_index`, with the merkle state account as tree
+    /// id.
+    fn proof(&self, leaf_index: u32) -> impl Future<Output = Result<MerkleProof, SdkError>> + Send;
+
+    /// Proof of the first leaf equal to `leaf`, see `proof`.
+    fn proof_by_hash(
+        &self,
+        leaf: [u8; 32],
+    ) -> impl Future<Output = Result<MerkleProof, SdkError>> + Send {
+        async move {
+            let leaf_index = self
+                .leaves()
+                .await?
+                .iter()
+                .position(|stored_leaf| *stored_leaf == leaf)
+                .ok_or(ProofError::LeafNotFound)?;
+            self.proof(leaf_index as u32).await
+        }
+    }
+}
+
+/// Tree of `program_id` read from the chain on each call.
+#[derive(Clone)]
+pub struct RpcProvider {
+    client: Arc<RpcClient>,
+    program_id: Pubkey,
+}
+
+impl RpcProvider {
+    pub fn new(client: Arc<RpcClient>, program_id: &Pubkey) -> Self {
+        Self {
+            client,
+            program_id: *program_id,
+        }
+    }
+}
+
+impl MerkleProvider for RpcProvider {
+    async fn leaves(&self) -> Result<Vec<[u8; 32]>, SdkError> {
+        Ok(fetch_leaves(&self.client, &self.program_id)
+            .await?
+            .map(|(_, leaves)| leaves)
+            .unwrap_or_default())
+    }
+
+    async fn root(&self) -> Result<Option<[u8; 32]>, SdkError> {
+        Ok(fetch_merkle_state(&self.client, &self.program_id)
+            .await?
+            .map(|merkle_state| merkle_state.get_root_hash()))
+    }
+
+    async fn proof(&self, leaf_index: u32) -> Result<MerkleProof, SdkError> {
+        let tree = fetch_leaves(&self.client, &self.program_id)
+            .await?
+            .map(|(tree_hasher, leaves)| MerkleTree::new(tree_hasher, leaves));
+        Ok(merkle_proof(&self.program_id, tree.as_ref(), leaf_index)?)
+    }
+}
+
+/// Local copy of the tree of `program_id`, e.g. kept by an application from its own inserts
+/// or loaded from a file `save` wrote.
+#[derive(Debug, Clone)]
+pub struct LocalStore {
+    program_id: Pubkey,
+    tree: MerkleTree,
+}
+
+/// Borsh encoding of a `LocalStore` file.
+#[derive(BorshSerialize, BorshDeserialize)]
+struct LocalStoreFile {
+    program_id: Pubkey,
+    tree_hasher: TreeHasher,
+    leaves: Vec<[u8; 32]>,
+}
+
+impl LocalStore {
+    pub fn new(program_id: &Pubkey, tree: MerkleTree) -> Self {
+        Self {
+            program_id: *program_id,
+            tree,
+        }
+    }
+
+    /// Store read from `path`, written by `save`.
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        let file = LocalStoreFile::deserialize_reader(&mut io::BufReader::new(File::open(path)?))?;
+        Ok(Self::new(
+            &file.program_id,
+            MerkleTree::new(file.tree_hasher, file.leaves),
+        ))
+    }
+
+    /// Write the store to `path` as borsh.
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let file = LocalStoreFile {
+            program_id: self.program_id,
+            tree_hasher: self.tree.tree_hasher(),
+            leaves: self.tree.leaves().to_vec(),
+        };
+        borsh::to_writer(io::BufWriter::new(File::create(path)?), &file)
+    }
+
+    pub fn program_id(&self) -> &Pubkey {
+        &self.program_id
+    }
+
+    pub fn tree(&self) -> &MerkleTree {
+        &self.tree
+    }
+
+    pub fn tree_mut(&mut self) -> &mut MerkleTree {
+        &mut self.tree
+    }
+}
+
+impl MerkleProvider for LocalStore {
+    async fn leaves(&self) -> Result<Vec<[u8; 32]>, SdkError> {
+        Ok(self.tree.leaves().to_vec())
+    }
+
+    async fn root(&self) -> Result<Option<[u8; 32]>, SdkError> {
+        Ok(Some(self.tree.root()))
+    }
+
+    async fn proof(&self, leaf_index: u32) -> Result<MerkleProof, SdkError> {
+        Ok(merkle_proof(
+            &self.program_id,
+            Some(&self.tree),
+            leaf_index,
+        )?)
+    }
+}
+
+/// Proof for leaf at `leaf_index` of `tree` of `program_id`, `None` if it isn't created yet.
+fn merkle_proof(
+    program_id: &Pubkey,
+    tree: Option<&MerkleTree>,
+    leaf_index: u32,
+) -> Result<MerkleProof, ProofError> {
+    let (merkle_state_pda, _) = tree_pdas(program_id).merkle_state;
+    match tree {
+        Some(tree) => tree.merkle_proof(&merkle_state_pda, leaf_index),
+        None => Err(ProofError::LeafIndexOutOfRange {
+            leaf_index,
+            leaves_count: 0,
+        }),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::account::{LeafSource, leaf_source};
+    use futures::executor::block_on;
+    use merkle_tree_program::{state::MerkleStateAccount, utils::hash_value};
+
+    /// Merkle state of `leaves_count` leaves hashed from their index.
+    fn merkle_state(tree_hasher: TreeHasher, leaves_count: u32) -> MerkleStateAccount {
+        let leaf = |value: u32| tree_hasher.hash_value(&value.to_le_bytes());
+        let mut merkle_state = MerkleStateAccount::new(&leaf(0), &Pubkey::new_unique());
+        merkle_state.set_tree_hasher(tree_hasher);
+        (1..leaves_count).for_each(|value| merkle_state.add_leaf(&leaf(value)));
+        merkle_state
+    }
+
+    #[test]
+    fn local_store_round_trips() {
+        let program_id = Pubkey::new_unique();
+        let tree = MerkleTree::from_merkle_state(&merkle_state(TreeHasher::OPENZEPPELIN, 5))
+            .expect("Leaves are stored");
+        let store = LocalStore::new(&program_id, tree);
+
+        let path = std::env::temp_dir().join(format!("local-store-{program_id}.bin"));
+        store.save(&path).expect("Can't save store");
+        let loaded = LocalStore::load(&path).expect("Can't load store");
+        std::fs::remove_file(&path).expect("Can't remove store file");
+
+        assert_eq!(loaded.program_id(), &program_id);
+        assert_eq!(loaded.tree().tree_hasher(), TreeHasher::OPENZEPPELIN);
+        assert_eq!(loaded.tree().leaves(), store.tree().leaves());
+        assert_eq!(loaded.tree().root(), store.tree().root());
+    }
+
+    #[test]
+    fn local_store_serves_root_and_proofs() {
+        let program_id = Pubkey::new_unique();
+        let merkle_state = merkle_state(TreeHasher::default(), 5);
+        let mut store = LocalStore::new(
+            &program_id,
+            MerkleTree::from_merkle_state(&merkle_state).expect("Leaves are stored"),
+        );
+        let (merkle_state_pda, _) = tree_pdas(&program_id).merkle_state;
+
+        assert_eq!(
+            block_on(store.root()).unwrap(),
+            Some(merkle_state.get_root_hash())
+        );
+        assert_eq!(
+            block_on(store.leaves()).unwrap(),
+            merkle_state.leaf_hashes()
+        );
+        for (leaf_index, leaf) in merkle_state.leaf_hashes().iter().enumerate() {
+            let proof = block_on(store.proof(leaf_index as u32)).unwrap();
+            assert_eq!(proof.tree_id, merkle_state_pda.to_bytes());
+            assert_eq!(proof.leaf_index, leaf_index as u32);
+            assert_eq!(&proof.leaf, leaf);
+            assert_eq!(proof.root, merkle_state.get_root_hash());
+            assert_eq!(proof.path, merkle_state.get_proof(leaf_index as u32));
+            assert_eq!(block_on(store.proof_by_hash(*leaf)).unwrap(), proof);
+        }
+
+        assert!(matches!(
+            block_on(store.proof(5)),
+            Err(SdkError::Proof(ProofError::LeafIndexOutOfRange {
+                leaf_index: 5,
+                leaves_count: 5
+            }))
+        ));
+        assert!(matches!(
+            block_on(store.proof_by_hash(hash_value(b"missing"))),
+            Err(SdkError::Proof(ProofError::LeafNotFound))
+        ));
+
+        // Appended leaves are served right away
+        let leaf = hash_value(b"appended");
+        store.tree_mut().append(leaf);
+        assert_eq!(block_on(store.proof_by_hash(leaf)).unwrap().leaf_index, 5);
+    }
+
+    #[test]
+    fn rpc_and_local_providers_agree() {
+        let program_id = Pubkey::new_unique();
+        for tree_hasher in [TreeHasher::default(), TreeHasher::RFC6962] {
+            let merkle_state = merkle_state(tree_hasher, 7);
+
+            // `RpcProvider` decodes the fetched account and builds the tree of its stored leaves
+            let data = borsh::to_vec(&merkle_state).expect("Can't serialize merkle state");
+            let fetched = MerkleStateAccount::unpack(&data).expect("Can't unpack merkle state");
+            let fetched_tree_hasher = fetched.get_tree_hasher();
+            let Ok(LeafSource::Stored(leaves)) = leaf_source(&program_id, fetched) else {
+                panic!("Leaves must be stored in the merkle state");
+            };
+            let rpc_tree = MerkleTree::new(fetched_tree_hasher, leaves);
+
+            let store = LocalStore::new(
+                &program_id,
+                MerkleTree::from_merkle_state(&merkle_state).expect("Leaves are stored"),
+            );
+            assert_eq!(rpc_tree.root(), block_on(store.root()).unwrap().unwrap());
+            for leaf_index in 0..7 {
+                assert_eq!(
+                    merkle_proof(&program_id, Some(&rpc_tree), leaf_index).unwrap(),
+                    block_on(store.proof(leaf_index)).unwrap()
+                );
+            }
+        }
+
+        // Trees not created yet have no leaves to prove
+        assert_eq!(
+            merkle_proof(&program_id, None, 0),
+            Err(ProofError::LeafIndexOutOfRange {
+                leaf_index: 0,
+                leaves_count: 0
+            })
+        );
+    }
+}
@@ -0,0 +1,298 @@
+//! Send pipeline confirming transactions at a chosen commitment, retrying failed RPC requests
+//! with exponential backoff and re-signing with a fresh blockhash once the previous one
+//! expired, so flaky RPC nodes don't drop inserts.
+
+use crate::{
+    SdkError,
+    transaction::{client_error, transaction_error},
+};
+use solana_client::{
+    client_error::ClientError, nonblocking::rpc_client::RpcClient,
+    rpc_config::RpcSendTransactionConfig,
+};
+use solana_sdk::{
+    commitment_config::CommitmentConfig,
+    signature::{Keypair, Signature},
+    transaction::{Transaction, TransactionError},
+};
+use std::time::Duration;
+
+/// Options of `send_transaction`.
+#[derive(Debug, Clone)]
+pub struct SendConfig {
+    /// Commitment the transaction is confirmed at, also used for blockhashes and preflight.
+    pub commitment: CommitmentConfig,
+    /// Retries of failed RPC requests and expired blockhashes before giving up.
+    pub max_retries: u32,
+    /// Delay before the first retry, doubled on each next one up to `max_backoff`.
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    /// Delay between signature status polls.
+    pub poll_interval: Duration,
+}
+
+impl Default for SendConfig {
+    fn default() -> Self {
+        Self {
+            commitment: CommitmentConfig::confirmed(),
+            max_retries: 5,
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(8),
+            poll_interval: Duration::from_millis(500),
+        }
+    }
+}
+
+/// Sign `tx` with `signers` and send it until it's confirmed at `config.commitment`. The same
+/// signed transaction is resent until its blockhash expires and only then re-signed, so it
+/// can't land twice. Transactions failing on-chain or in preflight aren't retried.
+pub async fn send_transaction(
+    client: &RpcClient,
+    mut tx: Transaction,
+    signers: &[&Keypair],
+    config: &SendConfig,
+) -> Result<Signature, SdkError> {
+    let mut retries = 0;
+    let mut last_valid_block_height = None;
+    loop {
+        let Some(last_valid) = last_valid_block_height else {
+            match client
+                .get_latest_blockhash_with_commitment(config.commitment)
+                .await
+            {
+                Ok((blockhash, last_valid)) => {
+                    tx.try_sign(signers, blockhash)
+                        .map_err(|err| SdkError::Transaction(err.to_string()))?;
+                    last_valid_block_height = Some(last_valid);
+                }
+                Err(err) => backoff(&mut retries, config, err).await?,
+            }
+            continue;
+        };
+        let signature = tx.signatures[0];
+
+        let send_config = RpcSendTransactionConfig {
+            preflight_commitment: Some(config.commitment.commitment),
+            ..Default::default()
+        };
+        if let Err(err) = client.send_transaction_with_config(&tx, send_config).await {
+            match err.get_transaction_error() {
+                // Resent after landing, confirm it
+                Some(TransactionError::AlreadyProcessed) => {}
+                Some(_) => return Err(client_error(err)),
+                None => {
+                    backoff(&mut retries, config, err).await?;
+                    continue;
+                }
+            }
+        }
+
+        match confirm(client, &signature, last_valid, config).await {
+            Ok(Some(Ok(()))) => return Ok(signature),
+            Ok(Some(Err(err))) => return Err(transaction_error(err)),
+            Ok(None) => {
+                if retries >= config.max_retries {
+                    return Err(SdkError::Transaction(
+                        "blockhash expired before confirmation".into(),
+                    ));
+                }
+                retries += 1;
+                last_valid_block_height = None;
+            }
+            Err(err) => backoff(&mut retries, config, err).await?,
+        }
+    }
+}
+
+/// Poll the status of `signature` until it's confirmed at `config.commitment` or failed,
+/// `None` once the block height passed `last_valid_block_height` without it landing.
+async fn confirm(
+    client: &RpcClient,
+    signature: &Signature,
+    last_valid_block_height: u64,
+    config: &SendConfig,
+) -> Result<Option<Result<(), TransactionError>>, ClientError> {
+    loop {
+        let status = client
+            .get_signature_statuses(&[*signature])
+            .await?
+            .value
+            .pop()
+            .flatten();
+        if let Some(status) = status {
+            if let Some(err) = status.err {
+                return Ok(Some(Err(err)));
+            }
+            if status.satisfies_commitment(config.commitment) {
+                return Ok(Some(Ok(())));
+            }
+        } else if client
+            .get_block_height_with_commitment(config.commitment)
+            .await?
+            > last_valid_block_height
+        {
+            return Ok(None);
+        }
+
+        tokio::time::sleep(config.poll_interval).await;
+    }
+}
+
+/// Wait out the backoff of the next retry, or fail with `err` once retries ran out.
+async fn backoff(retries: &mut u32, config: &SendConfig, err: ClientError) -> Result<(), SdkError> {
+    if *retries >= config.max_retries {
+        return Err(SdkError::Rpc(err.to_string()));
+    }
+
+    let delay = backoff_delay(*retries, config);
+    *retries += 1;
+    tokio::time::sleep(delay).await;
+    Ok(())
+}
+
+/// Delay before retry number `retries`, counted from 0.
+fn backoff_delay(retries: u32, config: &SendConfig) -> Duration {
+    config
+        .initial_backoff
+        .saturating_mul(1 << retries.min(16))
+        .min(config.max_backoff)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use merkle_tree_errors::MerkleTreeError;
+    use serde_json::{Value, json};
+    use solana_client::{
+        rpc_request::RpcRequest,
+        rpc_response::{Response, RpcBlockhash, RpcResponseContext},
+    };
+    use solana_program::{
+        hash::Hash,
+        instruction::{Instruction, InstructionError},
+        pubkey::Pubkey,
+    };
+    use solana_sdk::signer::Signer;
+    use solana_transaction_status::{TransactionConfirmationStatus, TransactionStatus};
+    use std::{collections::HashMap, str::FromStr};
+
+    /// Blockhash the mock RPC client returns unless overridden.
+    const MOCK_BLOCKHASH: &str = "7RoSF9fUmdphVCpabEoefH81WwrW7orsWonXWqTXkKV8";
+
+    /// Mock RPC client answering `mocks` once each, then with successful defaults.
+    fn mock_client(mocks: impl IntoIterator<Item = (RpcRequest, Value)>) -> RpcClient {
+        RpcClient::new_mock_with_mocks("succeeds".into(), HashMap::from_iter(mocks))
+    }
+
+    fn response(value: Value) -> Value {
+        json!(Response {
+            context: RpcResponseContext {
+                slot: 1,
+                api_version: None,
+            },
+            value,
+        })
+    }
+
+    fn config(max_retries: u32) -> SendConfig {
+        SendConfig {
+            max_retries,
+            initial_backoff: Duration::ZERO,
+            ..Default::default()
+        }
+    }
+
+    /// Unsigned transaction and its signature once signed with `blockhash`.
+    fn transaction(payer: &Keypair, blockhash: &str) -> (Transaction, Signature) {
+        let instruction = Instruction::new_with_bytes(Pubkey::new_unique(), &[1], vec![]);
+        let tx = Transaction::new_with_payer(&[instruction], Some(&payer.pubkey()));
+        let mut signed = tx.clone();
+        signed.sign(&[payer], Hash::from_str(blockhash).unwrap());
+        (tx, signed.signatures[0])
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_max() {
+        let config = SendConfig {
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(3),
+            ..Default::default()
+        };
+        let delays: Vec<_> = (0..5)
+            .map(|retries| backoff_delay(retries, &config))
+            .collect();
+        assert_eq!(
+            delays,
+            [500, 1_000, 2_000, 3_000, 3_000].map(Duration::from_millis)
+        );
+        assert_eq!(backoff_delay(u32::MAX, &config), config.max_backoff);
+    }
+
+    #[tokio::test]
+    async fn confirms_sent_transaction() {
+        let payer = Keypair::new();
+        let (tx, signature) = transaction(&payer, MOCK_BLOCKHASH);
+
+        let result = send_transaction(&mock_client([]), tx, &[&payer], &config(0)).await;
+        assert_eq!(result, Ok(signature));
+    }
+
+    #[tokio::test]
+    async fn rpc_failures_are_retried() {
+        let payer = Keypair::new();
+        let (tx, signature) = transaction(&payer, MOCK_BLOCKHASH);
+        let failing_blockhash = || mock_client([(RpcRequest::GetLatestBlockhash, Value::Null)]);
+
+        let result =
+            send_transaction(&failing_blockhash(), tx.clone(), &[&payer], &config(1)).await;
+        assert_eq!(result, Ok(signature));
+        let result = send_transaction(&failing_blockhash(), tx, &[&payer], &config(0)).await;
+        assert!(matches!(result, Err(SdkError::Rpc(_))));
+    }
+
+    #[tokio::test]
+    async fn failed_transaction_is_not_retried() {
+        let payer = Keypair::new();
+        let (tx, _) = transaction(&payer, MOCK_BLOCKHASH);
+        let err = TransactionError::InstructionError(
+            0,
+            InstructionError::Custom(MerkleTreeError::TreePaused.code()),
+        );
+        let status = TransactionStatus {
+            slot: 1,
+            confirmations: None,
+            status: Err(err.clone()),
+            err: Some(err),
+            confirmation_status: Some(TransactionConfirmationStatus::Processed),
+        };
+        let client = mock_client([(RpcRequest::GetSignatureStatuses, response(json!([status])))]);
+
+        let result = send_transaction(&client, tx, &[&payer], &config(5)).await;
+        assert_eq!(result, Err(SdkError::Program(MerkleTreeError::TreePaused)));
+    }
+
+    #[tokio::test]
+    async fn expired_blockhash_is_refreshed() {
+        let payer = Keypair::new();
+        let (tx, signature) = transaction(&payer, MOCK_BLOCKHASH);
+        // Expires below the mock block height and never lands
+        let expiring = || {
+            mock_client([
+                (
+                    RpcRequest::GetLatestBlockhash,
+                    response(json!(RpcBlockhash {
+                        blockhash: Hash::new_unique().to_string(),
+                        last_valid_block_height: 1,
+                    })),
+                ),
+                (RpcRequest::GetSignatureStatuses, response(json!([null]))),
+            ])
+        };
+
+        // Re-signed with the fresh blockhash once the first one expired
+        let result = send_transaction(&expiring(), tx.clone(), &[&payer], &config(1)).await;
+        assert_eq!(result, Ok(signature));
+        let result = send_transaction(&expiring(), tx, &[&payer], &config(0)).await;
+        assert!(matches!(result, Err(SdkError::Transaction(_))));
+    }
+}
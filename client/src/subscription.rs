@@ -0,0 +1,96 @@
+//! Root change notifications of a tree over the RPC websocket, so downstream systems react to
+//! inserts as they land instead of polling.
+
+use crate::{SdkError, pda::tree_pdas};
+use futures::{
+    Stream, StreamExt,
+    future::{self, BoxFuture},
+};
+use merkle_tree_program::state::MerkleStateAccount;
+use solana_account_decoder::UiAccountEncoding;
+use solana_client::{nonblocking::pubsub_client::PubsubClient, rpc_config::RpcAccountInfoConfig};
+use solana_program::pubkey::Pubkey;
+use solana_sdk::commitment_config::CommitmentConfig;
+
+/// Ends a subscription, its stream then ends too.
+pub type Unsubscribe = Box<dyn FnOnce() -> BoxFuture<'static, ()> + Send>;
+
+/// Root and leaves count of a tree after an update, at `slot`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RootUpdate {
+    pub slot: u64,
+    pub root: [u8; 32],
+    pub leaves_count: u32,
+    /// Leaves appended since the previous update. Empty on the first update and for trees
+    /// not storing leaves in the merkle state, i.e. in frontier mode or with leaf pages.
+    pub new_leaves: Vec<[u8; 32]>,
+}
+
+/// Subscribe to the merkle state account of `program_id` at `commitment`, yielding an update
+/// whenever its root or leaves count changes. Writes changing neither, e.g. config changes,
+/// are skipped. Lazy root trees report new leaves with an unchanged root until `CommitRoot`.
+pub async fn subscribe_roots<'a>(
+    pubsub_client: &'a PubsubClient,
+    program_id: &Pubkey,
+    commitment: CommitmentConfig,
+) -> Result<
+    (
+        impl Stream<Item = Result<RootUpdate, SdkError>> + 'a,
+        Unsubscribe,
+    ),
+    SdkError,
+> {
+    let (merkle_state_pda, _) = tree_pdas(program_id).merkle_state;
+    let (notifications, unsubscribe) = pubsub_client
+        .account_subscribe(
+            &merkle_state_pda,
+            Some(RpcAccountInfoConfig {
+                encoding: Some(UiAccountEncoding::Base64),
+                commitment: Some(commitment),
+                ..RpcAccountInfoConfig::default()
+            }),
+        )
+        .await
+        .map_err(|err| SdkError::Rpc(err.to_string()))?;
+
+    let mut last_update = None;
+    let updates = notifications.filter_map(move |notification| {
+        let update = notification
+            .value
+            .data
+            .decode()
+            .and_then(|data| MerkleStateAccount::unpack(&data).ok())
+            .map(|merkle_state| {
+                let leaves_count = merkle_state.get_leaves_count() as u32;
+                let new_leaves = last_update
+                    .and_then(|(_, last_leaves_count): ([u8; 32], u32)| {
+                        merkle_state
+                            .leaf_hashes()
+                            .get(last_leaves_count as usize..leaves_count as usize)
+                    })
+                    .unwrap_or_default()
+                    .to_vec();
+                RootUpdate {
+                    slot: notification.context.slot,
+                    root: *merkle_state.root(),
+                    leaves_count,
+                    new_leaves,
+                }
+            })
+            .ok_or(SdkError::InvalidAccount(merkle_state_pda));
+
+        let changed = match (&update, last_update) {
+            (Ok(update), Some((root, leaves_count))) => {
+                (update.root, update.leaves_count) != (root, leaves_count)
+            }
+            _ => true,
+        };
+        if let Ok(update) = &update {
+            last_update = Some((update.root, update.leaves_count));
+        }
+
+        future::ready(changed.then_some(update))
+    });
+
+    Ok((updates, unsubscribe))
+}
@@ -0,0 +1,252 @@
+//! Transactions with their compute budget set from a simulation, so inserts into large trees
+//! and verifies of long proofs don't fail on the default per-instruction compute unit limit.
+
+use crate::SdkError;
+use merkle_tree_errors::MerkleTreeError;
+use solana_client::{
+    client_error::ClientError, nonblocking::rpc_client::RpcClient,
+    rpc_config::RpcSimulateTransactionConfig, rpc_response::RpcSimulateTransactionResult,
+};
+use solana_program::{instruction::Instruction, pubkey::Pubkey};
+use solana_sdk::{
+    compute_budget::ComputeBudgetInstruction,
+    transaction::{Transaction, TransactionError},
+};
+
+/// Highest compute unit limit a transaction can request.
+pub const MAX_COMPUTE_UNIT_LIMIT: u32 = 1_400_000;
+
+/// Default headroom added to the simulated compute units, in percent.
+pub const DEFAULT_COMPUTE_UNIT_MARGIN: u32 = 10;
+
+/// Builds `instructions` paid by `payer` into a transaction prefixed with
+/// `set_compute_unit_limit` of their simulated compute units plus a margin, and
+/// `set_compute_unit_price` when a price is set.
+#[derive(Debug, Clone)]
+pub struct TransactionBuilder {
+    payer: Pubkey,
+    instructions: Vec<Instruction>,
+    compute_unit_price: Option<u64>,
+    compute_unit_margin: u32,
+}
+
+impl TransactionBuilder {
+    pub fn new(payer: &Pubkey) -> Self {
+        Self {
+            payer: *payer,
+            instructions: Vec::new(),
+            compute_unit_price: None,
+            compute_unit_margin: DEFAULT_COMPUTE_UNIT_MARGIN,
+        }
+    }
+
+    pub fn instruction(mut self, instruction: Instruction) -> Self {
+        self.instructions.push(instruction);
+        self
+    }
+
+    pub fn instructions(mut self, instructions: impl IntoIterator<Item = Instruction>) -> Self {
+        self.instructions.extend(instructions);
+        self
+    }
+
+    /// Priority fee in micro-lamports per compute unit.
+    pub fn compute_unit_price(mut self, micro_lamports: u64) -> Self {
+        self.compute_unit_price = Some(micro_lamports);
+        self
+    }
+
+    /// Headroom added to the simulated compute units in percent, for state changing between
+    /// the simulation and the transaction landing.
+    pub fn compute_unit_margin(mut self, percent: u32) -> Self {
+        self.compute_unit_margin = percent;
+        self
+    }
+
+    /// Compute units the instructions consume, simulated under the highest limit.
+    pub async fn simulate(&self, client: &RpcClient) -> Result<u64, SdkError> {
+        let tx = self.transaction(MAX_COMPUTE_UNIT_LIMIT);
+        let simulation = client
+            .simulate_transaction_with_config(&tx, simulation_config())
+            .await
+            .map_err(|err| SdkError::Rpc(err.to_string()))?
+            .value;
+        simulated_units(simulation)
+    }
+
+    /// Unsigned transaction with the simulated compute budget, see `simulate`.
+    pub async fn build(&self, client: &RpcClient) -> Result<Transaction, SdkError> {
+        let units = self.simulate(client).await?;
+        Ok(self.transaction(self.compute_unit_limit(units)))
+    }
+
+    /// `units` plus the margin, capped to `MAX_COMPUTE_UNIT_LIMIT`.
+    pub fn compute_unit_limit(&self, units: u64) -> u32 {
+        let limit = units.saturating_mul(100 + self.compute_unit_margin as u64) / 100;
+        limit.min(MAX_COMPUTE_UNIT_LIMIT as u64) as u32
+    }
+
+    /// Unsigned transaction requesting `compute_unit_limit`.
+    pub fn transaction(&self, compute_unit_limit: u32) -> Transaction {
+        let mut instructions = vec![ComputeBudgetInstruction::set_compute_unit_limit(
+            compute_unit_limit,
+        )];
+        instructions.extend(
+            self.compute_unit_price
+                .map(ComputeBudgetInstruction::set_compute_unit_price),
+        );
+        instructions.extend(self.instructions.iter().cloned());
+        Transaction::new_with_payer(&instructions, Some(&self.payer))
+    }
+}
+
+/// Simulate unsigned transactions against the latest blockhash.
+pub(crate) fn simulation_config() -> RpcSimulateTransactionConfig {
+    RpcSimulateTransactionConfig {
+        sig_verify: false,
+        replace_recent_blockhash: true,
+        ..Default::default()
+    }
+}
+
+/// Consumed compute units of a successful `simulation`.
+pub(crate) fn simulated_units(simulation: RpcSimulateTransactionResult) -> Result<u64, SdkError> {
+    if let Some(err) = simulation.err {
+        return Err(transaction_error(err));
+    }
+
+    simulation
+        .units_consumed
+        .ok_or_else(|| SdkError::Rpc("simulation didn't report consumed compute units".into()))
+}
+
+/// Program errors as `SdkError::Program`, other failures as `SdkError::Transaction`.
+pub(crate) fn transaction_error(err: TransactionError) -> SdkError {
+    match &err {
+        TransactionError::InstructionError(_, instruction_error) => {
+            match MerkleTreeError::from_instruction_error(instruction_error) {
+                Some(error) => SdkError::Program(error),
+                None => SdkError::Transaction(err.to_string()),
+            }
+        }
+        _ => SdkError::Transaction(err.to_string()),
+    }
+}
+
+/// Failed transactions as `transaction_error`, other failures as `SdkError::Rpc`.
+pub(crate) fn client_error(err: ClientError) -> SdkError {
+    match err.get_transaction_error() {
+        Some(err) => transaction_error(err),
+        None => SdkError::Rpc(err.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use solana_program::instruction::InstructionError;
+    use solana_sdk::compute_budget;
+
+    fn simulation(
+        err: Option<TransactionError>,
+        units: Option<u64>,
+    ) -> RpcSimulateTransactionResult {
+        RpcSimulateTransactionResult {
+            err,
+            logs: None,
+            accounts: None,
+            units_consumed: units,
+            return_data: None,
+            inner_instructions: None,
+        }
+    }
+
+    #[test]
+    fn compute_unit_limit_adds_margin_up_to_max() {
+        let builder = TransactionBuilder::new(&Pubkey::new_unique());
+        assert_eq!(builder.compute_unit_limit(10_000), 11_000);
+        assert_eq!(
+            builder
+                .clone()
+                .compute_unit_margin(0)
+                .compute_unit_limit(10_000),
+            10_000
+        );
+        assert_eq!(
+            builder.compute_unit_limit(MAX_COMPUTE_UNIT_LIMIT as u64),
+            MAX_COMPUTE_UNIT_LIMIT
+        );
+        assert_eq!(builder.compute_unit_limit(u64::MAX), MAX_COMPUTE_UNIT_LIMIT);
+    }
+
+    #[test]
+    fn transaction_prefixes_compute_budget() {
+        let payer = Pubkey::new_unique();
+        let instruction = Instruction::new_with_bytes(Pubkey::new_unique(), &[1], vec![]);
+        let builder = TransactionBuilder::new(&payer).instruction(instruction.clone());
+
+        let tx = builder.transaction(200_000);
+        assert_eq!(tx.message.account_keys[0], payer);
+        assert_eq!(tx.message.instructions.len(), 2);
+        assert_eq!(
+            tx.message.instructions[0].data,
+            ComputeBudgetInstruction::set_compute_unit_limit(200_000).data
+        );
+
+        let tx = builder.compute_unit_price(5).transaction(200_000);
+        let program_ids: Vec<_> = tx
+            .message
+            .instructions
+            .iter()
+            .map(|ix| tx.message.account_keys[ix.program_id_index as usize])
+            .collect();
+        assert_eq!(
+            program_ids,
+            [
+                compute_budget::id(),
+                compute_budget::id(),
+                instruction.program_id
+            ]
+        );
+        assert_eq!(
+            tx.message.instructions[1].data,
+            ComputeBudgetInstruction::set_compute_unit_price(5).data
+        );
+    }
+
+    #[test]
+    fn transaction_errors_are_classified() {
+        let program_error = MerkleTreeError::TreePaused;
+        let err =
+            TransactionError::InstructionError(1, InstructionError::Custom(program_error.code()));
+        assert!(matches!(
+            transaction_error(err),
+            SdkError::Program(MerkleTreeError::TreePaused)
+        ));
+
+        let err = TransactionError::InstructionError(0, InstructionError::InvalidAccountData);
+        assert!(matches!(transaction_error(err), SdkError::Transaction(_)));
+        let err = TransactionError::InstructionError(0, InstructionError::Custom(u32::MAX));
+        assert!(matches!(transaction_error(err), SdkError::Transaction(_)));
+        assert!(matches!(
+            transaction_error(TransactionError::InsufficientFundsForFee),
+            SdkError::Transaction(_)
+        ));
+    }
+
+    #[test]
+    fn simulated_units_requires_success() {
+        assert_eq!(
+            simulated_units(simulation(None, Some(1_234))).unwrap(),
+            1_234
+        );
+        assert!(matches!(
+            simulated_units(simulation(None, None)),
+            Err(SdkError::Rpc(_))
+        ));
+        assert!(matches!(
+            simulated_units(simulation(Some(TransactionError::AccountNotFound), Some(1))),
+            Err(SdkError::Transaction(_))
+        ));
+    }
+}
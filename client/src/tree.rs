@@ -0,0 +1,258 @@
+//! Off-chain copy of a tree built from its fetched leaves, producing the proofs the program
+//! verifies without another RPC request per proof, and verifying them as the program does.
+
+use merkle_tree_core::{
+    MerkleProof,
+    verify::{NodeHasher, compute_audit_root, compute_root_at},
+};
+use merkle_tree_errors::ProofError;
+use merkle_tree_program::{hash::TreeHasher, state::MerkleStateAccount};
+use solana_program::pubkey::Pubkey;
+
+/// Leaves of a tree, the hashing scheme they're hashed with and every inner node above them,
+/// so proofs are read off instead of rehashing the tree and leaf changes only rehash their
+/// paths.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MerkleTree {
+    tree_hasher: TreeHasher,
+    /// Nodes of each level from the leaves up to the root.
+    layers: Vec<Vec<[u8; 32]>>,
+}
+
+impl MerkleTree {
+    /// Tree of `leaves` hashed with `tree_hasher`.
+    pub fn new(tree_hasher: TreeHasher, leaves: Vec<[u8; 32]>) -> Self {
+        let dirty = (0..leaves.len()).collect();
+        let mut tree = Self {
+            tree_hasher,
+            layers: vec![leaves],
+        };
+        tree.rehash(dirty);
+
+        tree
+    }
+
+    /// Tree of the leaves stored in `merkle_state`. Frontier mode trees don't store them, see
+    /// `account::fetch_merkle_tree` for trees keeping them in leaf pages.
+    pub fn from_merkle_state(merkle_state: &MerkleStateAccount) -> Result<Self, ProofError> {
+        if merkle_state.is_frontier_mode() {
+            return Err(ProofError::LeavesNotStored);
+        }
+
+        Ok(Self::new(
+            merkle_state.get_tree_hasher(),
+            merkle_state.get_leaf_hashes(),
+        ))
+    }
+
+    /// Root of all leaves, including leaves a lazy root tree hasn't committed yet. The zero
+    /// hash for a tree without leaves.
+    pub fn root(&self) -> [u8; 32] {
+        match self.layers.last() {
+            Some(layer) if layer.len() == 1 => layer[0],
+            _ => [0u8; 32],
+        }
+    }
+
+    pub fn leaves(&self) -> &[[u8; 32]] {
+        &self.layers[0]
+    }
+
+    pub fn tree_hasher(&self) -> TreeHasher {
+        self.tree_hasher
+    }
+
+    /// Append `leaf`, rehashing only its path.
+    pub fn append(&mut self, leaf: [u8; 32]) {
+        self.layers[0].push(leaf);
+        self.rehash(vec![self.layers[0].len() - 1]);
+    }
+
+    /// Replace leaf at `leaf_index` with `leaf`, rehashing only its path.
+    pub fn set_leaf(&mut self, leaf_index: u32, leaf: [u8; 32]) -> Result<(), ProofError> {
+        self.check_leaf_index(leaf_index)?;
+        self.layers[0][leaf_index as usize] = leaf;
+        self.rehash(vec![leaf_index as usize]);
+
+        Ok(())
+    }
+
+    /// Bring the tree up to date with `leaves`, rehashing only the paths of changed and
+    /// appended leaves, and return how many changed. A shorter `leaves` rebuilds the tree.
+    pub fn update(&mut self, leaves: &[[u8; 32]]) -> usize {
+        if leaves.len() < self.leaves().len() {
+            *self = Self::new(self.tree_hasher, leaves.to_vec());
+            return leaves.len();
+        }
+
+        let dirty: Vec<usize> = (0..leaves.len())
+            .filter(|leaf_index| self.leaves().get(*leaf_index) != Some(&leaves[*leaf_index]))
+            .collect();
+        let changed = dirty.len();
+        if changed > 0 {
+            self.layers[0].clear();
+            self.layers[0].extend_from_slice(leaves);
+            self.rehash(dirty);
+        }
+
+        changed
+    }
+
+    /// Index of the first leaf equal to `leaf`.
+    pub fn leaf_index(&self, leaf: &[u8; 32]) -> Option<u32> {
+        self.leaves()
+            .iter()
+            .position(|tree_leaf| tree_leaf == leaf)
+            .map(|leaf_index| leaf_index as u32)
+    }
+
+    /// Proof for leaf at `leaf_index` as `MerkleStateAccount::get_proof` builds it: the full
+    /// proof, or the audit path of left-balanced trees.
+    pub fn proof(&self, leaf_index: u32) -> Result<Vec<[u8; 32]>, ProofError> {
+        self.check_leaf_index(leaf_index)?;
+
+        let height = self.layers.len() - 1;
+        let mut proof = Vec::with_capacity(height);
+        let mut index = leaf_index as usize;
+        for (level, layer) in self.layers[..height].iter().enumerate() {
+            match layer.get(index ^ 1) {
+                Some(sibling) => proof.push(*sibling),
+                None if !self.tree_hasher.left_balanced => {
+                    proof.push(self.tree_hasher.unpaired_sibling(level, &layer[index]))
+                }
+                None => {}
+            }
+            index /= 2;
+        }
+
+        Ok(proof)
+    }
+
+    /// Self-describing proof for leaf at `leaf_index` of tree `tree_id`, see `proof`.
+    pub fn merkle_proof(
+        &self,
+        tree_id: &Pubkey,
+        leaf_index: u32,
+    ) -> Result<MerkleProof, ProofError> {
+        let path = self.proof(leaf_index)?;
+        Ok(MerkleProof {
+            tree_id: tree_id.to_bytes(),
+            leaf_index,
+            leaf: self.leaves()[leaf_index as usize],
+            root: self.root(),
+            path,
+        })
+    }
+
+    /// Index and proof of the first leaf equal to `leaf`, see `proof`.
+    pub fn proof_by_hash(&self, leaf: &[u8; 32]) -> Result<(u32, Vec<[u8; 32]>), ProofError> {
+        let leaf_index = self.leaf_index(leaf).ok_or(ProofError::LeafNotFound)?;
+        Ok((leaf_index, self.proof(leaf_index)?))
+    }
+
+    /// Verify `proof` for `leaf` at `leaf_index` against the root of this tree, see
+    /// `verify_proof`.
+    pub fn verify(&self, leaf_index: u32, leaf: &[u8; 32], proof: &[[u8; 32]]) -> bool {
+        verify_proof(
+            &self.tree_hasher,
+            &self.root(),
+            self.leaves().len() as u32,
+            leaf_index,
+            leaf,
+            proof,
+        )
+    }
+
+    /// Deduplicated multiproof for `leaf_indices`(sorted, strictly increasing), see
+    /// `MerkleStateAccount::get_multiproof`.
+    pub fn multiproof(&self, leaf_indices: &[u32]) -> Result<Vec<[u8; 32]>, ProofError> {
+        for leaf_index in leaf_indices {
+            self.check_leaf_index(*leaf_index)?;
+        }
+
+        let mut proof = Vec::new();
+        let mut indices: Vec<usize> = leaf_indices.iter().map(|index| *index as usize).collect();
+        for layer in &self.layers[..self.layers.len() - 1] {
+            let mut i = 0;
+            while i < indices.len() {
+                let sibling_index = indices[i] ^ 1;
+                if indices.get(i + 1) == Some(&sibling_index) {
+                    i += 1;
+                } else if let Some(sibling) = layer.get(sibling_index) {
+                    proof.push(*sibling);
+                }
+                i += 1;
+            }
+
+            indices = indices.iter().map(|index| index / 2).collect();
+            indices.dedup();
+        }
+
+        Ok(proof)
+    }
+
+    /// Rehash the parents of `dirty` leaf indices(sorted, deduplicated) level by level up to
+    /// the root, adding or dropping levels as the leaves count changed the height.
+    fn rehash(&mut self, mut dirty: Vec<usize>) {
+        let tree_hasher = self.tree_hasher;
+        let mut level = 0;
+        while self.layers[level].len() > 1 {
+            if self.layers.len() == level + 1 {
+                self.layers.push(Vec::new());
+            }
+            let (lower, upper) = self.layers.split_at_mut(level + 1);
+            let (layer, parents) = (&lower[level], &mut upper[0]);
+            parents.resize(layer.len().div_ceil(2), [0u8; 32]);
+
+            dirty = dirty.iter().map(|index| index / 2).collect();
+            dirty.dedup();
+            for parent in &dirty {
+                let left = &layer[2 * parent];
+                parents[*parent] = match layer.get(2 * parent + 1) {
+                    Some(right) => tree_hasher.hash_nodes(left, right),
+                    None => tree_hasher.hash_unpaired(level, left),
+                };
+            }
+            level += 1;
+        }
+        self.layers.truncate(level + 1);
+    }
+
+    fn check_leaf_index(&self, leaf_index: u32) -> Result<(), ProofError> {
+        if leaf_index as usize >= self.leaves().len() {
+            return Err(ProofError::LeafIndexOutOfRange {
+                leaf_index,
+                leaves_count: self.leaves().len() as u32,
+            });
+        }
+
+        Ok(())
+    }
+}
+
+/// Verify `proof` for `leaf` at `leaf_index` of a tree of `leaves_count` leaves against `root`,
+/// as `MerkleStateAccount::verify_leaf` checks a full proof: nodes hashed per `tree_hasher`,
+/// ordered by `leaf_index` only in ordered trees, and audit paths in left-balanced trees.
+pub fn verify_proof(
+    tree_hasher: &TreeHasher,
+    root: &[u8; 32],
+    leaves_count: u32,
+    leaf_index: u32,
+    leaf: &[u8; 32],
+    proof: &[[u8; 32]],
+) -> bool {
+    if leaf_index >= leaves_count {
+        return false;
+    }
+    if tree_hasher.left_balanced {
+        return compute_audit_root(
+            tree_hasher,
+            leaves_count as usize,
+            leaf_index as usize,
+            leaf,
+            proof,
+        ) == Some(*root);
+    }
+
+    &compute_root_at(tree_hasher, leaf_index as usize, leaf, proof) == root
+}
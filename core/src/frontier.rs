@@ -0,0 +1,304 @@
+//! Rightmost frontier of an append-only tree with a changelog of recent changes, what frontier
+//! mode trees keep on-chain instead of their leaves.
+
+use crate::verify::{NodeHasher, compute_root_at, has_sibling, tree_height};
+use alloc::vec::Vec;
+use borsh::{BorshDeserialize, BorshSerialize};
+use core::cmp::Ordering;
+
+/// Leaf change recorded in frontier mode, lets proofs built against an older root be
+/// fast-forwarded to the current one, like the spl-account-compression changelog.
+#[derive(Debug, Clone, PartialEq, Eq, BorshSerialize, BorshDeserialize)]
+pub struct ChangeLogEntry {
+    /// Root after the change.
+    pub root: [u8; 32],
+    pub leaf_index: u32,
+    /// Leaves count after the change.
+    pub leaves_count: u32,
+    /// Nodes on the changed leaf path after the change, from the leaf up to below the root.
+    pub path: Vec<[u8; 32]>,
+}
+
+/// Rightmost frontier of the tree, enough to append leaves and compute the root in O(depth).
+#[derive(Debug, Clone, PartialEq, Eq, BorshSerialize, BorshDeserialize)]
+pub struct Frontier {
+    pub leaves_count: u32,
+    /// Node `i` is the last complete subtree root at level `i`, valid if bit `i` of
+    /// `leaves_count` is set.
+    pub nodes: Vec<[u8; 32]>,
+    /// Most recent changes, oldest first, at most `Self::CHANGELOG_LEN`.
+    pub changelog: Vec<ChangeLogEntry>,
+}
+
+impl Frontier {
+    /// Max number of changes a proof can be fast-forwarded over, the on-chain root history
+    /// length.
+    pub const CHANGELOG_LEN: usize = 8;
+
+    /// Frontier of `leaves` with an empty changelog, proofs must target its root.
+    pub fn from_leaves<H: NodeHasher>(hasher: &H, leaves: &[[u8; 32]]) -> Self {
+        let mut frontier = Self {
+            leaves_count: 0,
+            nodes: Vec::new(),
+            changelog: Vec::new(),
+        };
+        leaves.iter().for_each(|leaf| {
+            frontier.append(hasher, leaf);
+        });
+
+        frontier
+    }
+
+    /// Append `leaf` and record the change, returning the new root.
+    pub fn push<H: NodeHasher>(&mut self, hasher: &H, leaf: &[u8; 32]) -> [u8; 32] {
+        let mut path = self.append(hasher, leaf);
+        let root = self.extend_right_path(hasher, path.len(), &mut path);
+        self.record(ChangeLogEntry {
+            root,
+            leaf_index: self.leaves_count - 1,
+            leaves_count: self.leaves_count,
+            path,
+        });
+
+        root
+    }
+
+    /// Replace `old_leaf` at `leaf_index` with `new_leaf` and record the change, returning
+    /// the new root. `proof` may target `root` or any root in the changelog, `None` if it
+    /// doesn't or the leaf changed since.
+    pub fn replace_leaf<H: NodeHasher>(
+        &mut self,
+        hasher: &H,
+        root: &[u8; 32],
+        leaf_index: u32,
+        old_leaf: &[u8; 32],
+        new_leaf: &[u8; 32],
+        proof: &[[u8; 32]],
+    ) -> Option<[u8; 32]> {
+        if leaf_index >= self.leaves_count {
+            return None;
+        }
+
+        let proof_root = compute_root_at(hasher, leaf_index as usize, old_leaf, proof);
+        let proof = self.fast_forward_proof(&proof_root, leaf_index, proof)?;
+        if &compute_root_at(hasher, leaf_index as usize, old_leaf, &proof) != root {
+            return None;
+        }
+
+        // Rehash the path, nodes without a sibling are paired with themselves
+        let mut path = Vec::with_capacity(proof.len());
+        let mut node = *new_leaf;
+        for (level, sibling) in proof.iter().enumerate() {
+            path.push(node);
+            node = match has_sibling(
+                leaf_index as usize >> level,
+                self.leaves_count as usize,
+                level,
+            ) {
+                true if (leaf_index >> level) & 1 == 1 => hasher.hash_nodes(sibling, &node),
+                true => hasher.hash_nodes(&node, sibling),
+                false => hasher.hash_unpaired(level, &node),
+            };
+        }
+        let new_root = node;
+
+        // Update frontier nodes whose subtree holds the leaf
+        for level in 0..self.nodes.len() {
+            if (self.leaves_count >> level) & 1 == 1
+                && leaf_index >> level == (self.leaves_count >> level) - 1
+            {
+                self.nodes[level] = path.get(level).copied().unwrap_or(new_root);
+            }
+        }
+
+        self.record(ChangeLogEntry {
+            root: new_root,
+            leaf_index,
+            leaves_count: self.leaves_count,
+            path,
+        });
+
+        Some(new_root)
+    }
+
+    /// Update `proof` of `leaf_index` built against `proof_root` with the changelog entries
+    /// recorded after it. Unknown roots are assumed current, `None` if the leaf changed since.
+    pub fn fast_forward_proof(
+        &self,
+        proof_root: &[u8; 32],
+        leaf_index: u32,
+        proof: &[[u8; 32]],
+    ) -> Option<Vec<[u8; 32]>> {
+        let mut proof = proof.to_vec();
+        let Some(position) = self
+            .changelog
+            .iter()
+            .rposition(|entry| &entry.root == proof_root)
+        else {
+            return Some(proof);
+        };
+
+        for entry in &self.changelog[position + 1..] {
+            if entry.leaf_index == leaf_index {
+                return None;
+            }
+
+            // Sibling at the level the paths meet is on the changed path, a new top level
+            // is added when the change grew the tree
+            let critical_level =
+                (u32::BITS - 1 - (leaf_index ^ entry.leaf_index).leading_zeros()) as usize;
+            match critical_level.cmp(&proof.len()) {
+                Ordering::Less => proof[critical_level] = entry.path[critical_level],
+                Ordering::Equal => proof.push(entry.path[critical_level]),
+                Ordering::Greater => return None,
+            }
+
+            // Above it the paths are shared, self-paired nodes changed with them
+            for (level, sibling) in proof.iter_mut().enumerate().skip(critical_level + 1) {
+                if !has_sibling(
+                    leaf_index as usize >> level,
+                    entry.leaves_count as usize,
+                    level,
+                ) {
+                    *sibling = entry.path[level];
+                }
+            }
+        }
+
+        Some(proof)
+    }
+
+    /// Root of the tree, rightmost incomplete nodes go through `NodeHasher::hash_unpaired`.
+    pub fn root<H: NodeHasher>(&self, hasher: &H) -> [u8; 32] {
+        self.extend_right_path(
+            hasher,
+            self.leaves_count.trailing_zeros() as usize,
+            &mut Vec::new(),
+        )
+    }
+
+    /// Append `leaf`, merging complete subtrees on the right edge. Returns its path up to
+    /// the level of the frontier node it ended in.
+    pub(crate) fn append<H: NodeHasher>(&mut self, hasher: &H, leaf: &[u8; 32]) -> Vec<[u8; 32]> {
+        let mut path = Vec::new();
+        let mut node = *leaf;
+        let mut level = 0;
+        while (self.leaves_count >> level) & 1 == 1 {
+            path.push(node);
+            node = hasher.hash_nodes(&self.nodes[level], &node);
+            level += 1;
+        }
+
+        match self.nodes.get_mut(level) {
+            Some(frontier_node) => *frontier_node = node,
+            None => self.nodes.push(node),
+        }
+        self.leaves_count += 1;
+
+        path
+    }
+
+    /// Extend `path` with the rightmost node of each level from `level`, which must be at
+    /// most the lowest frontier node level, returning the root.
+    pub(crate) fn extend_right_path<H: NodeHasher>(
+        &self,
+        hasher: &H,
+        level: usize,
+        path: &mut Vec<[u8; 32]>,
+    ) -> [u8; 32] {
+        let height = tree_height(self.leaves_count as usize);
+        let mut right_node: Option<[u8; 32]> = None;
+
+        for level in level..height {
+            let node = &self.nodes[level];
+            let is_complete = (self.leaves_count >> level) & 1 == 1;
+            path.push(right_node.unwrap_or(*node));
+            right_node = Some(match (is_complete, right_node) {
+                (true, Some(right_node)) => hasher.hash_nodes(node, &right_node),
+                (true, None) => hasher.hash_unpaired(level, node),
+                (false, Some(right_node)) => hasher.hash_unpaired(level, &right_node),
+                (false, None) => unreachable!("Level below the lowest frontier node"),
+            });
+        }
+
+        right_node.unwrap_or_else(|| self.nodes[height])
+    }
+
+    fn record(&mut self, entry: ChangeLogEntry) {
+        if self.changelog.len() == Self::CHANGELOG_LEN {
+            self.changelog.remove(0);
+        }
+        self.changelog.push(entry);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        tree::{compute_tree_root, get_proof},
+        verify::test::{hash, leaves},
+    };
+
+    #[test]
+    fn push_matches_tree_root() {
+        let leaves = leaves(9);
+        let mut frontier = Frontier::from_leaves(&hash, &[]);
+
+        for count in 1..=leaves.len() {
+            let root = frontier.push(&hash, &leaves[count - 1]);
+            assert_eq!(root, compute_tree_root(&hash, &leaves[..count]));
+            assert_eq!(frontier.root(&hash), root);
+        }
+        assert_eq!(
+            Frontier::from_leaves(&hash, &leaves).root(&hash),
+            frontier.root(&hash)
+        );
+        assert_eq!(frontier.changelog.len(), Frontier::CHANGELOG_LEN);
+    }
+
+    #[test]
+    fn replace_leaf_fast_forwards_stale_proof() {
+        let mut leaves = leaves(7);
+        let mut frontier = Frontier::from_leaves(&hash, &leaves[..4]);
+        frontier.push(&hash, &leaves[4]);
+        let stale_proof = get_proof(&hash, &leaves[..5], 1);
+        frontier.push(&hash, &leaves[5]);
+        frontier.push(&hash, &leaves[6]);
+
+        let root = frontier.root(&hash);
+        let new_leaf = [0xaa; 32];
+        let new_root = frontier.replace_leaf(&hash, &root, 1, &leaves[1], &new_leaf, &stale_proof);
+        leaves[1] = new_leaf;
+        assert_eq!(new_root, Some(compute_tree_root(&hash, &leaves)));
+        assert_eq!(frontier.root(&hash), new_root.unwrap());
+
+        // The leaf changed since the proof was built
+        let new_root = new_root.unwrap();
+        assert_eq!(
+            frontier.replace_leaf(&hash, &new_root, 1, &[1; 32], &[0; 32], &stale_proof),
+            None
+        );
+    }
+
+    #[test]
+    fn replace_leaf_rejects_out_of_range_and_wrong_leaf() {
+        let leaves = leaves(5);
+        let mut frontier = Frontier::from_leaves(&hash, &leaves);
+        let root = frontier.root(&hash);
+        let proof = get_proof(&hash, &leaves, 4);
+
+        assert_eq!(
+            frontier.replace_leaf(&hash, &root, 5, &leaves[4], &[0; 32], &proof),
+            None
+        );
+        assert_eq!(
+            frontier.replace_leaf(&hash, &root, 4, &leaves[3], &[0; 32], &proof),
+            None
+        );
+        assert_eq!(
+            frontier.replace_leaf(&hash, &root, 4, &leaves[4], &[0; 32], &[[7; 32]; 70]),
+            None
+        );
+    }
+}
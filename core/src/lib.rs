@@ -0,0 +1,16 @@
+//! Tree building, proof generation and proof verification shared by the program and
+//! off-chain tools. `no_std` with `alloc`, hashing is injected through `NodeHasher`, so the
+//! same code runs on-chain, on SVM rollups, on the host and in wasm.
+
+#![no_std]
+
+extern crate alloc;
+
+pub mod frontier;
+pub mod proof;
+pub mod tree;
+pub mod verify;
+
+pub use frontier::{ChangeLogEntry, Frontier};
+pub use proof::MerkleProof;
+pub use verify::NodeHasher;
@@ -0,0 +1,109 @@
+//! Self-describing inclusion proof exchanged between the CLI, clients, services and other
+//! languages: borsh for compact binary, hex of the borsh bytes for single strings, and JSON
+//! with hex encoded hashes through serde with the `serde` feature.
+
+use alloc::{string::String, vec::Vec};
+use borsh::{BorshDeserialize, BorshSerialize};
+
+/// Proof of `leaf` at `leaf_index` in the tree `tree_id` with `root`. `path` is as the tree
+/// builds it, see `tree::get_proof`, or `tree::get_audit_proof` for left-balanced trees.
+#[derive(Debug, Clone, PartialEq, Eq, BorshSerialize, BorshDeserialize)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MerkleProof {
+    /// Tree the proof is for, the merkle state account key of on-chain trees.
+    #[cfg_attr(feature = "serde", serde(with = "hex_serde"))]
+    pub tree_id: [u8; 32],
+    pub leaf_index: u32,
+    #[cfg_attr(feature = "serde", serde(with = "hex_serde"))]
+    pub leaf: [u8; 32],
+    #[cfg_attr(feature = "serde", serde(with = "hex_serde"))]
+    pub root: [u8; 32],
+    #[cfg_attr(feature = "serde", serde(with = "hex_serde::path"))]
+    pub path: Vec<[u8; 32]>,
+}
+
+impl MerkleProof {
+    /// Lowercase hex of the borsh encoding.
+    pub fn to_hex(&self) -> String {
+        encode_hex(&borsh::to_vec(self).expect("Can't serialize proof"))
+    }
+
+    /// Decode `to_hex` output, `None` if it isn't hex of a borsh encoded proof.
+    pub fn from_hex(hex: &str) -> Option<Self> {
+        Self::try_from_slice(&decode_hex(hex)?).ok()
+    }
+}
+
+/// Lowercase hex of `bytes`.
+pub fn encode_hex(bytes: &[u8]) -> String {
+    const DIGITS: &[u8; 16] = b"0123456789abcdef";
+    bytes
+        .iter()
+        .flat_map(|byte| [byte >> 4, byte & 0xf])
+        .map(|nibble| DIGITS[nibble as usize] as char)
+        .collect()
+}
+
+/// Bytes of `hex`, either case, `None` if it has an odd length or a non-hex digit.
+pub fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+
+    hex.as_bytes()
+        .chunks(2)
+        .map(|pair| {
+            let high = (pair[0] as char).to_digit(16)?;
+            let low = (pair[1] as char).to_digit(16)?;
+            Some((high << 4 | low) as u8)
+        })
+        .collect()
+}
+
+/// Hashes as hex strings in human-readable formats such as JSON.
+#[cfg(feature = "serde")]
+mod hex_serde {
+    use super::{decode_hex, encode_hex};
+    use alloc::string::String;
+    use serde::{Deserialize, Deserializer, Serializer, de::Error};
+
+    pub fn serialize<S: Serializer>(hash: &[u8; 32], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&encode_hex(hash))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<[u8; 32], D::Error> {
+        decode_hash(&String::deserialize(deserializer)?)
+    }
+
+    fn decode_hash<E: Error>(hex: &str) -> Result<[u8; 32], E> {
+        decode_hex(hex)
+            .and_then(|bytes| bytes.try_into().ok())
+            .ok_or_else(|| E::custom("invalid 32 bytes hex"))
+    }
+
+    pub mod path {
+        use super::{decode_hash, encode_hex};
+        use alloc::{string::String, vec::Vec};
+        use serde::{Deserialize, Deserializer, Serializer, ser::SerializeSeq};
+
+        pub fn serialize<S: Serializer>(
+            path: &[[u8; 32]],
+            serializer: S,
+        ) -> Result<S::Ok, S::Error> {
+            let mut seq = serializer.serialize_seq(Some(path.len()))?;
+            for node in path {
+                seq.serialize_element(&encode_hex(node))?;
+            }
+            seq.end()
+        }
+
+        pub fn deserialize<'de, D: Deserializer<'de>>(
+            deserializer: D,
+        ) -> Result<Vec<[u8; 32]>, D::Error> {
+            Vec::<String>::deserialize(deserializer)?
+                .iter()
+                .map(|node| decode_hash(node))
+                .collect()
+        }
+    }
+}
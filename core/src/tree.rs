@@ -0,0 +1,288 @@
+//! Tree building and proof generation from leaves. Trees are as `verify` checks them: odd
+//! nodes go through `NodeHasher::hash_unpaired`, and full proofs carry
+//! `NodeHasher::unpaired_sibling` for them.
+
+use crate::{
+    frontier::Frontier,
+    verify::{NodeHasher, has_sibling, tree_height},
+};
+use alloc::vec::Vec;
+use core::ops::Range;
+
+/// Max tree height, leaves are counted in a u32.
+pub const MAX_HEIGHT: usize = 32;
+
+/// Root of `leaves`.
+pub fn compute_tree_root<H: NodeHasher>(hasher: &H, leaves: &[[u8; 32]]) -> [u8; 32] {
+    compute_root_with_canopy(hasher, leaves, &mut [], 0)
+}
+
+/// Root of `leaves`, caching upper levels in `canopy` of `canopy_depth` levels below the root.
+pub fn compute_root_with_canopy<H: NodeHasher>(
+    hasher: &H,
+    leaves: &[[u8; 32]],
+    canopy: &mut [[u8; 32]],
+    canopy_depth: u8,
+) -> [u8; 32] {
+    let mut filled_subtrees = [[0u8; 32]; MAX_HEIGHT];
+    fold_leaves(hasher, leaves, &mut filled_subtrees, canopy, canopy_depth)
+}
+
+/// Fold `leaves` left to right into their root, keeping only the last complete subtree root
+/// of each level in `filled_subtrees` and caching upper levels in `canopy` of `canopy_depth`
+/// levels below the root. Nothing is allocated, memory use is O(depth) whatever the leaves
+/// count.
+pub fn fold_leaves<H: NodeHasher>(
+    hasher: &H,
+    leaves: &[[u8; 32]],
+    filled_subtrees: &mut [[u8; 32]; MAX_HEIGHT],
+    canopy: &mut [[u8; 32]],
+    canopy_depth: u8,
+) -> [u8; 32] {
+    let leaves_count = leaves.len();
+    let height = tree_height(leaves_count);
+    filled_subtrees.fill([0u8; 32]);
+    canopy.fill([0u8; 32]);
+
+    // Cache node `index` of `level` if it's within the canopy
+    let mut cache = |level: usize, index: usize, node: &[u8; 32]| {
+        let depth = height - level;
+        if level < height && depth <= canopy_depth as usize {
+            canopy[(1 << depth) - 2 + index] = *node;
+        }
+    };
+
+    // Carry each leaf up through the subtrees it completes, as `Frontier::append`
+    for (leaf_index, leaf) in leaves.iter().enumerate() {
+        let mut node = *leaf;
+        let mut level = 0;
+        cache(level, leaf_index, &node);
+        while (leaf_index >> level) & 1 == 1 {
+            node = hasher.hash_nodes(&filled_subtrees[level], &node);
+            level += 1;
+            cache(level, leaf_index >> level, &node);
+        }
+        filled_subtrees[level] = node;
+    }
+
+    // Hash the incomplete right path up to the root, as `Frontier::extend_right_path`
+    let mut right_node: Option<[u8; 32]> = None;
+    let lowest_level = leaves_count.trailing_zeros() as usize;
+    for (level, node) in filled_subtrees[..height]
+        .iter()
+        .enumerate()
+        .skip(lowest_level)
+    {
+        let parent = match ((leaves_count >> level) & 1 == 1, right_node) {
+            (true, Some(right_node)) => hasher.hash_nodes(node, &right_node),
+            (true, None) => hasher.hash_unpaired(level, node),
+            (false, Some(right_node)) => hasher.hash_unpaired(level, &right_node),
+            (false, None) => unreachable!("Level below the lowest filled subtree"),
+        };
+        cache(level + 1, (leaves_count - 1) >> (level + 1), &parent);
+        right_node = Some(parent);
+    }
+
+    right_node.unwrap_or(filled_subtrees[height])
+}
+
+/// Append `leaf` to a tree of `leaves_count` leaves, updating its `filled_subtrees` and
+/// `canopy` of `canopy_depth` levels below the root, and return the new root. Only the new
+/// leaf path is hashed, unlike `compute_root_with_canopy` which hashes every leaf.
+pub fn append_with_canopy<H: NodeHasher>(
+    hasher: &H,
+    filled_subtrees: &mut [[u8; 32]; MAX_HEIGHT],
+    leaves_count: usize,
+    leaf: &[u8; 32],
+    canopy: &mut [[u8; 32]],
+    canopy_depth: u8,
+) -> [u8; 32] {
+    let mut frontier = Frontier {
+        leaves_count: leaves_count as u32,
+        nodes: filled_subtrees.to_vec(),
+        changelog: Vec::new(),
+    };
+    let mut path = frontier.append(hasher, leaf);
+    let root_hash = frontier.extend_right_path(hasher, path.len(), &mut path);
+    filled_subtrees.copy_from_slice(&frontier.nodes);
+
+    // A complete tree grew a level, cached levels move down into the left subtree and its
+    // root becomes the left child of the new root
+    let height = path.len();
+    if height > tree_height(leaves_count) && canopy_depth > 0 {
+        for depth in (2..=canopy_depth as usize).rev() {
+            let (upper, lower) = canopy.split_at_mut((1 << depth) - 2);
+            let half = 1 << (depth - 1);
+            lower[..half].copy_from_slice(&upper[half - 2..]);
+            lower[half..2 * half].fill([0u8; 32]);
+        }
+        canopy[0] = filled_subtrees[height - 1];
+    }
+
+    // Cache nodes on the new leaf path
+    for depth in 1..=height.min(canopy_depth as usize) {
+        let level = height - depth;
+        canopy[(1 << depth) - 2 + (leaves_count >> level)] = path[level];
+    }
+
+    root_hash
+}
+
+/// Last complete subtree root of each level of `leaves`, see `append_with_canopy`.
+pub fn compute_filled_subtrees<H: NodeHasher>(
+    hasher: &H,
+    leaves: &[[u8; 32]],
+) -> [[u8; 32]; MAX_HEIGHT] {
+    let mut filled_subtrees = [[0u8; 32]; MAX_HEIGHT];
+    fold_leaves(hasher, leaves, &mut filled_subtrees, &mut [], 0);
+    filled_subtrees
+}
+
+/// Hash `layer` pairs at `level` into the parent layer, see `NodeHasher::hash_unpaired` for
+/// odd nodes.
+pub fn next_layer<H: NodeHasher>(hasher: &H, level: usize, layer: &[[u8; 32]]) -> Vec<[u8; 32]> {
+    layer
+        .chunks(2)
+        .map(|pair| match pair {
+            [a, b] => hasher.hash_nodes(a, b),
+            [a] => hasher.hash_unpaired(level, a),
+            _ => unreachable!(),
+        })
+        .collect()
+}
+
+/// Full proof for leaf at `leaf_index` of `leaves`: the sibling of each node on its path, or
+/// `NodeHasher::unpaired_sibling` if it has none.
+pub fn get_proof<H: NodeHasher>(
+    hasher: &H,
+    leaves: &[[u8; 32]],
+    leaf_index: usize,
+) -> Vec<[u8; 32]> {
+    path_siblings(hasher, leaves, leaf_index, tree_height(leaves.len()))
+}
+
+/// Audit path for leaf at `leaf_index` of `leaves`, the full proof without entries for
+/// levels where the node has no sibling, see `verify::compute_audit_root`.
+pub fn get_audit_proof<H: NodeHasher>(
+    hasher: &H,
+    leaves: &[[u8; 32]],
+    leaf_index: usize,
+) -> Vec<[u8; 32]> {
+    get_proof(hasher, leaves, leaf_index)
+        .into_iter()
+        .enumerate()
+        .filter(|(level, _)| has_sibling(leaf_index >> level, leaves.len(), *level))
+        .map(|(_, sibling)| sibling)
+        .collect()
+}
+
+/// Deduplicated multiproof for `leaf_indices`(sorted, strictly increasing) of `leaves`, see
+/// `verify::compute_multiproof_root` for the node order.
+pub fn get_multiproof<H: NodeHasher>(
+    hasher: &H,
+    leaves: &[[u8; 32]],
+    leaf_indices: &[u32],
+) -> Vec<[u8; 32]> {
+    let mut proof = Vec::new();
+    let mut indices: Vec<usize> = leaf_indices.iter().map(|index| *index as usize).collect();
+    let mut layer = leaves.to_vec();
+    let mut level = 0;
+
+    while layer.len() > 1 {
+        let mut i = 0;
+        while i < indices.len() {
+            let sibling_index = indices[i] ^ 1;
+            if indices.get(i + 1) == Some(&sibling_index) {
+                i += 1;
+            } else if sibling_index < layer.len() {
+                proof.push(layer[sibling_index]);
+            }
+            i += 1;
+        }
+
+        indices = indices.iter().map(|index| index / 2).collect();
+        indices.dedup();
+        layer = next_layer(hasher, level, &layer);
+        level += 1;
+    }
+
+    proof
+}
+
+/// Consistency proof that the tree of the first `old_leaves_count` of `leaves` is a prefix of
+/// the tree of all of them: the last old leaf and its full proof, see
+/// `verify::verify_consistency`.
+pub fn get_consistency_proof<H: NodeHasher>(
+    hasher: &H,
+    leaves: &[[u8; 32]],
+    old_leaves_count: usize,
+) -> ([u8; 32], Vec<[u8; 32]>) {
+    let leaf_index = old_leaves_count.saturating_sub(1);
+    (leaves[leaf_index], get_proof(hasher, leaves, leaf_index))
+}
+
+/// Leaf indices of the subtree right below a `canopy_depth` canopy that holds leaf at
+/// `leaf_index` in a tree of `leaves_count` leaves. Its leaves and the canopy are all a proof
+/// needs, see `truncated_proof_from_subtree`.
+pub fn canopy_subtree_range(
+    leaves_count: usize,
+    canopy_depth: u8,
+    leaf_index: usize,
+) -> Range<usize> {
+    let subtree_level = canopy_subtree_level(leaves_count, canopy_depth);
+    let start = (leaf_index >> subtree_level) << subtree_level;
+
+    start..leaves_count.min(start + (1 << subtree_level))
+}
+
+/// Proof for leaf at `leaf_index` truncated at a `canopy_depth` canopy, built from only the
+/// leaves of its `canopy_subtree_range`. `None` if the leaf is out of range or the subtree
+/// leaves don't match the range.
+pub fn truncated_proof_from_subtree<H: NodeHasher>(
+    hasher: &H,
+    leaves_count: usize,
+    canopy_depth: u8,
+    leaf_index: usize,
+    subtree_leaves: &[[u8; 32]],
+) -> Option<Vec<[u8; 32]>> {
+    let subtree_range = canopy_subtree_range(leaves_count, canopy_depth, leaf_index);
+    if leaf_index >= leaves_count || subtree_leaves.len() != subtree_range.len() {
+        return None;
+    }
+
+    Some(path_siblings(
+        hasher,
+        subtree_leaves,
+        leaf_index - subtree_range.start,
+        canopy_subtree_level(leaves_count, canopy_depth),
+    ))
+}
+
+/// Level of the subtree roots right below a `canopy_depth` canopy.
+fn canopy_subtree_level(leaves_count: usize, canopy_depth: u8) -> usize {
+    let height = tree_height(leaves_count);
+    height - height.min(canopy_depth as usize)
+}
+
+/// Sibling of each node on the path of leaf at `leaf_index` of `leaves` up to `levels`, the
+/// unpaired sibling if it has none.
+fn path_siblings<H: NodeHasher>(
+    hasher: &H,
+    leaves: &[[u8; 32]],
+    leaf_index: usize,
+    levels: usize,
+) -> Vec<[u8; 32]> {
+    let mut proof = Vec::with_capacity(levels);
+    let mut index = leaf_index;
+    let mut layer = leaves.to_vec();
+    for level in 0..levels {
+        proof.push(match layer.get(index ^ 1) {
+            Some(sibling) => *sibling,
+            None => hasher.unpaired_sibling(level, &layer[index]),
+        });
+        index /= 2;
+        layer = next_layer(hasher, level, &layer);
+    }
+
+    proof
+}
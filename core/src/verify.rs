@@ -0,0 +1,512 @@
+//! Merkle proof verification without Solana syscalls or sysvars.
+//! Hashing is injected by the caller, so the same code runs on-chain, on SVM rollups and on the host.
+
+use alloc::{vec, vec::Vec};
+
+/// Combines two child nodes into their parent node, `a` is the left child and `b` the right.
+/// Sorted pair hashers ignore the order.
+pub trait NodeHasher {
+    fn hash_nodes(&self, a: &[u8; 32], b: &[u8; 32]) -> [u8; 32];
+
+    /// Parent of a node at `level` without sibling, the node paired with
+    /// `Self::unpaired_sibling` by default.
+    fn hash_unpaired(&self, level: usize, node: &[u8; 32]) -> [u8; 32] {
+        self.hash_nodes(node, &self.unpaired_sibling(level, node))
+    }
+
+    /// Node a node at `level` without sibling is paired with, and full proofs carry for it,
+    /// the node itself by default.
+    fn unpaired_sibling(&self, _level: usize, node: &[u8; 32]) -> [u8; 32] {
+        *node
+    }
+}
+
+impl<F> NodeHasher for F
+where
+    F: Fn(&[u8; 32], &[u8; 32]) -> [u8; 32],
+{
+    fn hash_nodes(&self, a: &[u8; 32], b: &[u8; 32]) -> [u8; 32] {
+        self(a, b)
+    }
+}
+
+/// Upper tree levels cached on-chain, node `i` at depth `j` below the root is stored at `2^j - 2 + i`.
+pub struct Canopy<'a> {
+    pub nodes: &'a [[u8; 32]],
+    pub depth: usize,
+}
+
+/// Fold `proof` siblings over `leaf`, returning the node reached at level `proof.len()`.
+/// Siblings are taken as right children, so ordered trees need `compute_root_at`.
+pub fn compute_root<H: NodeHasher>(hasher: &H, leaf: &[u8; 32], proof: &[[u8; 32]]) -> [u8; 32] {
+    compute_root_at(hasher, 0, leaf, proof)
+}
+
+/// Fold `proof` siblings over `leaf` at `leaf_index`, whose bits are the proof directions:
+/// bit `i` is set if `proof[i]` is the left child.
+pub fn compute_root_at<H: NodeHasher>(
+    hasher: &H,
+    leaf_index: usize,
+    leaf: &[u8; 32],
+    proof: &[[u8; 32]],
+) -> [u8; 32] {
+    proof
+        .iter()
+        .enumerate()
+        .fold(*leaf, |node, (level, sibling)| {
+            // Levels past the index bits are left children, proofs longer than
+            // `usize::BITS` just don't match
+            let index = leaf_index.checked_shr(level as u32).unwrap_or(0);
+            hash_at(hasher, index, &node, sibling)
+        })
+}
+
+/// Fold audit `proof` over `leaf` at `leaf_index` of a tree with `leaves_count` leaves. Unlike
+/// full proofs it has no entries for levels where the node has no sibling, as in RFC 6962.
+/// `None` if the leaf is out of range or the proof length doesn't match.
+pub fn compute_audit_root<H: NodeHasher>(
+    hasher: &H,
+    leaves_count: usize,
+    leaf_index: usize,
+    leaf: &[u8; 32],
+    proof: &[[u8; 32]],
+) -> Option<[u8; 32]> {
+    if leaf_index >= leaves_count {
+        return None;
+    }
+
+    let mut proof_iter = proof.iter();
+    let mut node = *leaf;
+    for level in 0..tree_height(leaves_count) {
+        let index = leaf_index >> level;
+        node = match has_sibling(index, leaves_count, level) {
+            true => hash_at(hasher, index, &node, proof_iter.next()?),
+            false => hasher.hash_unpaired(level, &node),
+        };
+    }
+
+    proof_iter.next().is_none().then_some(node)
+}
+
+/// Whether node `index` at `level` of a tree with `leaves_count` leaves has a sibling.
+pub(crate) fn has_sibling(index: usize, leaves_count: usize, level: usize) -> bool {
+    (index ^ 1) < leaves_count.div_ceil(1 << level)
+}
+
+/// Parent of `node` at `index` in its level and its `sibling`.
+pub fn hash_at<H: NodeHasher>(
+    hasher: &H,
+    index: usize,
+    node: &[u8; 32],
+    sibling: &[u8; 32],
+) -> [u8; 32] {
+    match index & 1 {
+        0 => hasher.hash_nodes(node, sibling),
+        _ => hasher.hash_nodes(sibling, node),
+    }
+}
+
+/// Verify full `proof` for `leaf` against `root`.
+pub fn verify_proof<H: NodeHasher>(
+    hasher: &H,
+    root: &[u8; 32],
+    leaf: &[u8; 32],
+    proof: &[[u8; 32]],
+) -> bool {
+    &compute_root(hasher, leaf, proof) == root
+}
+
+/// Verify full `proof` for `leaf` at `leaf_index` against any of `roots`.
+pub fn verify_proof_against_roots<H: NodeHasher>(
+    hasher: &H,
+    roots: &[[u8; 32]],
+    leaf_index: usize,
+    leaf: &[u8; 32],
+    proof: &[[u8; 32]],
+) -> bool {
+    roots.contains(&compute_root_at(hasher, leaf_index, leaf, proof))
+}
+
+/// Level a `ChainedProof` segment proves, ordered bottom-up.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ProofLevel {
+    /// `leaf -> tree root`.
+    Tree,
+    /// `tree root -> aggregate root` over multiple trees.
+    Forest,
+}
+
+/// Full proof chained through one or more levels, e.g. `leaf -> tree root -> aggregate root`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChainedProof {
+    /// Sibling paths with their level, at most one per level in `ProofLevel` order.
+    pub segments: Vec<(ProofLevel, Vec<[u8; 32]>)>,
+}
+
+impl ChainedProof {
+    /// Proof of `leaf -> tree root`.
+    pub fn tree(proof: Vec<[u8; 32]>) -> Self {
+        Self {
+            segments: vec![(ProofLevel::Tree, proof)],
+        }
+    }
+
+    /// Proof of `leaf -> tree root -> aggregate root`.
+    pub fn forest(tree_proof: Vec<[u8; 32]>, forest_proof: Vec<[u8; 32]>) -> Self {
+        Self {
+            segments: vec![
+                (ProofLevel::Tree, tree_proof),
+                (ProofLevel::Forest, forest_proof),
+            ],
+        }
+    }
+
+    /// Fold all segments over `leaf`, `None` if segment levels are out of order or repeated.
+    pub fn compute_root<H: NodeHasher>(&self, hasher: &H, leaf: &[u8; 32]) -> Option<[u8; 32]> {
+        if !self.segments.windows(2).all(|pair| pair[0].0 < pair[1].0) {
+            return None;
+        }
+
+        Some(
+            self.segments
+                .iter()
+                .fold(*leaf, |node, (_, proof)| compute_root(hasher, &node, proof)),
+        )
+    }
+
+    /// Root level the proof reaches, `None` for an empty proof.
+    pub fn top_level(&self) -> Option<ProofLevel> {
+        self.segments.last().map(|(level, _)| *level)
+    }
+}
+
+/// Verify multi-level `proof` for `leaf` against `root` of `level`, e.g. an aggregate root
+/// must be checked with `ProofLevel::Forest` so a bare tree proof can't pass for it.
+pub fn verify_chained_proof<H: NodeHasher>(
+    hasher: &H,
+    root: &[u8; 32],
+    level: ProofLevel,
+    leaf: &[u8; 32],
+    proof: &ChainedProof,
+) -> bool {
+    proof.top_level() == Some(level) && proof.compute_root(hasher, leaf).as_ref() == Some(root)
+}
+
+/// Verify `proof` for `leaf` at `leaf_index` in a tree of `height`, the proof may stop
+/// at any level cached in `canopy`.
+pub fn verify_proof_with_canopy<H: NodeHasher>(
+    hasher: &H,
+    root: &[u8; 32],
+    canopy: Canopy,
+    height: usize,
+    leaf_index: usize,
+    leaf: &[u8; 32],
+    proof: &[[u8; 32]],
+) -> bool {
+    let node = compute_root_at(hasher, leaf_index, leaf, proof);
+
+    let Some(depth) = height.checked_sub(proof.len()) else {
+        return false;
+    };
+    match depth {
+        0 => &node == root,
+        depth if depth <= canopy.depth => {
+            let index = leaf_index.checked_shr(proof.len() as u32).unwrap_or(0);
+            canopy.nodes.get((1 << depth) - 2 + index) == Some(&node)
+        }
+        _ => false,
+    }
+}
+
+/// Fold deduplicated multiproof `proof` over `leaves` (sorted by strictly increasing index)
+/// of a tree with `leaves_count` leaves. Siblings known from `leaves` or earlier levels are
+/// not included in `proof`, the rest are consumed level by level, left to right.
+pub fn compute_multiproof_root<H: NodeHasher>(
+    hasher: &H,
+    leaves_count: usize,
+    leaves: &[(u32, [u8; 32])],
+    proof: &[[u8; 32]],
+) -> Option<[u8; 32]> {
+    let is_sorted = leaves.windows(2).all(|pair| pair[0].0 < pair[1].0);
+    let is_in_range = leaves
+        .last()
+        .is_some_and(|(index, _)| (*index as usize) < leaves_count);
+    if !is_sorted || !is_in_range {
+        return None;
+    }
+
+    let mut layer: Vec<(usize, [u8; 32])> = leaves
+        .iter()
+        .map(|(index, leaf)| (*index as usize, *leaf))
+        .collect();
+    let mut layer_len = leaves_count;
+    let mut proof_iter = proof.iter();
+    let mut level = 0;
+
+    while layer_len > 1 {
+        let mut next_layer = Vec::with_capacity(layer.len());
+        let mut i = 0;
+
+        while i < layer.len() {
+            let (index, node) = layer[i];
+            let sibling_index = index ^ 1;
+
+            let parent = match layer.get(i + 1) {
+                Some((next_index, next_node)) if *next_index == sibling_index => {
+                    i += 1;
+                    hash_at(hasher, index, &node, next_node)
+                }
+                _ if sibling_index >= layer_len => hasher.hash_unpaired(level, &node),
+                _ => hash_at(hasher, index, &node, proof_iter.next()?),
+            };
+            next_layer.push((index / 2, parent));
+            i += 1;
+        }
+
+        layer = next_layer;
+        layer_len = layer_len.div_ceil(2);
+        level += 1;
+    }
+
+    // Every proof node must be consumed
+    if proof_iter.next().is_some() {
+        return None;
+    }
+    layer.first().map(|(_, node)| *node)
+}
+
+/// Verify deduplicated multiproof for `leaves` against `root`, see `compute_multiproof_root`.
+pub fn verify_multiproof<H: NodeHasher>(
+    hasher: &H,
+    root: &[u8; 32],
+    leaves_count: usize,
+    leaves: &[(u32, [u8; 32])],
+    proof: &[[u8; 32]],
+) -> bool {
+    compute_multiproof_root(hasher, leaves_count, leaves, proof).as_ref() == Some(root)
+}
+
+/// Compute roots of the trees with `old_leaves_count` and `new_leaves_count` leaves from
+/// consistency `proof`: the full proof of the last old leaf `leaf` in the new tree.
+/// Left siblings on its path are complete subtrees shared by both trees, the old tree has
+/// no right siblings. Entries of levels without sibling are ignored.
+pub fn compute_consistency_roots<H: NodeHasher>(
+    hasher: &H,
+    old_leaves_count: usize,
+    new_leaves_count: usize,
+    leaf: &[u8; 32],
+    proof: &[[u8; 32]],
+) -> Option<([u8; 32], [u8; 32])> {
+    let old_height = tree_height(old_leaves_count);
+    if old_leaves_count == 0
+        || old_leaves_count > new_leaves_count
+        || proof.len() != tree_height(new_leaves_count)
+    {
+        return None;
+    }
+
+    let index = old_leaves_count - 1;
+    let old_root = proof[..old_height]
+        .iter()
+        .enumerate()
+        .fold(*leaf, |node, (level, sibling)| match (index >> level) & 1 {
+            1 => hasher.hash_nodes(sibling, &node),
+            _ => hasher.hash_unpaired(level, &node),
+        });
+    let new_root =
+        proof
+            .iter()
+            .enumerate()
+            .fold(*leaf, |node, (level, sibling)| {
+                match has_sibling(index >> level, new_leaves_count, level) {
+                    true => hash_at(hasher, index >> level, &node, sibling),
+                    false => hasher.hash_unpaired(level, &node),
+                }
+            });
+
+    Some((old_root, new_root))
+}
+
+/// Verify consistency `proof` showing the tree at `old_root` is a prefix of the tree at
+/// `new_root`, see `compute_consistency_roots`.
+pub fn verify_consistency<H: NodeHasher>(
+    hasher: &H,
+    old_root: &[u8; 32],
+    old_leaves_count: usize,
+    new_root: &[u8; 32],
+    new_leaves_count: usize,
+    leaf: &[u8; 32],
+    proof: &[[u8; 32]],
+) -> bool {
+    compute_consistency_roots(hasher, old_leaves_count, new_leaves_count, leaf, proof)
+        == Some((*old_root, *new_root))
+}
+
+/// Tree height for `leaves_count` leaves, odd nodes are paired with themselves.
+pub fn tree_height(leaves_count: usize) -> usize {
+    match leaves_count {
+        0 => 0,
+        count => (usize::BITS - (count - 1).leading_zeros()) as usize,
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use super::*;
+    use crate::tree::{compute_tree_root, get_audit_proof, get_multiproof, get_proof};
+
+    /// Order dependent test hasher, FNV-1a over both children per output byte.
+    pub(crate) fn hash(a: &[u8; 32], b: &[u8; 32]) -> [u8; 32] {
+        core::array::from_fn(|i| {
+            let hash = a
+                .iter()
+                .chain(b)
+                .fold(0xcbf29ce484222325 ^ i as u64, |hash, byte| {
+                    (hash ^ *byte as u64).wrapping_mul(0x100000001b3)
+                });
+            (hash >> 56) as u8
+        })
+    }
+
+    pub(crate) fn leaves(count: u8) -> Vec<[u8; 32]> {
+        (0..count).map(|i| [i; 32]).collect()
+    }
+
+    #[test]
+    fn compute_root_at_matches_tree_root() {
+        for count in 1..=9 {
+            let leaves = leaves(count);
+            let root = compute_tree_root(&hash, &leaves);
+            for (index, leaf) in leaves.iter().enumerate() {
+                let proof = get_proof(&hash, &leaves, index);
+                assert_eq!(compute_root_at(&hash, index, leaf, &proof), root);
+                assert!(verify_proof_against_roots(
+                    &hash,
+                    &[root],
+                    index,
+                    leaf,
+                    &proof
+                ));
+                assert!(!verify_proof_against_roots(
+                    &hash,
+                    &[root],
+                    index,
+                    &[0xff; 32],
+                    &proof
+                ));
+            }
+        }
+    }
+
+    #[test]
+    fn compute_root_at_checks_proof_direction() {
+        let leaves = leaves(4);
+        let root = compute_tree_root(&hash, &leaves);
+        let proof = get_proof(&hash, &leaves, 1);
+
+        assert!(!verify_proof_against_roots(
+            &hash,
+            &[root],
+            0,
+            &leaves[1],
+            &proof
+        ));
+        assert!(!verify_proof_against_roots(
+            &hash,
+            &[root],
+            3,
+            &leaves[1],
+            &proof
+        ));
+    }
+
+    #[test]
+    fn overlong_proof_fails_without_panic() {
+        let proof = [[7u8; 32]; 70];
+
+        assert!(!verify_proof_against_roots(
+            &hash,
+            &[[0; 32]],
+            usize::MAX,
+            &[1; 32],
+            &proof
+        ));
+        let canopy = Canopy {
+            nodes: &[[0; 32]; 6],
+            depth: 2,
+        };
+        assert!(!verify_proof_with_canopy(
+            &hash,
+            &[0; 32],
+            canopy,
+            71,
+            usize::MAX,
+            &[1; 32],
+            &proof
+        ));
+    }
+
+    #[test]
+    fn compute_audit_root_matches_tree_root() {
+        for count in 1..=9 {
+            let leaves = leaves(count);
+            let root = compute_tree_root(&hash, &leaves);
+            for (index, leaf) in leaves.iter().enumerate() {
+                let proof = get_audit_proof(&hash, &leaves, index);
+                assert_eq!(
+                    compute_audit_root(&hash, leaves.len(), index, leaf, &proof),
+                    Some(root)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn compute_audit_root_rejects_malformed_proof() {
+        let leaves = leaves(5);
+        let proof = get_audit_proof(&hash, &leaves, 2);
+
+        assert_eq!(compute_audit_root(&hash, 5, 5, &leaves[2], &proof), None);
+        assert_eq!(
+            compute_audit_root(&hash, 5, 2, &leaves[2], &proof[1..]),
+            None
+        );
+        let mut extended = proof.clone();
+        extended.push([0; 32]);
+        assert_eq!(compute_audit_root(&hash, 5, 2, &leaves[2], &extended), None);
+    }
+
+    #[test]
+    fn multiproof_matches_tree_root() {
+        let leaves = leaves(7);
+        let root = compute_tree_root(&hash, &leaves);
+        let subsets: [&[u32]; 5] = [&[0], &[6], &[1, 2], &[0, 3, 6], &[0, 1, 2, 3, 4, 5, 6]];
+
+        for indices in subsets {
+            let proof = get_multiproof(&hash, &leaves, indices);
+            let proved: Vec<(u32, [u8; 32])> = indices
+                .iter()
+                .map(|index| (*index, leaves[*index as usize]))
+                .collect();
+            assert!(verify_multiproof(&hash, &root, 7, &proved, &proof));
+        }
+    }
+
+    #[test]
+    fn multiproof_rejects_malformed_input() {
+        let leaves = leaves(7);
+        let root = compute_tree_root(&hash, &leaves);
+        let proof = get_multiproof(&hash, &leaves, &[1, 4]);
+        let proved = [(1, leaves[1]), (4, leaves[4])];
+
+        let unsorted = [(4, leaves[4]), (1, leaves[1])];
+        assert_eq!(compute_multiproof_root(&hash, 7, &unsorted, &proof), None);
+        assert_eq!(compute_multiproof_root(&hash, 4, &proved, &proof), None);
+        assert_eq!(compute_multiproof_root(&hash, 7, &[], &proof), None);
+
+        let mut extended = proof.clone();
+        extended.push([0; 32]);
+        assert!(!verify_multiproof(&hash, &root, 7, &proved, &extended));
+        assert!(!verify_multiproof(&hash, &root, 7, &proved, &proof[1..]));
+    }
+}
@@ -0,0 +1,314 @@
+//! Errors shared by the program, SDK helpers, CLI and services, so an error code seen in
+//! transaction logs maps to the same named variant everywhere.
+
+use solana_program::{instruction::InstructionError, program_error::ProgramError, pubkey::Pubkey};
+use std::fmt;
+
+/// Program error, returned as `ProgramError::Custom(code)`. Codes are the variant order,
+/// new variants are only appended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MerkleTreeError {
+    /// Tree is paused by its authority, mutating instructions are rejected.
+    TreePaused,
+    /// Merkle proof doesn't match the root or canopy.
+    InvalidProof,
+    /// Archive root or content hash doesn't match the tree.
+    InvalidArchive,
+    /// Passed merkle state account is not the program merkle state PDA.
+    InvalidMerkleStatePda,
+    /// Passed system program account is not the system program.
+    InvalidSystemProgram,
+    /// Signer is not the tree authority.
+    InvalidAuthority,
+    /// Signer is not the pending tree authority, or none is proposed.
+    InvalidPendingAuthority,
+    /// Merkle state account reached max account size, no more data fits.
+    TreeFull,
+    /// Archive URI exceeds `MerkleStateAccount::MAX_ARCHIVE_URI_LEN`.
+    ArchiveUriTooLong,
+    /// Passed event authority is not the program event authority PDA or didn't sign.
+    InvalidEventAuthority,
+    /// Leaf is already present and the tree rejects duplicates.
+    DuplicateLeaf,
+    /// Tree is sealed by `FinalizeTree`, leaves can't change anymore.
+    TreeSealed,
+    /// Instruction needs stored leaves, which the tree dropped in frontier mode or can't drop
+    /// being left-balanced.
+    UnsupportedInFrontierMode,
+    /// Account data doesn't start with the merkle state discriminator.
+    InvalidAccountDiscriminator,
+    /// Merkle state account layout version isn't supported by this program.
+    UnsupportedAccountVersion,
+    /// `SweepExpired` found no expired leaves.
+    NoExpiredLeaves,
+    /// Passed leaf page is not the page PDA of the leaf or isn't a leaf page, or
+    /// `EnableLeafPages` doesn't resume at the next page to write.
+    InvalidLeafPage,
+    /// Serialized merkle state doesn't span its account, see `MerkleStateAccount::pack`, or
+    /// the account is too short to hold the state header.
+    StateSizeMismatch,
+    /// Passed tree metadata account is not the tree metadata PDA or isn't tree metadata.
+    InvalidTreeMetadata,
+    /// Tree metadata name, description or URI exceeds its max length.
+    MetadataTooLong,
+    /// Leaf of a Poseidon tree is not a BN254 field element, see `HashAlgo::is_valid_leaf`.
+    LeafNotInField,
+    /// Payer, authority or another account that must sign the instruction didn't.
+    MissingSigner,
+    /// Merkle state account isn't owned by this program.
+    InvalidMerkleStateOwner,
+    /// Tree is permissioned and the payer isn't an allowed inserter, or the inserter to
+    /// remove isn't allowed.
+    InvalidInserter,
+    /// Passed multisig account is not the tree multisig PDA or isn't a multisig, or its
+    /// threshold and signers are invalid, see `MultisigAccount::new`.
+    InvalidMultisig,
+    /// Payer exceeded the tree insert rate limit of the current slot or epoch.
+    RateLimited,
+    /// Insert expected another root than the current one, i.e. another write came first, or
+    /// the tree doesn't exist yet.
+    RootMismatch,
+    /// Leaf is the zero hash or an empty subtree root of the tree, see `SetRejectSentinelLeaves`.
+    SentinelLeaf,
+    /// Tree has a config timelock, config changes must go through `QueueConfig`. Changes that
+    /// can't be queued, e.g. authority transfers, wait until it's lifted.
+    ConfigTimelocked,
+    /// Pending config account is invalid or full, or holds no change due yet.
+    InvalidPendingConfig,
+    /// Instruction data starts with a tag no instruction has.
+    UnknownInstruction,
+    /// Instruction data ends before its fields or a length prefix says.
+    TruncatedInstructionData,
+    /// Instruction data has bytes past its last field.
+    OversizedInstructionData,
+    /// Account must grow by more than one instruction may realloc, grow it ahead with
+    /// `GrowTree` first.
+    ReallocLimitExceeded,
+    /// Leaves appended in lazy root mode aren't in the root yet, commit it with `CommitRoot`
+    /// first.
+    RootNotCommitted,
+    /// Leaf pages can't be enabled while the tree rejects duplicates, has pending leaf
+    /// expiries, is left-balanced or in lazy root mode.
+    UnsupportedWithLeafPages,
+}
+
+impl MerkleTreeError {
+    /// All errors in code order.
+    pub const ALL: [Self; 36] = [
+        Self::TreePaused,
+        Self::InvalidProof,
+        Self::InvalidArchive,
+        Self::InvalidMerkleStatePda,
+        Self::InvalidSystemProgram,
+        Self::InvalidAuthority,
+        Self::InvalidPendingAuthority,
+        Self::TreeFull,
+        Self::ArchiveUriTooLong,
+        Self::InvalidEventAuthority,
+        Self::DuplicateLeaf,
+        Self::TreeSealed,
+        Self::UnsupportedInFrontierMode,
+        Self::InvalidAccountDiscriminator,
+        Self::UnsupportedAccountVersion,
+        Self::NoExpiredLeaves,
+        Self::InvalidLeafPage,
+        Self::StateSizeMismatch,
+        Self::InvalidTreeMetadata,
+        Self::MetadataTooLong,
+        Self::LeafNotInField,
+        Self::MissingSigner,
+        Self::InvalidMerkleStateOwner,
+        Self::InvalidInserter,
+        Self::InvalidMultisig,
+        Self::RateLimited,
+        Self::RootMismatch,
+        Self::SentinelLeaf,
+        Self::ConfigTimelocked,
+        Self::InvalidPendingConfig,
+        Self::UnknownInstruction,
+        Self::TruncatedInstructionData,
+        Self::OversizedInstructionData,
+        Self::ReallocLimitExceeded,
+        Self::RootNotCommitted,
+        Self::UnsupportedWithLeafPages,
+    ];
+
+    pub fn code(self) -> u32 {
+        self as u32
+    }
+
+    pub fn from_code(code: u32) -> Option<Self> {
+        Self::ALL.get(code as usize).copied()
+    }
+
+    /// Program error of a failed instruction, `None` for runtime and other programs errors.
+    pub fn from_instruction_error(error: &InstructionError) -> Option<Self> {
+        match error {
+            InstructionError::Custom(code) => Self::from_code(*code),
+            _ => None,
+        }
+    }
+
+    /// Variant name, stable across the program, SDK, CLI and service responses.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::TreePaused => "TreePaused",
+            Self::InvalidProof => "InvalidProof",
+            Self::InvalidArchive => "InvalidArchive",
+            Self::InvalidMerkleStatePda => "InvalidMerkleStatePda",
+            Self::InvalidSystemProgram => "InvalidSystemProgram",
+            Self::InvalidAuthority => "InvalidAuthority",
+            Self::InvalidPendingAuthority => "InvalidPendingAuthority",
+            Self::TreeFull => "TreeFull",
+            Self::ArchiveUriTooLong => "ArchiveUriTooLong",
+            Self::InvalidEventAuthority => "InvalidEventAuthority",
+            Self::DuplicateLeaf => "DuplicateLeaf",
+            Self::TreeSealed => "TreeSealed",
+            Self::UnsupportedInFrontierMode => "UnsupportedInFrontierMode",
+            Self::InvalidAccountDiscriminator => "InvalidAccountDiscriminator",
+            Self::UnsupportedAccountVersion => "UnsupportedAccountVersion",
+            Self::NoExpiredLeaves => "NoExpiredLeaves",
+            Self::InvalidLeafPage => "InvalidLeafPage",
+            Self::StateSizeMismatch => "StateSizeMismatch",
+            Self::InvalidTreeMetadata => "InvalidTreeMetadata",
+            Self::MetadataTooLong => "MetadataTooLong",
+            Self::LeafNotInField => "LeafNotInField",
+            Self::MissingSigner => "MissingSigner",
+            Self::InvalidMerkleStateOwner => "InvalidMerkleStateOwner",
+            Self::InvalidInserter => "InvalidInserter",
+            Self::InvalidMultisig => "InvalidMultisig",
+            Self::RateLimited => "RateLimited",
+            Self::RootMismatch => "RootMismatch",
+            Self::SentinelLeaf => "SentinelLeaf",
+            Self::ConfigTimelocked => "ConfigTimelocked",
+            Self::InvalidPendingConfig => "InvalidPendingConfig",
+            Self::UnknownInstruction => "UnknownInstruction",
+            Self::TruncatedInstructionData => "TruncatedInstructionData",
+            Self::OversizedInstructionData => "OversizedInstructionData",
+            Self::ReallocLimitExceeded => "ReallocLimitExceeded",
+            Self::RootNotCommitted => "RootNotCommitted",
+            Self::UnsupportedWithLeafPages => "UnsupportedWithLeafPages",
+        }
+    }
+}
+
+impl fmt::Display for MerkleTreeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} (error {:#x})", self.name(), self.code())
+    }
+}
+
+impl std::error::Error for MerkleTreeError {}
+
+impl From<MerkleTreeError> for ProgramError {
+    fn from(error: MerkleTreeError) -> Self {
+        ProgramError::Custom(error as u32)
+    }
+}
+
+/// Getter return data decoding error, see `ReturnData`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReturnDataError {
+    /// Transaction or CPI didn't set return data.
+    Missing,
+    /// Return data is set by another program than the queried one.
+    WrongProgram { expected: Pubkey, actual: Pubkey },
+    /// Program id or payload of RPC return data is malformed.
+    InvalidEncoding,
+    /// Payload doesn't deserialize into the expected type.
+    InvalidData,
+}
+
+impl fmt::Display for ReturnDataError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Missing => write!(f, "return data is not set"),
+            Self::WrongProgram { expected, actual } => {
+                write!(f, "return data is set by {actual}, expected {expected}")
+            }
+            Self::InvalidEncoding => write!(f, "return data is not valid base64 or program id"),
+            Self::InvalidData => write!(f, "return data doesn't match the expected type"),
+        }
+    }
+}
+
+impl std::error::Error for ReturnDataError {}
+
+/// Proof can't be built from the fetched tree.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProofError {
+    /// Leaf isn't in the tree.
+    LeafNotFound,
+    /// Leaf index is past the last leaf.
+    LeafIndexOutOfRange { leaf_index: u32, leaves_count: u32 },
+    /// Tree stores only its frontier, proofs must be built from an off-chain copy.
+    LeavesNotStored,
+}
+
+impl fmt::Display for ProofError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::LeafNotFound => write!(f, "leaf is not in the tree"),
+            Self::LeafIndexOutOfRange {
+                leaf_index,
+                leaves_count,
+            } => write!(f, "leaf index {leaf_index} is out of {leaves_count} leaves"),
+            Self::LeavesNotStored => write!(f, "tree doesn't store its leaves"),
+        }
+    }
+}
+
+impl std::error::Error for ProofError {}
+
+/// Error of off-chain tree clients.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SdkError {
+    /// RPC request failed.
+    Rpc(String),
+    /// Account exists but doesn't hold the expected program account.
+    InvalidAccount(Pubkey),
+    /// Account or return data can't be decoded.
+    Decoding(ReturnDataError),
+    /// Proof can't be built.
+    Proof(ProofError),
+    /// Program rejected the instruction.
+    Program(MerkleTreeError),
+    /// Fetched transaction is malformed or lacks its metadata.
+    InvalidTransaction(&'static str),
+    /// Transaction failed outside of the program, e.g. on fees or compute units.
+    Transaction(String),
+}
+
+impl fmt::Display for SdkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Rpc(error) => write!(f, "RPC error: {error}"),
+            Self::InvalidAccount(pubkey) => write!(f, "account {pubkey} has invalid data"),
+            Self::Decoding(error) => write!(f, "decoding error: {error}"),
+            Self::Proof(error) => write!(f, "proof error: {error}"),
+            Self::Program(error) => write!(f, "program error: {error}"),
+            Self::InvalidTransaction(reason) => write!(f, "invalid transaction: {reason}"),
+            Self::Transaction(error) => write!(f, "transaction error: {error}"),
+        }
+    }
+}
+
+impl std::error::Error for SdkError {}
+
+impl From<ReturnDataError> for SdkError {
+    fn from(error: ReturnDataError) -> Self {
+        Self::Decoding(error)
+    }
+}
+
+impl From<ProofError> for SdkError {
+    fn from(error: ProofError) -> Self {
+        Self::Proof(error)
+    }
+}
+
+impl From<MerkleTreeError> for SdkError {
+    fn from(error: MerkleTreeError) -> Self {
+        Self::Program(error)
+    }
+}
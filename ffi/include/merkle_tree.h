@@ -0,0 +1,59 @@
+/* C API of merkle-tree-ffi: root computation and proof verification as the on-chain program
+ * runs them. Link against libmerkle_tree_ffi (cdylib or staticlib). Hashes are 32 bytes. */
+
+#ifndef MERKLE_TREE_H
+#define MERKLE_TREE_H
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* Success, or a valid proof. */
+#define MERKLE_TREE_OK 0
+/* Proof doesn't verify. */
+#define MERKLE_TREE_INVALID_PROOF 1
+/* A required pointer is null. */
+#define MERKLE_TREE_NULL_POINTER -1
+/* Hash algorithm isn't one of MERKLE_TREE_HASH_*. */
+#define MERKLE_TREE_INVALID_HASH_ALGO -2
+
+#define MERKLE_TREE_HASH_SHA256 0
+#define MERKLE_TREE_HASH_KECCAK256 1
+#define MERKLE_TREE_HASH_POSEIDON 2
+#define MERKLE_TREE_HASH_BLAKE3 3
+#define MERKLE_TREE_HASH_SHA256D 4
+
+/* Hashing scheme of a tree, the options it was created with. */
+typedef struct MerkleTreeHasher {
+    uint8_t hash_algo;
+    bool domain_separation;
+    bool ordered;
+    bool left_balanced;
+    bool zero_padded;
+} MerkleTreeHasher;
+
+/* Write the root of leaves_count leaves to root_out, the zero hash for no leaves. */
+int32_t merkle_tree_compute_root(const MerkleTreeHasher *tree_hasher,
+                                 const uint8_t (*leaves)[32], size_t leaves_count,
+                                 uint8_t (*root_out)[32]);
+
+/* Verify proof of proof_len hashes for leaf at leaf_index of a tree of leaves_count leaves
+ * against root. Returns MERKLE_TREE_OK if it verifies, MERKLE_TREE_INVALID_PROOF if not. */
+int32_t merkle_tree_verify_proof(const MerkleTreeHasher *tree_hasher, const uint8_t (*root)[32],
+                                 uint32_t leaves_count, uint32_t leaf_index,
+                                 const uint8_t (*leaf)[32], const uint8_t (*proof)[32],
+                                 size_t proof_len);
+
+/* Write the leaf hash of data_len bytes of data to hash_out, as InsertValue hashes values. */
+int32_t merkle_tree_hash_value(const MerkleTreeHasher *tree_hasher, const uint8_t *data,
+                               size_t data_len, uint8_t (*hash_out)[32]);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* MERKLE_TREE_H */
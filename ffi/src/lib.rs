@@ -0,0 +1,164 @@
+//! C API over the tree logic the program runs, so embedded verifiers and other languages link
+//! against the exact same root computation and proof verification. See
+//! `include/merkle_tree.h` for the C declarations.
+
+use merkle_tree_core::{
+    tree::compute_tree_root,
+    verify::{compute_audit_root, compute_root_at},
+};
+use merkle_tree_program::hash::{HashAlgo, TreeHasher};
+use std::slice;
+
+/// Success, or a valid proof.
+pub const MERKLE_TREE_OK: i32 = 0;
+/// Proof doesn't verify.
+pub const MERKLE_TREE_INVALID_PROOF: i32 = 1;
+/// A required pointer is null.
+pub const MERKLE_TREE_NULL_POINTER: i32 = -1;
+/// Hash algorithm isn't a `HashAlgo` discriminant.
+pub const MERKLE_TREE_INVALID_HASH_ALGO: i32 = -2;
+
+/// Hashing scheme of a tree, as `TreeHasher` with `hash_algo` its `HashAlgo` discriminant.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct MerkleTreeHasher {
+    pub hash_algo: u8,
+    pub domain_separation: bool,
+    pub ordered: bool,
+    pub left_balanced: bool,
+    pub zero_padded: bool,
+}
+
+impl TryFrom<MerkleTreeHasher> for TreeHasher {
+    type Error = i32;
+
+    fn try_from(tree_hasher: MerkleTreeHasher) -> Result<Self, i32> {
+        let hash_algo = borsh::from_slice::<HashAlgo>(&[tree_hasher.hash_algo])
+            .map_err(|_| MERKLE_TREE_INVALID_HASH_ALGO)?;
+        Ok(Self {
+            hash_algo,
+            domain_separation: tree_hasher.domain_separation,
+            ordered: tree_hasher.ordered,
+            left_balanced: tree_hasher.left_balanced,
+            zero_padded: tree_hasher.zero_padded,
+        })
+    }
+}
+
+/// Write the root of `leaves_count` leaves at `leaves` to `root_out`, the zero hash for no
+/// leaves.
+///
+/// # Safety
+///
+/// `tree_hasher` and `root_out` must be valid pointers, `leaves` must point to
+/// `leaves_count` hashes unless `leaves_count` is 0.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn merkle_tree_compute_root(
+    tree_hasher: *const MerkleTreeHasher,
+    leaves: *const [u8; 32],
+    leaves_count: usize,
+    root_out: *mut [u8; 32],
+) -> i32 {
+    if tree_hasher.is_null() || root_out.is_null() {
+        return MERKLE_TREE_NULL_POINTER;
+    }
+    let tree_hasher = match TreeHasher::try_from(unsafe { *tree_hasher }) {
+        Ok(tree_hasher) => tree_hasher,
+        Err(code) => return code,
+    };
+    let Some(leaves) = (unsafe { hashes(leaves, leaves_count) }) else {
+        return MERKLE_TREE_NULL_POINTER;
+    };
+
+    unsafe { *root_out = compute_tree_root(&tree_hasher, leaves) };
+    MERKLE_TREE_OK
+}
+
+/// Verify `proof` of `proof_len` hashes for `leaf` at `leaf_index` of a tree of
+/// `leaves_count` leaves against `root`, as the program checks a full proof: audit paths in
+/// left-balanced trees, nodes ordered by `leaf_index` only in ordered trees. Returns
+/// `MERKLE_TREE_OK` if it verifies, `MERKLE_TREE_INVALID_PROOF` if not.
+///
+/// # Safety
+///
+/// `tree_hasher`, `root` and `leaf` must be valid pointers, `proof` must point to
+/// `proof_len` hashes unless `proof_len` is 0.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn merkle_tree_verify_proof(
+    tree_hasher: *const MerkleTreeHasher,
+    root: *const [u8; 32],
+    leaves_count: u32,
+    leaf_index: u32,
+    leaf: *const [u8; 32],
+    proof: *const [u8; 32],
+    proof_len: usize,
+) -> i32 {
+    if tree_hasher.is_null() || root.is_null() || leaf.is_null() {
+        return MERKLE_TREE_NULL_POINTER;
+    }
+    let tree_hasher = match TreeHasher::try_from(unsafe { *tree_hasher }) {
+        Ok(tree_hasher) => tree_hasher,
+        Err(code) => return code,
+    };
+    let Some(proof) = (unsafe { hashes(proof, proof_len) }) else {
+        return MERKLE_TREE_NULL_POINTER;
+    };
+    let (root, leaf) = unsafe { (&*root, &*leaf) };
+
+    let valid = leaf_index < leaves_count
+        && match tree_hasher.left_balanced {
+            true => {
+                compute_audit_root(
+                    &tree_hasher,
+                    leaves_count as usize,
+                    leaf_index as usize,
+                    leaf,
+                    proof,
+                ) == Some(*root)
+            }
+            false => &compute_root_at(&tree_hasher, leaf_index as usize, leaf, proof) == root,
+        };
+    match valid {
+        true => MERKLE_TREE_OK,
+        false => MERKLE_TREE_INVALID_PROOF,
+    }
+}
+
+/// Write the leaf hash of `data_len` bytes at `data` to `hash_out`, as `InsertValue` hashes
+/// values on-chain.
+///
+/// # Safety
+///
+/// `tree_hasher` and `hash_out` must be valid pointers, `data` must point to `data_len`
+/// bytes unless `data_len` is 0.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn merkle_tree_hash_value(
+    tree_hasher: *const MerkleTreeHasher,
+    data: *const u8,
+    data_len: usize,
+    hash_out: *mut [u8; 32],
+) -> i32 {
+    if tree_hasher.is_null() || hash_out.is_null() || (data.is_null() && data_len > 0) {
+        return MERKLE_TREE_NULL_POINTER;
+    }
+    let tree_hasher = match TreeHasher::try_from(unsafe { *tree_hasher }) {
+        Ok(tree_hasher) => tree_hasher,
+        Err(code) => return code,
+    };
+    let data = match data_len {
+        0 => &[],
+        _ => unsafe { slice::from_raw_parts(data, data_len) },
+    };
+
+    unsafe { *hash_out = tree_hasher.hash_value(data) };
+    MERKLE_TREE_OK
+}
+
+/// `len` hashes at `ptr`, `None` if `ptr` is null while `len` isn't 0.
+unsafe fn hashes<'a>(ptr: *const [u8; 32], len: usize) -> Option<&'a [[u8; 32]]> {
+    match (ptr.is_null(), len) {
+        (_, 0) => Some(&[]),
+        (true, _) => None,
+        (false, _) => Some(unsafe { slice::from_raw_parts(ptr, len) }),
+    }
+}
@@ -0,0 +1,259 @@
+//! Node.js bindings of the tree logic and instruction builders, built with napi-rs. Roots,
+//! proofs and instruction data come from the same code the program and the Rust client run,
+//! so TypeScript backends get byte-identical results. Hashes are 32 bytes `Buffer`s, keys
+//! base58 strings and instructions plain objects `@solana/web3.js` `TransactionInstruction`
+//! takes once keys are wrapped in `PublicKey`.
+
+use merkle_tree_client::{
+    instruction::{insert_leaf_ix, insert_value_ix, verify_leaf_ix, with_leaf_page},
+    tree::{self, MerkleTree as ClientMerkleTree},
+};
+use merkle_tree_program::hash::{HashAlgo, TreeHasher};
+use napi::{Error, Result, bindgen_prelude::Buffer};
+use napi_derive::napi;
+use solana_program::{instruction::Instruction, pubkey::Pubkey};
+use std::str::FromStr;
+
+/// Hashing scheme of a tree, see `TreeHasher`. Unset options are off, the hash algorithm
+/// defaults to `sha256`.
+#[napi(object)]
+pub struct TreeHasherOptions {
+    pub hash_algo: Option<String>,
+    pub domain_separation: Option<bool>,
+    pub ordered: Option<bool>,
+    pub left_balanced: Option<bool>,
+    pub zero_padded: Option<bool>,
+}
+
+#[napi(object)]
+pub struct AccountMeta {
+    pub pubkey: String,
+    pub is_signer: bool,
+    pub is_writable: bool,
+}
+
+#[napi(object)]
+pub struct TransactionInstruction {
+    pub program_id: String,
+    pub keys: Vec<AccountMeta>,
+    pub data: Buffer,
+}
+
+/// Tree built from its leaves, see `tree::MerkleTree`.
+#[napi]
+pub struct MerkleTree(ClientMerkleTree);
+
+#[napi]
+impl MerkleTree {
+    #[napi(constructor)]
+    pub fn new(leaves: Vec<Buffer>, tree_hasher: Option<TreeHasherOptions>) -> Result<Self> {
+        let leaves = leaves
+            .iter()
+            .map(|leaf| to_hash(leaf))
+            .collect::<Result<_>>()?;
+        Ok(Self(ClientMerkleTree::new(
+            to_tree_hasher(tree_hasher)?,
+            leaves,
+        )))
+    }
+
+    #[napi(getter)]
+    pub fn length(&self) -> u32 {
+        self.0.leaves().len() as u32
+    }
+
+    #[napi]
+    pub fn root(&self) -> Buffer {
+        self.0.root().to_vec().into()
+    }
+
+    #[napi]
+    pub fn leaves(&self) -> Vec<Buffer> {
+        to_buffers(self.0.leaves())
+    }
+
+    #[napi]
+    pub fn append(&mut self, leaf: Buffer) -> Result<()> {
+        self.0.append(to_hash(&leaf)?);
+        Ok(())
+    }
+
+    #[napi]
+    pub fn set_leaf(&mut self, leaf_index: u32, leaf: Buffer) -> Result<()> {
+        self.0
+            .set_leaf(leaf_index, to_hash(&leaf)?)
+            .map_err(|err| Error::from_reason(err.to_string()))
+    }
+
+    /// Index of the first leaf equal to `leaf`, `null` if there is none.
+    #[napi]
+    pub fn leaf_index(&self, leaf: Buffer) -> Result<Option<u32>> {
+        Ok(self.0.leaf_index(&to_hash(&leaf)?))
+    }
+
+    #[napi]
+    pub fn proof(&self, leaf_index: u32) -> Result<Vec<Buffer>> {
+        self.0
+            .proof(leaf_index)
+            .map(|proof| to_buffers(&proof))
+            .map_err(|err| Error::from_reason(err.to_string()))
+    }
+
+    #[napi]
+    pub fn verify(&self, leaf_index: u32, leaf: Buffer, proof: Vec<Buffer>) -> Result<bool> {
+        Ok(self
+            .0
+            .verify(leaf_index, &to_hash(&leaf)?, &to_hashes(&proof)?))
+    }
+}
+
+/// See `tree::verify_proof`.
+#[napi]
+pub fn verify_proof(
+    tree_hasher: Option<TreeHasherOptions>,
+    root: Buffer,
+    leaves_count: u32,
+    leaf_index: u32,
+    leaf: Buffer,
+    proof: Vec<Buffer>,
+) -> Result<bool> {
+    Ok(tree::verify_proof(
+        &to_tree_hasher(tree_hasher)?,
+        &to_hash(&root)?,
+        leaves_count,
+        leaf_index,
+        &to_hash(&leaf)?,
+        &to_hashes(&proof)?,
+    ))
+}
+
+/// Leaf hash of raw `data`, as `InsertValue` hashes it on-chain.
+#[napi]
+pub fn hash_value(tree_hasher: Option<TreeHasherOptions>, data: Buffer) -> Result<Buffer> {
+    Ok(to_tree_hasher(tree_hasher)?
+        .hash_value(&data)
+        .to_vec()
+        .into())
+}
+
+/// See `instruction::insert_leaf_ix`. Trees with leaf pages pass the leaves count the leaf
+/// lands at as `leaf_index`, see `instruction::with_leaf_page`.
+#[napi]
+pub fn insert_leaf_instruction(
+    program_id: String,
+    payer: String,
+    hash: Buffer,
+    expected_root: Option<Buffer>,
+    leaf_index: Option<u32>,
+) -> Result<TransactionInstruction> {
+    let instruction = insert_leaf_ix(
+        &to_pubkey(&program_id)?,
+        &to_pubkey(&payer)?,
+        to_hash(&hash)?,
+        expected_root.as_deref().map(to_hash).transpose()?,
+    );
+    Ok(to_instruction(with_leaf_index(instruction, leaf_index)))
+}
+
+/// See `instruction::insert_value_ix` and `insert_leaf_instruction`.
+#[napi]
+pub fn insert_value_instruction(
+    program_id: String,
+    payer: String,
+    data: Buffer,
+    expected_root: Option<Buffer>,
+    leaf_index: Option<u32>,
+) -> Result<TransactionInstruction> {
+    let instruction = insert_value_ix(
+        &to_pubkey(&program_id)?,
+        &to_pubkey(&payer)?,
+        data.to_vec(),
+        expected_root.as_deref().map(to_hash).transpose()?,
+    );
+    Ok(to_instruction(with_leaf_index(instruction, leaf_index)))
+}
+
+/// See `instruction::verify_leaf_ix`.
+#[napi]
+pub fn verify_leaf_instruction(
+    program_id: String,
+    leaf_index: u32,
+    leaf: Buffer,
+    proof: Vec<Buffer>,
+) -> Result<TransactionInstruction> {
+    Ok(to_instruction(verify_leaf_ix(
+        &to_pubkey(&program_id)?,
+        leaf_index,
+        to_hash(&leaf)?,
+        to_hashes(&proof)?,
+    )))
+}
+
+fn with_leaf_index(instruction: Instruction, leaf_index: Option<u32>) -> Instruction {
+    match leaf_index {
+        Some(leaf_index) => with_leaf_page(instruction, leaf_index),
+        None => instruction,
+    }
+}
+
+fn to_tree_hasher(options: Option<TreeHasherOptions>) -> Result<TreeHasher> {
+    let Some(options) = options else {
+        return Ok(TreeHasher::default());
+    };
+    let hash_algo = match options.hash_algo.as_deref().map(str::to_ascii_lowercase) {
+        None => HashAlgo::Sha256,
+        Some(hash_algo) => match hash_algo.as_str() {
+            "sha256" => HashAlgo::Sha256,
+            "keccak256" => HashAlgo::Keccak256,
+            "poseidon" => HashAlgo::Poseidon,
+            "blake3" => HashAlgo::Blake3,
+            "sha256d" => HashAlgo::Sha256d,
+            _ => {
+                return Err(Error::from_reason(format!(
+                    "unknown hash algo `{hash_algo}`"
+                )));
+            }
+        },
+    };
+
+    Ok(TreeHasher {
+        hash_algo,
+        domain_separation: options.domain_separation.unwrap_or_default(),
+        ordered: options.ordered.unwrap_or_default(),
+        left_balanced: options.left_balanced.unwrap_or_default(),
+        zero_padded: options.zero_padded.unwrap_or_default(),
+    })
+}
+
+fn to_instruction(instruction: Instruction) -> TransactionInstruction {
+    TransactionInstruction {
+        program_id: instruction.program_id.to_string(),
+        keys: instruction
+            .accounts
+            .into_iter()
+            .map(|account| AccountMeta {
+                pubkey: account.pubkey.to_string(),
+                is_signer: account.is_signer,
+                is_writable: account.is_writable,
+            })
+            .collect(),
+        data: instruction.data.into(),
+    }
+}
+
+fn to_pubkey(pubkey: &str) -> Result<Pubkey> {
+    Pubkey::from_str(pubkey).map_err(|err| Error::from_reason(format!("{pubkey}: {err}")))
+}
+
+fn to_hash(hash: &[u8]) -> Result<[u8; 32]> {
+    hash.try_into()
+        .map_err(|_| Error::from_reason(format!("expected 32 bytes, got {}", hash.len())))
+}
+
+fn to_hashes(hashes: &[Buffer]) -> Result<Vec<[u8; 32]>> {
+    hashes.iter().map(|hash| to_hash(hash)).collect()
+}
+
+fn to_buffers(hashes: &[[u8; 32]]) -> Vec<Buffer> {
+    hashes.iter().map(|hash| hash.to_vec().into()).collect()
+}
@@ -0,0 +1,165 @@
+//! Timelocked configuration changes. Trees with a config timelock reject direct config
+//! instructions, the authority queues them with `QueueConfig` instead, in a PDA seeded by
+//! the merkle state key, see `pda::find_pending_config_pda`. `ApplyPendingConfig` applies
+//! them once the timelock passed and `CancelPendingConfig` drops them, so integrators see
+//! every change coming at least `timelock` slots ahead.
+
+use crate::{error::MerkleTreeError, events::EventVerbosity, state::MerkleStateAccount};
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_program::{program_error::ProgramError, pubkey::Pubkey};
+
+/// Config change of a direct config instruction, e.g. `SetRateLimit`.
+#[derive(Debug, Clone, PartialEq, Eq, BorshSerialize, BorshDeserialize)]
+pub enum ConfigChange {
+    RejectDuplicates(bool),
+    RecordLeafMetadata(bool),
+    EventVerbosity(EventVerbosity),
+    Permissioned(bool),
+    AddInserter(Pubkey),
+    RemoveInserter(Pubkey),
+    RateLimit {
+        max_inserts_per_slot: u32,
+        max_inserts_per_epoch: u32,
+    },
+    MaxLeaves(u32),
+    RejectSentinelLeaves(bool),
+    /// Slots later changes are delayed by, 0 allows direct config instructions again.
+    ConfigTimelock(u64),
+    GrowthPercent(u16),
+    CanopyDepth(u8),
+    LazyRoot(bool),
+}
+
+impl ConfigChange {
+    /// Apply the change to `merkle_state`, checked as by its direct instruction. Resizing
+    /// the account is up to the caller.
+    pub fn apply(&self, merkle_state: &mut MerkleStateAccount) -> Result<(), ProgramError> {
+        match *self {
+            Self::RejectDuplicates(reject_duplicates) => {
+                if reject_duplicates && merkle_state.is_frontier_mode() {
+                    return Err(MerkleTreeError::UnsupportedInFrontierMode.into());
+                }
+                merkle_state.set_reject_duplicates(reject_duplicates);
+            }
+            Self::RecordLeafMetadata(record_leaf_metadata) => {
+                merkle_state.set_record_leaf_metadata(record_leaf_metadata)
+            }
+            Self::EventVerbosity(event_verbosity) => {
+                merkle_state.set_event_verbosity(event_verbosity)
+            }
+            Self::Permissioned(permissioned) => merkle_state.set_permissioned(permissioned),
+            Self::AddInserter(inserter) => {
+                merkle_state.add_inserter(&inserter);
+            }
+            Self::RemoveInserter(inserter) => {
+                if !merkle_state.remove_inserter(&inserter) {
+                    return Err(MerkleTreeError::InvalidInserter.into());
+                }
+            }
+            Self::RateLimit {
+                max_inserts_per_slot,
+                max_inserts_per_epoch,
+            } => merkle_state.set_rate_limit(max_inserts_per_slot, max_inserts_per_epoch),
+            Self::MaxLeaves(max_leaves) => merkle_state.set_max_leaves(max_leaves),
+            Self::RejectSentinelLeaves(reject_sentinel_leaves) => {
+                merkle_state.set_reject_sentinel_leaves(reject_sentinel_leaves)
+            }
+            Self::ConfigTimelock(config_timelock) => {
+                merkle_state.set_config_timelock(config_timelock)
+            }
+            Self::GrowthPercent(growth_percent) => {
+                if growth_percent != 0 && merkle_state.is_frontier_mode() {
+                    return Err(MerkleTreeError::UnsupportedInFrontierMode.into());
+                }
+                merkle_state.set_growth_percent(growth_percent);
+            }
+            Self::CanopyDepth(canopy_depth) => {
+                if merkle_state.is_frontier_mode() {
+                    return Err(MerkleTreeError::UnsupportedInFrontierMode.into());
+                }
+                if canopy_depth > MerkleStateAccount::MAX_CANOPY_DEPTH {
+                    return Err(ProgramError::InvalidArgument);
+                }
+                merkle_state.set_canopy_depth(canopy_depth);
+            }
+            Self::LazyRoot(lazy_root) => {
+                if lazy_root && merkle_state.is_frontier_mode() {
+                    return Err(MerkleTreeError::UnsupportedInFrontierMode.into());
+                }
+                if !lazy_root && merkle_state.is_root_dirty() {
+                    return Err(MerkleTreeError::RootNotCommitted.into());
+                }
+                merkle_state.set_lazy_root(lazy_root);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, BorshSerialize, BorshDeserialize)]
+pub struct PendingConfig {
+    pub change: ConfigChange,
+    /// First slot the change can be applied at.
+    pub effective_slot: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, BorshSerialize, BorshDeserialize)]
+pub struct PendingConfigAccount {
+    /// Always `Self::DISCRIMINATOR`.
+    discriminator: [u8; 8],
+    /// Queued changes, oldest first.
+    pub changes: Vec<PendingConfig>,
+}
+
+impl PendingConfigAccount {
+    /// First 8 bytes of `sha256("account:PendingConfigAccount")`.
+    pub const DISCRIMINATOR: [u8; 8] = [183, 247, 107, 79, 0, 22, 242, 141];
+
+    /// Max queued changes.
+    pub const MAX_CHANGES: usize = 16;
+
+    pub fn new() -> Self {
+        Self {
+            discriminator: Self::DISCRIMINATOR,
+            changes: Vec::new(),
+        }
+    }
+
+    /// Deserialize pending config account `data`, rejecting other accounts.
+    pub fn unpack(data: &[u8]) -> Result<Self, ProgramError> {
+        if data.get(..8) != Some(&Self::DISCRIMINATOR[..]) {
+            return Err(MerkleTreeError::InvalidPendingConfig.into());
+        }
+        Self::try_from_slice(data).map_err(|_| MerkleTreeError::InvalidPendingConfig.into())
+    }
+
+    /// Queue `change` effective at `effective_slot`, `false` if the queue is full.
+    pub fn push(&mut self, change: ConfigChange, effective_slot: u64) -> bool {
+        if self.changes.len() >= Self::MAX_CHANGES {
+            return false;
+        }
+
+        self.changes.push(PendingConfig {
+            change,
+            effective_slot,
+        });
+        true
+    }
+
+    /// Remove changes effective at `slot` and return them, oldest first.
+    pub fn take_due(&mut self, slot: u64) -> Vec<ConfigChange> {
+        let (due, pending) = std::mem::take(&mut self.changes)
+            .into_iter()
+            .partition::<Vec<_>, _>(|pending| pending.effective_slot <= slot);
+        self.changes = pending;
+
+        due.into_iter().map(|pending| pending.change).collect()
+    }
+}
+
+impl Default for PendingConfigAccount {
+    fn default() -> Self {
+        Self::new()
+    }
+}
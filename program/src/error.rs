@@ -0,0 +1 @@
+pub use merkle_tree_errors::MerkleTreeError;
@@ -0,0 +1,85 @@
+use borsh::{BorshDeserialize, BorshSerialize};
+
+/// Anchor event CPI instruction tag(`0x1d9acb512ea545e4`, little-endian).
+pub const EVENT_IX_TAG_LE: [u8; 8] = [228, 69, 165, 46, 81, 203, 154, 29];
+
+/// Event emitted via self-CPI, instruction data is
+/// `EVENT_IX_TAG_LE + DISCRIMINATOR + borsh(event)` as in Anchor `emit_cpi!`.
+pub trait Event: BorshSerialize {
+    /// First 8 bytes of `sha256("event:<Name>")`.
+    const DISCRIMINATOR: [u8; 8];
+
+    fn to_instruction_data(&self) -> Vec<u8> {
+        let mut instruction_data = EVENT_IX_TAG_LE.to_vec();
+        instruction_data.extend_from_slice(&Self::DISCRIMINATOR);
+        instruction_data.extend_from_slice(&borsh::to_vec(self).expect("Can't serialize event"));
+
+        instruction_data
+    }
+}
+
+/// Events a tree emits, set by its authority to trade indexing detail for compute.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, BorshSerialize, BorshDeserialize)]
+pub enum EventVerbosity {
+    /// No events.
+    None,
+    /// `RootUpdated` only.
+    RootOnly,
+    /// `LeafInserted` and `RootUpdated`.
+    #[default]
+    Full,
+}
+
+impl EventVerbosity {
+    pub fn emits_root(&self) -> bool {
+        *self != Self::None
+    }
+
+    pub fn emits_changelog(&self) -> bool {
+        *self == Self::Full
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, BorshSerialize, BorshDeserialize)]
+pub struct LeafInserted {
+    pub leaf_index: u32,
+    pub leaf: [u8; 32],
+}
+
+impl Event for LeafInserted {
+    const DISCRIMINATOR: [u8; 8] = [89, 243, 212, 8, 215, 191, 187, 152];
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, BorshSerialize, BorshDeserialize)]
+pub struct RootUpdated {
+    pub root: [u8; 32],
+    pub leaves_count: u32,
+}
+
+impl Event for RootUpdated {
+    const DISCRIMINATOR: [u8; 8] = [94, 53, 22, 128, 141, 113, 98, 231];
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MerkleTreeEvent {
+    LeafInserted(LeafInserted),
+    RootUpdated(RootUpdated),
+}
+
+impl MerkleTreeEvent {
+    /// Decode event from self-CPI inner instruction data, `None` for other instructions.
+    pub fn decode(instruction_data: &[u8]) -> Option<Self> {
+        let event_data = instruction_data.strip_prefix(&EVENT_IX_TAG_LE)?;
+        let (discriminator, mut payload) = event_data.split_first_chunk::<8>()?;
+
+        match *discriminator {
+            LeafInserted::DISCRIMINATOR => LeafInserted::deserialize(&mut payload)
+                .ok()
+                .map(Self::LeafInserted),
+            RootUpdated::DISCRIMINATOR => RootUpdated::deserialize(&mut payload)
+                .ok()
+                .map(Self::RootUpdated),
+            _ => None,
+        }
+    }
+}
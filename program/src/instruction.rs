@@ -1,36 +1,817 @@
+use crate::{config::ConfigChange, error::MerkleTreeError, events::EventVerbosity, hash::HashAlgo};
 use borsh::{BorshDeserialize, BorshSerialize};
-use solana_program::program_error::ProgramError;
+use solana_program::{program_error::ProgramError, pubkey::Pubkey};
 
 #[derive(Debug, BorshSerialize, BorshDeserialize)]
 pub enum MerkleTreeInstruction {
-    InsertLeaf { hash: [u8; 32] },
+    /// Insert leaf `hash`. With `expected_root` set, inserts fail with `RootMismatch` unless
+    /// it's the current root, so interleaved writes are detected.
+    InsertLeaf {
+        hash: [u8; 32],
+        expected_root: Option<[u8; 32]>,
+    },
+    /// Propose new tree authority, must be signed by the current one.
+    ProposeAuthority { new_authority: Pubkey },
+    /// Accept proposed authority, must be signed by the pending one.
+    AcceptAuthority,
+    /// Reject instructions changing leaves, the root or where leaves are stored until resumed,
+    /// must be signed by the authority. Settings, authority changes and `GrowTree`, which only
+    /// preallocates space, stay allowed.
+    PauseTree,
+    /// Resume paused tree, must be signed by the authority.
+    ResumeTree,
+    /// Insert leaf hashed on-chain from raw `data`, see `utils::hash_value`.
+    InsertValue {
+        data: Vec<u8>,
+        expected_root: Option<[u8; 32]>,
+    },
+    /// Verify `leaf` inclusion, `proof` may be truncated at the cached canopy.
+    VerifyLeaf {
+        leaf_index: u32,
+        leaf: [u8; 32],
+        proof: Vec<[u8; 32]>,
+    },
+    /// Replace `old_leaf` with `new_leaf`, must be signed by the authority.
+    /// `proof` may target any root in the recent root history, in frontier mode any root in
+    /// the changelog it's fast-forwarded over.
+    ReplaceLeaf {
+        leaf_index: u32,
+        old_leaf: [u8; 32],
+        new_leaf: [u8; 32],
+        proof: Vec<[u8; 32]>,
+    },
+    /// Verify `(leaf_index, leaf)` pairs(sorted by index) with one deduplicated multiproof.
+    VerifyMultiProof {
+        leaves: Vec<(u32, [u8; 32])>,
+        proof: Vec<[u8; 32]>,
+    },
+    /// Link leaves snapshot stored at `uri` to the tree, must be signed by the authority.
+    /// `root` must be the current root and `content_hash` must match the current leaves.
+    SetArchiveUri {
+        root: [u8; 32],
+        content_hash: [u8; 32],
+        uri: String,
+    },
+    /// Set current root hash as return data, see `return_data::ReturnData`.
+    GetRootHash,
+    /// Toggle rejection of leaves already present in the tree, must be signed by the authority.
+    SetRejectDuplicates { reject_duplicates: bool },
+    /// Permanently seal the tree recording its final root, leaves count and slot,
+    /// must be signed by the authority.
+    FinalizeTree,
+    /// Verify the tree at `old_root` with `old_leaves_count` leaves is a prefix of the current
+    /// one. `proof` is the full proof of the last old leaf `leaf` in the current tree.
+    VerifyConsistency {
+        old_root: [u8; 32],
+        old_leaves_count: u32,
+        leaf: [u8; 32],
+        proof: Vec<[u8; 32]>,
+    },
+    /// Permanently drop stored leaves keeping only the tree frontier, refunding freed rent.
+    /// Must be signed by the authority.
+    EnableFrontierMode,
+    /// Set which events the tree emits, must be signed by the authority.
+    SetEventVerbosity { event_verbosity: EventVerbosity },
+    /// Insert leaf which `SweepExpired` tombstones once `expires_at`(unix timestamp) passed.
+    InsertExpiringLeaf {
+        hash: [u8; 32],
+        expires_at: i64,
+        expected_root: Option<[u8; 32]>,
+    },
+    /// Tombstone expired leaves, permissionless. The signer gets
+    /// `MerkleStateAccount::SWEEP_BOUNTY_LAMPORTS` per swept leaf from the tree treasury.
+    SweepExpired,
+    /// Toggle recording slot and inserter of newly inserted leaves, must be signed by the
+    /// authority.
+    SetRecordLeafMetadata { record_leaf_metadata: bool },
+    /// Permanently move stored leaves to leaf pages keeping only the tree frontier, must be
+    /// signed by the authority. Writes `pages_count` pages from `first_page`, which must be
+    /// the next page to write, so large trees convert over several calls. The last call
+    /// drops stored leaves, inserts and replacements then take the leaf page as well.
+    EnableLeafPages { first_page: u32, pages_count: u32 },
+    /// Create the tree with its first leaf `hash`, hashing nodes with `hash_algo`, prefixed by
+    /// node kind with `domain_separation`, in tree order if `ordered`, promoting nodes
+    /// without sibling if `left_balanced` or pairing them with empty subtrees if
+    /// `zero_padded`, see `TreeHasher`. Rent for `leaf_capacity` leaves is paid up front,
+    /// inserts then fill the preallocated space and fail once the tree is full. A
+    /// `leaf_capacity` of 0 creates a freely growing tree.
+    InitTree {
+        hash: [u8; 32],
+        leaf_capacity: u32,
+        hash_algo: HashAlgo,
+        domain_separation: bool,
+        ordered: bool,
+        left_balanced: bool,
+        zero_padded: bool,
+    },
+    /// Upgrade the merkle state account of an older layout version in place, keeping leaves
+    /// and root. Permissionless, the signer pays rent for the grown account.
+    MigrateState,
+    /// Create or overwrite the tree metadata account, must be signed by the authority.
+    SetMetadata {
+        name: String,
+        description: String,
+        uri: String,
+    },
+    /// Allow `inserter` to insert while the tree is permissioned, must be signed by the
+    /// authority which pays rent for the grown account.
+    AddInserter { inserter: Pubkey },
+    /// Disallow `inserter`, refunding freed rent. Must be signed by the authority.
+    RemoveInserter { inserter: Pubkey },
+    /// Toggle accepting inserts only from allowed inserters, see `AddInserter`. Must be
+    /// signed by the authority.
+    SetPermissioned { permissioned: bool },
+    /// Create or overwrite the tree multisig requiring `threshold` of `signers`, see
+    /// `multisig`. Must be signed by the authority, a separate signer pays rent. Hand the
+    /// tree to the multisig with `ProposeAuthority` and `AcceptAuthority`.
+    SetMultisig { threshold: u8, signers: Vec<Pubkey> },
+    /// Limit inserts of each payer per slot and per epoch, 0 lifts a limit. Must be signed by
+    /// the authority.
+    SetRateLimit {
+        max_inserts_per_slot: u32,
+        max_inserts_per_epoch: u32,
+    },
+    /// Cap the tree at `max_leaves` leaves, inserts then fail with `TreeFull`. 0 lifts the
+    /// cap. Must be signed by the authority.
+    SetMaxLeaves { max_leaves: u32 },
+    /// Toggle rejection of the zero hash and empty subtree roots as inserted or replacing
+    /// leaves, they then fail with `SentinelLeaf`. Must be signed by the authority.
+    SetRejectSentinelLeaves { reject_sentinel_leaves: bool },
+    /// Delay config changes by `config_timelock` slots, 0 applies them directly again. Must
+    /// be signed by the authority, and be queued itself once the tree has a timelock.
+    SetConfigTimelock { config_timelock: u64 },
+    /// Queue `change` of a timelocked tree, see `config`. Must be signed by the authority, a
+    /// separate signer pays rent of the pending config account.
+    QueueConfig { change: ConfigChange },
+    /// Apply queued changes whose timelock passed, must be signed by the authority which
+    /// pays rent for the grown account.
+    ApplyPendingConfig,
+    /// Drop all queued changes, must be signed by the authority which gets the rent back.
+    CancelPendingConfig,
+    /// Add `growth_percent` of the leaves count in spare leaves whenever the account grows,
+    /// so reallocs are amortized over inserts. 0 grows it by exactly the bytes written and
+    /// refunds spare bytes to the authority, which must sign.
+    SetGrowthPercent { growth_percent: u16 },
+    /// Grow the account of a tree keeping spare bytes towards `target_len` bytes, by at most
+    /// `MAX_PERMITTED_DATA_INCREASE` per instruction. Repeat it, e.g. in one transaction, to
+    /// grow further ahead of writes too large for a single realloc. Any signer pays rent.
+    GrowTree { target_len: u32 },
+    /// Cache the top `canopy_depth` levels of the tree, at most
+    /// `MerkleStateAccount::MAX_CANOPY_DEPTH`, so replacing a leaf only rehashes the subtree
+    /// below the canopy holding it. Must be signed by the authority which pays rent for the
+    /// grown account or is refunded.
+    SetCanopyDepth { canopy_depth: u8 },
+    /// Only append leaves on insert, leaving the root to `CommitRoot`, e.g. while bulk loading.
+    /// Proofs, replacements and snapshots fail with `RootNotCommitted` until it's committed.
+    /// Must be signed by the authority, and the root committed before disabling it.
+    SetLazyRoot { lazy_root: bool },
+    /// Recompute the root, canopy and filled subtrees of leaves appended in lazy root mode and
+    /// publish it. Anyone may commit unless the tree is paused, trees already up to date are
+    /// left as is.
+    CommitRoot,
 }
 
 impl MerkleTreeInstruction {
     pub fn pack(&self) -> Vec<u8> {
         match self {
-            Self::InsertLeaf { hash } => {
+            Self::InsertLeaf {
+                hash,
+                expected_root,
+            } => {
                 let mut instruction_data = vec![0u8];
                 instruction_data.extend_from_slice(hash);
+                pack_expected_root(&mut instruction_data, expected_root);
 
                 instruction_data
             }
+            Self::ProposeAuthority { new_authority } => {
+                let mut instruction_data = vec![1u8];
+                instruction_data.extend_from_slice(new_authority.as_ref());
+
+                instruction_data
+            }
+            Self::AcceptAuthority => vec![2u8],
+            Self::PauseTree => vec![3u8],
+            Self::ResumeTree => vec![4u8],
+            Self::InsertValue {
+                data,
+                expected_root,
+            } => {
+                let mut instruction_data = vec![5u8];
+                instruction_data.extend_from_slice(&(data.len() as u32).to_le_bytes());
+                instruction_data.extend_from_slice(data);
+                pack_expected_root(&mut instruction_data, expected_root);
+
+                instruction_data
+            }
+            Self::VerifyLeaf {
+                leaf_index,
+                leaf,
+                proof,
+            } => {
+                let mut instruction_data = vec![6u8];
+                instruction_data.extend_from_slice(&leaf_index.to_le_bytes());
+                instruction_data.extend_from_slice(leaf);
+                pack_proof(&mut instruction_data, proof);
+
+                instruction_data
+            }
+            Self::ReplaceLeaf {
+                leaf_index,
+                old_leaf,
+                new_leaf,
+                proof,
+            } => {
+                let mut instruction_data = vec![7u8];
+                instruction_data.extend_from_slice(&leaf_index.to_le_bytes());
+                instruction_data.extend_from_slice(old_leaf);
+                instruction_data.extend_from_slice(new_leaf);
+                pack_proof(&mut instruction_data, proof);
+
+                instruction_data
+            }
+            Self::VerifyMultiProof { leaves, proof } => {
+                let mut instruction_data = vec![8u8];
+                instruction_data.extend_from_slice(&(leaves.len() as u32).to_le_bytes());
+                for (leaf_index, leaf) in leaves {
+                    instruction_data.extend_from_slice(&leaf_index.to_le_bytes());
+                    instruction_data.extend_from_slice(leaf);
+                }
+                pack_proof(&mut instruction_data, proof);
+
+                instruction_data
+            }
+            Self::SetArchiveUri {
+                root,
+                content_hash,
+                uri,
+            } => {
+                let mut instruction_data = vec![9u8];
+                instruction_data.extend_from_slice(root);
+                instruction_data.extend_from_slice(content_hash);
+                instruction_data.extend_from_slice(&(uri.len() as u32).to_le_bytes());
+                instruction_data.extend_from_slice(uri.as_bytes());
+
+                instruction_data
+            }
+            Self::GetRootHash => vec![10u8],
+            Self::SetRejectDuplicates { reject_duplicates } => {
+                vec![11u8, *reject_duplicates as u8]
+            }
+            Self::FinalizeTree => vec![12u8],
+            Self::VerifyConsistency {
+                old_root,
+                old_leaves_count,
+                leaf,
+                proof,
+            } => {
+                let mut instruction_data = vec![13u8];
+                instruction_data.extend_from_slice(old_root);
+                instruction_data.extend_from_slice(&old_leaves_count.to_le_bytes());
+                instruction_data.extend_from_slice(leaf);
+                pack_proof(&mut instruction_data, proof);
+
+                instruction_data
+            }
+            Self::EnableFrontierMode => vec![14u8],
+            Self::SetEventVerbosity { event_verbosity } => vec![15u8, *event_verbosity as u8],
+            Self::InsertExpiringLeaf {
+                hash,
+                expires_at,
+                expected_root,
+            } => {
+                let mut instruction_data = vec![16u8];
+                instruction_data.extend_from_slice(hash);
+                instruction_data.extend_from_slice(&expires_at.to_le_bytes());
+                pack_expected_root(&mut instruction_data, expected_root);
+
+                instruction_data
+            }
+            Self::SweepExpired => vec![17u8],
+            Self::SetRecordLeafMetadata {
+                record_leaf_metadata,
+            } => vec![18u8, *record_leaf_metadata as u8],
+            Self::EnableLeafPages {
+                first_page,
+                pages_count,
+            } => {
+                let mut instruction_data = vec![19u8];
+                instruction_data.extend_from_slice(&first_page.to_le_bytes());
+                instruction_data.extend_from_slice(&pages_count.to_le_bytes());
+
+                instruction_data
+            }
+            Self::InitTree {
+                hash,
+                leaf_capacity,
+                hash_algo,
+                domain_separation,
+                ordered,
+                left_balanced,
+                zero_padded,
+            } => {
+                let mut instruction_data = vec![20u8];
+                instruction_data.extend_from_slice(hash);
+                instruction_data.extend_from_slice(&leaf_capacity.to_le_bytes());
+                instruction_data.push(*hash_algo as u8);
+                instruction_data.push(*domain_separation as u8);
+                instruction_data.push(*ordered as u8);
+                instruction_data.push(*left_balanced as u8);
+                instruction_data.push(*zero_padded as u8);
+
+                instruction_data
+            }
+            Self::MigrateState => vec![21u8],
+            Self::SetMetadata {
+                name,
+                description,
+                uri,
+            } => {
+                let mut instruction_data = vec![22u8];
+                pack_string(&mut instruction_data, name);
+                pack_string(&mut instruction_data, description);
+                pack_string(&mut instruction_data, uri);
+
+                instruction_data
+            }
+            Self::AddInserter { inserter } => {
+                let mut instruction_data = vec![23u8];
+                instruction_data.extend_from_slice(inserter.as_ref());
+
+                instruction_data
+            }
+            Self::RemoveInserter { inserter } => {
+                let mut instruction_data = vec![24u8];
+                instruction_data.extend_from_slice(inserter.as_ref());
+
+                instruction_data
+            }
+            Self::SetPermissioned { permissioned } => vec![25u8, *permissioned as u8],
+            Self::SetMultisig { threshold, signers } => {
+                let mut instruction_data = vec![26u8, *threshold];
+                instruction_data.extend_from_slice(&(signers.len() as u32).to_le_bytes());
+                signers
+                    .iter()
+                    .for_each(|signer| instruction_data.extend_from_slice(signer.as_ref()));
+
+                instruction_data
+            }
+            Self::SetRateLimit {
+                max_inserts_per_slot,
+                max_inserts_per_epoch,
+            } => {
+                let mut instruction_data = vec![27u8];
+                instruction_data.extend_from_slice(&max_inserts_per_slot.to_le_bytes());
+                instruction_data.extend_from_slice(&max_inserts_per_epoch.to_le_bytes());
+
+                instruction_data
+            }
+            Self::SetMaxLeaves { max_leaves } => {
+                let mut instruction_data = vec![28u8];
+                instruction_data.extend_from_slice(&max_leaves.to_le_bytes());
+
+                instruction_data
+            }
+            Self::SetRejectSentinelLeaves {
+                reject_sentinel_leaves,
+            } => vec![29u8, *reject_sentinel_leaves as u8],
+            Self::SetConfigTimelock { config_timelock } => {
+                let mut instruction_data = vec![30u8];
+                instruction_data.extend_from_slice(&config_timelock.to_le_bytes());
+
+                instruction_data
+            }
+            Self::QueueConfig { change } => {
+                let mut instruction_data = vec![31u8];
+                instruction_data
+                    .extend_from_slice(&borsh::to_vec(change).expect("Can't serialize change"));
+
+                instruction_data
+            }
+            Self::ApplyPendingConfig => vec![32u8],
+            Self::CancelPendingConfig => vec![33u8],
+            Self::SetGrowthPercent { growth_percent } => {
+                let mut instruction_data = vec![34u8];
+                instruction_data.extend_from_slice(&growth_percent.to_le_bytes());
+
+                instruction_data
+            }
+            Self::GrowTree { target_len } => {
+                let mut instruction_data = vec![35u8];
+                instruction_data.extend_from_slice(&target_len.to_le_bytes());
+
+                instruction_data
+            }
+            Self::SetCanopyDepth { canopy_depth } => vec![36u8, *canopy_depth],
+            Self::SetLazyRoot { lazy_root } => vec![37u8, *lazy_root as u8],
+            Self::CommitRoot => vec![38u8],
         }
     }
 
+    /// Unpack instruction `input`, failing with `UnknownInstruction` on an unknown tag,
+    /// `TruncatedInstructionData` if fields or length prefixed vecs run past its end and
+    /// `OversizedInstructionData` on bytes left after the last field. Invalid field values,
+    /// e.g. a bool other than 0 or 1, fail with `InvalidInstructionData`.
     pub fn unpack(input: &[u8]) -> Result<Self, ProgramError> {
-        let (instruction_id, instruction_data) = input
-            .split_first()
-            .ok_or(ProgramError::InvalidInstructionData)?;
+        let (instruction_id, instruction_data) = unpack_u8(input)?;
 
-        match instruction_id {
+        let (instruction, rest) = match instruction_id {
             0 => {
-                let hash: [u8; 32] = instruction_data
-                    .try_into()
-                    .map_err(|_| ProgramError::InvalidInstructionData)?;
-                Ok(Self::InsertLeaf { hash })
+                let (hash, rest) = unpack_hash(instruction_data)?;
+                let (expected_root, rest) = unpack_expected_root(rest)?;
+                (
+                    Self::InsertLeaf {
+                        hash,
+                        expected_root,
+                    },
+                    rest,
+                )
+            }
+            1 => {
+                let (new_authority, rest) = unpack_pubkey(instruction_data)?;
+                (Self::ProposeAuthority { new_authority }, rest)
+            }
+            2 => (Self::AcceptAuthority, instruction_data),
+            3 => (Self::PauseTree, instruction_data),
+            4 => (Self::ResumeTree, instruction_data),
+            5 => {
+                let (data, rest) = unpack_vec(instruction_data, 1)?;
+                let (expected_root, rest) = unpack_expected_root(rest)?;
+                (
+                    Self::InsertValue {
+                        data: data.to_vec(),
+                        expected_root,
+                    },
+                    rest,
+                )
+            }
+            6 => {
+                let (leaf_index, rest) = unpack_u32(instruction_data)?;
+                let (leaf, rest) = unpack_hash(rest)?;
+                let (proof, rest) = unpack_proof(rest)?;
+                (
+                    Self::VerifyLeaf {
+                        leaf_index,
+                        leaf,
+                        proof,
+                    },
+                    rest,
+                )
+            }
+            7 => {
+                let (leaf_index, rest) = unpack_u32(instruction_data)?;
+                let (old_leaf, rest) = unpack_hash(rest)?;
+                let (new_leaf, rest) = unpack_hash(rest)?;
+                let (proof, rest) = unpack_proof(rest)?;
+                (
+                    Self::ReplaceLeaf {
+                        leaf_index,
+                        old_leaf,
+                        new_leaf,
+                        proof,
+                    },
+                    rest,
+                )
+            }
+            8 => {
+                // leaves: leaf index and leaf
+                let (leaves, rest) = unpack_vec(instruction_data, 4 + 32)?;
+                let leaves = leaves
+                    .chunks_exact(4 + 32)
+                    .map(|leaf| {
+                        let (leaf_index, leaf) = leaf.split_at(4);
+                        (
+                            u32::from_le_bytes(leaf_index.try_into().expect("Invalid u32 length")),
+                            leaf.try_into().expect("Invalid leaf length"),
+                        )
+                    })
+                    .collect();
+                let (proof, rest) = unpack_proof(rest)?;
+                (Self::VerifyMultiProof { leaves, proof }, rest)
+            }
+            9 => {
+                let (root, rest) = unpack_hash(instruction_data)?;
+                let (content_hash, rest) = unpack_hash(rest)?;
+                let (uri, rest) = unpack_string(rest)?;
+                (
+                    Self::SetArchiveUri {
+                        root,
+                        content_hash,
+                        uri,
+                    },
+                    rest,
+                )
+            }
+            10 => (Self::GetRootHash, instruction_data),
+            11 => {
+                let (reject_duplicates, rest) = unpack_bool(instruction_data)?;
+                (Self::SetRejectDuplicates { reject_duplicates }, rest)
+            }
+            12 => (Self::FinalizeTree, instruction_data),
+            13 => {
+                let (old_root, rest) = unpack_hash(instruction_data)?;
+                let (old_leaves_count, rest) = unpack_u32(rest)?;
+                let (leaf, rest) = unpack_hash(rest)?;
+                let (proof, rest) = unpack_proof(rest)?;
+                (
+                    Self::VerifyConsistency {
+                        old_root,
+                        old_leaves_count,
+                        leaf,
+                        proof,
+                    },
+                    rest,
+                )
+            }
+            14 => (Self::EnableFrontierMode, instruction_data),
+            15 => {
+                let (event_verbosity, rest) = unpack_u8(instruction_data)?;
+                let event_verbosity = match event_verbosity {
+                    0 => EventVerbosity::None,
+                    1 => EventVerbosity::RootOnly,
+                    2 => EventVerbosity::Full,
+                    _ => return Err(ProgramError::InvalidInstructionData),
+                };
+                (Self::SetEventVerbosity { event_verbosity }, rest)
+            }
+            16 => {
+                let (hash, rest) = unpack_hash(instruction_data)?;
+                let (expires_at, rest) = unpack_u64(rest)?;
+                let (expected_root, rest) = unpack_expected_root(rest)?;
+                (
+                    Self::InsertExpiringLeaf {
+                        hash,
+                        expires_at: expires_at as i64,
+                        expected_root,
+                    },
+                    rest,
+                )
+            }
+            17 => (Self::SweepExpired, instruction_data),
+            18 => {
+                let (record_leaf_metadata, rest) = unpack_bool(instruction_data)?;
+                (
+                    Self::SetRecordLeafMetadata {
+                        record_leaf_metadata,
+                    },
+                    rest,
+                )
+            }
+            19 => {
+                let (first_page, rest) = unpack_u32(instruction_data)?;
+                let (pages_count, rest) = unpack_u32(rest)?;
+                (
+                    Self::EnableLeafPages {
+                        first_page,
+                        pages_count,
+                    },
+                    rest,
+                )
+            }
+            20 => {
+                let (hash, rest) = unpack_hash(instruction_data)?;
+                let (leaf_capacity, rest) = unpack_u32(rest)?;
+                let (hash_algo, rest) = unpack_u8(rest)?;
+                let (domain_separation, rest) = unpack_bool(rest)?;
+                let (ordered, rest) = unpack_bool(rest)?;
+                let (left_balanced, rest) = unpack_bool(rest)?;
+                let (zero_padded, rest) = unpack_bool(rest)?;
+                let hash_algo = match hash_algo {
+                    0 => HashAlgo::Sha256,
+                    1 => HashAlgo::Keccak256,
+                    2 => HashAlgo::Poseidon,
+                    #[cfg(feature = "blake3")]
+                    3 => HashAlgo::Blake3,
+                    4 => HashAlgo::Sha256d,
+                    _ => return Err(ProgramError::InvalidInstructionData),
+                };
+                (
+                    Self::InitTree {
+                        hash,
+                        leaf_capacity,
+                        hash_algo,
+                        domain_separation,
+                        ordered,
+                        left_balanced,
+                        zero_padded,
+                    },
+                    rest,
+                )
+            }
+            21 => (Self::MigrateState, instruction_data),
+            22 => {
+                let (name, rest) = unpack_string(instruction_data)?;
+                let (description, rest) = unpack_string(rest)?;
+                let (uri, rest) = unpack_string(rest)?;
+                (
+                    Self::SetMetadata {
+                        name,
+                        description,
+                        uri,
+                    },
+                    rest,
+                )
+            }
+            23 => {
+                let (inserter, rest) = unpack_pubkey(instruction_data)?;
+                (Self::AddInserter { inserter }, rest)
+            }
+            24 => {
+                let (inserter, rest) = unpack_pubkey(instruction_data)?;
+                (Self::RemoveInserter { inserter }, rest)
+            }
+            25 => {
+                let (permissioned, rest) = unpack_bool(instruction_data)?;
+                (Self::SetPermissioned { permissioned }, rest)
+            }
+            26 => {
+                let (threshold, rest) = unpack_u8(instruction_data)?;
+                let (signers, rest) = unpack_vec(rest, 32)?;
+                let signers = signers
+                    .chunks_exact(32)
+                    .map(|signer| Pubkey::try_from(signer).expect("Invalid signer length"))
+                    .collect();
+                (Self::SetMultisig { threshold, signers }, rest)
+            }
+            27 => {
+                let (max_inserts_per_slot, rest) = unpack_u32(instruction_data)?;
+                let (max_inserts_per_epoch, rest) = unpack_u32(rest)?;
+                (
+                    Self::SetRateLimit {
+                        max_inserts_per_slot,
+                        max_inserts_per_epoch,
+                    },
+                    rest,
+                )
+            }
+            28 => {
+                let (max_leaves, rest) = unpack_u32(instruction_data)?;
+                (Self::SetMaxLeaves { max_leaves }, rest)
+            }
+            29 => {
+                let (reject_sentinel_leaves, rest) = unpack_bool(instruction_data)?;
+                (
+                    Self::SetRejectSentinelLeaves {
+                        reject_sentinel_leaves,
+                    },
+                    rest,
+                )
+            }
+            30 => {
+                let (config_timelock, rest) = unpack_u64(instruction_data)?;
+                (Self::SetConfigTimelock { config_timelock }, rest)
+            }
+            31 => {
+                let (change, rest) = unpack_config_change(instruction_data)?;
+                (Self::QueueConfig { change }, rest)
+            }
+            32 => (Self::ApplyPendingConfig, instruction_data),
+            33 => (Self::CancelPendingConfig, instruction_data),
+            34 => {
+                let (growth_percent, rest) = unpack_u16(instruction_data)?;
+                (Self::SetGrowthPercent { growth_percent }, rest)
+            }
+            35 => {
+                let (target_len, rest) = unpack_u32(instruction_data)?;
+                (Self::GrowTree { target_len }, rest)
+            }
+            36 => {
+                let (canopy_depth, rest) = unpack_u8(instruction_data)?;
+                (Self::SetCanopyDepth { canopy_depth }, rest)
+            }
+            37 => {
+                let (lazy_root, rest) = unpack_bool(instruction_data)?;
+                (Self::SetLazyRoot { lazy_root }, rest)
+            }
+            38 => (Self::CommitRoot, instruction_data),
+            _ => return Err(MerkleTreeError::UnknownInstruction.into()),
+        };
+
+        if !rest.is_empty() {
+            return Err(MerkleTreeError::OversizedInstructionData.into());
+        }
+
+        Ok(instruction)
+    }
+}
+
+/// Append `proof` as u32 length prefix followed by nodes.
+fn pack_proof(instruction_data: &mut Vec<u8>, proof: &[[u8; 32]]) {
+    instruction_data.extend_from_slice(&(proof.len() as u32).to_le_bytes());
+    proof
+        .iter()
+        .for_each(|node| instruction_data.extend_from_slice(node));
+}
+
+/// Append `expected_root` of insert instructions if set, older clients leave it out.
+fn pack_expected_root(instruction_data: &mut Vec<u8>, expected_root: &Option<[u8; 32]>) {
+    if let Some(expected_root) = expected_root {
+        instruction_data.extend_from_slice(expected_root);
+    }
+}
+
+/// Unpack optional trailing expected root, older clients leave it out.
+fn unpack_expected_root(input: &[u8]) -> Result<(Option<[u8; 32]>, &[u8]), ProgramError> {
+    if input.is_empty() {
+        return Ok((None, input));
+    }
+    let (expected_root, rest) = unpack_hash(input)?;
+    Ok((Some(expected_root), rest))
+}
+
+/// Append `value` as u32 length prefix followed by UTF-8 bytes.
+fn pack_string(instruction_data: &mut Vec<u8>, value: &str) {
+    instruction_data.extend_from_slice(&(value.len() as u32).to_le_bytes());
+    instruction_data.extend_from_slice(value.as_bytes());
+}
+
+fn unpack_string(input: &[u8]) -> Result<(String, &[u8]), ProgramError> {
+    let (value, rest) = unpack_vec(input, 1)?;
+    let value =
+        String::from_utf8(value.to_vec()).map_err(|_| ProgramError::InvalidInstructionData)?;
+    Ok((value, rest))
+}
+
+fn unpack_u8(input: &[u8]) -> Result<(u8, &[u8]), ProgramError> {
+    let (value, rest) = input
+        .split_first()
+        .ok_or(MerkleTreeError::TruncatedInstructionData)?;
+    Ok((*value, rest))
+}
+
+fn unpack_bool(input: &[u8]) -> Result<(bool, &[u8]), ProgramError> {
+    match unpack_u8(input)? {
+        (0, rest) => Ok((false, rest)),
+        (1, rest) => Ok((true, rest)),
+        _ => Err(ProgramError::InvalidInstructionData),
+    }
+}
+
+fn unpack_u16(input: &[u8]) -> Result<(u16, &[u8]), ProgramError> {
+    let (value, rest) = input
+        .split_first_chunk::<2>()
+        .ok_or(MerkleTreeError::TruncatedInstructionData)?;
+    Ok((u16::from_le_bytes(*value), rest))
+}
+
+fn unpack_u32(input: &[u8]) -> Result<(u32, &[u8]), ProgramError> {
+    let (value, rest) = input
+        .split_first_chunk::<4>()
+        .ok_or(MerkleTreeError::TruncatedInstructionData)?;
+    Ok((u32::from_le_bytes(*value), rest))
+}
+
+fn unpack_u64(input: &[u8]) -> Result<(u64, &[u8]), ProgramError> {
+    let (value, rest) = input
+        .split_first_chunk::<8>()
+        .ok_or(MerkleTreeError::TruncatedInstructionData)?;
+    Ok((u64::from_le_bytes(*value), rest))
+}
+
+fn unpack_hash(input: &[u8]) -> Result<([u8; 32], &[u8]), ProgramError> {
+    let (hash, rest) = input
+        .split_first_chunk::<32>()
+        .ok_or(MerkleTreeError::TruncatedInstructionData)?;
+    Ok((*hash, rest))
+}
+
+fn unpack_pubkey(input: &[u8]) -> Result<(Pubkey, &[u8]), ProgramError> {
+    let (pubkey, rest) = unpack_hash(input)?;
+    Ok((Pubkey::new_from_array(pubkey), rest))
+}
+
+/// Unpack u32 length prefixed vec of `item_len` byte items, checking the prefix against
+/// the payload before anything is allocated.
+fn unpack_vec(input: &[u8], item_len: usize) -> Result<(&[u8], &[u8]), ProgramError> {
+    let (len, rest) = unpack_u32(input)?;
+    let len = (len as usize)
+        .checked_mul(item_len)
+        .filter(|len| *len <= rest.len())
+        .ok_or(MerkleTreeError::TruncatedInstructionData)?;
+    Ok(rest.split_at(len))
+}
+
+fn unpack_proof(input: &[u8]) -> Result<(Vec<[u8; 32]>, &[u8]), ProgramError> {
+    let (proof, rest) = unpack_vec(input, 32)?;
+    let proof = proof
+        .chunks_exact(32)
+        .map(|node| node.try_into().expect("Invalid node length"))
+        .collect();
+    Ok((proof, rest))
+}
+
+fn unpack_config_change(input: &[u8]) -> Result<(ConfigChange, &[u8]), ProgramError> {
+    let mut rest = input;
+    match ConfigChange::deserialize(&mut rest) {
+        Ok(change) => Ok((change, rest)),
+        Err(_) => {
+            // Borsh reports short input like invalid values, the change is truncated if it
+            // decodes once zero padded, every change fits in 1 + 32 bytes
+            let padded = [input, &[0; 33]].concat();
+            match ConfigChange::deserialize(&mut padded.as_slice()) {
+                Ok(_) => Err(MerkleTreeError::TruncatedInstructionData.into()),
+                Err(_) => Err(ProgramError::InvalidInstructionData),
             }
-            _ => Err(ProgramError::InvalidInstructionData),
         }
     }
 }
@@ -0,0 +1,49 @@
+//! Leaf storage of trees with leaf pages. The merkle state account keeps only the frontier,
+//! leaves are written to fixed-size page PDAs seeded by the merkle state key and page index,
+//! see `pda::find_leaf_page_pda`. Page `i` holds leaves `i * LEAVES_PER_PAGE..` after the
+//! discriminator, unwritten slots are zeroed.
+
+use crate::error::MerkleTreeError;
+use solana_program::program_error::ProgramError;
+
+/// Leaves stored per page.
+pub const LEAVES_PER_PAGE: usize = 256;
+
+/// First 8 bytes of `sha256("account:LeafPage")`.
+pub const DISCRIMINATOR: [u8; 8] = [141, 157, 163, 125, 156, 93, 247, 112];
+
+/// Leaf page account length(in bytes): 8(discriminator) + 32 * LEAVES_PER_PAGE(leaves).
+pub const LEN: usize = 8 + 32 * LEAVES_PER_PAGE;
+
+pub fn page_index(leaf_index: u32) -> u32 {
+    leaf_index / LEAVES_PER_PAGE as u32
+}
+
+/// Pages needed to store `leaves_count` leaves.
+pub fn pages_count(leaves_count: usize) -> usize {
+    leaves_count.div_ceil(LEAVES_PER_PAGE)
+}
+
+/// Write discriminator of a newly created page.
+pub fn init(data: &mut [u8]) {
+    data[..8].copy_from_slice(&DISCRIMINATOR);
+}
+
+/// Leaves of page account `data`.
+pub fn leaves(data: &[u8]) -> Result<&[[u8; 32]], ProgramError> {
+    check(data)?;
+    Ok(bytemuck::cast_slice(&data[8..]))
+}
+
+pub fn leaves_mut(data: &mut [u8]) -> Result<&mut [[u8; 32]], ProgramError> {
+    check(data)?;
+    Ok(bytemuck::cast_slice_mut(&mut data[8..]))
+}
+
+fn check(data: &[u8]) -> Result<(), ProgramError> {
+    if data.len() != LEN || data[..8] != DISCRIMINATOR {
+        return Err(MerkleTreeError::InvalidLeafPage.into());
+    }
+
+    Ok(())
+}
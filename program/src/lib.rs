@@ -1,12 +1,38 @@
 #![allow(unexpected_cfgs)]
 
+pub mod config;
+pub mod error;
+pub mod events;
+pub mod hash;
 pub mod instruction;
+pub mod leaf_page;
+pub mod multisig;
+pub mod pda;
 mod processor;
+pub mod return_data;
 pub mod state;
+pub mod tree_metadata;
+pub mod tx_size;
 pub mod utils;
+pub mod zero_copy;
+
+pub use merkle_tree_core::verify;
 
 use instruction::MerkleTreeInstruction;
-use processor::process_insert_leaf;
+use processor::{
+    process_accept_authority, process_add_inserter, process_apply_pending_config,
+    process_cancel_pending_config, process_commit_root, process_emit_event,
+    process_enable_frontier_mode, process_enable_leaf_pages, process_finalize_tree,
+    process_get_root_hash, process_grow_tree, process_init_tree, process_insert_leaf,
+    process_insert_value, process_migrate_state, process_propose_authority, process_queue_config,
+    process_remove_inserter, process_replace_leaf, process_set_archive_uri,
+    process_set_canopy_depth, process_set_config_timelock, process_set_event_verbosity,
+    process_set_growth_percent, process_set_lazy_root, process_set_max_leaves,
+    process_set_metadata, process_set_multisig, process_set_paused, process_set_permissioned,
+    process_set_rate_limit, process_set_record_leaf_metadata, process_set_reject_duplicates,
+    process_set_reject_sentinel_leaves, process_sweep_expired, process_verify_consistency,
+    process_verify_leaf, process_verify_multiproof,
+};
 use solana_program::{
     account_info::AccountInfo, entrypoint, entrypoint::ProgramResult, pubkey::Pubkey,
 };
@@ -18,29 +44,210 @@ pub fn process_instruction(
     accounts: &[AccountInfo],
     instruction_data: &[u8],
 ) -> ProgramResult {
+    if instruction_data.starts_with(&events::EVENT_IX_TAG_LE) {
+        return process_emit_event(program_id, accounts);
+    }
+
     let instruction = MerkleTreeInstruction::unpack(instruction_data)?;
 
     match instruction {
-        MerkleTreeInstruction::InsertLeaf { hash } => {
-            process_insert_leaf(program_id, accounts, &hash)
+        MerkleTreeInstruction::InsertLeaf {
+            hash,
+            expected_root,
+        } => process_insert_leaf(program_id, accounts, &hash, None, expected_root.as_ref()),
+        MerkleTreeInstruction::ProposeAuthority { new_authority } => {
+            process_propose_authority(program_id, accounts, &new_authority)
+        }
+        MerkleTreeInstruction::AcceptAuthority => process_accept_authority(program_id, accounts),
+        MerkleTreeInstruction::PauseTree => process_set_paused(program_id, accounts, true),
+        MerkleTreeInstruction::ResumeTree => process_set_paused(program_id, accounts, false),
+        MerkleTreeInstruction::InsertValue {
+            data,
+            expected_root,
+        } => process_insert_value(program_id, accounts, &data, expected_root.as_ref()),
+        MerkleTreeInstruction::VerifyLeaf {
+            leaf_index,
+            leaf,
+            proof,
+        } => process_verify_leaf(program_id, accounts, leaf_index, &leaf, &proof),
+        MerkleTreeInstruction::ReplaceLeaf {
+            leaf_index,
+            old_leaf,
+            new_leaf,
+            proof,
+        } => process_replace_leaf(
+            program_id, accounts, leaf_index, &old_leaf, &new_leaf, &proof,
+        ),
+        MerkleTreeInstruction::VerifyMultiProof { leaves, proof } => {
+            process_verify_multiproof(program_id, accounts, &leaves, &proof)
+        }
+        MerkleTreeInstruction::SetArchiveUri {
+            root,
+            content_hash,
+            uri,
+        } => process_set_archive_uri(
+            program_id,
+            accounts,
+            state::ArchiveRecord {
+                root,
+                content_hash,
+                uri,
+            },
+        ),
+        MerkleTreeInstruction::GetRootHash => process_get_root_hash(program_id, accounts),
+        MerkleTreeInstruction::SetRejectDuplicates { reject_duplicates } => {
+            process_set_reject_duplicates(program_id, accounts, reject_duplicates)
+        }
+        MerkleTreeInstruction::FinalizeTree => process_finalize_tree(program_id, accounts),
+        MerkleTreeInstruction::VerifyConsistency {
+            old_root,
+            old_leaves_count,
+            leaf,
+            proof,
+        } => process_verify_consistency(
+            program_id,
+            accounts,
+            &old_root,
+            old_leaves_count,
+            &leaf,
+            &proof,
+        ),
+        MerkleTreeInstruction::EnableFrontierMode => {
+            process_enable_frontier_mode(program_id, accounts)
+        }
+        MerkleTreeInstruction::SetEventVerbosity { event_verbosity } => {
+            process_set_event_verbosity(program_id, accounts, event_verbosity)
+        }
+        MerkleTreeInstruction::InsertExpiringLeaf {
+            hash,
+            expires_at,
+            expected_root,
+        } => process_insert_leaf(
+            program_id,
+            accounts,
+            &hash,
+            Some(expires_at),
+            expected_root.as_ref(),
+        ),
+        MerkleTreeInstruction::SweepExpired => process_sweep_expired(program_id, accounts),
+        MerkleTreeInstruction::SetRecordLeafMetadata {
+            record_leaf_metadata,
+        } => process_set_record_leaf_metadata(program_id, accounts, record_leaf_metadata),
+        MerkleTreeInstruction::EnableLeafPages {
+            first_page,
+            pages_count,
+        } => process_enable_leaf_pages(program_id, accounts, first_page, pages_count),
+        MerkleTreeInstruction::InitTree {
+            hash,
+            leaf_capacity,
+            hash_algo,
+            domain_separation,
+            ordered,
+            left_balanced,
+            zero_padded,
+        } => process_init_tree(
+            program_id,
+            accounts,
+            &hash,
+            leaf_capacity,
+            hash::TreeHasher {
+                hash_algo,
+                domain_separation,
+                ordered,
+                left_balanced,
+                zero_padded,
+            },
+        ),
+        MerkleTreeInstruction::MigrateState => process_migrate_state(program_id, accounts),
+        MerkleTreeInstruction::SetMetadata {
+            name,
+            description,
+            uri,
+        } => process_set_metadata(
+            program_id,
+            accounts,
+            tree_metadata::TreeMetadataAccount::new(name, description, uri)?,
+        ),
+        MerkleTreeInstruction::AddInserter { inserter } => {
+            process_add_inserter(program_id, accounts, &inserter)
+        }
+        MerkleTreeInstruction::RemoveInserter { inserter } => {
+            process_remove_inserter(program_id, accounts, &inserter)
+        }
+        MerkleTreeInstruction::SetPermissioned { permissioned } => {
+            process_set_permissioned(program_id, accounts, permissioned)
         }
+        MerkleTreeInstruction::SetMultisig { threshold, signers } => process_set_multisig(
+            program_id,
+            accounts,
+            multisig::MultisigAccount::new(threshold, signers)?,
+        ),
+        MerkleTreeInstruction::SetRateLimit {
+            max_inserts_per_slot,
+            max_inserts_per_epoch,
+        } => process_set_rate_limit(
+            program_id,
+            accounts,
+            max_inserts_per_slot,
+            max_inserts_per_epoch,
+        ),
+        MerkleTreeInstruction::SetMaxLeaves { max_leaves } => {
+            process_set_max_leaves(program_id, accounts, max_leaves)
+        }
+        MerkleTreeInstruction::SetRejectSentinelLeaves {
+            reject_sentinel_leaves,
+        } => process_set_reject_sentinel_leaves(program_id, accounts, reject_sentinel_leaves),
+        MerkleTreeInstruction::SetConfigTimelock { config_timelock } => {
+            process_set_config_timelock(program_id, accounts, config_timelock)
+        }
+        MerkleTreeInstruction::QueueConfig { change } => {
+            process_queue_config(program_id, accounts, change)
+        }
+        MerkleTreeInstruction::ApplyPendingConfig => {
+            process_apply_pending_config(program_id, accounts)
+        }
+        MerkleTreeInstruction::CancelPendingConfig => {
+            process_cancel_pending_config(program_id, accounts)
+        }
+        MerkleTreeInstruction::SetGrowthPercent { growth_percent } => {
+            process_set_growth_percent(program_id, accounts, growth_percent)
+        }
+        MerkleTreeInstruction::GrowTree { target_len } => {
+            process_grow_tree(program_id, accounts, target_len)
+        }
+        MerkleTreeInstruction::SetCanopyDepth { canopy_depth } => {
+            process_set_canopy_depth(program_id, accounts, canopy_depth)
+        }
+        MerkleTreeInstruction::SetLazyRoot { lazy_root } => {
+            process_set_lazy_root(program_id, accounts, lazy_root)
+        }
+        MerkleTreeInstruction::CommitRoot => process_commit_root(program_id, accounts),
     }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
-    use borsh::BorshDeserialize;
+    use borsh::{BorshDeserialize, BorshSerialize};
+    use config::{ConfigChange, PendingConfigAccount};
+    use error::MerkleTreeError;
+    use events::{Event, MerkleTreeEvent, RootUpdated};
+    use hash::{HashAlgo, TreeHasher};
+    use pda::{find_event_authority_pda, find_merkle_state_pda, find_pending_config_pda};
+    use return_data::{ReturnData, ReturnDataError};
     use sha2::{Digest, Sha256};
     use solana_program_test::{ProgramTest, processor};
     use solana_sdk::{
-        instruction::{AccountMeta, Instruction},
+        entrypoint::MAX_PERMITTED_DATA_INCREASE,
+        instruction::{AccountMeta, Instruction, InstructionError},
+        signature::Keypair,
         signer::Signer,
         system_program,
-        transaction::Transaction,
+        transaction::{Transaction, TransactionError},
     };
     use state::MerkleStateAccount;
-    use utils::{find_merkle_state_pda, hash_sorted_pair};
+    use utils::{hash_leaves, hash_sorted_pair, hash_value};
+    use verify::NodeHasher;
 
     #[tokio::test]
     async fn success_init_merkle_state() {
@@ -56,20 +263,27 @@ mod test {
 
         // Calculate merkle state pda
         let (merkle_state_pda, _) = find_merkle_state_pda(&program_id);
+        let (event_authority_pda, _) = find_event_authority_pda(&program_id);
 
         // Prepare insert ix
         let mut hasher = Sha256::new();
         let data = 1337u32;
         hasher.update(data.to_le_bytes());
-        let hash: [u8; 32] = hasher.finalize().try_into().expect("Invalid hash length");
+        let hash: [u8; 32] = hasher.finalize().into();
 
         let insert_leaf_ix = Instruction::new_with_bytes(
             program_id,
-            &instruction::MerkleTreeInstruction::InsertLeaf { hash: hash.clone() }.pack(),
+            &instruction::MerkleTreeInstruction::InsertLeaf {
+                hash,
+                expected_root: None,
+            }
+            .pack(),
             vec![
                 AccountMeta::new(merkle_state_pda, false),
                 AccountMeta::new(payer.pubkey(), true),
                 AccountMeta::new_readonly(system_program::id(), false),
+                AccountMeta::new_readonly(event_authority_pda, false),
+                AccountMeta::new_readonly(program_id, false),
             ],
         );
 
@@ -127,9 +341,10 @@ mod test {
 
         // Calculate merkle state pda
         let (merkle_state_pda, _) = find_merkle_state_pda(&program_id);
+        let (event_authority_pda, _) = find_event_authority_pda(&program_id);
 
         // Prepare insert ix
-        let data_values = vec![1u32, 2, 3, 4, 5];
+        let data_values = [1u32, 2, 3, 4, 5];
         let data_hashes: Vec<[u8; 32]> = data_values
             .iter()
             .map(|value| Sha256::digest(value.to_le_bytes()).into())
@@ -139,11 +354,17 @@ mod test {
         for hash in &data_hashes {
             let insert_leaf_ix = Instruction::new_with_bytes(
                 program_id,
-                &instruction::MerkleTreeInstruction::InsertLeaf { hash: *hash }.pack(),
+                &instruction::MerkleTreeInstruction::InsertLeaf {
+                    hash: *hash,
+                    expected_root: None,
+                }
+                .pack(),
                 vec![
                     AccountMeta::new(merkle_state_pda, false),
                     AccountMeta::new(payer.pubkey(), true),
                     AccountMeta::new_readonly(system_program::id(), false),
+                    AccountMeta::new_readonly(event_authority_pda, false),
+                    AccountMeta::new_readonly(program_id, false),
                 ],
             );
 
@@ -190,4 +411,5990 @@ mod test {
         let root_hash = hash_sorted_pair(&h3, &h4);
         assert_eq!(merkle_state.get_root_hash(), root_hash);
     }
+
+    #[tokio::test]
+    async fn success_transfer_authority() {
+        // Setup test env
+        let program_id = Pubkey::new_unique();
+        let (mut banks_client, payer, recent_blockhash) = ProgramTest::new(
+            "merkle_tree_program",
+            program_id,
+            processor!(process_instruction),
+        )
+        .start()
+        .await;
+
+        // Calculate merkle state pda
+        let (merkle_state_pda, _) = find_merkle_state_pda(&program_id);
+        let (event_authority_pda, _) = find_event_authority_pda(&program_id);
+
+        // Init merkle state, payer becomes authority
+        let hash: [u8; 32] = Sha256::digest(1337u32.to_le_bytes()).into();
+        let insert_leaf_ix = Instruction::new_with_bytes(
+            program_id,
+            &instruction::MerkleTreeInstruction::InsertLeaf {
+                hash,
+                expected_root: None,
+            }
+            .pack(),
+            vec![
+                AccountMeta::new(merkle_state_pda, false),
+                AccountMeta::new(payer.pubkey(), true),
+                AccountMeta::new_readonly(system_program::id(), false),
+                AccountMeta::new_readonly(event_authority_pda, false),
+                AccountMeta::new_readonly(program_id, false),
+            ],
+        );
+
+        // Propose and accept new authority
+        let new_authority = Keypair::new();
+        let propose_authority_ix = Instruction::new_with_bytes(
+            program_id,
+            &instruction::MerkleTreeInstruction::ProposeAuthority {
+                new_authority: new_authority.pubkey(),
+            }
+            .pack(),
+            vec![
+                AccountMeta::new(merkle_state_pda, false),
+                AccountMeta::new_readonly(payer.pubkey(), true),
+            ],
+        );
+        let accept_authority_ix = Instruction::new_with_bytes(
+            program_id,
+            &instruction::MerkleTreeInstruction::AcceptAuthority.pack(),
+            vec![
+                AccountMeta::new(merkle_state_pda, false),
+                AccountMeta::new_readonly(new_authority.pubkey(), true),
+            ],
+        );
+
+        let mut tx = Transaction::new_with_payer(
+            &[insert_leaf_ix, propose_authority_ix, accept_authority_ix],
+            Some(&payer.pubkey()),
+        );
+        tx.sign(&[&payer, &new_authority], recent_blockhash);
+        banks_client
+            .process_transaction(tx)
+            .await
+            .expect("Can't process tx");
+
+        // Check merkle state authority
+        let Some(merkle_state_account) = banks_client
+            .get_account(merkle_state_pda)
+            .await
+            .expect("Can't get merkle state account")
+        else {
+            panic!("Merkle state account is uninitialized");
+        };
+        let merkle_state = MerkleStateAccount::try_from_slice(&merkle_state_account.data)
+            .expect("Invalid merkle state data");
+        assert_eq!(merkle_state.get_authority(), new_authority.pubkey());
+        assert_eq!(merkle_state.get_pending_authority(), None);
+
+        // Old authority can't propose anymore
+        let propose_authority_ix = Instruction::new_with_bytes(
+            program_id,
+            &instruction::MerkleTreeInstruction::ProposeAuthority {
+                new_authority: payer.pubkey(),
+            }
+            .pack(),
+            vec![
+                AccountMeta::new(merkle_state_pda, false),
+                AccountMeta::new_readonly(payer.pubkey(), true),
+            ],
+        );
+        let mut tx = Transaction::new_with_payer(&[propose_authority_ix], Some(&payer.pubkey()));
+        tx.sign(&[&payer], recent_blockhash);
+        let error = banks_client
+            .process_transaction(tx)
+            .await
+            .expect_err("Propose must fail for old authority")
+            .unwrap();
+        assert_eq!(
+            error,
+            TransactionError::InstructionError(
+                0,
+                InstructionError::Custom(MerkleTreeError::InvalidAuthority as u32)
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn fail_insert_leaf_with_wrong_accounts() {
+        // Setup test env
+        let program_id = Pubkey::new_unique();
+        let (mut banks_client, payer, recent_blockhash) = ProgramTest::new(
+            "merkle_tree_program",
+            program_id,
+            processor!(process_instruction),
+        )
+        .start()
+        .await;
+
+        // Calculate merkle state pda
+        let (merkle_state_pda, _) = find_merkle_state_pda(&program_id);
+        let (event_authority_pda, _) = find_event_authority_pda(&program_id);
+
+        let hash = hash_value(&1u32.to_le_bytes());
+        let cases = [
+            (
+                Pubkey::new_unique(),
+                system_program::id(),
+                MerkleTreeError::InvalidMerkleStatePda,
+            ),
+            (
+                merkle_state_pda,
+                Pubkey::new_unique(),
+                MerkleTreeError::InvalidSystemProgram,
+            ),
+        ];
+        for (merkle_state_address, system_program_address, expected_error) in cases {
+            let insert_leaf_ix = Instruction::new_with_bytes(
+                program_id,
+                &instruction::MerkleTreeInstruction::InsertLeaf {
+                    hash,
+                    expected_root: None,
+                }
+                .pack(),
+                vec![
+                    AccountMeta::new(merkle_state_address, false),
+                    AccountMeta::new(payer.pubkey(), true),
+                    AccountMeta::new_readonly(system_program_address, false),
+                    AccountMeta::new_readonly(event_authority_pda, false),
+                    AccountMeta::new_readonly(program_id, false),
+                ],
+            );
+            let mut tx = Transaction::new_with_payer(&[insert_leaf_ix], Some(&payer.pubkey()));
+            tx.sign(&[&payer], recent_blockhash);
+            let error = banks_client
+                .process_transaction(tx)
+                .await
+                .expect_err("Insert must fail for wrong accounts")
+                .unwrap();
+            assert_eq!(
+                error,
+                TransactionError::InstructionError(
+                    0,
+                    InstructionError::Custom(expected_error as u32)
+                )
+            );
+        }
+
+        // Payer must sign even though the fee payer does
+        let insert_leaf_ix = Instruction::new_with_bytes(
+            program_id,
+            &instruction::MerkleTreeInstruction::InsertLeaf {
+                hash,
+                expected_root: None,
+            }
+            .pack(),
+            vec![
+                AccountMeta::new(merkle_state_pda, false),
+                AccountMeta::new(Pubkey::new_unique(), false),
+                AccountMeta::new_readonly(system_program::id(), false),
+                AccountMeta::new_readonly(event_authority_pda, false),
+                AccountMeta::new_readonly(program_id, false),
+            ],
+        );
+        let mut tx = Transaction::new_with_payer(&[insert_leaf_ix], Some(&payer.pubkey()));
+        tx.sign(&[&payer], recent_blockhash);
+        let error = banks_client
+            .process_transaction(tx)
+            .await
+            .expect_err("Insert must fail for unsigned payer")
+            .unwrap();
+        assert_eq!(
+            error,
+            TransactionError::InstructionError(
+                0,
+                InstructionError::Custom(MerkleTreeError::MissingSigner as u32)
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn fail_insert_leaf_when_paused() {
+        // Setup test env
+        let program_id = Pubkey::new_unique();
+        let (mut banks_client, payer, recent_blockhash) = ProgramTest::new(
+            "merkle_tree_program",
+            program_id,
+            processor!(process_instruction),
+        )
+        .start()
+        .await;
+
+        // Calculate merkle state pda
+        let (merkle_state_pda, _) = find_merkle_state_pda(&program_id);
+        let (event_authority_pda, _) = find_event_authority_pda(&program_id);
+
+        let insert_leaf_ix = |value: u32| {
+            Instruction::new_with_bytes(
+                program_id,
+                &instruction::MerkleTreeInstruction::InsertLeaf {
+                    hash: Sha256::digest(value.to_le_bytes()).into(),
+                    expected_root: None,
+                }
+                .pack(),
+                vec![
+                    AccountMeta::new(merkle_state_pda, false),
+                    AccountMeta::new(payer.pubkey(), true),
+                    AccountMeta::new_readonly(system_program::id(), false),
+                    AccountMeta::new_readonly(event_authority_pda, false),
+                    AccountMeta::new_readonly(program_id, false),
+                ],
+            )
+        };
+        let authority_ix = |ix: instruction::MerkleTreeInstruction| {
+            Instruction::new_with_bytes(
+                program_id,
+                &ix.pack(),
+                vec![
+                    AccountMeta::new(merkle_state_pda, false),
+                    AccountMeta::new_readonly(payer.pubkey(), true),
+                ],
+            )
+        };
+
+        // Init merkle state and pause it
+        let mut tx = Transaction::new_with_payer(
+            &[
+                insert_leaf_ix(1),
+                authority_ix(instruction::MerkleTreeInstruction::PauseTree),
+            ],
+            Some(&payer.pubkey()),
+        );
+        tx.sign(&[&payer], recent_blockhash);
+        banks_client
+            .process_transaction(tx)
+            .await
+            .expect("Can't process tx");
+
+        // Insert is rejected while paused
+        let mut tx = Transaction::new_with_payer(&[insert_leaf_ix(2)], Some(&payer.pubkey()));
+        tx.sign(&[&payer], recent_blockhash);
+        let error = banks_client
+            .process_transaction(tx)
+            .await
+            .expect_err("Insert must fail while paused")
+            .unwrap();
+        assert_eq!(
+            error,
+            TransactionError::InstructionError(
+                0,
+                InstructionError::Custom(MerkleTreeError::TreePaused as u32)
+            )
+        );
+
+        // Insert succeeds after resume
+        let mut tx = Transaction::new_with_payer(
+            &[
+                authority_ix(instruction::MerkleTreeInstruction::ResumeTree),
+                insert_leaf_ix(2),
+            ],
+            Some(&payer.pubkey()),
+        );
+        tx.sign(&[&payer], recent_blockhash);
+        banks_client
+            .process_transaction(tx)
+            .await
+            .expect("Can't process tx");
+    }
+
+    #[tokio::test]
+    async fn fail_admin_changes_when_paused() {
+        // Setup test env
+        let program_id = Pubkey::new_unique();
+        let (mut banks_client, payer, recent_blockhash) = ProgramTest::new(
+            "merkle_tree_program",
+            program_id,
+            processor!(process_instruction),
+        )
+        .start()
+        .await;
+
+        // Calculate merkle state pda
+        let (merkle_state_pda, _) = find_merkle_state_pda(&program_id);
+        let (event_authority_pda, _) = find_event_authority_pda(&program_id);
+
+        let insert_leaf_ix = Instruction::new_with_bytes(
+            program_id,
+            &instruction::MerkleTreeInstruction::InsertLeaf {
+                hash: Sha256::digest(1u32.to_le_bytes()).into(),
+                expected_root: None,
+            }
+            .pack(),
+            vec![
+                AccountMeta::new(merkle_state_pda, false),
+                AccountMeta::new(payer.pubkey(), true),
+                AccountMeta::new_readonly(system_program::id(), false),
+                AccountMeta::new_readonly(event_authority_pda, false),
+                AccountMeta::new_readonly(program_id, false),
+            ],
+        );
+        let authority_ix = |ix: instruction::MerkleTreeInstruction| {
+            Instruction::new_with_bytes(
+                program_id,
+                &ix.pack(),
+                vec![
+                    AccountMeta::new(merkle_state_pda, false),
+                    AccountMeta::new(payer.pubkey(), true),
+                    AccountMeta::new_readonly(system_program::id(), false),
+                ],
+            )
+        };
+        let enable_leaf_pages_ix = Instruction::new_with_bytes(
+            program_id,
+            &instruction::MerkleTreeInstruction::EnableLeafPages {
+                first_page: 0,
+                pages_count: 1,
+            }
+            .pack(),
+            vec![
+                AccountMeta::new(merkle_state_pda, false),
+                AccountMeta::new(payer.pubkey(), true),
+                AccountMeta::new_readonly(system_program::id(), false),
+                AccountMeta::new(
+                    pda::find_leaf_page_pda(&program_id, &merkle_state_pda, 0).0,
+                    false,
+                ),
+            ],
+        );
+
+        // Init merkle state and pause it
+        let mut tx = Transaction::new_with_payer(
+            &[
+                insert_leaf_ix,
+                authority_ix(instruction::MerkleTreeInstruction::PauseTree),
+            ],
+            Some(&payer.pubkey()),
+        );
+        tx.sign(&[&payer], recent_blockhash);
+        banks_client
+            .process_transaction(tx)
+            .await
+            .expect("Can't process tx");
+
+        // Sealing and moving leaves wait for the tree to be resumed
+        for instruction in [
+            authority_ix(instruction::MerkleTreeInstruction::FinalizeTree),
+            authority_ix(instruction::MerkleTreeInstruction::EnableFrontierMode),
+            enable_leaf_pages_ix,
+        ] {
+            let mut tx = Transaction::new_with_payer(&[instruction], Some(&payer.pubkey()));
+            tx.sign(&[&payer], recent_blockhash);
+            let error = banks_client
+                .process_transaction(tx)
+                .await
+                .expect_err("Change must fail while paused")
+                .unwrap();
+            assert_eq!(
+                error,
+                TransactionError::InstructionError(
+                    0,
+                    InstructionError::Custom(MerkleTreeError::TreePaused as u32)
+                )
+            );
+        }
+
+        // Sealing succeeds after resume
+        let mut tx = Transaction::new_with_payer(
+            &[
+                authority_ix(instruction::MerkleTreeInstruction::ResumeTree),
+                authority_ix(instruction::MerkleTreeInstruction::FinalizeTree),
+            ],
+            Some(&payer.pubkey()),
+        );
+        tx.sign(&[&payer], recent_blockhash);
+        banks_client
+            .process_transaction(tx)
+            .await
+            .expect("Can't process tx");
+    }
+
+    #[tokio::test]
+    async fn fail_commit_root_when_paused() {
+        // Setup test env
+        let program_id = Pubkey::new_unique();
+        let (mut banks_client, payer, recent_blockhash) = ProgramTest::new(
+            "merkle_tree_program",
+            program_id,
+            processor!(process_instruction),
+        )
+        .start()
+        .await;
+
+        // Calculate merkle state pda
+        let (merkle_state_pda, _) = find_merkle_state_pda(&program_id);
+        let (event_authority_pda, _) = find_event_authority_pda(&program_id);
+
+        let insert_leaf_ix = |value: u32| {
+            Instruction::new_with_bytes(
+                program_id,
+                &instruction::MerkleTreeInstruction::InsertLeaf {
+                    hash: Sha256::digest(value.to_le_bytes()).into(),
+                    expected_root: None,
+                }
+                .pack(),
+                vec![
+                    AccountMeta::new(merkle_state_pda, false),
+                    AccountMeta::new(payer.pubkey(), true),
+                    AccountMeta::new_readonly(system_program::id(), false),
+                    AccountMeta::new_readonly(event_authority_pda, false),
+                    AccountMeta::new_readonly(program_id, false),
+                ],
+            )
+        };
+        let authority_ix = |ix: instruction::MerkleTreeInstruction| {
+            Instruction::new_with_bytes(
+                program_id,
+                &ix.pack(),
+                vec![
+                    AccountMeta::new(merkle_state_pda, false),
+                    AccountMeta::new_readonly(payer.pubkey(), true),
+                ],
+            )
+        };
+        let commit_root_ix = Instruction::new_with_bytes(
+            program_id,
+            &instruction::MerkleTreeInstruction::CommitRoot.pack(),
+            vec![
+                AccountMeta::new(merkle_state_pda, false),
+                AccountMeta::new_readonly(event_authority_pda, false),
+                AccountMeta::new_readonly(program_id, false),
+            ],
+        );
+
+        // Append a leaf in lazy root mode and pause the tree before committing it
+        let mut tx = Transaction::new_with_payer(
+            &[
+                insert_leaf_ix(1),
+                authority_ix(instruction::MerkleTreeInstruction::SetLazyRoot { lazy_root: true }),
+                insert_leaf_ix(2),
+                authority_ix(instruction::MerkleTreeInstruction::PauseTree),
+            ],
+            Some(&payer.pubkey()),
+        );
+        tx.sign(&[&payer], recent_blockhash);
+        banks_client
+            .process_transaction(tx)
+            .await
+            .expect("Can't process tx");
+
+        // Commit is rejected while paused
+        let mut tx = Transaction::new_with_payer(
+            std::slice::from_ref(&commit_root_ix),
+            Some(&payer.pubkey()),
+        );
+        tx.sign(&[&payer], recent_blockhash);
+        let error = banks_client
+            .process_transaction(tx)
+            .await
+            .expect_err("Commit must fail while paused")
+            .unwrap();
+        assert_eq!(
+            error,
+            TransactionError::InstructionError(
+                0,
+                InstructionError::Custom(MerkleTreeError::TreePaused as u32)
+            )
+        );
+
+        // Commit succeeds after resume
+        let mut tx = Transaction::new_with_payer(
+            &[
+                authority_ix(instruction::MerkleTreeInstruction::ResumeTree),
+                commit_root_ix,
+            ],
+            Some(&payer.pubkey()),
+        );
+        tx.sign(&[&payer], recent_blockhash);
+        banks_client
+            .process_transaction(tx)
+            .await
+            .expect("Can't process tx");
+    }
+
+    #[tokio::test]
+    async fn fail_insert_duplicate_leaf() {
+        // Setup test env
+        let program_id = Pubkey::new_unique();
+        let (mut banks_client, payer, recent_blockhash) = ProgramTest::new(
+            "merkle_tree_program",
+            program_id,
+            processor!(process_instruction),
+        )
+        .start()
+        .await;
+
+        // Calculate merkle state pda
+        let (merkle_state_pda, _) = find_merkle_state_pda(&program_id);
+        let (event_authority_pda, _) = find_event_authority_pda(&program_id);
+
+        let insert_leaf_ix = |value: u32| {
+            Instruction::new_with_bytes(
+                program_id,
+                &instruction::MerkleTreeInstruction::InsertLeaf {
+                    hash: hash_value(&value.to_le_bytes()),
+                    expected_root: None,
+                }
+                .pack(),
+                vec![
+                    AccountMeta::new(merkle_state_pda, false),
+                    AccountMeta::new(payer.pubkey(), true),
+                    AccountMeta::new_readonly(system_program::id(), false),
+                    AccountMeta::new_readonly(event_authority_pda, false),
+                    AccountMeta::new_readonly(program_id, false),
+                ],
+            )
+        };
+        let set_reject_duplicates_ix = |reject_duplicates: bool| {
+            Instruction::new_with_bytes(
+                program_id,
+                &instruction::MerkleTreeInstruction::SetRejectDuplicates { reject_duplicates }
+                    .pack(),
+                vec![
+                    AccountMeta::new(merkle_state_pda, false),
+                    AccountMeta::new_readonly(payer.pubkey(), true),
+                ],
+            )
+        };
+
+        // Init merkle state and reject duplicates
+        let mut tx = Transaction::new_with_payer(
+            &[insert_leaf_ix(1), set_reject_duplicates_ix(true)],
+            Some(&payer.pubkey()),
+        );
+        tx.sign(&[&payer], recent_blockhash);
+        banks_client
+            .process_transaction(tx)
+            .await
+            .expect("Can't process tx");
+
+        // Same leaf is rejected
+        let mut tx = Transaction::new_with_payer(&[insert_leaf_ix(1)], Some(&payer.pubkey()));
+        tx.sign(&[&payer], recent_blockhash);
+        let error = banks_client
+            .process_transaction(tx)
+            .await
+            .expect_err("Duplicate insert must fail")
+            .unwrap();
+        assert_eq!(
+            error,
+            TransactionError::InstructionError(
+                0,
+                InstructionError::Custom(MerkleTreeError::DuplicateLeaf as u32)
+            )
+        );
+
+        // Duplicates are accepted again once disabled
+        let mut tx = Transaction::new_with_payer(
+            &[set_reject_duplicates_ix(false), insert_leaf_ix(1)],
+            Some(&payer.pubkey()),
+        );
+        tx.sign(&[&payer], recent_blockhash);
+        banks_client
+            .process_transaction(tx)
+            .await
+            .expect("Can't process tx");
+    }
+
+    #[tokio::test]
+    async fn fail_insert_sentinel_leaf() {
+        // Setup test env
+        let program_id = Pubkey::new_unique();
+        let (mut banks_client, payer, recent_blockhash) = ProgramTest::new(
+            "merkle_tree_program",
+            program_id,
+            processor!(process_instruction),
+        )
+        .start()
+        .await;
+
+        // Calculate merkle state pda
+        let (merkle_state_pda, _) = find_merkle_state_pda(&program_id);
+        let (event_authority_pda, _) = find_event_authority_pda(&program_id);
+
+        let insert_leaf_ix = |hash: [u8; 32]| {
+            Instruction::new_with_bytes(
+                program_id,
+                &instruction::MerkleTreeInstruction::InsertLeaf {
+                    hash,
+                    expected_root: None,
+                }
+                .pack(),
+                vec![
+                    AccountMeta::new(merkle_state_pda, false),
+                    AccountMeta::new(payer.pubkey(), true),
+                    AccountMeta::new_readonly(system_program::id(), false),
+                    AccountMeta::new_readonly(event_authority_pda, false),
+                    AccountMeta::new_readonly(program_id, false),
+                ],
+            )
+        };
+        let set_reject_sentinel_leaves_ix = |reject_sentinel_leaves: bool| {
+            Instruction::new_with_bytes(
+                program_id,
+                &instruction::MerkleTreeInstruction::SetRejectSentinelLeaves {
+                    reject_sentinel_leaves,
+                }
+                .pack(),
+                vec![
+                    AccountMeta::new(merkle_state_pda, false),
+                    AccountMeta::new_readonly(payer.pubkey(), true),
+                ],
+            )
+        };
+
+        // Init merkle state and reject sentinel leaves
+        let mut tx = Transaction::new_with_payer(
+            &[
+                insert_leaf_ix(hash_value(&1u32.to_le_bytes())),
+                set_reject_sentinel_leaves_ix(true),
+            ],
+            Some(&payer.pubkey()),
+        );
+        tx.sign(&[&payer], recent_blockhash);
+        banks_client
+            .process_transaction(tx)
+            .await
+            .expect("Can't process tx");
+
+        // Zero hash and the empty subtree root above it are rejected
+        let empty_node = hash_sorted_pair(&[0u8; 32], &[0u8; 32]);
+        for sentinel in [[0u8; 32], empty_node] {
+            let mut tx =
+                Transaction::new_with_payer(&[insert_leaf_ix(sentinel)], Some(&payer.pubkey()));
+            tx.sign(&[&payer], recent_blockhash);
+            let error = banks_client
+                .process_transaction(tx)
+                .await
+                .expect_err("Sentinel insert must fail")
+                .unwrap();
+            assert_eq!(
+                error,
+                TransactionError::InstructionError(
+                    0,
+                    InstructionError::Custom(MerkleTreeError::SentinelLeaf as u32)
+                )
+            );
+        }
+
+        // Sentinels are accepted again once disabled
+        let mut tx = Transaction::new_with_payer(
+            &[
+                set_reject_sentinel_leaves_ix(false),
+                insert_leaf_ix([0u8; 32]),
+            ],
+            Some(&payer.pubkey()),
+        );
+        tx.sign(&[&payer], recent_blockhash);
+        banks_client
+            .process_transaction(tx)
+            .await
+            .expect("Can't process tx");
+    }
+
+    #[tokio::test]
+    async fn fail_insert_leaf_when_permissioned() {
+        // Setup test env
+        let program_id = Pubkey::new_unique();
+        let (mut banks_client, payer, recent_blockhash) = ProgramTest::new(
+            "merkle_tree_program",
+            program_id,
+            processor!(process_instruction),
+        )
+        .start()
+        .await;
+
+        // Calculate merkle state pda
+        let (merkle_state_pda, _) = find_merkle_state_pda(&program_id);
+        let (event_authority_pda, _) = find_event_authority_pda(&program_id);
+
+        let inserter = Keypair::new();
+        let insert_leaf_ix = |value: u32, payer: &Pubkey| {
+            Instruction::new_with_bytes(
+                program_id,
+                &instruction::MerkleTreeInstruction::InsertLeaf {
+                    hash: hash_value(&value.to_le_bytes()),
+                    expected_root: None,
+                }
+                .pack(),
+                vec![
+                    AccountMeta::new(merkle_state_pda, false),
+                    AccountMeta::new(*payer, true),
+                    AccountMeta::new_readonly(system_program::id(), false),
+                    AccountMeta::new_readonly(event_authority_pda, false),
+                    AccountMeta::new_readonly(program_id, false),
+                ],
+            )
+        };
+        let authority_ix = |instruction: instruction::MerkleTreeInstruction| {
+            Instruction::new_with_bytes(
+                program_id,
+                &instruction.pack(),
+                vec![
+                    AccountMeta::new(merkle_state_pda, false),
+                    AccountMeta::new(payer.pubkey(), true),
+                    AccountMeta::new_readonly(system_program::id(), false),
+                ],
+            )
+        };
+        let insert_error = |error: MerkleTreeError| {
+            TransactionError::InstructionError(0, InstructionError::Custom(error as u32))
+        };
+
+        // Init merkle state, permissioned trees reject payers not allowed, even the authority
+        let mut tx = Transaction::new_with_payer(
+            &[
+                insert_leaf_ix(1, &payer.pubkey()),
+                authority_ix(instruction::MerkleTreeInstruction::SetPermissioned {
+                    permissioned: true,
+                }),
+                solana_sdk::system_instruction::transfer(
+                    &payer.pubkey(),
+                    &inserter.pubkey(),
+                    1_000_000_000,
+                ),
+            ],
+            Some(&payer.pubkey()),
+        );
+        tx.sign(&[&payer], recent_blockhash);
+        banks_client
+            .process_transaction(tx)
+            .await
+            .expect("Can't process tx");
+
+        for payer_key in [payer.pubkey(), inserter.pubkey()] {
+            let mut tx = Transaction::new_with_payer(
+                &[insert_leaf_ix(2, &payer_key)],
+                Some(&payer.pubkey()),
+            );
+            match payer_key == payer.pubkey() {
+                true => tx.sign(&[&payer], recent_blockhash),
+                false => tx.sign(&[&payer, &inserter], recent_blockhash),
+            }
+            let error = banks_client
+                .process_transaction(tx)
+                .await
+                .expect_err("Insert of not allowed payer must fail")
+                .unwrap();
+            assert_eq!(error, insert_error(MerkleTreeError::InvalidInserter));
+        }
+
+        // Allowed inserter may insert
+        let mut tx = Transaction::new_with_payer(
+            &[
+                authority_ix(instruction::MerkleTreeInstruction::AddInserter {
+                    inserter: inserter.pubkey(),
+                }),
+                insert_leaf_ix(2, &inserter.pubkey()),
+            ],
+            Some(&payer.pubkey()),
+        );
+        tx.sign(&[&payer, &inserter], recent_blockhash);
+        banks_client
+            .process_transaction(tx)
+            .await
+            .expect("Can't process tx");
+
+        let merkle_state_account = banks_client
+            .get_account(merkle_state_pda)
+            .await
+            .expect("Can't get merkle state account")
+            .expect("Merkle state account is uninitialized");
+        let merkle_state = MerkleStateAccount::try_from_slice(&merkle_state_account.data)
+            .expect("Invalid merkle state data");
+        assert!(merkle_state.is_permissioned());
+        assert_eq!(merkle_state.get_inserters(), [inserter.pubkey()]);
+        assert_eq!(merkle_state.get_leaves_count(), 2);
+
+        // Removed inserter is rejected again, it can't be removed twice
+        let mut tx = Transaction::new_with_payer(
+            &[authority_ix(
+                instruction::MerkleTreeInstruction::RemoveInserter {
+                    inserter: inserter.pubkey(),
+                },
+            )],
+            Some(&payer.pubkey()),
+        );
+        tx.sign(&[&payer], recent_blockhash);
+        banks_client
+            .process_transaction(tx)
+            .await
+            .expect("Can't process tx");
+
+        let mut tx = Transaction::new_with_payer(
+            &[insert_leaf_ix(3, &inserter.pubkey())],
+            Some(&payer.pubkey()),
+        );
+        tx.sign(&[&payer, &inserter], recent_blockhash);
+        let error = banks_client
+            .process_transaction(tx)
+            .await
+            .expect_err("Insert of removed inserter must fail")
+            .unwrap();
+        assert_eq!(error, insert_error(MerkleTreeError::InvalidInserter));
+
+        let mut tx = Transaction::new_with_payer(
+            &[authority_ix(
+                instruction::MerkleTreeInstruction::RemoveInserter {
+                    inserter: inserter.pubkey(),
+                },
+            )],
+            Some(&payer.pubkey()),
+        );
+        tx.sign(&[&payer], recent_blockhash);
+        let error = banks_client
+            .process_transaction(tx)
+            .await
+            .expect_err("Removing not allowed inserter must fail")
+            .unwrap();
+        assert_eq!(error, insert_error(MerkleTreeError::InvalidInserter));
+
+        // Anyone may insert once the tree isn't permissioned anymore
+        let mut tx = Transaction::new_with_payer(
+            &[
+                authority_ix(instruction::MerkleTreeInstruction::SetPermissioned {
+                    permissioned: false,
+                }),
+                insert_leaf_ix(3, &inserter.pubkey()),
+            ],
+            Some(&payer.pubkey()),
+        );
+        tx.sign(&[&payer, &inserter], recent_blockhash);
+        banks_client
+            .process_transaction(tx)
+            .await
+            .expect("Can't process tx");
+    }
+
+    #[tokio::test]
+    async fn fail_insert_leaf_when_max_leaves_reached() {
+        // Setup test env
+        let program_id = Pubkey::new_unique();
+        let (mut banks_client, payer, recent_blockhash) = ProgramTest::new(
+            "merkle_tree_program",
+            program_id,
+            processor!(process_instruction),
+        )
+        .start()
+        .await;
+
+        // Calculate merkle state pda
+        let (merkle_state_pda, _) = find_merkle_state_pda(&program_id);
+        let (event_authority_pda, _) = find_event_authority_pda(&program_id);
+
+        let insert_leaf_ix = |value: u32| {
+            Instruction::new_with_bytes(
+                program_id,
+                &instruction::MerkleTreeInstruction::InsertLeaf {
+                    hash: hash_value(&value.to_le_bytes()),
+                    expected_root: None,
+                }
+                .pack(),
+                vec![
+                    AccountMeta::new(merkle_state_pda, false),
+                    AccountMeta::new(payer.pubkey(), true),
+                    AccountMeta::new_readonly(system_program::id(), false),
+                    AccountMeta::new_readonly(event_authority_pda, false),
+                    AccountMeta::new_readonly(program_id, false),
+                ],
+            )
+        };
+        let set_max_leaves_ix = |max_leaves: u32| {
+            Instruction::new_with_bytes(
+                program_id,
+                &instruction::MerkleTreeInstruction::SetMaxLeaves { max_leaves }.pack(),
+                vec![
+                    AccountMeta::new(merkle_state_pda, false),
+                    AccountMeta::new_readonly(payer.pubkey(), true),
+                ],
+            )
+        };
+
+        // Init merkle state capped at 2 leaves and fill it
+        let mut tx = Transaction::new_with_payer(
+            &[insert_leaf_ix(1), set_max_leaves_ix(2), insert_leaf_ix(2)],
+            Some(&payer.pubkey()),
+        );
+        tx.sign(&[&payer], recent_blockhash);
+        banks_client
+            .process_transaction(tx)
+            .await
+            .expect("Can't process tx");
+
+        // Full tree rejects inserts without growing
+        let merkle_state_len = |account: Option<solana_sdk::account::Account>| {
+            account
+                .expect("Merkle state account is uninitialized")
+                .data
+                .len()
+        };
+        let len_before = merkle_state_len(
+            banks_client
+                .get_account(merkle_state_pda)
+                .await
+                .expect("Can't get merkle state account"),
+        );
+        let mut tx = Transaction::new_with_payer(&[insert_leaf_ix(3)], Some(&payer.pubkey()));
+        tx.sign(&[&payer], recent_blockhash);
+        let error = banks_client
+            .process_transaction(tx)
+            .await
+            .expect_err("Insert into full tree must fail")
+            .unwrap();
+        assert_eq!(
+            error,
+            TransactionError::InstructionError(
+                0,
+                InstructionError::Custom(MerkleTreeError::TreeFull as u32)
+            )
+        );
+        let len_after = merkle_state_len(
+            banks_client
+                .get_account(merkle_state_pda)
+                .await
+                .expect("Can't get merkle state account"),
+        );
+        assert_eq!(len_before, len_after);
+
+        // Inserts are accepted again once the cap is lifted
+        let mut tx = Transaction::new_with_payer(
+            &[set_max_leaves_ix(0), insert_leaf_ix(3)],
+            Some(&payer.pubkey()),
+        );
+        tx.sign(&[&payer], recent_blockhash);
+        banks_client
+            .process_transaction(tx)
+            .await
+            .expect("Can't process tx");
+    }
+
+    #[tokio::test]
+    async fn fail_insert_leaf_with_stale_root() {
+        // Setup test env
+        let program_id = Pubkey::new_unique();
+        let (mut banks_client, payer, recent_blockhash) = ProgramTest::new(
+            "merkle_tree_program",
+            program_id,
+            processor!(process_instruction),
+        )
+        .start()
+        .await;
+
+        // Calculate merkle state pda
+        let (merkle_state_pda, _) = find_merkle_state_pda(&program_id);
+        let (event_authority_pda, _) = find_event_authority_pda(&program_id);
+
+        let insert_leaf_ix = |value: u32, expected_root: Option<[u8; 32]>| {
+            Instruction::new_with_bytes(
+                program_id,
+                &instruction::MerkleTreeInstruction::InsertLeaf {
+                    hash: hash_value(&value.to_le_bytes()),
+                    expected_root,
+                }
+                .pack(),
+                vec![
+                    AccountMeta::new(merkle_state_pda, false),
+                    AccountMeta::new(payer.pubkey(), true),
+                    AccountMeta::new_readonly(system_program::id(), false),
+                    AccountMeta::new_readonly(event_authority_pda, false),
+                    AccountMeta::new_readonly(program_id, false),
+                ],
+            )
+        };
+
+        // Init merkle state, its root is the first leaf
+        let mut tx = Transaction::new_with_payer(&[insert_leaf_ix(1, None)], Some(&payer.pubkey()));
+        tx.sign(&[&payer], recent_blockhash);
+        banks_client
+            .process_transaction(tx)
+            .await
+            .expect("Can't process tx");
+
+        // Insert against the current root
+        let root_hash = hash_value(&1u32.to_le_bytes());
+        let mut tx = Transaction::new_with_payer(
+            &[insert_leaf_ix(2, Some(root_hash))],
+            Some(&payer.pubkey()),
+        );
+        tx.sign(&[&payer], recent_blockhash);
+        banks_client
+            .process_transaction(tx)
+            .await
+            .expect("Can't process tx");
+
+        // Same root is stale now
+        let mut tx = Transaction::new_with_payer(
+            &[insert_leaf_ix(3, Some(root_hash))],
+            Some(&payer.pubkey()),
+        );
+        tx.sign(&[&payer], recent_blockhash);
+        let error = banks_client
+            .process_transaction(tx)
+            .await
+            .expect_err("Insert with stale root must fail")
+            .unwrap();
+        assert_eq!(
+            error,
+            TransactionError::InstructionError(
+                0,
+                InstructionError::Custom(MerkleTreeError::RootMismatch as u32)
+            )
+        );
+
+        let merkle_state_account = banks_client
+            .get_account(merkle_state_pda)
+            .await
+            .expect("Can't get merkle state account")
+            .expect("Merkle state account not found");
+        let merkle_state = MerkleStateAccount::try_from_slice(&merkle_state_account.data)
+            .expect("Can't deserialize merkle state");
+        assert_eq!(merkle_state.get_leaves_count(), 2);
+    }
+
+    #[tokio::test]
+    async fn fail_insert_leaf_when_sealed() {
+        // Setup test env
+        let program_id = Pubkey::new_unique();
+        let (mut banks_client, payer, recent_blockhash) = ProgramTest::new(
+            "merkle_tree_program",
+            program_id,
+            processor!(process_instruction),
+        )
+        .start()
+        .await;
+
+        // Calculate merkle state pda
+        let (merkle_state_pda, _) = find_merkle_state_pda(&program_id);
+        let (event_authority_pda, _) = find_event_authority_pda(&program_id);
+
+        let insert_leaf_ix = |value: u32| {
+            Instruction::new_with_bytes(
+                program_id,
+                &instruction::MerkleTreeInstruction::InsertLeaf {
+                    hash: hash_value(&value.to_le_bytes()),
+                    expected_root: None,
+                }
+                .pack(),
+                vec![
+                    AccountMeta::new(merkle_state_pda, false),
+                    AccountMeta::new(payer.pubkey(), true),
+                    AccountMeta::new_readonly(system_program::id(), false),
+                    AccountMeta::new_readonly(event_authority_pda, false),
+                    AccountMeta::new_readonly(program_id, false),
+                ],
+            )
+        };
+        let finalize_tree_ix = Instruction::new_with_bytes(
+            program_id,
+            &instruction::MerkleTreeInstruction::FinalizeTree.pack(),
+            vec![
+                AccountMeta::new(merkle_state_pda, false),
+                AccountMeta::new(payer.pubkey(), true),
+                AccountMeta::new_readonly(system_program::id(), false),
+            ],
+        );
+
+        // Insert two leaves and seal the tree
+        let mut tx = Transaction::new_with_payer(
+            &[insert_leaf_ix(1), insert_leaf_ix(2), finalize_tree_ix],
+            Some(&payer.pubkey()),
+        );
+        tx.sign(&[&payer], recent_blockhash);
+        banks_client
+            .process_transaction(tx)
+            .await
+            .expect("Can't process tx");
+
+        let Some(merkle_state_account) = banks_client
+            .get_account(merkle_state_pda)
+            .await
+            .expect("Can't get merkle state account")
+        else {
+            panic!("Merkle state account is uninitialized");
+        };
+        let merkle_state = MerkleStateAccount::try_from_slice(&merkle_state_account.data)
+            .expect("Invalid merkle state data");
+        let seal = merkle_state.get_seal().expect("Tree is not sealed");
+        assert_eq!(seal.root, merkle_state.get_root_hash());
+        assert_eq!(seal.leaves_count, 2);
+
+        // Insert is rejected once sealed
+        let mut tx = Transaction::new_with_payer(&[insert_leaf_ix(3)], Some(&payer.pubkey()));
+        tx.sign(&[&payer], recent_blockhash);
+        let error = banks_client
+            .process_transaction(tx)
+            .await
+            .expect_err("Insert must fail when sealed")
+            .unwrap();
+        assert_eq!(
+            error,
+            TransactionError::InstructionError(
+                0,
+                InstructionError::Custom(MerkleTreeError::TreeSealed as u32)
+            )
+        );
+    }
+
+    #[test]
+    fn tree_pdas_match_seeds() {
+        let program_id = Pubkey::new_unique();
+        let tree_pdas = pda::TreePdas::find(&program_id);
+        let (merkle_state_pda, merkle_state_bump) = tree_pdas.merkle_state;
+        let (leaf_page_pda, leaf_page_bump) = tree_pdas.find_leaf_page(3);
+
+        let pdas = [
+            (tree_pdas.merkle_state, vec![pda::MERKLE_STATE_SEED]),
+            (tree_pdas.event_authority, vec![pda::EVENT_AUTHORITY_SEED]),
+            (
+                tree_pdas.tree_metadata,
+                vec![pda::TREE_METADATA_SEED, merkle_state_pda.as_ref()],
+            ),
+            (
+                tree_pdas.multisig,
+                vec![pda::MULTISIG_SEED, merkle_state_pda.as_ref()],
+            ),
+            (
+                tree_pdas.pending_config,
+                vec![pda::PENDING_CONFIG_SEED, merkle_state_pda.as_ref()],
+            ),
+            (
+                (leaf_page_pda, leaf_page_bump),
+                vec![
+                    pda::LEAF_PAGE_SEED,
+                    merkle_state_pda.as_ref(),
+                    &[3, 0, 0, 0],
+                ],
+            ),
+        ];
+        assert_eq!(merkle_state_bump, find_merkle_state_pda(&program_id).1);
+        for ((address, bump), mut seeds) in pdas {
+            let bump = [bump];
+            seeds.push(&bump);
+            assert_eq!(
+                Pubkey::create_program_address(&seeds, &program_id),
+                Ok(address)
+            );
+        }
+    }
+
+    #[test]
+    fn frontier_root_matches_full_tree() {
+        let leaves: Vec<[u8; 32]> = (0u32..33).map(|i| hash_value(&i.to_le_bytes())).collect();
+        let mut merkle_state = MerkleStateAccount::new(&leaves[0], &Pubkey::new_unique());
+        let mut frontier = state::Frontier::from_leaves(&HashAlgo::Sha256, &leaves[..1]);
+
+        for leaf in &leaves[1..] {
+            merkle_state.add_leaf(leaf);
+            assert_eq!(
+                frontier.push(&HashAlgo::Sha256, leaf),
+                merkle_state.get_root_hash()
+            );
+            assert_eq!(
+                frontier.root(&HashAlgo::Sha256),
+                merkle_state.get_root_hash()
+            );
+        }
+        assert_eq!(frontier.nodes.len(), 6);
+        assert_eq!(frontier.changelog.len(), state::Frontier::CHANGELOG_LEN);
+
+        // Audit recomputes stored root and canopy in both modes
+        let (root_hash, canopy) = merkle_state.recompute_root();
+        assert_eq!(root_hash, merkle_state.get_root_hash());
+        assert_eq!(canopy, merkle_state.get_canopy());
+        merkle_state.enable_frontier_mode();
+        assert_eq!(merkle_state.recompute_root(), (root_hash, Vec::new()));
+    }
+
+    #[test]
+    fn frontier_fast_forwards_stale_proofs() {
+        let leaves: Vec<[u8; 32]> = (0u32..17).map(|i| hash_value(&i.to_le_bytes())).collect();
+        let mut merkle_state = MerkleStateAccount::new(&leaves[0], &Pubkey::new_unique());
+        let mut frontier = state::Frontier::from_leaves(&HashAlgo::Sha256, &leaves[..1]);
+        for leaf in &leaves[1..15] {
+            merkle_state.add_leaf(leaf);
+            frontier.push(&HashAlgo::Sha256, leaf);
+        }
+
+        // Proofs against the root of 15 leaves, incl. the self-paired rightmost leaf
+        let stale_proofs: Vec<(u32, Vec<[u8; 32]>)> = [0, 3, 9, 14]
+            .into_iter()
+            .map(|leaf_index| (leaf_index, merkle_state.get_proof(leaf_index)))
+            .collect();
+
+        // Grow the tree by one level and replace a leaf
+        for leaf in &leaves[15..] {
+            merkle_state.add_leaf(leaf);
+            frontier.push(&HashAlgo::Sha256, leaf);
+        }
+        let replaced = hash_value(b"replaced 3");
+        let proof = merkle_state.get_proof(3);
+        assert!(merkle_state.replace_leaf(3, &leaves[3], &replaced, &proof));
+        assert_eq!(
+            frontier.replace_leaf(
+                &HashAlgo::Sha256,
+                &frontier.root(&HashAlgo::Sha256),
+                3,
+                &leaves[3],
+                &replaced,
+                &proof
+            ),
+            Some(merkle_state.get_root_hash())
+        );
+
+        // Stale proof of the changed leaf is rejected, the others are fast-forwarded
+        let (leaf_index, proof) = &stale_proofs[1];
+        let root = frontier.root(&HashAlgo::Sha256);
+        assert_eq!(
+            frontier.replace_leaf(
+                &HashAlgo::Sha256,
+                &root,
+                *leaf_index,
+                &leaves[3],
+                &replaced,
+                proof
+            ),
+            None
+        );
+        for (leaf_index, proof) in [&stale_proofs[0], &stale_proofs[2], &stale_proofs[3]] {
+            let old_leaf = leaves[*leaf_index as usize];
+            let new_leaf = hash_value(format!("replaced {leaf_index}").as_bytes());
+            let fresh_proof = merkle_state.get_proof(*leaf_index);
+            assert!(merkle_state.replace_leaf(*leaf_index, &old_leaf, &new_leaf, &fresh_proof));
+
+            let root = frontier.root(&HashAlgo::Sha256);
+            assert_eq!(
+                frontier.replace_leaf(
+                    &HashAlgo::Sha256,
+                    &root,
+                    *leaf_index,
+                    &old_leaf,
+                    &new_leaf,
+                    proof
+                ),
+                Some(merkle_state.get_root_hash())
+            );
+            assert_eq!(
+                frontier.root(&HashAlgo::Sha256),
+                merkle_state.get_root_hash()
+            );
+        }
+
+        // Appends after replacements still match
+        let leaf = hash_value(b"appended");
+        merkle_state.add_leaf(&leaf);
+        assert_eq!(
+            frontier.push(&HashAlgo::Sha256, &leaf),
+            merkle_state.get_root_hash()
+        );
+    }
+
+    #[test]
+    fn merkle_state_len_matches_serialized_state() {
+        let leaves: Vec<[u8; 32]> = (0u32..8).map(|i| hash_value(&i.to_le_bytes())).collect();
+        let mut merkle_state = MerkleStateAccount::new(&leaves[0], &Pubkey::new_unique());
+        let mut data = vec![0u8; MerkleStateAccount::INIT_LEN];
+        assert_eq!(
+            merkle_state.account_len(data.len()),
+            Ok(MerkleStateAccount::INIT_LEN)
+        );
+        merkle_state
+            .pack(&mut data)
+            .expect("Can't pack merkle state");
+
+        // Growing by `account_len` round trips every write
+        for (leaf_index, leaf) in leaves.iter().enumerate().skip(1) {
+            merkle_state.add_leaf(leaf);
+            merkle_state.set_leaf_expiry(leaf_index as u32, i64::MAX);
+            let new_len = merkle_state
+                .account_len(data.len())
+                .expect("Can't compute account length");
+            data.resize(new_len, 0);
+            merkle_state
+                .pack(&mut data)
+                .expect("Can't pack merkle state");
+            let unpacked = MerkleStateAccount::unpack(&data).expect("Invalid merkle state data");
+            assert_eq!(unpacked.get_leaves_count(), leaf_index + 1);
+            assert_eq!(unpacked.get_root_hash(), merkle_state.get_root_hash());
+        }
+
+        // Short and stale accounts are rejected instead of truncated or left dirty
+        let error = Err(MerkleTreeError::StateSizeMismatch.into());
+        let data_len = data.len();
+        assert_eq!(merkle_state.pack(&mut data[..data_len - 1]), error);
+        data.push(0);
+        assert_eq!(merkle_state.pack(&mut data), error);
+
+        // Preallocated trees keep spare bytes
+        merkle_state.set_leaf_capacity(16);
+        assert_eq!(merkle_state.account_len(data.len()), Ok(data.len()));
+        assert_eq!(merkle_state.pack(&mut data), Ok(()));
+
+        let mut preallocated_state = MerkleStateAccount::new(&leaves[0], &Pubkey::new_unique());
+        preallocated_state.set_leaf_capacity(leaves.len() as u32);
+        leaves[1..]
+            .iter()
+            .for_each(|leaf| preallocated_state.add_leaf(leaf));
+        assert_eq!(
+            borsh::object_length(&preallocated_state).ok(),
+            Some(MerkleStateAccount::preallocated_len(leaves.len() as u32))
+        );
+    }
+
+    #[test]
+    fn geometric_growth_amortizes_reallocs() {
+        let leaves: Vec<[u8; 32]> = (0u32..40).map(|i| hash_value(&i.to_le_bytes())).collect();
+        let mut merkle_state = MerkleStateAccount::new(&leaves[0], &Pubkey::new_unique());
+        merkle_state.set_growth_percent(100);
+        let mut data = borsh::to_vec(&merkle_state).expect("Can't serialize merkle state");
+
+        // Each realloc doubles the leaves, the appends in between write into spare bytes
+        let mut reallocs = 0;
+        for leaf in &leaves[1..] {
+            let layout = zero_copy::MerkleStateLayout::parse(&data).expect("Invalid layout");
+            if layout.spare.len() < MerkleStateAccount::LEAF_LEN {
+                let grow_len = MerkleStateAccount::growth_len(
+                    MerkleStateAccount::LEAF_LEN - layout.spare.len(),
+                    layout.leaves_count(),
+                    100,
+                    data.len(),
+                );
+                assert_eq!(
+                    grow_len,
+                    (layout.leaves_count() + 1) * MerkleStateAccount::LEAF_LEN
+                );
+                data.resize(data.len() + grow_len, 0);
+                reallocs += 1;
+            }
+            zero_copy::append_leaf(&mut data, leaf).expect("Can't append leaf");
+            merkle_state.add_leaf(leaf);
+        }
+        assert_eq!(reallocs, 5);
+        assert_eq!(
+            MerkleStateAccount::unpack(&data)
+                .expect("Invalid merkle state data")
+                .get_root_hash(),
+            merkle_state.get_root_hash()
+        );
+
+        // Borsh writes grow the same way, spare bytes are kept until growth is turned off
+        let packed_len = borsh::object_length(&merkle_state).expect("Can't compute length");
+        assert_eq!(merkle_state.account_len(data.len()), Ok(data.len()));
+        assert_eq!(
+            merkle_state.account_len(packed_len - 1),
+            Ok(packed_len - 1 + 1 + 40 * 100 / 100 * MerkleStateAccount::LEAF_LEN)
+        );
+        merkle_state.set_growth_percent(0);
+        assert_eq!(merkle_state.account_len(data.len()), Ok(packed_len));
+        assert!(MerkleStateAccount::unpack(&data[..packed_len]).is_ok());
+
+        // Spare leaves of huge trees saturate to a single realloc instead of overflowing
+        assert_eq!(
+            MerkleStateAccount::growth_len(MerkleStateAccount::LEAF_LEN, usize::MAX, 1000, 0),
+            MAX_PERMITTED_DATA_INCREASE
+        );
+        assert_eq!(
+            MerkleStateAccount::preallocated_len(u32::MAX),
+            (u32::MAX as usize - 1) * MerkleStateAccount::LEAF_LEN + MerkleStateAccount::INIT_LEN
+        );
+    }
+
+    #[test]
+    fn zero_copy_append_matches_borsh() {
+        let leaves: Vec<[u8; 32]> = (0u32..12).map(|i| hash_value(&i.to_le_bytes())).collect();
+        let mut merkle_state = MerkleStateAccount::new(&leaves[0], &Pubkey::new_unique());
+        leaves[1..5]
+            .iter()
+            .for_each(|leaf| merkle_state.add_leaf(leaf));
+        assert!(merkle_state.set_archive(state::ArchiveRecord {
+            root: merkle_state.get_root_hash(),
+            content_hash: hash_leaves(&leaves[..5]),
+            uri: "ipfs://snapshot".into(),
+        }));
+
+        let mut data = borsh::to_vec(&merkle_state).expect("Can't serialize merkle state");
+        let header = zero_copy::MerkleStateHeader::from_bytes(&data).expect("Invalid header");
+        assert_eq!(header.root_hash, merkle_state.get_root_hash());
+        assert_eq!(header.authority, merkle_state.get_authority());
+
+        // Appending in place produces the same bytes as borsh round trip
+        for leaf in &leaves[5..] {
+            data.extend_from_slice(&[0u8; 32]);
+            let (root_hash, leaves_count) =
+                zero_copy::append_leaf(&mut data, leaf).expect("Can't append leaf");
+            merkle_state.add_leaf(leaf);
+
+            assert_eq!(root_hash, merkle_state.get_root_hash());
+            assert_eq!(leaves_count, merkle_state.get_leaves_count());
+            assert_eq!(
+                data,
+                borsh::to_vec(&merkle_state).expect("Can't serialize merkle state")
+            );
+        }
+
+        // Trailing bytes are rejected
+        data.push(0);
+        assert!(zero_copy::MerkleStateLayout::parse(&data).is_err());
+
+        // Preallocated trees append into their spare bytes until full
+        let mut merkle_state = MerkleStateAccount::new(&leaves[0], &Pubkey::new_unique());
+        merkle_state.set_leaf_capacity(3);
+        let mut data = vec![0u8; MerkleStateAccount::preallocated_len(3)];
+        merkle_state
+            .serialize(&mut &mut data[..])
+            .expect("Can't serialize merkle state");
+        for leaf in &leaves[1..3] {
+            let (root_hash, _) =
+                zero_copy::append_leaf(&mut data, leaf).expect("Can't append leaf");
+            merkle_state.add_leaf(leaf);
+            assert_eq!(root_hash, merkle_state.get_root_hash());
+        }
+        assert_eq!(
+            data,
+            borsh::to_vec(&merkle_state).expect("Can't serialize merkle state")
+        );
+        assert!(zero_copy::append_leaf(&mut data, &leaves[3]).is_err());
+    }
+
+    #[test]
+    fn zero_copy_push_item_matches_borsh() {
+        let leaves: Vec<[u8; 32]> = (0u32..4).map(|i| hash_value(&i.to_le_bytes())).collect();
+        let payer = Pubkey::new_unique();
+        let mut merkle_state = MerkleStateAccount::new(&leaves[0], &payer);
+        merkle_state.set_record_leaf_metadata(true);
+        merkle_state.set_rate_limit(0, 10);
+
+        // Items are pushed back to front ahead of the leaf, shifting the leaves
+        for (leaf_index, leaf) in leaves.iter().enumerate().skip(1) {
+            let mut data = borsh::to_vec(&merkle_state).expect("Can't serialize merkle state");
+            let layout = zero_copy::MerkleStateLayout::parse(&data).expect("Invalid layout");
+            let counter = state::InsertCounter {
+                payer,
+                slot: 1,
+                slot_inserts: leaf_index as u32,
+                epoch: 0,
+                epoch_inserts: leaf_index as u32,
+            };
+            let leaf_metadata = state::LeafMetadata {
+                leaf_index: leaf_index as u32,
+                slot: 1,
+                inserter: payer,
+            };
+            let expiry = state::LeafExpiry {
+                leaf_index: leaf_index as u32,
+                expires_at: 100,
+            };
+            let items = [
+                (
+                    &layout.leaf_metadata,
+                    borsh::to_vec(&leaf_metadata).expect("Can't serialize item"),
+                ),
+                (
+                    &layout.expiries,
+                    borsh::to_vec(&expiry).expect("Can't serialize item"),
+                ),
+            ];
+            let counter_item = (
+                &layout.insert_counters,
+                borsh::to_vec(&counter).expect("Can't serialize item"),
+            );
+            let items_len = items.iter().map(|(_, item)| item.len()).sum::<usize>()
+                + (leaf_index == 1) as usize * counter_item.1.len();
+            let mut state_len = data.len();
+            data.resize(state_len + items_len + MerkleStateAccount::LEAF_LEN, 0);
+            if leaf_index == 1 {
+                state_len =
+                    zero_copy::push_item(&mut data, counter_item.0, state_len, &counter_item.1)
+                        .expect("Can't push counter");
+            } else {
+                let offset = layout.insert_counters.start;
+                data[offset..offset + counter_item.1.len()].copy_from_slice(&counter_item.1);
+            }
+            for (items, item) in items {
+                state_len = zero_copy::push_item(&mut data, items, state_len, &item)
+                    .expect("Can't push item");
+            }
+            zero_copy::append_leaf(&mut data, leaf).expect("Can't append leaf");
+
+            assert!(merkle_state.count_insert(&payer, 1, 0));
+            merkle_state.add_leaf(leaf);
+            merkle_state.record_leaf_metadata(1, &payer);
+            merkle_state.set_leaf_expiry(leaf_index as u32, 100);
+            assert_eq!(
+                data,
+                borsh::to_vec(&merkle_state).expect("Can't serialize merkle state")
+            );
+        }
+    }
+
+    #[test]
+    fn incremental_root_matches_full_recompute() {
+        let leaves: Vec<[u8; 32]> = (0u32..70).map(|i| hash_value(&i.to_le_bytes())).collect();
+        for tree_hasher in [
+            TreeHasher::default(),
+            TreeHasher::OPENZEPPELIN,
+            TreeHasher::RFC6962,
+        ] {
+            let mut merkle_state = MerkleStateAccount::new(&leaves[0], &Pubkey::new_unique());
+            merkle_state.set_tree_hasher(tree_hasher);
+            for (leaf_index, leaf) in leaves.iter().enumerate().skip(1) {
+                merkle_state.add_leaf(leaf);
+                assert_eq!(
+                    merkle_state.recompute_root(),
+                    (merkle_state.get_root_hash(), merkle_state.get_canopy())
+                );
+
+                // Filled subtrees are rebuilt after a leaf changes in place
+                if leaf_index == 40 {
+                    let proof = merkle_state.get_proof(7);
+                    assert!(merkle_state.replace_leaf(7, &leaves[7], &[7u8; 32], &proof));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn lazy_root_commits_appended_leaves() {
+        let leaves: Vec<[u8; 32]> = (0u32..50).map(|i| hash_value(&i.to_le_bytes())).collect();
+        let mut eager_state = MerkleStateAccount::new(&leaves[0], &Pubkey::new_unique());
+        let mut lazy_state = eager_state.clone();
+        lazy_state.set_lazy_root(true);
+
+        // Appends leave the root as is, borsh and zero-copy alike
+        let mut data = borsh::to_vec(&lazy_state).expect("Can't serialize merkle state");
+        for leaf in &leaves[1..30] {
+            eager_state.add_leaf(leaf);
+            lazy_state.add_leaf(leaf);
+            data.extend_from_slice(&[0u8; 32]);
+            let (root_hash, _) =
+                zero_copy::append_leaf(&mut data, leaf).expect("Can't append leaf");
+            assert_eq!(root_hash, leaves[0]);
+        }
+        assert!(lazy_state.is_root_dirty());
+        assert_eq!(lazy_state.get_root_hash(), leaves[0]);
+        assert_eq!(
+            data,
+            borsh::to_vec(&lazy_state).expect("Can't serialize merkle state")
+        );
+
+        // Committing publishes the root of all leaves once
+        assert!(lazy_state.commit_root());
+        assert!(!lazy_state.commit_root());
+        assert_eq!(lazy_state.get_root_hash(), eager_state.get_root_hash());
+        assert_eq!(lazy_state.get_canopy(), eager_state.get_canopy());
+        assert_eq!(
+            lazy_state.get_root_history(),
+            vec![leaves[0], eager_state.get_root_hash()]
+        );
+
+        // Filled subtrees are rebuilt, so later eager appends stay incremental
+        lazy_state.set_lazy_root(false);
+        for leaf in &leaves[30..] {
+            eager_state.add_leaf(leaf);
+            lazy_state.add_leaf(leaf);
+            assert_eq!(lazy_state.get_root_hash(), eager_state.get_root_hash());
+        }
+        assert_eq!(
+            lazy_state.recompute_root(),
+            (lazy_state.get_root_hash(), lazy_state.get_canopy())
+        );
+    }
+
+    #[test]
+    fn canopy_depth_caches_upper_layers() {
+        let leaves: Vec<[u8; 32]> = (0u32..45).map(|i| hash_value(&i.to_le_bytes())).collect();
+        for tree_hasher in [TreeHasher::default(), TreeHasher::RFC6962] {
+            for canopy_depth in 0..=MerkleStateAccount::MAX_CANOPY_DEPTH {
+                let mut merkle_state = MerkleStateAccount::new(&leaves[0], &Pubkey::new_unique());
+                merkle_state.set_tree_hasher(tree_hasher);
+                merkle_state.set_canopy_depth(canopy_depth);
+                for (leaves_count, leaf) in leaves.iter().enumerate().skip(1) {
+                    merkle_state.add_leaf(leaf);
+
+                    // Replacing a leaf only rehashes its subtree below the canopy
+                    let leaf_index = (leaves_count * 7 % (leaves_count + 1)) as u32;
+                    let old_leaf = merkle_state.leaf_hashes()[leaf_index as usize];
+                    let proof = merkle_state.get_proof(leaf_index);
+                    assert!(merkle_state.replace_leaf(
+                        leaf_index,
+                        &old_leaf,
+                        &hash_value(&old_leaf),
+                        &proof
+                    ));
+                    assert_eq!(
+                        merkle_state.recompute_root(),
+                        (merkle_state.get_root_hash(), merkle_state.get_canopy())
+                    );
+
+                    // Truncated proofs only need the leaves below the canopy
+                    let range = MerkleStateAccount::canopy_subtree_range(
+                        leaves_count + 1,
+                        canopy_depth,
+                        leaf_index as usize,
+                    );
+                    let proof = MerkleStateAccount::truncated_proof_from_subtree(
+                        &tree_hasher,
+                        leaves_count + 1,
+                        canopy_depth,
+                        leaf_index as usize,
+                        &merkle_state.leaf_hashes()[range],
+                    );
+                    match tree_hasher.left_balanced {
+                        true => assert_eq!(proof, None),
+                        false => {
+                            assert_eq!(proof, Some(merkle_state.get_truncated_proof(leaf_index)))
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn core_tree_proofs_verify_without_program() {
+        // Clients build trees and proofs from `merkle_tree_core` with any `NodeHasher`
+        let hasher = hash_sorted_pair;
+        let leaves: Vec<[u8; 32]> = (0u32..13).map(|i| hash_value(&i.to_le_bytes())).collect();
+        for leaves_count in 1..=leaves.len() {
+            let leaves = &leaves[..leaves_count];
+            let root = merkle_tree_core::tree::compute_tree_root(&hasher, leaves);
+            assert_eq!(
+                root,
+                state::Frontier::from_leaves(&hasher, leaves).root(&hasher)
+            );
+
+            let mut merkle_state = MerkleStateAccount::new(&leaves[0], &Pubkey::new_unique());
+            leaves[1..]
+                .iter()
+                .for_each(|leaf| merkle_state.add_leaf(leaf));
+            assert_eq!(merkle_state.get_root_hash(), root);
+
+            for (leaf_index, leaf) in leaves.iter().enumerate() {
+                let proof = merkle_tree_core::tree::get_proof(&hasher, leaves, leaf_index);
+                assert_eq!(merkle_state.get_proof(leaf_index as u32), proof);
+                assert!(verify::verify_proof(&hasher, &root, leaf, &proof));
+
+                let audit_proof =
+                    merkle_tree_core::tree::get_audit_proof(&hasher, leaves, leaf_index);
+                assert_eq!(
+                    verify::compute_audit_root(
+                        &hasher,
+                        leaves_count,
+                        leaf_index,
+                        leaf,
+                        &audit_proof
+                    ),
+                    Some(root)
+                );
+
+                let (leaf, proof) =
+                    merkle_tree_core::tree::get_consistency_proof(&hasher, leaves, leaf_index + 1);
+                let old_root =
+                    merkle_tree_core::tree::compute_tree_root(&hasher, &leaves[..=leaf_index]);
+                assert!(verify::verify_consistency(
+                    &hasher,
+                    &old_root,
+                    leaf_index + 1,
+                    &root,
+                    leaves_count,
+                    &leaf,
+                    &proof
+                ));
+            }
+
+            let leaf_indices: Vec<u32> = (0..leaves_count as u32).step_by(3).collect();
+            let proof = merkle_tree_core::tree::get_multiproof(&hasher, leaves, &leaf_indices);
+            let leaves: Vec<(u32, [u8; 32])> = leaf_indices
+                .iter()
+                .map(|index| (*index, leaves[*index as usize]))
+                .collect();
+            assert!(verify::verify_multiproof(
+                &hasher,
+                &root,
+                leaves_count,
+                &leaves,
+                &proof
+            ));
+        }
+    }
+
+    #[test]
+    fn merkle_proof_encodings() {
+        let leaves: Vec<[u8; 32]> = (0u32..5).map(|i| hash_value(&i.to_le_bytes())).collect();
+        let proof = merkle_tree_core::MerkleProof {
+            tree_id: Pubkey::new_unique().to_bytes(),
+            leaf_index: 3,
+            leaf: leaves[3],
+            root: merkle_tree_core::tree::compute_tree_root(&hash_sorted_pair, &leaves),
+            path: merkle_tree_core::tree::get_proof(&hash_sorted_pair, &leaves, 3),
+        };
+
+        // Borsh and hex of borsh round trip, hex in either case
+        let bytes = borsh::to_vec(&proof).unwrap();
+        assert_eq!(bytes.len(), 32 + 4 + 32 + 32 + 4 + 32 * proof.path.len());
+        assert_eq!(
+            merkle_tree_core::MerkleProof::try_from_slice(&bytes).unwrap(),
+            proof
+        );
+        let hex = proof.to_hex();
+        assert_eq!(merkle_tree_core::proof::decode_hex(&hex).unwrap(), bytes);
+        assert_eq!(
+            merkle_tree_core::MerkleProof::from_hex(&hex),
+            Some(proof.clone())
+        );
+        assert_eq!(
+            merkle_tree_core::MerkleProof::from_hex(&hex.to_uppercase()),
+            Some(proof)
+        );
+
+        // Truncated, odd length and non-hex input is rejected
+        assert_eq!(
+            merkle_tree_core::MerkleProof::from_hex(&hex[..hex.len() - 2]),
+            None
+        );
+        assert_eq!(merkle_tree_core::MerkleProof::from_hex(&hex[1..]), None);
+        assert_eq!(
+            merkle_tree_core::MerkleProof::from_hex(&format!("{}zz", &hex[..hex.len() - 2])),
+            None
+        );
+    }
+
+    #[test]
+    fn zero_padded_tree_matches_zero_leaves() {
+        // Precomputed tables match hashing level by level
+        for tree_hasher in [TreeHasher::default(), TreeHasher::OPENZEPPELIN] {
+            let mut empty_node = [0u8; 32];
+            for level in 0..MerkleStateAccount::MAX_HEIGHT {
+                assert_eq!(tree_hasher.empty_subtree(level), empty_node);
+                empty_node = tree_hasher.hash_nodes(&empty_node, &empty_node);
+            }
+        }
+
+        let leaves: Vec<[u8; 32]> = (0u32..20).map(|i| hash_value(&i.to_le_bytes())).collect();
+        for tree_hasher in [
+            TreeHasher::default(),
+            TreeHasher::OPENZEPPELIN,
+            TreeHasher::RFC6962,
+        ] {
+            let tree_hasher = TreeHasher {
+                left_balanced: false,
+                zero_padded: true,
+                ..tree_hasher
+            };
+            let mut merkle_state = MerkleStateAccount::new(&leaves[0], &Pubkey::new_unique());
+            merkle_state.set_tree_hasher(tree_hasher);
+            let mut data = borsh::to_vec(&merkle_state).expect("Can't serialize merkle state");
+            for leaves_count in 2..=leaves.len() {
+                merkle_state.add_leaf(&leaves[leaves_count - 1]);
+                data.extend_from_slice(&[0u8; 32]);
+                zero_copy::append_leaf(&mut data, &leaves[leaves_count - 1])
+                    .expect("Can't append leaf");
+                assert_eq!(
+                    data,
+                    borsh::to_vec(&merkle_state).expect("Can't serialize merkle state")
+                );
+                assert_eq!(
+                    merkle_state.recompute_root(),
+                    (merkle_state.get_root_hash(), merkle_state.get_canopy())
+                );
+
+                // Root of the leaves padded with zero leaves to a complete tree
+                let mut padded = leaves[..leaves_count].to_vec();
+                padded.resize(leaves_count.next_power_of_two(), [0u8; 32]);
+                assert_eq!(
+                    merkle_state.get_root_hash(),
+                    state::Frontier::from_leaves(&tree_hasher, &padded).root(&tree_hasher)
+                );
+
+                for leaf_index in 0..leaves_count as u32 {
+                    let leaf = &leaves[leaf_index as usize];
+                    let proof = merkle_state.get_truncated_proof(leaf_index);
+                    assert!(merkle_state.verify_leaf(leaf_index, leaf, &proof));
+                }
+                let last_index = leaves_count as u32 - 1;
+                let multiproof = merkle_state.get_multiproof(&[0, last_index]);
+                assert!(merkle_state.verify_leaves(
+                    &[(0, leaves[0]), (last_index, leaves[last_index as usize])],
+                    &multiproof
+                ));
+                let old_root =
+                    state::Frontier::from_leaves(&tree_hasher, &leaves[..leaves_count - 1])
+                        .root(&tree_hasher);
+                let (leaf, proof) = merkle_state.get_consistency_proof(last_index);
+                assert!(merkle_state.verify_consistency(&old_root, last_index, &leaf, &proof));
+            }
+        }
+    }
+
+    #[test]
+    fn single_pass_recompute_matches_layers() {
+        let leaves: Vec<[u8; 32]> = (0u32..40).map(|i| hash_value(&i.to_le_bytes())).collect();
+        for tree_hasher in [TreeHasher::default(), TreeHasher::OPENZEPPELIN] {
+            for leaves_count in 1..=leaves.len() {
+                let mut merkle_state = MerkleStateAccount::new(&leaves[0], &Pubkey::new_unique());
+                merkle_state.set_tree_hasher(tree_hasher);
+                leaves[1..leaves_count]
+                    .iter()
+                    .for_each(|leaf| merkle_state.add_leaf(leaf));
+
+                // Replacing a leaf folds all leaves again, canopy-truncated proofs of every
+                // leaf still verify
+                let proof = merkle_state.get_proof(0);
+                assert!(merkle_state.replace_leaf(0, &leaves[0], &[7u8; 32], &proof));
+                assert_eq!(
+                    merkle_state.get_root_hash(),
+                    state::Frontier::from_leaves(&tree_hasher, merkle_state.leaf_hashes())
+                        .root(&tree_hasher)
+                );
+                for (leaf_index, leaf) in merkle_state.leaf_hashes().iter().enumerate() {
+                    let proof = merkle_state.get_truncated_proof(leaf_index as u32);
+                    assert!(merkle_state.verify_leaf(leaf_index as u32, leaf, &proof));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn keccak_tree_matches_sorted_pairs() {
+        // keccak256("")
+        assert_eq!(
+            HashAlgo::Keccak256.hash_value(b""),
+            [
+                197, 210, 70, 1, 134, 247, 35, 60, 146, 126, 125, 178, 220, 199, 3, 192, 229, 0,
+                182, 83, 202, 130, 39, 59, 123, 250, 216, 4, 93, 133, 164, 112
+            ]
+        );
+
+        let leaves: Vec<[u8; 32]> = (0u32..7)
+            .map(|i| HashAlgo::Keccak256.hash_value(&i.to_le_bytes()))
+            .collect();
+        let mut merkle_state = MerkleStateAccount::new(&leaves[0], &Pubkey::new_unique());
+        merkle_state.set_tree_hasher(HashAlgo::Keccak256.into());
+        let mut data = borsh::to_vec(&merkle_state).expect("Can't serialize merkle state");
+        for leaf in &leaves[1..] {
+            merkle_state.add_leaf(leaf);
+            data.extend_from_slice(&[0u8; 32]);
+            zero_copy::append_leaf(&mut data, leaf).expect("Can't append leaf");
+        }
+
+        // Root of sorted keccak pairs, the odd node paired with itself
+        let pair = |a: &[u8; 32], b: &[u8; 32]| {
+            let (left, right) = if a <= b { (a, b) } else { (b, a) };
+            solana_program::keccak::hashv(&[left, right]).to_bytes()
+        };
+        let root = pair(
+            &pair(&pair(&leaves[0], &leaves[1]), &pair(&leaves[2], &leaves[3])),
+            &pair(&pair(&leaves[4], &leaves[5]), &pair(&leaves[6], &leaves[6])),
+        );
+        assert_eq!(merkle_state.get_root_hash(), root);
+        assert_ne!(
+            root,
+            state::Frontier::from_leaves(&HashAlgo::Sha256, &leaves).root(&HashAlgo::Sha256)
+        );
+        assert_eq!(
+            data,
+            borsh::to_vec(&merkle_state).expect("Can't serialize merkle state")
+        );
+
+        for leaf_index in 0..leaves.len() as u32 {
+            let proof = merkle_state.get_proof(leaf_index);
+            assert!(merkle_state.verify_leaf(leaf_index, &leaves[leaf_index as usize], &proof));
+        }
+        merkle_state.enable_frontier_mode();
+        assert_eq!(merkle_state.recompute_root().0, root);
+    }
+
+    #[test]
+    fn poseidon_tree_matches_circom() {
+        // circom poseidon([1, 2])
+        let (mut one, mut two) = ([0u8; 32], [0u8; 32]);
+        (one[31], two[31]) = (1, 2);
+        assert_eq!(
+            HashAlgo::Poseidon.hash_sorted_pair(&two, &one),
+            [
+                17, 92, 192, 245, 231, 214, 144, 65, 61, 246, 76, 107, 150, 98, 233, 207, 42, 54,
+                23, 242, 116, 50, 69, 81, 158, 25, 96, 122, 68, 23, 24, 154
+            ]
+        );
+
+        let domain_separated = TreeHasher {
+            hash_algo: HashAlgo::Poseidon,
+            domain_separation: true,
+            ordered: false,
+            left_balanced: false,
+            zero_padded: false,
+        }
+        .hash_nodes(&one, &two);
+        assert!(HashAlgo::Poseidon.is_valid_leaf(&domain_separated));
+        assert_ne!(
+            domain_separated,
+            HashAlgo::Poseidon.hash_sorted_pair(&one, &two)
+        );
+
+        let leaves: Vec<[u8; 32]> = (0u32..5)
+            .map(|i| HashAlgo::Poseidon.hash_value(&i.to_le_bytes()))
+            .collect();
+        assert!(
+            leaves
+                .iter()
+                .all(|leaf| HashAlgo::Poseidon.is_valid_leaf(leaf))
+        );
+        assert!(!HashAlgo::Poseidon.is_valid_leaf(&HashAlgo::BN254_MODULUS));
+        assert!(HashAlgo::Sha256.is_valid_leaf(&HashAlgo::BN254_MODULUS));
+
+        let mut merkle_state = MerkleStateAccount::new(&leaves[0], &Pubkey::new_unique());
+        merkle_state.set_tree_hasher(HashAlgo::Poseidon.into());
+        leaves[1..]
+            .iter()
+            .for_each(|leaf| merkle_state.add_leaf(leaf));
+        assert_eq!(
+            merkle_state.get_root_hash(),
+            state::Frontier::from_leaves(&HashAlgo::Poseidon, &leaves).root(&HashAlgo::Poseidon)
+        );
+        let proof = merkle_state.get_proof(2);
+        assert!(merkle_state.verify_leaf(2, &leaves[2], &proof));
+
+        // Proof nodes outside the field are rejected rather than aborting
+        let mut bad_proof = proof.clone();
+        bad_proof[0] = [u8::MAX; 32];
+        assert!(!merkle_state.verify_leaf(2, &leaves[2], &bad_proof));
+    }
+
+    #[test]
+    fn hash_algo_is_stored_in_header() {
+        for hash_algo in [
+            HashAlgo::Sha256,
+            HashAlgo::Keccak256,
+            HashAlgo::Poseidon,
+            HashAlgo::Sha256d,
+        ] {
+            let instruction_data = instruction::MerkleTreeInstruction::InitTree {
+                hash: hash_algo.hash_value(b"init"),
+                leaf_capacity: 0,
+                hash_algo,
+                domain_separation: false,
+                ordered: false,
+                left_balanced: false,
+                zero_padded: false,
+            }
+            .pack();
+            assert!(matches!(
+                instruction::MerkleTreeInstruction::unpack(&instruction_data),
+                Ok(instruction::MerkleTreeInstruction::InitTree { hash_algo: unpacked, .. })
+                    if unpacked == hash_algo
+            ));
+
+            let mut merkle_state =
+                MerkleStateAccount::new(&hash_algo.hash_value(b"init"), &Pubkey::new_unique());
+            merkle_state.set_tree_hasher(hash_algo.into());
+            let data = borsh::to_vec(&merkle_state).expect("Can't serialize merkle state");
+            let header = zero_copy::MerkleStateHeader::from_bytes(&data).expect("Invalid header");
+            assert_eq!(header.get_hash_algo(), Ok(hash_algo));
+        }
+
+        // Unknown hash functions are rejected
+        let mut instruction_data = instruction::MerkleTreeInstruction::InitTree {
+            hash: [1u8; 32],
+            leaf_capacity: 0,
+            hash_algo: HashAlgo::Sha256,
+            domain_separation: false,
+            ordered: false,
+            left_balanced: false,
+            zero_padded: false,
+        }
+        .pack();
+        let hash_algo_offset = instruction_data.len() - 5;
+        instruction_data[hash_algo_offset] = u8::MAX;
+        assert!(instruction::MerkleTreeInstruction::unpack(&instruction_data).is_err());
+    }
+
+    #[test]
+    fn domain_separation_rejects_nodes_as_leaves() {
+        let tree_hasher = TreeHasher {
+            hash_algo: HashAlgo::Sha256,
+            domain_separation: true,
+            ordered: false,
+            left_balanced: false,
+            zero_padded: false,
+        };
+        // sha256(0x00)
+        assert_eq!(
+            tree_hasher.hash_value(b""),
+            [
+                110, 52, 11, 156, 255, 179, 122, 152, 156, 165, 68, 230, 187, 120, 10, 44, 120,
+                144, 29, 63, 179, 55, 56, 118, 133, 17, 163, 6, 23, 175, 160, 29
+            ]
+        );
+        // sha256(0x01 || [0; 32] || [1; 32]), pairs are sorted
+        assert_eq!(
+            tree_hasher.hash_nodes(&[1u8; 32], &[0u8; 32]),
+            [
+                42, 216, 44, 58, 81, 232, 237, 100, 24, 203, 91, 242, 103, 197, 249, 229, 33, 185,
+                159, 122, 180, 252, 230, 87, 244, 96, 168, 241, 163, 232, 123, 46
+            ]
+        );
+
+        for domain_separation in [false, true] {
+            let tree_hasher = TreeHasher {
+                hash_algo: HashAlgo::Sha256,
+                domain_separation,
+                ordered: false,
+                left_balanced: false,
+                zero_padded: false,
+            };
+            let leaves: Vec<[u8; 32]> = (0u32..4)
+                .map(|i| tree_hasher.hash_value(&i.to_le_bytes()))
+                .collect();
+            let mut merkle_state = MerkleStateAccount::new(&leaves[0], &Pubkey::new_unique());
+            merkle_state.set_tree_hasher(tree_hasher);
+            leaves[1..]
+                .iter()
+                .for_each(|leaf| merkle_state.add_leaf(leaf));
+            let proof = merkle_state.get_proof(1);
+            assert!(merkle_state.verify_leaf(1, &leaves[1], &proof));
+
+            // Value hashing to the inner node of the first two leaves only passes for a leaf
+            // with the rest of the proof without domain separation
+            let forged_value = [leaves[0].min(leaves[1]), leaves[0].max(leaves[1])].concat();
+            let forged_leaf = tree_hasher.hash_value(&forged_value);
+            assert_eq!(
+                merkle_state.verify_leaf(0, &forged_leaf, &proof[1..]),
+                !domain_separation
+            );
+        }
+    }
+
+    #[test]
+    fn sha256d_tree_matches_bitcoin() {
+        // sha256d("hello")
+        assert_eq!(
+            HashAlgo::Sha256d.hash_value(b"hello"),
+            [
+                149, 149, 201, 223, 144, 7, 81, 72, 235, 6, 134, 3, 101, 223, 51, 88, 75, 117, 191,
+                247, 130, 165, 16, 198, 205, 72, 131, 164, 25, 131, 61, 80
+            ]
+        );
+
+        // Txids of Bitcoin block 100000 in internal byte order, i.e. reversed
+        let leaves: Vec<[u8; 32]> = [
+            "8c14f0db3df150123e6f3dbbf30f8b955a8249b62ac1d1ff16284aefa3d06d87",
+            "fff2525b8931402dd09222c50775608f75787bd2b87e56995a7bdd30f79702c4",
+            "6359f0868171b1d194cbee1af2f16ea598ae8fad666d9b012c8ed2b79a236ec4",
+            "e9a66845e05d5abc0ad04ec80f774a7e585c6e8db975962d069a522137b80c1d",
+        ]
+        .iter()
+        .map(|txid| {
+            let mut leaf: [u8; 32] = std::array::from_fn(|i| {
+                u8::from_str_radix(&txid[2 * i..2 * i + 2], 16).expect("Invalid txid")
+            });
+            leaf.reverse();
+            leaf
+        })
+        .collect();
+        let tree_hasher = TreeHasher {
+            hash_algo: HashAlgo::Sha256d,
+            domain_separation: false,
+            ordered: true,
+            left_balanced: false,
+            zero_padded: false,
+        };
+        let mut merkle_state = MerkleStateAccount::new(&leaves[0], &Pubkey::new_unique());
+        merkle_state.set_tree_hasher(tree_hasher);
+        leaves[1..]
+            .iter()
+            .for_each(|leaf| merkle_state.add_leaf(leaf));
+
+        // Block merkle root f3e94742...a95766, reversed
+        assert_eq!(
+            merkle_state.get_root_hash(),
+            [
+                102, 87, 169, 37, 42, 172, 213, 192, 178, 148, 9, 150, 236, 255, 149, 34, 40, 195,
+                6, 124, 195, 141, 72, 133, 239, 181, 164, 172, 66, 71, 233, 243
+            ]
+        );
+        let proof = merkle_state.get_proof(2);
+        assert!(merkle_state.verify_leaf(2, &leaves[2], &proof));
+    }
+
+    #[test]
+    fn rfc6962_tree_matches_certificate_transparency() {
+        let from_hex = |hex: &str| -> Vec<u8> {
+            (0..hex.len())
+                .step_by(2)
+                .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).expect("Invalid hex"))
+                .collect()
+        };
+        // Leaf inputs and tree heads of every tree size from the RFC 6962 test data of the
+        // certificate-transparency reference implementation
+        let values = [
+            "",
+            "00",
+            "10",
+            "2021",
+            "3031",
+            "40414243",
+            "5051525354555657",
+            "606162636465666768696a6b6c6d6e6f",
+        ];
+        let roots = [
+            "6e340b9cffb37a989ca544e6bb780a2c78901d3fb33738768511a30617afa01d",
+            "fac54203e7cc696cf0dfcb42c92a1d9dbaf70ad9e621f4bd8d98662f00e3c125",
+            "aeb6bcfe274b70a14fb067a5e5578264db0fa9b51af5e0ba159158f329e06e77",
+            "d37ee418976dd95753c1c73862b9398fa2a2cf9b4ff0fdfe8b30cd95209614b7",
+            "4e3bbb1f7b478dcfe71fb631631519a3bca12c9aefca1612bfce4c13a86264d4",
+            "76e67dadbcdf1e10e1b74ddc608abd2f98dfb16fbce75277b5232a127f2087ef",
+            "ddb89be403809e325750d3d263cd78929c2942b7942a34b77e122c9594a74c8c",
+            "5dc9da79a70659a9ad559cb701ded9a2ab9d823aad2f4960cfe370eff4604328",
+        ];
+
+        let tree_hasher = TreeHasher::RFC6962;
+        let leaves: Vec<[u8; 32]> = values
+            .iter()
+            .map(|value| tree_hasher.hash_value(&from_hex(value)))
+            .collect();
+        let mut merkle_state = MerkleStateAccount::new(&leaves[0], &Pubkey::new_unique());
+        merkle_state.set_tree_hasher(tree_hasher);
+        for (leaves_count, root) in roots.iter().enumerate().map(|(i, root)| (i + 1, root)) {
+            if leaves_count > 1 {
+                merkle_state.add_leaf(&leaves[leaves_count - 1]);
+            }
+            assert_eq!(merkle_state.get_root_hash().to_vec(), from_hex(root));
+            assert_eq!(
+                state::Frontier::from_leaves(&tree_hasher, &leaves[..leaves_count])
+                    .root(&tree_hasher),
+                merkle_state.get_root_hash()
+            );
+        }
+
+        // Audit paths skip promoted nodes and only verify at their leaf index
+        for leaf_index in 0..leaves.len() as u32 {
+            let proof = merkle_state.get_truncated_proof(leaf_index);
+            assert!(merkle_state.verify_leaf(leaf_index, &leaves[leaf_index as usize], &proof));
+        }
+        let mut merkle_state = MerkleStateAccount::new(&leaves[0], &Pubkey::new_unique());
+        merkle_state.set_tree_hasher(tree_hasher);
+        leaves[1..7]
+            .iter()
+            .for_each(|leaf| merkle_state.add_leaf(leaf));
+        let proof = merkle_state.get_proof(6);
+        assert_eq!(proof.len(), 2);
+        assert!(merkle_state.verify_leaf(6, &leaves[6], &proof));
+        assert!(!merkle_state.verify_leaf(5, &leaves[6], &proof));
+
+        let proof = merkle_state.get_multiproof(&[0, 6]);
+        assert!(merkle_state.verify_leaves(&[(0, leaves[0]), (6, leaves[6])], &proof));
+        let (leaf, proof) = merkle_state.get_consistency_proof(5);
+        assert!(merkle_state.verify_consistency(
+            &from_hex(roots[4]).try_into().expect("Invalid root"),
+            5,
+            &leaf,
+            &proof
+        ));
+    }
+
+    #[test]
+    fn keccak_tree_matches_openzeppelin() {
+        // `StandardMerkleTree.of([[0x11..11, 5 ether], [0x22..22, 2.5 ether]], ["address",
+        // "uint256"])` from the @openzeppelin/merkle-tree README
+        let standard_leaf = |address: u8, amount: u128| {
+            let mut encoded = [0u8; 64];
+            encoded[12..32].fill(address);
+            encoded[48..].copy_from_slice(&amount.to_be_bytes());
+            HashAlgo::Keccak256.hash_value(&HashAlgo::Keccak256.hash_value(&encoded))
+        };
+        let leaves = [
+            standard_leaf(0x11, 5_000_000_000_000_000_000),
+            standard_leaf(0x22, 2_500_000_000_000_000_000),
+        ];
+        let root = [
+            212, 222, 224, 190, 171, 45, 83, 242, 204, 131, 229, 103, 23, 27, 210, 130, 14, 73,
+            137, 129, 48, 162, 38, 34, 177, 14, 173, 56, 62, 144, 189, 119,
+        ];
+
+        let tree_hasher = TreeHasher::OPENZEPPELIN;
+        let mut merkle_state = MerkleStateAccount::new(&leaves[0], &Pubkey::new_unique());
+        merkle_state.set_tree_hasher(tree_hasher);
+        merkle_state.add_leaf(&leaves[1]);
+        assert_eq!(merkle_state.get_root_hash(), root);
+
+        // With 2 leaves the tree shapes agree, so OpenZeppelin proofs are the sibling paths
+        // verified here
+        let oz_proof = [leaves[1]];
+        assert!(merkle_state.verify_leaf(0, &leaves[0], &oz_proof));
+
+        // `MerkleProof.processProof` folds sorted pairs like `verify::compute_root`, so it
+        // accepts proofs of this program for any leaves count, including a last node paired
+        // with itself
+        merkle_state.add_leaf(&standard_leaf(0x33, 1_000_000_000_000_000_000));
+        let oz_process_proof = |leaf: &[u8; 32], proof: &[[u8; 32]]| {
+            proof.iter().fold(*leaf, |node, sibling| {
+                let (left, right) = if &node <= sibling {
+                    (&node, sibling)
+                } else {
+                    (sibling, &node)
+                };
+                solana_program::keccak::hashv(&[left, right]).to_bytes()
+            })
+        };
+        for (leaf_index, leaf) in merkle_state.leaf_hashes().iter().enumerate() {
+            let proof = merkle_state.get_proof(leaf_index as u32);
+            assert_eq!(oz_process_proof(leaf, &proof), merkle_state.get_root_hash());
+        }
+    }
+
+    #[test]
+    fn ordered_tree_proofs_carry_directions() {
+        let tree_hasher = TreeHasher {
+            hash_algo: HashAlgo::Keccak256,
+            domain_separation: false,
+            ordered: true,
+            left_balanced: false,
+            zero_padded: false,
+        };
+        let leaves: Vec<[u8; 32]> = (0u32..5)
+            .map(|i| tree_hasher.hash_value(&i.to_le_bytes()))
+            .collect();
+        let mut merkle_state = MerkleStateAccount::new(&leaves[0], &Pubkey::new_unique());
+        merkle_state.set_tree_hasher(tree_hasher);
+        leaves[1..]
+            .iter()
+            .for_each(|leaf| merkle_state.add_leaf(leaf));
+
+        // keccak256(left || right) in tree order, the odd node is paired with itself
+        let hash = |left: &[u8; 32], right: &[u8; 32]| {
+            solana_program::keccak::hashv(&[left, right]).to_bytes()
+        };
+        let right = hash(&leaves[4], &leaves[4]);
+        let root = hash(
+            &hash(&hash(&leaves[0], &leaves[1]), &hash(&leaves[2], &leaves[3])),
+            &hash(&right, &right),
+        );
+        assert_eq!(merkle_state.get_root_hash(), root);
+        assert_ne!(
+            root,
+            state::Frontier::from_leaves(&HashAlgo::Keccak256, &leaves).root(&HashAlgo::Keccak256)
+        );
+        assert_eq!(
+            state::Frontier::from_leaves(&tree_hasher, &leaves).root(&tree_hasher),
+            root
+        );
+
+        // Proofs only verify at the leaf index giving their directions
+        for leaf_index in 0..leaves.len() as u32 {
+            let proof = merkle_state.get_proof(leaf_index);
+            assert!(merkle_state.verify_leaf(leaf_index, &leaves[leaf_index as usize], &proof));
+        }
+        let proof = merkle_state.get_proof(1);
+        assert!(!merkle_state.verify_leaf(0, &leaves[1], &proof));
+
+        let proof = merkle_state.get_multiproof(&[1, 2, 4]);
+        assert!(
+            merkle_state.verify_leaves(&[(1, leaves[1]), (2, leaves[2]), (4, leaves[4])], &proof)
+        );
+        let (leaf, proof) = merkle_state.get_consistency_proof(3);
+        let old_root = state::Frontier::from_leaves(&tree_hasher, &leaves[..3]).root(&tree_hasher);
+        assert!(merkle_state.verify_consistency(&old_root, 3, &leaf, &proof));
+
+        let new_leaf = tree_hasher.hash_value(b"new");
+        let proof = merkle_state.get_proof(3);
+        assert!(merkle_state.replace_leaf(3, &leaves[3], &new_leaf, &proof));
+        assert!(merkle_state.verify_leaf(3, &new_leaf, &merkle_state.get_proof(3)));
+
+        // Frontier mode rehashes the replaced path in tree order as well
+        let proof = merkle_state.get_proof(1);
+        let mut leaves = merkle_state.get_leaf_hashes();
+        merkle_state.enable_frontier_mode();
+        assert!(merkle_state.replace_leaf(1, &leaves[1], &new_leaf, &proof));
+        leaves[1] = new_leaf;
+        assert_eq!(
+            merkle_state.get_root_hash(),
+            state::Frontier::from_leaves(&tree_hasher, &leaves).root(&tree_hasher)
+        );
+    }
+
+    #[cfg(feature = "blake3")]
+    #[test]
+    fn blake3_tree_matches_frontier() {
+        let leaves: Vec<[u8; 32]> = (0u32..9)
+            .map(|i| HashAlgo::Blake3.hash_value(&i.to_le_bytes()))
+            .collect();
+        let mut merkle_state = MerkleStateAccount::new(&leaves[0], &Pubkey::new_unique());
+        merkle_state.set_tree_hasher(HashAlgo::Blake3.into());
+        leaves[1..]
+            .iter()
+            .for_each(|leaf| merkle_state.add_leaf(leaf));
+
+        assert_eq!(
+            merkle_state.get_root_hash(),
+            state::Frontier::from_leaves(&HashAlgo::Blake3, &leaves).root(&HashAlgo::Blake3)
+        );
+        assert_eq!(
+            HashAlgo::Blake3.hash_sorted_pair(&leaves[1], &leaves[0]),
+            <[u8; 32]>::from(blake3::hash(
+                &[leaves[0].min(leaves[1]), leaves[0].max(leaves[1])].concat()
+            ))
+        );
+        let proof = merkle_state.get_proof(8);
+        assert!(merkle_state.verify_leaf(8, &leaves[8], &proof));
+    }
+
+    #[tokio::test]
+    async fn success_frontier_mode() {
+        // Setup test env
+        let program_id = Pubkey::new_unique();
+        let (mut banks_client, payer, recent_blockhash) = ProgramTest::new(
+            "merkle_tree_program",
+            program_id,
+            processor!(process_instruction),
+        )
+        .start()
+        .await;
+
+        // Calculate merkle state pda
+        let (merkle_state_pda, _) = find_merkle_state_pda(&program_id);
+        let (event_authority_pda, _) = find_event_authority_pda(&program_id);
+
+        let leaves: Vec<[u8; 32]> = (0u32..7).map(|i| hash_value(&i.to_le_bytes())).collect();
+        let insert_leaf_ix = |hash: [u8; 32]| {
+            Instruction::new_with_bytes(
+                program_id,
+                &instruction::MerkleTreeInstruction::InsertLeaf {
+                    hash,
+                    expected_root: None,
+                }
+                .pack(),
+                vec![
+                    AccountMeta::new(merkle_state_pda, false),
+                    AccountMeta::new(payer.pubkey(), true),
+                    AccountMeta::new_readonly(system_program::id(), false),
+                    AccountMeta::new_readonly(event_authority_pda, false),
+                    AccountMeta::new_readonly(program_id, false),
+                ],
+            )
+        };
+        let enable_frontier_mode_ix = Instruction::new_with_bytes(
+            program_id,
+            &instruction::MerkleTreeInstruction::EnableFrontierMode.pack(),
+            vec![
+                AccountMeta::new(merkle_state_pda, false),
+                AccountMeta::new(payer.pubkey(), true),
+            ],
+        );
+
+        // Insert 3 leaves, drop them, then insert the rest in frontier mode
+        let mut ixs: Vec<Instruction> = leaves[..3]
+            .iter()
+            .map(|leaf| insert_leaf_ix(*leaf))
+            .collect();
+        ixs.push(enable_frontier_mode_ix);
+        ixs.extend(leaves[3..].iter().map(|leaf| insert_leaf_ix(*leaf)));
+        let mut tx = Transaction::new_with_payer(&ixs, Some(&payer.pubkey()));
+        tx.sign(&[&payer], recent_blockhash);
+        banks_client
+            .process_transaction(tx)
+            .await
+            .expect("Can't process tx");
+
+        // Root matches the full tree, only the frontier is stored
+        let mut full_state = MerkleStateAccount::new(&leaves[0], &payer.pubkey());
+        leaves[1..]
+            .iter()
+            .for_each(|leaf| full_state.add_leaf(leaf));
+
+        let Some(merkle_state_account) = banks_client
+            .get_account(merkle_state_pda)
+            .await
+            .expect("Can't get merkle state account")
+        else {
+            panic!("Merkle state account is uninitialized");
+        };
+        let merkle_state = MerkleStateAccount::try_from_slice(&merkle_state_account.data)
+            .expect("Invalid merkle state data");
+        assert_eq!(merkle_state.get_root_hash(), full_state.get_root_hash());
+        assert_eq!(merkle_state.get_leaves_count(), leaves.len());
+        assert!(merkle_state.get_leaf_hashes().is_empty());
+        assert!(
+            merkle_state_account.data.len()
+                < borsh::object_length(&full_state).expect("Can't get full state length")
+        );
+
+        // Full proofs built off-chain still verify
+        let verify_leaf_ix = Instruction::new_with_bytes(
+            program_id,
+            &instruction::MerkleTreeInstruction::VerifyLeaf {
+                leaf_index: 1,
+                leaf: leaves[1],
+                proof: full_state.get_proof(1),
+            }
+            .pack(),
+            vec![AccountMeta::new_readonly(merkle_state_pda, false)],
+        );
+        let mut tx = Transaction::new_with_payer(&[verify_leaf_ix], Some(&payer.pubkey()));
+        tx.sign(&[&payer], recent_blockhash);
+        banks_client
+            .process_transaction(tx)
+            .await
+            .expect("Can't process tx");
+    }
+
+    #[tokio::test]
+    async fn fail_foreign_merkle_state_account() {
+        // Setup test env with a foreign account at the merkle state PDA
+        let program_id = Pubkey::new_unique();
+        let mut program_test = ProgramTest::new(
+            "merkle_tree_program",
+            program_id,
+            processor!(process_instruction),
+        );
+
+        let (merkle_state_pda, _) = find_merkle_state_pda(&program_id);
+        let merkle_state =
+            MerkleStateAccount::new(&hash_value(&1u32.to_le_bytes()), &Pubkey::new_unique());
+        let mut data = borsh::to_vec(&merkle_state).expect("Can't serialize merkle state");
+        data[..8].copy_from_slice(&[1u8; 8]);
+        program_test.add_account(
+            merkle_state_pda,
+            solana_sdk::account::Account {
+                lamports: 1_000_000_000,
+                data,
+                owner: program_id,
+                executable: false,
+                rent_epoch: 0,
+            },
+        );
+        let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+        // Account is rejected before its data is used
+        let get_root_hash_ix = Instruction::new_with_bytes(
+            program_id,
+            &instruction::MerkleTreeInstruction::GetRootHash.pack(),
+            vec![AccountMeta::new_readonly(merkle_state_pda, false)],
+        );
+        let mut tx = Transaction::new_with_payer(&[get_root_hash_ix], Some(&payer.pubkey()));
+        tx.sign(&[&payer], recent_blockhash);
+        let error = banks_client
+            .process_transaction(tx)
+            .await
+            .expect_err("Foreign account must be rejected")
+            .unwrap();
+        assert_eq!(
+            error,
+            TransactionError::InstructionError(
+                0,
+                InstructionError::Custom(MerkleTreeError::InvalidAccountDiscriminator as u32)
+            )
+        );
+
+        // Other layout versions are detected too
+        let mut data = borsh::to_vec(&merkle_state).expect("Can't serialize merkle state");
+        data[8] = MerkleStateAccount::VERSION + 1;
+        assert_eq!(
+            MerkleStateAccount::unpack(&data).expect_err("Version must be rejected"),
+            MerkleTreeError::UnsupportedAccountVersion.into()
+        );
+    }
+
+    #[tokio::test]
+    async fn fail_merkle_state_account_of_other_owner() {
+        // Setup test env with merkle state accounts owned by another program and too short
+        // for the header
+        let program_id = Pubkey::new_unique();
+        let mut program_test = ProgramTest::new(
+            "merkle_tree_program",
+            program_id,
+            processor!(process_instruction),
+        );
+        let (merkle_state_pda, _) = find_merkle_state_pda(&program_id);
+        let merkle_state =
+            MerkleStateAccount::new(&hash_value(&1u32.to_le_bytes()), &Pubkey::new_unique());
+        let data = borsh::to_vec(&merkle_state).expect("Can't serialize merkle state");
+        program_test.add_account(
+            merkle_state_pda,
+            solana_sdk::account::Account {
+                lamports: 1_000_000_000,
+                data: data.clone(),
+                owner: Pubkey::new_unique(),
+                executable: false,
+                rent_epoch: 0,
+            },
+        );
+        let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+        let get_root_hash_ix = Instruction::new_with_bytes(
+            program_id,
+            &instruction::MerkleTreeInstruction::GetRootHash.pack(),
+            vec![AccountMeta::new_readonly(merkle_state_pda, false)],
+        );
+        let mut tx = Transaction::new_with_payer(&[get_root_hash_ix], Some(&payer.pubkey()));
+        tx.sign(&[&payer], recent_blockhash);
+        let error = banks_client
+            .process_transaction(tx)
+            .await
+            .expect_err("Account of other owner must be rejected")
+            .unwrap();
+        assert_eq!(
+            error,
+            TransactionError::InstructionError(
+                0,
+                InstructionError::Custom(MerkleTreeError::InvalidMerkleStateOwner as u32)
+            )
+        );
+
+        let program_id = Pubkey::new_unique();
+        let mut program_test = ProgramTest::new(
+            "merkle_tree_program",
+            program_id,
+            processor!(process_instruction),
+        );
+        let (merkle_state_pda, _) = find_merkle_state_pda(&program_id);
+        program_test.add_account(
+            merkle_state_pda,
+            solana_sdk::account::Account {
+                lamports: 1_000_000_000,
+                data: data[..zero_copy::MerkleStateHeader::LEN - 1].to_vec(),
+                owner: program_id,
+                executable: false,
+                rent_epoch: 0,
+            },
+        );
+        let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+        let get_root_hash_ix = Instruction::new_with_bytes(
+            program_id,
+            &instruction::MerkleTreeInstruction::GetRootHash.pack(),
+            vec![AccountMeta::new_readonly(merkle_state_pda, false)],
+        );
+        let mut tx = Transaction::new_with_payer(&[get_root_hash_ix], Some(&payer.pubkey()));
+        tx.sign(&[&payer], recent_blockhash);
+        let error = banks_client
+            .process_transaction(tx)
+            .await
+            .expect_err("Truncated account must be rejected")
+            .unwrap();
+        assert_eq!(
+            error,
+            TransactionError::InstructionError(
+                0,
+                InstructionError::Custom(MerkleTreeError::StateSizeMismatch as u32)
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn success_migrate_state() {
+        // Setup test env with a version 1 merkle state account
+        let program_id = Pubkey::new_unique();
+        let mut program_test = ProgramTest::new(
+            "merkle_tree_program",
+            program_id,
+            processor!(process_instruction),
+        );
+
+        let (merkle_state_pda, _) = find_merkle_state_pda(&program_id);
+        let (event_authority_pda, _) = find_event_authority_pda(&program_id);
+        let leaves: Vec<[u8; 32]> = (0u32..6).map(|i| hash_value(&i.to_le_bytes())).collect();
+        let mut root_history = [[0u8; 32]; MerkleStateAccount::ROOT_HISTORY_LEN];
+        root_history[0] =
+            state::Frontier::from_leaves(&HashAlgo::Sha256, &leaves[..5]).root(&HashAlgo::Sha256);
+        let legacy_state = state::MerkleStateAccountV1 {
+            discriminator: MerkleStateAccount::DISCRIMINATOR,
+            version: 1,
+            root_hash: root_history[0],
+            root_history,
+            root_history_index: 0,
+            authority: Pubkey::new_unique(),
+            pending_authority: Pubkey::default(),
+            paused: false,
+            reject_duplicates: false,
+            event_verbosity: events::EventVerbosity::Full,
+            canopy_depth: 0,
+            canopy: Vec::new(),
+            archive: None,
+            seal: None,
+            frontier: None,
+            leaf_hashes: leaves[..5].to_vec(),
+        };
+        program_test.add_account(
+            merkle_state_pda,
+            solana_sdk::account::Account {
+                lamports: 1_000_000_000,
+                data: borsh::to_vec(&legacy_state).expect("Can't serialize merkle state"),
+                owner: program_id,
+                executable: false,
+                rent_epoch: 0,
+            },
+        );
+        let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+        // Migrated tree accepts inserts again
+        let migrate_state_ix = Instruction::new_with_bytes(
+            program_id,
+            &instruction::MerkleTreeInstruction::MigrateState.pack(),
+            vec![
+                AccountMeta::new(merkle_state_pda, false),
+                AccountMeta::new(payer.pubkey(), true),
+                AccountMeta::new_readonly(system_program::id(), false),
+            ],
+        );
+        let insert_leaf_ix = Instruction::new_with_bytes(
+            program_id,
+            &instruction::MerkleTreeInstruction::InsertLeaf {
+                hash: leaves[5],
+                expected_root: None,
+            }
+            .pack(),
+            vec![
+                AccountMeta::new(merkle_state_pda, false),
+                AccountMeta::new(payer.pubkey(), true),
+                AccountMeta::new_readonly(system_program::id(), false),
+                AccountMeta::new_readonly(event_authority_pda, false),
+                AccountMeta::new_readonly(program_id, false),
+            ],
+        );
+        let mut tx = Transaction::new_with_payer(
+            &[migrate_state_ix.clone(), migrate_state_ix, insert_leaf_ix],
+            Some(&payer.pubkey()),
+        );
+        tx.sign(&[&payer], recent_blockhash);
+        banks_client
+            .process_transaction(tx)
+            .await
+            .expect("Can't process tx");
+
+        let Some(merkle_state_account) = banks_client
+            .get_account(merkle_state_pda)
+            .await
+            .expect("Can't get merkle state account")
+        else {
+            panic!("Merkle state account is uninitialized");
+        };
+        let merkle_state = MerkleStateAccount::unpack(&merkle_state_account.data)
+            .expect("Invalid merkle state data");
+        assert_eq!(merkle_state.get_leaf_hashes(), &leaves[..]);
+        assert_eq!(merkle_state.get_authority(), legacy_state.authority);
+        assert_eq!(
+            merkle_state.get_root_hash(),
+            state::Frontier::from_leaves(&HashAlgo::Sha256, &leaves).root(&HashAlgo::Sha256)
+        );
+        assert!(
+            merkle_state
+                .get_root_history()
+                .contains(&legacy_state.root_hash)
+        );
+        assert_eq!(merkle_state.leaf_hashes(), &leaves[..]);
+        assert_eq!(
+            merkle_state.root_history().last(),
+            Some(merkle_state.root())
+        );
+    }
+
+    #[tokio::test]
+    async fn success_migrate_headerless_state() {
+        // Setup test env with a headerless merkle state account, as created before the header
+        let program_id = Pubkey::new_unique();
+        let mut program_test = ProgramTest::new(
+            "merkle_tree_program",
+            program_id,
+            processor!(process_instruction),
+        );
+
+        let (merkle_state_pda, _) = find_merkle_state_pda(&program_id);
+        let leaves: Vec<[u8; 32]> = (0u32..5).map(|i| hash_value(&i.to_le_bytes())).collect();
+        let legacy_state = state::MerkleStateAccountV0 {
+            root_hash: state::Frontier::from_leaves(&HashAlgo::Sha256, &leaves)
+                .root(&HashAlgo::Sha256),
+            leaf_hashes: leaves.clone(),
+        };
+        program_test.add_account(
+            merkle_state_pda,
+            solana_sdk::account::Account {
+                lamports: 1_000_000_000,
+                data: borsh::to_vec(&legacy_state).expect("Can't serialize merkle state"),
+                owner: program_id,
+                executable: false,
+                rent_epoch: 0,
+            },
+        );
+        let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+        let migrate_state_ix = Instruction::new_with_bytes(
+            program_id,
+            &instruction::MerkleTreeInstruction::MigrateState.pack(),
+            vec![
+                AccountMeta::new(merkle_state_pda, false),
+                AccountMeta::new(payer.pubkey(), true),
+                AccountMeta::new_readonly(system_program::id(), false),
+            ],
+        );
+        let mut tx = Transaction::new_with_payer(&[migrate_state_ix], Some(&payer.pubkey()));
+        tx.sign(&[&payer], recent_blockhash);
+        banks_client
+            .process_transaction(tx)
+            .await
+            .expect("Can't process tx");
+
+        let Some(merkle_state_account) = banks_client
+            .get_account(merkle_state_pda)
+            .await
+            .expect("Can't get merkle state account")
+        else {
+            panic!("Merkle state account is uninitialized");
+        };
+        let merkle_state = MerkleStateAccount::unpack(&merkle_state_account.data)
+            .expect("Invalid merkle state data");
+        assert_eq!(merkle_state.get_leaf_hashes(), &leaves[..]);
+        assert_eq!(merkle_state.get_root_hash(), legacy_state.root_hash);
+        assert_eq!(merkle_state.get_authority(), Pubkey::default());
+        assert!(
+            merkle_state
+                .get_root_history()
+                .contains(&legacy_state.root_hash)
+        );
+    }
+
+    #[test]
+    fn migrate_headerless_state() {
+        let leaves: Vec<[u8; 32]> = (0u32..5).map(|i| hash_value(&i.to_le_bytes())).collect();
+        let root_hash =
+            state::Frontier::from_leaves(&HashAlgo::Sha256, &leaves).root(&HashAlgo::Sha256);
+        let legacy_state = state::MerkleStateAccountV0 {
+            root_hash,
+            leaf_hashes: leaves.clone(),
+        };
+        let data = borsh::to_vec(&legacy_state).expect("Can't serialize merkle state");
+        assert_eq!(
+            MerkleStateAccount::unpack(&data).expect_err("Headerless state must be rejected"),
+            MerkleTreeError::InvalidAccountDiscriminator.into()
+        );
+
+        // Header and cached nodes are rebuilt, the tree has no authority
+        let mut merkle_state = MerkleStateAccount::migrate(&data)
+            .expect("Can't migrate merkle state")
+            .expect("Headerless state must be migrated");
+        assert_eq!(merkle_state.get_root_hash(), root_hash);
+        assert_eq!(merkle_state.get_leaf_hashes(), &leaves[..]);
+        assert_eq!(merkle_state.get_authority(), Pubkey::default());
+        assert_eq!(merkle_state.get_root_history(), [root_hash]);
+        merkle_state.add_leaf(&hash_value(&5u32.to_le_bytes()));
+        let mut all_leaves = leaves.clone();
+        all_leaves.push(hash_value(&5u32.to_le_bytes()));
+        assert_eq!(
+            merkle_state.get_root_hash(),
+            state::Frontier::from_leaves(&HashAlgo::Sha256, &all_leaves).root(&HashAlgo::Sha256)
+        );
+
+        // Leaves must hash to the stored root, other lengths aren't headerless state
+        let mut tampered = data.clone();
+        tampered[0] ^= 1;
+        assert_eq!(
+            MerkleStateAccount::migrate(&tampered).expect_err("Root mismatch must be rejected"),
+            solana_program::program_error::ProgramError::InvalidAccountData
+        );
+        assert_eq!(
+            MerkleStateAccount::migrate(&data[..data.len() - 1])
+                .expect_err("Truncated state must be rejected"),
+            MerkleTreeError::InvalidAccountDiscriminator.into()
+        );
+    }
+
+    #[test]
+    fn migrate_frontier_state() {
+        let leaves: Vec<[u8; 32]> = (0u32..5).map(|i| hash_value(&i.to_le_bytes())).collect();
+        let frontier = state::Frontier::from_leaves(&HashAlgo::Sha256, &leaves);
+        let mut root_history = [[0u8; 32]; MerkleStateAccount::ROOT_HISTORY_LEN];
+        root_history[0] = frontier.root(&HashAlgo::Sha256);
+        let legacy_state = state::MerkleStateAccountV1 {
+            discriminator: MerkleStateAccount::DISCRIMINATOR,
+            version: 1,
+            root_hash: root_history[0],
+            root_history,
+            root_history_index: 0,
+            authority: Pubkey::new_unique(),
+            pending_authority: Pubkey::default(),
+            paused: true,
+            reject_duplicates: false,
+            event_verbosity: events::EventVerbosity::RootOnly,
+            canopy_depth: 0,
+            canopy: Vec::new(),
+            archive: None,
+            seal: None,
+            frontier: Some(state::FrontierV1 {
+                leaves_count: frontier.leaves_count,
+                nodes: frontier.nodes.clone(),
+            }),
+            leaf_hashes: Vec::new(),
+        };
+        let data = borsh::to_vec(&legacy_state).expect("Can't serialize merkle state");
+        assert_eq!(
+            MerkleStateAccount::unpack(&data).expect_err("Version must be rejected"),
+            MerkleTreeError::UnsupportedAccountVersion.into()
+        );
+
+        // Settings and frontier carry over, the changelog starts empty
+        let mut merkle_state = MerkleStateAccount::migrate(&data)
+            .expect("Can't migrate merkle state")
+            .expect("Version 1 must be migrated");
+        assert!(merkle_state.is_frontier_mode());
+        assert!(merkle_state.is_paused());
+        assert_eq!(
+            merkle_state.get_event_verbosity(),
+            events::EventVerbosity::RootOnly
+        );
+        assert_eq!(merkle_state.get_root_hash(), legacy_state.root_hash);
+        merkle_state.add_leaf(&hash_value(&5u32.to_le_bytes()));
+        assert_eq!(merkle_state.get_leaves_count(), 6);
+
+        let data = borsh::to_vec(&merkle_state).expect("Can't serialize merkle state");
+        assert!(matches!(MerkleStateAccount::migrate(&data), Ok(None)));
+    }
+
+    #[test]
+    fn migrate_version_2_state() {
+        let leaves: Vec<[u8; 32]> = (0u32..5).map(|i| hash_value(&i.to_le_bytes())).collect();
+        let mut merkle_state = MerkleStateAccount::new(&leaves[0], &Pubkey::new_unique());
+        for leaf in &leaves[1..] {
+            merkle_state.add_leaf(leaf);
+        }
+        let mut root_history = [[0u8; 32]; MerkleStateAccount::ROOT_HISTORY_LEN];
+        for (root, history_root) in merkle_state.root_history().zip(&mut root_history) {
+            *history_root = *root;
+        }
+        let legacy_state = state::MerkleStateAccountV2 {
+            discriminator: MerkleStateAccount::DISCRIMINATOR,
+            version: 2,
+            root_hash: *merkle_state.root(),
+            root_history,
+            root_history_index: (leaves.len() - 1) as u8,
+            authority: merkle_state.get_authority(),
+            pending_authority: Pubkey::default(),
+            paused: true,
+            reject_duplicates: false,
+            record_leaf_metadata: false,
+            event_verbosity: events::EventVerbosity::Full,
+            canopy_depth: merkle_state.get_canopy_depth(),
+            leaf_capacity: 8,
+            canopy: merkle_state.canopy().to_vec(),
+            archive: None,
+            seal: None,
+            frontier: None,
+            leaf_pages: false,
+            expiries: Vec::new(),
+            leaf_metadata: Vec::new(),
+            leaf_hashes: leaves.clone(),
+        };
+
+        // Preallocated trees keep spare bytes after the state
+        let mut data = borsh::to_vec(&legacy_state).expect("Can't serialize merkle state");
+        data.resize(data.len() + 3 * MerkleStateAccount::LEAF_LEN, 0);
+        assert_eq!(
+            MerkleStateAccount::unpack(&data).expect_err("Version must be rejected"),
+            MerkleTreeError::UnsupportedAccountVersion.into()
+        );
+
+        // Settings carry over, the tree keeps accepting inserts up to its capacity
+        let mut migrated_state = MerkleStateAccount::migrate(&data)
+            .expect("Can't migrate merkle state")
+            .expect("Version 2 must be migrated");
+        assert_eq!(migrated_state.get_root_hash(), legacy_state.root_hash);
+        assert_eq!(migrated_state.get_leaf_capacity(), Some(8));
+        assert!(migrated_state.is_paused());
+        migrated_state.add_leaf(&hash_value(&5u32.to_le_bytes()));
+        merkle_state.add_leaf(&hash_value(&5u32.to_le_bytes()));
+        assert_eq!(migrated_state.get_root_hash(), merkle_state.get_root_hash());
+
+        let data = borsh::to_vec(&migrated_state).expect("Can't serialize merkle state");
+        assert!(matches!(MerkleStateAccount::migrate(&data), Ok(None)));
+    }
+
+    #[test]
+    fn error_codes_round_trip() {
+        for (code, error) in MerkleTreeError::ALL.iter().enumerate() {
+            assert_eq!(error.code(), code as u32);
+            assert_eq!(MerkleTreeError::from_code(code as u32), Some(*error));
+            assert_eq!(
+                MerkleTreeError::from_instruction_error(&InstructionError::Custom(code as u32)),
+                Some(*error)
+            );
+        }
+        assert_eq!(
+            MerkleTreeError::from_code(MerkleTreeError::ALL.len() as u32),
+            None
+        );
+        assert_eq!(
+            MerkleTreeError::TreeSealed.to_string(),
+            "TreeSealed (error 0xb)"
+        );
+    }
+
+    #[tokio::test]
+    async fn success_insert_value() {
+        // Setup test env
+        let program_id = Pubkey::new_unique();
+        let (mut banks_client, payer, recent_blockhash) = ProgramTest::new(
+            "merkle_tree_program",
+            program_id,
+            processor!(process_instruction),
+        )
+        .start()
+        .await;
+
+        // Calculate merkle state pda
+        let (merkle_state_pda, _) = find_merkle_state_pda(&program_id);
+        let (event_authority_pda, _) = find_event_authority_pda(&program_id);
+
+        // Submit raw values, leaf hashes are computed on-chain
+        let data_values = [b"hello".to_vec(), 1337u32.to_le_bytes().to_vec()];
+        for data in &data_values {
+            let insert_value_ix = Instruction::new_with_bytes(
+                program_id,
+                &instruction::MerkleTreeInstruction::InsertValue {
+                    data: data.clone(),
+                    expected_root: None,
+                }
+                .pack(),
+                vec![
+                    AccountMeta::new(merkle_state_pda, false),
+                    AccountMeta::new(payer.pubkey(), true),
+                    AccountMeta::new_readonly(system_program::id(), false),
+                    AccountMeta::new_readonly(event_authority_pda, false),
+                    AccountMeta::new_readonly(program_id, false),
+                ],
+            );
+
+            let mut tx = Transaction::new_with_payer(&[insert_value_ix], Some(&payer.pubkey()));
+            tx.sign(&[&payer], recent_blockhash);
+            banks_client
+                .process_transaction(tx)
+                .await
+                .expect("Can't process tx");
+        }
+
+        // Leaves match off-chain canonical encoding
+        let Some(merkle_state_account) = banks_client
+            .get_account(merkle_state_pda)
+            .await
+            .expect("Can't get merkle state account")
+        else {
+            panic!("Merkle state account is uninitialized");
+        };
+        let merkle_state = MerkleStateAccount::try_from_slice(&merkle_state_account.data)
+            .expect("Invalid merkle state data");
+        assert_eq!(
+            merkle_state.get_leaf_hashes(),
+            vec![
+                hash_value(b"hello"),
+                Sha256::digest(1337u32.to_le_bytes()).into()
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn success_verify_leaf_with_canopy() {
+        // Setup test env
+        let program_id = Pubkey::new_unique();
+        let (mut banks_client, payer, recent_blockhash) = ProgramTest::new(
+            "merkle_tree_program",
+            program_id,
+            processor!(process_instruction),
+        )
+        .start()
+        .await;
+
+        // Calculate merkle state pda
+        let (merkle_state_pda, _) = find_merkle_state_pda(&program_id);
+        let (event_authority_pda, _) = find_event_authority_pda(&program_id);
+
+        // Submit all leaf hashes
+        let data_hashes: Vec<[u8; 32]> = (1u32..=5)
+            .map(|value| hash_value(&value.to_le_bytes()))
+            .collect();
+        let insert_leaf_ixs: Vec<Instruction> = data_hashes
+            .iter()
+            .map(|hash| {
+                Instruction::new_with_bytes(
+                    program_id,
+                    &instruction::MerkleTreeInstruction::InsertLeaf {
+                        hash: *hash,
+                        expected_root: None,
+                    }
+                    .pack(),
+                    vec![
+                        AccountMeta::new(merkle_state_pda, false),
+                        AccountMeta::new(payer.pubkey(), true),
+                        AccountMeta::new_readonly(system_program::id(), false),
+                        AccountMeta::new_readonly(event_authority_pda, false),
+                        AccountMeta::new_readonly(program_id, false),
+                    ],
+                )
+            })
+            .collect();
+        let mut tx = Transaction::new_with_payer(&insert_leaf_ixs, Some(&payer.pubkey()));
+        tx.sign(&[&payer], recent_blockhash);
+        banks_client
+            .process_transaction(tx)
+            .await
+            .expect("Can't process tx");
+
+        let verify_leaf_ix = |leaf: [u8; 32], proof: Vec<[u8; 32]>| {
+            Instruction::new_with_bytes(
+                program_id,
+                &instruction::MerkleTreeInstruction::VerifyLeaf {
+                    leaf_index: 0,
+                    leaf,
+                    proof,
+                }
+                .pack(),
+                vec![AccountMeta::new_readonly(merkle_state_pda, false)],
+            )
+        };
+
+        // Full proof and proof truncated at the canopy are both accepted
+        let h1 = hash_sorted_pair(&data_hashes[2], &data_hashes[3]);
+        let h2 = hash_sorted_pair(&data_hashes[4], &data_hashes[4]);
+        let h4 = hash_sorted_pair(&h2, &h2);
+        let mut tx = Transaction::new_with_payer(
+            &[
+                verify_leaf_ix(data_hashes[0], vec![data_hashes[1], h1, h4]),
+                verify_leaf_ix(data_hashes[0], vec![data_hashes[1]]),
+            ],
+            Some(&payer.pubkey()),
+        );
+        tx.sign(&[&payer], recent_blockhash);
+        banks_client
+            .process_transaction(tx)
+            .await
+            .expect("Can't process tx");
+
+        // Wrong leaf is rejected
+        let mut tx = Transaction::new_with_payer(
+            &[verify_leaf_ix(data_hashes[2], vec![data_hashes[1]])],
+            Some(&payer.pubkey()),
+        );
+        tx.sign(&[&payer], recent_blockhash);
+        let error = banks_client
+            .process_transaction(tx)
+            .await
+            .expect_err("Verify must fail for wrong leaf")
+            .unwrap();
+        assert_eq!(
+            error,
+            TransactionError::InstructionError(
+                0,
+                InstructionError::Custom(MerkleTreeError::InvalidProof as u32)
+            )
+        );
+    }
+
+    /// Prints `leaves,compute_units` for inserts into a growing tree, requires SBF build, see
+    /// README for comparing builds with and without overflow checks:
+    /// `cargo test-sbf -- --ignored --nocapture report_insert_compute_units`.
+    #[tokio::test]
+    #[ignore]
+    async fn report_insert_compute_units() {
+        // Setup test env with SBF program
+        let program_id = Pubkey::new_unique();
+        let mut program_test = ProgramTest::new("merkle_tree_program", program_id, None);
+        program_test.prefer_bpf(true);
+        let (mut banks_client, payer, _) = program_test.start().await;
+
+        // Calculate merkle state pda
+        let (merkle_state_pda, _) = find_merkle_state_pda(&program_id);
+        let (event_authority_pda, _) = find_event_authority_pda(&program_id);
+
+        println!("leaves,compute_units");
+        for value in 1u32..=64 {
+            let insert_leaf_ix = Instruction::new_with_bytes(
+                program_id,
+                &instruction::MerkleTreeInstruction::InsertLeaf {
+                    hash: hash_value(&value.to_le_bytes()),
+                    expected_root: None,
+                }
+                .pack(),
+                vec![
+                    AccountMeta::new(merkle_state_pda, false),
+                    AccountMeta::new(payer.pubkey(), true),
+                    AccountMeta::new_readonly(system_program::id(), false),
+                    AccountMeta::new_readonly(event_authority_pda, false),
+                    AccountMeta::new_readonly(program_id, false),
+                ],
+            );
+
+            let recent_blockhash = banks_client
+                .get_latest_blockhash()
+                .await
+                .expect("Can't get latest blockhash");
+            let mut tx = Transaction::new_with_payer(&[insert_leaf_ix], Some(&payer.pubkey()));
+            tx.sign(&[&payer], recent_blockhash);
+            let result = banks_client
+                .process_transaction_with_metadata(tx)
+                .await
+                .expect("Can't process tx");
+            let Some(metadata) = result.metadata else {
+                panic!("Tx metadata is empty");
+            };
+
+            println!("{value},{}", metadata.compute_units_consumed);
+        }
+    }
+
+    /// Benchmarked tree sizes(in leaves), small enough for the state to fit the program heap.
+    const BENCH_TREE_SIZES: [usize; 5] = [1, 16, 64, 128, 256];
+
+    /// Start SBF program test holding `merkle_state` of `authority`, which is funded to sign.
+    async fn start_bench_tree(
+        merkle_state: &MerkleStateAccount,
+        authority: &Keypair,
+    ) -> (Pubkey, solana_program_test::BanksClient, Keypair) {
+        let program_id = Pubkey::new_unique();
+        let mut program_test = ProgramTest::new("merkle_tree_program", program_id, None);
+        program_test.prefer_bpf(true);
+
+        let (merkle_state_pda, _) = find_merkle_state_pda(&program_id);
+        program_test.add_account(
+            merkle_state_pda,
+            solana_sdk::account::Account {
+                lamports: 1_000_000_000,
+                data: borsh::to_vec(merkle_state).expect("Can't serialize merkle state"),
+                owner: program_id,
+                executable: false,
+                rent_epoch: 0,
+            },
+        );
+        program_test.add_account(
+            authority.pubkey(),
+            solana_sdk::account::Account {
+                lamports: 1_000_000_000,
+                ..Default::default()
+            },
+        );
+        let (banks_client, payer, _) = program_test.start().await;
+
+        (program_id, banks_client, payer)
+    }
+
+    /// Compute units consumed by `instruction` signed by the payer and `signers`.
+    async fn measure_compute_units(
+        banks_client: &mut solana_program_test::BanksClient,
+        payer: &Keypair,
+        signers: &[&Keypair],
+        instruction: Instruction,
+    ) -> u64 {
+        let recent_blockhash = banks_client
+            .get_latest_blockhash()
+            .await
+            .expect("Can't get latest blockhash");
+        let mut tx = Transaction::new_with_payer(&[instruction], Some(&payer.pubkey()));
+        tx.sign(&[&[payer], signers].concat(), recent_blockhash);
+        let result = banks_client
+            .process_transaction_with_metadata(tx)
+            .await
+            .expect("Can't process tx");
+        result.result.expect("Benchmarked instruction failed");
+        let Some(metadata) = result.metadata else {
+            panic!("Tx metadata is empty");
+        };
+
+        metadata.compute_units_consumed
+    }
+
+    /// Writes `operation,size,compute_units` rows for inserts, replacements, commits and
+    /// (multi)proof verification to `COMPUTE_UNITS_REPORT`(`target/compute_units.csv` by
+    /// default). With `COMPUTE_UNITS_BASELINE` set to an earlier report, fails on rows using
+    /// more compute units than there. Requires SBF build:
+    /// `cargo test-sbf -- --ignored report_compute_units`.
+    #[tokio::test]
+    #[ignore]
+    async fn report_compute_units() {
+        let leaves: Vec<[u8; 32]> = (0u32..=256)
+            .map(|value| hash_value(&value.to_le_bytes()))
+            .collect();
+        let authority = Keypair::new();
+        let tree = |leaves_count: usize, lazy_root: bool| {
+            let mut merkle_state = MerkleStateAccount::new(&leaves[0], &authority.pubkey());
+            merkle_state.set_lazy_root(lazy_root);
+            leaves[1..leaves_count]
+                .iter()
+                .for_each(|leaf| merkle_state.add_leaf(leaf));
+            merkle_state
+        };
+
+        let mut rows = Vec::new();
+        for (operation, size) in BENCH_TREE_SIZES
+            .iter()
+            .flat_map(|&size| {
+                ["insert", "insert_lazy", "replace", "commit_root", "verify"]
+                    .map(|operation| (operation, size))
+            })
+            .chain([1, 2, 4, 8, 16].map(|size| ("verify_multiproof", size)))
+        {
+            let merkle_state = match operation {
+                "insert_lazy" | "commit_root" => tree(size, true),
+                "verify_multiproof" => tree(256, false),
+                _ => tree(size, false),
+            };
+            let (program_id, mut banks_client, payer) =
+                start_bench_tree(&merkle_state, &authority).await;
+            let (merkle_state_pda, _) = find_merkle_state_pda(&program_id);
+            let (event_authority_pda, _) = find_event_authority_pda(&program_id);
+
+            let leaf_index = size as u32 / 2;
+            let (instruction_data, accounts, signers) = match operation {
+                "insert" | "insert_lazy" => (
+                    instruction::MerkleTreeInstruction::InsertLeaf {
+                        hash: leaves[size],
+                        expected_root: None,
+                    },
+                    vec![
+                        AccountMeta::new(merkle_state_pda, false),
+                        AccountMeta::new(payer.pubkey(), true),
+                        AccountMeta::new_readonly(system_program::id(), false),
+                        AccountMeta::new_readonly(event_authority_pda, false),
+                        AccountMeta::new_readonly(program_id, false),
+                    ],
+                    vec![],
+                ),
+                "replace" => (
+                    instruction::MerkleTreeInstruction::ReplaceLeaf {
+                        leaf_index,
+                        old_leaf: leaves[leaf_index as usize],
+                        new_leaf: leaves[size],
+                        proof: merkle_state.get_proof(leaf_index),
+                    },
+                    vec![
+                        AccountMeta::new(merkle_state_pda, false),
+                        AccountMeta::new(authority.pubkey(), true),
+                        AccountMeta::new_readonly(system_program::id(), false),
+                        AccountMeta::new_readonly(event_authority_pda, false),
+                        AccountMeta::new_readonly(program_id, false),
+                    ],
+                    vec![&authority],
+                ),
+                "commit_root" => (
+                    instruction::MerkleTreeInstruction::CommitRoot,
+                    vec![
+                        AccountMeta::new(merkle_state_pda, false),
+                        AccountMeta::new_readonly(event_authority_pda, false),
+                        AccountMeta::new_readonly(program_id, false),
+                    ],
+                    vec![],
+                ),
+                "verify" => (
+                    instruction::MerkleTreeInstruction::VerifyLeaf {
+                        leaf_index,
+                        leaf: leaves[leaf_index as usize],
+                        proof: merkle_state.get_proof(leaf_index),
+                    },
+                    vec![AccountMeta::new_readonly(merkle_state_pda, false)],
+                    vec![],
+                ),
+                _ => {
+                    let leaf_indices: Vec<u32> = (0..size as u32).collect();
+                    (
+                        instruction::MerkleTreeInstruction::VerifyMultiProof {
+                            leaves: leaf_indices
+                                .iter()
+                                .map(|&leaf_index| (leaf_index, leaves[leaf_index as usize]))
+                                .collect(),
+                            proof: merkle_state.get_multiproof(&leaf_indices),
+                        },
+                        vec![AccountMeta::new_readonly(merkle_state_pda, false)],
+                        vec![],
+                    )
+                }
+            };
+            let compute_units = measure_compute_units(
+                &mut banks_client,
+                &payer,
+                &signers,
+                Instruction::new_with_bytes(program_id, &instruction_data.pack(), accounts),
+            )
+            .await;
+            rows.push(format!("{operation},{size},{compute_units}"));
+        }
+
+        let report = format!("operation,size,compute_units\n{}\n", rows.join("\n"));
+        let report_path = std::env::var("COMPUTE_UNITS_REPORT").unwrap_or_else(|_| {
+            concat!(env!("CARGO_MANIFEST_DIR"), "/../target/compute_units.csv").to_string()
+        });
+        std::fs::write(&report_path, &report).expect("Can't write report");
+        print!("{report}");
+
+        // Compare against the baseline row by row, new rows have nothing to regress from
+        let Ok(baseline_path) = std::env::var("COMPUTE_UNITS_BASELINE") else {
+            return;
+        };
+        let baseline = std::fs::read_to_string(baseline_path).expect("Can't read baseline");
+        let baseline: std::collections::HashMap<&str, u64> = baseline
+            .lines()
+            .skip(1)
+            .filter_map(|row| row.rsplit_once(','))
+            .map(|(key, compute_units)| (key, compute_units.parse().expect("Invalid baseline")))
+            .collect();
+        let regressions: Vec<String> = rows
+            .iter()
+            .filter_map(|row| {
+                let (key, compute_units) = row.rsplit_once(',').expect("Invalid row");
+                let compute_units: u64 = compute_units.parse().expect("Invalid row");
+                baseline
+                    .get(key)
+                    .filter(|&&baseline| compute_units > baseline)
+                    .map(|baseline| format!("{key}: {baseline} -> {compute_units}"))
+            })
+            .collect();
+        assert!(
+            regressions.is_empty(),
+            "Compute unit regressions:\n{}",
+            regressions.join("\n")
+        );
+    }
+
+    /// Proof case shared by the pure verifier and on-chain `VerifyLeaf` conformance tests.
+    struct ConformanceCase {
+        leaf_index: u32,
+        leaf: [u8; 32],
+        proof: Vec<[u8; 32]>,
+        is_valid: bool,
+    }
+
+    /// Leaves, root and proof cases of a 5 leaf tree.
+    fn conformance_vectors() -> (Vec<[u8; 32]>, [u8; 32], Vec<ConformanceCase>) {
+        let leaves: Vec<[u8; 32]> = (1u32..=5)
+            .map(|value| hash_value(&value.to_le_bytes()))
+            .collect();
+
+        let h0 = hash_sorted_pair(&leaves[0], &leaves[1]);
+        let h1 = hash_sorted_pair(&leaves[2], &leaves[3]);
+        let h2 = hash_sorted_pair(&leaves[4], &leaves[4]);
+        let h3 = hash_sorted_pair(&h0, &h1);
+        let h4 = hash_sorted_pair(&h2, &h2);
+        let root = hash_sorted_pair(&h3, &h4);
+
+        let cases = vec![
+            ConformanceCase {
+                leaf_index: 0,
+                leaf: leaves[0],
+                proof: vec![leaves[1], h1, h4],
+                is_valid: true,
+            },
+            ConformanceCase {
+                leaf_index: 3,
+                leaf: leaves[3],
+                proof: vec![leaves[2], h0, h4],
+                is_valid: true,
+            },
+            ConformanceCase {
+                leaf_index: 4,
+                leaf: leaves[4],
+                proof: vec![leaves[4], h2, h3],
+                is_valid: true,
+            },
+            ConformanceCase {
+                leaf_index: 0,
+                leaf: leaves[0],
+                proof: vec![leaves[2], h1, h4],
+                is_valid: false,
+            },
+            ConformanceCase {
+                leaf_index: 1,
+                leaf: hash_value(b"unknown"),
+                proof: vec![leaves[0], h1, h4],
+                is_valid: false,
+            },
+        ];
+
+        (leaves, root, cases)
+    }
+
+    #[test]
+    fn conformance_pure_verifier() {
+        let (_, root, cases) = conformance_vectors();
+
+        for case in cases {
+            assert_eq!(
+                verify::verify_proof(&hash_sorted_pair, &root, &case.leaf, &case.proof),
+                case.is_valid
+            );
+        }
+    }
+
+    #[test]
+    fn multi_level_pure_verifier() {
+        // Aggregate root over the conformance tree and a single leaf tree
+        let (_, tree_root, cases) = conformance_vectors();
+        let other_tree_root = hash_value(&6u32.to_le_bytes());
+        let aggregate_root = hash_sorted_pair(&tree_root, &other_tree_root);
+
+        let case = cases
+            .iter()
+            .find(|case| case.is_valid)
+            .expect("No valid case");
+        let proof = verify::ChainedProof::forest(case.proof.clone(), vec![other_tree_root]);
+        assert!(verify::verify_chained_proof(
+            &hash_sorted_pair,
+            &aggregate_root,
+            verify::ProofLevel::Forest,
+            &case.leaf,
+            &proof
+        ));
+
+        // Level metadata must match the root and be ordered bottom-up
+        assert!(!verify::verify_chained_proof(
+            &hash_sorted_pair,
+            &aggregate_root,
+            verify::ProofLevel::Tree,
+            &case.leaf,
+            &proof
+        ));
+        let mut reversed = proof.clone();
+        reversed.segments.reverse();
+        assert_eq!(reversed.compute_root(&hash_sorted_pair, &case.leaf), None);
+        assert!(verify::verify_chained_proof(
+            &hash_sorted_pair,
+            &tree_root,
+            verify::ProofLevel::Tree,
+            &case.leaf,
+            &verify::ChainedProof::tree(case.proof.clone())
+        ));
+    }
+
+    #[test]
+    fn consistency_proofs() {
+        // Record root after every insert
+        let leaves: Vec<[u8; 32]> = (0u32..11).map(|i| hash_value(&i.to_le_bytes())).collect();
+        let mut merkle_state = MerkleStateAccount::new(&leaves[0], &Pubkey::new_unique());
+        let mut roots = vec![merkle_state.get_root_hash()];
+        for leaf in &leaves[1..] {
+            merkle_state.add_leaf(leaf);
+            roots.push(merkle_state.get_root_hash());
+        }
+
+        // Every smaller tree is a prefix of the current one
+        for old_leaves_count in 1..=leaves.len() as u32 {
+            let (leaf, proof) = merkle_state.get_consistency_proof(old_leaves_count);
+            let old_root = roots[old_leaves_count as usize - 1];
+            assert!(merkle_state.verify_consistency(&old_root, old_leaves_count, &leaf, &proof));
+            assert!(!merkle_state.verify_consistency(
+                &old_root,
+                old_leaves_count + 1,
+                &leaf,
+                &proof
+            ));
+        }
+
+        // Rewritten history isn't consistent anymore
+        let (leaf, proof) = merkle_state.get_consistency_proof(6);
+        assert!(merkle_state.replace_leaf(2, &leaves[2], &[0u8; 32], &merkle_state.get_proof(2)));
+        let (_, new_proof) = merkle_state.get_consistency_proof(6);
+        assert!(!merkle_state.verify_consistency(&roots[5], 6, &leaf, &proof));
+        assert!(!merkle_state.verify_consistency(&roots[5], 6, &leaf, &new_proof));
+    }
+
+    #[tokio::test]
+    async fn conformance_onchain_verifier() {
+        // Setup test env
+        let program_id = Pubkey::new_unique();
+        let (mut banks_client, payer, recent_blockhash) = ProgramTest::new(
+            "merkle_tree_program",
+            program_id,
+            processor!(process_instruction),
+        )
+        .start()
+        .await;
+
+        // Calculate merkle state pda
+        let (merkle_state_pda, _) = find_merkle_state_pda(&program_id);
+        let (event_authority_pda, _) = find_event_authority_pda(&program_id);
+
+        // Submit all leaf hashes
+        let (leaves, _, cases) = conformance_vectors();
+        let insert_leaf_ixs: Vec<Instruction> = leaves
+            .iter()
+            .map(|hash| {
+                Instruction::new_with_bytes(
+                    program_id,
+                    &instruction::MerkleTreeInstruction::InsertLeaf {
+                        hash: *hash,
+                        expected_root: None,
+                    }
+                    .pack(),
+                    vec![
+                        AccountMeta::new(merkle_state_pda, false),
+                        AccountMeta::new(payer.pubkey(), true),
+                        AccountMeta::new_readonly(system_program::id(), false),
+                        AccountMeta::new_readonly(event_authority_pda, false),
+                        AccountMeta::new_readonly(program_id, false),
+                    ],
+                )
+            })
+            .collect();
+        let mut tx = Transaction::new_with_payer(&insert_leaf_ixs, Some(&payer.pubkey()));
+        tx.sign(&[&payer], recent_blockhash);
+        banks_client
+            .process_transaction(tx)
+            .await
+            .expect("Can't process tx");
+
+        // On-chain verifier agrees with every case
+        for case in cases {
+            let verify_leaf_ix = Instruction::new_with_bytes(
+                program_id,
+                &instruction::MerkleTreeInstruction::VerifyLeaf {
+                    leaf_index: case.leaf_index,
+                    leaf: case.leaf,
+                    proof: case.proof,
+                }
+                .pack(),
+                vec![AccountMeta::new_readonly(merkle_state_pda, false)],
+            );
+
+            let mut tx = Transaction::new_with_payer(&[verify_leaf_ix], Some(&payer.pubkey()));
+            tx.sign(&[&payer], recent_blockhash);
+            let result = banks_client.process_transaction(tx).await;
+            assert_eq!(result.is_ok(), case.is_valid);
+        }
+    }
+
+    #[tokio::test]
+    async fn success_replace_leaf_with_stale_proof() {
+        // Setup test env
+        let program_id = Pubkey::new_unique();
+        let (mut banks_client, payer, recent_blockhash) = ProgramTest::new(
+            "merkle_tree_program",
+            program_id,
+            processor!(process_instruction),
+        )
+        .start()
+        .await;
+
+        // Calculate merkle state pda
+        let (merkle_state_pda, _) = find_merkle_state_pda(&program_id);
+        let (event_authority_pda, _) = find_event_authority_pda(&program_id);
+
+        // Submit 5 leaves, then one more so the proof below targets a stale root
+        let (mut leaves, stale_root, cases) = conformance_vectors();
+        leaves.push(hash_value(&6u32.to_le_bytes()));
+        let insert_leaf_ixs: Vec<Instruction> = leaves
+            .iter()
+            .map(|hash| {
+                Instruction::new_with_bytes(
+                    program_id,
+                    &instruction::MerkleTreeInstruction::InsertLeaf {
+                        hash: *hash,
+                        expected_root: None,
+                    }
+                    .pack(),
+                    vec![
+                        AccountMeta::new(merkle_state_pda, false),
+                        AccountMeta::new(payer.pubkey(), true),
+                        AccountMeta::new_readonly(system_program::id(), false),
+                        AccountMeta::new_readonly(event_authority_pda, false),
+                        AccountMeta::new_readonly(program_id, false),
+                    ],
+                )
+            })
+            .collect();
+
+        // Replace first leaf using its proof against the stale root
+        let new_leaf = hash_value(b"replaced");
+        let replace_leaf_ix = Instruction::new_with_bytes(
+            program_id,
+            &instruction::MerkleTreeInstruction::ReplaceLeaf {
+                leaf_index: cases[0].leaf_index,
+                old_leaf: cases[0].leaf,
+                new_leaf,
+                proof: cases[0].proof.clone(),
+            }
+            .pack(),
+            vec![
+                AccountMeta::new(merkle_state_pda, false),
+                AccountMeta::new(payer.pubkey(), true),
+                AccountMeta::new_readonly(system_program::id(), false),
+                AccountMeta::new_readonly(event_authority_pda, false),
+                AccountMeta::new_readonly(program_id, false),
+            ],
+        );
+
+        let mut tx = Transaction::new_with_payer(
+            &[insert_leaf_ixs, vec![replace_leaf_ix]].concat(),
+            Some(&payer.pubkey()),
+        );
+        tx.sign(&[&payer], recent_blockhash);
+        banks_client
+            .process_transaction(tx)
+            .await
+            .expect("Can't process tx");
+
+        // Check merkle state after replace
+        let Some(merkle_state_account) = banks_client
+            .get_account(merkle_state_pda)
+            .await
+            .expect("Can't get merkle state account")
+        else {
+            panic!("Merkle state account is uninitialized");
+        };
+        let merkle_state = MerkleStateAccount::try_from_slice(&merkle_state_account.data)
+            .expect("Invalid merkle state data");
+        assert_eq!(merkle_state.get_leaf_hashes()[0], new_leaf);
+        assert!(merkle_state.get_root_history().contains(&stale_root));
+        assert_ne!(merkle_state.get_root_hash(), stale_root);
+    }
+
+    #[tokio::test]
+    async fn success_verify_multiproof() {
+        // Setup test env
+        let program_id = Pubkey::new_unique();
+        let (mut banks_client, payer, recent_blockhash) = ProgramTest::new(
+            "merkle_tree_program",
+            program_id,
+            processor!(process_instruction),
+        )
+        .start()
+        .await;
+
+        // Calculate merkle state pda
+        let (merkle_state_pda, _) = find_merkle_state_pda(&program_id);
+        let (event_authority_pda, _) = find_event_authority_pda(&program_id);
+
+        // Submit all leaf hashes
+        let (leaves, _, _) = conformance_vectors();
+        let insert_leaf_ixs: Vec<Instruction> = leaves
+            .iter()
+            .map(|hash| {
+                Instruction::new_with_bytes(
+                    program_id,
+                    &instruction::MerkleTreeInstruction::InsertLeaf {
+                        hash: *hash,
+                        expected_root: None,
+                    }
+                    .pack(),
+                    vec![
+                        AccountMeta::new(merkle_state_pda, false),
+                        AccountMeta::new(payer.pubkey(), true),
+                        AccountMeta::new_readonly(system_program::id(), false),
+                        AccountMeta::new_readonly(event_authority_pda, false),
+                        AccountMeta::new_readonly(program_id, false),
+                    ],
+                )
+            })
+            .collect();
+        let mut tx = Transaction::new_with_payer(&insert_leaf_ixs, Some(&payer.pubkey()));
+        tx.sign(&[&payer], recent_blockhash);
+        banks_client
+            .process_transaction(tx)
+            .await
+            .expect("Can't process tx");
+
+        // Build multiproof for leaves 0, 1 and 4 off-chain
+        let Some(merkle_state_account) = banks_client
+            .get_account(merkle_state_pda)
+            .await
+            .expect("Can't get merkle state account")
+        else {
+            panic!("Merkle state account is uninitialized");
+        };
+        let merkle_state = MerkleStateAccount::try_from_slice(&merkle_state_account.data)
+            .expect("Invalid merkle state data");
+        let proof = merkle_state.get_multiproof(&[0, 1, 4]);
+
+        // Leaves 0 and 1 share a parent, so only h1 is needed below the root
+        let h1 = hash_sorted_pair(&leaves[2], &leaves[3]);
+        assert_eq!(proof, vec![h1]);
+
+        let verify_multiproof_ix = Instruction::new_with_bytes(
+            program_id,
+            &instruction::MerkleTreeInstruction::VerifyMultiProof {
+                leaves: vec![(0, leaves[0]), (1, leaves[1]), (4, leaves[4])],
+                proof,
+            }
+            .pack(),
+            vec![AccountMeta::new_readonly(merkle_state_pda, false)],
+        );
+        let mut tx = Transaction::new_with_payer(&[verify_multiproof_ix], Some(&payer.pubkey()));
+        tx.sign(&[&payer], recent_blockhash);
+        banks_client
+            .process_transaction(tx)
+            .await
+            .expect("Can't process tx");
+    }
+
+    #[tokio::test]
+    async fn success_set_archive_uri() {
+        // Setup test env
+        let program_id = Pubkey::new_unique();
+        let (mut banks_client, payer, recent_blockhash) = ProgramTest::new(
+            "merkle_tree_program",
+            program_id,
+            processor!(process_instruction),
+        )
+        .start()
+        .await;
+
+        // Calculate merkle state pda
+        let (merkle_state_pda, _) = find_merkle_state_pda(&program_id);
+        let (event_authority_pda, _) = find_event_authority_pda(&program_id);
+
+        // Submit all leaf hashes
+        let (leaves, root, _) = conformance_vectors();
+        let insert_leaf_ixs: Vec<Instruction> = leaves
+            .iter()
+            .map(|hash| {
+                Instruction::new_with_bytes(
+                    program_id,
+                    &instruction::MerkleTreeInstruction::InsertLeaf {
+                        hash: *hash,
+                        expected_root: None,
+                    }
+                    .pack(),
+                    vec![
+                        AccountMeta::new(merkle_state_pda, false),
+                        AccountMeta::new(payer.pubkey(), true),
+                        AccountMeta::new_readonly(system_program::id(), false),
+                        AccountMeta::new_readonly(event_authority_pda, false),
+                        AccountMeta::new_readonly(program_id, false),
+                    ],
+                )
+            })
+            .collect();
+        let mut tx = Transaction::new_with_payer(&insert_leaf_ixs, Some(&payer.pubkey()));
+        tx.sign(&[&payer], recent_blockhash);
+        banks_client
+            .process_transaction(tx)
+            .await
+            .expect("Can't process tx");
+
+        let set_archive_uri_ix = |content_hash: [u8; 32]| {
+            Instruction::new_with_bytes(
+                program_id,
+                &instruction::MerkleTreeInstruction::SetArchiveUri {
+                    root,
+                    content_hash,
+                    uri: "ipfs://snapshot".into(),
+                }
+                .pack(),
+                vec![
+                    AccountMeta::new(merkle_state_pda, false),
+                    AccountMeta::new(payer.pubkey(), true),
+                    AccountMeta::new_readonly(system_program::id(), false),
+                ],
+            )
+        };
+
+        // Snapshot missing the last leaf is rejected
+        let mut tx = Transaction::new_with_payer(
+            &[set_archive_uri_ix(hash_leaves(&leaves[..4]))],
+            Some(&payer.pubkey()),
+        );
+        tx.sign(&[&payer], recent_blockhash);
+        let error = banks_client
+            .process_transaction(tx)
+            .await
+            .expect_err("Archive must fail for partial snapshot")
+            .unwrap();
+        assert_eq!(
+            error,
+            TransactionError::InstructionError(
+                0,
+                InstructionError::Custom(MerkleTreeError::InvalidArchive as u32)
+            )
+        );
+
+        // Full snapshot is recorded
+        let mut tx = Transaction::new_with_payer(
+            &[set_archive_uri_ix(hash_leaves(&leaves))],
+            Some(&payer.pubkey()),
+        );
+        tx.sign(&[&payer], recent_blockhash);
+        banks_client
+            .process_transaction(tx)
+            .await
+            .expect("Can't process tx");
+
+        let Some(merkle_state_account) = banks_client
+            .get_account(merkle_state_pda)
+            .await
+            .expect("Can't get merkle state account")
+        else {
+            panic!("Merkle state account is uninitialized");
+        };
+        let merkle_state = MerkleStateAccount::try_from_slice(&merkle_state_account.data)
+            .expect("Invalid merkle state data");
+        let archive = merkle_state.get_archive().expect("Archive is not set");
+        assert_eq!(archive.root, root);
+        assert_eq!(archive.uri, "ipfs://snapshot");
+    }
+
+    #[tokio::test]
+    async fn success_get_root_hash() {
+        // Setup test env
+        let program_id = Pubkey::new_unique();
+        let (mut banks_client, payer, recent_blockhash) = ProgramTest::new(
+            "merkle_tree_program",
+            program_id,
+            processor!(process_instruction),
+        )
+        .start()
+        .await;
+
+        // Calculate merkle state pda
+        let (merkle_state_pda, _) = find_merkle_state_pda(&program_id);
+        let (event_authority_pda, _) = find_event_authority_pda(&program_id);
+
+        // Submit all leaf hashes
+        let (leaves, root, _) = conformance_vectors();
+        let insert_leaf_ixs: Vec<Instruction> = leaves
+            .iter()
+            .map(|hash| {
+                Instruction::new_with_bytes(
+                    program_id,
+                    &instruction::MerkleTreeInstruction::InsertLeaf {
+                        hash: *hash,
+                        expected_root: None,
+                    }
+                    .pack(),
+                    vec![
+                        AccountMeta::new(merkle_state_pda, false),
+                        AccountMeta::new(payer.pubkey(), true),
+                        AccountMeta::new_readonly(system_program::id(), false),
+                        AccountMeta::new_readonly(event_authority_pda, false),
+                        AccountMeta::new_readonly(program_id, false),
+                    ],
+                )
+            })
+            .collect();
+        let mut tx = Transaction::new_with_payer(&insert_leaf_ixs, Some(&payer.pubkey()));
+        tx.sign(&[&payer], recent_blockhash);
+        banks_client
+            .process_transaction(tx)
+            .await
+            .expect("Can't process tx");
+
+        // Simulate getter and decode its return data
+        let get_root_hash_ix = Instruction::new_with_bytes(
+            program_id,
+            &instruction::MerkleTreeInstruction::GetRootHash.pack(),
+            vec![AccountMeta::new_readonly(merkle_state_pda, false)],
+        );
+        let mut tx = Transaction::new_with_payer(&[get_root_hash_ix], Some(&payer.pubkey()));
+        tx.sign(&[&payer], recent_blockhash);
+        let return_data = banks_client
+            .simulate_transaction(tx)
+            .await
+            .expect("Can't simulate tx")
+            .simulation_details
+            .and_then(|details| details.return_data)
+            .expect("Return data is empty");
+
+        let root_hash: [u8; 32] =
+            ReturnData::decode(&program_id, &return_data.program_id, &return_data.data)
+                .expect("Invalid return data");
+        assert_eq!(root_hash, root);
+        assert!(matches!(
+            ReturnData::<[u8; 32]>::decode(
+                &Pubkey::new_unique(),
+                &return_data.program_id,
+                &return_data.data
+            ),
+            Err(ReturnDataError::WrongProgram { .. })
+        ));
+    }
+
+    #[test]
+    fn return_data_with_stripped_zeros() {
+        // Runtime strips trailing zeros of return data outside of CPI
+        let program_id = Pubkey::new_unique();
+        let mut root = hash_value(b"root");
+        root[24..].fill(0);
+
+        let root_hash: [u8; 32] =
+            ReturnData::decode(&program_id, &program_id, &root[..24]).expect("Invalid return data");
+        assert_eq!(root_hash, root);
+    }
+
+    #[tokio::test]
+    async fn success_emit_events() {
+        // Setup test env
+        let program_id = Pubkey::new_unique();
+        let (mut banks_client, payer, recent_blockhash) = ProgramTest::new(
+            "merkle_tree_program",
+            program_id,
+            processor!(process_instruction),
+        )
+        .start()
+        .await;
+
+        // Calculate merkle state and event authority pda
+        let (merkle_state_pda, _) = find_merkle_state_pda(&program_id);
+        let (event_authority_pda, _) = find_event_authority_pda(&program_id);
+
+        // Simulate first insert, banks client doesn't record inner instructions so check
+        // self-CPI invocations in logs
+        let hash = hash_value(&1u32.to_le_bytes());
+        let insert_leaf_ix = Instruction::new_with_bytes(
+            program_id,
+            &instruction::MerkleTreeInstruction::InsertLeaf {
+                hash,
+                expected_root: None,
+            }
+            .pack(),
+            vec![
+                AccountMeta::new(merkle_state_pda, false),
+                AccountMeta::new(payer.pubkey(), true),
+                AccountMeta::new_readonly(system_program::id(), false),
+                AccountMeta::new_readonly(event_authority_pda, false),
+                AccountMeta::new_readonly(program_id, false),
+            ],
+        );
+        let mut tx = Transaction::new_with_payer(&[insert_leaf_ix], Some(&payer.pubkey()));
+        tx.sign(&[&payer], recent_blockhash);
+        let logs = banks_client
+            .simulate_transaction(tx)
+            .await
+            .expect("Can't simulate tx")
+            .simulation_details
+            .expect("Simulation details are empty")
+            .logs;
+        let self_invoke_log = format!("Program {program_id} invoke [2]");
+        assert_eq!(
+            logs.iter().filter(|log| **log == self_invoke_log).count(),
+            2
+        );
+
+        // Event instruction data round trip
+        let event = RootUpdated {
+            root: hash,
+            leaves_count: 1,
+        };
+        assert_eq!(
+            MerkleTreeEvent::decode(&event.to_instruction_data()),
+            Some(MerkleTreeEvent::RootUpdated(event))
+        );
+
+        // Events can't be emitted from outside of the program
+        let emit_event_ix = Instruction::new_with_bytes(
+            program_id,
+            &RootUpdated {
+                root: [0u8; 32],
+                leaves_count: 0,
+            }
+            .to_instruction_data(),
+            vec![AccountMeta::new_readonly(event_authority_pda, false)],
+        );
+        let mut tx = Transaction::new_with_payer(&[emit_event_ix], Some(&payer.pubkey()));
+        tx.sign(&[&payer], recent_blockhash);
+        let error = banks_client
+            .process_transaction(tx)
+            .await
+            .expect_err("Event must fail without event authority signature")
+            .unwrap();
+        assert_eq!(
+            error,
+            TransactionError::InstructionError(
+                0,
+                InstructionError::Custom(MerkleTreeError::InvalidEventAuthority as u32)
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn success_set_event_verbosity() {
+        // Setup test env
+        let program_id = Pubkey::new_unique();
+        let (mut banks_client, payer, recent_blockhash) = ProgramTest::new(
+            "merkle_tree_program",
+            program_id,
+            processor!(process_instruction),
+        )
+        .start()
+        .await;
+
+        // Calculate merkle state and event authority pda
+        let (merkle_state_pda, _) = find_merkle_state_pda(&program_id);
+        let (event_authority_pda, _) = find_event_authority_pda(&program_id);
+
+        let insert_leaf_ix = |value: u32| {
+            Instruction::new_with_bytes(
+                program_id,
+                &instruction::MerkleTreeInstruction::InsertLeaf {
+                    hash: hash_value(&value.to_le_bytes()),
+                    expected_root: None,
+                }
+                .pack(),
+                vec![
+                    AccountMeta::new(merkle_state_pda, false),
+                    AccountMeta::new(payer.pubkey(), true),
+                    AccountMeta::new_readonly(system_program::id(), false),
+                    AccountMeta::new_readonly(event_authority_pda, false),
+                    AccountMeta::new_readonly(program_id, false),
+                ],
+            )
+        };
+        let set_event_verbosity_ix = |event_verbosity| {
+            Instruction::new_with_bytes(
+                program_id,
+                &instruction::MerkleTreeInstruction::SetEventVerbosity { event_verbosity }.pack(),
+                vec![
+                    AccountMeta::new(merkle_state_pda, false),
+                    AccountMeta::new_readonly(payer.pubkey(), true),
+                ],
+            )
+        };
+        let self_invoke_log = format!("Program {program_id} invoke [2]");
+
+        let mut tx = Transaction::new_with_payer(&[insert_leaf_ix(1)], Some(&payer.pubkey()));
+        tx.sign(&[&payer], recent_blockhash);
+        banks_client
+            .process_transaction(tx)
+            .await
+            .expect("Can't process tx");
+
+        // Each verbosity emits its own number of events on insert
+        for (event_verbosity, events_count) in [
+            (events::EventVerbosity::RootOnly, 1),
+            (events::EventVerbosity::None, 0),
+            (events::EventVerbosity::Full, 2),
+        ] {
+            let mut tx = Transaction::new_with_payer(
+                &[set_event_verbosity_ix(event_verbosity)],
+                Some(&payer.pubkey()),
+            );
+            tx.sign(&[&payer], recent_blockhash);
+            banks_client
+                .process_transaction(tx)
+                .await
+                .expect("Can't process tx");
+
+            let mut tx = Transaction::new_with_payer(&[insert_leaf_ix(2)], Some(&payer.pubkey()));
+            tx.sign(&[&payer], recent_blockhash);
+            let logs = banks_client
+                .simulate_transaction(tx)
+                .await
+                .expect("Can't simulate tx")
+                .simulation_details
+                .expect("Simulation details are empty")
+                .logs;
+            assert_eq!(
+                logs.iter().filter(|log| **log == self_invoke_log).count(),
+                events_count
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn success_sweep_expired() {
+        // Setup test env
+        let program_id = Pubkey::new_unique();
+        let (mut banks_client, payer, recent_blockhash) = ProgramTest::new(
+            "merkle_tree_program",
+            program_id,
+            processor!(process_instruction),
+        )
+        .start()
+        .await;
+
+        // Calculate merkle state and event authority pda
+        let (merkle_state_pda, _) = find_merkle_state_pda(&program_id);
+        let (event_authority_pda, _) = find_event_authority_pda(&program_id);
+
+        let insert_leaf_ix = |instruction: instruction::MerkleTreeInstruction| {
+            Instruction::new_with_bytes(
+                program_id,
+                &instruction.pack(),
+                vec![
+                    AccountMeta::new(merkle_state_pda, false),
+                    AccountMeta::new(payer.pubkey(), true),
+                    AccountMeta::new_readonly(system_program::id(), false),
+                    AccountMeta::new_readonly(event_authority_pda, false),
+                    AccountMeta::new_readonly(program_id, false),
+                ],
+            )
+        };
+        let sweep_expired_ix = Instruction::new_with_bytes(
+            program_id,
+            &instruction::MerkleTreeInstruction::SweepExpired.pack(),
+            vec![
+                AccountMeta::new(merkle_state_pda, false),
+                AccountMeta::new(payer.pubkey(), true),
+                AccountMeta::new_readonly(event_authority_pda, false),
+                AccountMeta::new_readonly(program_id, false),
+            ],
+        );
+
+        // Insert a plain, an expired and a not yet expired leaf
+        let leaves: Vec<[u8; 32]> = (0u32..3).map(|i| hash_value(&i.to_le_bytes())).collect();
+        let mut tx = Transaction::new_with_payer(
+            &[
+                insert_leaf_ix(instruction::MerkleTreeInstruction::InsertLeaf {
+                    hash: leaves[0],
+                    expected_root: None,
+                }),
+                insert_leaf_ix(instruction::MerkleTreeInstruction::InsertExpiringLeaf {
+                    hash: leaves[1],
+                    expires_at: 0,
+                    expected_root: None,
+                }),
+                insert_leaf_ix(instruction::MerkleTreeInstruction::InsertExpiringLeaf {
+                    hash: leaves[2],
+                    expires_at: i64::MAX,
+                    expected_root: None,
+                }),
+            ],
+            Some(&payer.pubkey()),
+        );
+        tx.sign(&[&payer], recent_blockhash);
+        banks_client
+            .process_transaction(tx)
+            .await
+            .expect("Can't process tx");
+        let lamports = banks_client
+            .get_balance(merkle_state_pda)
+            .await
+            .expect("Can't get merkle state balance");
+
+        // Sweep tombstones the expired leaf only, paying the bounty from freed rent
+        let mut tx = Transaction::new_with_payer(
+            std::slice::from_ref(&sweep_expired_ix),
+            Some(&payer.pubkey()),
+        );
+        tx.sign(&[&payer], recent_blockhash);
+        banks_client
+            .process_transaction(tx)
+            .await
+            .expect("Can't process tx");
+
+        let Some(merkle_state_account) = banks_client
+            .get_account(merkle_state_pda)
+            .await
+            .expect("Can't get merkle state account")
+        else {
+            panic!("Merkle state account is uninitialized");
+        };
+        assert_eq!(
+            merkle_state_account.lamports,
+            lamports - MerkleStateAccount::SWEEP_BOUNTY_LAMPORTS
+        );
+        let merkle_state = MerkleStateAccount::unpack(&merkle_state_account.data)
+            .expect("Invalid merkle state data");
+        let mut expected_state = MerkleStateAccount::new(&leaves[0], &payer.pubkey());
+        expected_state.add_leaf(&MerkleStateAccount::TOMBSTONE);
+        expected_state.add_leaf(&leaves[2]);
+        assert_eq!(merkle_state.get_root_hash(), expected_state.get_root_hash());
+        assert_eq!(
+            merkle_state.get_leaf_expiries(),
+            &[state::LeafExpiry {
+                leaf_index: 2,
+                expires_at: i64::MAX,
+            }]
+        );
+
+        // Nothing left to sweep after a plain insert
+        let mut tx = Transaction::new_with_payer(
+            &[
+                insert_leaf_ix(instruction::MerkleTreeInstruction::InsertLeaf {
+                    hash: hash_value(&3u32.to_le_bytes()),
+                    expected_root: None,
+                }),
+                sweep_expired_ix,
+            ],
+            Some(&payer.pubkey()),
+        );
+        tx.sign(&[&payer], recent_blockhash);
+        let error = banks_client
+            .process_transaction(tx)
+            .await
+            .expect_err("Sweep must fail without expired leaves")
+            .unwrap();
+        assert_eq!(
+            error,
+            TransactionError::InstructionError(
+                1,
+                InstructionError::Custom(MerkleTreeError::NoExpiredLeaves as u32)
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn success_record_leaf_metadata() {
+        // Setup test env
+        let program_id = Pubkey::new_unique();
+        let (mut banks_client, payer, recent_blockhash) = ProgramTest::new(
+            "merkle_tree_program",
+            program_id,
+            processor!(process_instruction),
+        )
+        .start()
+        .await;
+
+        // Calculate merkle state and event authority pda
+        let (merkle_state_pda, _) = find_merkle_state_pda(&program_id);
+        let (event_authority_pda, _) = find_event_authority_pda(&program_id);
+
+        let insert_leaf_ix = |value: u32| {
+            Instruction::new_with_bytes(
+                program_id,
+                &instruction::MerkleTreeInstruction::InsertLeaf {
+                    hash: hash_value(&value.to_le_bytes()),
+                    expected_root: None,
+                }
+                .pack(),
+                vec![
+                    AccountMeta::new(merkle_state_pda, false),
+                    AccountMeta::new(payer.pubkey(), true),
+                    AccountMeta::new_readonly(system_program::id(), false),
+                    AccountMeta::new_readonly(event_authority_pda, false),
+                    AccountMeta::new_readonly(program_id, false),
+                ],
+            )
+        };
+        let set_record_leaf_metadata_ix = Instruction::new_with_bytes(
+            program_id,
+            &instruction::MerkleTreeInstruction::SetRecordLeafMetadata {
+                record_leaf_metadata: true,
+            }
+            .pack(),
+            vec![
+                AccountMeta::new(merkle_state_pda, false),
+                AccountMeta::new_readonly(payer.pubkey(), true),
+            ],
+        );
+
+        // Only leaves inserted after enabling get metadata
+        let mut tx = Transaction::new_with_payer(
+            &[
+                insert_leaf_ix(0),
+                set_record_leaf_metadata_ix,
+                insert_leaf_ix(1),
+                insert_leaf_ix(2),
+            ],
+            Some(&payer.pubkey()),
+        );
+        tx.sign(&[&payer], recent_blockhash);
+        banks_client
+            .process_transaction(tx)
+            .await
+            .expect("Can't process tx");
+
+        let Some(merkle_state_account) = banks_client
+            .get_account(merkle_state_pda)
+            .await
+            .expect("Can't get merkle state account")
+        else {
+            panic!("Merkle state account is uninitialized");
+        };
+        let merkle_state = MerkleStateAccount::unpack(&merkle_state_account.data)
+            .expect("Invalid merkle state data");
+        assert!(merkle_state.records_leaf_metadata());
+        assert_eq!(merkle_state.get_leaf_metadata(0), None);
+        assert_eq!(merkle_state.get_leaves_metadata().len(), 2);
+        for leaf_index in 1..3 {
+            let metadata = merkle_state
+                .get_leaf_metadata(leaf_index)
+                .expect("Leaf metadata is not recorded");
+            assert_eq!(metadata.inserter, payer.pubkey());
+        }
+
+        let mut expected_state =
+            MerkleStateAccount::new(&hash_value(&0u32.to_le_bytes()), &payer.pubkey());
+        (1u32..3).for_each(|value| expected_state.add_leaf(&hash_value(&value.to_le_bytes())));
+        assert_eq!(merkle_state.get_root_hash(), expected_state.get_root_hash());
+    }
+
+    #[tokio::test]
+    async fn success_leaf_pages() {
+        // Setup test env
+        let program_id = Pubkey::new_unique();
+        let (mut banks_client, payer, recent_blockhash) = ProgramTest::new(
+            "merkle_tree_program",
+            program_id,
+            processor!(process_instruction),
+        )
+        .start()
+        .await;
+
+        // Calculate merkle state, event authority and leaf page pdas
+        let (merkle_state_pda, _) = find_merkle_state_pda(&program_id);
+        let (event_authority_pda, _) = find_event_authority_pda(&program_id);
+        let leaf_page_pda =
+            |page_index| pda::find_leaf_page_pda(&program_id, &merkle_state_pda, page_index).0;
+
+        let leaves: Vec<[u8; 32]> = (0u32..4).map(|i| hash_value(&i.to_le_bytes())).collect();
+        let insert_leaf_ix = |hash: [u8; 32], leaf_page: Option<Pubkey>| {
+            let mut accounts = vec![
+                AccountMeta::new(merkle_state_pda, false),
+                AccountMeta::new(payer.pubkey(), true),
+                AccountMeta::new_readonly(system_program::id(), false),
+                AccountMeta::new_readonly(event_authority_pda, false),
+                AccountMeta::new_readonly(program_id, false),
+            ];
+            accounts.extend(leaf_page.map(|leaf_page| AccountMeta::new(leaf_page, false)));
+            Instruction::new_with_bytes(
+                program_id,
+                &instruction::MerkleTreeInstruction::InsertLeaf {
+                    hash,
+                    expected_root: None,
+                }
+                .pack(),
+                accounts,
+            )
+        };
+        let enable_leaf_pages_ix = Instruction::new_with_bytes(
+            program_id,
+            &instruction::MerkleTreeInstruction::EnableLeafPages {
+                first_page: 0,
+                pages_count: 1,
+            }
+            .pack(),
+            vec![
+                AccountMeta::new(merkle_state_pda, false),
+                AccountMeta::new(payer.pubkey(), true),
+                AccountMeta::new_readonly(system_program::id(), false),
+                AccountMeta::new(leaf_page_pda(0), false),
+            ],
+        );
+
+        // Move 3 stored leaves to the first page and insert one more into it
+        let mut tx = Transaction::new_with_payer(
+            &[
+                insert_leaf_ix(leaves[0], None),
+                insert_leaf_ix(leaves[1], None),
+                insert_leaf_ix(leaves[2], None),
+                enable_leaf_pages_ix,
+                insert_leaf_ix(leaves[3], Some(leaf_page_pda(0))),
+            ],
+            Some(&payer.pubkey()),
+        );
+        tx.sign(&[&payer], recent_blockhash);
+        banks_client
+            .process_transaction(tx)
+            .await
+            .expect("Can't process tx");
+
+        let Some(leaf_page_account) = banks_client
+            .get_account(leaf_page_pda(0))
+            .await
+            .expect("Can't get leaf page account")
+        else {
+            panic!("Leaf page account is uninitialized");
+        };
+        let page_leaves = leaf_page::leaves(&leaf_page_account.data).expect("Invalid leaf page");
+        assert_eq!(&page_leaves[..4], &leaves[..]);
+        assert!(page_leaves[4..].iter().all(|leaf| *leaf == [0u8; 32]));
+
+        let Some(merkle_state_account) = banks_client
+            .get_account(merkle_state_pda)
+            .await
+            .expect("Can't get merkle state account")
+        else {
+            panic!("Merkle state account is uninitialized");
+        };
+        let merkle_state = MerkleStateAccount::unpack(&merkle_state_account.data)
+            .expect("Invalid merkle state data");
+        assert!(merkle_state.has_leaf_pages());
+        assert!(merkle_state.get_leaf_hashes().is_empty());
+        let mut expected_state = MerkleStateAccount::new(&leaves[0], &payer.pubkey());
+        leaves[1..]
+            .iter()
+            .for_each(|leaf| expected_state.add_leaf(leaf));
+        assert_eq!(merkle_state.get_root_hash(), expected_state.get_root_hash());
+
+        // Leaf must go to its own page
+        let mut tx = Transaction::new_with_payer(
+            &[insert_leaf_ix(
+                hash_value(b"wrong page"),
+                Some(leaf_page_pda(1)),
+            )],
+            Some(&payer.pubkey()),
+        );
+        tx.sign(&[&payer], recent_blockhash);
+        let error = banks_client
+            .process_transaction(tx)
+            .await
+            .expect_err("Insert must fail for wrong leaf page")
+            .unwrap();
+        assert_eq!(
+            error,
+            TransactionError::InstructionError(
+                0,
+                InstructionError::Custom(MerkleTreeError::InvalidLeafPage as u32)
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn success_leaf_pages_over_several_calls() {
+        // Setup test env with a tree spanning two leaf pages
+        let program_id = Pubkey::new_unique();
+        let mut program_test = ProgramTest::new(
+            "merkle_tree_program",
+            program_id,
+            processor!(process_instruction),
+        );
+
+        let (merkle_state_pda, _) = find_merkle_state_pda(&program_id);
+        let leaf_page_pda =
+            |page_index| pda::find_leaf_page_pda(&program_id, &merkle_state_pda, page_index).0;
+        let leaves: Vec<[u8; 32]> = (0u32..300).map(|i| hash_value(&i.to_le_bytes())).collect();
+        let authority = Keypair::new();
+        let mut merkle_state = MerkleStateAccount::new(&leaves[0], &authority.pubkey());
+        leaves[1..]
+            .iter()
+            .for_each(|leaf| merkle_state.add_leaf(leaf));
+        program_test.add_account(
+            merkle_state_pda,
+            solana_sdk::account::Account {
+                lamports: 1_000_000_000,
+                data: borsh::to_vec(&merkle_state).expect("Can't serialize merkle state"),
+                owner: program_id,
+                executable: false,
+                rent_epoch: 0,
+            },
+        );
+        let (mut banks_client, payer, recent_blockhash) = program_test.start().await;
+
+        let enable_leaf_pages_ix = |first_page: u32| {
+            Instruction::new_with_bytes(
+                program_id,
+                &instruction::MerkleTreeInstruction::EnableLeafPages {
+                    first_page,
+                    pages_count: 1,
+                }
+                .pack(),
+                vec![
+                    AccountMeta::new(merkle_state_pda, false),
+                    AccountMeta::new(authority.pubkey(), true),
+                    AccountMeta::new_readonly(system_program::id(), false),
+                    AccountMeta::new(leaf_page_pda(first_page), false),
+                ],
+            )
+        };
+        let fund_authority_ix = solana_sdk::system_instruction::transfer(
+            &payer.pubkey(),
+            &authority.pubkey(),
+            1_000_000_000,
+        );
+
+        // First call pages the first 256 leaves and keeps the stored ones
+        let mut tx = Transaction::new_with_payer(
+            &[fund_authority_ix, enable_leaf_pages_ix(0)],
+            Some(&payer.pubkey()),
+        );
+        tx.sign(&[&payer, &authority], recent_blockhash);
+        banks_client
+            .process_transaction(tx)
+            .await
+            .expect("Can't process tx");
+
+        let Some(merkle_state_account) = banks_client
+            .get_account(merkle_state_pda)
+            .await
+            .expect("Can't get merkle state account")
+        else {
+            panic!("Merkle state account is uninitialized");
+        };
+        let paged_state = MerkleStateAccount::unpack(&merkle_state_account.data)
+            .expect("Invalid merkle state data");
+        assert!(!paged_state.has_leaf_pages());
+        assert_eq!(paged_state.get_paged_leaves(), 256);
+        assert_eq!(paged_state.get_leaf_hashes(), &leaves[..]);
+
+        // Calls must resume at the next page
+        let mut tx = Transaction::new_with_payer(&[enable_leaf_pages_ix(0)], Some(&payer.pubkey()));
+        tx.sign(&[&payer, &authority], recent_blockhash);
+        let error = banks_client
+            .process_transaction(tx)
+            .await
+            .expect_err("Leaf pages must resume at the next page")
+            .unwrap();
+        assert_eq!(
+            error,
+            TransactionError::InstructionError(
+                0,
+                InstructionError::Custom(MerkleTreeError::InvalidLeafPage as u32)
+            )
+        );
+
+        // Last call drops the stored leaves
+        let mut tx = Transaction::new_with_payer(&[enable_leaf_pages_ix(1)], Some(&payer.pubkey()));
+        tx.sign(&[&payer, &authority], recent_blockhash);
+        banks_client
+            .process_transaction(tx)
+            .await
+            .expect("Can't process tx");
+
+        let Some(merkle_state_account) = banks_client
+            .get_account(merkle_state_pda)
+            .await
+            .expect("Can't get merkle state account")
+        else {
+            panic!("Merkle state account is uninitialized");
+        };
+        let paged_state = MerkleStateAccount::unpack(&merkle_state_account.data)
+            .expect("Invalid merkle state data");
+        assert!(paged_state.has_leaf_pages());
+        assert!(paged_state.get_leaf_hashes().is_empty());
+        assert_eq!(paged_state.get_root_hash(), merkle_state.get_root_hash());
+        for (page_index, page_leaves) in leaves.chunks(leaf_page::LEAVES_PER_PAGE).enumerate() {
+            let Some(leaf_page_account) = banks_client
+                .get_account(leaf_page_pda(page_index as u32))
+                .await
+                .expect("Can't get leaf page account")
+            else {
+                panic!("Leaf page account is uninitialized");
+            };
+            let leaf_page_leaves =
+                leaf_page::leaves(&leaf_page_account.data).expect("Invalid leaf page");
+            assert_eq!(&leaf_page_leaves[..page_leaves.len()], page_leaves);
+        }
+    }
+
+    #[test]
+    fn leaf_pages_resume_after_leaf_changes() {
+        let leaves: Vec<[u8; 32]> = (0u32..600).map(|i| hash_value(&i.to_le_bytes())).collect();
+        let mut merkle_state = MerkleStateAccount::new(&leaves[0], &Pubkey::new_unique());
+        leaves[1..]
+            .iter()
+            .for_each(|leaf| merkle_state.add_leaf(leaf));
+
+        assert!(!merkle_state.page_leaves(2));
+        assert_eq!(merkle_state.get_paged_leaves(), 512);
+
+        // Leaves changed in place are paged again from their page
+        let leaf_index = 300;
+        let proof = merkle_state.get_proof(leaf_index);
+        assert!(merkle_state.replace_leaf(
+            leaf_index,
+            &leaves[leaf_index as usize],
+            &hash_value(b"new leaf"),
+            &proof,
+        ));
+        assert_eq!(merkle_state.get_paged_leaves(), 256);
+
+        // Conversion finishes once every leaf is paged
+        assert!(merkle_state.page_leaves(3));
+        assert!(merkle_state.has_leaf_pages());
+        assert_eq!(merkle_state.get_paged_leaves(), 0);
+    }
+
+    #[tokio::test]
+    async fn fail_enable_leaf_pages_when_rejecting_duplicates() {
+        // Setup test env
+        let program_id = Pubkey::new_unique();
+        let (mut banks_client, payer, recent_blockhash) = ProgramTest::new(
+            "merkle_tree_program",
+            program_id,
+            processor!(process_instruction),
+        )
+        .start()
+        .await;
+
+        // Calculate merkle state, event authority and leaf page pdas
+        let (merkle_state_pda, _) = find_merkle_state_pda(&program_id);
+        let (event_authority_pda, _) = find_event_authority_pda(&program_id);
+        let (leaf_page_pda, _) = pda::find_leaf_page_pda(&program_id, &merkle_state_pda, 0);
+
+        let insert_leaf_ix = Instruction::new_with_bytes(
+            program_id,
+            &instruction::MerkleTreeInstruction::InsertLeaf {
+                hash: hash_value(b"leaf"),
+                expected_root: None,
+            }
+            .pack(),
+            vec![
+                AccountMeta::new(merkle_state_pda, false),
+                AccountMeta::new(payer.pubkey(), true),
+                AccountMeta::new_readonly(system_program::id(), false),
+                AccountMeta::new_readonly(event_authority_pda, false),
+                AccountMeta::new_readonly(program_id, false),
+            ],
+        );
+        let set_reject_duplicates_ix = Instruction::new_with_bytes(
+            program_id,
+            &instruction::MerkleTreeInstruction::SetRejectDuplicates {
+                reject_duplicates: true,
+            }
+            .pack(),
+            vec![
+                AccountMeta::new(merkle_state_pda, false),
+                AccountMeta::new_readonly(payer.pubkey(), true),
+            ],
+        );
+        let enable_leaf_pages_ix = Instruction::new_with_bytes(
+            program_id,
+            &instruction::MerkleTreeInstruction::EnableLeafPages {
+                first_page: 0,
+                pages_count: 1,
+            }
+            .pack(),
+            vec![
+                AccountMeta::new(merkle_state_pda, false),
+                AccountMeta::new(payer.pubkey(), true),
+                AccountMeta::new_readonly(system_program::id(), false),
+                AccountMeta::new(leaf_page_pda, false),
+            ],
+        );
+
+        // Pages can't be checked for duplicates
+        let mut tx = Transaction::new_with_payer(
+            &[
+                insert_leaf_ix,
+                set_reject_duplicates_ix,
+                enable_leaf_pages_ix,
+            ],
+            Some(&payer.pubkey()),
+        );
+        tx.sign(&[&payer], recent_blockhash);
+        let error = banks_client
+            .process_transaction(tx)
+            .await
+            .expect_err("Leaf pages must fail while rejecting duplicates")
+            .unwrap();
+        assert_eq!(
+            error,
+            TransactionError::InstructionError(
+                2,
+                InstructionError::Custom(MerkleTreeError::UnsupportedWithLeafPages as u32)
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn success_init_tree() {
+        // Setup test env
+        let program_id = Pubkey::new_unique();
+        let (mut banks_client, payer, recent_blockhash) = ProgramTest::new(
+            "merkle_tree_program",
+            program_id,
+            processor!(process_instruction),
+        )
+        .start()
+        .await;
+
+        // Calculate merkle state and event authority pda
+        let (merkle_state_pda, _) = find_merkle_state_pda(&program_id);
+        let (event_authority_pda, _) = find_event_authority_pda(&program_id);
+
+        let accounts = vec![
+            AccountMeta::new(merkle_state_pda, false),
+            AccountMeta::new(payer.pubkey(), true),
+            AccountMeta::new_readonly(system_program::id(), false),
+            AccountMeta::new_readonly(event_authority_pda, false),
+            AccountMeta::new_readonly(program_id, false),
+        ];
+        let leaves: Vec<[u8; 32]> = (0u32..4).map(|i| hash_value(&i.to_le_bytes())).collect();
+        let insert_leaf_ix = |hash: [u8; 32]| {
+            Instruction::new_with_bytes(
+                program_id,
+                &instruction::MerkleTreeInstruction::InsertLeaf {
+                    hash,
+                    expected_root: None,
+                }
+                .pack(),
+                accounts.clone(),
+            )
+        };
+        let init_tree_ix = Instruction::new_with_bytes(
+            program_id,
+            &instruction::MerkleTreeInstruction::InitTree {
+                hash: leaves[0],
+                leaf_capacity: 3,
+                hash_algo: HashAlgo::Sha256,
+                domain_separation: false,
+                ordered: false,
+                left_balanced: false,
+                zero_padded: false,
+            }
+            .pack(),
+            accounts.clone(),
+        );
+
+        // Fill the tree, inserts don't grow the account
+        let mut tx = Transaction::new_with_payer(
+            &[
+                init_tree_ix,
+                insert_leaf_ix(leaves[1]),
+                insert_leaf_ix(leaves[2]),
+            ],
+            Some(&payer.pubkey()),
+        );
+        tx.sign(&[&payer], recent_blockhash);
+        banks_client
+            .process_transaction(tx)
+            .await
+            .expect("Can't process tx");
+
+        let Some(merkle_state_account) = banks_client
+            .get_account(merkle_state_pda)
+            .await
+            .expect("Can't get merkle state account")
+        else {
+            panic!("Merkle state account is uninitialized");
+        };
+        assert_eq!(
+            merkle_state_account.data.len(),
+            MerkleStateAccount::preallocated_len(3)
+        );
+        let merkle_state = MerkleStateAccount::unpack(&merkle_state_account.data)
+            .expect("Invalid merkle state data");
+        assert_eq!(merkle_state.get_leaf_capacity(), Some(3));
+        assert!(merkle_state.is_full());
+        let mut expected_state = MerkleStateAccount::new(&leaves[0], &payer.pubkey());
+        leaves[1..3]
+            .iter()
+            .for_each(|leaf| expected_state.add_leaf(leaf));
+        assert_eq!(merkle_state.get_root_hash(), expected_state.get_root_hash());
+
+        // Full tree rejects further leaves
+        let mut tx =
+            Transaction::new_with_payer(&[insert_leaf_ix(leaves[3])], Some(&payer.pubkey()));
+        tx.sign(&[&payer], recent_blockhash);
+        let error = banks_client
+            .process_transaction(tx)
+            .await
+            .expect_err("Insert must fail for a full tree")
+            .unwrap();
+        assert_eq!(
+            error,
+            TransactionError::InstructionError(
+                0,
+                InstructionError::Custom(MerkleTreeError::TreeFull as u32)
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn success_grow_tree() {
+        // Setup test env
+        let program_id = Pubkey::new_unique();
+        let (mut banks_client, payer, recent_blockhash) = ProgramTest::new(
+            "merkle_tree_program",
+            program_id,
+            processor!(process_instruction),
+        )
+        .start()
+        .await;
+
+        // Calculate merkle state and event authority pda
+        let (merkle_state_pda, _) = find_merkle_state_pda(&program_id);
+        let (event_authority_pda, _) = find_event_authority_pda(&program_id);
+
+        let accounts = vec![
+            AccountMeta::new(merkle_state_pda, false),
+            AccountMeta::new(payer.pubkey(), true),
+            AccountMeta::new_readonly(system_program::id(), false),
+            AccountMeta::new_readonly(event_authority_pda, false),
+            AccountMeta::new_readonly(program_id, false),
+        ];
+        let leaf_capacity = 1024;
+        let preallocated_len = MerkleStateAccount::preallocated_len(leaf_capacity);
+        let init_tree_ix = Instruction::new_with_bytes(
+            program_id,
+            &instruction::MerkleTreeInstruction::InitTree {
+                hash: hash_value(&0u32.to_le_bytes()),
+                leaf_capacity,
+                hash_algo: HashAlgo::Sha256,
+                domain_separation: false,
+                ordered: false,
+                left_balanced: false,
+                zero_padded: false,
+            }
+            .pack(),
+            accounts.clone(),
+        );
+        let grow_tree_ix = Instruction::new_with_bytes(
+            program_id,
+            &instruction::MerkleTreeInstruction::GrowTree {
+                target_len: preallocated_len as u32,
+            }
+            .pack(),
+            accounts[..3].to_vec(),
+        );
+        let insert_leaf_ix = Instruction::new_with_bytes(
+            program_id,
+            &instruction::MerkleTreeInstruction::InsertLeaf {
+                hash: hash_value(&1u32.to_le_bytes()),
+                expected_root: None,
+            }
+            .pack(),
+            accounts.clone(),
+        );
+
+        // Capacity above one realloc is created partially, then grown one realloc per
+        // instruction, extra instructions leave the account as is
+        let steps = preallocated_len.div_ceil(MAX_PERMITTED_DATA_INCREASE);
+        let mut instructions = vec![init_tree_ix];
+        instructions.extend(vec![grow_tree_ix; steps]);
+        instructions.push(insert_leaf_ix);
+        let mut tx = Transaction::new_with_payer(&instructions, Some(&payer.pubkey()));
+        tx.sign(&[&payer], recent_blockhash);
+        banks_client
+            .process_transaction(tx)
+            .await
+            .expect("Can't process tx");
+
+        let Some(merkle_state_account) = banks_client
+            .get_account(merkle_state_pda)
+            .await
+            .expect("Can't get merkle state account")
+        else {
+            panic!("Merkle state account is uninitialized");
+        };
+        assert_eq!(merkle_state_account.data.len(), preallocated_len);
+        let merkle_state = MerkleStateAccount::unpack(&merkle_state_account.data)
+            .expect("Invalid merkle state data");
+        assert_eq!(merkle_state.get_leaves_count(), 2);
+    }
+
+    #[tokio::test]
+    async fn fail_grow_tree_without_spare() {
+        // Setup test env
+        let program_id = Pubkey::new_unique();
+        let (mut banks_client, payer, recent_blockhash) = ProgramTest::new(
+            "merkle_tree_program",
+            program_id,
+            processor!(process_instruction),
+        )
+        .start()
+        .await;
+
+        // Calculate merkle state and event authority pda
+        let (merkle_state_pda, _) = find_merkle_state_pda(&program_id);
+        let (event_authority_pda, _) = find_event_authority_pda(&program_id);
+
+        let accounts = vec![
+            AccountMeta::new(merkle_state_pda, false),
+            AccountMeta::new(payer.pubkey(), true),
+            AccountMeta::new_readonly(system_program::id(), false),
+            AccountMeta::new_readonly(event_authority_pda, false),
+            AccountMeta::new_readonly(program_id, false),
+        ];
+        let insert_leaf_ix = Instruction::new_with_bytes(
+            program_id,
+            &instruction::MerkleTreeInstruction::InsertLeaf {
+                hash: hash_value(&0u32.to_le_bytes()),
+                expected_root: None,
+            }
+            .pack(),
+            accounts.clone(),
+        );
+        let grow_tree_ix = Instruction::new_with_bytes(
+            program_id,
+            &instruction::MerkleTreeInstruction::GrowTree {
+                target_len: MerkleStateAccount::preallocated_len(8) as u32,
+            }
+            .pack(),
+            accounts[..3].to_vec(),
+        );
+
+        // Trees without capacity or growth must span their account exactly
+        let mut tx =
+            Transaction::new_with_payer(&[insert_leaf_ix, grow_tree_ix], Some(&payer.pubkey()));
+        tx.sign(&[&payer], recent_blockhash);
+        let error = banks_client
+            .process_transaction(tx)
+            .await
+            .expect_err("Grow must fail for a tree without spare bytes")
+            .unwrap();
+        assert_eq!(
+            error,
+            TransactionError::InstructionError(
+                1,
+                InstructionError::Custom(MerkleTreeError::StateSizeMismatch as u32)
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn success_set_canopy_depth() {
+        // Setup test env
+        let program_id = Pubkey::new_unique();
+        let (mut banks_client, payer, recent_blockhash) = ProgramTest::new(
+            "merkle_tree_program",
+            program_id,
+            processor!(process_instruction),
+        )
+        .start()
+        .await;
+
+        // Calculate merkle state and event authority pda
+        let (merkle_state_pda, _) = find_merkle_state_pda(&program_id);
+        let (event_authority_pda, _) = find_event_authority_pda(&program_id);
+
+        let accounts = vec![
+            AccountMeta::new(merkle_state_pda, false),
+            AccountMeta::new(payer.pubkey(), true),
+            AccountMeta::new_readonly(system_program::id(), false),
+            AccountMeta::new_readonly(event_authority_pda, false),
+            AccountMeta::new_readonly(program_id, false),
+        ];
+        let leaves: Vec<[u8; 32]> = (0u32..20).map(|i| hash_value(&i.to_le_bytes())).collect();
+        let mut instructions = vec![Instruction::new_with_bytes(
+            program_id,
+            &instruction::MerkleTreeInstruction::InitTree {
+                hash: leaves[0],
+                leaf_capacity: 0,
+                hash_algo: HashAlgo::Sha256,
+                domain_separation: false,
+                ordered: false,
+                left_balanced: false,
+                zero_padded: false,
+            }
+            .pack(),
+            accounts.clone(),
+        )];
+        instructions.extend(leaves[1..].iter().map(|&hash| {
+            Instruction::new_with_bytes(
+                program_id,
+                &instruction::MerkleTreeInstruction::InsertLeaf {
+                    hash,
+                    expected_root: None,
+                }
+                .pack(),
+                accounts.clone(),
+            )
+        }));
+        instructions.push(Instruction::new_with_bytes(
+            program_id,
+            &instruction::MerkleTreeInstruction::SetCanopyDepth { canopy_depth: 4 }.pack(),
+            accounts[..3].to_vec(),
+        ));
+        let mut tx = Transaction::new_with_payer(&instructions, Some(&payer.pubkey()));
+        tx.sign(&[&payer], recent_blockhash);
+        banks_client
+            .process_transaction(tx)
+            .await
+            .expect("Can't process tx");
+
+        // Replace a leaf below the deeper canopy
+        let mut expected_state = MerkleStateAccount::new(&leaves[0], &payer.pubkey());
+        leaves[1..]
+            .iter()
+            .for_each(|leaf| expected_state.add_leaf(leaf));
+        expected_state.set_canopy_depth(4);
+        let new_leaf = hash_value(&42u32.to_le_bytes());
+        let proof = expected_state.get_proof(13);
+        assert!(expected_state.replace_leaf(13, &leaves[13], &new_leaf, &proof));
+        let replace_leaf_ix = Instruction::new_with_bytes(
+            program_id,
+            &instruction::MerkleTreeInstruction::ReplaceLeaf {
+                leaf_index: 13,
+                old_leaf: leaves[13],
+                new_leaf,
+                proof,
+            }
+            .pack(),
+            accounts.clone(),
+        );
+        let mut tx = Transaction::new_with_payer(&[replace_leaf_ix], Some(&payer.pubkey()));
+        tx.sign(&[&payer], recent_blockhash);
+        banks_client
+            .process_transaction(tx)
+            .await
+            .expect("Can't process tx");
+
+        let Some(merkle_state_account) = banks_client
+            .get_account(merkle_state_pda)
+            .await
+            .expect("Can't get merkle state account")
+        else {
+            panic!("Merkle state account is uninitialized");
+        };
+        let merkle_state = MerkleStateAccount::unpack(&merkle_state_account.data)
+            .expect("Invalid merkle state data");
+        assert_eq!(merkle_state.get_canopy_depth(), 4);
+        assert_eq!(merkle_state.get_root_hash(), expected_state.get_root_hash());
+        assert_eq!(merkle_state.get_canopy(), expected_state.get_canopy());
+    }
+
+    #[tokio::test]
+    async fn success_commit_lazy_root() {
+        // Setup test env
+        let program_id = Pubkey::new_unique();
+        let (mut banks_client, payer, recent_blockhash) = ProgramTest::new(
+            "merkle_tree_program",
+            program_id,
+            processor!(process_instruction),
+        )
+        .start()
+        .await;
+
+        // Calculate merkle state and event authority pda
+        let (merkle_state_pda, _) = find_merkle_state_pda(&program_id);
+        let (event_authority_pda, _) = find_event_authority_pda(&program_id);
+
+        let accounts = vec![
+            AccountMeta::new(merkle_state_pda, false),
+            AccountMeta::new(payer.pubkey(), true),
+            AccountMeta::new_readonly(system_program::id(), false),
+            AccountMeta::new_readonly(event_authority_pda, false),
+            AccountMeta::new_readonly(program_id, false),
+        ];
+        let leaves: Vec<[u8; 32]> = (0u32..10).map(|i| hash_value(&i.to_le_bytes())).collect();
+        let insert_leaf_ix = |hash| {
+            Instruction::new_with_bytes(
+                program_id,
+                &instruction::MerkleTreeInstruction::InsertLeaf {
+                    hash,
+                    expected_root: None,
+                }
+                .pack(),
+                accounts.clone(),
+            )
+        };
+        let mut instructions = vec![
+            insert_leaf_ix(leaves[0]),
+            Instruction::new_with_bytes(
+                program_id,
+                &instruction::MerkleTreeInstruction::SetLazyRoot { lazy_root: true }.pack(),
+                accounts[..2].to_vec(),
+            ),
+        ];
+        instructions.extend(leaves[1..].iter().map(|&hash| insert_leaf_ix(hash)));
+        let mut tx = Transaction::new_with_payer(&instructions, Some(&payer.pubkey()));
+        tx.sign(&[&payer], recent_blockhash);
+        banks_client
+            .process_transaction(tx)
+            .await
+            .expect("Can't process tx");
+
+        // Proofs are rejected until the root is committed
+        let mut expected_state = MerkleStateAccount::new(&leaves[0], &payer.pubkey());
+        leaves[1..]
+            .iter()
+            .for_each(|leaf| expected_state.add_leaf(leaf));
+        let verify_leaf_ix = Instruction::new_with_bytes(
+            program_id,
+            &instruction::MerkleTreeInstruction::VerifyLeaf {
+                leaf_index: 7,
+                leaf: leaves[7],
+                proof: expected_state.get_proof(7),
+            }
+            .pack(),
+            vec![AccountMeta::new_readonly(merkle_state_pda, false)],
+        );
+        let mut tx = Transaction::new_with_payer(
+            std::slice::from_ref(&verify_leaf_ix),
+            Some(&payer.pubkey()),
+        );
+        tx.sign(&[&payer], recent_blockhash);
+        let error = banks_client
+            .process_transaction(tx)
+            .await
+            .expect_err("Dirty root must be rejected")
+            .unwrap();
+        assert_eq!(
+            error,
+            TransactionError::InstructionError(
+                0,
+                InstructionError::Custom(MerkleTreeError::RootNotCommitted as u32)
+            )
+        );
+
+        // Anyone commits the root of all leaves, then proofs verify
+        let commit_root_ix = Instruction::new_with_bytes(
+            program_id,
+            &instruction::MerkleTreeInstruction::CommitRoot.pack(),
+            vec![
+                AccountMeta::new(merkle_state_pda, false),
+                AccountMeta::new_readonly(event_authority_pda, false),
+                AccountMeta::new_readonly(program_id, false),
+            ],
+        );
+        let mut tx =
+            Transaction::new_with_payer(&[commit_root_ix, verify_leaf_ix], Some(&payer.pubkey()));
+        tx.sign(&[&payer], recent_blockhash);
+        banks_client
+            .process_transaction(tx)
+            .await
+            .expect("Can't process tx");
+
+        let Some(merkle_state_account) = banks_client
+            .get_account(merkle_state_pda)
+            .await
+            .expect("Can't get merkle state account")
+        else {
+            panic!("Merkle state account is uninitialized");
+        };
+        let merkle_state = MerkleStateAccount::unpack(&merkle_state_account.data)
+            .expect("Invalid merkle state data");
+        assert!(!merkle_state.is_root_dirty());
+        assert_eq!(merkle_state.get_root_hash(), expected_state.get_root_hash());
+        assert_eq!(merkle_state.get_canopy(), expected_state.get_canopy());
+    }
+
+    #[tokio::test]
+    async fn success_set_metadata() {
+        // Setup test env
+        let program_id = Pubkey::new_unique();
+        let (mut banks_client, payer, recent_blockhash) = ProgramTest::new(
+            "merkle_tree_program",
+            program_id,
+            processor!(process_instruction),
+        )
+        .start()
+        .await;
+
+        // Calculate merkle state, event authority and metadata pdas
+        let (merkle_state_pda, _) = find_merkle_state_pda(&program_id);
+        let (event_authority_pda, _) = find_event_authority_pda(&program_id);
+        let (metadata_pda, _) = pda::find_tree_metadata_pda(&program_id, &merkle_state_pda);
+
+        let insert_leaf_ix = Instruction::new_with_bytes(
+            program_id,
+            &instruction::MerkleTreeInstruction::InsertLeaf {
+                hash: hash_value(&0u32.to_le_bytes()),
+                expected_root: None,
+            }
+            .pack(),
+            vec![
+                AccountMeta::new(merkle_state_pda, false),
+                AccountMeta::new(payer.pubkey(), true),
+                AccountMeta::new_readonly(system_program::id(), false),
+                AccountMeta::new_readonly(event_authority_pda, false),
+                AccountMeta::new_readonly(program_id, false),
+            ],
+        );
+        let set_metadata_ix = |name: &str, description: &str| {
+            Instruction::new_with_bytes(
+                program_id,
+                &instruction::MerkleTreeInstruction::SetMetadata {
+                    name: name.into(),
+                    description: description.into(),
+                    uri: "https://example.com/leaves.json".into(),
+                }
+                .pack(),
+                vec![
+                    AccountMeta::new(merkle_state_pda, false),
+                    AccountMeta::new(payer.pubkey(), true),
+                    AccountMeta::new_readonly(system_program::id(), false),
+                    AccountMeta::new(metadata_pda, false),
+                ],
+            )
+        };
+
+        // Create metadata, then grow it with a longer description
+        let mut tx = Transaction::new_with_payer(
+            &[
+                insert_leaf_ix,
+                set_metadata_ix("airdrop", ""),
+                set_metadata_ix("airdrop", "Season 1 airdrop claims"),
+            ],
+            Some(&payer.pubkey()),
+        );
+        tx.sign(&[&payer], recent_blockhash);
+        banks_client
+            .process_transaction(tx)
+            .await
+            .expect("Can't process tx");
+
+        let Some(metadata_account) = banks_client
+            .get_account(metadata_pda)
+            .await
+            .expect("Can't get metadata account")
+        else {
+            panic!("Metadata account is uninitialized");
+        };
+        let metadata = tree_metadata::TreeMetadataAccount::unpack(&metadata_account.data)
+            .expect("Invalid metadata data");
+        assert_eq!(metadata.name, "airdrop");
+        assert_eq!(metadata.description, "Season 1 airdrop claims");
+        assert_eq!(metadata.uri, "https://example.com/leaves.json");
+
+        // Over-long name is rejected
+        let name = "n".repeat(tree_metadata::TreeMetadataAccount::MAX_NAME_LEN + 1);
+        let mut tx =
+            Transaction::new_with_payer(&[set_metadata_ix(&name, "")], Some(&payer.pubkey()));
+        tx.sign(&[&payer], recent_blockhash);
+        let error = banks_client
+            .process_transaction(tx)
+            .await
+            .expect_err("Set metadata must fail for over-long name")
+            .unwrap();
+        assert_eq!(
+            error,
+            TransactionError::InstructionError(
+                0,
+                InstructionError::Custom(MerkleTreeError::MetadataTooLong as u32)
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn success_multisig_authority() {
+        // Setup test env
+        let program_id = Pubkey::new_unique();
+        let (mut banks_client, payer, recent_blockhash) = ProgramTest::new(
+            "merkle_tree_program",
+            program_id,
+            processor!(process_instruction),
+        )
+        .start()
+        .await;
+
+        // Calculate merkle state, event authority and multisig pdas
+        let (merkle_state_pda, _) = find_merkle_state_pda(&program_id);
+        let (event_authority_pda, _) = find_event_authority_pda(&program_id);
+        let (multisig_pda, _) = pda::find_multisig_pda(&program_id, &merkle_state_pda);
+        let signers = [Keypair::new(), Keypair::new(), Keypair::new()];
+
+        let insert_leaf_ix = Instruction::new_with_bytes(
+            program_id,
+            &instruction::MerkleTreeInstruction::InsertLeaf {
+                hash: hash_value(&0u32.to_le_bytes()),
+                expected_root: None,
+            }
+            .pack(),
+            vec![
+                AccountMeta::new(merkle_state_pda, false),
+                AccountMeta::new(payer.pubkey(), true),
+                AccountMeta::new_readonly(system_program::id(), false),
+                AccountMeta::new_readonly(event_authority_pda, false),
+                AccountMeta::new_readonly(program_id, false),
+            ],
+        );
+        let set_multisig_ix = Instruction::new_with_bytes(
+            program_id,
+            &instruction::MerkleTreeInstruction::SetMultisig {
+                threshold: 2,
+                signers: signers.iter().map(Keypair::pubkey).collect(),
+            }
+            .pack(),
+            vec![
+                AccountMeta::new(merkle_state_pda, false),
+                AccountMeta::new_readonly(payer.pubkey(), true),
+                AccountMeta::new(payer.pubkey(), true),
+                AccountMeta::new_readonly(system_program::id(), false),
+                AccountMeta::new(multisig_pda, false),
+            ],
+        );
+        let propose_authority_ix = Instruction::new_with_bytes(
+            program_id,
+            &instruction::MerkleTreeInstruction::ProposeAuthority {
+                new_authority: multisig_pda,
+            }
+            .pack(),
+            vec![
+                AccountMeta::new(merkle_state_pda, false),
+                AccountMeta::new_readonly(payer.pubkey(), true),
+            ],
+        );
+        // Multisig acts unsigned, approved by the signing signers
+        let multisig_ix = |instruction: instruction::MerkleTreeInstruction,
+                           approvers: &[&Keypair]| {
+            let mut accounts = vec![
+                AccountMeta::new(merkle_state_pda, false),
+                AccountMeta::new_readonly(multisig_pda, false),
+            ];
+            accounts.extend(
+                approvers
+                    .iter()
+                    .map(|approver| AccountMeta::new_readonly(approver.pubkey(), true)),
+            );
+            Instruction::new_with_bytes(program_id, &instruction.pack(), accounts)
+        };
+
+        // Create the multisig and hand the tree to it, accepted by 2 of 3 signers
+        let mut tx = Transaction::new_with_payer(
+            &[
+                insert_leaf_ix,
+                set_multisig_ix,
+                propose_authority_ix,
+                multisig_ix(
+                    instruction::MerkleTreeInstruction::AcceptAuthority,
+                    &[&signers[0], &signers[1]],
+                ),
+            ],
+            Some(&payer.pubkey()),
+        );
+        tx.sign(&[&payer, &signers[0], &signers[1]], recent_blockhash);
+        banks_client
+            .process_transaction(tx)
+            .await
+            .expect("Can't process tx");
+
+        let merkle_state_account = banks_client
+            .get_account(merkle_state_pda)
+            .await
+            .expect("Can't get merkle state account")
+            .expect("Merkle state account is uninitialized");
+        let merkle_state = MerkleStateAccount::try_from_slice(&merkle_state_account.data)
+            .expect("Invalid merkle state data");
+        assert_eq!(merkle_state.get_authority(), multisig_pda);
+
+        // One signature is below the threshold, the former authority is no signer anymore
+        let mut tx = Transaction::new_with_payer(
+            &[multisig_ix(
+                instruction::MerkleTreeInstruction::PauseTree,
+                &[&signers[2]],
+            )],
+            Some(&payer.pubkey()),
+        );
+        tx.sign(&[&payer, &signers[2]], recent_blockhash);
+        let error = banks_client
+            .process_transaction(tx)
+            .await
+            .expect_err("Pause must fail below threshold")
+            .unwrap();
+        assert_eq!(
+            error,
+            TransactionError::InstructionError(
+                0,
+                InstructionError::Custom(MerkleTreeError::MissingSigner as u32)
+            )
+        );
+
+        let pause_tree_ix = Instruction::new_with_bytes(
+            program_id,
+            &instruction::MerkleTreeInstruction::PauseTree.pack(),
+            vec![
+                AccountMeta::new(merkle_state_pda, false),
+                AccountMeta::new_readonly(payer.pubkey(), true),
+            ],
+        );
+        let mut tx = Transaction::new_with_payer(&[pause_tree_ix], Some(&payer.pubkey()));
+        tx.sign(&[&payer], recent_blockhash);
+        let error = banks_client
+            .process_transaction(tx)
+            .await
+            .expect_err("Pause must fail for the former authority")
+            .unwrap();
+        assert_eq!(
+            error,
+            TransactionError::InstructionError(
+                0,
+                InstructionError::Custom(MerkleTreeError::InvalidAuthority as u32)
+            )
+        );
+
+        // Any 2 of 3 signers may act as the authority
+        let mut tx = Transaction::new_with_payer(
+            &[multisig_ix(
+                instruction::MerkleTreeInstruction::PauseTree,
+                &[&signers[0], &signers[2]],
+            )],
+            Some(&payer.pubkey()),
+        );
+        tx.sign(&[&payer, &signers[0], &signers[2]], recent_blockhash);
+        banks_client
+            .process_transaction(tx)
+            .await
+            .expect("Can't process tx");
+    }
+
+    #[tokio::test]
+    async fn success_timelocked_config() {
+        // Setup test env
+        let program_id = Pubkey::new_unique();
+        let mut context = ProgramTest::new(
+            "merkle_tree_program",
+            program_id,
+            processor!(process_instruction),
+        )
+        .start_with_context()
+        .await;
+        let payer = context.payer.insecure_clone();
+
+        // Calculate merkle state and pending config pdas
+        let (merkle_state_pda, _) = find_merkle_state_pda(&program_id);
+        let (event_authority_pda, _) = find_event_authority_pda(&program_id);
+        let (pending_config_pda, _) = find_pending_config_pda(&program_id, &merkle_state_pda);
+
+        let insert_leaf_ix = Instruction::new_with_bytes(
+            program_id,
+            &instruction::MerkleTreeInstruction::InsertLeaf {
+                hash: hash_value(&1u32.to_le_bytes()),
+                expected_root: None,
+            }
+            .pack(),
+            vec![
+                AccountMeta::new(merkle_state_pda, false),
+                AccountMeta::new(payer.pubkey(), true),
+                AccountMeta::new_readonly(system_program::id(), false),
+                AccountMeta::new_readonly(event_authority_pda, false),
+                AccountMeta::new_readonly(program_id, false),
+            ],
+        );
+        let admin_ix = |instruction: instruction::MerkleTreeInstruction| {
+            Instruction::new_with_bytes(
+                program_id,
+                &instruction.pack(),
+                vec![
+                    AccountMeta::new(merkle_state_pda, false),
+                    AccountMeta::new(payer.pubkey(), true),
+                ],
+            )
+        };
+        let queue_config_ix = |change: ConfigChange| {
+            Instruction::new_with_bytes(
+                program_id,
+                &instruction::MerkleTreeInstruction::QueueConfig { change }.pack(),
+                vec![
+                    AccountMeta::new(merkle_state_pda, false),
+                    AccountMeta::new_readonly(payer.pubkey(), true),
+                    AccountMeta::new(payer.pubkey(), true),
+                    AccountMeta::new_readonly(system_program::id(), false),
+                    AccountMeta::new(pending_config_pda, false),
+                ],
+            )
+        };
+        let apply_pending_config_ix = Instruction::new_with_bytes(
+            program_id,
+            &instruction::MerkleTreeInstruction::ApplyPendingConfig.pack(),
+            vec![
+                AccountMeta::new(merkle_state_pda, false),
+                AccountMeta::new(payer.pubkey(), true),
+                AccountMeta::new_readonly(system_program::id(), false),
+                AccountMeta::new(pending_config_pda, false),
+            ],
+        );
+        let cancel_pending_config_ix = Instruction::new_with_bytes(
+            program_id,
+            &instruction::MerkleTreeInstruction::CancelPendingConfig.pack(),
+            vec![
+                AccountMeta::new(merkle_state_pda, false),
+                AccountMeta::new(payer.pubkey(), true),
+                AccountMeta::new(pending_config_pda, false),
+            ],
+        );
+        let set_max_leaves_ix =
+            admin_ix(instruction::MerkleTreeInstruction::SetMaxLeaves { max_leaves: 2 });
+
+        // Init merkle state with a config timelock of 100 slots
+        let mut tx = Transaction::new_with_payer(
+            &[
+                insert_leaf_ix,
+                admin_ix(instruction::MerkleTreeInstruction::SetConfigTimelock {
+                    config_timelock: 100,
+                }),
+            ],
+            Some(&payer.pubkey()),
+        );
+        tx.sign(&[&payer], context.last_blockhash);
+        context
+            .banks_client
+            .process_transaction(tx)
+            .await
+            .expect("Can't process tx");
+
+        // Direct config changes are rejected, queued ones apply once the timelock passed
+        for (instructions, error) in [
+            (vec![set_max_leaves_ix], MerkleTreeError::ConfigTimelocked),
+            (
+                vec![
+                    queue_config_ix(ConfigChange::MaxLeaves(2)),
+                    apply_pending_config_ix.clone(),
+                ],
+                MerkleTreeError::InvalidPendingConfig,
+            ),
+        ] {
+            let mut tx = Transaction::new_with_payer(&instructions, Some(&payer.pubkey()));
+            tx.sign(&[&payer], context.last_blockhash);
+            let tx_error = context
+                .banks_client
+                .process_transaction(tx)
+                .await
+                .expect_err("Config change must be delayed")
+                .unwrap();
+            assert_eq!(
+                tx_error,
+                TransactionError::InstructionError(
+                    instructions.len() as u8 - 1,
+                    InstructionError::Custom(error as u32)
+                )
+            );
+        }
+
+        let mut tx = Transaction::new_with_payer(
+            &[queue_config_ix(ConfigChange::MaxLeaves(2))],
+            Some(&payer.pubkey()),
+        );
+        tx.sign(&[&payer], context.last_blockhash);
+        context
+            .banks_client
+            .process_transaction(tx)
+            .await
+            .expect("Can't process tx");
+
+        let clock: solana_sdk::clock::Clock = context
+            .banks_client
+            .get_sysvar()
+            .await
+            .expect("Can't get clock");
+        context
+            .warp_to_slot(clock.slot + 100)
+            .expect("Can't warp to slot");
+        let recent_blockhash = context
+            .get_new_latest_blockhash()
+            .await
+            .expect("Can't get blockhash");
+
+        // Apply the due change, then queue another one and cancel it
+        let mut tx = Transaction::new_with_payer(
+            &[
+                apply_pending_config_ix,
+                queue_config_ix(ConfigChange::ConfigTimelock(0)),
+                cancel_pending_config_ix,
+            ],
+            Some(&payer.pubkey()),
+        );
+        tx.sign(&[&payer], recent_blockhash);
+        context
+            .banks_client
+            .process_transaction(tx)
+            .await
+            .expect("Can't process tx");
+
+        let merkle_state_account = context
+            .banks_client
+            .get_account(merkle_state_pda)
+            .await
+            .expect("Can't get merkle state account")
+            .expect("Merkle state account not found");
+        let merkle_state = MerkleStateAccount::try_from_slice(&merkle_state_account.data)
+            .expect("Can't deserialize merkle state");
+        assert_eq!(merkle_state.get_max_leaves(), Some(2));
+        assert_eq!(merkle_state.get_config_timelock(), Some(100));
+        assert!(
+            context
+                .banks_client
+                .get_account(pending_config_pda)
+                .await
+                .expect("Can't get pending config account")
+                .is_none()
+        );
+    }
+
+    #[tokio::test]
+    async fn fail_timelocked_authority_changes() {
+        // Setup test env
+        let program_id = Pubkey::new_unique();
+        let mut context = ProgramTest::new(
+            "merkle_tree_program",
+            program_id,
+            processor!(process_instruction),
+        )
+        .start_with_context()
+        .await;
+        let payer = context.payer.insecure_clone();
+
+        // Calculate merkle state, multisig and pending config pdas
+        let (merkle_state_pda, _) = find_merkle_state_pda(&program_id);
+        let (event_authority_pda, _) = find_event_authority_pda(&program_id);
+        let (multisig_pda, _) = pda::find_multisig_pda(&program_id, &merkle_state_pda);
+        let (pending_config_pda, _) = find_pending_config_pda(&program_id, &merkle_state_pda);
+
+        let insert_leaf_ix = Instruction::new_with_bytes(
+            program_id,
+            &instruction::MerkleTreeInstruction::InsertLeaf {
+                hash: hash_value(&1u32.to_le_bytes()),
+                expected_root: None,
+            }
+            .pack(),
+            vec![
+                AccountMeta::new(merkle_state_pda, false),
+                AccountMeta::new(payer.pubkey(), true),
+                AccountMeta::new_readonly(system_program::id(), false),
+                AccountMeta::new_readonly(event_authority_pda, false),
+                AccountMeta::new_readonly(program_id, false),
+            ],
+        );
+        let admin_ix = |instruction: instruction::MerkleTreeInstruction| {
+            Instruction::new_with_bytes(
+                program_id,
+                &instruction.pack(),
+                vec![
+                    AccountMeta::new(merkle_state_pda, false),
+                    AccountMeta::new(payer.pubkey(), true),
+                    AccountMeta::new_readonly(system_program::id(), false),
+                ],
+            )
+        };
+        let propose_authority_ix = admin_ix(instruction::MerkleTreeInstruction::ProposeAuthority {
+            new_authority: payer.pubkey(),
+        });
+        let accept_authority_ix = admin_ix(instruction::MerkleTreeInstruction::AcceptAuthority);
+        let set_multisig_ix = Instruction::new_with_bytes(
+            program_id,
+            &instruction::MerkleTreeInstruction::SetMultisig {
+                threshold: 1,
+                signers: vec![Pubkey::new_unique()],
+            }
+            .pack(),
+            vec![
+                AccountMeta::new(merkle_state_pda, false),
+                AccountMeta::new_readonly(payer.pubkey(), true),
+                AccountMeta::new(payer.pubkey(), true),
+                AccountMeta::new_readonly(system_program::id(), false),
+                AccountMeta::new(multisig_pda, false),
+            ],
+        );
+        let queue_config_ix = Instruction::new_with_bytes(
+            program_id,
+            &instruction::MerkleTreeInstruction::QueueConfig {
+                change: ConfigChange::ConfigTimelock(0),
+            }
+            .pack(),
+            vec![
+                AccountMeta::new(merkle_state_pda, false),
+                AccountMeta::new_readonly(payer.pubkey(), true),
+                AccountMeta::new(payer.pubkey(), true),
+                AccountMeta::new_readonly(system_program::id(), false),
+                AccountMeta::new(pending_config_pda, false),
+            ],
+        );
+        let apply_pending_config_ix = Instruction::new_with_bytes(
+            program_id,
+            &instruction::MerkleTreeInstruction::ApplyPendingConfig.pack(),
+            vec![
+                AccountMeta::new(merkle_state_pda, false),
+                AccountMeta::new(payer.pubkey(), true),
+                AccountMeta::new_readonly(system_program::id(), false),
+                AccountMeta::new(pending_config_pda, false),
+            ],
+        );
+
+        // Init merkle state with a pending authority, then set a config timelock of 100 slots
+        let mut tx = Transaction::new_with_payer(
+            &[
+                insert_leaf_ix,
+                propose_authority_ix.clone(),
+                admin_ix(instruction::MerkleTreeInstruction::SetConfigTimelock {
+                    config_timelock: 100,
+                }),
+                queue_config_ix,
+            ],
+            Some(&payer.pubkey()),
+        );
+        tx.sign(&[&payer], context.last_blockhash);
+        context
+            .banks_client
+            .process_transaction(tx)
+            .await
+            .expect("Can't process tx");
+
+        // Changes that can't be queued are rejected while the timelock is set
+        for instruction in [
+            propose_authority_ix,
+            accept_authority_ix.clone(),
+            set_multisig_ix,
+            admin_ix(instruction::MerkleTreeInstruction::EnableFrontierMode),
+            Instruction::new_with_bytes(
+                program_id,
+                &instruction::MerkleTreeInstruction::EnableLeafPages {
+                    first_page: 0,
+                    pages_count: 1,
+                }
+                .pack(),
+                vec![
+                    AccountMeta::new(merkle_state_pda, false),
+                    AccountMeta::new(payer.pubkey(), true),
+                    AccountMeta::new_readonly(system_program::id(), false),
+                    AccountMeta::new(
+                        pda::find_leaf_page_pda(&program_id, &merkle_state_pda, 0).0,
+                        false,
+                    ),
+                ],
+            ),
+        ] {
+            let mut tx = Transaction::new_with_payer(&[instruction], Some(&payer.pubkey()));
+            tx.sign(&[&payer], context.last_blockhash);
+            let tx_error = context
+                .banks_client
+                .process_transaction(tx)
+                .await
+                .expect_err("Change must wait for the timelock to be lifted")
+                .unwrap();
+            assert_eq!(
+                tx_error,
+                TransactionError::InstructionError(
+                    0,
+                    InstructionError::Custom(MerkleTreeError::ConfigTimelocked as u32)
+                )
+            );
+        }
+
+        let clock: solana_sdk::clock::Clock = context
+            .banks_client
+            .get_sysvar()
+            .await
+            .expect("Can't get clock");
+        context
+            .warp_to_slot(clock.slot + 100)
+            .expect("Can't warp to slot");
+        let recent_blockhash = context
+            .get_new_latest_blockhash()
+            .await
+            .expect("Can't get blockhash");
+
+        // Once the queued timelock removal applied, authority may be transferred again
+        let mut tx = Transaction::new_with_payer(
+            &[apply_pending_config_ix, accept_authority_ix],
+            Some(&payer.pubkey()),
+        );
+        tx.sign(&[&payer], recent_blockhash);
+        context
+            .banks_client
+            .process_transaction(tx)
+            .await
+            .expect("Can't process tx");
+    }
+
+    #[test]
+    fn pending_config_takes_due_changes_in_order() {
+        let inserter = Pubkey::new_unique();
+        let mut pending_config = PendingConfigAccount::new();
+        assert!(pending_config.push(ConfigChange::AddInserter(inserter), 10));
+        assert!(pending_config.push(ConfigChange::MaxLeaves(2), 20));
+        assert!(pending_config.push(ConfigChange::RemoveInserter(inserter), 10));
+        assert!(pending_config.take_due(9).is_empty());
+
+        let changes = pending_config.take_due(10);
+        assert_eq!(
+            changes,
+            [
+                ConfigChange::AddInserter(inserter),
+                ConfigChange::RemoveInserter(inserter)
+            ]
+        );
+        assert_eq!(pending_config.changes.len(), 1);
+
+        // Changes are checked as by their direct instruction
+        let mut merkle_state = MerkleStateAccount::new(&hash_value(b"init"), &Pubkey::new_unique());
+        assert!(
+            changes
+                .iter()
+                .all(|change| change.apply(&mut merkle_state).is_ok())
+        );
+        assert_eq!(
+            ConfigChange::RemoveInserter(inserter).apply(&mut merkle_state),
+            Err(MerkleTreeError::InvalidInserter.into())
+        );
+
+        // Queue is bounded
+        (1..PendingConfigAccount::MAX_CHANGES)
+            .for_each(|_| assert!(pending_config.push(ConfigChange::MaxLeaves(0), 30)));
+        assert!(!pending_config.push(ConfigChange::MaxLeaves(0), 30));
+    }
+
+    #[test]
+    fn rate_limit_counts_inserts_per_payer() {
+        let (alice, bob) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let mut merkle_state = MerkleStateAccount::new(&hash_value(&[0]), &alice);
+        assert!((0..10).all(|_| merkle_state.count_insert(&alice, 1, 0)));
+        assert!(merkle_state.get_insert_counters().is_empty());
+
+        // 2 per slot and 3 per epoch, each payer counted on its own
+        merkle_state.set_rate_limit(2, 3);
+        assert!(merkle_state.count_insert(&alice, 1, 0));
+        assert!(merkle_state.count_insert(&alice, 1, 0));
+        assert!(!merkle_state.count_insert(&alice, 1, 0));
+        assert!(merkle_state.count_insert(&bob, 1, 0));
+        assert!(merkle_state.count_insert(&alice, 2, 0));
+        assert!(!merkle_state.count_insert(&alice, 3, 0));
+
+        // Next epoch starts over, dropping counters of the last one
+        assert!(merkle_state.count_insert(&alice, 432_000, 1));
+        assert_eq!(merkle_state.get_insert_counters().len(), 1);
+
+        merkle_state.set_rate_limit(0, 0);
+        assert!(merkle_state.get_insert_counters().is_empty());
+    }
+
+    #[test]
+    fn multisig_rejects_invalid_signer_sets() {
+        let signers: Vec<Pubkey> = (0..3).map(|_| Pubkey::new_unique()).collect();
+        assert!(multisig::MultisigAccount::new(3, signers.clone()).is_ok());
+
+        let invalid = [
+            (0, signers.clone()),
+            (4, signers.clone()),
+            (1, vec![signers[0], signers[1], signers[0]]),
+            (
+                1,
+                vec![Pubkey::new_unique(); multisig::MultisigAccount::MAX_SIGNERS + 1],
+            ),
+        ];
+        for (threshold, signers) in invalid {
+            assert_eq!(
+                multisig::MultisigAccount::new(threshold, signers),
+                Err(MerkleTreeError::InvalidMultisig.into())
+            );
+        }
+
+        let instruction_data = instruction::MerkleTreeInstruction::SetMultisig {
+            threshold: 2,
+            signers: signers.clone(),
+        }
+        .pack();
+        assert!(matches!(
+            instruction::MerkleTreeInstruction::unpack(&instruction_data),
+            Ok(instruction::MerkleTreeInstruction::SetMultisig { threshold: 2, signers: unpacked })
+                if unpacked == signers
+        ));
+    }
+
+    #[test]
+    fn sentinel_leaves_are_empty_subtree_roots() {
+        let tree_hasher = TreeHasher::RFC6962;
+        let mut empty_node = [0u8; 32];
+        for height in 0..4 {
+            assert!(tree_hasher.is_sentinel_leaf(&empty_node, height));
+            empty_node = tree_hasher.hash_nodes(&empty_node, &empty_node);
+            // Roots of higher subtrees can't be nodes of the tree yet
+            assert!(!tree_hasher.is_sentinel_leaf(&empty_node, height));
+        }
+        assert!(!tree_hasher.is_sentinel_leaf(&tree_hasher.hash_value(b"leaf"), 32));
+
+        // Rejection is opt-in
+        let mut merkle_state = MerkleStateAccount::new(&hash_value(b"init"), &Pubkey::new_unique());
+        assert!(!merkle_state.is_sentinel_leaf(&[0u8; 32]));
+        merkle_state.set_reject_sentinel_leaves(true);
+        assert!(merkle_state.is_sentinel_leaf(&[0u8; 32]));
+        assert!(merkle_state.is_sentinel_leaf(&hash_sorted_pair(&[0u8; 32], &[0u8; 32])));
+    }
+
+    #[test]
+    fn expected_root_is_optional_in_insert_data() {
+        let hash = hash_value(b"leaf");
+        let root_hash = hash_value(b"root");
+        let data = instruction::MerkleTreeInstruction::InsertLeaf {
+            hash,
+            expected_root: None,
+        }
+        .pack();
+        assert_eq!(data.len(), 1 + 32);
+        assert!(matches!(
+            instruction::MerkleTreeInstruction::unpack(&data),
+            Ok(instruction::MerkleTreeInstruction::InsertLeaf {
+                expected_root: None,
+                ..
+            })
+        ));
+
+        let data = instruction::MerkleTreeInstruction::InsertExpiringLeaf {
+            hash,
+            expires_at: 1,
+            expected_root: Some(root_hash),
+        }
+        .pack();
+        assert!(matches!(
+            instruction::MerkleTreeInstruction::unpack(&data),
+            Ok(instruction::MerkleTreeInstruction::InsertExpiringLeaf {
+                expires_at: 1,
+                expected_root: Some(root),
+                ..
+            }) if root == root_hash
+        ));
+        assert_eq!(
+            instruction::MerkleTreeInstruction::unpack(&data[..data.len() - 1]).err(),
+            Some(MerkleTreeError::TruncatedInstructionData.into())
+        );
+    }
+
+    #[test]
+    fn unpack_rejects_malformed_instruction_data() {
+        let unpack_err = |data: &[u8]| instruction::MerkleTreeInstruction::unpack(data).err();
+        let truncated = Some(MerkleTreeError::TruncatedInstructionData.into());
+        let oversized = Some(MerkleTreeError::OversizedInstructionData.into());
+
+        assert_eq!(unpack_err(&[]), truncated);
+        assert_eq!(
+            unpack_err(&[u8::MAX]),
+            Some(MerkleTreeError::UnknownInstruction.into())
+        );
+
+        // Trailing bytes, including after instructions without fields
+        let mut data = instruction::MerkleTreeInstruction::PauseTree.pack();
+        data.push(0);
+        assert_eq!(unpack_err(&data), oversized);
+        let mut data = instruction::MerkleTreeInstruction::AddInserter {
+            inserter: Pubkey::new_unique(),
+        }
+        .pack();
+        assert_eq!(unpack_err(&data[..data.len() - 1]), truncated);
+        data.push(0);
+        assert_eq!(unpack_err(&data), oversized);
+        let mut data = instruction::MerkleTreeInstruction::QueueConfig {
+            change: ConfigChange::MaxLeaves(8),
+        }
+        .pack();
+        assert_eq!(unpack_err(&data[..data.len() - 1]), truncated);
+        data.push(0);
+        assert_eq!(unpack_err(&data), oversized);
+        // Every InitTree flag is required, including the trailing zero_padded
+        let data = instruction::MerkleTreeInstruction::InitTree {
+            hash: hash_value(b"init"),
+            leaf_capacity: 0,
+            hash_algo: HashAlgo::Sha256,
+            domain_separation: false,
+            ordered: false,
+            left_balanced: false,
+            zero_padded: true,
+        }
+        .pack();
+        assert_eq!(unpack_err(&data[..data.len() - 1]), truncated);
+
+        // Length prefixes are checked against the payload before allocating
+        let data = instruction::MerkleTreeInstruction::VerifyMultiProof {
+            leaves: vec![(0, hash_value(b"leaf"))],
+            proof: vec![hash_value(b"node")],
+        }
+        .pack();
+        let mut oversized_len = data.clone();
+        oversized_len[1..5].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert_eq!(unpack_err(&oversized_len), truncated);
+        assert_eq!(unpack_err(&data[..data.len() - 1]), truncated);
+        assert_eq!(unpack_err(&[&data[..], &[0]].concat()), oversized);
+
+        // Invalid values keep the generic error
+        assert_eq!(
+            unpack_err(&[11, 2]),
+            Some(solana_program::program_error::ProgramError::InvalidInstructionData)
+        );
+        assert_eq!(
+            unpack_err(&[31, u8::MAX]),
+            Some(solana_program::program_error::ProgramError::InvalidInstructionData)
+        );
+    }
+
+    #[test]
+    fn verify_instructions_fit_transaction() {
+        let program_id = Pubkey::new_unique();
+        let payer = Pubkey::new_unique();
+
+        let mut merkle_state = MerkleStateAccount::new(&hash_value(&0u32.to_le_bytes()), &payer);
+        (1u32..1024).for_each(|value| merkle_state.add_leaf(&hash_value(&value.to_le_bytes())));
+
+        // Single leaf uses a canopy-truncated proof, which the tree accepts
+        let instructions =
+            tx_size::build_verify_instructions(&program_id, &payer, &merkle_state, &[5], &[])
+                .expect("Single leaf must fit");
+        let instruction::MerkleTreeInstruction::VerifyLeaf {
+            leaf_index,
+            leaf,
+            proof,
+        } = instruction::MerkleTreeInstruction::unpack(&instructions[0].data)
+            .expect("Invalid instruction data")
+        else {
+            panic!("Expected VerifyLeaf");
+        };
+        assert_eq!(
+            proof.len(),
+            10 - MerkleStateAccount::DEFAULT_CANOPY_DEPTH as usize
+        );
+        assert!(merkle_state.verify_leaf(leaf_index, &leaf, &proof));
+
+        // Too many leaves are rejected with a batch split estimate
+        let leaf_indices: Vec<u32> = (0..1024).step_by(16).collect();
+        let error = tx_size::build_verify_instructions(
+            &program_id,
+            &payer,
+            &merkle_state,
+            &leaf_indices,
+            &[],
+        )
+        .expect_err("Leaves must not fit");
+        assert!(error.size > tx_size::MAX_TRANSACTION_SIZE);
+        assert!(error.batches > 1);
+    }
 }
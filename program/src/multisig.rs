@@ -0,0 +1,68 @@
+//! Built-in M-of-N multisig a tree authority can be handed to, kept in a PDA seeded by the
+//! merkle state key, see `pda::find_multisig_pda`, and set via `SetMultisig`. Admin
+//! instructions then pass the multisig PDA as authority, unsigned, along with at least
+//! `threshold` of its signers as extra signing accounts.
+
+use crate::error::MerkleTreeError;
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_program::{account_info::AccountInfo, program_error::ProgramError, pubkey::Pubkey};
+
+#[derive(Debug, Clone, PartialEq, Eq, BorshSerialize, BorshDeserialize)]
+pub struct MultisigAccount {
+    /// Always `Self::DISCRIMINATOR`.
+    discriminator: [u8; 8],
+    /// Signatures required to act as the multisig.
+    pub threshold: u8,
+    pub signers: Vec<Pubkey>,
+}
+
+impl MultisigAccount {
+    /// First 8 bytes of `sha256("account:MultisigAccount")`.
+    pub const DISCRIMINATOR: [u8; 8] = [77, 9, 180, 199, 183, 246, 156, 81];
+
+    /// Max signers, same as SPL token multisigs.
+    pub const MAX_SIGNERS: usize = 11;
+
+    /// Multisig of distinct `signers` requiring `threshold` of them, at least one.
+    pub fn new(threshold: u8, signers: Vec<Pubkey>) -> Result<Self, ProgramError> {
+        let distinct = signers
+            .iter()
+            .enumerate()
+            .all(|(index, signer)| !signers[..index].contains(signer));
+        if threshold == 0
+            || threshold as usize > signers.len()
+            || signers.len() > Self::MAX_SIGNERS
+            || !distinct
+        {
+            return Err(MerkleTreeError::InvalidMultisig.into());
+        }
+
+        Ok(Self {
+            discriminator: Self::DISCRIMINATOR,
+            threshold,
+            signers,
+        })
+    }
+
+    /// Deserialize multisig account `data`, rejecting other accounts.
+    pub fn unpack(data: &[u8]) -> Result<Self, ProgramError> {
+        if data.get(..8) != Some(&Self::DISCRIMINATOR[..]) {
+            return Err(MerkleTreeError::InvalidMultisig.into());
+        }
+        Self::try_from_slice(data).map_err(|_| MerkleTreeError::InvalidMultisig.into())
+    }
+
+    /// Whether at least `threshold` signers signed among `accounts`, each counted once.
+    pub fn is_approved(&self, accounts: &[AccountInfo]) -> bool {
+        let approvals = self
+            .signers
+            .iter()
+            .filter(|signer| {
+                accounts
+                    .iter()
+                    .any(|account| account.is_signer && account.key == *signer)
+            })
+            .count();
+        approvals >= self.threshold as usize
+    }
+}
@@ -0,0 +1,88 @@
+//! Seeds and derivation of every PDA of the program, shared by the processors and clients so
+//! seeds can't drift between them. Each `find_*_pda` searches the bump, which costs a hash per
+//! try: `TreePdas` derives the fixed accounts of a tree once and keeps their bumps.
+
+use solana_program::pubkey::Pubkey;
+
+/// Seed of the merkle state PDA, the tree itself.
+pub const MERKLE_STATE_SEED: &[u8] = b"merkle_state";
+
+pub fn find_merkle_state_pda(program_id: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[MERKLE_STATE_SEED], program_id)
+}
+
+/// Seed of the PDA signing self-CPI events, same as Anchor `#[event_cpi]`.
+pub const EVENT_AUTHORITY_SEED: &[u8] = b"__event_authority";
+
+pub fn find_event_authority_pda(program_id: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[EVENT_AUTHORITY_SEED], program_id)
+}
+
+/// Seed of leaf page PDAs, see `leaf_page`.
+pub const LEAF_PAGE_SEED: &[u8] = b"leaf_page";
+
+pub fn find_leaf_page_pda(
+    program_id: &Pubkey,
+    merkle_state: &Pubkey,
+    page_index: u32,
+) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[
+            LEAF_PAGE_SEED,
+            merkle_state.as_ref(),
+            &page_index.to_le_bytes(),
+        ],
+        program_id,
+    )
+}
+
+/// Seed of the tree metadata PDA, see `tree_metadata`.
+pub const TREE_METADATA_SEED: &[u8] = b"tree_metadata";
+
+pub fn find_tree_metadata_pda(program_id: &Pubkey, merkle_state: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[TREE_METADATA_SEED, merkle_state.as_ref()], program_id)
+}
+
+/// Seed of the tree multisig PDA, see `multisig`.
+pub const MULTISIG_SEED: &[u8] = b"multisig";
+
+pub fn find_multisig_pda(program_id: &Pubkey, merkle_state: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[MULTISIG_SEED, merkle_state.as_ref()], program_id)
+}
+
+/// Seed of the pending config PDA, see `config`.
+pub const PENDING_CONFIG_SEED: &[u8] = b"pending_config";
+
+pub fn find_pending_config_pda(program_id: &Pubkey, merkle_state: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[PENDING_CONFIG_SEED, merkle_state.as_ref()], program_id)
+}
+
+/// Fixed PDAs of the tree of a program with their bumps, as `find_*_pda` return them. Leaf
+/// pages are unbounded, `find_leaf_page` derives them on demand.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TreePdas {
+    pub program_id: Pubkey,
+    pub merkle_state: (Pubkey, u8),
+    pub event_authority: (Pubkey, u8),
+    pub tree_metadata: (Pubkey, u8),
+    pub multisig: (Pubkey, u8),
+    pub pending_config: (Pubkey, u8),
+}
+
+impl TreePdas {
+    pub fn find(program_id: &Pubkey) -> Self {
+        let merkle_state = find_merkle_state_pda(program_id);
+        Self {
+            program_id: *program_id,
+            merkle_state,
+            event_authority: find_event_authority_pda(program_id),
+            tree_metadata: find_tree_metadata_pda(program_id, &merkle_state.0),
+            multisig: find_multisig_pda(program_id, &merkle_state.0),
+            pending_config: find_pending_config_pda(program_id, &merkle_state.0),
+        }
+    }
+
+    pub fn find_leaf_page(&self, page_index: u32) -> (Pubkey, u8) {
+        find_leaf_page_pda(&self.program_id, &self.merkle_state.0, page_index)
+    }
+}
@@ -1,41 +1,96 @@
-use crate::{state::MerkleStateAccount, utils::find_merkle_state_pda};
-use borsh::{BorshDeserialize, BorshSerialize};
+use crate::{
+    config::{ConfigChange, PendingConfigAccount},
+    error::MerkleTreeError,
+    events::{Event, EventVerbosity, LeafInserted, RootUpdated},
+    hash::TreeHasher,
+    leaf_page,
+    multisig::MultisigAccount,
+    pda::{
+        EVENT_AUTHORITY_SEED, LEAF_PAGE_SEED, MERKLE_STATE_SEED, MULTISIG_SEED,
+        PENDING_CONFIG_SEED, TREE_METADATA_SEED, find_event_authority_pda, find_leaf_page_pda,
+        find_merkle_state_pda, find_multisig_pda, find_pending_config_pda, find_tree_metadata_pda,
+    },
+    state::{ArchiveRecord, InsertCounter, LeafExpiry, LeafMetadata, MerkleStateAccount},
+    tree_metadata::TreeMetadataAccount,
+    zero_copy::{MerkleStateHeader, MerkleStateLayout, append_leaf, push_item},
+};
+use borsh::BorshSerialize;
 use solana_program::{
     account_info::{AccountInfo, next_account_info},
-    entrypoint::ProgramResult,
+    entrypoint::{MAX_PERMITTED_DATA_INCREASE, ProgramResult},
+    instruction::{AccountMeta, Instruction},
     msg,
-    program::{invoke, invoke_signed},
+    program::{invoke, invoke_signed, set_return_data},
     program_error::ProgramError,
     pubkey::Pubkey,
     rent::Rent,
-    system_instruction, system_program,
-    sysvar::Sysvar,
+    system_instruction::{self, MAX_PERMITTED_DATA_LENGTH},
+    system_program,
+    sysvar::{Sysvar, clock::Clock},
 };
 
+/// Insert leaf hashed from raw `data` with the tree hasher, plain SHA-256 for a new tree.
+pub fn process_insert_value(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    data: &[u8],
+    expected_root: Option<&[u8; 32]>,
+) -> ProgramResult {
+    let merkle_state_account = accounts.first().ok_or(ProgramError::NotEnoughAccountKeys)?;
+    verify_merkle_state_owner(program_id, merkle_state_account)?;
+    let tree_hasher = if merkle_state_account.data_is_empty() {
+        TreeHasher::default()
+    } else {
+        let data = merkle_state_account.data.borrow();
+        MerkleStateAccount::check_header(&data)?;
+        MerkleStateHeader::from_bytes(&data)?.get_tree_hasher()?
+    };
+
+    process_insert_leaf(
+        program_id,
+        accounts,
+        &tree_hasher.hash_value(data),
+        None,
+        expected_root,
+    )
+}
+
+/// Insert `hash`, optionally expiring at unix timestamp `expires_at`. With `expected_root`
+/// set, the tree must exist and have that root.
 pub fn process_insert_leaf(
     program_id: &Pubkey,
     accounts: &[AccountInfo],
     hash: &[u8; 32],
+    expires_at: Option<i64>,
+    expected_root: Option<&[u8; 32]>,
 ) -> ProgramResult {
     let accounts_iter = &mut accounts.iter();
 
     let merkle_state_account = next_account_info(accounts_iter)?;
     let payer_account = next_account_info(accounts_iter)?;
     let system_program = next_account_info(accounts_iter)?;
+    let event_authority_account = next_account_info(accounts_iter)?;
+    let program_account = next_account_info(accounts_iter)?;
 
-    // 1. Verify passed system program
+    // 1. Verify payer signature and passed system program
+    verify_signer(payer_account)?;
     if !system_program::check_id(system_program.key) {
-        return Err(ProgramError::InvalidAccountData);
+        return Err(MerkleTreeError::InvalidSystemProgram.into());
     }
 
-    // 2. Verify passed merkle state PDA
+    // 2. Verify passed merkle state PDA and its owner
     let (merkle_state_pda, merkle_state_bump) = find_merkle_state_pda(program_id);
     if &merkle_state_pda != merkle_state_account.key {
-        return Err(ProgramError::InvalidAccountData);
+        return Err(MerkleTreeError::InvalidMerkleStatePda.into());
     }
+    verify_merkle_state_owner(program_id, merkle_state_account)?;
 
     // 3. Get or create merkle state account, append leaf node, recalc root hash..
-    if merkle_state_account.data_is_empty() {
+    let (root_hash, leaves_count, event_verbosity) = if merkle_state_account.data_is_empty() {
+        if expected_root.is_some() {
+            return Err(MerkleTreeError::RootMismatch.into());
+        }
+
         let rent = Rent::get()?;
         let lamports = rent.minimum_balance(MerkleStateAccount::INIT_LEN);
 
@@ -52,41 +107,2146 @@ pub fn process_insert_leaf(
                 merkle_state_account.clone(),
                 system_program.clone(),
             ],
-            &[&[b"merkle_state", &[merkle_state_bump]]],
+            &[&[MERKLE_STATE_SEED, &[merkle_state_bump]]],
         )?;
 
-        let merkle_state = MerkleStateAccount::new(hash);
-        merkle_state.serialize(&mut &mut merkle_state_account.data.borrow_mut()[..])?;
-
-        msg!("{:x?}", merkle_state.get_root_hash());
-        Ok(())
+        let mut merkle_state = MerkleStateAccount::new(hash, payer_account.key);
+        if let Some(expires_at) = expires_at {
+            merkle_state.set_leaf_expiry(0, expires_at);
+            resize_merkle_state(
+                &merkle_state,
+                merkle_state_account,
+                payer_account,
+                system_program,
+            )?;
+        }
+        merkle_state.pack(&mut merkle_state_account.data.borrow_mut())?;
+        (
+            Some(merkle_state.get_root_hash()),
+            merkle_state.get_leaves_count(),
+            merkle_state.get_event_verbosity(),
+        )
     } else {
-        let rent = Rent::get()?;
-        let mut merkle_state =
-            MerkleStateAccount::try_from_slice(&merkle_state_account.data.borrow())?;
+        let data = merkle_state_account.data.borrow();
+        MerkleStateAccount::check_header(&data)?;
+        let header = *MerkleStateHeader::from_bytes(&data)?;
+        let layout = MerkleStateLayout::parse(&data)?;
+        if header.is_paused() {
+            return Err(MerkleTreeError::TreePaused.into());
+        }
+        if expected_root.is_some() && header.is_root_dirty() {
+            return Err(MerkleTreeError::RootNotCommitted.into());
+        }
+        if expected_root.is_some_and(|expected_root| expected_root != &header.root_hash) {
+            return Err(MerkleTreeError::RootMismatch.into());
+        }
+        if layout.is_sealed {
+            return Err(MerkleTreeError::TreeSealed.into());
+        }
+        if !header.get_hash_algo()?.is_valid_leaf(hash) {
+            return Err(MerkleTreeError::LeafNotInField.into());
+        }
+        if header.rejects_sentinel_leaves()
+            && !layout.is_frontier_mode
+            && header
+                .get_tree_hasher()?
+                .is_sentinel_leaf(hash, MerkleStateAccount::height(layout.leaves_count() + 1))
+        {
+            return Err(MerkleTreeError::SentinelLeaf.into());
+        }
+        if header.is_permissioned() && !layout.inserters(&data).contains(payer_account.key) {
+            return Err(MerkleTreeError::InvalidInserter.into());
+        }
+        if header.rejects_duplicates() && layout.leaf_hashes(&data).contains(hash) {
+            return Err(MerkleTreeError::DuplicateLeaf.into());
+        }
+        if layout.is_frontier_mode && expires_at.is_some() {
+            return Err(MerkleTreeError::UnsupportedInFrontierMode.into());
+        }
+        if header
+            .get_leaf_capacity()
+            .into_iter()
+            .chain(header.get_max_leaves())
+            .any(|max_leaves| layout.leaves_count() >= max_leaves as usize)
+        {
+            return Err(MerkleTreeError::TreeFull.into());
+        }
+        drop(data);
+
+        // Expiries, leaf metadata and insert counters are pushed in place too, unless insert
+        // counters of past epochs must be pruned first
+        let clock = match header.is_rate_limited() || header.records_leaf_metadata() {
+            true => Some(Clock::get()?),
+            false => None,
+        };
+        let insert_counters = layout.insert_counters(&merkle_state_account.data.borrow());
+        let has_stale_counters = header.is_rate_limited()
+            && clock.as_ref().is_some_and(|clock| {
+                insert_counters
+                    .iter()
+                    .any(|counter| counter.epoch != clock.epoch)
+            });
+
+        let (root_hash, leaves_count) = if layout.is_frontier_mode || has_stale_counters {
+            let mut merkle_state = MerkleStateAccount::unpack(&merkle_state_account.data.borrow())?;
+            // Frontier trees keep no leaves in the layout, their count is checked here
+            if merkle_state.is_full() {
+                return Err(MerkleTreeError::TreeFull.into());
+            }
+            if layout.is_frontier_mode && merkle_state.is_sentinel_leaf(hash) {
+                return Err(MerkleTreeError::SentinelLeaf.into());
+            }
+            let clock = Clock::get()?;
+            if !merkle_state.count_insert(payer_account.key, clock.slot, clock.epoch) {
+                return Err(MerkleTreeError::RateLimited.into());
+            }
+            merkle_state.add_leaf(hash);
+            let leaf_index = merkle_state.get_leaves_count() as u32 - 1;
+            merkle_state.record_leaf_metadata(clock.slot, payer_account.key);
+            if let Some(expires_at) = expires_at {
+                merkle_state.set_leaf_expiry(leaf_index, expires_at);
+            }
+            if merkle_state.has_leaf_pages() {
+                let leaf_page_account = next_account_info(accounts_iter)?;
+                get_or_create_leaf_page(
+                    program_id,
+                    merkle_state_account,
+                    leaf_page_account,
+                    payer_account,
+                    system_program,
+                    leaf_page::page_index(leaf_index),
+                )?;
+                leaf_page::leaves_mut(&mut leaf_page_account.data.borrow_mut())?
+                    [leaf_index as usize % leaf_page::LEAVES_PER_PAGE] = *hash;
+            }
+            resize_merkle_state(
+                &merkle_state,
+                merkle_state_account,
+                payer_account,
+                system_program,
+            )?;
+            merkle_state.pack(&mut merkle_state_account.data.borrow_mut())?;
+            (
+                merkle_state.get_root_hash(),
+                merkle_state.get_leaves_count(),
+            )
+        } else {
+            let leaf_index = layout.leaves_count() as u32;
+            let expiry = expires_at.map(|expires_at| LeafExpiry {
+                leaf_index,
+                expires_at,
+            });
+            let leaf_metadata = clock
+                .as_ref()
+                .filter(|_| header.records_leaf_metadata())
+                .map(|clock| LeafMetadata {
+                    leaf_index,
+                    slot: clock.slot,
+                    inserter: *payer_account.key,
+                });
+            // Counter position, `None` if the payer gets a new one
+            let insert_counter = match clock.as_ref().filter(|_| header.is_rate_limited()) {
+                Some(clock) => {
+                    let position = insert_counters
+                        .iter()
+                        .position(|counter| &counter.payer == payer_account.key);
+                    let mut counter = match position {
+                        Some(position) => insert_counters[position],
+                        None => InsertCounter {
+                            payer: *payer_account.key,
+                            slot: clock.slot,
+                            slot_inserts: 0,
+                            epoch: clock.epoch,
+                            epoch_inserts: 0,
+                        },
+                    };
+                    let (max_inserts_per_slot, max_inserts_per_epoch) = header.get_rate_limit();
+                    if !counter.count(clock.slot, max_inserts_per_slot, max_inserts_per_epoch) {
+                        return Err(MerkleTreeError::RateLimited.into());
+                    }
+                    Some((position, counter))
+                }
+                None => None,
+            };
+
+            // Trees keeping spare bytes only grow once they're used up. Preallocated trees
+            // grow to their capacity, as far as one realloc may, others by their growth
+            let items_len = expiry.map_or(0, |_| 4 + 8)
+                + leaf_metadata.map_or(0, |_| 4 + 8 + 32)
+                + match insert_counter {
+                    Some((None, _)) => MerkleStateLayout::INSERT_COUNTER_LEN,
+                    _ => 0,
+                };
+            let append_len = MerkleStateAccount::LEAF_LEN + items_len;
+            let grow_len = match (header.get_leaf_capacity(), header.get_growth_percent()) {
+                _ if layout.spare.len() >= append_len => 0,
+                (Some(leaf_capacity), _) => (leaf_capacity as usize)
+                    .checked_sub(layout.leaves_count())
+                    .and_then(|spare_leaves| spare_leaves.checked_mul(MerkleStateAccount::LEAF_LEN))
+                    .and_then(|spare_len| spare_len.checked_add(items_len))
+                    .and_then(|spare_len| spare_len.checked_sub(layout.spare.len()))
+                    .ok_or(ProgramError::ArithmeticOverflow)?
+                    .min(MAX_PERMITTED_DATA_INCREASE),
+                (None, Some(growth_percent)) => MerkleStateAccount::growth_len(
+                    append_len - layout.spare.len(),
+                    layout.leaves_count(),
+                    growth_percent,
+                    merkle_state_account.data_len(),
+                ),
+                (None, None) => append_len,
+            };
+            if grow_len > 0 {
+                resize_account(
+                    merkle_state_account
+                        .data_len()
+                        .checked_add(grow_len)
+                        .ok_or(ProgramError::ArithmeticOverflow)?,
+                    merkle_state_account,
+                    payer_account,
+                    system_program,
+                )?;
+            }
+
+            // Push items back to front, so ranges of the earlier vecs stay valid, then
+            // append the leaf. Leaves are neither deserialized nor rewritten
+            let mut data = merkle_state_account.data.borrow_mut();
+            let mut state_len = layout.leaf_hashes.end;
+            match insert_counter {
+                Some((Some(position), counter)) => {
+                    let offset = layout.insert_counters.start
+                        + position * MerkleStateLayout::INSERT_COUNTER_LEN;
+                    counter.serialize(
+                        &mut &mut data[offset..offset + MerkleStateLayout::INSERT_COUNTER_LEN],
+                    )?;
+                }
+                Some((None, counter)) => {
+                    state_len = push_item(
+                        &mut data,
+                        &layout.insert_counters,
+                        state_len,
+                        &borsh::to_vec(&counter)?,
+                    )?;
+                }
+                None => {}
+            }
+            if let Some(leaf_metadata) = leaf_metadata {
+                state_len = push_item(
+                    &mut data,
+                    &layout.leaf_metadata,
+                    state_len,
+                    &borsh::to_vec(&leaf_metadata)?,
+                )?;
+            }
+            if let Some(expiry) = expiry {
+                push_item(
+                    &mut data,
+                    &layout.expiries,
+                    state_len,
+                    &borsh::to_vec(&expiry)?,
+                )?;
+            }
+            append_leaf(&mut data, hash)?
+        };
+        // Roots of lazy trees are published by `CommitRoot` instead
+        let root_hash = (!header.is_lazy_root()).then_some(root_hash);
+        (root_hash, leaves_count, header.get_event_verbosity()?)
+    };
+    match &root_hash {
+        Some(root_hash) => msg!("{:x?}", root_hash),
+        None => msg!("Root pending commit"),
+    }
+
+    // 4. Emit leaf inserted and root updated events, as configured
+    emit_insert_events(
+        program_id,
+        event_authority_account,
+        program_account,
+        event_verbosity,
+        hash,
+        root_hash.as_ref(),
+        leaves_count as u32,
+    )
+}
+
+/// Create the tree with its first leaf `hash` hashing nodes with `tree_hasher`, preallocated
+/// for `leaf_capacity` leaves unless it's 0.
+pub fn process_init_tree(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    hash: &[u8; 32],
+    leaf_capacity: u32,
+    tree_hasher: TreeHasher,
+) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+
+    let merkle_state_account = next_account_info(accounts_iter)?;
+    let payer_account = next_account_info(accounts_iter)?;
+    let system_program = next_account_info(accounts_iter)?;
+    let event_authority_account = next_account_info(accounts_iter)?;
+    let program_account = next_account_info(accounts_iter)?;
+
+    // 1. Verify payer signature and passed system program
+    verify_signer(payer_account)?;
+    if !system_program::check_id(system_program.key) {
+        return Err(MerkleTreeError::InvalidSystemProgram.into());
+    }
+
+    // 2. Verify passed merkle state PDA and its owner
+    let (merkle_state_pda, merkle_state_bump) = find_merkle_state_pda(program_id);
+    if &merkle_state_pda != merkle_state_account.key {
+        return Err(MerkleTreeError::InvalidMerkleStatePda.into());
+    }
+    verify_merkle_state_owner(program_id, merkle_state_account)?;
+    if !merkle_state_account.data_is_empty() {
+        return Err(ProgramError::AccountAlreadyInitialized);
+    }
+    if !tree_hasher.is_valid_leaf(hash) {
+        return Err(MerkleTreeError::LeafNotInField.into());
+    }
+
+    // 3. Create merkle state account paying rent for the full capacity up front. Accounts
+    // created via CPI are capped at `MAX_PERMITTED_DATA_INCREASE` bytes, larger trees are
+    // grown by inserts once the allocated space is used up, without further rent
+    let preallocated_len = MerkleStateAccount::preallocated_len(leaf_capacity);
+    if preallocated_len > MAX_PERMITTED_DATA_LENGTH as usize {
+        return Err(MerkleTreeError::TreeFull.into());
+    }
+    invoke_signed(
+        &system_instruction::create_account(
+            payer_account.key,
+            &merkle_state_pda,
+            Rent::get()?.minimum_balance(preallocated_len),
+            preallocated_len.min(MAX_PERMITTED_DATA_INCREASE) as u64,
+            program_id,
+        ),
+        &[
+            payer_account.clone(),
+            merkle_state_account.clone(),
+            system_program.clone(),
+        ],
+        &[&[MERKLE_STATE_SEED, &[merkle_state_bump]]],
+    )?;
+
+    let mut merkle_state = MerkleStateAccount::new(hash, payer_account.key);
+    merkle_state.set_leaf_capacity(leaf_capacity);
+    merkle_state.set_tree_hasher(tree_hasher);
+    merkle_state.pack(&mut merkle_state_account.data.borrow_mut())?;
+    msg!("{:x?}", merkle_state.get_root_hash());
+
+    // 4. Emit leaf inserted and root updated events, as configured
+    emit_insert_events(
+        program_id,
+        event_authority_account,
+        program_account,
+        merkle_state.get_event_verbosity(),
+        hash,
+        Some(&merkle_state.get_root_hash()),
+        1,
+    )
+}
+
+/// Emit `LeafInserted` and `RootUpdated` for the last of `leaves_count` leaves, as
+/// `event_verbosity` configures. `root` is `None` under lazy root while it's left to
+/// `CommitRoot`, then only `LeafInserted` is emitted.
+fn emit_insert_events<'a>(
+    program_id: &Pubkey,
+    event_authority_account: &AccountInfo<'a>,
+    program_account: &AccountInfo<'a>,
+    event_verbosity: EventVerbosity,
+    leaf: &[u8; 32],
+    root: Option<&[u8; 32]>,
+    leaves_count: u32,
+) -> ProgramResult {
+    if event_verbosity.emits_changelog() {
+        emit_event(
+            program_id,
+            event_authority_account,
+            program_account,
+            &LeafInserted {
+                leaf_index: leaves_count - 1,
+                leaf: *leaf,
+            },
+        )?;
+    }
+    if let Some(root) = root.filter(|_| event_verbosity.emits_root()) {
+        emit_event(
+            program_id,
+            event_authority_account,
+            program_account,
+            &RootUpdated {
+                root: *root,
+                leaves_count,
+            },
+        )?;
+    }
+
+    Ok(())
+}
+
+pub fn process_propose_authority(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    new_authority: &Pubkey,
+) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+
+    let merkle_state_account = next_account_info(accounts_iter)?;
+    let authority_account = next_account_info(accounts_iter)?;
+
+    // 1. Verify passed merkle state PDA and its owner
+    let (merkle_state_pda, _) = find_merkle_state_pda(program_id);
+    if &merkle_state_pda != merkle_state_account.key {
+        return Err(MerkleTreeError::InvalidMerkleStatePda.into());
+    }
+    verify_merkle_state_owner(program_id, merkle_state_account)?;
+
+    // 2. Verify current authority, timelocked trees must lift the timelock first
+    let mut merkle_state = MerkleStateAccount::unpack(&merkle_state_account.data.borrow())?;
+    verify_authority(program_id, &merkle_state, authority_account, accounts)?;
+    verify_config_unlocked(&merkle_state)?;
 
-        // Calculate new size and updated rent-excempt balance
-        let new_size = merkle_state_account.data.borrow().len() + MerkleStateAccount::LEAF_LEN;
-        let lamports_diff = rent
-            .minimum_balance(new_size)
-            .checked_sub(merkle_state_account.lamports())
-            .ok_or(ProgramError::AccountNotRentExempt)?;
+    // 3. Store pending authority
+    merkle_state.propose_authority(new_authority);
+    merkle_state.pack(&mut merkle_state_account.data.borrow_mut())?;
 
+    Ok(())
+}
+
+pub fn process_accept_authority(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+
+    let merkle_state_account = next_account_info(accounts_iter)?;
+    let pending_authority_account = next_account_info(accounts_iter)?;
+
+    // 1. Verify passed merkle state PDA and its owner
+    let (merkle_state_pda, _) = find_merkle_state_pda(program_id);
+    if &merkle_state_pda != merkle_state_account.key {
+        return Err(MerkleTreeError::InvalidMerkleStatePda.into());
+    }
+    verify_merkle_state_owner(program_id, merkle_state_account)?;
+
+    // 2. Verify pending authority
+    let mut merkle_state = MerkleStateAccount::unpack(&merkle_state_account.data.borrow())?;
+    verify_signer_or_multisig(program_id, pending_authority_account, accounts)?;
+    if merkle_state.get_pending_authority().as_ref() != Some(pending_authority_account.key) {
+        return Err(MerkleTreeError::InvalidPendingAuthority.into());
+    }
+    verify_config_unlocked(&merkle_state)?;
+
+    // 3. Promote pending authority
+    merkle_state.accept_authority();
+    merkle_state.pack(&mut merkle_state_account.data.borrow_mut())?;
+
+    Ok(())
+}
+
+pub fn process_set_paused(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    paused: bool,
+) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+
+    let merkle_state_account = next_account_info(accounts_iter)?;
+    let authority_account = next_account_info(accounts_iter)?;
+
+    // 1. Verify passed merkle state PDA and its owner
+    let (merkle_state_pda, _) = find_merkle_state_pda(program_id);
+    if &merkle_state_pda != merkle_state_account.key {
+        return Err(MerkleTreeError::InvalidMerkleStatePda.into());
+    }
+    verify_merkle_state_owner(program_id, merkle_state_account)?;
+
+    // 2. Verify current authority
+    let mut merkle_state = MerkleStateAccount::unpack(&merkle_state_account.data.borrow())?;
+    verify_authority(program_id, &merkle_state, authority_account, accounts)?;
+
+    // 3. Toggle pause flag
+    merkle_state.set_paused(paused);
+    merkle_state.pack(&mut merkle_state_account.data.borrow_mut())?;
+
+    msg!("Paused: {}", paused);
+    Ok(())
+}
+
+/// Realloc merkle state account down to `MerkleStateAccount::account_len` and write it,
+/// refunding lamports above the rent-exempt minimum to `refund_account`.
+fn shrink_merkle_state(
+    merkle_state: &MerkleStateAccount,
+    merkle_state_account: &AccountInfo,
+    refund_account: &AccountInfo,
+) -> ProgramResult {
+    shrink_account(
+        merkle_state.account_len(merkle_state_account.data_len())?,
+        merkle_state_account,
+        refund_account,
+    )?;
+    merkle_state.pack(&mut merkle_state_account.data.borrow_mut())
+}
+
+/// Realloc program-owned `account` down to `new_size`, refunding lamports above the
+/// rent-exempt minimum to `refund_account`.
+fn shrink_account(
+    new_size: usize,
+    account: &AccountInfo,
+    refund_account: &AccountInfo,
+) -> ProgramResult {
+    account.realloc(new_size, false)?;
+
+    let refund = account
+        .lamports()
+        .saturating_sub(Rent::get()?.minimum_balance(new_size));
+    **account.try_borrow_mut_lamports()? -= refund;
+    **refund_account.try_borrow_mut_lamports()? += refund;
+
+    Ok(())
+}
+
+/// Write back `pending_config` refunding freed rent to `refund_account`, closing its
+/// account once no changes are left.
+fn shrink_pending_config(
+    pending_config: &PendingConfigAccount,
+    pending_config_account: &AccountInfo,
+    refund_account: &AccountInfo,
+) -> ProgramResult {
+    let (new_size, rent_exempt_balance) = match pending_config.changes.is_empty() {
+        true => (0, 0),
+        false => {
+            let new_size = borsh::object_length(pending_config)?;
+            (new_size, Rent::get()?.minimum_balance(new_size))
+        }
+    };
+    pending_config_account.realloc(new_size, false)?;
+    if new_size > 0 {
+        pending_config.serialize(&mut &mut pending_config_account.data.borrow_mut()[..])?;
+    }
+
+    let refund = pending_config_account
+        .lamports()
+        .saturating_sub(rent_exempt_balance);
+    **pending_config_account.try_borrow_mut_lamports()? -= refund;
+    **refund_account.try_borrow_mut_lamports()? += refund;
+    // Closed account can be created again by a later instruction of the same transaction
+    if new_size == 0 {
+        pending_config_account.assign(&system_program::ID);
+    }
+
+    Ok(())
+}
+
+/// Verify `leaf_page_account` is leaf page `page_index` of the tree, creating it funded by
+/// `payer_account` if it doesn't exist yet.
+fn get_or_create_leaf_page<'a>(
+    program_id: &Pubkey,
+    merkle_state_account: &AccountInfo<'a>,
+    leaf_page_account: &AccountInfo<'a>,
+    payer_account: &AccountInfo<'a>,
+    system_program: &AccountInfo<'a>,
+    page_index: u32,
+) -> ProgramResult {
+    let (leaf_page_pda, leaf_page_bump) =
+        find_leaf_page_pda(program_id, merkle_state_account.key, page_index);
+    if &leaf_page_pda != leaf_page_account.key {
+        return Err(MerkleTreeError::InvalidLeafPage.into());
+    }
+    if !leaf_page_account.data_is_empty() {
+        return Ok(());
+    }
+
+    invoke_signed(
+        &system_instruction::create_account(
+            payer_account.key,
+            &leaf_page_pda,
+            Rent::get()?.minimum_balance(leaf_page::LEN),
+            leaf_page::LEN as u64,
+            program_id,
+        ),
+        &[
+            payer_account.clone(),
+            leaf_page_account.clone(),
+            system_program.clone(),
+        ],
+        &[&[
+            LEAF_PAGE_SEED,
+            merkle_state_account.key.as_ref(),
+            &page_index.to_le_bytes(),
+            &[leaf_page_bump],
+        ]],
+    )?;
+    leaf_page::init(&mut leaf_page_account.data.borrow_mut());
+
+    Ok(())
+}
+
+/// Realloc merkle state account to `MerkleStateAccount::account_len`, see `resize_account`.
+fn resize_merkle_state<'a>(
+    merkle_state: &MerkleStateAccount,
+    merkle_state_account: &AccountInfo<'a>,
+    payer_account: &AccountInfo<'a>,
+    system_program: &AccountInfo<'a>,
+) -> ProgramResult {
+    resize_account(
+        merkle_state.account_len(merkle_state_account.data_len())?,
+        merkle_state_account,
+        payer_account,
+        system_program,
+    )
+}
+
+/// Realloc program-owned `account` to `new_size`, rent-exempt balance is topped up from
+/// `payer_account`. Growing by more than `MAX_PERMITTED_DATA_INCREASE` past the length at the
+/// start of the instruction fails with `ReallocLimitExceeded` rather than an opaque runtime
+/// error. Extra lamports on the account (e.g. direct transfers) only lower the top-up.
+/// A program-owned payer, i.e. a multisig authority, is debited directly down to its own
+/// rent-exempt minimum.
+fn resize_account<'a>(
+    new_size: usize,
+    account: &AccountInfo<'a>,
+    payer_account: &AccountInfo<'a>,
+    system_program: &AccountInfo<'a>,
+) -> ProgramResult {
+    if new_size > MAX_PERMITTED_DATA_LENGTH as usize {
+        return Err(MerkleTreeError::TreeFull.into());
+    }
+    // SAFETY: `account` comes from the entrypoint input, which serializes the original data
+    // length right before the account key.
+    let original_len = unsafe { account.original_data_len() };
+    if new_size > original_len + MAX_PERMITTED_DATA_INCREASE {
+        return Err(MerkleTreeError::ReallocLimitExceeded.into());
+    }
+
+    let rent = Rent::get()?;
+    let lamports_diff = rent
+        .minimum_balance(new_size)
+        .saturating_sub(account.lamports());
+
+    if lamports_diff > 0 && payer_account.owner == account.owner {
+        if payer_account.lamports() < rent.minimum_balance(payer_account.data_len()) + lamports_diff
+        {
+            return Err(ProgramError::InsufficientFunds);
+        }
+        **payer_account.try_borrow_mut_lamports()? -= lamports_diff;
+        **account.try_borrow_mut_lamports()? += lamports_diff;
+    } else if lamports_diff > 0 {
         invoke(
-            &system_instruction::transfer(payer_account.key, &merkle_state_pda, lamports_diff),
+            &system_instruction::transfer(payer_account.key, account.key, lamports_diff),
             &[
                 payer_account.clone(),
-                merkle_state_account.clone(),
+                account.clone(),
                 system_program.clone(),
             ],
         )?;
+    }
+
+    account.realloc(new_size, false)
+}
+
+/// Emit `event` via self-CPI signed by the event authority PDA, see `events::Event`.
+fn emit_event<'a, E: Event>(
+    program_id: &Pubkey,
+    event_authority_account: &AccountInfo<'a>,
+    program_account: &AccountInfo<'a>,
+    event: &E,
+) -> ProgramResult {
+    let (event_authority_pda, event_authority_bump) = find_event_authority_pda(program_id);
+    if &event_authority_pda != event_authority_account.key {
+        return Err(MerkleTreeError::InvalidEventAuthority.into());
+    }
+    if program_account.key != program_id {
+        return Err(ProgramError::IncorrectProgramId);
+    }
+
+    invoke_signed(
+        &Instruction::new_with_bytes(
+            *program_id,
+            &event.to_instruction_data(),
+            vec![AccountMeta::new_readonly(event_authority_pda, true)],
+        ),
+        &[event_authority_account.clone(), program_account.clone()],
+        &[&[EVENT_AUTHORITY_SEED, &[event_authority_bump]]],
+    )
+}
+
+/// Verify a non-empty merkle state account is owned by the program and holds at least the
+/// state header, before its data is deserialized. Empty accounts are yet to be created.
+fn verify_merkle_state_owner(
+    program_id: &Pubkey,
+    merkle_state_account: &AccountInfo,
+) -> ProgramResult {
+    if merkle_state_account.data_is_empty() {
+        return Ok(());
+    }
+    if merkle_state_account.owner != program_id {
+        return Err(MerkleTreeError::InvalidMerkleStateOwner.into());
+    }
+    if merkle_state_account.data_len() < MerkleStateHeader::LEN {
+        return Err(MerkleTreeError::StateSizeMismatch.into());
+    }
+
+    Ok(())
+}
+
+/// Verify `account` signed, rather than leaving e.g. an unsigned payer to fail in a later
+/// system program transfer.
+fn verify_signer(account: &AccountInfo) -> ProgramResult {
+    if !account.is_signer {
+        return Err(MerkleTreeError::MissingSigner.into());
+    }
+
+    Ok(())
+}
+
+/// Verify `account` signed, or is a multisig approved by enough of its signers among
+/// `accounts`, see `MultisigAccount::is_approved`.
+fn verify_signer_or_multisig(
+    program_id: &Pubkey,
+    account: &AccountInfo,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    if account.is_signer {
+        return Ok(());
+    }
+    if account.owner != program_id {
+        return Err(MerkleTreeError::MissingSigner.into());
+    }
+    match MultisigAccount::unpack(&account.data.borrow()) {
+        Ok(multisig) if multisig.is_approved(accounts) => Ok(()),
+        _ => Err(MerkleTreeError::MissingSigner.into()),
+    }
+}
+
+fn verify_authority(
+    program_id: &Pubkey,
+    merkle_state: &MerkleStateAccount,
+    authority_account: &AccountInfo,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    verify_signer_or_multisig(program_id, authority_account, accounts)?;
+    if &merkle_state.get_authority() != authority_account.key {
+        return Err(MerkleTreeError::InvalidAuthority.into());
+    }
+
+    Ok(())
+}
+
+/// Verify config of `merkle_state` may be changed directly, i.e. it has no config timelock.
+fn verify_config_unlocked(merkle_state: &MerkleStateAccount) -> ProgramResult {
+    if merkle_state.get_config_timelock().is_some() {
+        return Err(MerkleTreeError::ConfigTimelocked.into());
+    }
+
+    Ok(())
+}
+
+/// Verify the root of `merkle_state` reflects all its leaves, i.e. none appended in lazy root
+/// mode are pending.
+fn verify_root_committed(merkle_state: &MerkleStateAccount) -> ProgramResult {
+    if merkle_state.is_root_dirty() {
+        return Err(MerkleTreeError::RootNotCommitted.into());
+    }
+
+    Ok(())
+}
+
+pub fn process_verify_leaf(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    leaf_index: u32,
+    leaf: &[u8; 32],
+    proof: &[[u8; 32]],
+) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+
+    let merkle_state_account = next_account_info(accounts_iter)?;
+
+    // 1. Verify passed merkle state PDA and its owner
+    let (merkle_state_pda, _) = find_merkle_state_pda(program_id);
+    if &merkle_state_pda != merkle_state_account.key {
+        return Err(MerkleTreeError::InvalidMerkleStatePda.into());
+    }
+    verify_merkle_state_owner(program_id, merkle_state_account)?;
+
+    // 2. Verify proof against root or canopy
+    let merkle_state = MerkleStateAccount::unpack(&merkle_state_account.data.borrow())?;
+    verify_root_committed(&merkle_state)?;
+    if !merkle_state.verify_leaf(leaf_index, leaf, proof) {
+        return Err(MerkleTreeError::InvalidProof.into());
+    }
+
+    Ok(())
+}
+
+pub fn process_replace_leaf(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    leaf_index: u32,
+    old_leaf: &[u8; 32],
+    new_leaf: &[u8; 32],
+    proof: &[[u8; 32]],
+) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+
+    let merkle_state_account = next_account_info(accounts_iter)?;
+    let authority_account = next_account_info(accounts_iter)?;
+    let system_program = next_account_info(accounts_iter)?;
+    let event_authority_account = next_account_info(accounts_iter)?;
+    let program_account = next_account_info(accounts_iter)?;
+
+    // 1. Verify passed system program
+    if !system_program::check_id(system_program.key) {
+        return Err(MerkleTreeError::InvalidSystemProgram.into());
+    }
+
+    // 2. Verify passed merkle state PDA and its owner
+    let (merkle_state_pda, _) = find_merkle_state_pda(program_id);
+    if &merkle_state_pda != merkle_state_account.key {
+        return Err(MerkleTreeError::InvalidMerkleStatePda.into());
+    }
+    verify_merkle_state_owner(program_id, merkle_state_account)?;
+
+    // 3. Verify current authority
+    let mut merkle_state = MerkleStateAccount::unpack(&merkle_state_account.data.borrow())?;
+    if merkle_state.is_paused() {
+        return Err(MerkleTreeError::TreePaused.into());
+    }
+    if merkle_state.is_sealed() {
+        return Err(MerkleTreeError::TreeSealed.into());
+    }
+    verify_authority(program_id, &merkle_state, authority_account, accounts)?;
+    verify_root_committed(&merkle_state)?;
+    if !merkle_state.get_hash_algo().is_valid_leaf(new_leaf) {
+        return Err(MerkleTreeError::LeafNotInField.into());
+    }
+    if merkle_state.is_sentinel_leaf(new_leaf) {
+        return Err(MerkleTreeError::SentinelLeaf.into());
+    }
+    if merkle_state.is_duplicate(new_leaf) {
+        return Err(MerkleTreeError::DuplicateLeaf.into());
+    }
+
+    // 4. Verify proof against recent roots, replace leaf and recalc root hash.
+    // Frontier changelog may grow the account
+    if !merkle_state.replace_leaf(leaf_index, old_leaf, new_leaf, proof) {
+        return Err(MerkleTreeError::InvalidProof.into());
+    }
+    if merkle_state.has_leaf_pages() {
+        let leaf_page_account = next_account_info(accounts_iter)?;
+        get_or_create_leaf_page(
+            program_id,
+            merkle_state_account,
+            leaf_page_account,
+            authority_account,
+            system_program,
+            leaf_page::page_index(leaf_index),
+        )?;
+        let mut leaf_page_data = leaf_page_account.data.borrow_mut();
+        let page_leaf = &mut leaf_page::leaves_mut(&mut leaf_page_data)?
+            [leaf_index as usize % leaf_page::LEAVES_PER_PAGE];
+        if page_leaf != old_leaf {
+            return Err(MerkleTreeError::InvalidLeafPage.into());
+        }
+        *page_leaf = *new_leaf;
+    }
+    resize_merkle_state(
+        &merkle_state,
+        merkle_state_account,
+        authority_account,
+        system_program,
+    )?;
+    merkle_state.pack(&mut merkle_state_account.data.borrow_mut())?;
+    msg!("{:x?}", merkle_state.get_root_hash());
+
+    // 5. Emit root updated event, as configured
+    if merkle_state.get_event_verbosity().emits_root() {
+        emit_event(
+            program_id,
+            event_authority_account,
+            program_account,
+            &RootUpdated {
+                root: merkle_state.get_root_hash(),
+                leaves_count: merkle_state.get_leaves_count() as u32,
+            },
+        )?;
+    }
+
+    Ok(())
+}
+
+pub fn process_verify_multiproof(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    leaves: &[(u32, [u8; 32])],
+    proof: &[[u8; 32]],
+) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+
+    let merkle_state_account = next_account_info(accounts_iter)?;
+
+    // 1. Verify passed merkle state PDA and its owner
+    let (merkle_state_pda, _) = find_merkle_state_pda(program_id);
+    if &merkle_state_pda != merkle_state_account.key {
+        return Err(MerkleTreeError::InvalidMerkleStatePda.into());
+    }
+    verify_merkle_state_owner(program_id, merkle_state_account)?;
 
-        merkle_state_account.realloc(new_size, false)?;
+    // 2. Verify all leaves against root in one pass
+    let merkle_state = MerkleStateAccount::unpack(&merkle_state_account.data.borrow())?;
+    verify_root_committed(&merkle_state)?;
+    if !merkle_state.verify_leaves(leaves, proof) {
+        return Err(MerkleTreeError::InvalidProof.into());
+    }
+
+    Ok(())
+}
+
+pub fn process_set_archive_uri(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    archive: ArchiveRecord,
+) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+
+    let merkle_state_account = next_account_info(accounts_iter)?;
+    let authority_account = next_account_info(accounts_iter)?;
+    let system_program = next_account_info(accounts_iter)?;
+
+    // 1. Verify passed system program
+    if !system_program::check_id(system_program.key) {
+        return Err(MerkleTreeError::InvalidSystemProgram.into());
+    }
+
+    // 2. Verify passed merkle state PDA and its owner
+    let (merkle_state_pda, _) = find_merkle_state_pda(program_id);
+    if &merkle_state_pda != merkle_state_account.key {
+        return Err(MerkleTreeError::InvalidMerkleStatePda.into());
+    }
+    verify_merkle_state_owner(program_id, merkle_state_account)?;
+
+    // 3. Verify current authority
+    let mut merkle_state = MerkleStateAccount::unpack(&merkle_state_account.data.borrow())?;
+    verify_authority(program_id, &merkle_state, authority_account, accounts)?;
+    verify_root_committed(&merkle_state)?;
+    if merkle_state.is_frontier_mode() {
+        return Err(MerkleTreeError::UnsupportedInFrontierMode.into());
+    }
+
+    // 4. Verify archive against current root and leaves, resize account for the new URI
+    if archive.uri.len() > MerkleStateAccount::MAX_ARCHIVE_URI_LEN {
+        return Err(MerkleTreeError::ArchiveUriTooLong.into());
+    }
+    if !merkle_state.set_archive(archive) {
+        return Err(MerkleTreeError::InvalidArchive.into());
+    }
+    resize_merkle_state(
+        &merkle_state,
+        merkle_state_account,
+        authority_account,
+        system_program,
+    )?;
+    merkle_state.pack(&mut merkle_state_account.data.borrow_mut())?;
+
+    Ok(())
+}
+
+pub fn process_get_root_hash(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
 
-        merkle_state.add_leaf(hash);
-        merkle_state.serialize(&mut &mut merkle_state_account.data.borrow_mut()[..])?;
+    let merkle_state_account = next_account_info(accounts_iter)?;
 
-        msg!("{:x?}", merkle_state.get_root_hash());
-        Ok(())
+    // 1. Verify passed merkle state PDA and its owner
+    let (merkle_state_pda, _) = find_merkle_state_pda(program_id);
+    if &merkle_state_pda != merkle_state_account.key {
+        return Err(MerkleTreeError::InvalidMerkleStatePda.into());
     }
+    verify_merkle_state_owner(program_id, merkle_state_account)?;
+
+    // 2. Return root hash
+    let merkle_state = MerkleStateAccount::unpack(&merkle_state_account.data.borrow())?;
+    verify_root_committed(&merkle_state)?;
+    set_return_data(&borsh::to_vec(&merkle_state.get_root_hash())?);
+
+    Ok(())
+}
+
+/// Accept self-CPI event, the payload is only recorded in inner instructions.
+pub fn process_emit_event(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+
+    let event_authority_account = next_account_info(accounts_iter)?;
+
+    // 1. Verify event is emitted by the program itself
+    let (event_authority_pda, _) = find_event_authority_pda(program_id);
+    if !event_authority_account.is_signer || &event_authority_pda != event_authority_account.key {
+        return Err(MerkleTreeError::InvalidEventAuthority.into());
+    }
+
+    Ok(())
+}
+
+pub fn process_set_reject_duplicates(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    reject_duplicates: bool,
+) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+
+    let merkle_state_account = next_account_info(accounts_iter)?;
+    let authority_account = next_account_info(accounts_iter)?;
+
+    // 1. Verify passed merkle state PDA and its owner
+    let (merkle_state_pda, _) = find_merkle_state_pda(program_id);
+    if &merkle_state_pda != merkle_state_account.key {
+        return Err(MerkleTreeError::InvalidMerkleStatePda.into());
+    }
+    verify_merkle_state_owner(program_id, merkle_state_account)?;
+
+    // 2. Verify current authority
+    let mut merkle_state = MerkleStateAccount::unpack(&merkle_state_account.data.borrow())?;
+    verify_authority(program_id, &merkle_state, authority_account, accounts)?;
+    verify_config_unlocked(&merkle_state)?;
+    if reject_duplicates && merkle_state.is_frontier_mode() {
+        return Err(MerkleTreeError::UnsupportedInFrontierMode.into());
+    }
+
+    // 3. Toggle duplicate rejection, leaves already in the tree are kept
+    merkle_state.set_reject_duplicates(reject_duplicates);
+    merkle_state.pack(&mut merkle_state_account.data.borrow_mut())?;
+
+    msg!("Reject duplicates: {}", reject_duplicates);
+    Ok(())
+}
+
+pub fn process_set_record_leaf_metadata(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    record_leaf_metadata: bool,
+) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+
+    let merkle_state_account = next_account_info(accounts_iter)?;
+    let authority_account = next_account_info(accounts_iter)?;
+
+    // 1. Verify passed merkle state PDA and its owner
+    let (merkle_state_pda, _) = find_merkle_state_pda(program_id);
+    if &merkle_state_pda != merkle_state_account.key {
+        return Err(MerkleTreeError::InvalidMerkleStatePda.into());
+    }
+    verify_merkle_state_owner(program_id, merkle_state_account)?;
+
+    // 2. Verify current authority
+    let mut merkle_state = MerkleStateAccount::unpack(&merkle_state_account.data.borrow())?;
+    verify_authority(program_id, &merkle_state, authority_account, accounts)?;
+    verify_config_unlocked(&merkle_state)?;
+
+    // 3. Toggle leaf metadata recording, already recorded metadata is kept
+    merkle_state.set_record_leaf_metadata(record_leaf_metadata);
+    merkle_state.pack(&mut merkle_state_account.data.borrow_mut())?;
+
+    msg!("Record leaf metadata: {}", record_leaf_metadata);
+    Ok(())
+}
+
+pub fn process_finalize_tree(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+
+    let merkle_state_account = next_account_info(accounts_iter)?;
+    let authority_account = next_account_info(accounts_iter)?;
+    let system_program = next_account_info(accounts_iter)?;
+
+    // 1. Verify passed system program
+    if !system_program::check_id(system_program.key) {
+        return Err(MerkleTreeError::InvalidSystemProgram.into());
+    }
+
+    // 2. Verify passed merkle state PDA and its owner
+    let (merkle_state_pda, _) = find_merkle_state_pda(program_id);
+    if &merkle_state_pda != merkle_state_account.key {
+        return Err(MerkleTreeError::InvalidMerkleStatePda.into());
+    }
+    verify_merkle_state_owner(program_id, merkle_state_account)?;
+
+    // 3. Verify current authority
+    let mut merkle_state = MerkleStateAccount::unpack(&merkle_state_account.data.borrow())?;
+    if merkle_state.is_paused() {
+        return Err(MerkleTreeError::TreePaused.into());
+    }
+    if merkle_state.is_sealed() {
+        return Err(MerkleTreeError::TreeSealed.into());
+    }
+    verify_authority(program_id, &merkle_state, authority_account, accounts)?;
+    verify_root_committed(&merkle_state)?;
+
+    // 4. Record final root, leaves count and slot
+    merkle_state.seal(Clock::get()?.slot);
+    resize_merkle_state(
+        &merkle_state,
+        merkle_state_account,
+        authority_account,
+        system_program,
+    )?;
+    merkle_state.pack(&mut merkle_state_account.data.borrow_mut())?;
+
+    msg!("Sealed: {:x?}", merkle_state.get_root_hash());
+    Ok(())
+}
+
+pub fn process_verify_consistency(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    old_root: &[u8; 32],
+    old_leaves_count: u32,
+    leaf: &[u8; 32],
+    proof: &[[u8; 32]],
+) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+
+    let merkle_state_account = next_account_info(accounts_iter)?;
+
+    // 1. Verify passed merkle state PDA and its owner
+    let (merkle_state_pda, _) = find_merkle_state_pda(program_id);
+    if &merkle_state_pda != merkle_state_account.key {
+        return Err(MerkleTreeError::InvalidMerkleStatePda.into());
+    }
+    verify_merkle_state_owner(program_id, merkle_state_account)?;
+
+    // 2. Verify old tree is a prefix of the current one
+    let merkle_state = MerkleStateAccount::unpack(&merkle_state_account.data.borrow())?;
+    verify_root_committed(&merkle_state)?;
+    if !merkle_state.verify_consistency(old_root, old_leaves_count, leaf, proof) {
+        return Err(MerkleTreeError::InvalidProof.into());
+    }
+
+    Ok(())
+}
+
+pub fn process_enable_frontier_mode(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+
+    let merkle_state_account = next_account_info(accounts_iter)?;
+    let authority_account = next_account_info(accounts_iter)?;
+
+    // 1. Verify passed merkle state PDA and its owner
+    let (merkle_state_pda, _) = find_merkle_state_pda(program_id);
+    if &merkle_state_pda != merkle_state_account.key {
+        return Err(MerkleTreeError::InvalidMerkleStatePda.into());
+    }
+    verify_merkle_state_owner(program_id, merkle_state_account)?;
+
+    // 2. Verify current authority, duplicates, expired leaves, left-balanced proofs and lazy
+    // roots can't be handled without stored leaves
+    let mut merkle_state = MerkleStateAccount::unpack(&merkle_state_account.data.borrow())?;
+    if merkle_state.is_paused() {
+        return Err(MerkleTreeError::TreePaused.into());
+    }
+    verify_authority(program_id, &merkle_state, authority_account, accounts)?;
+    verify_config_unlocked(&merkle_state)?;
+    if merkle_state.rejects_duplicates()
+        || !merkle_state.get_leaf_expiries().is_empty()
+        || merkle_state.get_tree_hasher().left_balanced
+        || merkle_state.is_lazy_root()
+    {
+        return Err(MerkleTreeError::UnsupportedInFrontierMode.into());
+    }
+
+    // 3. Drop stored leaves, shrink account and refund freed rent to the authority
+    merkle_state.enable_frontier_mode();
+    shrink_merkle_state(&merkle_state, merkle_state_account, authority_account)?;
+
+    msg!("Frontier mode: {} leaves", merkle_state.get_leaves_count());
+    Ok(())
+}
+
+pub fn process_set_event_verbosity(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    event_verbosity: EventVerbosity,
+) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+
+    let merkle_state_account = next_account_info(accounts_iter)?;
+    let authority_account = next_account_info(accounts_iter)?;
+
+    // 1. Verify passed merkle state PDA and its owner
+    let (merkle_state_pda, _) = find_merkle_state_pda(program_id);
+    if &merkle_state_pda != merkle_state_account.key {
+        return Err(MerkleTreeError::InvalidMerkleStatePda.into());
+    }
+    verify_merkle_state_owner(program_id, merkle_state_account)?;
+
+    // 2. Verify current authority
+    let mut merkle_state = MerkleStateAccount::unpack(&merkle_state_account.data.borrow())?;
+    verify_authority(program_id, &merkle_state, authority_account, accounts)?;
+    verify_config_unlocked(&merkle_state)?;
+
+    // 3. Set emitted events
+    merkle_state.set_event_verbosity(event_verbosity);
+    merkle_state.pack(&mut merkle_state_account.data.borrow_mut())?;
+
+    msg!("Event verbosity: {:?}", event_verbosity);
+    Ok(())
+}
+
+pub fn process_sweep_expired(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+
+    let merkle_state_account = next_account_info(accounts_iter)?;
+    let cranker_account = next_account_info(accounts_iter)?;
+    let event_authority_account = next_account_info(accounts_iter)?;
+    let program_account = next_account_info(accounts_iter)?;
+
+    // 1. Verify passed merkle state PDA and its owner
+    let (merkle_state_pda, _) = find_merkle_state_pda(program_id);
+    if &merkle_state_pda != merkle_state_account.key {
+        return Err(MerkleTreeError::InvalidMerkleStatePda.into());
+    }
+    verify_merkle_state_owner(program_id, merkle_state_account)?;
+
+    // 2. Verify tree accepts leaf changes, anyone can sweep
+    let mut merkle_state = MerkleStateAccount::unpack(&merkle_state_account.data.borrow())?;
+    verify_signer(cranker_account)?;
+    if merkle_state.is_paused() {
+        return Err(MerkleTreeError::TreePaused.into());
+    }
+    if merkle_state.is_sealed() {
+        return Err(MerkleTreeError::TreeSealed.into());
+    }
+
+    // 3. Tombstone expired leaves, recalc root hash and shrink account
+    let swept = merkle_state.sweep_expired(Clock::get()?.unix_timestamp);
+    if swept == 0 {
+        return Err(MerkleTreeError::NoExpiredLeaves.into());
+    }
+    let new_size = merkle_state.account_len(merkle_state_account.data_len())?;
+    merkle_state_account.realloc(new_size, false)?;
+    merkle_state.pack(&mut merkle_state_account.data.borrow_mut())?;
+    msg!("Swept: {} leaves", swept);
+
+    // 4. Pay bounty from the balance above rent-exempt minimum, i.e. direct transfers and
+    // rent freed by the sweep
+    let treasury = merkle_state_account
+        .lamports()
+        .saturating_sub(Rent::get()?.minimum_balance(new_size));
+    let bounty = treasury.min(swept as u64 * MerkleStateAccount::SWEEP_BOUNTY_LAMPORTS);
+    **merkle_state_account.try_borrow_mut_lamports()? -= bounty;
+    **cranker_account.try_borrow_mut_lamports()? += bounty;
+
+    // 5. Emit root updated event, as configured
+    if merkle_state.get_event_verbosity().emits_root() {
+        emit_event(
+            program_id,
+            event_authority_account,
+            program_account,
+            &RootUpdated {
+                root: merkle_state.get_root_hash(),
+                leaves_count: merkle_state.get_leaves_count() as u32,
+            },
+        )?;
+    }
+
+    Ok(())
+}
+
+pub fn process_enable_leaf_pages(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    first_page: u32,
+    pages_count: u32,
+) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+
+    let merkle_state_account = next_account_info(accounts_iter)?;
+    let authority_account = next_account_info(accounts_iter)?;
+    let system_program = next_account_info(accounts_iter)?;
+
+    // 1. Verify passed system program
+    if !system_program::check_id(system_program.key) {
+        return Err(MerkleTreeError::InvalidSystemProgram.into());
+    }
+
+    // 2. Verify passed merkle state PDA and its owner
+    let (merkle_state_pda, _) = find_merkle_state_pda(program_id);
+    if &merkle_state_pda != merkle_state_account.key {
+        return Err(MerkleTreeError::InvalidMerkleStatePda.into());
+    }
+    verify_merkle_state_owner(program_id, merkle_state_account)?;
+
+    // 3. Verify current authority, leaves must still be stored to be paged
+    let mut merkle_state = MerkleStateAccount::unpack(&merkle_state_account.data.borrow())?;
+    if merkle_state.is_paused() {
+        return Err(MerkleTreeError::TreePaused.into());
+    }
+    verify_authority(program_id, &merkle_state, authority_account, accounts)?;
+    verify_config_unlocked(&merkle_state)?;
+    if merkle_state.is_frontier_mode() {
+        return Err(MerkleTreeError::UnsupportedInFrontierMode.into());
+    }
+    if merkle_state.rejects_duplicates()
+        || !merkle_state.get_leaf_expiries().is_empty()
+        || merkle_state.get_tree_hasher().left_balanced
+        || merkle_state.is_lazy_root()
+    {
+        return Err(MerkleTreeError::UnsupportedWithLeafPages.into());
+    }
+
+    // 4. Verify the range resumes at the next page to write and holds leaves, pages before
+    // it were written by earlier calls
+    let leaves_count = merkle_state.get_leaves_count();
+    let end_page = first_page
+        .checked_add(pages_count)
+        .ok_or(ProgramError::ArithmeticOverflow)?;
+    if first_page != leaf_page::page_index(merkle_state.get_paged_leaves())
+        || pages_count == 0
+        || end_page as usize > leaf_page::pages_count(leaves_count)
+    {
+        return Err(MerkleTreeError::InvalidLeafPage.into());
+    }
+
+    // 5. Write stored leaves of the range to pages, passed in page order
+    let page_leaves = merkle_state
+        .leaf_hashes()
+        .chunks(leaf_page::LEAVES_PER_PAGE)
+        .skip(first_page as usize)
+        .take(pages_count as usize);
+    for (page_index, page_leaves) in (first_page..end_page).zip(page_leaves) {
+        let leaf_page_account = next_account_info(accounts_iter)?;
+        get_or_create_leaf_page(
+            program_id,
+            merkle_state_account,
+            leaf_page_account,
+            authority_account,
+            system_program,
+            page_index,
+        )?;
+        leaf_page::leaves_mut(&mut leaf_page_account.data.borrow_mut())?[..page_leaves.len()]
+            .copy_from_slice(page_leaves);
+    }
+
+    // 6. Record progress, once every leaf is paged drop stored leaves, shrink account and
+    // refund freed rent to the authority
+    if !merkle_state.page_leaves(end_page) {
+        merkle_state.pack(&mut merkle_state_account.data.borrow_mut())?;
+        msg!(
+            "Leaf pages: {} of {} leaves paged",
+            merkle_state.get_paged_leaves(),
+            leaves_count
+        );
+        return Ok(());
+    }
+    shrink_merkle_state(&merkle_state, merkle_state_account, authority_account)?;
+
+    msg!(
+        "Leaf pages: {} leaves in {} pages",
+        leaves_count,
+        leaf_page::pages_count(leaves_count)
+    );
+    Ok(())
+}
+
+pub fn process_migrate_state(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+
+    let merkle_state_account = next_account_info(accounts_iter)?;
+    let payer_account = next_account_info(accounts_iter)?;
+    let system_program = next_account_info(accounts_iter)?;
+
+    // 1. Verify payer signature and passed system program
+    verify_signer(payer_account)?;
+    if !system_program::check_id(system_program.key) {
+        return Err(MerkleTreeError::InvalidSystemProgram.into());
+    }
+
+    // 2. Verify passed merkle state PDA and its owner
+    let (merkle_state_pda, _) = find_merkle_state_pda(program_id);
+    if &merkle_state_pda != merkle_state_account.key {
+        return Err(MerkleTreeError::InvalidMerkleStatePda.into());
+    }
+    verify_merkle_state_owner(program_id, merkle_state_account)?;
+
+    // 3. Upgrade older layout, current accounts are left as is
+    let Some(merkle_state) = MerkleStateAccount::migrate(&merkle_state_account.data.borrow())?
+    else {
+        msg!("Merkle state is up to date");
+        return Ok(());
+    };
+
+    // 4. Grow account and write current layout
+    resize_merkle_state(
+        &merkle_state,
+        merkle_state_account,
+        payer_account,
+        system_program,
+    )?;
+    merkle_state.pack(&mut merkle_state_account.data.borrow_mut())?;
+
+    msg!("Migrated to version {}", MerkleStateAccount::VERSION);
+    Ok(())
+}
+
+pub fn process_set_metadata(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    metadata: TreeMetadataAccount,
+) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+
+    let merkle_state_account = next_account_info(accounts_iter)?;
+    let authority_account = next_account_info(accounts_iter)?;
+    let system_program = next_account_info(accounts_iter)?;
+    let metadata_account = next_account_info(accounts_iter)?;
+
+    // 1. Verify passed system program
+    if !system_program::check_id(system_program.key) {
+        return Err(MerkleTreeError::InvalidSystemProgram.into());
+    }
+
+    // 2. Verify passed merkle state PDA and its owner
+    let (merkle_state_pda, _) = find_merkle_state_pda(program_id);
+    if &merkle_state_pda != merkle_state_account.key {
+        return Err(MerkleTreeError::InvalidMerkleStatePda.into());
+    }
+    verify_merkle_state_owner(program_id, merkle_state_account)?;
+
+    // 3. Verify current authority
+    let merkle_state = MerkleStateAccount::unpack(&merkle_state_account.data.borrow())?;
+    verify_authority(program_id, &merkle_state, authority_account, accounts)?;
+
+    // 4. Verify passed metadata PDA, create or resize it for the new metadata
+    let (metadata_pda, metadata_bump) =
+        find_tree_metadata_pda(program_id, merkle_state_account.key);
+    if &metadata_pda != metadata_account.key {
+        return Err(MerkleTreeError::InvalidTreeMetadata.into());
+    }
+    let new_size = borsh::object_length(&metadata)?;
+    if metadata_account.data_is_empty() {
+        invoke_signed(
+            &system_instruction::create_account(
+                authority_account.key,
+                &metadata_pda,
+                Rent::get()?.minimum_balance(new_size),
+                new_size as u64,
+                program_id,
+            ),
+            &[
+                authority_account.clone(),
+                metadata_account.clone(),
+                system_program.clone(),
+            ],
+            &[&[
+                TREE_METADATA_SEED,
+                merkle_state_account.key.as_ref(),
+                &[metadata_bump],
+            ]],
+        )?;
+    } else {
+        TreeMetadataAccount::unpack(&metadata_account.data.borrow())?;
+        resize_account(
+            new_size,
+            metadata_account,
+            authority_account,
+            system_program,
+        )?;
+    }
+    metadata.serialize(&mut &mut metadata_account.data.borrow_mut()[..])?;
+
+    Ok(())
+}
+
+pub fn process_add_inserter(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    inserter: &Pubkey,
+) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+
+    let merkle_state_account = next_account_info(accounts_iter)?;
+    let authority_account = next_account_info(accounts_iter)?;
+    let system_program = next_account_info(accounts_iter)?;
+
+    // 1. Verify passed system program
+    if !system_program::check_id(system_program.key) {
+        return Err(MerkleTreeError::InvalidSystemProgram.into());
+    }
+
+    // 2. Verify passed merkle state PDA and its owner
+    let (merkle_state_pda, _) = find_merkle_state_pda(program_id);
+    if &merkle_state_pda != merkle_state_account.key {
+        return Err(MerkleTreeError::InvalidMerkleStatePda.into());
+    }
+    verify_merkle_state_owner(program_id, merkle_state_account)?;
+
+    // 3. Verify current authority
+    let mut merkle_state = MerkleStateAccount::unpack(&merkle_state_account.data.borrow())?;
+    verify_authority(program_id, &merkle_state, authority_account, accounts)?;
+    verify_config_unlocked(&merkle_state)?;
+
+    // 4. Allow inserter, resize account for it unless it's already allowed
+    if !merkle_state.add_inserter(inserter) {
+        msg!("Inserter already allowed");
+        return Ok(());
+    }
+    resize_merkle_state(
+        &merkle_state,
+        merkle_state_account,
+        authority_account,
+        system_program,
+    )?;
+    merkle_state.pack(&mut merkle_state_account.data.borrow_mut())?;
+
+    Ok(())
+}
+
+pub fn process_remove_inserter(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    inserter: &Pubkey,
+) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+
+    let merkle_state_account = next_account_info(accounts_iter)?;
+    let authority_account = next_account_info(accounts_iter)?;
+
+    // 1. Verify passed merkle state PDA and its owner
+    let (merkle_state_pda, _) = find_merkle_state_pda(program_id);
+    if &merkle_state_pda != merkle_state_account.key {
+        return Err(MerkleTreeError::InvalidMerkleStatePda.into());
+    }
+    verify_merkle_state_owner(program_id, merkle_state_account)?;
+
+    // 2. Verify current authority
+    let mut merkle_state = MerkleStateAccount::unpack(&merkle_state_account.data.borrow())?;
+    verify_authority(program_id, &merkle_state, authority_account, accounts)?;
+    verify_config_unlocked(&merkle_state)?;
+
+    // 3. Disallow inserter, shrink account refunding its rent to the authority
+    if !merkle_state.remove_inserter(inserter) {
+        return Err(MerkleTreeError::InvalidInserter.into());
+    }
+    shrink_merkle_state(&merkle_state, merkle_state_account, authority_account)
+}
+
+pub fn process_set_permissioned(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    permissioned: bool,
+) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+
+    let merkle_state_account = next_account_info(accounts_iter)?;
+    let authority_account = next_account_info(accounts_iter)?;
+
+    // 1. Verify passed merkle state PDA and its owner
+    let (merkle_state_pda, _) = find_merkle_state_pda(program_id);
+    if &merkle_state_pda != merkle_state_account.key {
+        return Err(MerkleTreeError::InvalidMerkleStatePda.into());
+    }
+    verify_merkle_state_owner(program_id, merkle_state_account)?;
+
+    // 2. Verify current authority
+    let mut merkle_state = MerkleStateAccount::unpack(&merkle_state_account.data.borrow())?;
+    verify_authority(program_id, &merkle_state, authority_account, accounts)?;
+    verify_config_unlocked(&merkle_state)?;
+
+    // 3. Toggle permissioned inserts, allowed inserters are kept
+    merkle_state.set_permissioned(permissioned);
+    merkle_state.pack(&mut merkle_state_account.data.borrow_mut())?;
+
+    msg!("Permissioned: {}", permissioned);
+    Ok(())
+}
+
+pub fn process_set_multisig(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    multisig: MultisigAccount,
+) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+
+    let merkle_state_account = next_account_info(accounts_iter)?;
+    let authority_account = next_account_info(accounts_iter)?;
+    let payer_account = next_account_info(accounts_iter)?;
+    let system_program = next_account_info(accounts_iter)?;
+    let multisig_account = next_account_info(accounts_iter)?;
+
+    // 1. Verify payer signature and passed system program
+    verify_signer(payer_account)?;
+    if !system_program::check_id(system_program.key) {
+        return Err(MerkleTreeError::InvalidSystemProgram.into());
+    }
+
+    // 2. Verify passed merkle state PDA and its owner
+    let (merkle_state_pda, _) = find_merkle_state_pda(program_id);
+    if &merkle_state_pda != merkle_state_account.key {
+        return Err(MerkleTreeError::InvalidMerkleStatePda.into());
+    }
+    verify_merkle_state_owner(program_id, merkle_state_account)?;
+
+    // 3. Verify current authority, possibly the multisig itself
+    let merkle_state = MerkleStateAccount::unpack(&merkle_state_account.data.borrow())?;
+    verify_authority(program_id, &merkle_state, authority_account, accounts)?;
+    verify_config_unlocked(&merkle_state)?;
+
+    // 4. Verify passed multisig PDA, create or resize it for the new signers
+    let (multisig_pda, multisig_bump) = find_multisig_pda(program_id, merkle_state_account.key);
+    if &multisig_pda != multisig_account.key {
+        return Err(MerkleTreeError::InvalidMultisig.into());
+    }
+    let new_size = borsh::object_length(&multisig)?;
+    if multisig_account.data_is_empty() {
+        invoke_signed(
+            &system_instruction::create_account(
+                payer_account.key,
+                &multisig_pda,
+                Rent::get()?.minimum_balance(new_size),
+                new_size as u64,
+                program_id,
+            ),
+            &[
+                payer_account.clone(),
+                multisig_account.clone(),
+                system_program.clone(),
+            ],
+            &[&[
+                MULTISIG_SEED,
+                merkle_state_account.key.as_ref(),
+                &[multisig_bump],
+            ]],
+        )?;
+    } else {
+        MultisigAccount::unpack(&multisig_account.data.borrow())?;
+        resize_account(new_size, multisig_account, payer_account, system_program)?;
+    }
+    multisig.serialize(&mut &mut multisig_account.data.borrow_mut()[..])?;
+
+    msg!(
+        "Multisig: {} of {}",
+        multisig.threshold,
+        multisig.signers.len()
+    );
+    Ok(())
+}
+
+pub fn process_set_rate_limit(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    max_inserts_per_slot: u32,
+    max_inserts_per_epoch: u32,
+) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+
+    let merkle_state_account = next_account_info(accounts_iter)?;
+    let authority_account = next_account_info(accounts_iter)?;
+
+    // 1. Verify passed merkle state PDA and its owner
+    let (merkle_state_pda, _) = find_merkle_state_pda(program_id);
+    if &merkle_state_pda != merkle_state_account.key {
+        return Err(MerkleTreeError::InvalidMerkleStatePda.into());
+    }
+    verify_merkle_state_owner(program_id, merkle_state_account)?;
+
+    // 2. Verify current authority
+    let mut merkle_state = MerkleStateAccount::unpack(&merkle_state_account.data.borrow())?;
+    verify_authority(program_id, &merkle_state, authority_account, accounts)?;
+    verify_config_unlocked(&merkle_state)?;
+
+    // 3. Set rate limit, refunding rent of counters dropped when it's lifted
+    merkle_state.set_rate_limit(max_inserts_per_slot, max_inserts_per_epoch);
+    shrink_merkle_state(&merkle_state, merkle_state_account, authority_account)?;
+
+    msg!(
+        "Rate limit: {} per slot, {} per epoch",
+        max_inserts_per_slot,
+        max_inserts_per_epoch
+    );
+    Ok(())
+}
+
+pub fn process_set_max_leaves(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    max_leaves: u32,
+) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+
+    let merkle_state_account = next_account_info(accounts_iter)?;
+    let authority_account = next_account_info(accounts_iter)?;
+
+    // 1. Verify passed merkle state PDA and its owner
+    let (merkle_state_pda, _) = find_merkle_state_pda(program_id);
+    if &merkle_state_pda != merkle_state_account.key {
+        return Err(MerkleTreeError::InvalidMerkleStatePda.into());
+    }
+    verify_merkle_state_owner(program_id, merkle_state_account)?;
+
+    // 2. Verify current authority
+    let mut merkle_state = MerkleStateAccount::unpack(&merkle_state_account.data.borrow())?;
+    verify_authority(program_id, &merkle_state, authority_account, accounts)?;
+    verify_config_unlocked(&merkle_state)?;
+
+    // 3. Cap the tree, leaves already beyond the cap are kept
+    merkle_state.set_max_leaves(max_leaves);
+    merkle_state.pack(&mut merkle_state_account.data.borrow_mut())?;
+
+    msg!("Max leaves: {}", max_leaves);
+    Ok(())
+}
+
+pub fn process_set_growth_percent(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    growth_percent: u16,
+) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+
+    let merkle_state_account = next_account_info(accounts_iter)?;
+    let authority_account = next_account_info(accounts_iter)?;
+
+    // 1. Verify passed merkle state PDA and its owner
+    let (merkle_state_pda, _) = find_merkle_state_pda(program_id);
+    if &merkle_state_pda != merkle_state_account.key {
+        return Err(MerkleTreeError::InvalidMerkleStatePda.into());
+    }
+    verify_merkle_state_owner(program_id, merkle_state_account)?;
+
+    // 2. Verify current authority
+    let mut merkle_state = MerkleStateAccount::unpack(&merkle_state_account.data.borrow())?;
+    verify_authority(program_id, &merkle_state, authority_account, accounts)?;
+    verify_config_unlocked(&merkle_state)?;
+    if growth_percent != 0 && merkle_state.is_frontier_mode() {
+        return Err(MerkleTreeError::UnsupportedInFrontierMode.into());
+    }
+
+    // 3. Set growth, spare bytes of a tree no longer keeping them are refunded
+    merkle_state.set_growth_percent(growth_percent);
+    shrink_merkle_state(&merkle_state, merkle_state_account, authority_account)?;
+
+    msg!("Growth: {}%", growth_percent);
+    Ok(())
+}
+
+pub fn process_set_canopy_depth(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    canopy_depth: u8,
+) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+
+    let merkle_state_account = next_account_info(accounts_iter)?;
+    let authority_account = next_account_info(accounts_iter)?;
+    let system_program = next_account_info(accounts_iter)?;
+
+    // 1. Verify passed system program
+    if !system_program::check_id(system_program.key) {
+        return Err(MerkleTreeError::InvalidSystemProgram.into());
+    }
+
+    // 2. Verify passed merkle state PDA and its owner
+    let (merkle_state_pda, _) = find_merkle_state_pda(program_id);
+    if &merkle_state_pda != merkle_state_account.key {
+        return Err(MerkleTreeError::InvalidMerkleStatePda.into());
+    }
+    verify_merkle_state_owner(program_id, merkle_state_account)?;
+
+    // 3. Verify current authority, frontier trees have no leaves to cache layers of
+    let mut merkle_state = MerkleStateAccount::unpack(&merkle_state_account.data.borrow())?;
+    if merkle_state.is_paused() {
+        return Err(MerkleTreeError::TreePaused.into());
+    }
+    verify_authority(program_id, &merkle_state, authority_account, accounts)?;
+    verify_config_unlocked(&merkle_state)?;
+    if merkle_state.is_frontier_mode() {
+        return Err(MerkleTreeError::UnsupportedInFrontierMode.into());
+    }
+    if canopy_depth > MerkleStateAccount::MAX_CANOPY_DEPTH {
+        return Err(ProgramError::InvalidArgument);
+    }
+
+    // 4. Recompute the canopy at its new depth, growing the account with the authority
+    // paying or shrinking it with the authority refunded
+    merkle_state.set_canopy_depth(canopy_depth);
+    let account_len = merkle_state.account_len(merkle_state_account.data_len())?;
+    match account_len > merkle_state_account.data_len() {
+        true => resize_account(
+            account_len,
+            merkle_state_account,
+            authority_account,
+            system_program,
+        )?,
+        false => shrink_account(account_len, merkle_state_account, authority_account)?,
+    }
+    merkle_state.pack(&mut merkle_state_account.data.borrow_mut())?;
+
+    msg!("Canopy depth: {}", canopy_depth);
+    Ok(())
+}
+
+pub fn process_set_lazy_root(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    lazy_root: bool,
+) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+
+    let merkle_state_account = next_account_info(accounts_iter)?;
+    let authority_account = next_account_info(accounts_iter)?;
+
+    // 1. Verify passed merkle state PDA and its owner
+    let (merkle_state_pda, _) = find_merkle_state_pda(program_id);
+    if &merkle_state_pda != merkle_state_account.key {
+        return Err(MerkleTreeError::InvalidMerkleStatePda.into());
+    }
+    verify_merkle_state_owner(program_id, merkle_state_account)?;
+
+    // 2. Verify current authority, frontier trees must hash inserts into the frontier
+    let mut merkle_state = MerkleStateAccount::unpack(&merkle_state_account.data.borrow())?;
+    verify_authority(program_id, &merkle_state, authority_account, accounts)?;
+    verify_config_unlocked(&merkle_state)?;
+    if lazy_root && merkle_state.is_frontier_mode() {
+        return Err(MerkleTreeError::UnsupportedInFrontierMode.into());
+    }
+    if !lazy_root {
+        verify_root_committed(&merkle_state)?;
+    }
+
+    // 3. Toggle lazy root mode
+    merkle_state.set_lazy_root(lazy_root);
+    merkle_state.pack(&mut merkle_state_account.data.borrow_mut())?;
+
+    msg!("Lazy root: {}", lazy_root);
+    Ok(())
+}
+
+/// Publish the root of leaves appended in lazy root mode, recomputed from all leaves at once.
+pub fn process_commit_root(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+
+    let merkle_state_account = next_account_info(accounts_iter)?;
+    let event_authority_account = next_account_info(accounts_iter)?;
+    let program_account = next_account_info(accounts_iter)?;
+
+    // 1. Verify passed merkle state PDA and its owner
+    let (merkle_state_pda, _) = find_merkle_state_pda(program_id);
+    if &merkle_state_pda != merkle_state_account.key {
+        return Err(MerkleTreeError::InvalidMerkleStatePda.into());
+    }
+    verify_merkle_state_owner(program_id, merkle_state_account)?;
+
+    // 2. Recompute root, canopy and filled subtrees, anyone can commit unless paused
+    let mut merkle_state = MerkleStateAccount::unpack(&merkle_state_account.data.borrow())?;
+    if merkle_state.is_paused() {
+        return Err(MerkleTreeError::TreePaused.into());
+    }
+    if !merkle_state.commit_root() {
+        msg!("Root up to date");
+        return Ok(());
+    }
+    merkle_state.pack(&mut merkle_state_account.data.borrow_mut())?;
+    msg!("{:x?}", merkle_state.get_root_hash());
+
+    // 3. Emit root updated event, as configured
+    if merkle_state.get_event_verbosity().emits_root() {
+        emit_event(
+            program_id,
+            event_authority_account,
+            program_account,
+            &RootUpdated {
+                root: merkle_state.get_root_hash(),
+                leaves_count: merkle_state.get_leaves_count() as u32,
+            },
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Grow the tree account towards `target_len` bytes by at most one realloc, paid by
+/// `payer_account`. Only trees keeping spare bytes can hold the grown space.
+pub fn process_grow_tree(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    target_len: u32,
+) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+
+    let merkle_state_account = next_account_info(accounts_iter)?;
+    let payer_account = next_account_info(accounts_iter)?;
+    let system_program = next_account_info(accounts_iter)?;
+
+    // 1. Verify payer signature and passed system program
+    verify_signer(payer_account)?;
+    if !system_program::check_id(system_program.key) {
+        return Err(MerkleTreeError::InvalidSystemProgram.into());
+    }
+
+    // 2. Verify passed merkle state PDA and its owner
+    let (merkle_state_pda, _) = find_merkle_state_pda(program_id);
+    if &merkle_state_pda != merkle_state_account.key {
+        return Err(MerkleTreeError::InvalidMerkleStatePda.into());
+    }
+    verify_merkle_state_owner(program_id, merkle_state_account)?;
+
+    // 3. Verify the tree keeps spare bytes, others must span their account exactly
+    {
+        let data = merkle_state_account.data.borrow();
+        MerkleStateAccount::check_header(&data)?;
+        if !MerkleStateHeader::from_bytes(&data)?.keeps_spare() {
+            return Err(MerkleTreeError::StateSizeMismatch.into());
+        }
+    }
+
+    // 4. Grow as far as one realloc may, already large enough accounts are left as is
+    let data_len = merkle_state_account.data_len();
+    let new_size = (target_len as usize).min(data_len.saturating_add(MAX_PERMITTED_DATA_INCREASE));
+    if new_size > data_len {
+        resize_account(
+            new_size,
+            merkle_state_account,
+            payer_account,
+            system_program,
+        )?;
+    }
+
+    msg!("Size: {}", merkle_state_account.data_len());
+    Ok(())
+}
+
+pub fn process_set_reject_sentinel_leaves(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    reject_sentinel_leaves: bool,
+) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+
+    let merkle_state_account = next_account_info(accounts_iter)?;
+    let authority_account = next_account_info(accounts_iter)?;
+
+    // 1. Verify passed merkle state PDA and its owner
+    let (merkle_state_pda, _) = find_merkle_state_pda(program_id);
+    if &merkle_state_pda != merkle_state_account.key {
+        return Err(MerkleTreeError::InvalidMerkleStatePda.into());
+    }
+    verify_merkle_state_owner(program_id, merkle_state_account)?;
+
+    // 2. Verify current authority
+    let mut merkle_state = MerkleStateAccount::unpack(&merkle_state_account.data.borrow())?;
+    verify_authority(program_id, &merkle_state, authority_account, accounts)?;
+    verify_config_unlocked(&merkle_state)?;
+
+    // 3. Toggle sentinel rejection, leaves already in the tree are kept
+    merkle_state.set_reject_sentinel_leaves(reject_sentinel_leaves);
+    merkle_state.pack(&mut merkle_state_account.data.borrow_mut())?;
+
+    msg!("Reject sentinel leaves: {}", reject_sentinel_leaves);
+    Ok(())
+}
+
+pub fn process_set_config_timelock(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    config_timelock: u64,
+) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+
+    let merkle_state_account = next_account_info(accounts_iter)?;
+    let authority_account = next_account_info(accounts_iter)?;
+
+    // 1. Verify passed merkle state PDA and its owner
+    let (merkle_state_pda, _) = find_merkle_state_pda(program_id);
+    if &merkle_state_pda != merkle_state_account.key {
+        return Err(MerkleTreeError::InvalidMerkleStatePda.into());
+    }
+    verify_merkle_state_owner(program_id, merkle_state_account)?;
+
+    // 2. Verify current authority, a timelock in place delays its own change too
+    let mut merkle_state = MerkleStateAccount::unpack(&merkle_state_account.data.borrow())?;
+    verify_authority(program_id, &merkle_state, authority_account, accounts)?;
+    verify_config_unlocked(&merkle_state)?;
+
+    // 3. Set config timelock
+    merkle_state.set_config_timelock(config_timelock);
+    merkle_state.pack(&mut merkle_state_account.data.borrow_mut())?;
+
+    msg!("Config timelock: {} slots", config_timelock);
+    Ok(())
+}
+
+pub fn process_queue_config(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    change: ConfigChange,
+) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+
+    let merkle_state_account = next_account_info(accounts_iter)?;
+    let authority_account = next_account_info(accounts_iter)?;
+    let payer_account = next_account_info(accounts_iter)?;
+    let system_program = next_account_info(accounts_iter)?;
+    let pending_config_account = next_account_info(accounts_iter)?;
+
+    // 1. Verify payer signature and passed system program
+    verify_signer(payer_account)?;
+    if !system_program::check_id(system_program.key) {
+        return Err(MerkleTreeError::InvalidSystemProgram.into());
+    }
+
+    // 2. Verify passed merkle state PDA and its owner
+    let (merkle_state_pda, _) = find_merkle_state_pda(program_id);
+    if &merkle_state_pda != merkle_state_account.key {
+        return Err(MerkleTreeError::InvalidMerkleStatePda.into());
+    }
+    verify_merkle_state_owner(program_id, merkle_state_account)?;
+
+    // 3. Verify current authority and that the change applies to the current config, the
+    // changed state isn't written back
+    let mut merkle_state = MerkleStateAccount::unpack(&merkle_state_account.data.borrow())?;
+    verify_authority(program_id, &merkle_state, authority_account, accounts)?;
+    let config_timelock = merkle_state.get_config_timelock().unwrap_or_default();
+    change.apply(&mut merkle_state)?;
+
+    // 4. Verify passed pending config PDA, create or grow it for the queued change
+    let (pending_config_pda, pending_config_bump) =
+        find_pending_config_pda(program_id, merkle_state_account.key);
+    if &pending_config_pda != pending_config_account.key {
+        return Err(MerkleTreeError::InvalidPendingConfig.into());
+    }
+    let mut pending_config = match pending_config_account.data_is_empty() {
+        true => PendingConfigAccount::new(),
+        false => PendingConfigAccount::unpack(&pending_config_account.data.borrow())?,
+    };
+    let effective_slot = Clock::get()?.slot.saturating_add(config_timelock);
+    if !pending_config.push(change, effective_slot) {
+        return Err(MerkleTreeError::InvalidPendingConfig.into());
+    }
+    let new_size = borsh::object_length(&pending_config)?;
+    if pending_config_account.data_is_empty() {
+        invoke_signed(
+            &system_instruction::create_account(
+                payer_account.key,
+                &pending_config_pda,
+                Rent::get()?.minimum_balance(new_size),
+                new_size as u64,
+                program_id,
+            ),
+            &[
+                payer_account.clone(),
+                pending_config_account.clone(),
+                system_program.clone(),
+            ],
+            &[&[
+                PENDING_CONFIG_SEED,
+                merkle_state_account.key.as_ref(),
+                &[pending_config_bump],
+            ]],
+        )?;
+    } else {
+        resize_account(
+            new_size,
+            pending_config_account,
+            payer_account,
+            system_program,
+        )?;
+    }
+    pending_config.serialize(&mut &mut pending_config_account.data.borrow_mut()[..])?;
+
+    msg!("Config change effective at slot {}", effective_slot);
+    Ok(())
+}
+
+pub fn process_apply_pending_config(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+
+    let merkle_state_account = next_account_info(accounts_iter)?;
+    let authority_account = next_account_info(accounts_iter)?;
+    let system_program = next_account_info(accounts_iter)?;
+    let pending_config_account = next_account_info(accounts_iter)?;
+
+    // 1. Verify passed system program
+    if !system_program::check_id(system_program.key) {
+        return Err(MerkleTreeError::InvalidSystemProgram.into());
+    }
+
+    // 2. Verify passed merkle state PDA and its owner
+    let (merkle_state_pda, _) = find_merkle_state_pda(program_id);
+    if &merkle_state_pda != merkle_state_account.key {
+        return Err(MerkleTreeError::InvalidMerkleStatePda.into());
+    }
+    verify_merkle_state_owner(program_id, merkle_state_account)?;
+
+    // 3. Verify current authority
+    let mut merkle_state = MerkleStateAccount::unpack(&merkle_state_account.data.borrow())?;
+    verify_authority(program_id, &merkle_state, authority_account, accounts)?;
+
+    // 4. Verify passed pending config PDA and take the changes due
+    let (pending_config_pda, _) = find_pending_config_pda(program_id, merkle_state_account.key);
+    if &pending_config_pda != pending_config_account.key
+        || pending_config_account.owner != program_id
+    {
+        return Err(MerkleTreeError::InvalidPendingConfig.into());
+    }
+    let mut pending_config = PendingConfigAccount::unpack(&pending_config_account.data.borrow())?;
+    let changes = pending_config.take_due(Clock::get()?.slot);
+    if changes.is_empty() {
+        return Err(MerkleTreeError::InvalidPendingConfig.into());
+    }
+
+    // 5. Apply them in queue order, resizing the account with the authority paying or
+    // refunded
+    for change in &changes {
+        change.apply(&mut merkle_state)?;
+    }
+    resize_merkle_state(
+        &merkle_state,
+        merkle_state_account,
+        authority_account,
+        system_program,
+    )?;
+    shrink_merkle_state(&merkle_state, merkle_state_account, authority_account)?;
+    shrink_pending_config(&pending_config, pending_config_account, authority_account)?;
+
+    msg!("Applied {} config changes", changes.len());
+    Ok(())
+}
+
+pub fn process_cancel_pending_config(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+) -> ProgramResult {
+    let accounts_iter = &mut accounts.iter();
+
+    let merkle_state_account = next_account_info(accounts_iter)?;
+    let authority_account = next_account_info(accounts_iter)?;
+    let pending_config_account = next_account_info(accounts_iter)?;
+
+    // 1. Verify passed merkle state PDA and its owner
+    let (merkle_state_pda, _) = find_merkle_state_pda(program_id);
+    if &merkle_state_pda != merkle_state_account.key {
+        return Err(MerkleTreeError::InvalidMerkleStatePda.into());
+    }
+    verify_merkle_state_owner(program_id, merkle_state_account)?;
+
+    // 2. Verify current authority
+    let merkle_state = MerkleStateAccount::unpack(&merkle_state_account.data.borrow())?;
+    verify_authority(program_id, &merkle_state, authority_account, accounts)?;
+
+    // 3. Verify passed pending config PDA and close it, refunding rent to the authority
+    let (pending_config_pda, _) = find_pending_config_pda(program_id, merkle_state_account.key);
+    if &pending_config_pda != pending_config_account.key
+        || pending_config_account.owner != program_id
+    {
+        return Err(MerkleTreeError::InvalidPendingConfig.into());
+    }
+    let pending_config = PendingConfigAccount::unpack(&pending_config_account.data.borrow())?;
+    shrink_pending_config(
+        &PendingConfigAccount::new(),
+        pending_config_account,
+        authority_account,
+    )?;
+
+    msg!("Cancelled {} config changes", pending_config.changes.len());
+    Ok(())
 }
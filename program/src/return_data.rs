@@ -0,0 +1,52 @@
+use base64::{Engine, engine::general_purpose::STANDARD};
+use borsh::BorshDeserialize;
+use solana_program::{program::get_return_data, pubkey::Pubkey};
+use std::{io::Read, marker::PhantomData, str::FromStr};
+
+pub use merkle_tree_errors::ReturnDataError;
+
+/// Borsh encoded getter result, set via `set_return_data` by `program_id`.
+pub struct ReturnData<T>(PhantomData<T>);
+
+impl<T: BorshDeserialize> ReturnData<T> {
+    /// Decode return data `data` set by `returned_program_id`, e.g. `TransactionReturnData`
+    /// of banks client simulation or metadata.
+    /// Runtime strips trailing zeros outside of CPI, so missing bytes are read as zeros.
+    pub fn decode(
+        program_id: &Pubkey,
+        returned_program_id: &Pubkey,
+        data: &[u8],
+    ) -> Result<T, ReturnDataError> {
+        if returned_program_id != program_id {
+            return Err(ReturnDataError::WrongProgram {
+                expected: *program_id,
+                actual: *returned_program_id,
+            });
+        }
+
+        T::deserialize_reader(&mut data.chain(std::io::repeat(0)))
+            .map_err(|_| ReturnDataError::InvalidData)
+    }
+
+    /// Decode return data of a getter invoked via CPI by the current program.
+    pub fn from_cpi(program_id: &Pubkey) -> Result<T, ReturnDataError> {
+        let (returned_program_id, data) = get_return_data().ok_or(ReturnDataError::Missing)?;
+        Self::decode(program_id, &returned_program_id, &data)
+    }
+
+    /// Decode RPC return data(`UiTransactionReturnData` of simulation response or
+    /// transaction metadata), `data_base64` is the base64 encoded payload.
+    pub fn from_rpc(
+        program_id: &Pubkey,
+        returned_program_id: &str,
+        data_base64: &str,
+    ) -> Result<T, ReturnDataError> {
+        let returned_program_id =
+            Pubkey::from_str(returned_program_id).map_err(|_| ReturnDataError::InvalidEncoding)?;
+        let data = STANDARD
+            .decode(data_base64)
+            .map_err(|_| ReturnDataError::InvalidEncoding)?;
+
+        Self::decode(program_id, &returned_program_id, &data)
+    }
+}
@@ -1,58 +1,1443 @@
-use crate::utils::hash_sorted_pair;
+use crate::{
+    error::MerkleTreeError,
+    events::EventVerbosity,
+    hash::{HashAlgo, TreeHasher},
+    leaf_page,
+    utils::hash_leaves,
+    verify::{
+        Canopy, NodeHasher, compute_audit_root, hash_at, tree_height, verify_consistency,
+        verify_multiproof, verify_proof_against_roots, verify_proof_with_canopy,
+    },
+};
 use borsh::{BorshDeserialize, BorshSerialize};
+use merkle_tree_core::tree::{
+    self, append_with_canopy, compute_filled_subtrees, compute_root_with_canopy, fold_leaves,
+};
+use solana_program::{
+    entrypoint::{MAX_PERMITTED_DATA_INCREASE, ProgramResult},
+    program_error::ProgramError,
+    pubkey::Pubkey,
+    system_instruction::MAX_PERMITTED_DATA_LENGTH,
+};
+use std::ops::Range;
 
-#[derive(Debug, BorshSerialize, BorshDeserialize)]
+pub use merkle_tree_core::frontier::{ChangeLogEntry, Frontier};
+
+// Proofs fast-forwarded over the frontier changelog target a root in the history
+const _: () = assert!(Frontier::CHANGELOG_LEN == MerkleStateAccount::ROOT_HISTORY_LEN);
+
+/// Off-chain snapshot of the tree leaves, see `utils::hash_leaves`.
+#[derive(Debug, Clone, PartialEq, Eq, BorshSerialize, BorshDeserialize)]
+pub struct ArchiveRecord {
+    /// Tree root the snapshot was taken at.
+    pub root: [u8; 32],
+    /// Snapshot content hash.
+    pub content_hash: [u8; 32],
+    /// Snapshot location, e.g. `ipfs://<cid>` or `ar://<tx id>`.
+    pub uri: String,
+}
+
+/// Final tree state recorded by `FinalizeTree`.
+#[derive(Debug, Clone, PartialEq, Eq, BorshSerialize, BorshDeserialize)]
+pub struct TreeSeal {
+    pub root: [u8; 32],
+    pub leaves_count: u32,
+    /// Slot the tree was sealed at.
+    pub slot: u64,
+}
+
+/// Who inserted a leaf and when, recorded while the tree records leaf metadata.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, BorshSerialize, BorshDeserialize)]
+pub struct LeafMetadata {
+    pub leaf_index: u32,
+    /// Slot the leaf was inserted at.
+    pub slot: u64,
+    /// Payer of the insert instruction.
+    pub inserter: Pubkey,
+}
+
+/// Inserts of one payer in its last slot and the current epoch, kept while the tree is rate
+/// limited, see `MerkleStateAccount::count_insert`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, BorshSerialize, BorshDeserialize)]
+pub struct InsertCounter {
+    pub payer: Pubkey,
+    pub slot: u64,
+    pub slot_inserts: u32,
+    pub epoch: u64,
+    pub epoch_inserts: u32,
+}
+
+impl InsertCounter {
+    /// Count an insert at `slot` of the counter epoch, `false` if it exceeds
+    /// `max_inserts_per_slot` or `max_inserts_per_epoch`, 0 being unlimited.
+    pub fn count(
+        &mut self,
+        slot: u64,
+        max_inserts_per_slot: u32,
+        max_inserts_per_epoch: u32,
+    ) -> bool {
+        if self.slot != slot {
+            self.slot = slot;
+            self.slot_inserts = 0;
+        }
+
+        if (max_inserts_per_slot != 0 && self.slot_inserts >= max_inserts_per_slot)
+            || (max_inserts_per_epoch != 0 && self.epoch_inserts >= max_inserts_per_epoch)
+        {
+            return false;
+        }
+
+        self.slot_inserts += 1;
+        self.epoch_inserts += 1;
+        true
+    }
+}
+
+/// Expiry of a leaf inserted with `InsertExpiringLeaf`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, BorshSerialize, BorshDeserialize)]
+pub struct LeafExpiry {
+    pub leaf_index: u32,
+    /// Unix timestamp the leaf can be swept at.
+    pub expires_at: i64,
+}
+
+#[derive(Debug, Clone, BorshSerialize, BorshDeserialize)]
 pub struct MerkleStateAccount {
+    /// Always `Self::DISCRIMINATOR`, tells merkle state apart from other accounts.
+    discriminator: [u8; 8],
+    /// Layout version, always `Self::VERSION` for accounts this program reads.
+    version: u8,
     root_hash: [u8; 32],
+    /// Ring buffer of the most recent roots, including the current one.
+    root_history: [[u8; 32]; MerkleStateAccount::ROOT_HISTORY_LEN],
+    /// Position of the current root in `root_history`.
+    root_history_index: u8,
+    authority: Pubkey,
+    /// Authority proposed via `ProposeAuthority`, `Pubkey::default()` if none.
+    pending_authority: Pubkey,
+    paused: bool,
+    /// Reject leaves already present in the tree.
+    reject_duplicates: bool,
+    /// Record slot and inserter of newly inserted leaves in `leaf_metadata`.
+    record_leaf_metadata: bool,
+    event_verbosity: EventVerbosity,
+    /// Number of tree levels below the root cached in `canopy`.
+    canopy_depth: u8,
+    /// Max leaves of a tree preallocated by `InitTree`, 0 if the tree grows freely.
+    leaf_capacity: u32,
+    /// Hash function of the tree nodes, set by `InitTree`.
+    hash_algo: HashAlgo,
+    /// Prefix leaf values and nodes by their kind before hashing, set by `InitTree`.
+    domain_separation: bool,
+    /// Hash nodes in tree order instead of as sorted pairs, set by `InitTree`.
+    ordered: bool,
+    /// Promote nodes without sibling instead of pairing them with themselves, set by
+    /// `InitTree`.
+    left_balanced: bool,
+    /// Only accept inserts paid by one of `inserters`.
+    permissioned: bool,
+    /// Max inserts of a payer per slot, 0 if unlimited.
+    max_inserts_per_slot: u32,
+    /// Max inserts of a payer per epoch, 0 if unlimited.
+    max_inserts_per_epoch: u32,
+    /// Max leaves of the tree whether preallocated or not, 0 if unlimited.
+    max_leaves: u32,
+    /// Reject the zero hash and empty subtree roots as leaves.
+    reject_sentinel_leaves: bool,
+    /// Slots queued config changes are delayed by, 0 if they're applied directly.
+    config_timelock: u64,
+    /// Spare leaves added whenever the account grows, in percent of the leaves count, so
+    /// reallocs are amortized. 0 grows it by exactly the bytes written.
+    growth_percent: u16,
+    /// Pair nodes without sibling with the empty subtree root of their level, set by
+    /// `InitTree`.
+    zero_padded: bool,
+    /// Only append leaves on insert, leaving root, canopy and filled subtrees to
+    /// `commit_root`, so bulk loads hash the tree once.
+    lazy_root: bool,
+    /// Set while leaves appended in lazy root mode aren't reflected in the root.
+    root_dirty: bool,
+    /// Leaves already written to leaf pages while `EnableLeafPages` is converting the tree.
+    paged_leaves: u32,
+    /// Last complete subtree root of each level, valid where `Frontier::nodes` would be, so
+    /// inserts only hash the new leaf path. Unused in frontier mode.
+    filled_subtrees: [[u8; 32]; MerkleStateAccount::MAX_HEIGHT],
+    /// Upper tree nodes, node `i` at depth `j` below the root is stored at `2^j - 2 + i`.
+    canopy: Vec<[u8; 32]>,
+    /// Latest published leaves snapshot, if any.
+    archive: Option<ArchiveRecord>,
+    /// Set once the tree is sealed against further leaf changes.
+    seal: Option<TreeSeal>,
+    /// Set in frontier mode, `leaf_hashes` and `canopy` are empty then.
+    frontier: Option<Frontier>,
+    /// Set with frontier mode when leaves are kept in leaf pages, see `leaf_page`.
+    leaf_pages: bool,
+    /// Pending expiries of not yet swept leaves.
+    expiries: Vec<LeafExpiry>,
+    /// Metadata of leaves inserted while `record_leaf_metadata` was set, by leaf index.
+    leaf_metadata: Vec<LeafMetadata>,
+    /// Payers allowed to insert while `permissioned` is set.
+    inserters: Vec<Pubkey>,
+    /// Insert counts of payers in the current epoch while inserts are rate limited.
+    insert_counters: Vec<InsertCounter>,
     leaf_hashes: Vec<[u8; 32]>,
 }
 
 impl MerkleStateAccount {
     /// Merkle state account length(in bytes).
-    /// 32(root_hash) + 4(vec) + Self::LEAF_LEN * n(total leaf nodes).
-    pub const INIT_LEN: usize = 32 + 4 + Self::LEAF_LEN;
+    /// 8(discriminator) + 1(version) + 32(root_hash) + 32 * Self::ROOT_HISTORY_LEN(root_history) + 1(root_history_index)
+    /// + 32(authority) + 32(pending_authority) + 1(paused) + 1(reject_duplicates) + 1(record_leaf_metadata)
+    /// + 1(event_verbosity) + 1(canopy_depth) + 4(leaf_capacity) + 1(hash_algo) + 1(domain_separation)
+    /// + 1(ordered) + 1(left_balanced) + 1(permissioned) + 4(max_inserts_per_slot) + 4(max_inserts_per_epoch)
+    /// + 4(max_leaves) + 1(reject_sentinel_leaves) + 8(config_timelock) + 2(growth_percent)
+    /// + 1(zero_padded) + 1(lazy_root) + 1(root_dirty) + 4(paged_leaves) + 32 * Self::MAX_HEIGHT(filled_subtrees)
+    /// + 4(vec) + 32 * Self::canopy_len(canopy_depth)
+    /// + 1(archive option) + 1(seal option) + 1(frontier option) + 1(leaf_pages) + 4(vec) + 4(vec) + 4(vec)
+    /// + 4(vec) + 4(vec)
+    /// + Self::LEAF_LEN * n(total leaf nodes).
+    pub const INIT_LEN: usize = 8
+        + 1
+        + 32
+        + 32 * Self::ROOT_HISTORY_LEN
+        + 1
+        + 32
+        + 32
+        + 1
+        + 1
+        + 1
+        + 1
+        + 1
+        + 4
+        + 1
+        + 1
+        + 1
+        + 1
+        + 1
+        + 4
+        + 4
+        + 4
+        + 4
+        + 1
+        + 8
+        + 2
+        + 1
+        + 1
+        + 1
+        + 4
+        + 32 * Self::MAX_HEIGHT
+        + 32 * Self::canopy_len(Self::DEFAULT_CANOPY_DEPTH)
+        + 1
+        + 1
+        + 1
+        + 1
+        + 4
+        + 4
+        + 4
+        + 4
+        + 4
+        + Self::LEAF_LEN;
+
+    /// First 8 bytes of `sha256("account:MerkleStateAccount")`.
+    pub const DISCRIMINATOR: [u8; 8] = [221, 149, 60, 186, 200, 152, 226, 200];
+
+    /// Current account layout version, older accounts are upgraded by `MigrateState`.
+    pub const VERSION: u8 = 3;
 
     /// Leaf node size in bytes.
     pub const LEAF_LEN: usize = 32;
 
-    pub fn new(init_hash: &[u8; 32]) -> Self {
+    /// Number of recent roots proofs can be verified against.
+    pub const ROOT_HISTORY_LEN: usize = 8;
+
+    /// Max tree height, leaves are counted in a u32.
+    pub const MAX_HEIGHT: usize = tree::MAX_HEIGHT;
+
+    /// Canopy depth used for newly created trees.
+    pub const DEFAULT_CANOPY_DEPTH: u8 = 3;
+
+    /// Max canopy depth, deepening the canopy by a level then grows the account by at most
+    /// 8 KiB, within a single realloc.
+    pub const MAX_CANOPY_DEPTH: u8 = 8;
+
+    /// Max archive URI length(in bytes).
+    pub const MAX_ARCHIVE_URI_LEN: usize = 256;
+
+    /// Leaf hash expired leaves are replaced with.
+    pub const TOMBSTONE: [u8; 32] = [0u8; 32];
+
+    /// Lamports paid per swept leaf, from the account balance above its rent-exempt minimum.
+    pub const SWEEP_BOUNTY_LAMPORTS: u64 = 5_000;
+
+    /// Total canopy nodes for `canopy_depth` levels below the root.
+    pub const fn canopy_len(canopy_depth: u8) -> usize {
+        (1 << (canopy_depth as usize + 1)) - 2
+    }
+
+    /// Tree height for `leaves_count` leaves, odd nodes are paired with themselves.
+    pub fn height(leaves_count: usize) -> usize {
+        tree_height(leaves_count)
+    }
+
+    pub fn new(init_hash: &[u8; 32], authority: &Pubkey) -> Self {
+        let mut root_history = [[0u8; 32]; Self::ROOT_HISTORY_LEN];
+        root_history[0] = *init_hash;
+        let mut filled_subtrees = [[0u8; 32]; Self::MAX_HEIGHT];
+        filled_subtrees[0] = *init_hash;
+
         Self {
+            discriminator: Self::DISCRIMINATOR,
+            version: Self::VERSION,
             root_hash: *init_hash,
+            root_history,
+            root_history_index: 0,
+            authority: *authority,
+            pending_authority: Pubkey::default(),
+            paused: false,
+            reject_duplicates: false,
+            record_leaf_metadata: false,
+            event_verbosity: EventVerbosity::default(),
+            canopy_depth: Self::DEFAULT_CANOPY_DEPTH,
+            leaf_capacity: 0,
+            hash_algo: HashAlgo::default(),
+            domain_separation: false,
+            ordered: false,
+            left_balanced: false,
+            permissioned: false,
+            max_inserts_per_slot: 0,
+            max_inserts_per_epoch: 0,
+            max_leaves: 0,
+            reject_sentinel_leaves: false,
+            config_timelock: 0,
+            growth_percent: 0,
+            zero_padded: false,
+            lazy_root: false,
+            root_dirty: false,
+            paged_leaves: 0,
+            filled_subtrees,
+            canopy: vec![[0u8; 32]; Self::canopy_len(Self::DEFAULT_CANOPY_DEPTH)],
+            archive: None,
+            seal: None,
+            frontier: None,
+            leaf_pages: false,
+            expiries: Vec::new(),
+            leaf_metadata: Vec::new(),
+            inserters: Vec::new(),
+            insert_counters: Vec::new(),
             leaf_hashes: vec![*init_hash],
         }
     }
 
+    /// Deserialize merkle state account `data`, rejecting other accounts and layout versions.
+    /// Spare bytes following the state are only allowed in trees that keep them.
+    pub fn unpack(data: &[u8]) -> Result<Self, ProgramError> {
+        Self::check_header(data)?;
+        let mut slice = data;
+        let merkle_state = Self::deserialize(&mut slice)?;
+        if !slice.is_empty() && !merkle_state.keeps_spare() {
+            return Err(ProgramError::InvalidAccountData);
+        }
+        Ok(merkle_state)
+    }
+
+    /// Serialize into merkle state account `data` sized by `account_len`. Fails rather than
+    /// truncating the state or leaving stale bytes after it, which only trees keeping spare
+    /// bytes may have.
+    pub fn pack(&self, data: &mut [u8]) -> ProgramResult {
+        let mut unwritten = data;
+        self.serialize(&mut unwritten)
+            .map_err(|_| MerkleTreeError::StateSizeMismatch)?;
+        if !unwritten.is_empty() && !self.keeps_spare() {
+            return Err(MerkleTreeError::StateSizeMismatch.into());
+        }
+
+        Ok(())
+    }
+
+    /// Account length for the state when currently `data_len` long, the serialized length
+    /// unless spare bytes make it longer. Trees growing geometrically add spare leaves, see
+    /// `growth_len`.
+    pub fn account_len(&self, data_len: usize) -> Result<usize, ProgramError> {
+        let packed_len = borsh::object_length(self)?;
+        Ok(
+            match (self.get_leaf_capacity(), self.get_growth_percent()) {
+                (None, None) => packed_len,
+                (None, Some(growth_percent)) if packed_len > data_len => data_len
+                    .checked_add(Self::growth_len(
+                        packed_len - data_len,
+                        self.get_leaves_count(),
+                        growth_percent,
+                        data_len,
+                    ))
+                    .ok_or(ProgramError::ArithmeticOverflow)?,
+                _ => packed_len.max(data_len),
+            },
+        )
+    }
+
+    /// Bytes to grow an account of `data_len` by when `needed_len` more don't fit its spare
+    /// bytes: `needed_len` plus `growth_percent` of `leaves_count` in spare leaves, as far
+    /// as one realloc and the account size limit allow.
+    pub fn growth_len(
+        needed_len: usize,
+        leaves_count: usize,
+        growth_percent: u16,
+        data_len: usize,
+    ) -> usize {
+        // Spare bytes are capped below anyway, saturate rather than fail on huge trees
+        let spare_len = (leaves_count.saturating_mul(growth_percent as usize) / 100)
+            .saturating_mul(Self::LEAF_LEN);
+        needed_len
+            .saturating_add(spare_len)
+            .min(MAX_PERMITTED_DATA_INCREASE)
+            .min((MAX_PERMITTED_DATA_LENGTH as usize).saturating_sub(data_len))
+            .max(needed_len)
+    }
+
+    /// Whether spare bytes may follow the state, i.e. the tree is preallocated or grows
+    /// geometrically.
+    pub fn keeps_spare(&self) -> bool {
+        self.leaf_capacity != 0 || self.growth_percent != 0
+    }
+
+    /// Account length for `InitTree` to preallocate for `leaf_capacity` leaves.
+    pub fn preallocated_len(leaf_capacity: u32) -> usize {
+        (leaf_capacity as usize)
+            .saturating_sub(1)
+            .saturating_mul(Self::LEAF_LEN)
+            .saturating_add(Self::INIT_LEN)
+    }
+
+    /// Upgrade merkle state account `data` of an older layout version, or headerless state of
+    /// trees created before the header, `None` if it's already current.
+    pub fn migrate(data: &[u8]) -> Result<Option<Self>, ProgramError> {
+        match Self::check_header(data) {
+            Err(err) if err == MerkleTreeError::UnsupportedAccountVersion.into() => {}
+            Err(err) if err == MerkleTreeError::InvalidAccountDiscriminator.into() => {
+                let Some(state) = MerkleStateAccountV0::unpack(data) else {
+                    return Err(err);
+                };
+                return state.try_into().map(Some);
+            }
+            result => return result.map(|_| None),
+        }
+
+        match data[8] {
+            1 => Ok(Some(MerkleStateAccountV1::try_from_slice(data)?.into())),
+            2 => {
+                let mut slice = data;
+                let merkle_state = MerkleStateAccountV2::deserialize(&mut slice)?;
+                if !slice.is_empty() && merkle_state.leaf_capacity == 0 {
+                    return Err(ProgramError::InvalidAccountData);
+                }
+                Ok(Some(merkle_state.into()))
+            }
+            _ => Err(MerkleTreeError::UnsupportedAccountVersion.into()),
+        }
+    }
+
+    /// Verify account `data` starts with the merkle state discriminator and current version.
+    pub fn check_header(data: &[u8]) -> ProgramResult {
+        let Some((discriminator, data)) = data.split_first_chunk::<8>() else {
+            return Err(MerkleTreeError::InvalidAccountDiscriminator.into());
+        };
+        if discriminator != &Self::DISCRIMINATOR {
+            return Err(MerkleTreeError::InvalidAccountDiscriminator.into());
+        }
+        if data.first() != Some(&Self::VERSION) {
+            return Err(MerkleTreeError::UnsupportedAccountVersion.into());
+        }
+
+        Ok(())
+    }
+
     pub fn add_leaf(&mut self, leaf_hash: &[u8; 32]) {
-        self.leaf_hashes.push(*leaf_hash);
+        let tree_hasher = self.get_tree_hasher();
+        match &mut self.frontier {
+            Some(frontier) => {
+                let root_hash = frontier.push(&tree_hasher, leaf_hash);
+                self.set_root_hash(&root_hash);
+            }
+            None if self.lazy_root => {
+                self.leaf_hashes.push(*leaf_hash);
+                self.root_dirty = true;
+            }
+            None => {
+                let root_hash = append_with_canopy(
+                    &tree_hasher,
+                    &mut self.filled_subtrees,
+                    self.leaf_hashes.len(),
+                    leaf_hash,
+                    &mut self.canopy,
+                    self.canopy_depth,
+                );
+                self.leaf_hashes.push(*leaf_hash);
+                self.set_root_hash(&root_hash);
+            }
+        }
+    }
+
+    /// Drop stored leaves and canopy keeping only the tree frontier. Proofs must be built
+    /// off-chain from then on, archives and duplicate checks are unavailable and leaf
+    /// replacement relies on the frontier changelog.
+    pub fn enable_frontier_mode(&mut self) {
+        if self.frontier.is_some() {
+            return;
+        }
+
+        self.frontier = Some(Frontier::from_leaves(
+            &self.get_tree_hasher(),
+            &self.leaf_hashes,
+        ));
+        self.leaf_hashes = Vec::new();
+        self.canopy_depth = 0;
+        self.leaf_capacity = 0;
+        self.growth_percent = 0;
+        self.canopy = Vec::new();
+    }
+
+    pub fn is_frontier_mode(&self) -> bool {
+        self.frontier.is_some()
+    }
+
+    /// Cap the tree at `leaf_capacity` leaves, see `preallocated_len`.
+    pub fn set_leaf_capacity(&mut self, leaf_capacity: u32) {
+        self.leaf_capacity = leaf_capacity;
+    }
+
+    pub fn get_leaf_capacity(&self) -> Option<u32> {
+        (self.leaf_capacity != 0).then_some(self.leaf_capacity)
+    }
+
+    /// Cap the tree at `max_leaves` leaves, 0 lifts the cap. Unlike `leaf_capacity` no rent
+    /// is paid up front.
+    pub fn set_max_leaves(&mut self, max_leaves: u32) {
+        self.max_leaves = max_leaves;
+    }
+
+    pub fn get_max_leaves(&self) -> Option<u32> {
+        (self.max_leaves != 0).then_some(self.max_leaves)
+    }
+
+    /// Delay config changes by `config_timelock` slots, 0 applies them directly.
+    pub fn set_config_timelock(&mut self, config_timelock: u64) {
+        self.config_timelock = config_timelock;
+    }
+
+    /// Slots config changes are delayed by, `None` if they're applied directly.
+    pub fn get_config_timelock(&self) -> Option<u64> {
+        (self.config_timelock != 0).then_some(self.config_timelock)
+    }
+
+    /// Add `growth_percent` of the leaves count in spare leaves whenever the account grows,
+    /// 0 grows it by exactly the bytes written.
+    pub fn set_growth_percent(&mut self, growth_percent: u16) {
+        self.growth_percent = growth_percent;
+    }
+
+    pub fn get_growth_percent(&self) -> Option<u16> {
+        (self.growth_percent != 0).then_some(self.growth_percent)
+    }
+
+    /// Leave the root to `commit_root` on insert, only meant to be disabled once it's
+    /// committed.
+    pub fn set_lazy_root(&mut self, lazy_root: bool) {
+        self.lazy_root = lazy_root;
+    }
+
+    pub fn is_lazy_root(&self) -> bool {
+        self.lazy_root
+    }
+
+    /// Whether leaves appended in lazy root mode are still missing from the root, which
+    /// proofs and snapshots then can't be checked against.
+    pub fn is_root_dirty(&self) -> bool {
+        self.root_dirty
+    }
+
+    /// Recompute and publish the root of leaves appended in lazy root mode, `false` if it's
+    /// already up to date.
+    pub fn commit_root(&mut self) -> bool {
+        if !self.root_dirty {
+            return false;
+        }
+
         self.update_root_hash();
+        true
+    }
+
+    /// Whether the tree holds as many leaves as it was preallocated for or is capped at.
+    pub fn is_full(&self) -> bool {
+        self.get_leaf_capacity()
+            .into_iter()
+            .chain(self.get_max_leaves())
+            .any(|max_leaves| self.get_leaves_count() >= max_leaves as usize)
+    }
+
+    /// Hash tree nodes with `tree_hasher`, only meant for a tree holding just its init leaf.
+    pub fn set_tree_hasher(&mut self, tree_hasher: TreeHasher) {
+        self.hash_algo = tree_hasher.hash_algo;
+        self.domain_separation = tree_hasher.domain_separation;
+        self.ordered = tree_hasher.ordered;
+        self.left_balanced = tree_hasher.left_balanced;
+        self.zero_padded = tree_hasher.zero_padded;
+    }
+
+    pub fn get_tree_hasher(&self) -> TreeHasher {
+        TreeHasher {
+            hash_algo: self.hash_algo,
+            domain_separation: self.domain_separation,
+            ordered: self.ordered,
+            left_balanced: self.left_balanced,
+            zero_padded: self.zero_padded,
+        }
+    }
+
+    pub fn get_hash_algo(&self) -> HashAlgo {
+        self.hash_algo
+    }
+
+    /// Enable frontier mode keeping leaves in leaf pages, which the caller writes.
+    pub fn enable_leaf_pages(&mut self) {
+        self.enable_frontier_mode();
+        self.leaf_pages = true;
+        self.paged_leaves = 0;
+    }
+
+    /// Record leaves of pages before `end_page` as written by the caller, enabling leaf
+    /// pages once every leaf is. Returns whether the conversion finished.
+    pub fn page_leaves(&mut self, end_page: u32) -> bool {
+        let leaves_count = self.leaf_hashes.len();
+        let paged_leaves = (end_page as usize).saturating_mul(leaf_page::LEAVES_PER_PAGE);
+        if paged_leaves < leaves_count {
+            self.paged_leaves = paged_leaves as u32;
+            return false;
+        }
+
+        self.enable_leaf_pages();
+        true
+    }
+
+    /// Leaves written to leaf pages so far by an unfinished `EnableLeafPages` conversion.
+    pub fn get_paged_leaves(&self) -> u32 {
+        self.paged_leaves
     }
 
+    /// Rewind an unfinished leaf pages conversion to the page of `leaf_index` after the leaf
+    /// changed in place, so its page is written again.
+    fn unpage_leaf(&mut self, leaf_index: u32) {
+        let page_start = leaf_page::page_index(leaf_index) * leaf_page::LEAVES_PER_PAGE as u32;
+        self.paged_leaves = self.paged_leaves.min(page_start);
+    }
+
+    pub fn has_leaf_pages(&self) -> bool {
+        self.leaf_pages
+    }
+
+    /// Let `SweepExpired` tombstone leaf `leaf_index` once `expires_at` passed.
+    pub fn set_leaf_expiry(&mut self, leaf_index: u32, expires_at: i64) {
+        self.expiries
+            .retain(|expiry| expiry.leaf_index != leaf_index);
+        self.expiries.push(LeafExpiry {
+            leaf_index,
+            expires_at,
+        });
+    }
+
+    pub fn get_leaf_expiries(&self) -> &[LeafExpiry] {
+        &self.expiries
+    }
+
+    /// Replace leaves expired at `now` with `Self::TOMBSTONE` and recalc the root hash,
+    /// returning the number of swept leaves.
+    pub fn sweep_expired(&mut self, now: i64) -> usize {
+        let leaf_hashes = &mut self.leaf_hashes;
+        let expiries_len = self.expiries.len();
+        let mut first_swept = u32::MAX;
+        self.expiries.retain(|expiry| {
+            if expiry.expires_at > now {
+                return true;
+            }
+            leaf_hashes[expiry.leaf_index as usize] = Self::TOMBSTONE;
+            first_swept = first_swept.min(expiry.leaf_index);
+            false
+        });
+        self.unpage_leaf(first_swept);
+
+        let swept = expiries_len - self.expiries.len();
+        if swept > 0 {
+            self.update_root_hash();
+        }
+        swept
+    }
+
+    /// Recompute root, canopy and filled subtrees from all leaves in a single pass, after a
+    /// leaf changed in place or leaves were appended in lazy root mode.
     fn update_root_hash(&mut self) {
-        let mut current_layer = self.leaf_hashes.to_vec();
+        let root_hash = fold_leaves(
+            &self.get_tree_hasher(),
+            &self.leaf_hashes,
+            &mut self.filled_subtrees,
+            &mut self.canopy,
+            self.canopy_depth,
+        );
+        self.root_dirty = false;
+        self.set_root_hash(&root_hash);
+    }
 
-        while current_layer.len() > 1 {
-            let mut next_layer = Vec::new();
+    /// Rehash the path of leaf at `leaf_index` after it changed in place: the subtree below
+    /// the canopy holding it from its leaves, then the cached canopy nodes up to the root.
+    /// Deeper canopies leave fewer leaves to rehash, see `set_canopy_depth`.
+    fn update_leaf_path(&mut self, leaf_index: usize) {
+        let tree_hasher = self.get_tree_hasher();
+        let leaves_count = self.leaf_hashes.len();
+        let height = Self::height(leaves_count);
+        let subtree_range = Self::canopy_subtree_range(leaves_count, self.canopy_depth, leaf_index);
+        let subtree_level = height - height.min(self.canopy_depth as usize);
 
-            for pair in current_layer.chunks(2) {
-                let combined = match pair {
-                    [a, b] => hash_sorted_pair(a, b),
-                    [a] => hash_sorted_pair(a, a),
-                    _ => unreachable!(),
-                };
-                next_layer.push(combined);
+        // Rehash the subtree up to its root, nodes of a partial subtree without sibling are
+        // unpaired up to the canopy
+        let subtree_leaves = &self.leaf_hashes[subtree_range.clone()];
+        let mut subtree_filled = [[0u8; 32]; Self::MAX_HEIGHT];
+        let mut node = fold_leaves(
+            &tree_hasher,
+            subtree_leaves,
+            &mut subtree_filled,
+            &mut [],
+            0,
+        );
+        for level in tree_height(subtree_leaves.len())..subtree_level {
+            node = tree_hasher.hash_unpaired(level, &node);
+        }
+        if subtree_range.end == leaves_count {
+            for (level, node) in subtree_filled[..subtree_level].iter().enumerate() {
+                if (leaves_count >> level) & 1 == 1 {
+                    self.filled_subtrees[level] = *node;
+                }
             }
+        }
 
-            current_layer = next_layer;
+        // Walk the canopy up to the root, caching the new path
+        let mut index = leaf_index >> subtree_level;
+        for level in subtree_level..height {
+            let offset = (1 << (height - level)) - 2;
+            self.canopy[offset + index] = node;
+            if (leaves_count >> level) & 1 == 1 && index == (leaves_count >> level) - 1 {
+                self.filled_subtrees[level] = node;
+            }
+            node = match (index ^ 1) < leaves_count.div_ceil(1 << level) {
+                true => hash_at(
+                    &tree_hasher,
+                    index,
+                    &node,
+                    &self.canopy[offset + (index ^ 1)],
+                ),
+                false => tree_hasher.hash_unpaired(level, &node),
+            };
+            index >>= 1;
+        }
+        if (leaves_count >> height) & 1 == 1 {
+            self.filled_subtrees[height] = node;
         }
 
-        self.root_hash = current_layer[0];
+        self.set_root_hash(&node);
+    }
+
+    fn set_root_hash(&mut self, root_hash: &[u8; 32]) {
+        self.root_hash = *root_hash;
+
+        // Remember new root
+        self.root_history_index =
+            ((self.root_history_index as usize + 1) % Self::ROOT_HISTORY_LEN) as u8;
+        self.root_history[self.root_history_index as usize] = self.root_hash;
+    }
+
+    /// Verify `leaf` at `leaf_index` with a proof truncated at the canopy, or a full proof
+    /// up to the current root or any root in the history. Left-balanced trees take an audit
+    /// path to the current root.
+    pub fn verify_leaf(&self, leaf_index: u32, leaf: &[u8; 32], proof: &[[u8; 32]]) -> bool {
+        let leaves_count = self.get_leaves_count();
+        if leaf_index as usize >= leaves_count {
+            return false;
+        }
+        if self.left_balanced {
+            return compute_audit_root(
+                &self.get_tree_hasher(),
+                leaves_count,
+                leaf_index as usize,
+                leaf,
+                proof,
+            ) == Some(self.root_hash);
+        }
+
+        verify_proof_against_roots(
+            &self.get_tree_hasher(),
+            &self.root_history,
+            leaf_index as usize,
+            leaf,
+            proof,
+        ) || verify_proof_with_canopy(
+            &self.get_tree_hasher(),
+            &self.root_hash,
+            Canopy {
+                nodes: &self.canopy,
+                depth: self.canopy_depth as usize,
+            },
+            Self::height(leaves_count),
+            leaf_index as usize,
+            leaf,
+            proof,
+        )
+    }
+
+    /// Verify deduplicated multiproof for `leaves` against the current root.
+    pub fn verify_leaves(&self, leaves: &[(u32, [u8; 32])], proof: &[[u8; 32]]) -> bool {
+        verify_multiproof(
+            &self.get_tree_hasher(),
+            &self.root_hash,
+            self.get_leaves_count(),
+            leaves,
+            proof,
+        )
+    }
+
+    /// Build deduplicated multiproof for `leaf_indices`(sorted, strictly increasing),
+    /// see `verify::compute_multiproof_root` for the node order.
+    pub fn get_multiproof(&self, leaf_indices: &[u32]) -> Vec<[u8; 32]> {
+        tree::get_multiproof(&self.get_tree_hasher(), &self.leaf_hashes, leaf_indices)
+    }
+
+    /// Full proof for leaf at `leaf_index`, a node without sibling is paired with itself or
+    /// the empty subtree of its level, see `TreeHasher::unpaired_sibling`. Left-balanced trees
+    /// return the audit path, skipping such nodes.
+    pub fn get_proof(&self, leaf_index: u32) -> Vec<[u8; 32]> {
+        let tree_hasher = self.get_tree_hasher();
+        match self.left_balanced {
+            true => tree::get_audit_proof(&tree_hasher, &self.leaf_hashes, leaf_index as usize),
+            false => tree::get_proof(&tree_hasher, &self.leaf_hashes, leaf_index as usize),
+        }
+    }
+
+    /// Shortest proof for leaf at `leaf_index` accepted by `verify_leaf`, cut at the canopy.
+    /// Audit paths of left-balanced trees are never cut.
+    pub fn get_truncated_proof(&self, leaf_index: u32) -> Vec<[u8; 32]> {
+        let mut proof = self.get_proof(leaf_index);
+        if self.left_balanced {
+            return proof;
+        }
+        let height = Self::height(self.leaf_hashes.len());
+        proof.truncate(height.saturating_sub(self.canopy_depth as usize));
+
+        proof
+    }
+
+    /// Consistency proof that the tree with the first `old_leaves_count` leaves is a prefix of
+    /// the current one: the last old leaf and its full proof, see `verify_consistency`.
+    pub fn get_consistency_proof(&self, old_leaves_count: u32) -> ([u8; 32], Vec<[u8; 32]>) {
+        tree::get_consistency_proof(
+            &self.get_tree_hasher(),
+            &self.leaf_hashes,
+            old_leaves_count as usize,
+        )
+    }
+
+    /// Verify the tree at `old_root` with `old_leaves_count` leaves is a prefix of the current
+    /// one, i.e. leaves were only appended since.
+    pub fn verify_consistency(
+        &self,
+        old_root: &[u8; 32],
+        old_leaves_count: u32,
+        leaf: &[u8; 32],
+        proof: &[[u8; 32]],
+    ) -> bool {
+        verify_consistency(
+            &self.get_tree_hasher(),
+            old_root,
+            old_leaves_count as usize,
+            &self.root_hash,
+            self.get_leaves_count(),
+            leaf,
+            proof,
+        )
+    }
+
+    /// Replace `old_leaf` at `leaf_index` with `new_leaf`, the proof for `old_leaf` may target
+    /// any recent root, in frontier mode any root in the frontier changelog. Clears the leaf
+    /// expiry. Returns `false` if the leaf or the proof doesn't match.
+    pub fn replace_leaf(
+        &mut self,
+        leaf_index: u32,
+        old_leaf: &[u8; 32],
+        new_leaf: &[u8; 32],
+        proof: &[[u8; 32]],
+    ) -> bool {
+        let tree_hasher = self.get_tree_hasher();
+        if let Some(frontier) = &mut self.frontier {
+            let Some(root_hash) = frontier.replace_leaf(
+                &tree_hasher,
+                &self.root_hash,
+                leaf_index,
+                old_leaf,
+                new_leaf,
+                proof,
+            ) else {
+                return false;
+            };
+            self.set_root_hash(&root_hash);
+            return true;
+        }
+
+        if self.leaf_hashes.get(leaf_index as usize) != Some(old_leaf)
+            || !self.verify_leaf(leaf_index, old_leaf, proof)
+        {
+            return false;
+        }
+
+        self.leaf_hashes[leaf_index as usize] = *new_leaf;
+        self.expiries
+            .retain(|expiry| expiry.leaf_index != leaf_index);
+        self.unpage_leaf(leaf_index);
+        self.update_leaf_path(leaf_index as usize);
+        true
+    }
+
+    pub fn propose_authority(&mut self, new_authority: &Pubkey) {
+        self.pending_authority = *new_authority;
+    }
+
+    /// Promote pending authority to current one.
+    pub fn accept_authority(&mut self) {
+        self.authority = self.pending_authority;
+        self.pending_authority = Pubkey::default();
+    }
+
+    pub fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+    }
+
+    pub fn set_reject_duplicates(&mut self, reject_duplicates: bool) {
+        self.reject_duplicates = reject_duplicates;
+    }
+
+    pub fn set_reject_sentinel_leaves(&mut self, reject_sentinel_leaves: bool) {
+        self.reject_sentinel_leaves = reject_sentinel_leaves;
+    }
+
+    pub fn set_record_leaf_metadata(&mut self, record_leaf_metadata: bool) {
+        self.record_leaf_metadata = record_leaf_metadata;
+    }
+
+    pub fn set_permissioned(&mut self, permissioned: bool) {
+        self.permissioned = permissioned;
+    }
+
+    /// Allow `inserter` to insert into a permissioned tree, `false` if it already is.
+    pub fn add_inserter(&mut self, inserter: &Pubkey) -> bool {
+        if self.inserters.contains(inserter) {
+            return false;
+        }
+
+        self.inserters.push(*inserter);
+        true
+    }
+
+    /// Disallow `inserter`, `false` if it wasn't allowed.
+    pub fn remove_inserter(&mut self, inserter: &Pubkey) -> bool {
+        let len = self.inserters.len();
+        self.inserters.retain(|allowed| allowed != inserter);
+        self.inserters.len() != len
+    }
+
+    /// Whether `payer` may insert, i.e. the tree isn't permissioned or allows it.
+    pub fn can_insert(&self, payer: &Pubkey) -> bool {
+        !self.permissioned || self.inserters.contains(payer)
+    }
+
+    /// Limit inserts of each payer to `max_inserts_per_slot` per slot and
+    /// `max_inserts_per_epoch` per epoch, 0 lifts a limit. Counters are dropped once both are.
+    pub fn set_rate_limit(&mut self, max_inserts_per_slot: u32, max_inserts_per_epoch: u32) {
+        self.max_inserts_per_slot = max_inserts_per_slot;
+        self.max_inserts_per_epoch = max_inserts_per_epoch;
+        if !self.is_rate_limited() {
+            self.insert_counters.clear();
+        }
+    }
+
+    /// Count an insert of `payer` at `slot` in `epoch`, `false` without counting it if it
+    /// exceeds the rate limit. Counters of past epochs are dropped.
+    pub fn count_insert(&mut self, payer: &Pubkey, slot: u64, epoch: u64) -> bool {
+        if !self.is_rate_limited() {
+            return true;
+        }
+
+        self.insert_counters
+            .retain(|counter| counter.epoch == epoch);
+        let counter = match self
+            .insert_counters
+            .iter_mut()
+            .find(|counter| &counter.payer == payer)
+        {
+            Some(counter) => counter,
+            None => {
+                self.insert_counters.push(InsertCounter {
+                    payer: *payer,
+                    slot,
+                    slot_inserts: 0,
+                    epoch,
+                    epoch_inserts: 0,
+                });
+                self.insert_counters.last_mut().expect("Counter is pushed")
+            }
+        };
+        counter.count(slot, self.max_inserts_per_slot, self.max_inserts_per_epoch)
+    }
+
+    /// Record `slot` and `inserter` of the last inserted leaf, if the tree records leaf
+    /// metadata.
+    pub fn record_leaf_metadata(&mut self, slot: u64, inserter: &Pubkey) {
+        if !self.record_leaf_metadata {
+            return;
+        }
+
+        self.leaf_metadata.push(LeafMetadata {
+            leaf_index: self.get_leaves_count() as u32 - 1,
+            slot,
+            inserter: *inserter,
+        });
+    }
+
+    pub fn set_event_verbosity(&mut self, event_verbosity: EventVerbosity) {
+        self.event_verbosity = event_verbosity;
+    }
+
+    /// Whether `leaf` can't be added, i.e. duplicates are rejected and it's already present.
+    pub fn is_duplicate(&self, leaf: &[u8; 32]) -> bool {
+        self.reject_duplicates && self.leaf_hashes.contains(leaf)
+    }
+
+    /// Whether `leaf` is rejected as a sentinel of the tree once it holds one more leaf,
+    /// see `TreeHasher::is_sentinel_leaf`.
+    pub fn is_sentinel_leaf(&self, leaf: &[u8; 32]) -> bool {
+        self.reject_sentinel_leaves
+            && self
+                .get_tree_hasher()
+                .is_sentinel_leaf(leaf, Self::height(self.get_leaves_count() + 1))
+    }
+
+    /// Record `archive` if it was taken at the current root and its content hash matches
+    /// the current leaves. Returns `false` otherwise.
+    pub fn set_archive(&mut self, archive: ArchiveRecord) -> bool {
+        if archive.root != self.root_hash || archive.content_hash != hash_leaves(&self.leaf_hashes)
+        {
+            return false;
+        }
+
+        self.archive = Some(archive);
+        true
     }
 
     pub fn get_root_hash(&self) -> [u8; 32] {
         self.root_hash
     }
 
+    pub fn root(&self) -> &[u8; 32] {
+        &self.root_hash
+    }
+
+    /// Stored leaves, empty in frontier mode. Clones them, see `leaf_hashes`.
     pub fn get_leaf_hashes(&self) -> Vec<[u8; 32]> {
         self.leaf_hashes.clone()
     }
+
+    /// Stored leaves, empty in frontier mode.
+    pub fn leaf_hashes(&self) -> &[[u8; 32]] {
+        &self.leaf_hashes
+    }
+
+    pub fn get_leaves_count(&self) -> usize {
+        match &self.frontier {
+            Some(frontier) => frontier.leaves_count as usize,
+            None => self.leaf_hashes.len(),
+        }
+    }
+
+    pub fn get_frontier(&self) -> Option<&Frontier> {
+        self.frontier.as_ref()
+    }
+
+    pub fn get_authority(&self) -> Pubkey {
+        self.authority
+    }
+
+    pub fn get_pending_authority(&self) -> Option<Pubkey> {
+        (self.pending_authority != Pubkey::default()).then_some(self.pending_authority)
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn rejects_duplicates(&self) -> bool {
+        self.reject_duplicates
+    }
+
+    pub fn rejects_sentinel_leaves(&self) -> bool {
+        self.reject_sentinel_leaves
+    }
+
+    pub fn records_leaf_metadata(&self) -> bool {
+        self.record_leaf_metadata
+    }
+
+    pub fn is_permissioned(&self) -> bool {
+        self.permissioned
+    }
+
+    pub fn get_inserters(&self) -> &[Pubkey] {
+        &self.inserters
+    }
+
+    pub fn is_rate_limited(&self) -> bool {
+        self.max_inserts_per_slot != 0 || self.max_inserts_per_epoch != 0
+    }
+
+    /// Max inserts of a payer per slot and per epoch, 0 if unlimited.
+    pub fn get_rate_limit(&self) -> (u32, u32) {
+        (self.max_inserts_per_slot, self.max_inserts_per_epoch)
+    }
+
+    pub fn get_insert_counters(&self) -> &[InsertCounter] {
+        &self.insert_counters
+    }
+
+    /// Metadata of leaf `leaf_index`, `None` if it was inserted without recording it.
+    pub fn get_leaf_metadata(&self, leaf_index: u32) -> Option<&LeafMetadata> {
+        self.leaf_metadata
+            .binary_search_by_key(&leaf_index, |metadata| metadata.leaf_index)
+            .ok()
+            .map(|position| &self.leaf_metadata[position])
+    }
+
+    /// Recorded leaf metadata, by leaf index.
+    pub fn get_leaves_metadata(&self) -> &[LeafMetadata] {
+        &self.leaf_metadata
+    }
+
+    pub fn get_event_verbosity(&self) -> EventVerbosity {
+        self.event_verbosity
+    }
+
+    pub fn get_canopy_depth(&self) -> u8 {
+        self.canopy_depth
+    }
+
+    /// Cache `canopy_depth` levels below the root, at most `Self::MAX_CANOPY_DEPTH`. Resizing
+    /// the account is up to the caller, see `account_len`.
+    pub fn set_canopy_depth(&mut self, canopy_depth: u8) {
+        self.canopy_depth = canopy_depth;
+        self.canopy = vec![[0u8; 32]; Self::canopy_len(canopy_depth)];
+        compute_root_with_canopy(
+            &self.get_tree_hasher(),
+            &self.leaf_hashes,
+            &mut self.canopy,
+            canopy_depth,
+        );
+    }
+
+    /// Leaf indices of the subtree right below a `canopy_depth` canopy that holds leaf at
+    /// `leaf_index` in a tree of `leaves_count` leaves. Its leaves and the canopy are all
+    /// a proof needs, so clients can fetch just this slice of the account, see
+    /// `truncated_proof_from_subtree`.
+    pub fn canopy_subtree_range(
+        leaves_count: usize,
+        canopy_depth: u8,
+        leaf_index: usize,
+    ) -> Range<usize> {
+        tree::canopy_subtree_range(leaves_count, canopy_depth, leaf_index)
+    }
+
+    /// Proof for leaf at `leaf_index` truncated at a `canopy_depth` canopy, as
+    /// `get_truncated_proof` returns it, built from only the leaves of its
+    /// `canopy_subtree_range`. `None` for left-balanced trees, which take full audit paths.
+    pub fn truncated_proof_from_subtree(
+        tree_hasher: &TreeHasher,
+        leaves_count: usize,
+        canopy_depth: u8,
+        leaf_index: usize,
+        subtree_leaves: &[[u8; 32]],
+    ) -> Option<Vec<[u8; 32]>> {
+        if tree_hasher.left_balanced {
+            return None;
+        }
+
+        tree::truncated_proof_from_subtree(
+            tree_hasher,
+            leaves_count,
+            canopy_depth,
+            leaf_index,
+            subtree_leaves,
+        )
+    }
+
+    pub fn get_canopy(&self) -> Vec<[u8; 32]> {
+        self.canopy.clone()
+    }
+
+    pub fn canopy(&self) -> &[[u8; 32]] {
+        &self.canopy
+    }
+
+    /// Root and canopy recomputed from stored leaves, or from the frontier in frontier mode,
+    /// to audit the stored ones.
+    pub fn recompute_root(&self) -> ([u8; 32], Vec<[u8; 32]>) {
+        match &self.frontier {
+            Some(frontier) => (frontier.root(&self.get_tree_hasher()), Vec::new()),
+            None => {
+                let mut canopy = vec![[0u8; 32]; Self::canopy_len(self.canopy_depth)];
+                let root_hash = compute_root_with_canopy(
+                    &self.get_tree_hasher(),
+                    &self.leaf_hashes,
+                    &mut canopy,
+                    self.canopy_depth,
+                );
+                (root_hash, canopy)
+            }
+        }
+    }
+
+    /// Seal the tree at its current root and leaves count.
+    pub fn seal(&mut self, slot: u64) {
+        self.seal = Some(TreeSeal {
+            root: self.root_hash,
+            leaves_count: self.get_leaves_count() as u32,
+            slot,
+        });
+    }
+
+    pub fn is_sealed(&self) -> bool {
+        self.seal.is_some()
+    }
+
+    pub fn get_seal(&self) -> Option<&TreeSeal> {
+        self.seal.as_ref()
+    }
+
+    pub fn get_archive(&self) -> Option<&ArchiveRecord> {
+        self.archive.as_ref()
+    }
+
+    /// Recent roots, oldest first.
+    pub fn get_root_history(&self) -> Vec<[u8; 32]> {
+        self.root_history().copied().collect()
+    }
+
+    /// Recent roots, oldest first, ending with the current one.
+    pub fn root_history(&self) -> impl Iterator<Item = &[u8; 32]> {
+        let start = self.root_history_index as usize + 1;
+        self.root_history[start..]
+            .iter()
+            .chain(&self.root_history[..start])
+            .filter(|root| **root != [0u8; 32])
+    }
+}
+
+/// Headerless merkle state of trees created before the account header, upgraded in place by
+/// `MigrateState`.
+#[derive(Debug, Clone, PartialEq, Eq, BorshSerialize, BorshDeserialize)]
+pub struct MerkleStateAccountV0 {
+    pub root_hash: [u8; 32],
+    pub leaf_hashes: Vec<[u8; 32]>,
+}
+
+impl MerkleStateAccountV0 {
+    /// Deserialize `data` only if it's exactly a root followed by its leaves, `32 + 4 + 32n`
+    /// bytes for `n` leaves.
+    pub fn unpack(data: &[u8]) -> Option<Self> {
+        let leaves_count = u32::from_le_bytes(*data.get(32..36)?.first_chunk::<4>()?) as usize;
+        let packed_len = leaves_count
+            .checked_mul(MerkleStateAccount::LEAF_LEN)?
+            .checked_add(36)?;
+        if leaves_count == 0 || data.len() != packed_len {
+            return None;
+        }
+        Self::try_from_slice(data).ok()
+    }
+}
+
+impl TryFrom<MerkleStateAccountV0> for MerkleStateAccount {
+    type Error = ProgramError;
+
+    /// Rebuild header, root history, filled subtrees and canopy from the leaves, which must
+    /// hash to the stored root. These trees had no authority and get none, so migrating
+    /// doesn't hand the tree to whoever pays for it.
+    fn try_from(state: MerkleStateAccountV0) -> Result<Self, Self::Error> {
+        let mut merkle_state = Self::new(&state.root_hash, &Pubkey::default());
+        merkle_state.leaf_hashes = state.leaf_hashes;
+        let root_hash = fold_leaves(
+            &merkle_state.get_tree_hasher(),
+            &merkle_state.leaf_hashes,
+            &mut merkle_state.filled_subtrees,
+            &mut merkle_state.canopy,
+            merkle_state.canopy_depth,
+        );
+        if root_hash != state.root_hash {
+            return Err(ProgramError::InvalidAccountData);
+        }
+
+        Ok(merkle_state)
+    }
+}
+
+/// Frontier of `MerkleStateAccountV1`, before the changelog was kept.
+#[derive(Debug, Clone, PartialEq, Eq, BorshSerialize, BorshDeserialize)]
+pub struct FrontierV1 {
+    pub leaves_count: u32,
+    pub nodes: Vec<[u8; 32]>,
+}
+
+/// Merkle state account layout version 1, upgraded in place by `MigrateState`.
+#[derive(Debug, Clone, PartialEq, Eq, BorshSerialize, BorshDeserialize)]
+pub struct MerkleStateAccountV1 {
+    pub discriminator: [u8; 8],
+    pub version: u8,
+    pub root_hash: [u8; 32],
+    pub root_history: [[u8; 32]; MerkleStateAccount::ROOT_HISTORY_LEN],
+    pub root_history_index: u8,
+    pub authority: Pubkey,
+    pub pending_authority: Pubkey,
+    pub paused: bool,
+    pub reject_duplicates: bool,
+    pub event_verbosity: EventVerbosity,
+    pub canopy_depth: u8,
+    pub canopy: Vec<[u8; 32]>,
+    pub archive: Option<ArchiveRecord>,
+    pub seal: Option<TreeSeal>,
+    pub frontier: Option<FrontierV1>,
+    pub leaf_hashes: Vec<[u8; 32]>,
+}
+
+impl From<MerkleStateAccountV1> for MerkleStateAccount {
+    /// Keep leaves, roots and settings, fields added since start empty or disabled.
+    fn from(state: MerkleStateAccountV1) -> Self {
+        Self {
+            discriminator: state.discriminator,
+            version: Self::VERSION,
+            root_hash: state.root_hash,
+            root_history: state.root_history,
+            root_history_index: state.root_history_index,
+            authority: state.authority,
+            pending_authority: state.pending_authority,
+            paused: state.paused,
+            reject_duplicates: state.reject_duplicates,
+            record_leaf_metadata: false,
+            event_verbosity: state.event_verbosity,
+            canopy_depth: state.canopy_depth,
+            leaf_capacity: 0,
+            hash_algo: HashAlgo::default(),
+            domain_separation: false,
+            ordered: false,
+            left_balanced: false,
+            permissioned: false,
+            max_inserts_per_slot: 0,
+            max_inserts_per_epoch: 0,
+            max_leaves: 0,
+            reject_sentinel_leaves: false,
+            config_timelock: 0,
+            growth_percent: 0,
+            zero_padded: false,
+            lazy_root: false,
+            root_dirty: false,
+            paged_leaves: 0,
+            filled_subtrees: compute_filled_subtrees(&TreeHasher::default(), &state.leaf_hashes),
+            canopy: state.canopy,
+            archive: state.archive,
+            seal: state.seal,
+            frontier: state.frontier.map(|frontier| Frontier {
+                leaves_count: frontier.leaves_count,
+                nodes: frontier.nodes,
+                changelog: Vec::new(),
+            }),
+            leaf_pages: false,
+            expiries: Vec::new(),
+            leaf_metadata: Vec::new(),
+            inserters: Vec::new(),
+            insert_counters: Vec::new(),
+            leaf_hashes: state.leaf_hashes,
+        }
+    }
+}
+
+/// Merkle state account layout version 2 as released, before hashing, insert limits and
+/// growth settings were added, upgraded in place by `MigrateState`. State written by
+/// development builds in between doesn't match this layout and fails to decode rather than
+/// being misread.
+#[derive(Debug, Clone, PartialEq, Eq, BorshSerialize, BorshDeserialize)]
+pub struct MerkleStateAccountV2 {
+    pub discriminator: [u8; 8],
+    pub version: u8,
+    pub root_hash: [u8; 32],
+    pub root_history: [[u8; 32]; MerkleStateAccount::ROOT_HISTORY_LEN],
+    pub root_history_index: u8,
+    pub authority: Pubkey,
+    pub pending_authority: Pubkey,
+    pub paused: bool,
+    pub reject_duplicates: bool,
+    pub record_leaf_metadata: bool,
+    pub event_verbosity: EventVerbosity,
+    pub canopy_depth: u8,
+    pub leaf_capacity: u32,
+    pub canopy: Vec<[u8; 32]>,
+    pub archive: Option<ArchiveRecord>,
+    pub seal: Option<TreeSeal>,
+    pub frontier: Option<Frontier>,
+    pub leaf_pages: bool,
+    pub expiries: Vec<LeafExpiry>,
+    pub leaf_metadata: Vec<LeafMetadata>,
+    pub leaf_hashes: Vec<[u8; 32]>,
+}
+
+impl From<MerkleStateAccountV2> for MerkleStateAccount {
+    /// Keep leaves, roots and settings, fields added since version 2 empty or disabled.
+    fn from(state: MerkleStateAccountV2) -> Self {
+        Self {
+            discriminator: state.discriminator,
+            version: Self::VERSION,
+            root_hash: state.root_hash,
+            root_history: state.root_history,
+            root_history_index: state.root_history_index,
+            authority: state.authority,
+            pending_authority: state.pending_authority,
+            paused: state.paused,
+            reject_duplicates: state.reject_duplicates,
+            record_leaf_metadata: state.record_leaf_metadata,
+            event_verbosity: state.event_verbosity,
+            canopy_depth: state.canopy_depth,
+            leaf_capacity: state.leaf_capacity,
+            hash_algo: HashAlgo::default(),
+            domain_separation: false,
+            ordered: false,
+            left_balanced: false,
+            permissioned: false,
+            max_inserts_per_slot: 0,
+            max_inserts_per_epoch: 0,
+            max_leaves: 0,
+            reject_sentinel_leaves: false,
+            config_timelock: 0,
+            growth_percent: 0,
+            zero_padded: false,
+            lazy_root: false,
+            root_dirty: false,
+            paged_leaves: 0,
+            filled_subtrees: compute_filled_subtrees(&TreeHasher::default(), &state.leaf_hashes),
+            canopy: state.canopy,
+            archive: state.archive,
+            seal: state.seal,
+            frontier: state.frontier,
+            leaf_pages: state.leaf_pages,
+            expiries: state.expiries,
+            leaf_metadata: state.leaf_metadata,
+            inserters: Vec::new(),
+            insert_counters: Vec::new(),
+            leaf_hashes: state.leaf_hashes,
+        }
+    }
 }
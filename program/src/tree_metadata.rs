@@ -0,0 +1,55 @@
+//! Optional descriptive metadata of a tree, kept in a PDA seeded by the merkle state key,
+//! see `pda::find_tree_metadata_pda`, and set by the tree authority via `SetMetadata`.
+
+use crate::error::MerkleTreeError;
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_program::program_error::ProgramError;
+
+#[derive(Debug, Clone, PartialEq, Eq, BorshSerialize, BorshDeserialize)]
+pub struct TreeMetadataAccount {
+    /// Always `Self::DISCRIMINATOR`.
+    discriminator: [u8; 8],
+    /// Short human-readable tree name.
+    pub name: String,
+    pub description: String,
+    /// External resource of the tree, e.g. location of off-chain leaf data.
+    pub uri: String,
+}
+
+impl TreeMetadataAccount {
+    /// First 8 bytes of `sha256("account:TreeMetadataAccount")`.
+    pub const DISCRIMINATOR: [u8; 8] = [92, 204, 179, 81, 170, 157, 201, 52];
+
+    /// Max name length(in bytes).
+    pub const MAX_NAME_LEN: usize = 32;
+
+    /// Max description length(in bytes).
+    pub const MAX_DESCRIPTION_LEN: usize = 256;
+
+    /// Max URI length(in bytes).
+    pub const MAX_URI_LEN: usize = 256;
+
+    pub fn new(name: String, description: String, uri: String) -> Result<Self, ProgramError> {
+        if name.len() > Self::MAX_NAME_LEN
+            || description.len() > Self::MAX_DESCRIPTION_LEN
+            || uri.len() > Self::MAX_URI_LEN
+        {
+            return Err(MerkleTreeError::MetadataTooLong.into());
+        }
+
+        Ok(Self {
+            discriminator: Self::DISCRIMINATOR,
+            name,
+            description,
+            uri,
+        })
+    }
+
+    /// Deserialize tree metadata account `data`, rejecting other accounts.
+    pub fn unpack(data: &[u8]) -> Result<Self, ProgramError> {
+        if data.get(..8) != Some(&Self::DISCRIMINATOR[..]) {
+            return Err(MerkleTreeError::InvalidTreeMetadata.into());
+        }
+        Self::try_from_slice(data).map_err(|_| MerkleTreeError::InvalidTreeMetadata.into())
+    }
+}
@@ -0,0 +1,123 @@
+use crate::{
+    instruction::MerkleTreeInstruction, pda::find_merkle_state_pda, state::MerkleStateAccount,
+};
+use solana_program::{
+    instruction::{AccountMeta, Instruction},
+    message::Message,
+    pubkey::Pubkey,
+};
+use std::fmt;
+
+/// Max serialized transaction size, same as `solana_sdk::packet::PACKET_DATA_SIZE`.
+pub const MAX_TRANSACTION_SIZE: usize = 1232;
+
+/// Serialized size of a legacy transaction with `instructions` paid by `payer`.
+pub fn transaction_size(payer: &Pubkey, instructions: &[Instruction]) -> usize {
+    let message = Message::new(instructions, Some(payer));
+    let signatures_len = message.header.num_required_signatures as usize;
+
+    short_vec_len(signatures_len) + 64 * signatures_len + message.serialize().len()
+}
+
+/// Compact-u16 length prefix size.
+fn short_vec_len(len: usize) -> usize {
+    match len {
+        0..=0x7f => 1,
+        0x80..=0x3fff => 2,
+        _ => 3,
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TransactionTooLarge {
+    /// Smallest transaction size found.
+    pub size: usize,
+    /// Size taken by the other instructions of the transaction alone.
+    pub base_size: usize,
+    /// Estimated number of transactions to split the leaves into.
+    pub batches: usize,
+}
+
+impl fmt::Display for TransactionTooLarge {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "transaction is {} bytes, max is {MAX_TRANSACTION_SIZE}",
+            self.size
+        )?;
+        if self.base_size >= MAX_TRANSACTION_SIZE {
+            write!(
+                f,
+                ": other instructions alone take {} bytes, move their accounts to an address lookup table",
+                self.base_size
+            )
+        } else {
+            write!(
+                f,
+                ": split leaves into {} transactions or move other accounts to an address lookup table",
+                self.batches
+            )
+        }
+    }
+}
+
+impl std::error::Error for TransactionTooLarge {}
+
+/// Build instructions verifying leaves at `leaf_indices`(sorted, strictly increasing) with the
+/// smallest encoding: canopy-truncated `VerifyLeaf` per leaf or one `VerifyMultiProof`.
+/// Fails if they don't fit in one transaction along with `instructions`.
+pub fn build_verify_instructions(
+    program_id: &Pubkey,
+    payer: &Pubkey,
+    merkle_state: &MerkleStateAccount,
+    leaf_indices: &[u32],
+    instructions: &[Instruction],
+) -> Result<Vec<Instruction>, TransactionTooLarge> {
+    let (merkle_state_pda, _) = find_merkle_state_pda(program_id);
+    let leaf_hashes = merkle_state.leaf_hashes();
+    let verify_ix = |instruction: MerkleTreeInstruction| {
+        Instruction::new_with_bytes(
+            *program_id,
+            &instruction.pack(),
+            vec![AccountMeta::new_readonly(merkle_state_pda, false)],
+        )
+    };
+
+    let truncated: Vec<Instruction> = leaf_indices
+        .iter()
+        .map(|leaf_index| {
+            verify_ix(MerkleTreeInstruction::VerifyLeaf {
+                leaf_index: *leaf_index,
+                leaf: leaf_hashes[*leaf_index as usize],
+                proof: merkle_state.get_truncated_proof(*leaf_index),
+            })
+        })
+        .collect();
+    let multiproof = vec![verify_ix(MerkleTreeInstruction::VerifyMultiProof {
+        leaves: leaf_indices
+            .iter()
+            .map(|leaf_index| (*leaf_index, leaf_hashes[*leaf_index as usize]))
+            .collect(),
+        proof: merkle_state.get_multiproof(leaf_indices),
+    })];
+
+    let (size, verify_instructions) = [truncated, multiproof]
+        .into_iter()
+        .map(|verify_instructions| {
+            let size = transaction_size(payer, &[instructions, &verify_instructions].concat());
+            (size, verify_instructions)
+        })
+        .min_by_key(|(size, _)| *size)
+        .expect("No proof encodings");
+    if size <= MAX_TRANSACTION_SIZE {
+        return Ok(verify_instructions);
+    }
+
+    let base_size = transaction_size(payer, instructions);
+    let available = MAX_TRANSACTION_SIZE.saturating_sub(base_size).max(1);
+    Err(TransactionTooLarge {
+        size,
+        base_size,
+        batches: (size - base_size).div_ceil(available),
+    })
+}
@@ -1,6 +1,29 @@
 use sha2::{Digest, Sha256};
-use solana_program::pubkey::Pubkey;
 
+/// Canonical leaf hash for raw value bytes: `sha256(data)`, see `HashAlgo::hash_value` for
+/// trees created with another hash function.
+/// Integers are encoded as little-endian bytes before hashing.
+pub fn hash_value(data: &[u8]) -> [u8; 32] {
+    Sha256::digest(data).into()
+}
+
+/// Blinded leaf hash for raw value bytes: `sha256(salt || data)`, hides `data` until the salt
+/// is revealed along with the proof.
+pub fn hash_salted_value(salt: &[u8; 32], data: &[u8]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(salt);
+    hasher.update(data);
+    hasher.finalize().into()
+}
+
+/// Tree snapshot content hash: `sha256` over leaf hashes concatenated in insertion order.
+pub fn hash_leaves(leaves: &[[u8; 32]]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    leaves.iter().for_each(|leaf| hasher.update(leaf));
+    hasher.finalize().into()
+}
+
+/// Node hash of SHA-256 trees, other trees dispatch through `HashAlgo::hash_sorted_pair`.
 pub fn hash_sorted_pair(a: &[u8; 32], b: &[u8; 32]) -> [u8; 32] {
     let mut hasher = Sha256::new();
     let (left, right) = if a <= b { (a, b) } else { (b, a) };
@@ -9,7 +32,3 @@ pub fn hash_sorted_pair(a: &[u8; 32], b: &[u8; 32]) -> [u8; 32] {
     hasher.update(right);
     hasher.finalize().into()
 }
-
-pub fn find_merkle_state_pda(program_id: &Pubkey) -> (Pubkey, u8) {
-    Pubkey::find_program_address(&[b"merkle_state"], program_id)
-}
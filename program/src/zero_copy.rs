@@ -0,0 +1,385 @@
+//! Zero-copy access to the `MerkleStateAccount` borsh layout. Its fixed-size prefix is
+//! overlaid by the `Pod` `MerkleStateHeader` and leaf hashes are the trailing vec, so hot
+//! paths can read the header and append leaves without deserializing the whole account.
+
+use crate::{
+    events::EventVerbosity,
+    hash::{HashAlgo, TreeHasher},
+    state::{InsertCounter, MerkleStateAccount},
+};
+use borsh::BorshDeserialize;
+use bytemuck::{Pod, Zeroable};
+use merkle_tree_core::tree::append_with_canopy;
+use solana_program::{program_error::ProgramError, pubkey::Pubkey};
+use std::ops::Range;
+
+/// Fixed-size prefix of the `MerkleStateAccount` borsh layout, field for field.
+/// Check it with `MerkleStateAccount::check_header` before use.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+pub struct MerkleStateHeader {
+    pub discriminator: [u8; 8],
+    pub version: u8,
+    pub root_hash: [u8; 32],
+    pub root_history: [[u8; 32]; MerkleStateAccount::ROOT_HISTORY_LEN],
+    pub root_history_index: u8,
+    pub authority: Pubkey,
+    pub pending_authority: Pubkey,
+    /// Borsh `bool`.
+    pub paused: u8,
+    /// Borsh `bool`.
+    pub reject_duplicates: u8,
+    /// Borsh `bool`.
+    pub record_leaf_metadata: u8,
+    /// Borsh `EventVerbosity` variant index.
+    pub event_verbosity: u8,
+    pub canopy_depth: u8,
+    /// Borsh `u32`, 0 unless the tree was preallocated.
+    pub leaf_capacity: [u8; 4],
+    /// Borsh `HashAlgo` variant index.
+    pub hash_algo: u8,
+    /// Borsh `bool`.
+    pub domain_separation: u8,
+    /// Borsh `bool`.
+    pub ordered: u8,
+    /// Borsh `bool`.
+    pub left_balanced: u8,
+    /// Borsh `bool`.
+    pub permissioned: u8,
+    /// Borsh `u32`, 0 if unlimited.
+    pub max_inserts_per_slot: [u8; 4],
+    /// Borsh `u32`, 0 if unlimited.
+    pub max_inserts_per_epoch: [u8; 4],
+    /// Borsh `u32`, 0 if unlimited.
+    pub max_leaves: [u8; 4],
+    /// Borsh `bool`.
+    pub reject_sentinel_leaves: u8,
+    /// Borsh `u64`, 0 unless config changes are timelocked.
+    pub config_timelock: [u8; 8],
+    /// Borsh `u16`, 0 unless the account grows geometrically.
+    pub growth_percent: [u8; 2],
+    /// Borsh `bool`.
+    pub zero_padded: u8,
+    /// Borsh `bool`.
+    pub lazy_root: u8,
+    /// Borsh `bool`.
+    pub root_dirty: u8,
+    /// Borsh `u32`, 0 unless leaf pages are being enabled.
+    pub paged_leaves: [u8; 4],
+    pub filled_subtrees: [[u8; 32]; MerkleStateAccount::MAX_HEIGHT],
+}
+
+impl MerkleStateHeader {
+    pub const LEN: usize = size_of::<Self>();
+
+    pub fn from_bytes(data: &[u8]) -> Result<&Self, ProgramError> {
+        data.get(..Self::LEN)
+            .and_then(|header| bytemuck::try_from_bytes(header).ok())
+            .ok_or(ProgramError::InvalidAccountData)
+    }
+
+    pub fn from_bytes_mut(data: &mut [u8]) -> Result<&mut Self, ProgramError> {
+        data.get_mut(..Self::LEN)
+            .and_then(|header| bytemuck::try_from_bytes_mut(header).ok())
+            .ok_or(ProgramError::InvalidAccountData)
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused != 0
+    }
+
+    pub fn rejects_duplicates(&self) -> bool {
+        self.reject_duplicates != 0
+    }
+
+    pub fn records_leaf_metadata(&self) -> bool {
+        self.record_leaf_metadata != 0
+    }
+
+    pub fn rejects_sentinel_leaves(&self) -> bool {
+        self.reject_sentinel_leaves != 0
+    }
+
+    pub fn is_permissioned(&self) -> bool {
+        self.permissioned != 0
+    }
+
+    pub fn is_rate_limited(&self) -> bool {
+        self.max_inserts_per_slot != [0; 4] || self.max_inserts_per_epoch != [0; 4]
+    }
+
+    /// Max inserts of a payer per slot and per epoch, 0 if unlimited.
+    pub fn get_rate_limit(&self) -> (u32, u32) {
+        (
+            u32::from_le_bytes(self.max_inserts_per_slot),
+            u32::from_le_bytes(self.max_inserts_per_epoch),
+        )
+    }
+
+    /// Max leaves of a preallocated tree, `None` if the tree grows freely.
+    pub fn get_leaf_capacity(&self) -> Option<u32> {
+        let leaf_capacity = u32::from_le_bytes(self.leaf_capacity);
+        (leaf_capacity != 0).then_some(leaf_capacity)
+    }
+
+    pub fn get_growth_percent(&self) -> Option<u16> {
+        let growth_percent = u16::from_le_bytes(self.growth_percent);
+        (growth_percent != 0).then_some(growth_percent)
+    }
+
+    pub fn is_lazy_root(&self) -> bool {
+        self.lazy_root != 0
+    }
+
+    pub fn is_root_dirty(&self) -> bool {
+        self.root_dirty != 0
+    }
+
+    /// Whether spare bytes may follow the state, see `MerkleStateAccount::keeps_spare`.
+    pub fn keeps_spare(&self) -> bool {
+        self.get_leaf_capacity().is_some() || self.get_growth_percent().is_some()
+    }
+
+    /// Max leaves the tree is capped at, `None` if it isn't.
+    pub fn get_max_leaves(&self) -> Option<u32> {
+        let max_leaves = u32::from_le_bytes(self.max_leaves);
+        (max_leaves != 0).then_some(max_leaves)
+    }
+
+    pub fn get_event_verbosity(&self) -> Result<EventVerbosity, ProgramError> {
+        EventVerbosity::try_from_slice(&[self.event_verbosity])
+            .map_err(|_| ProgramError::InvalidAccountData)
+    }
+
+    pub fn get_hash_algo(&self) -> Result<HashAlgo, ProgramError> {
+        HashAlgo::try_from_slice(&[self.hash_algo]).map_err(|_| ProgramError::InvalidAccountData)
+    }
+
+    pub fn get_tree_hasher(&self) -> Result<TreeHasher, ProgramError> {
+        Ok(TreeHasher {
+            hash_algo: self.get_hash_algo()?,
+            domain_separation: self.domain_separation != 0,
+            ordered: self.ordered != 0,
+            left_balanced: self.left_balanced != 0,
+            zero_padded: self.zero_padded != 0,
+        })
+    }
+
+    /// Set current root and remember it in the root history.
+    pub fn set_root_hash(&mut self, root_hash: &[u8; 32]) {
+        self.root_hash = *root_hash;
+        self.root_history_index =
+            ((self.root_history_index as usize + 1) % MerkleStateAccount::ROOT_HISTORY_LEN) as u8;
+        self.root_history[self.root_history_index as usize] = *root_hash;
+    }
+}
+
+/// Byte ranges of the variable-length fields following `MerkleStateHeader`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MerkleStateLayout {
+    pub canopy: Range<usize>,
+    pub expiries: Range<usize>,
+    pub leaf_metadata: Range<usize>,
+    pub inserters: Range<usize>,
+    pub insert_counters: Range<usize>,
+    pub leaf_hashes: Range<usize>,
+    /// Unused bytes preallocated for upcoming leaves.
+    pub spare: Range<usize>,
+    pub is_sealed: bool,
+    pub is_frontier_mode: bool,
+}
+
+impl MerkleStateLayout {
+    /// Borsh length of an `InsertCounter`.
+    pub const INSERT_COUNTER_LEN: usize = 32 + 8 + 4 + 8 + 4;
+
+    /// Locate variable-length fields in merkle state account `data`, which they must span
+    /// up to the spare bytes of trees keeping them.
+    pub fn parse(data: &[u8]) -> Result<Self, ProgramError> {
+        let keeps_spare = MerkleStateHeader::from_bytes(data)?.keeps_spare();
+        let mut offset = MerkleStateHeader::LEN;
+        let canopy = read_vec(data, &mut offset, 32)?;
+
+        // archive: root, content hash and length prefixed URI
+        if read_option(data, &mut offset)? {
+            offset += 64;
+            read_vec(data, &mut offset, 1)?;
+        }
+        // seal: root, leaves count and slot
+        let is_sealed = read_option(data, &mut offset)?;
+        if is_sealed {
+            offset += 32 + 4 + 8;
+        }
+        // frontier: leaves count, nodes and changelog entries of root, leaf index, leaves
+        // count and path
+        let is_frontier_mode = read_option(data, &mut offset)?;
+        if is_frontier_mode {
+            offset += 4;
+            read_vec(data, &mut offset, 32)?;
+            for _ in 0..read_u32(data, &mut offset)? {
+                offset += 32 + 4 + 4;
+                read_vec(data, &mut offset, 32)?;
+            }
+        }
+        // leaf pages flag
+        read_u8(data, &mut offset)?;
+        // expiries: leaf index and timestamp
+        let expiries = read_vec(data, &mut offset, 4 + 8)?;
+        // leaf metadata: leaf index, slot and inserter
+        let leaf_metadata = read_vec(data, &mut offset, 4 + 8 + 32)?;
+        let inserters = read_vec(data, &mut offset, 32)?;
+        // insert counters: payer, slot, slot inserts, epoch and epoch inserts
+        let insert_counters = read_vec(data, &mut offset, Self::INSERT_COUNTER_LEN)?;
+
+        let leaf_hashes = read_vec(data, &mut offset, MerkleStateAccount::LEAF_LEN)?;
+        if leaf_hashes.end != data.len() && !keeps_spare {
+            return Err(ProgramError::InvalidAccountData);
+        }
+
+        Ok(Self {
+            canopy,
+            expiries,
+            leaf_metadata,
+            inserters,
+            insert_counters,
+            spare: leaf_hashes.end..data.len(),
+            leaf_hashes,
+            is_sealed,
+            is_frontier_mode,
+        })
+    }
+
+    pub fn leaf_hashes<'a>(&self, data: &'a [u8]) -> &'a [[u8; 32]] {
+        bytemuck::cast_slice(&data[self.leaf_hashes.clone()])
+    }
+
+    pub fn inserters<'a>(&self, data: &'a [u8]) -> &'a [Pubkey] {
+        bytemuck::cast_slice(&data[self.inserters.clone()])
+    }
+
+    pub fn insert_counters(&self, data: &[u8]) -> Vec<InsertCounter> {
+        data[self.insert_counters.clone()]
+            .chunks_exact(Self::INSERT_COUNTER_LEN)
+            .map(|counter| InsertCounter::try_from_slice(counter).expect("Invalid counter length"))
+            .collect()
+    }
+
+    pub fn leaves_count(&self) -> usize {
+        self.leaf_hashes.len() / MerkleStateAccount::LEAF_LEN
+    }
+}
+
+/// Append `leaf` to merkle state `data` and update the root in place, hashing only the new
+/// leaf path, returning new root and leaves count. Trees in lazy root mode only mark the
+/// root dirty and return it unchanged. Trees keeping spare bytes write into
+/// them, others must be grown by `MerkleStateAccount::LEAF_LEN` bytes first. Trees in
+/// frontier mode must go through `MerkleStateAccount::add_leaf`.
+pub fn append_leaf(data: &mut [u8], leaf: &[u8; 32]) -> Result<([u8; 32], usize), ProgramError> {
+    let keeps_spare = MerkleStateHeader::from_bytes(data)?.keeps_spare();
+    let old_len = if keeps_spare {
+        data.len()
+    } else {
+        data.len()
+            .checked_sub(MerkleStateAccount::LEAF_LEN)
+            .ok_or(ProgramError::InvalidAccountData)?
+    };
+    let layout = MerkleStateLayout::parse(&data[..old_len])?;
+    if layout.is_frontier_mode || layout.leaf_hashes.end + MerkleStateAccount::LEAF_LEN > data.len()
+    {
+        return Err(ProgramError::InvalidAccountData);
+    }
+
+    // Write leaf and bump vec length
+    let leaves_count = layout.leaves_count() + 1;
+    let len_offset = layout.leaf_hashes.start - 4;
+    data[len_offset..layout.leaf_hashes.start]
+        .copy_from_slice(&(leaves_count as u32).to_le_bytes());
+    let leaf_offset = layout.leaf_hashes.end;
+    data[leaf_offset..leaf_offset + MerkleStateAccount::LEAF_LEN].copy_from_slice(leaf);
+
+    // Split into non-overlapping header and canopy
+    let (header, canopy) = data[..layout.canopy.end].split_at_mut(layout.canopy.start);
+    let header = MerkleStateHeader::from_bytes_mut(header)?;
+
+    if header.is_lazy_root() {
+        header.root_dirty = 1;
+        return Ok((header.root_hash, leaves_count));
+    }
+
+    let tree_hasher = header.get_tree_hasher()?;
+    let canopy_depth = header.canopy_depth;
+    let root_hash = append_with_canopy(
+        &tree_hasher,
+        &mut header.filled_subtrees,
+        leaves_count - 1,
+        leaf,
+        bytemuck::cast_slice_mut(canopy),
+        canopy_depth,
+    );
+    header.set_root_hash(&root_hash);
+
+    Ok((root_hash, leaves_count))
+}
+
+/// Push `item` onto the vec spanning `items` in merkle state `data`, shifting the state bytes
+/// after it up to `state_len` right into unused bytes. Returns the new state length.
+pub fn push_item(
+    data: &mut [u8],
+    items: &Range<usize>,
+    state_len: usize,
+    item: &[u8],
+) -> Result<usize, ProgramError> {
+    let new_state_len = state_len + item.len();
+    if items.end > state_len || new_state_len > data.len() {
+        return Err(ProgramError::InvalidAccountData);
+    }
+
+    let mut offset = items.start - 4;
+    let len = read_u32(data, &mut offset)? + 1;
+    data[items.start - 4..items.start].copy_from_slice(&len.to_le_bytes());
+    data.copy_within(items.end..state_len, items.end + item.len());
+    data[items.end..items.end + item.len()].copy_from_slice(item);
+
+    Ok(new_state_len)
+}
+
+fn read_u8(data: &[u8], offset: &mut usize) -> Result<u8, ProgramError> {
+    let value = *data.get(*offset).ok_or(ProgramError::InvalidAccountData)?;
+    *offset += 1;
+    Ok(value)
+}
+
+fn read_u32(data: &[u8], offset: &mut usize) -> Result<u32, ProgramError> {
+    let value = data
+        .get(*offset..*offset + 4)
+        .map(|value| u32::from_le_bytes(value.try_into().expect("Invalid u32 length")))
+        .ok_or(ProgramError::InvalidAccountData)?;
+    *offset += 4;
+    Ok(value)
+}
+
+/// Read borsh `Option` tag, `true` if the value follows.
+fn read_option(data: &[u8], offset: &mut usize) -> Result<bool, ProgramError> {
+    match read_u8(data, offset)? {
+        0 => Ok(false),
+        1 => Ok(true),
+        _ => Err(ProgramError::InvalidAccountData),
+    }
+}
+
+/// Read borsh vec length prefix, returning the byte range of its items.
+fn read_vec(
+    data: &[u8],
+    offset: &mut usize,
+    item_len: usize,
+) -> Result<Range<usize>, ProgramError> {
+    let len = read_u32(data, offset)?;
+    let start = *offset;
+    let end = start + len as usize * item_len;
+    if end > data.len() {
+        return Err(ProgramError::InvalidAccountData);
+    }
+
+    *offset = end;
+    Ok(start..end)
+}
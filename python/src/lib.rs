@@ -0,0 +1,238 @@
+//! Python bindings of the tree logic and basic client reads, built with maturin as the
+//! `merkle_tree` module. Roots and proofs come from the same code the program and the Rust
+//! client run, so data pipelines don't reimplement the hashing scheme. Hashes are 32 bytes
+//! `bytes`.
+
+use merkle_tree_client::{
+    blocking,
+    tree::{self, MerkleTree},
+};
+use merkle_tree_errors::{ProofError, SdkError};
+use merkle_tree_program::hash::{HashAlgo, TreeHasher};
+use pyo3::{
+    exceptions::{PyRuntimeError, PyValueError},
+    prelude::*,
+    types::PyBytes,
+};
+use solana_client::rpc_client::RpcClient;
+use solana_program::pubkey::Pubkey;
+use std::str::FromStr;
+
+/// Hashing scheme of a tree, see `TreeHasher`.
+#[pyclass(name = "TreeHasher", frozen)]
+#[derive(Clone, Copy)]
+struct PyTreeHasher(TreeHasher);
+
+#[pymethods]
+impl PyTreeHasher {
+    #[new]
+    #[pyo3(signature = (
+        hash_algo = "sha256",
+        domain_separation = false,
+        ordered = false,
+        left_balanced = false,
+        zero_padded = false,
+    ))]
+    fn new(
+        hash_algo: &str,
+        domain_separation: bool,
+        ordered: bool,
+        left_balanced: bool,
+        zero_padded: bool,
+    ) -> PyResult<Self> {
+        Ok(Self(TreeHasher {
+            hash_algo: parse_hash_algo(hash_algo)?,
+            domain_separation,
+            ordered,
+            left_balanced,
+            zero_padded,
+        }))
+    }
+
+    #[staticmethod]
+    fn openzeppelin() -> Self {
+        Self(TreeHasher::OPENZEPPELIN)
+    }
+
+    #[staticmethod]
+    fn rfc6962() -> Self {
+        Self(TreeHasher::RFC6962)
+    }
+
+    /// Leaf hash of raw `data`, as `InsertValue` hashes it on-chain.
+    fn hash_value<'py>(&self, py: Python<'py>, data: &[u8]) -> Bound<'py, PyBytes> {
+        PyBytes::new_bound(py, &self.0.hash_value(data))
+    }
+
+    fn __repr__(&self) -> String {
+        format!("{:?}", self.0)
+    }
+}
+
+/// Tree built from its leaves, see `tree::MerkleTree`.
+#[pyclass(name = "MerkleTree")]
+struct PyMerkleTree(MerkleTree);
+
+#[pymethods]
+impl PyMerkleTree {
+    #[new]
+    #[pyo3(signature = (leaves, tree_hasher = None))]
+    fn new(leaves: Vec<[u8; 32]>, tree_hasher: Option<PyTreeHasher>) -> Self {
+        let tree_hasher = tree_hasher.map_or_else(TreeHasher::default, |tree_hasher| tree_hasher.0);
+        Self(MerkleTree::new(tree_hasher, leaves))
+    }
+
+    #[getter]
+    fn tree_hasher(&self) -> PyTreeHasher {
+        PyTreeHasher(self.0.tree_hasher())
+    }
+
+    fn root<'py>(&self, py: Python<'py>) -> Bound<'py, PyBytes> {
+        PyBytes::new_bound(py, &self.0.root())
+    }
+
+    fn leaves<'py>(&self, py: Python<'py>) -> Vec<Bound<'py, PyBytes>> {
+        to_py_hashes(py, self.0.leaves())
+    }
+
+    fn __len__(&self) -> usize {
+        self.0.leaves().len()
+    }
+
+    fn append(&mut self, leaf: [u8; 32]) {
+        self.0.append(leaf);
+    }
+
+    fn set_leaf(&mut self, leaf_index: u32, leaf: [u8; 32]) -> PyResult<()> {
+        self.0.set_leaf(leaf_index, leaf).map_err(proof_error)
+    }
+
+    /// Index of the first leaf equal to `leaf`, `None` if there is none.
+    fn leaf_index(&self, leaf: [u8; 32]) -> Option<u32> {
+        self.0.leaf_index(&leaf)
+    }
+
+    fn proof<'py>(&self, py: Python<'py>, leaf_index: u32) -> PyResult<Vec<Bound<'py, PyBytes>>> {
+        let proof = self.0.proof(leaf_index).map_err(proof_error)?;
+        Ok(to_py_hashes(py, &proof))
+    }
+
+    /// Index of `leaf` with its proof.
+    fn proof_by_hash<'py>(
+        &self,
+        py: Python<'py>,
+        leaf: [u8; 32],
+    ) -> PyResult<(u32, Vec<Bound<'py, PyBytes>>)> {
+        let (leaf_index, proof) = self.0.proof_by_hash(&leaf).map_err(proof_error)?;
+        Ok((leaf_index, to_py_hashes(py, &proof)))
+    }
+
+    fn multiproof<'py>(
+        &self,
+        py: Python<'py>,
+        leaf_indices: Vec<u32>,
+    ) -> PyResult<Vec<Bound<'py, PyBytes>>> {
+        let proof = self.0.multiproof(&leaf_indices).map_err(proof_error)?;
+        Ok(to_py_hashes(py, &proof))
+    }
+
+    fn verify(&self, leaf_index: u32, leaf: [u8; 32], proof: Vec<[u8; 32]>) -> bool {
+        self.0.verify(leaf_index, &leaf, &proof)
+    }
+}
+
+/// See `tree::verify_proof`.
+#[pyfunction]
+fn verify_proof(
+    tree_hasher: PyTreeHasher,
+    root: [u8; 32],
+    leaves_count: u32,
+    leaf_index: u32,
+    leaf: [u8; 32],
+    proof: Vec<[u8; 32]>,
+) -> bool {
+    tree::verify_proof(
+        &tree_hasher.0,
+        &root,
+        leaves_count,
+        leaf_index,
+        &leaf,
+        &proof,
+    )
+}
+
+/// Tree of `program_id` read over RPC at `url`, `None` if it isn't created yet.
+#[pyfunction]
+fn fetch_merkle_tree(
+    py: Python<'_>,
+    url: &str,
+    program_id: &str,
+) -> PyResult<Option<PyMerkleTree>> {
+    let program_id = parse_pubkey(program_id)?;
+    let client = RpcClient::new(url.to_string());
+    py.allow_threads(|| blocking::fetch_merkle_tree(&client, &program_id))
+        .map(|tree| tree.map(PyMerkleTree))
+        .map_err(sdk_error)
+}
+
+/// Root and leaves count of the tree of `program_id`, `None` if it isn't created yet.
+#[pyfunction]
+fn fetch_root<'py>(
+    py: Python<'py>,
+    url: &str,
+    program_id: &str,
+) -> PyResult<Option<(Bound<'py, PyBytes>, usize)>> {
+    let program_id = parse_pubkey(program_id)?;
+    let client = RpcClient::new(url.to_string());
+    let merkle_state = py
+        .allow_threads(|| blocking::fetch_merkle_state(&client, &program_id))
+        .map_err(sdk_error)?;
+    Ok(merkle_state.map(|merkle_state| {
+        (
+            PyBytes::new_bound(py, &merkle_state.get_root_hash()),
+            merkle_state.get_leaves_count(),
+        )
+    }))
+}
+
+#[pymodule]
+fn merkle_tree(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<PyTreeHasher>()?;
+    m.add_class::<PyMerkleTree>()?;
+    m.add_function(wrap_pyfunction!(verify_proof, m)?)?;
+    m.add_function(wrap_pyfunction!(fetch_merkle_tree, m)?)?;
+    m.add_function(wrap_pyfunction!(fetch_root, m)?)?;
+    Ok(())
+}
+
+fn parse_hash_algo(hash_algo: &str) -> PyResult<HashAlgo> {
+    match hash_algo.to_ascii_lowercase().as_str() {
+        "sha256" => Ok(HashAlgo::Sha256),
+        "keccak256" => Ok(HashAlgo::Keccak256),
+        "poseidon" => Ok(HashAlgo::Poseidon),
+        "blake3" => Ok(HashAlgo::Blake3),
+        "sha256d" => Ok(HashAlgo::Sha256d),
+        _ => Err(PyValueError::new_err(format!(
+            "unknown hash algo `{hash_algo}`"
+        ))),
+    }
+}
+
+fn parse_pubkey(pubkey: &str) -> PyResult<Pubkey> {
+    Pubkey::from_str(pubkey).map_err(|err| PyValueError::new_err(err.to_string()))
+}
+
+fn to_py_hashes<'py>(py: Python<'py>, hashes: &[[u8; 32]]) -> Vec<Bound<'py, PyBytes>> {
+    hashes
+        .iter()
+        .map(|hash| PyBytes::new_bound(py, hash))
+        .collect()
+}
+
+fn proof_error(err: ProofError) -> PyErr {
+    PyValueError::new_err(err.to_string())
+}
+
+fn sdk_error(err: SdkError) -> PyErr {
+    PyRuntimeError::new_err(err.to_string())
+}